@@ -12,7 +12,7 @@ use reqwest::Method;
 use serde_json::Value;
 
 use super::{
-    Principal, PrincipalField, PrincipalUpdate, PrincipalValue, Type,
+    LIST_BATCH_SIZE, Principal, PrincipalField, PrincipalUpdate, PrincipalValue, Type,
     cli::{AccountCommands, Client},
 };
 
@@ -305,23 +305,40 @@ impl Client {
         page: Option<usize>,
         limit: Option<usize>,
     ) {
-        let mut query = form_urlencoded::Serializer::new("/api/principal?".to_string());
+        // Without an explicit page, all principals are fetched in batches
+        let fetch_all = page.is_none() && limit.is_none();
+        let mut results = ListResponse::default();
+        let mut cursor = None;
 
-        query.append_pair("type", record_type);
+        loop {
+            let mut query = form_urlencoded::Serializer::new("/api/principal?".to_string());
 
-        if let Some(filter) = &filter {
-            query.append_pair("filter", filter);
-        }
-        if let Some(limit) = limit {
-            query.append_pair("limit", &limit.to_string());
-        }
-        if let Some(page) = page {
-            query.append_pair("page", &page.to_string());
-        }
+            query.append_pair("type", record_type);
 
-        let results = self
-            .http_request::<ListResponse, String>(Method::GET, &query.finish(), None)
-            .await;
+            if let Some(filter) = &filter {
+                query.append_pair("filter", filter);
+            }
+            if let Some(limit) = limit.or(fetch_all.then_some(LIST_BATCH_SIZE)) {
+                query.append_pair("limit", &limit.to_string());
+            }
+            if let Some(page) = page {
+                query.append_pair("page", &page.to_string());
+            }
+            if let Some(cursor) = &cursor {
+                query.append_pair("cursor", cursor);
+            }
+
+            let response = self
+                .http_request::<ListResponse, String>(Method::GET, &query.finish(), None)
+                .await;
+            results.total = response.total;
+            results.items.extend(response.items);
+
+            match response.cursor {
+                Some(next) if fetch_all => cursor = Some(next),
+                _ => break,
+            }
+        }
         if !results.items.is_empty() {
             let mut table = Table::new();
             table.add_row(Row::new(vec![
@@ -346,10 +363,12 @@ impl Client {
     }
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Default, serde::Deserialize)]
 struct ListResponse {
     pub total: usize,
     pub items: Vec<String>,
+    #[serde(default)]
+    pub cursor: Option<String>,
 }
 
 impl Display for Type {
@@ -24,6 +24,7 @@ pub mod queue;
 pub mod report;
 
 const RETRY_ATTEMPTS: usize = 5;
+const LIST_BATCH_SIZE: usize = 500;
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Principal {
@@ -104,6 +105,8 @@ pub enum PrincipalField {
 pub struct List<T> {
     pub items: Vec<T>,
     pub total: u64,
+    #[serde(default)]
+    pub cursor: Option<String>,
 }
 
 #[derive(Clone, serde::Serialize, serde::Deserialize, Default)]
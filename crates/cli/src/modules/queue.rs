@@ -5,7 +5,7 @@
  */
 
 use super::{
-    List,
+    LIST_BATCH_SIZE, List,
     cli::{Client, QueueCommands},
 };
 use console::Term;
@@ -394,24 +394,42 @@ impl Client {
         before: &Option<DateTime>,
         after: &Option<DateTime>,
     ) -> Vec<u64> {
-        let mut query = form_urlencoded::Serializer::new("/api/queue/messages".to_string());
+        let mut ids = Vec::new();
+        let mut cursor = None;
 
-        if let Some(sender) = from {
-            query.append_pair("from", sender);
-        }
-        if let Some(rcpt) = rcpt {
-            query.append_pair("to", rcpt);
-        }
-        if let Some(before) = before {
-            query.append_pair("before", &before.to_rfc3339());
-        }
-        if let Some(after) = after {
-            query.append_pair("after", &after.to_rfc3339());
+        loop {
+            let mut query = form_urlencoded::Serializer::new("/api/queue/messages".to_string());
+
+            if let Some(sender) = from {
+                query.append_pair("from", sender);
+            }
+            if let Some(rcpt) = rcpt {
+                query.append_pair("to", rcpt);
+            }
+            if let Some(before) = before {
+                query.append_pair("before", &before.to_rfc3339());
+            }
+            if let Some(after) = after {
+                query.append_pair("after", &after.to_rfc3339());
+            }
+            query.append_pair("limit", &LIST_BATCH_SIZE.to_string());
+            if let Some(cursor) = &cursor {
+                query.append_pair("cursor", cursor);
+            }
+
+            let response = self
+                .http_request::<List<u64>, String>(Method::GET, &query.finish(), None)
+                .await;
+            ids.extend(response.items);
+
+            if let Some(next) = response.cursor {
+                cursor = Some(next);
+            } else {
+                break;
+            }
         }
 
-        self.http_request::<List<u64>, String>(Method::GET, &query.finish(), None)
-            .await
-            .items
+        ids
     }
 }
 
@@ -55,6 +55,23 @@ impl Server {
         Ok(None)
     }
 
+    // Returns true when a recipient was resolved through the catch-all address
+    // rather than one of the account's own addresses
+    pub async fn is_catch_all_rcpt(&self, emails: &[String], rcpt: &str, session_id: u64) -> bool {
+        let rcpt_config = &self.core.smtp.session.rcpt;
+        if matches!(rcpt_config.catch_all, AddressMapping::Disable) {
+            return false;
+        }
+
+        let address = rcpt_config
+            .subaddressing
+            .to_subaddress(self, rcpt, session_id)
+            .await;
+        !emails
+            .iter()
+            .any(|email| email.eq_ignore_ascii_case(address.as_ref()))
+    }
+
     pub async fn rcpt(
         &self,
         directory: &Directory,
@@ -11,16 +11,17 @@ use directory::{
         lookup::DirectoryStore,
         manage::{ChangedPrincipals, ManageDirectory},
     },
+    core::{protocols::Protocol, senders::SenderGrant},
 };
 use jmap_proto::{
     request::RequestMethod,
-    types::{acl::Acl, collection::Collection, id::Id},
+    types::{acl::Acl, collection::Collection, id::Id, property::Property},
 };
 use std::{
     hash::{DefaultHasher, Hash, Hasher},
     sync::Arc,
 };
-use store::{dispatch::lookup::KeyValue, query::acl::AclQuery};
+use store::{ValueKey, dispatch::lookup::KeyValue, query::acl::AclQuery, write::ValueClass};
 use trc::AddContext;
 use utils::map::{
     bitmap::{Bitmap, BitmapItem},
@@ -98,6 +99,44 @@ impl Server {
 
         // SPDX-SnippetEnd
 
+        // Obtain sender grants, including those inherited from groups
+        let mut senders = principal.sender_grants().collect::<Vec<_>>();
+        for group_id in principal.member_of() {
+            if let Some(group) = self
+                .store()
+                .query(QueryBy::Id(*group_id), false)
+                .await
+                .caused_by(trc::location!())?
+            {
+                if group.typ == Type::Group {
+                    for grant in group.sender_grants() {
+                        if !senders.contains(&grant) {
+                            senders.push(grant);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Encryption at rest is configured if the time of its last update is stored
+        let encrypted_at = self
+            .store()
+            .get_value::<u64>(ValueKey {
+                account_id: principal.id(),
+                collection: Collection::Principal.into(),
+                document_id: 0,
+                class: ValueClass::Property(Property::EncryptionUpdatedAt.into()),
+            })
+            .await
+            .caused_by(trc::location!())?;
+
+        // An empty list of protocols means all of them are enabled
+        let protocols = principal
+            .enabled_protocols()
+            .iter()
+            .filter_map(|protocol| Protocol::parse(protocol))
+            .collect::<Vec<_>>();
+
         // Build access token
         let mut access_token = AccessToken {
             primary_id: principal.id(),
@@ -110,6 +149,8 @@ impl Server {
             name: principal.name,
             description: principal.description,
             emails: principal.emails,
+            senders,
+            protocols,
             quota: principal.quota.unwrap_or_default(),
             permissions,
             concurrent_imap_requests: self.core.imap.rate_concurrent.map(ConcurrencyLimiter::new),
@@ -123,8 +164,10 @@ impl Server {
                 .jmap
                 .upload_max_concurrent
                 .map(ConcurrencyLimiter::new),
+            encrypted_at,
             obj_size: 0,
             revision,
+            impersonation: None,
         };
 
         for grant_account_id in [access_token.primary_id]
@@ -194,6 +237,32 @@ impl Server {
         }
     }
 
+    // Existing sessions are terminated once their protocol is disabled for the account
+    pub async fn is_protocol_revoked(
+        &self,
+        account_id: u32,
+        protocol: Protocol,
+        session_id: u64,
+    ) -> bool {
+        if self.core.jmap.protocol_disconnect
+            && matches!(
+                self.get_access_token(account_id).await,
+                Ok(access_token) if !access_token.is_protocol_enabled(protocol)
+            )
+        {
+            trc::event!(
+                Auth(trc::AuthEvent::ProtocolDisabled),
+                SpanId = session_id,
+                AccountId = account_id,
+                Type = protocol.as_str(),
+                Details = "Session terminated",
+            );
+            true
+        } else {
+            false
+        }
+    }
+
     pub async fn get_access_token(
         &self,
         principal: impl Into<PrincipalOrId>,
@@ -334,7 +403,7 @@ impl Server {
         }
     }
 
-    async fn increment_revision(&self, id: u32) {
+    pub async fn increment_revision(&self, id: u32) {
         if let Err(err) = self
             .in_memory_store()
             .counter_incr(
@@ -414,6 +483,7 @@ impl AccessToken {
         let mut s = DefaultHasher::new();
         self.member_of.hash(&mut s);
         self.access_to.hash(&mut s);
+        self.encrypted_at.hash(&mut s);
         s.finish() as u32
     }
 
@@ -694,13 +764,40 @@ impl AccessToken {
             .map_or(LimiterResult::Disabled, |limiter| limiter.is_allowed())
     }
 
+    pub fn sender_grant(&self, address: &str) -> Option<&SenderGrant> {
+        self.senders.iter().find(|grant| grant.matches(address))
+    }
+
+    pub fn is_protocol_enabled(&self, protocol: Protocol) -> bool {
+        self.protocols.is_empty() || self.protocols.contains(&protocol)
+    }
+
+    pub fn assert_protocol_enabled(&self, protocol: Protocol) -> trc::Result<()> {
+        if self.is_protocol_enabled(protocol) {
+            Ok(())
+        } else {
+            Err(trc::AuthEvent::ProtocolDisabled
+                .into_err()
+                .details("protocol disabled for this account")
+                .account_id(self.primary_id)
+                .ctx(trc::Key::Type, protocol.as_str()))
+        }
+    }
+
     pub fn update_size(mut self) -> Self {
         self.obj_size = (std::mem::size_of::<AccessToken>()
             + (self.member_of.len() * std::mem::size_of::<u32>())
             + (self.access_to.len() * (std::mem::size_of::<u32>() + std::mem::size_of::<u64>()))
             + self.name.len()
             + self.description.as_ref().map_or(0, |v| v.len())
-            + self.emails.iter().map(|v| v.len()).sum::<usize>()) as u64;
+            + self.emails.iter().map(|v| v.len()).sum::<usize>()
+            + self
+                .senders
+                .iter()
+                .map(|v| v.address.len() + std::mem::size_of::<SenderGrant>())
+                .sum::<usize>()
+            + (self.protocols.len() * std::mem::size_of::<Protocol>()))
+            as u64;
         self
     }
 }
@@ -0,0 +1,528 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use directory::{Permission, QueryBy, Type};
+use store::{
+    blake3,
+    dispatch::lookup::KeyValue,
+    rand::{Rng, distr::Alphanumeric, rng},
+    write::now,
+};
+use trc::AddContext;
+use utils::config::Config;
+
+use crate::{KV_IMPERSONATION, Server};
+
+use super::AccessToken;
+
+pub const IMPERSONATION_TOKEN_PREFIX: &str = "imp_";
+
+const SESSION_ID_LEN: usize = 16;
+const SESSION_SECRET_LEN: usize = 32;
+const INDEX_KEY: &[u8] = b"i";
+
+#[derive(Debug, Clone)]
+pub struct ImpersonationConfig {
+    pub enable: bool,
+    pub allow_write: bool,
+    pub notify: bool,
+    pub max_duration: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ImpersonationSession {
+    pub id: String,
+    #[serde(rename = "adminId")]
+    pub admin_id: u32,
+    #[serde(rename = "adminName")]
+    pub admin_name: String,
+    #[serde(rename = "accountId")]
+    pub account_id: u32,
+    #[serde(rename = "accountName")]
+    pub account_name: String,
+    #[serde(rename = "readOnly")]
+    pub read_only: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: u64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredSession {
+    #[serde(flatten)]
+    session: ImpersonationSession,
+    #[serde(rename = "secretHash")]
+    secret_hash: String,
+}
+
+// Account management permissions are never carried over to an impersonated
+// session, administrative ones (which precede Authenticate) are removed as well
+const DENIED_PERMISSIONS: &[Permission] = &[
+    Permission::AuthenticateOauth,
+    Permission::ManageEncryption,
+    Permission::ManagePasswords,
+    Permission::ApiKeyList,
+    Permission::ApiKeyGet,
+    Permission::ApiKeyCreate,
+    Permission::ApiKeyUpdate,
+    Permission::ApiKeyDelete,
+    Permission::OauthClientList,
+    Permission::OauthClientGet,
+    Permission::OauthClientCreate,
+    Permission::OauthClientUpdate,
+    Permission::OauthClientDelete,
+    Permission::OauthClientRegistration,
+    Permission::OauthClientOverride,
+    Permission::Troubleshoot,
+    Permission::ManageIngestHooks,
+];
+
+const WRITE_PERMISSIONS: &[Permission] = &[
+    Permission::EmailSend,
+    Permission::JmapEmailSet,
+    Permission::JmapMailboxSet,
+    Permission::JmapIdentitySet,
+    Permission::JmapEmailSubmissionSet,
+    Permission::JmapPushSubscriptionSet,
+    Permission::JmapSieveScriptSet,
+    Permission::JmapVacationResponseSet,
+    Permission::JmapEmailCopy,
+    Permission::JmapBlobCopy,
+    Permission::JmapEmailImport,
+    Permission::JmapBlobUpload,
+    Permission::ImapAclSet,
+    Permission::ImapAppend,
+    Permission::ImapCopy,
+    Permission::ImapMove,
+    Permission::ImapCreate,
+    Permission::ImapDelete,
+    Permission::ImapExpunge,
+    Permission::ImapRename,
+    Permission::ImapStore,
+    Permission::ImapSubscribe,
+    Permission::Pop3Dele,
+    Permission::SieveSetActive,
+    Permission::SievePutScript,
+    Permission::SieveDeleteScript,
+    Permission::SieveRenameScript,
+    Permission::DavFilePropPatch,
+    Permission::DavFileMkCol,
+    Permission::DavFileDelete,
+    Permission::DavFilePut,
+    Permission::DavFileCopy,
+    Permission::DavFileMove,
+    Permission::DavFileLock,
+    Permission::DavFileAcl,
+    Permission::DavCardPropPatch,
+    Permission::DavCardMkCol,
+    Permission::DavCardDelete,
+    Permission::DavCardPut,
+    Permission::DavCardCopy,
+    Permission::DavCardMove,
+    Permission::DavCardLock,
+    Permission::DavCardAcl,
+    Permission::DavCalPropPatch,
+    Permission::DavCalMkCol,
+    Permission::DavCalDelete,
+    Permission::DavCalPut,
+    Permission::DavCalCopy,
+    Permission::DavCalMove,
+    Permission::DavCalLock,
+    Permission::DavCalAcl,
+];
+
+impl ImpersonationConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        ImpersonationConfig {
+            enable: config
+                .property_or_default("authentication.impersonation.enable", "false")
+                .unwrap_or_default(),
+            allow_write: config
+                .property_or_default("authentication.impersonation.allow-write", "false")
+                .unwrap_or_default(),
+            notify: config
+                .property_or_default("authentication.impersonation.notify", "true")
+                .unwrap_or(true),
+            max_duration: config
+                .property_or_default("authentication.impersonation.max-duration", "1h")
+                .unwrap_or_else(|| Duration::from_secs(3600)),
+        }
+    }
+}
+
+impl Server {
+    pub async fn create_impersonation(
+        &self,
+        admin: &AccessToken,
+        account_name: &str,
+        read_only: bool,
+        duration: Option<Duration>,
+        reason: Option<String>,
+    ) -> trc::Result<(ImpersonationSession, String)> {
+        let config = &self.core.jmap.impersonation;
+        if !config.enable {
+            return Err(trc::SecurityEvent::Unauthorized
+                .into_err()
+                .details("Impersonation is disabled"));
+        } else if !read_only && !config.allow_write {
+            return Err(trc::SecurityEvent::Unauthorized
+                .into_err()
+                .details("Read-write impersonation is disabled"));
+        }
+
+        let principal = self
+            .impersonation_target(admin, account_name)
+            .await
+            .caused_by(trc::location!())?;
+        let duration = duration
+            .unwrap_or(config.max_duration)
+            .min(config.max_duration)
+            .as_secs();
+        if duration == 0 {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Invalid impersonation duration"));
+        }
+
+        let mut random = rng()
+            .sample_iter(Alphanumeric)
+            .take(SESSION_ID_LEN + SESSION_SECRET_LEN)
+            .map(char::from)
+            .collect::<String>();
+        let secret = random.split_off(SESSION_ID_LEN);
+        let id = random;
+        let created_at = now();
+        let session = ImpersonationSession {
+            id,
+            admin_id: admin.primary_id,
+            admin_name: admin.name.clone(),
+            account_id: principal.id,
+            account_name: principal.name,
+            read_only,
+            reason: reason.filter(|reason| !reason.is_empty()),
+            created_at,
+            expires_at: created_at + duration,
+        };
+
+        // Sessions live in the in-memory store so they are seen (and revoked) cluster-wide
+        let value = serde_json::to_vec(&StoredSession {
+            session: session.clone(),
+            secret_hash: blake3::hash(secret.as_bytes()).to_hex().to_string(),
+        })
+        .unwrap_or_default();
+        self.in_memory_store()
+            .key_set(
+                KeyValue::with_prefix(KV_IMPERSONATION, session_key(&session.id), value)
+                    .expires(duration),
+            )
+            .await
+            .caused_by(trc::location!())?;
+        let mut ids = self.impersonation_index().await?;
+        ids.push(session.id.clone());
+        self.set_impersonation_index(&ids).await?;
+
+        trc::event!(
+            Auth(trc::AuthEvent::ImpersonationGranted),
+            AccountId = session.account_id,
+            AccountName = session.account_name.clone(),
+            Source = session.admin_name.clone(),
+            Id = session.id.clone(),
+            Type = session.access_type(),
+            Expires = trc::Value::Timestamp(session.expires_at),
+        );
+
+        let token = format!("{IMPERSONATION_TOKEN_PREFIX}{}{secret}", session.id);
+
+        Ok((session, token))
+    }
+
+    pub async fn list_impersonations(&self) -> trc::Result<Vec<ImpersonationSession>> {
+        let ids = self.impersonation_index().await?;
+        let mut sessions = Vec::with_capacity(ids.len());
+        let now = now();
+
+        for id in &ids {
+            if let Some(stored) = self.get_impersonation(id).await? {
+                if stored.session.expires_at > now {
+                    sessions.push(stored.session);
+                }
+            }
+        }
+
+        // Drop expired and revoked sessions from the index
+        if sessions.len() != ids.len() {
+            self.set_impersonation_index(
+                &sessions
+                    .iter()
+                    .map(|session| session.id.clone())
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+        }
+
+        Ok(sessions)
+    }
+
+    pub async fn revoke_impersonation(
+        &self,
+        id: &str,
+        revoked_by: &AccessToken,
+    ) -> trc::Result<Option<ImpersonationSession>> {
+        let Some(stored) = self.get_impersonation(id).await? else {
+            return Ok(None);
+        };
+
+        self.in_memory_store()
+            .key_delete(KeyValue::<()>::build_key(KV_IMPERSONATION, session_key(id)))
+            .await
+            .caused_by(trc::location!())?;
+        let mut ids = self.impersonation_index().await?;
+        ids.retain(|session_id| session_id != id);
+        self.set_impersonation_index(&ids).await?;
+
+        trc::event!(
+            Auth(trc::AuthEvent::ImpersonationRevoked),
+            AccountId = stored.session.account_id,
+            AccountName = stored.session.account_name.clone(),
+            Source = revoked_by.name.clone(),
+            Id = stored.session.id.clone(),
+        );
+
+        Ok(Some(stored.session))
+    }
+
+    // Validates a bearer token minted by create_impersonation
+    pub async fn authenticate_impersonation(
+        &self,
+        token: &str,
+        session_id: u64,
+    ) -> trc::Result<Arc<AccessToken>> {
+        let failed = || {
+            trc::AuthEvent::Failed
+                .into_err()
+                .details("Invalid impersonation token")
+        };
+
+        if !self.core.jmap.impersonation.enable {
+            return Err(failed());
+        }
+        let token = token
+            .strip_prefix(IMPERSONATION_TOKEN_PREFIX)
+            .filter(|token| token.len() == SESSION_ID_LEN + SESSION_SECRET_LEN && token.is_ascii())
+            .ok_or_else(failed)?;
+        let (id, secret) = token.split_at(SESSION_ID_LEN);
+        let stored = self.get_impersonation(id).await?.ok_or_else(failed)?;
+
+        // blake3::Hash comparisons are constant-time
+        if blake3::Hash::from_hex(&stored.secret_hash).ok() != Some(blake3::hash(secret.as_bytes()))
+        {
+            return Err(failed());
+        } else if stored.session.expires_at <= now() {
+            return Err(trc::AuthEvent::TokenExpired.into_err());
+        }
+
+        self.impersonated_access_token(stored.session, session_id)
+            .await
+    }
+
+    // Resolves a SASL authorization identity, the authenticated administrator
+    // must have been granted an active session for the target account
+    pub async fn authorize_impersonation(
+        &self,
+        admin: &AccessToken,
+        authzid: &str,
+        session_id: u64,
+    ) -> trc::Result<Arc<AccessToken>> {
+        if !self.core.jmap.impersonation.enable {
+            return Err(trc::SecurityEvent::Unauthorized
+                .into_err()
+                .details("Impersonation is disabled"));
+        }
+        admin.assert_has_permission(Permission::Impersonate)?;
+
+        let principal = self.impersonation_target(admin, authzid).await?;
+        let session = self
+            .list_impersonations()
+            .await?
+            .into_iter()
+            .filter(|session| {
+                session.admin_id == admin.primary_id && session.account_id == principal.id
+            })
+            .max_by_key(|session| session.expires_at)
+            .ok_or_else(|| {
+                trc::SecurityEvent::Unauthorized
+                    .into_err()
+                    .details("No active impersonation session for this account")
+                    .ctx(trc::Key::AccountName, authzid.to_string())
+            })?;
+
+        self.impersonated_access_token(session, session_id).await
+    }
+
+    async fn impersonated_access_token(
+        &self,
+        session: ImpersonationSession,
+        session_id: u64,
+    ) -> trc::Result<Arc<AccessToken>> {
+        let target = self
+            .get_access_token(session.account_id)
+            .await
+            .caused_by(trc::location!())?;
+        target.assert_has_permission(Permission::Authenticate)?;
+
+        let mut permissions = target.permissions.clone();
+        for permission_id in 0..Permission::Authenticate.id() {
+            permissions.clear(permission_id);
+        }
+        for permission in DENIED_PERMISSIONS {
+            permissions.clear(permission.id());
+        }
+        if session.read_only {
+            for permission in WRITE_PERMISSIONS {
+                permissions.clear(permission.id());
+            }
+        }
+
+        trc::event!(
+            Auth(trc::AuthEvent::Impersonated),
+            SpanId = session_id,
+            AccountId = session.account_id,
+            AccountName = session.account_name.clone(),
+            Source = session.admin_name.clone(),
+            Id = session.id.clone(),
+            Type = session.access_type(),
+        );
+
+        Ok(Arc::new(
+            AccessToken {
+                primary_id: target.primary_id,
+                member_of: target.member_of.clone(),
+                access_to: target.access_to.clone(),
+                name: target.name.clone(),
+                description: target.description.clone(),
+                emails: target.emails.clone(),
+                senders: target.senders.clone(),
+                protocols: target.protocols.clone(),
+                quota: target.quota,
+                permissions,
+                tenant: target.tenant,
+                concurrent_http_requests: target.concurrent_http_requests.clone(),
+                concurrent_imap_requests: target.concurrent_imap_requests.clone(),
+                concurrent_uploads: target.concurrent_uploads.clone(),
+                encrypted_at: target.encrypted_at,
+                revision: target.revision,
+                obj_size: 0,
+                impersonation: Some(Arc::new(session)),
+            }
+            .update_size(),
+        ))
+    }
+
+    async fn impersonation_target(
+        &self,
+        admin: &AccessToken,
+        account_name: &str,
+    ) -> trc::Result<directory::Principal> {
+        let principal = self
+            .core
+            .storage
+            .directory
+            .query(QueryBy::Name(account_name), false)
+            .await
+            .caused_by(trc::location!())?
+            .filter(|principal| {
+                principal.typ == Type::Individual
+                    && admin
+                        .tenant
+                        .is_none_or(|tenant| principal.tenant == Some(tenant.id))
+            })
+            .ok_or_else(|| {
+                trc::ManageEvent::NotFound
+                    .into_err()
+                    .ctx(trc::Key::AccountName, account_name.to_string())
+            })?;
+
+        if principal.id == admin.primary_id {
+            Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Administrators cannot impersonate themselves"))
+        } else {
+            Ok(principal)
+        }
+    }
+
+    async fn get_impersonation(&self, id: &str) -> trc::Result<Option<StoredSession>> {
+        self.in_memory_store()
+            .key_get::<String>(KeyValue::<()>::build_key(KV_IMPERSONATION, session_key(id)))
+            .await
+            .caused_by(trc::location!())
+            .map(|value| value.and_then(|value| serde_json::from_str(&value).ok()))
+    }
+
+    async fn impersonation_index(&self) -> trc::Result<Vec<String>> {
+        self.in_memory_store()
+            .key_get::<String>(KeyValue::<()>::build_key(KV_IMPERSONATION, INDEX_KEY))
+            .await
+            .caused_by(trc::location!())
+            .map(|value| {
+                value
+                    .and_then(|value| serde_json::from_str(&value).ok())
+                    .unwrap_or_default()
+            })
+    }
+
+    async fn set_impersonation_index(&self, ids: &[String]) -> trc::Result<()> {
+        self.in_memory_store()
+            .key_set(KeyValue::with_prefix(
+                KV_IMPERSONATION,
+                INDEX_KEY,
+                serde_json::to_vec(ids).unwrap_or_default(),
+            ))
+            .await
+            .caused_by(trc::location!())
+    }
+}
+
+impl ImpersonationSession {
+    pub fn access_type(&self) -> &'static str {
+        if self.read_only {
+            "read-only"
+        } else {
+            "read-write"
+        }
+    }
+}
+
+impl AccessToken {
+    pub fn is_read_only(&self) -> bool {
+        self.impersonation
+            .as_ref()
+            .is_some_and(|session| session.read_only)
+    }
+}
+
+fn session_key(id: &str) -> Vec<u8> {
+    let mut key = Vec::with_capacity(id.len() + 1);
+    key.push(b's');
+    key.extend_from_slice(id.as_bytes());
+    key
+}
+
+impl Default for ImpersonationConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            allow_write: false,
+            notify: true,
+            max_duration: Duration::from_secs(3600),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::Directory;
+use store::{dispatch::lookup::KeyValue, write::now};
+use trc::AddContext;
+
+use crate::{KV_MAINTENANCE, Server};
+
+use super::AccessToken;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceTarget<'x> {
+    Account(u32),
+    Domain(&'x str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MaintenanceStatus {
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<u64>,
+}
+
+impl MaintenanceTarget<'_> {
+    fn key(&self) -> Vec<u8> {
+        let mut key = Vec::with_capacity(16);
+        match self {
+            MaintenanceTarget::Account(account_id) => {
+                key.push(b'a');
+                key.extend_from_slice(&account_id.to_be_bytes());
+            }
+            MaintenanceTarget::Domain(domain) => {
+                key.push(b'd');
+                key.extend_from_slice(domain.to_lowercase().as_bytes());
+            }
+        }
+        key
+    }
+}
+
+impl Server {
+    pub async fn set_maintenance(
+        &self,
+        target: MaintenanceTarget<'_>,
+        ttl: Option<u64>,
+    ) -> trc::Result<MaintenanceStatus> {
+        // The flag lives in the in-memory store so it is seen by all nodes in the cluster
+        let expires_at = ttl.map(|ttl| now() + ttl);
+        self.in_memory_store()
+            .key_set(
+                KeyValue::with_prefix(
+                    KV_MAINTENANCE,
+                    target.key(),
+                    expires_at.unwrap_or_default().to_string().into_bytes(),
+                )
+                .expires_opt(ttl),
+            )
+            .await
+            .caused_by(trc::location!())
+            .map(|_| MaintenanceStatus { expires_at })
+    }
+
+    pub async fn clear_maintenance(&self, target: MaintenanceTarget<'_>) -> trc::Result<()> {
+        self.in_memory_store()
+            .key_delete(KeyValue::<()>::build_key(KV_MAINTENANCE, target.key()))
+            .await
+            .caused_by(trc::location!())
+    }
+
+    pub async fn get_maintenance(
+        &self,
+        target: MaintenanceTarget<'_>,
+    ) -> trc::Result<Option<MaintenanceStatus>> {
+        self.in_memory_store()
+            .key_get::<String>(KeyValue::<()>::build_key(KV_MAINTENANCE, target.key()))
+            .await
+            .caused_by(trc::location!())
+            .map(|value| {
+                value.map(|value| MaintenanceStatus {
+                    expires_at: value.parse::<u64>().ok().filter(|v| *v != 0),
+                })
+            })
+    }
+
+    pub async fn account_maintenance(
+        &self,
+        access_token: &AccessToken,
+    ) -> trc::Result<Option<MaintenanceStatus>> {
+        if let Some(status) = self
+            .get_maintenance(MaintenanceTarget::Account(access_token.primary_id))
+            .await?
+        {
+            return Ok(Some(status));
+        }
+
+        let mut domains = access_token
+            .emails
+            .iter()
+            .filter_map(|email| email.rsplit_once('@').map(|(_, domain)| domain))
+            .collect::<Vec<_>>();
+        domains.sort_unstable();
+        domains.dedup();
+        for domain in domains {
+            if let Some(status) = self
+                .get_maintenance(MaintenanceTarget::Domain(domain))
+                .await?
+            {
+                return Ok(Some(status));
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub async fn is_address_in_maintenance(
+        &self,
+        directory: &Directory,
+        address: &str,
+        session_id: u64,
+    ) -> trc::Result<bool> {
+        if let Some((_, domain)) = address.rsplit_once('@') {
+            if self
+                .get_maintenance(MaintenanceTarget::Domain(domain))
+                .await?
+                .is_some()
+            {
+                return Ok(true);
+            }
+        }
+
+        if let Some(account_id) = self.email_to_id(directory, address, session_id).await? {
+            self.account_id_maintenance(account_id)
+                .await
+                .map(|status| status.is_some())
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub async fn assert_not_in_maintenance(&self, access_token: &AccessToken) -> trc::Result<()> {
+        if self.account_maintenance(access_token).await?.is_none() {
+            Ok(())
+        } else {
+            Err(trc::LimitEvent::Maintenance
+                .into_err()
+                .account_id(access_token.primary_id)
+                .details("Account is in maintenance mode, try again later."))
+        }
+    }
+
+    pub async fn account_id_maintenance(
+        &self,
+        account_id: u32,
+    ) -> trc::Result<Option<MaintenanceStatus>> {
+        let access_token = self
+            .get_access_token(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        self.account_maintenance(&access_token).await
+    }
+
+    pub async fn assert_account_not_in_maintenance(&self, account_id: u32) -> trc::Result<()> {
+        let access_token = self
+            .get_access_token(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        self.assert_not_in_maintenance(&access_token).await
+    }
+}
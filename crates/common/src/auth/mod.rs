@@ -7,8 +7,10 @@
 use std::{net::IpAddr, sync::Arc};
 
 use directory::{
-    Directory, Permission, Permissions, Principal, QueryBy, core::secret::verify_secret_hash,
+    Directory, Permission, Permissions, Principal, QueryBy,
+    core::{protocols::Protocol, secret::verify_secret_hash, senders::SenderGrant},
 };
+use impersonate::ImpersonationSession;
 use jmap_proto::types::collection::Collection;
 use mail_send::Credentials;
 use oauth::GrantType;
@@ -20,6 +22,8 @@ use utils::{
 use crate::{Server, listener::limiter::ConcurrencyLimiter};
 
 pub mod access_token;
+pub mod impersonate;
+pub mod maintenance;
 pub mod oauth;
 pub mod rate_limit;
 pub mod roles;
@@ -33,14 +37,19 @@ pub struct AccessToken {
     pub name: String,
     pub description: Option<String>,
     pub emails: Vec<String>,
+    pub senders: Vec<SenderGrant>,
+    pub protocols: Vec<Protocol>,
     pub quota: u64,
     pub permissions: Permissions,
     pub tenant: Option<TenantInfo>,
     pub concurrent_http_requests: Option<ConcurrencyLimiter>,
     pub concurrent_imap_requests: Option<ConcurrencyLimiter>,
     pub concurrent_uploads: Option<ConcurrencyLimiter>,
+    pub encrypted_at: Option<u64>,
     pub revision: u64,
     pub obj_size: u64,
+    // Set when an administrator is acting on behalf of this account
+    pub impersonation: Option<Arc<ImpersonationSession>>,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
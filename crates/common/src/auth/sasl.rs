@@ -7,12 +7,22 @@
 use mail_send::Credentials;
 
 pub fn sasl_decode_challenge_plain(challenge: &[u8]) -> Option<Credentials<String>> {
+    sasl_decode_challenge_plain_authzid(challenge).map(|(credentials, _)| credentials)
+}
+
+// Also returns the authorization identity, if present and different from the login
+pub fn sasl_decode_challenge_plain_authzid(
+    challenge: &[u8],
+) -> Option<(Credentials<String>, Option<String>)> {
+    let mut authzid = Vec::new();
     let mut username = Vec::new();
     let mut secret = Vec::new();
     let mut arg_num = 0;
     for &ch in challenge {
         if ch != 0 {
-            if arg_num == 1 {
+            if arg_num == 0 {
+                authzid.push(ch);
+            } else if arg_num == 1 {
                 username.push(ch);
             } else if arg_num == 2 {
                 secret.push(ch);
@@ -22,9 +32,15 @@ pub fn sasl_decode_challenge_plain(challenge: &[u8]) -> Option<Credentials<Strin
         }
     }
 
-    match (String::from_utf8(username), String::from_utf8(secret)) {
-        (Ok(username), Ok(secret)) if !username.is_empty() && !secret.is_empty() => {
-            Some((username, secret).into())
+    match (
+        String::from_utf8(authzid),
+        String::from_utf8(username),
+        String::from_utf8(secret),
+    ) {
+        (Ok(authzid), Ok(username), Ok(secret)) if !username.is_empty() && !secret.is_empty() => {
+            let authzid = Some(authzid)
+                .filter(|authzid| !authzid.is_empty() && !authzid.eq_ignore_ascii_case(&username));
+            Some(((username, secret).into(), authzid))
         }
         _ => None,
     }
@@ -61,6 +77,23 @@ fn extract_oauth_bearer(bytes: &[u8]) -> Option<&str> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_decode_plain_authzid() {
+        let (credentials, authzid) =
+            sasl_decode_challenge_plain_authzid(b"jdoe@example.com\0admin\0secret").unwrap();
+        assert!(matches!(
+            credentials,
+            Credentials::Plain { username, secret } if username == "admin" && secret == "secret"
+        ));
+        assert_eq!(authzid.as_deref(), Some("jdoe@example.com"));
+
+        for challenge in [&b"\0admin\0secret"[..], b"Admin\0admin\0secret"] {
+            let (_, authzid) = sasl_decode_challenge_plain_authzid(challenge).unwrap();
+            assert_eq!(authzid, None);
+        }
+        assert!(sasl_decode_challenge_plain_authzid(b"jdoe\0admin\0").is_none());
+    }
+
     #[test]
     fn test_extract_oauth_bearer() {
         let input = b"auth=Bearer validtoken";
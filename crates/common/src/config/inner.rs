@@ -10,7 +10,7 @@ use crate::{
     MessageUidCache, TlsConnectors,
     auth::{AccessToken, roles::RolePermissions},
     config::smtp::resolver::{Policy, Tlsa},
-    listener::blocked::BlockedIps,
+    listener::{blocked::BlockedIps, tickets::TicketKeys},
     manager::webadmin::WebAdminManager,
 };
 use ahash::{AHashMap, AHashSet};
@@ -23,6 +23,7 @@ use std::{
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     sync::Arc,
 };
+use store::write::now;
 use utils::{
     cache::{Cache, CacheWithTtl},
     config::Config,
@@ -59,6 +60,16 @@ impl Data {
             })
             .ok()
             .map(Arc::new),
+            tls_ticket_keys: ArcSwap::from_pointee(
+                // Keys are generated again on the first refresh
+                TicketKeys::generate(now()).unwrap_or_else(|_| {
+                    config.new_build_error(
+                        "server.tls.session-tickets",
+                        "Failed to generate TLS ticket keys",
+                    );
+                    TicketKeys::default()
+                }),
+            ),
             blocked_ips: RwLock::new(BlockedIps::parse(config).blocked_ip_addresses),
             jmap_id_gen: id_generator.clone(),
             queue_id_gen: id_generator.clone(),
@@ -69,6 +80,11 @@ impl Data {
                 .map(|path| WebAdminManager::new(path.into()))
                 .unwrap_or_default(),
             logos: Default::default(),
+            tls_fingerprints: Default::default(),
+            tls_handshakes: Default::default(),
+            running_tasks: Default::default(),
+            load_test: Default::default(),
+            index_backlog: Default::default(),
             smtp_connectors: TlsConnectors::default(),
             asn_geo_data: Default::default(),
         }
@@ -218,6 +234,7 @@ impl Default for Data {
         Self {
             tls_certificates: Default::default(),
             tls_self_signed_cert: Default::default(),
+            tls_ticket_keys: Default::default(),
             blocked_ips: Default::default(),
             jmap_id_gen: Default::default(),
             queue_id_gen: Default::default(),
@@ -225,6 +242,11 @@ impl Default for Data {
             queue_status: true.into(),
             webadmin: Default::default(),
             logos: Default::default(),
+            tls_fingerprints: Default::default(),
+            tls_handshakes: Default::default(),
+            running_tasks: Default::default(),
+            load_test: Default::default(),
+            index_backlog: Default::default(),
             smtp_connectors: Default::default(),
             asn_geo_data: Default::default(),
         }
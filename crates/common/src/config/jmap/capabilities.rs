@@ -8,8 +8,8 @@ use ahash::AHashSet;
 use jmap_proto::{
     request::capability::{
         BlobCapabilities, Capabilities, Capability, CoreCapabilities, EmptyCapabilities,
-        MailCapabilities, SieveAccountCapabilities, SieveSessionCapabilities,
-        SubmissionCapabilities,
+        EncryptionAtRestCapabilities, MailCapabilities, SieveAccountCapabilities,
+        SieveSessionCapabilities, SubmissionCapabilities,
     },
     types::type_state::DataType,
 };
@@ -176,5 +176,17 @@ impl JmapConfig {
             Capability::Quota,
             Capabilities::Empty(EmptyCapabilities::default()),
         );
+
+        // Add encryption at rest capabilities, the account status is added to each session
+        if self.encrypt {
+            self.capabilities.session.append(
+                Capability::EncryptionAtRest,
+                Capabilities::Empty(EmptyCapabilities::default()),
+            );
+            self.capabilities.account.append(
+                Capability::EncryptionAtRest,
+                Capabilities::EncryptionAtRest(EncryptionAtRestCapabilities::default()),
+            );
+        }
     }
 }
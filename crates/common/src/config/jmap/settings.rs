@@ -4,11 +4,18 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{str::FromStr, time::Duration};
+use std::{str::FromStr, sync::Arc, time::Duration};
 
+use ahash::AHashSet;
 use jmap_proto::request::capability::BaseCapabilities;
 use nlp::language::Language;
-use utils::config::{Config, Rate, cron::SimpleCron, utils::ParseValue};
+use utils::{
+    PublicIpResolver,
+    config::{Config, Rate, cron::SimpleCron, utils::ParseValue},
+    glob::GlobPattern,
+};
+
+use crate::auth::impersonate::ImpersonationConfig;
 
 #[derive(Default, Clone)]
 pub struct JmapConfig {
@@ -33,8 +40,14 @@ pub struct JmapConfig {
     pub upload_tmp_quota_amount: usize,
     pub upload_tmp_ttl: u64,
 
+    pub upload_scan_block_types: Vec<String>,
+    pub upload_scan_max_size: usize,
+    pub upload_scan_async_size: usize,
+    pub upload_scan_max_age: Duration,
+
     pub mailbox_max_depth: usize,
     pub mailbox_name_max_len: usize,
+    pub thread_max_tree_nodes: usize,
     pub mail_attachments_max_size: usize,
     pub mail_parse_max_items: usize,
     pub mail_max_size: usize,
@@ -61,15 +74,80 @@ pub struct JmapConfig {
 
     pub fallback_admin: Option<(String, String)>,
     pub master_user: Option<(String, String)>,
+    pub impersonation: ImpersonationConfig,
+    pub protocol_disconnect: bool,
 
     pub default_folders: Vec<DefaultFolder>,
     pub shared_folder: String,
 
     pub http_headers: Vec<(hyper::header::HeaderName, hyper::header::HeaderValue)>,
     pub http_use_forwarded: bool,
+    pub http_keep_alive: bool,
+    pub http_keep_alive_timeout: Option<Duration>,
+    pub http_max_requests: Option<u64>,
+    pub http2_enable: bool,
+    pub http2_max_concurrent_streams: Option<u32>,
+    pub http2_keep_alive_interval: Option<Duration>,
+    pub http2_keep_alive_timeout: Duration,
+    pub http_client_cert_require: Vec<String>,
+    pub http_client_cert_match_email: bool,
 
     pub encrypt: bool,
     pub encrypt_append: bool,
+    pub encrypt_existing_rate: Option<Rate>,
+    pub encrypt_wkd_timeout: Duration,
+    pub encrypt_wkd_max_size: usize,
+    pub encrypt_max_size: usize,
+    pub encrypt_pgp_signing_key: Option<Vec<u8>>,
+    pub encrypt_pgp_require_user_id: bool,
+    pub encrypt_failure_alert_after: u64,
+    pub encrypt_failure_notify: Vec<String>,
+
+    pub index_backlog_alert: Option<u64>,
+    pub index_backlog_max: Option<u64>,
+
+    pub ingest_hook_enable: bool,
+    pub ingest_hook_domains_allow: AHashSet<String>,
+    pub ingest_hook_domains_deny: AHashSet<String>,
+    pub ingest_hook_max_body: usize,
+    pub ingest_hook_client: reqwest::Client,
+    pub ingest_hook_allow_private_ips: bool,
+    pub ingest_hook_attempts_max: u32,
+    pub ingest_hook_retry_interval: Duration,
+    pub ingest_hook_dead_letter_max: usize,
+
+    pub mail_pack_enable: bool,
+    pub mail_pack_frequency: SimpleCron,
+    pub mail_pack_min_age: Duration,
+    pub mail_pack_max_message_size: usize,
+    pub mail_pack_max_size: usize,
+    pub mail_pack_min_messages: usize,
+    pub mail_pack_rewrite_threshold: f64,
+    pub mail_pack_max_deletions: u64,
+    pub mail_pack_deletions_window: Duration,
+
+    pub remote_content_enable: bool,
+    pub remote_content_max_size: usize,
+    pub remote_content_timeout: Duration,
+    pub remote_content_cache_ttl: Duration,
+    pub remote_content_types: Vec<String>,
+    pub remote_content_rate: Option<Rate>,
+    pub remote_content_allow_private_ips: bool,
+
+    pub mail_integrity_seal_key: Option<String>,
+    pub mail_audit_enable: bool,
+    pub mail_audit_frequency: SimpleCron,
+    pub mail_audit_sample_size: usize,
+    pub mail_audit_max_bytes: usize,
+    pub mail_audit_rate: u64,
+
+    pub mail_loop_max_deliveries: Option<u64>,
+    pub mail_loop_window: Duration,
+
+    pub mail_catch_all_auto_file: bool,
+    pub mail_catch_all_folder: String,
+    pub mail_catch_all_max_mailboxes: usize,
+    pub mail_catch_all_reply_from: Vec<GlobPattern>,
 
     pub capabilities: BaseCapabilities,
     pub account_purge_frequency: SimpleCron,
@@ -101,6 +179,13 @@ pub enum SpecialUse {
 }
 
 impl JmapConfig {
+    pub fn is_ingest_hook_allowed(&self, domain: &str) -> bool {
+        self.ingest_hook_enable
+            && !self.ingest_hook_domains_deny.contains(domain)
+            && (self.ingest_hook_domains_allow.is_empty()
+                || self.ingest_hook_domains_allow.contains(domain))
+    }
+
     pub fn parse(config: &mut Config) -> Self {
         // Parse HTTP headers
         let mut http_headers = config
@@ -275,10 +360,26 @@ impl JmapConfig {
                 .property_or_default::<Duration>("jmap.protocol.upload.ttl", "1h")
                 .unwrap_or_else(|| Duration::from_secs(3600))
                 .as_secs(),
+            upload_scan_block_types: config
+                .values("jmap.protocol.upload.scan.block-types")
+                .map(|(_, v)| v.trim().to_ascii_lowercase())
+                .collect(),
+            upload_scan_max_size: config
+                .property_or_default("jmap.protocol.upload.scan.max-size", "0")
+                .unwrap_or(0),
+            upload_scan_async_size: config
+                .property_or_default("jmap.protocol.upload.scan.async-size", "10485760")
+                .unwrap_or(10485760),
+            upload_scan_max_age: config
+                .property_or_default::<Duration>("jmap.protocol.upload.scan.max-age", "1d")
+                .unwrap_or_else(|| Duration::from_secs(86400)),
             mailbox_max_depth: config.property("jmap.mailbox.max-depth").unwrap_or(10),
             mailbox_name_max_len: config
                 .property("jmap.mailbox.max-name-length")
                 .unwrap_or(255),
+            thread_max_tree_nodes: config
+                .property("jmap.thread.max-tree-nodes")
+                .unwrap_or(1000),
             mail_attachments_max_size: config
                 .property("jmap.email.max-attachment-size")
                 .unwrap_or(50000000),
@@ -321,7 +422,214 @@ impl JmapConfig {
             encrypt_append: config
                 .property_or_default("email.encryption.append", "false")
                 .unwrap_or(false),
+            encrypt_existing_rate: config
+                .property_or_default::<Option<Rate>>(
+                    "email.encryption.existing.rate-limit",
+                    "50/1s",
+                )
+                .unwrap_or_default(),
+            encrypt_wkd_timeout: config
+                .property_or_default("email.encryption.wkd.timeout", "10s")
+                .unwrap_or_else(|| Duration::from_secs(10)),
+            encrypt_wkd_max_size: config
+                .property_or_default("email.encryption.wkd.max-size", "65536")
+                .unwrap_or(65536),
+            encrypt_max_size: config
+                .property_or_default("email.encryption.max-size", "52428800")
+                .unwrap_or(52428800),
+            encrypt_pgp_signing_key: config
+                .value("email.encryption.pgp.signing-key")
+                .map(|key| key.as_bytes().to_vec()),
+            encrypt_pgp_require_user_id: config
+                .property_or_default("email.encryption.pgp.require-user-id", "true")
+                .unwrap_or(true),
+            encrypt_failure_alert_after: config
+                .property_or_default("email.encryption.failure.alert-after", "3")
+                .unwrap_or(3),
+            encrypt_failure_notify: config
+                .values("email.encryption.failure.notify")
+                .map(|(_, v)| v.to_lowercase())
+                .collect(),
+            index_backlog_alert: config
+                .property_or_default::<Option<u64>>("storage.full-text.backlog.alert", "10000")
+                .unwrap_or_default(),
+            index_backlog_max: config
+                .property_or_default::<Option<u64>>(
+                    "storage.full-text.backlog.max-pending",
+                    "false",
+                )
+                .unwrap_or_default(),
+            ingest_hook_enable: config
+                .property_or_default("email.ingest-hook.enable", "false")
+                .unwrap_or(false),
+            ingest_hook_domains_allow: config
+                .values("email.ingest-hook.domains.allow")
+                .map(|(_, v)| v.to_lowercase())
+                .collect(),
+            ingest_hook_domains_deny: config
+                .values("email.ingest-hook.domains.deny")
+                .map(|(_, v)| v.to_lowercase())
+                .collect(),
+            ingest_hook_max_body: config
+                .property_or_default("email.ingest-hook.max-body-size", "65536")
+                .unwrap_or(65536),
+            ingest_hook_client: ingest_hook_client(
+                config
+                    .property_or_default("email.ingest-hook.timeout", "10s")
+                    .unwrap_or_else(|| Duration::from_secs(10)),
+                config
+                    .property_or_default("email.ingest-hook.allow-private-ips", "false")
+                    .unwrap_or(false),
+            ),
+            ingest_hook_allow_private_ips: config
+                .property_or_default("email.ingest-hook.allow-private-ips", "false")
+                .unwrap_or(false),
+            ingest_hook_attempts_max: config
+                .property_or_default("email.ingest-hook.attempts.max", "5")
+                .unwrap_or(5),
+            ingest_hook_retry_interval: config
+                .property_or_default("email.ingest-hook.attempts.interval", "30s")
+                .unwrap_or_else(|| Duration::from_secs(30)),
+            ingest_hook_dead_letter_max: config
+                .property_or_default("email.ingest-hook.dead-letter.max", "100")
+                .unwrap_or(100),
+            mail_pack_enable: config
+                .property_or_default("email.packing.enable", "false")
+                .unwrap_or(false),
+            mail_pack_frequency: config
+                .property_or_default::<SimpleCron>("email.packing.frequency", "30 3 *")
+                .unwrap_or_else(|| SimpleCron::parse_value("30 3 *").unwrap()),
+            mail_pack_min_age: config
+                .property_or_default("email.packing.min-age", "30d")
+                .unwrap_or_else(|| Duration::from_secs(30 * 86400)),
+            mail_pack_max_message_size: config
+                .property_or_default("email.packing.max-message-size", "32768")
+                .unwrap_or(32768),
+            mail_pack_max_size: config
+                .property_or_default("email.packing.max-pack-size", "8388608")
+                .unwrap_or(8388608),
+            mail_pack_min_messages: config
+                .property_or_default("email.packing.min-messages", "16")
+                .unwrap_or(16),
+            mail_pack_rewrite_threshold: config
+                .property_or_default::<f64>("email.packing.rewrite-threshold", "0.5")
+                .unwrap_or(0.5)
+                .clamp(0.01, 1.0),
+            mail_pack_max_deletions: config
+                .property_or_default("email.packing.deletions.max", "1000")
+                .unwrap_or(1000),
+            mail_pack_deletions_window: config
+                .property_or_default("email.packing.deletions.window", "7d")
+                .unwrap_or_else(|| Duration::from_secs(7 * 86400)),
+            remote_content_enable: config
+                .property_or_default("email.remote-content.enable", "false")
+                .unwrap_or(false),
+            remote_content_max_size: config
+                .property_or_default("email.remote-content.max-size", "2097152")
+                .unwrap_or(2097152),
+            remote_content_timeout: config
+                .property_or_default("email.remote-content.timeout", "10s")
+                .unwrap_or_else(|| Duration::from_secs(10)),
+            remote_content_cache_ttl: config
+                .property_or_default("email.remote-content.cache-ttl", "1d")
+                .unwrap_or_else(|| Duration::from_secs(86400)),
+            remote_content_types: {
+                let content_types = config
+                    .values("email.remote-content.content-types")
+                    .map(|(_, v)| v.to_lowercase())
+                    .collect::<Vec<_>>();
+                if !content_types.is_empty() {
+                    content_types
+                } else {
+                    [
+                        "image/png",
+                        "image/jpeg",
+                        "image/gif",
+                        "image/webp",
+                        "image/avif",
+                        "image/bmp",
+                    ]
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+                }
+            },
+            remote_content_rate: config
+                .property_or_default::<Option<Rate>>("email.remote-content.rate-limit", "500/1h")
+                .unwrap_or_default(),
+            remote_content_allow_private_ips: config
+                .property_or_default("email.remote-content.allow-private-ips", "false")
+                .unwrap_or(false),
+            mail_integrity_seal_key: config
+                .value("email.integrity.seal-key")
+                .filter(|key| !key.is_empty())
+                .map(|key| key.to_string()),
+            mail_audit_enable: config
+                .property_or_default("email.integrity.audit.enable", "false")
+                .unwrap_or(false),
+            mail_audit_frequency: config
+                .property_or_default::<SimpleCron>("email.integrity.audit.frequency", "0 4 *")
+                .unwrap_or_else(|| SimpleCron::parse_value("0 4 *").unwrap()),
+            mail_audit_sample_size: config
+                .property_or_default("email.integrity.audit.sample-size", "1000")
+                .unwrap_or(1000),
+            mail_audit_max_bytes: config
+                .property_or_default("email.integrity.audit.max-bytes", "268435456")
+                .unwrap_or(268435456),
+            mail_audit_rate: config
+                .property_or_default::<u64>("email.integrity.audit.rate", "4194304")
+                .unwrap_or(4194304)
+                .max(1),
+            mail_loop_max_deliveries: config
+                .property_or_default::<Option<u64>>("email.loop.max-deliveries", "5")
+                .unwrap_or(Some(5)),
+            mail_loop_window: config
+                .property_or_default("email.loop.window", "1d")
+                .unwrap_or_else(|| Duration::from_secs(86400)),
+            mail_catch_all_auto_file: config
+                .property_or_default("email.catch-all.auto-file", "false")
+                .unwrap_or(false),
+            mail_catch_all_folder: config
+                .value("email.catch-all.folder")
+                .unwrap_or("Catch-all")
+                .trim_matches('/')
+                .to_string(),
+            mail_catch_all_max_mailboxes: config
+                .property_or_default("email.catch-all.max-mailboxes", "100")
+                .unwrap_or(100),
+            mail_catch_all_reply_from: config
+                .values("email.catch-all.reply-from")
+                .map(|(_, pattern)| GlobPattern::compile(pattern, true))
+                .collect(),
             http_use_forwarded: config.property("http.use-x-forwarded").unwrap_or(false),
+            http_keep_alive: config
+                .property_or_default("http.keep-alive.enable", "true")
+                .unwrap_or(true),
+            http_keep_alive_timeout: config
+                .property_or_default::<Option<Duration>>("http.keep-alive.timeout", "30s")
+                .unwrap_or(Some(Duration::from_secs(30))),
+            http_max_requests: config
+                .property_or_default::<Option<u64>>("http.keep-alive.max-requests", "1000")
+                .unwrap_or(Some(1000)),
+            http2_enable: config
+                .property_or_default("http.http2.enable", "true")
+                .unwrap_or(true),
+            http2_max_concurrent_streams: config
+                .property_or_default::<Option<u32>>("http.http2.max-concurrent-streams", "100")
+                .unwrap_or(Some(100)),
+            http2_keep_alive_interval: config
+                .property_or_default::<Option<Duration>>("http.http2.keep-alive.interval", "1m")
+                .unwrap_or(Some(Duration::from_secs(60))),
+            http2_keep_alive_timeout: config
+                .property_or_default("http.http2.keep-alive.timeout", "20s")
+                .unwrap_or_else(|| Duration::from_secs(20)),
+            http_client_cert_require: config
+                .values("http.client-certificate.require")
+                .map(|(_, path)| path.to_string())
+                .collect(),
+            http_client_cert_match_email: config
+                .property_or_default("http.client-certificate.match-email", "false")
+                .unwrap_or(false),
             http_headers,
             push_attempt_interval: config
                 .property_or_default("jmap.push.attempts.interval", "1m")
@@ -356,6 +664,10 @@ impl JmapConfig {
                     .value("authentication.master.secret")
                     .map(|p| (u.to_string(), p.to_string()))
             }),
+            impersonation: ImpersonationConfig::parse(config),
+            protocol_disconnect: config
+                .property_or_default("authentication.protocols.disconnect", "false")
+                .unwrap_or(false),
             default_folders,
             shared_folder,
         };
@@ -366,6 +678,24 @@ impl JmapConfig {
     }
 }
 
+// Hooks are sent to user supplied URLs, so one client without redirects is shared
+// by all deliveries and it refuses to connect to addresses that are not public
+fn ingest_hook_client(timeout: Duration, allow_private_ips: bool) -> reqwest::Client {
+    let builder = reqwest::Client::builder()
+        .timeout(timeout)
+        .redirect(reqwest::redirect::Policy::none());
+    let builder = if allow_private_ips {
+        builder
+    } else {
+        builder.dns_resolver(Arc::new(PublicIpResolver))
+    };
+
+    #[cfg(feature = "test_mode")]
+    let builder = builder.danger_accept_invalid_certs(true);
+
+    builder.build().unwrap_or_default()
+}
+
 impl ParseValue for SpecialUse {
     fn parse_value(value: &str) -> Result<Self, String> {
         hashify::tiny_map_ignore_case!(value.as_bytes(),
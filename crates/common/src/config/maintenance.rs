@@ -0,0 +1,143 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use ahash::AHashMap;
+use chrono::{DateTime, Local};
+use utils::config::{Config, cron::TimeWindow};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MaintenanceTask {
+    PurgeAccounts,
+    PurgeStore,
+    BlobPack,
+    IntegrityAudit,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MaintenanceConfig {
+    pub blackout: Vec<TimeWindow>,
+    pub max_concurrency: Option<usize>,
+    pub tasks: AHashMap<MaintenanceTask, TaskPolicy>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TaskPolicy {
+    pub windows: Vec<TimeWindow>,
+    pub critical: bool,
+    pub max_concurrency: Option<usize>,
+    pub max_duration: Option<Duration>,
+}
+
+static DEFAULT_POLICY: TaskPolicy = TaskPolicy {
+    windows: Vec::new(),
+    critical: false,
+    max_concurrency: None,
+    max_duration: None,
+};
+
+impl MaintenanceTask {
+    pub const ALL: [MaintenanceTask; 4] = [
+        MaintenanceTask::PurgeAccounts,
+        MaintenanceTask::PurgeStore,
+        MaintenanceTask::BlobPack,
+        MaintenanceTask::IntegrityAudit,
+    ];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            MaintenanceTask::PurgeAccounts => "purge-accounts",
+            MaintenanceTask::PurgeStore => "purge-store",
+            MaintenanceTask::BlobPack => "blob-pack",
+            MaintenanceTask::IntegrityAudit => "integrity-audit",
+        }
+    }
+
+    pub fn parse(id: &str) -> Option<Self> {
+        MaintenanceTask::ALL
+            .into_iter()
+            .find(|task| task.id() == id)
+    }
+}
+
+impl MaintenanceConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut maintenance = MaintenanceConfig {
+            blackout: config
+                .properties::<TimeWindow>("maintenance.blackout")
+                .into_iter()
+                .map(|(_, window)| window)
+                .collect(),
+            max_concurrency: config
+                .property::<usize>("maintenance.max-concurrency")
+                .filter(|max| *max > 0),
+            tasks: AHashMap::new(),
+        };
+
+        for task in MaintenanceTask::ALL {
+            let id = task.id();
+            maintenance.tasks.insert(
+                task,
+                TaskPolicy {
+                    windows: config
+                        .properties::<TimeWindow>(("maintenance.task", id, "window"))
+                        .into_iter()
+                        .map(|(_, window)| window)
+                        .collect(),
+                    critical: config
+                        .property_or_default(("maintenance.task", id, "critical"), "false")
+                        .unwrap_or(false),
+                    max_concurrency: config
+                        .property::<usize>(("maintenance.task", id, "max-concurrency"))
+                        .filter(|max| *max > 0),
+                    max_duration: config.property(("maintenance.task", id, "max-duration")),
+                },
+            );
+        }
+
+        maintenance
+    }
+
+    pub fn policy(&self, task: MaintenanceTask) -> &TaskPolicy {
+        self.tasks.get(&task).unwrap_or(&DEFAULT_POLICY)
+    }
+
+    // Tasks run inside any of their windows, blackout periods only allow critical tasks
+    pub fn is_allowed(&self, task: MaintenanceTask, time: &DateTime<Local>) -> bool {
+        let policy = self.policy(task);
+        (policy.windows.is_empty() || policy.windows.iter().any(|window| window.contains(time)))
+            && (policy.critical || !self.blackout.iter().any(|window| window.contains(time)))
+    }
+
+    // Returns how long a task has to wait until it is allowed to run, None if it can run now
+    pub fn time_to_window(&self, task: MaintenanceTask, now: DateTime<Local>) -> Option<Duration> {
+        if self.is_allowed(task, &now) {
+            return None;
+        }
+
+        let policy = self.policy(task);
+        let mut candidates = policy
+            .windows
+            .iter()
+            .flat_map(|window| window.occurrences(now, 8).map(|(start, _)| start))
+            .chain(
+                self.blackout
+                    .iter()
+                    .flat_map(|window| window.occurrences(now, 8).map(|(_, end)| end)),
+            )
+            .filter(|time| *time > now)
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+        candidates
+            .into_iter()
+            .find(|time| self.is_allowed(task, time))
+            .and_then(|time| (time - now).to_std().ok())
+            .unwrap_or(Duration::from_secs(86400))
+            .into()
+    }
+}
@@ -32,6 +32,7 @@ pub mod groupware;
 pub mod imap;
 pub mod inner;
 pub mod jmap;
+pub mod maintenance;
 pub mod network;
 pub mod scripts;
 pub mod server;
@@ -189,6 +190,9 @@ impl Core {
             )
         }
 
+        // Blobs moved into packs are located using the data store
+        blob = blob.with_packs(data.clone());
+
         Self {
             #[cfg(feature = "enterprise")]
             enterprise,
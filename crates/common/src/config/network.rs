@@ -6,12 +6,15 @@
 
 use std::time::Duration;
 
-use crate::expr::{if_block::IfBlock, tokenizer::TokenMap};
+use crate::{
+    expr::{if_block::IfBlock, tokenizer::TokenMap},
+    listener::tickets::TicketKeySettings,
+};
 use ahash::AHashSet;
 
 use utils::config::{Config, Rate};
 
-use super::*;
+use super::{maintenance::MaintenanceConfig, *};
 
 #[derive(Clone)]
 pub struct Network {
@@ -24,6 +27,25 @@ pub struct Network {
     pub http_response_url: IfBlock,
     pub http_allowed_endpoint: IfBlock,
     pub asn_geo_lookup: AsnGeoLookupConfig,
+    pub public_suffix: Option<PublicSuffixConfig>,
+    pub eval_limits: EvalLimits,
+    pub maintenance: MaintenanceConfig,
+    pub tls_tickets: TicketKeySettings,
+}
+
+#[derive(Clone)]
+pub struct PublicSuffixConfig {
+    pub urls: Vec<String>,
+    pub refresh: Duration,
+    pub timeout: Duration,
+    pub max_size: usize,
+}
+
+#[derive(Clone)]
+pub struct EvalLimits {
+    pub max_calls: u32,
+    pub call_timeout: Duration,
+    pub timeout: Duration,
 }
 
 #[derive(Clone)]
@@ -102,6 +124,10 @@ impl Default for Network {
             ),
             http_allowed_endpoint: IfBlock::new::<()>("http.allowed-endpoint", [], "200"),
             asn_geo_lookup: AsnGeoLookupConfig::Disabled,
+            public_suffix: None,
+            eval_limits: EvalLimits::default(),
+            maintenance: MaintenanceConfig::default(),
+            tls_tickets: TicketKeySettings::default(),
             server_name: Default::default(),
             report_domain: Default::default(),
             roles: ClusterRoles {
@@ -205,6 +231,10 @@ impl Network {
             security: Security::parse(config),
             contact_form: ContactForm::parse(config),
             asn_geo_lookup: AsnGeoLookupConfig::parse(config).unwrap_or_default(),
+            public_suffix: PublicSuffixConfig::parse(config),
+            eval_limits: EvalLimits::parse(config),
+            maintenance: MaintenanceConfig::parse(config),
+            tls_tickets: TicketKeySettings::parse(config),
             ..Default::default()
         };
         let token_map = &TokenMap::default().with_variables(HTTP_VARS);
@@ -252,6 +282,56 @@ impl Network {
     }
 }
 
+impl EvalLimits {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = EvalLimits::default();
+        EvalLimits {
+            max_calls: config
+                .property("expression.limits.max-calls")
+                .unwrap_or(default.max_calls),
+            call_timeout: config
+                .property("expression.limits.call-timeout")
+                .unwrap_or(default.call_timeout),
+            timeout: config
+                .property("expression.limits.timeout")
+                .unwrap_or(default.timeout),
+        }
+    }
+}
+
+impl Default for EvalLimits {
+    fn default() -> Self {
+        Self {
+            max_calls: 20,
+            call_timeout: Duration::from_secs(5),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl PublicSuffixConfig {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        // Without mirrors the compiled-in list is used
+        let urls = config
+            .values("public-suffix.urls")
+            .map(|(_, v)| v.to_string())
+            .collect::<Vec<_>>();
+        if urls.is_empty() {
+            return None;
+        }
+
+        PublicSuffixConfig {
+            urls,
+            refresh: config.property_or_default::<Duration>("public-suffix.refresh", "1d")?,
+            timeout: config.property_or_default::<Duration>("public-suffix.timeout", "1m")?,
+            max_size: config
+                .property("public-suffix.max-size")
+                .unwrap_or(10 * 1024 * 1024),
+        }
+        .into()
+    }
+}
+
 impl AsnGeoLookupConfig {
     pub fn parse(config: &mut Config) -> Option<Self> {
         match config.value("asn.type")? {
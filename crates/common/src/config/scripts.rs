@@ -13,6 +13,7 @@ use utils::config::Config;
 
 use crate::{
     VERSION_PUBLIC,
+    expr::{V_RECIPIENT, V_RECIPIENT_DOMAIN, V_SENDER, V_SENDER_DOMAIN},
     scripts::{
         functions::{register_functions_trusted, register_functions_untrusted},
         plugins::RegisterSievePlugins,
@@ -29,6 +30,7 @@ pub struct Scripting {
     pub from_name: IfBlock,
     pub return_path: IfBlock,
     pub sign: IfBlock,
+    pub prelude: IfBlock,
     pub trusted_scripts: AHashMap<String, Arc<Sieve>>,
     pub untrusted_scripts: AHashMap<String, Arc<Sieve>>,
 }
@@ -351,6 +353,17 @@ impl Scripting {
                     )
                 },
             ),
+            prelude: IfBlock::try_parse(
+                config,
+                "sieve.untrusted.prelude",
+                &TokenMap::default().with_variables(&[
+                    V_RECIPIENT,
+                    V_RECIPIENT_DOMAIN,
+                    V_SENDER,
+                    V_SENDER_DOMAIN,
+                ]),
+            )
+            .unwrap_or_else(|| IfBlock::empty("sieve.untrusted.prelude")),
             untrusted_scripts,
             trusted_scripts,
         }
@@ -378,6 +391,7 @@ impl Default for Scripting {
                     "'ed25519-' + config_get('report.domain')]"
                 ),
             ),
+            prelude: IfBlock::empty("sieve.untrusted.prelude"),
             untrusted_scripts: AHashMap::new(),
             trusted_scripts: AHashMap::new(),
         }
@@ -394,6 +408,7 @@ impl Clone for Scripting {
             from_name: self.from_name.clone(),
             return_path: self.return_path.clone(),
             sign: self.sign.clone(),
+            prelude: self.prelude.clone(),
             trusted_scripts: self.trusted_scripts.clone(),
             untrusted_scripts: self.untrusted_scripts.clone(),
         }
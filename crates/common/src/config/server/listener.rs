@@ -4,12 +4,17 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{io::Cursor, net::SocketAddr, sync::Arc, time::Duration};
 
 use rustls::{
-    ALL_VERSIONS, ServerConfig, SupportedCipherSuite,
-    crypto::ring::{ALL_CIPHER_SUITES, default_provider},
+    ALL_VERSIONS, RootCertStore, ServerConfig, SupportedCipherSuite,
+    crypto::{
+        CryptoProvider,
+        ring::{ALL_CIPHER_SUITES, default_provider},
+    },
+    server::{WebPkiClientVerifier, danger::ClientCertVerifier},
 };
+use rustls_pemfile::certs;
 
 use tokio::net::TcpSocket;
 use tokio_rustls::TlsAcceptor;
@@ -23,11 +28,11 @@ use utils::{
 
 use crate::{
     Inner,
-    listener::{TcpAcceptor, tls::CertificateResolver},
+    listener::{TcpAcceptor, tickets::SessionTicketer, tls::CertificateResolver},
 };
 
 use super::{
-    Listener, Listeners, ServerProtocol, TcpListener,
+    Listener, ListenerBanner, Listeners, ServerProtocol, TcpListener,
     tls::{TLS12_VERSION, TLS13_VERSION},
 };
 
@@ -196,6 +201,9 @@ impl Listeners {
             proxy_networks.push(network);
         }
 
+        // Parse greeting and capability settings
+        let banner = parse_banner(config, id, protocol);
+
         let span_id_gen = self.span_id_gen.clone();
         self.servers.push(Listener {
             max_connections: config
@@ -210,11 +218,12 @@ impl Listeners {
             listeners,
             proxy_networks,
             span_id_gen,
+            banner,
         });
     }
 
     pub fn parse_tcp_acceptors(&mut self, config: &mut Config, inner: Arc<Inner>) {
-        let resolver = Arc::new(CertificateResolver::new(inner.clone()));
+        let resolver = Arc::new(CertificateResolver::new(inner.clone(), None));
 
         for id_ in config
             .sub_keys("server.listener", ".protocol")
@@ -251,7 +260,99 @@ impl Listeners {
                     );
                 }
 
-                // Parse cipher suites
+                // Parse the allowed range of protocol versions
+                let mut version_range = [0x0303, 0x0304];
+                let mut has_version_range = false;
+                let mut version_err = None;
+                for (pos, key) in ["tls.min-version", "tls.max-version"]
+                    .into_iter()
+                    .enumerate()
+                {
+                    if let Some(value) =
+                        config.value_or_else(("server.listener", id, key), ("server.tls", key))
+                    {
+                        match parse_tls_version(value) {
+                            Ok(version) => {
+                                version_range[pos] = version;
+                                has_version_range = true;
+                            }
+                            Err(err) => {
+                                version_err = (key, err).into();
+                                break;
+                            }
+                        }
+                    }
+                }
+                let [min_version, max_version] = version_range;
+                if let Some((key, err)) = version_err {
+                    self.reject_tls_listener(config, id, key, err);
+                    continue;
+                } else if min_version > max_version {
+                    self.reject_tls_listener(
+                        config,
+                        id,
+                        "tls.min-version",
+                        format!(
+                            "Minimum TLS version {} is higher than maximum version {}",
+                            tls_version_name(min_version),
+                            tls_version_name(max_version)
+                        ),
+                    );
+                    continue;
+                }
+                tls_v2 &= min_version <= 0x0303;
+                tls_v3 &= max_version >= 0x0304;
+                if has_version_range && !tls_v2 && !tls_v3 {
+                    self.reject_tls_listener(
+                        config,
+                        id,
+                        "tls.min-version",
+                        format!(
+                            "All TLS versions between {} and {} are disabled",
+                            tls_version_name(min_version),
+                            tls_version_name(max_version)
+                        ),
+                    );
+                    continue;
+                }
+                let versions = if tls_v3 == tls_v2 {
+                    ALL_VERSIONS
+                } else if tls_v3 {
+                    TLS13_VERSION
+                } else {
+                    TLS12_VERSION
+                };
+
+                // Parse cipher suites, an explicit list also sets the preference order
+                let cipher_keys = if config
+                    .value(("server.listener", id, "tls.ciphers"))
+                    .is_some()
+                    || config.has_prefix(("server.listener", id, "tls.ciphers"))
+                {
+                    ("server.listener", id, "tls.ciphers").as_key()
+                } else {
+                    "server.tls.ciphers".as_key()
+                };
+                let mut ciphers: Vec<SupportedCipherSuite> = Vec::new();
+                let mut cipher_err = None;
+                for (_, cipher) in config.values(cipher_keys) {
+                    match SupportedCipherSuite::parse_value(cipher) {
+                        Ok(cipher) => {
+                            if !ciphers.contains(&cipher) {
+                                ciphers.push(cipher);
+                            }
+                        }
+                        Err(_) => {
+                            cipher_err = format!("Unknown cipher suite {cipher:?}").into();
+                            break;
+                        }
+                    }
+                }
+                if let Some(cipher_err) = cipher_err {
+                    self.reject_tls_listener(config, id, "tls.ciphers", cipher_err);
+                    continue;
+                }
+
                 let mut disabled_ciphers: Vec<SupportedCipherSuite> = Vec::new();
                 let cipher_keys =
                     if config.has_prefix(("server.listener", id, "tls.disable-ciphers")) {
@@ -265,32 +366,91 @@ impl Listeners {
 
                 // Build cert provider
                 let mut provider = default_provider();
-                if !disabled_ciphers.is_empty() {
-                    provider.cipher_suites = ALL_CIPHER_SUITES
-                        .iter()
+                if !ciphers.is_empty() || !disabled_ciphers.is_empty() {
+                    if ciphers.is_empty() {
+                        ciphers = ALL_CIPHER_SUITES.to_vec();
+                    }
+                    provider.cipher_suites = ciphers
+                        .into_iter()
                         .filter(|suite| !disabled_ciphers.contains(suite))
-                        .copied()
                         .collect();
+                    if !provider.cipher_suites.iter().any(|suite| {
+                        versions
+                            .iter()
+                            .any(|version| version.version == suite.version().version)
+                    }) {
+                        self.reject_tls_listener(
+                            config,
+                            id,
+                            "tls.ciphers",
+                            "None of the configured cipher suites can be used with the enabled TLS versions",
+                        );
+                        continue;
+                    }
                 }
+                let provider = Arc::new(provider);
+
+                // Request client certificates signed by the configured CAs, these are
+                // optional at the TLS layer and enforced by each endpoint
+                let client_verifier = if let Some(ca) = config
+                    .value(("server.listener", id, "tls.client-auth.ca"))
+                    .map(|ca| ca.as_bytes().to_vec())
+                {
+                    match build_client_verifier(ca, provider.clone()) {
+                        Ok(verifier) => Some(verifier),
+                        Err(err) => {
+                            config.new_build_error(
+                                ("server.listener", id, "tls.client-auth.ca"),
+                                err,
+                            );
+                            None
+                        }
+                    }
+                } else {
+                    None
+                };
+
+                // Listeners referencing a certificate or ACME provider serve its
+                // certificates before the ones shared by all listeners
+                let listener_resolver = match config
+                    .value(("server.listener", id, "tls.certificate"))
+                    .map(|cert_id| cert_id.to_string())
+                {
+                    Some(cert_id) if is_certificate_source(config, &cert_id) => {
+                        Arc::new(CertificateResolver::new(inner.clone(), id_.clone().into()))
+                    }
+                    Some(cert_id) => {
+                        self.reject_tls_listener(
+                            config,
+                            id,
+                            "tls.certificate",
+                            format!("Unknown certificate or ACME provider {cert_id:?}"),
+                        );
+                        continue;
+                    }
+                    None => resolver.clone(),
+                };
 
                 // Build server config
-                let mut server_config = match ServerConfig::builder_with_provider(provider.into())
-                    .with_protocol_versions(if tls_v3 == tls_v2 {
-                        ALL_VERSIONS
-                    } else if tls_v3 {
-                        TLS13_VERSION
-                    } else {
-                        TLS12_VERSION
-                    }) {
-                    Ok(server_config) => server_config
-                        .with_no_client_auth()
-                        .with_cert_resolver(resolver.clone()),
+                let mut server_config = match ServerConfig::builder_with_provider(provider)
+                    .with_protocol_versions(versions)
+                {
+                    Ok(server_config) => {
+                        let server_config = if let Some(verifier) = client_verifier {
+                            server_config.with_client_cert_verifier(verifier)
+                        } else {
+                            server_config.with_no_client_auth()
+                        };
+                        server_config.with_cert_resolver(listener_resolver.clone())
+                    }
                     Err(err) => {
-                        config.new_build_error(
-                            ("server.listener", id, "tls"),
+                        self.reject_tls_listener(
+                            config,
+                            id,
+                            "tls",
                             format!("Failed to build TLS server config: {err}"),
                         );
-                        return;
+                        continue;
                     }
                 };
 
@@ -302,6 +462,49 @@ impl Listeners {
                     )
                     .unwrap_or(true);
 
+                // Session tickets are encrypted with keys shared by all listeners and
+                // nodes, stateful resumption is used when they are disabled
+                if config
+                    .property_or_else(
+                        ("server.listener", id, "tls.session-tickets.enable"),
+                        "server.tls.session-tickets.enable",
+                        "true",
+                    )
+                    .unwrap_or(true)
+                {
+                    server_config.ticketer = Arc::new(SessionTicketer::new(inner.clone()));
+                }
+
+                // Negotiate HTTP/2 on HTTP listeners, ACME TLS-ALPN-01 challenges are
+                // detected before the handshake and use their own configuration
+                if config.property::<ServerProtocol>(("server.listener", id, "protocol"))
+                    == Some(ServerProtocol::Http)
+                    && config
+                        .property_or_default::<bool>("http.http2.enable", "true")
+                        .unwrap_or(true)
+                {
+                    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+                }
+
+                // Explicit ALPN protocols replace the defaults
+                let alpn_key = ("server.listener", id, "tls.alpn");
+                if config.value(alpn_key).is_some() || config.has_prefix(alpn_key) {
+                    let alpn_protocols = config
+                        .values(alpn_key)
+                        .map(|(_, protocol)| protocol.trim().as_bytes().to_vec())
+                        .filter(|protocol| !protocol.is_empty())
+                        .collect::<Vec<_>>();
+                    if let Some(protocol) = alpn_protocols.iter().find(|p| p.len() > 255) {
+                        let err = format!(
+                            "ALPN protocol {:?} exceeds 255 bytes",
+                            String::from_utf8_lossy(protocol)
+                        );
+                        self.reject_tls_listener(config, id, "tls.alpn", err);
+                        continue;
+                    }
+                    server_config.alpn_protocols = alpn_protocols;
+                }
+
                 // Build acceptor
                 let default_config = Arc::new(server_config);
                 TcpAcceptor::Tls {
@@ -310,6 +513,14 @@ impl Listeners {
                     implicit: config
                         .property_or_default(("server.listener", id, "tls.implicit"), "false")
                         .unwrap_or(false),
+                    fingerprint: config
+                        .property_or_else(
+                            ("server.listener", id, "tls.fingerprint"),
+                            "server.tls.fingerprint",
+                            "false",
+                        )
+                        .unwrap_or(false),
+                    resolver: Some(listener_resolver),
                 }
             } else {
                 TcpAcceptor::Plain
@@ -318,6 +529,146 @@ impl Listeners {
             self.tcp_acceptors.insert(id_, acceptor);
         }
     }
+
+    // Listeners with an invalid TLS policy are not started rather than falling
+    // back to plain text
+    fn reject_tls_listener(
+        &mut self,
+        config: &mut Config,
+        id: &str,
+        key: &str,
+        details: impl AsRef<str>,
+    ) {
+        config.new_build_error(
+            ("server.listener", id, key),
+            format!("Listener {id:?}: {}", details.as_ref()),
+        );
+        self.servers.retain(|server| server.id != id);
+        self.tcp_acceptors.remove(id);
+    }
+}
+
+fn is_certificate_source(config: &Config, id: &str) -> bool {
+    config.value(("certificate", id, "cert")).is_some()
+        || config.value(("acme", id, "directory")).is_some()
+}
+
+fn parse_tls_version(value: &str) -> Result<u16, String> {
+    match value {
+        "TLSv1.2" | "0x0303" => Ok(0x0303),
+        "TLSv1.3" | "0x0304" => Ok(0x0304),
+        "TLSv1.0" | "0x0301" | "TLSv1.1" | "0x0302" => Err(format!(
+            "TLS protocol {value:?} is not supported, the lowest available version is TLSv1.2"
+        )),
+        _ => Err(format!("Unknown TLS protocol {value:?}")),
+    }
+}
+
+fn tls_version_name(version: u16) -> &'static str {
+    match version {
+        0x0303 => "TLSv1.2",
+        _ => "TLSv1.3",
+    }
+}
+
+fn parse_banner(config: &mut Config, id: &str, protocol: ServerProtocol) -> ListenerBanner {
+    let mut banner = ListenerBanner {
+        greeting: config
+            .value(("server.listener", id, "banner.greeting"))
+            .map(|greeting| greeting.trim().to_string())
+            .filter(|greeting| !greeting.is_empty()),
+        hide_product: config
+            .property_or_else(
+                ("server.listener", id, "banner.hide-product"),
+                "server.banner.hide-product",
+                "false",
+            )
+            .unwrap_or(false),
+        ..Default::default()
+    };
+
+    if banner
+        .greeting
+        .as_ref()
+        .is_some_and(|greeting| greeting.contains(['\r', '\n']))
+    {
+        config.new_build_error(
+            ("server.listener", id, "banner.greeting"),
+            "Greeting cannot contain line breaks",
+        );
+        banner.greeting = None;
+    }
+
+    for (option, is_tls) in [("hide-before-tls", true), ("hide-before-auth", false)] {
+        let key = ("server.listener", id, "capabilities", option);
+        for capability in config
+            .values(key)
+            .map(|(_, value)| value.trim().to_ascii_uppercase())
+            .collect::<Vec<_>>()
+        {
+            if !protocol
+                .hideable_capabilities()
+                .contains(&capability.as_str())
+            {
+                config.new_build_error(
+                    key,
+                    format!("Capability {capability:?} cannot be hidden on {protocol} listeners"),
+                );
+            } else if !is_tls && (capability.starts_with("AUTH=") || capability == "USER") {
+                // Authentication capabilities are only advertised before authentication
+                config.new_build_error(
+                    key,
+                    format!("Hiding {capability:?} until authentication would disable it"),
+                );
+            } else if is_tls {
+                banner.hide_before_tls.insert(capability);
+            } else {
+                banner.hide_before_auth.insert(capability);
+            }
+        }
+    }
+
+    // Make sure clients are still able to authenticate on listeners without TLS
+    if !banner.hide_before_tls.is_empty()
+        && !config
+            .property_or_default(("server.listener", id, "tls.enable"), "true")
+            .unwrap_or(true)
+        && protocol
+            .hideable_capabilities()
+            .iter()
+            .filter(|capability| capability.starts_with("AUTH="))
+            .all(|capability| banner.hide_before_tls.contains(*capability))
+    {
+        config.new_build_error(
+            ("server.listener", id, "capabilities.hide-before-tls"),
+            "All authentication mechanisms are hidden on a listener without TLS",
+        );
+        banner
+            .hide_before_tls
+            .retain(|capability| !capability.starts_with("AUTH="));
+    }
+
+    banner
+}
+
+fn build_client_verifier(
+    ca: Vec<u8>,
+    provider: Arc<CryptoProvider>,
+) -> Result<Arc<dyn ClientCertVerifier>, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in certs(&mut Cursor::new(ca)) {
+        roots
+            .add(cert.map_err(|err| format!("Failed to read CA certificates: {err}"))?)
+            .map_err(|err| format!("Invalid CA certificate: {err}"))?;
+    }
+    if roots.is_empty() {
+        return Err("No CA certificates found.".to_string());
+    }
+
+    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+        .allow_unauthenticated()
+        .build()
+        .map_err(|err| format!("Failed to build client certificate verifier: {err}"))
 }
 
 impl ParseValue for ServerProtocol {
@@ -6,7 +6,7 @@
 
 use std::{fmt::Display, net::SocketAddr, sync::Arc, time::Duration};
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use serde::{Deserialize, Serialize};
 use tokio::net::TcpSocket;
 use utils::{config::ipmask::IpAddrMask, snowflake::SnowflakeIdGenerator};
@@ -31,6 +31,15 @@ pub struct Listener {
     pub proxy_networks: Vec<IpAddrMask>,
     pub max_connections: u64,
     pub span_id_gen: Arc<SnowflakeIdGenerator>,
+    pub banner: ListenerBanner,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListenerBanner {
+    pub greeting: Option<String>,
+    pub hide_product: bool,
+    pub hide_before_tls: AHashSet<String>,
+    pub hide_before_auth: AHashSet<String>,
 }
 
 #[derive(Debug)]
@@ -69,6 +78,61 @@ impl ServerProtocol {
     }
 }
 
+impl ListenerBanner {
+    pub fn greeting(
+        &self,
+        hostname: &str,
+        listener: &str,
+        protocol: ServerProtocol,
+    ) -> Option<String> {
+        self.greeting.as_ref().map(|greeting| {
+            greeting
+                .replace("{hostname}", hostname)
+                .replace("{listener}", listener)
+                .replace("{protocol}", protocol.as_str())
+        })
+    }
+
+    pub fn is_hidden(&self, capability: &str, is_tls: bool, is_authenticated: bool) -> bool {
+        (!is_tls && self.hide_before_tls.contains(capability))
+            || (!is_authenticated && self.hide_before_auth.contains(capability))
+    }
+}
+
+impl ServerProtocol {
+    // Capabilities that can be hidden without breaking the protocol,
+    // the corresponding commands are rejected while the capability is hidden.
+    pub fn hideable_capabilities(&self) -> &'static [&'static str] {
+        match self {
+            ServerProtocol::Smtp | ServerProtocol::Lmtp => &[
+                "PIPELINING",
+                "VRFY",
+                "EXPN",
+                "AUTH=PLAIN",
+                "AUTH=LOGIN",
+                "AUTH=OAUTHBEARER",
+                "AUTH=XOAUTH2",
+            ],
+            ServerProtocol::Imap => &[
+                "ID",
+                "SASL-IR",
+                "JMAPACCESS",
+                "AUTH=PLAIN",
+                "AUTH=OAUTHBEARER",
+                "AUTH=XOAUTH2",
+            ],
+            ServerProtocol::Pop3 => &[
+                "USER",
+                "IMPLEMENTATION",
+                "AUTH=PLAIN",
+                "AUTH=OAUTHBEARER",
+                "AUTH=XOAUTH2",
+            ],
+            ServerProtocol::Http | ServerProtocol::ManageSieve => &[],
+        }
+    }
+}
+
 impl Display for ServerProtocol {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(self.as_str())
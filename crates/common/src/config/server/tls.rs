@@ -18,6 +18,10 @@ use base64::{
 };
 use dns_update::{DnsUpdater, TsigAlgorithm, providers::rfc2136::DnsAddress};
 use rcgen::generate_simple_self_signed;
+use ring::{
+    rand::SystemRandom,
+    signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair},
+};
 use rustls::{
     SupportedProtocolVersion,
     crypto::ring::sign::any_supported_type,
@@ -33,11 +37,18 @@ use x509_parser::{
     extensions::{GeneralName, ParsedExtension},
 };
 
-use crate::listener::{
-    acme::{
-        AcmeProvider, ChallengeSettings, EabSettings, directory::LETS_ENCRYPT_PRODUCTION_DIRECTORY,
+use crate::{
+    config::parse_http_headers,
+    listener::{
+        acme::{
+            AcmeProvider, ChallengeSettings, EabSettings,
+            crypto::AcmeStorageKeys,
+            dane::DaneSettings,
+            directory::LETS_ENCRYPT_PRODUCTION_DIRECTORY,
+            dns::{DnsChallengeUpdater, DnsWebhook},
+        },
+        tls::{AcmeProviders, insert_certificate},
     },
-    tls::AcmeProviders,
 };
 
 pub static TLS13_VERSION: &[&SupportedProtocolVersion] = &[&TLS13];
@@ -70,6 +81,11 @@ impl AcmeProviders {
                 .property_or_default(("acme", acme_id, "renew-before"), "30d")
                 .unwrap_or_else(|| Duration::from_secs(30 * 24 * 60 * 60));
 
+            // Domains failing authorization this many times are excluded from the order
+            let auth_attempts = config
+                .property_or_default::<u32>(("acme", acme_id, "auth-attempts"), "3")
+                .unwrap_or(3);
+
             if directory.is_empty() {
                 config.new_parse_error(format!("acme.{acme_id}.directory"), "Missing property");
                 continue;
@@ -80,10 +96,22 @@ impl AcmeProviders {
                 continue;
             }
 
-            // Parse challenge type
+            // Domains covered by this ACME manager
+            let domains = config
+                .values(("acme", acme_id, "domains"))
+                .map(|(_, s)| s.trim().to_string())
+                .collect::<Vec<_>>();
+
+            // Parse challenge type, wildcard domains can only be validated using DNS-01
+            let has_wildcards = domains.iter().any(|d| d.starts_with("*."));
+            let default_challenge = if has_wildcards {
+                "dns-01"
+            } else {
+                "tls-alpn-01"
+            };
             let challenge = match config
                 .value(("acme", acme_id, "challenge"))
-                .unwrap_or("tls-alpn-01")
+                .unwrap_or(default_challenge)
             {
                 "tls-alpn-01" => ChallengeSettings::TlsAlpn01,
                 "http-01" => ChallengeSettings::Http01,
@@ -115,14 +143,7 @@ impl AcmeProviders {
                 }
             };
 
-            // Domains covered by this ACME manager
-            let domains = config
-                .values(("acme", acme_id, "domains"))
-                .map(|(_, s)| s.trim().to_string())
-                .collect::<Vec<_>>();
-            if !matches!(challenge, ChallengeSettings::Dns01 { .. })
-                && domains.iter().any(|d| d.starts_with("*."))
-            {
+            if !matches!(challenge, ChallengeSettings::Dns01 { .. }) && has_wildcards {
                 config.new_parse_error(
                     ("acme", acme_id, "domains"),
                     "Wildcard domains are only supported with DNS-01 challenge",
@@ -130,34 +151,80 @@ impl AcmeProviders {
                 continue 'outer;
             }
 
-            // Obtain EAB settings
-            let eab = if let (Some(eab_kid), Some(eab_hmac_key)) = (
+            // Obtain EAB settings, required by CAs such as ZeroSSL or Google Trust Services
+            let eab = match (
                 config
                     .value(("acme", acme_id, "eab.kid"))
+                    .map(|s| s.trim())
                     .filter(|s| !s.is_empty()),
                 config
                     .value(("acme", acme_id, "eab.hmac-key"))
+                    .map(|s| s.trim())
                     .filter(|s| !s.is_empty()),
             ) {
-                if let Ok(hmac_key) =
-                    general_purpose::URL_SAFE_NO_PAD.decode(eab_hmac_key.trim().as_bytes())
-                {
-                    EabSettings {
-                        kid: eab_kid.to_string(),
-                        hmac_key,
+                (Some(eab_kid), Some(eab_hmac_key)) => {
+                    // CAs hand out the HMAC key base64url encoded but some
+                    // dashboards display it with padding or in standard base64
+                    let eab_kid = eab_kid.to_string();
+                    if let Some(hmac_key) = [
+                        general_purpose::URL_SAFE_NO_PAD,
+                        general_purpose::URL_SAFE,
+                        general_purpose::STANDARD,
+                    ]
+                    .iter()
+                    .find_map(|engine| engine.decode(eab_hmac_key.as_bytes()).ok())
+                    .filter(|hmac_key| !hmac_key.is_empty())
+                    {
+                        EabSettings {
+                            kid: eab_kid,
+                            hmac_key,
+                        }
+                        .into()
+                    } else {
+                        config.new_build_error(
+                            format!("acme.{acme_id}.eab.hmac-key"),
+                            "Failed to base64 decode HMAC key",
+                        );
+                        continue;
                     }
-                    .into()
-                } else {
-                    config.new_build_error(
+                }
+                (Some(_), None) => {
+                    config.new_parse_error(
                         format!("acme.{acme_id}.eab.hmac-key"),
-                        "Failed to base64 decode HMAC key",
+                        "Missing property, required when an EAB key id is set",
+                    );
+                    continue;
+                }
+                (None, Some(_)) => {
+                    config.new_parse_error(
+                        format!("acme.{acme_id}.eab.kid"),
+                        "Missing property, required when an EAB HMAC key is set",
                     );
-                    None
+                    continue;
+                }
+                (None, None) => None,
+            };
+
+            // Pinned account key, otherwise one is generated and kept in the store
+            let account_key = if let Some(pem) = config
+                .value(("acme", acme_id, "account-key"))
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+            {
+                match parse_account_key(pem) {
+                    Ok(account_key) => Some(account_key),
+                    Err(err) => {
+                        config.new_build_error(format!("acme.{acme_id}.account-key"), err);
+                        continue;
+                    }
                 }
             } else {
                 None
             };
 
+            // Send the TLSA records of renewed certificates to a webhook
+            let dane = parse_dane_settings(config, acme_id, &domains);
+
             // This ACME manager is the default when SNI is not available
             let default = config
                 .property::<bool>(("acme", acme_id, "default"))
@@ -171,7 +238,11 @@ impl AcmeProviders {
                     contact,
                     challenge,
                     eab,
+                    dane,
+                    certificate_listeners(config, acme_id),
+                    account_key,
                     renew_before,
+                    auth_attempts,
                     default,
                 ) {
                     Ok(acme_provider) => {
@@ -184,13 +255,101 @@ impl AcmeProviders {
             }
         }
 
-        AcmeProviders { providers }
+        // Challenge certificates are encrypted with the first key before being
+        // written to the in-memory store
+        let storage_keys = AcmeStorageKeys::new(
+            config
+                .values("acme.storage-key")
+                .map(|(_, key)| key.trim())
+                .filter(|key| !key.is_empty()),
+        );
+
+        AcmeProviders {
+            providers,
+            storage_keys,
+        }
+    }
+}
+
+fn parse_dane_settings(
+    config: &mut Config,
+    acme_id: &str,
+    domains: &[String],
+) -> Option<DaneSettings> {
+    let url = config
+        .value(("acme", acme_id, "dane.url"))
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())?
+        .to_string();
+
+    // Records are published for the certificate's domains unless the MX hosts are listed
+    let mut hosts = config
+        .values(("acme", acme_id, "dane.hosts"))
+        .map(|(_, s)| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    if hosts.is_empty() {
+        hosts = domains
+            .iter()
+            .filter(|domain| !domain.starts_with("*."))
+            .cloned()
+            .collect();
+    }
+
+    Some(DaneSettings {
+        webhook: DnsWebhook {
+            url,
+            headers: parse_http_headers(config, ("acme", acme_id, "dane")),
+            timeout: config
+                .property_or_default(("acme", acme_id, "dane.timeout"), "30s")
+                .unwrap_or_else(|| Duration::from_secs(30)),
+            tls_allow_invalid_certs: config
+                .property_or_default(("acme", acme_id, "dane.allow-invalid-certs"), "false")
+                .unwrap_or_default(),
+        },
+        hosts,
+        pre_publish: config
+            .property_or_default(("acme", acme_id, "dane.pre-publish"), "false")
+            .unwrap_or_default(),
+    })
+}
+
+fn parse_account_key(pem: &str) -> Result<Vec<u8>, String> {
+    match read_one(&mut Cursor::new(pem.as_bytes())) {
+        Ok(Some(Item::Pkcs8Key(key))) => {
+            let key = key.secret_pkcs8_der().to_vec();
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &key, &SystemRandom::new())
+                .map(|_| key)
+                .map_err(|err| format!("Account key is not a valid ECDSA P-256 key: {err}"))
+        }
+        Ok(Some(Item::Sec1Key(_))) => Err(concat!(
+            "SEC1 account keys are not supported, convert it to PKCS#8 ",
+            "using 'openssl pkcs8 -topk8 -nocrypt'"
+        )
+        .to_string()),
+        Ok(Some(_)) => Err("Account key must be an ECDSA P-256 key in PKCS#8 format".to_string()),
+        Ok(None) => Err("No private key found in PEM".to_string()),
+        Err(err) => Err(format!("Failed to read account key: {err}")),
     }
 }
 
 #[allow(clippy::unnecessary_to_owned)]
-fn build_dns_updater(config: &mut Config, acme_id: &str) -> Option<DnsUpdater> {
+fn build_dns_updater(config: &mut Config, acme_id: &str) -> Option<DnsChallengeUpdater> {
     match config.value_require(("acme", acme_id, "provider"))? {
+        "webhook" => DnsChallengeUpdater::Webhook(DnsWebhook {
+            url: config
+                .value_require(("acme", acme_id, "url"))?
+                .trim()
+                .to_string(),
+            headers: parse_http_headers(config, ("acme", acme_id)),
+            timeout: config
+                .property_or_default(("acme", acme_id, "timeout"), "30s")
+                .unwrap_or_else(|| Duration::from_secs(30)),
+            tls_allow_invalid_certs: config
+                .property_or_default(("acme", acme_id, "allow-invalid-certs"), "false")
+                .unwrap_or_default(),
+        })
+        .into(),
         "rfc2136-tsig" => {
             let algorithm: TsigAlgorithm = config
                 .value_require(("acme", acme_id, "tsig-algorithm"))?
@@ -227,6 +386,7 @@ fn build_dns_updater(config: &mut Config, acme_id: &str) -> Option<DnsUpdater> {
                 key,
                 algorithm,
             )
+            .map(DnsChallengeUpdater::Provider)
             .map_err(|err| {
                 config.new_build_error(
                     ("acme", acme_id, "provider"),
@@ -248,6 +408,7 @@ fn build_dns_updater(config: &mut Config, acme_id: &str) -> Option<DnsUpdater> {
                 config.value(("acme", acme_id, "user")).map(|s| s.trim()),
                 timeout.into(),
             )
+            .map(DnsChallengeUpdater::Provider)
             .map_err(|err| {
                 config.new_build_error(
                     ("acme", acme_id, "provider"),
@@ -338,13 +499,14 @@ pub(crate) fn parse_certificates(
                             // Add domain names
                             subject_names.extend(names.iter().cloned());
 
-                            // Add certificates
+                            // Add certificates, wildcards are stored as ".domain"
                             let cert = Arc::new(cert);
+                            let listeners = certificate_listeners(config, cert_id);
                             for name in names {
-                                certificates.insert(
-                                    name.strip_prefix("*.")
-                                        .map(|name| name.to_string())
-                                        .unwrap_or(name),
+                                insert_certificate(
+                                    certificates,
+                                    &listeners,
+                                    name.strip_prefix('*').unwrap_or(&name),
                                     cert.clone(),
                                 );
                             }
@@ -354,7 +516,7 @@ pub(crate) fn parse_certificates(
                                 .property::<bool>(("certificate", cert_id, "default"))
                                 .unwrap_or_default()
                             {
-                                certificates.insert("*".to_string(), cert.clone());
+                                insert_certificate(certificates, &listeners, "*", cert.clone());
                             }
                         }
                         Err(err) => config.new_build_error(format!("certificate.{cert_id}"), err),
@@ -366,6 +528,18 @@ pub(crate) fn parse_certificates(
     }
 }
 
+// Returns the listeners that serve the certificates of a static certificate or
+// ACME provider before the ones shared by all listeners
+pub(crate) fn certificate_listeners(config: &Config, id: &str) -> Vec<String> {
+    config
+        .sub_keys("server.listener", ".protocol")
+        .filter(|listener_id| {
+            config.value(("server.listener", *listener_id, "tls.certificate")) == Some(id)
+        })
+        .map(|listener_id| listener_id.to_string())
+        .collect()
+}
+
 pub(crate) fn build_certified_key(cert: Vec<u8>, pk: Vec<u8>) -> Result<CertifiedKey, String> {
     let cert = certs(&mut Cursor::new(cert))
         .collect::<Result<Vec<_>, _>>()
@@ -232,6 +232,11 @@ impl MailAuthConfig {
             })
             .collect();
 
+        // Validate the signed headers early, keys are parsed lazily on first use
+        for id in mail_auth.signatures.keys() {
+            parse_signed_headers(config, id);
+        }
+
         mail_auth
     }
 }
@@ -338,21 +343,8 @@ pub fn simple_pem_parse(contents: &str) -> Option<Vec<u8>> {
     base64_decode(&base64)
 }
 
-fn parse_signature<T: SigningKey, U: SigningKey<Hasher = Sha256>>(
-    config: &mut Config,
-    id: &str,
-    key_dkim: T,
-    key_arc: U,
-) -> Option<(
-    mail_auth::dkim::DkimSigner<T, Done>,
-    mail_auth::arc::ArcSealer<U, Done>,
-)> {
-    let domain = config
-        .value_require(("signature", id, "domain"))?
-        .to_string();
-    let selector = config
-        .value_require(("signature", id, "selector"))?
-        .to_string();
+/// Builds the list of headers to sign for a signature, applying exclusions and over-signing.
+fn parse_signed_headers(config: &mut Config, id: &str) -> Option<Vec<String>> {
     let mut headers = config
         .values(("signature", id, "headers"))
         .filter_map(|(_, v)| {
@@ -373,6 +365,68 @@ fn parse_signature<T: SigningKey, U: SigningKey<Hasher = Sha256>>(
         ];
     }
 
+    // Remove excluded headers, such as those rewritten by forwarders
+    let exclude = config
+        .values(("signature", id, "exclude"))
+        .map(|(_, v)| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>();
+    headers.retain(|h| !exclude.iter().any(|e| e.eq_ignore_ascii_case(h)));
+
+    // The From header must always be signed (RFC 6376, section 5.4)
+    if !headers.iter().any(|h| h.eq_ignore_ascii_case("From")) {
+        config.new_build_error(
+            ("signature", id, "headers"),
+            "The From header must be included in the list of signed headers",
+        );
+        return None;
+    }
+
+    // Over-sign headers by listing them once more than they appear in the message,
+    // which prevents additional instances from being added after signing
+    let oversign = config
+        .values(("signature", id, "oversign"))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect::<Vec<_>>();
+    for (key, value) in oversign {
+        let (name, count) = value
+            .split_once(':')
+            .map(|(name, count)| (name.trim(), count.trim().parse::<usize>().ok()))
+            .unwrap_or((value.trim(), Some(1)));
+        match count {
+            Some(count) if !name.is_empty() && count <= 10 => {
+                headers.extend(std::iter::repeat_n(name.to_string(), count));
+            }
+            _ => {
+                config.new_build_error(
+                    key,
+                    format!("Invalid over-sign value {value:?}, expected \"header[:count]\""),
+                );
+                return None;
+            }
+        }
+    }
+
+    Some(headers)
+}
+
+fn parse_signature<T: SigningKey, U: SigningKey<Hasher = Sha256>>(
+    config: &mut Config,
+    id: &str,
+    key_dkim: T,
+    key_arc: U,
+) -> Option<(
+    mail_auth::dkim::DkimSigner<T, Done>,
+    mail_auth::arc::ArcSealer<U, Done>,
+)> {
+    let domain = config
+        .value_require(("signature", id, "domain"))?
+        .to_string();
+    let selector = config
+        .value_require(("signature", id, "selector"))?
+        .to_string();
+    let mut headers = parse_signed_headers(config, id)?;
+
     let mut signer = mail_auth::dkim::DkimSigner::from_key(key_dkim)
         .domain(&domain)
         .selector(&selector)
@@ -388,8 +442,10 @@ fn parse_signature<T: SigningKey, U: SigningKey<Hasher = Sha256>>(
         .selector(selector)
         .headers(headers);
 
-    if let Some(c) = config.property::<DkimCanonicalization>(("signature", id, "canonicalization"))
-    {
+    if let Some(c) = config.property_or_default::<DkimCanonicalization>(
+        ("signature", id, "canonicalization"),
+        "relaxed/relaxed",
+    ) {
         signer = signer
             .body_canonicalization(c.body)
             .header_canonicalization(c.headers);
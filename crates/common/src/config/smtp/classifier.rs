@@ -0,0 +1,309 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use ahash::AHashMap;
+use regex::{Regex, RegexBuilder};
+use smtp_proto::{Response, Severity};
+use utils::config::{Config, utils::ParseValue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResponseCategory {
+    Greylisted,
+    RateLimited,
+    MailboxFull,
+    PolicyBlock,
+}
+
+#[derive(Clone)]
+pub struct ResponseClassifier {
+    // Configured rules are evaluated before the built-in ones
+    pub rules: Vec<ResponseRule>,
+    pub retry: AHashMap<ResponseCategory, Vec<Duration>>,
+    pub max_retry_after: Duration,
+    retry_after: Regex,
+}
+
+#[derive(Clone)]
+pub struct ResponseRule {
+    pub id: String,
+    pub category: ResponseCategory,
+    pub codes: Vec<u16>,
+    pub patterns: Vec<Regex>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseClass {
+    pub category: ResponseCategory,
+    pub rule: String,
+    pub retry_after: Option<Duration>,
+}
+
+const BUILT_IN_RULES: &[(&str, ResponseCategory, &[&str])] = &[
+    (
+        "greylist",
+        ResponseCategory::Greylisted,
+        &[r"gr[ae]y[- ]?list", r"\bpostgrey\b", r"try again in \d+"],
+    ),
+    (
+        "rate-limit",
+        ResponseCategory::RateLimited,
+        &[
+            r"rate[- ]?limit",
+            r"\bthrottl",
+            r"too many (messages|connections|recipients|emails|mails)",
+            r"unusual rate",
+            r"receiving mail at a rate",
+            r"\b4\.7\.28\b",
+            r"\[TSS?0\d\]",
+            r"server busy",
+            r"exceeded .*sending limit",
+        ],
+    ),
+    (
+        "mailbox-full",
+        ResponseCategory::MailboxFull,
+        &[
+            r"mailbox (is )?full",
+            r"over ?quota",
+            r"quota exceeded",
+            r"insufficient (system )?storage",
+            r"\b4\.2\.2\b",
+        ],
+    ),
+    (
+        "policy",
+        ResponseCategory::PolicyBlock,
+        &[
+            r"\bpolicy\b",
+            r"\bblocked\b",
+            r"\b(black|block|deny)list",
+            r"\bspamhaus\b",
+            r"\b(RBL|DNSBL)\b",
+        ],
+    ),
+];
+
+impl ResponseClassifier {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut classifier = ResponseClassifier::default();
+        let mut rules = Vec::new();
+
+        for id in config
+            .sub_keys("queue.classifier.rule", ".category")
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+        {
+            if let Some(rule) = parse_response_rule(config, &id) {
+                rules.push(rule);
+            }
+        }
+        rules.append(&mut classifier.rules);
+        classifier.rules = rules;
+
+        for category in [
+            ResponseCategory::Greylisted,
+            ResponseCategory::RateLimited,
+            ResponseCategory::MailboxFull,
+            ResponseCategory::PolicyBlock,
+        ] {
+            let schedule = config
+                .properties::<Duration>(("queue.classifier.retry", category.as_str()))
+                .into_iter()
+                .map(|(_, duration)| duration)
+                .collect::<Vec<_>>();
+            if !schedule.is_empty() {
+                classifier.retry.insert(category, schedule);
+            }
+        }
+        classifier.max_retry_after = config
+            .property_or_default::<Duration>("queue.classifier.max-retry-after", "6h")
+            .unwrap_or(classifier.max_retry_after);
+
+        classifier
+    }
+
+    // Only transient responses are classified, a permanent failure is never retried
+    pub fn classify(&self, response: &Response<String>) -> Option<ResponseClass> {
+        if response.severity() != Severity::TransientNegativeCompletion {
+            return None;
+        }
+
+        let text = format!(
+            "{} {}.{}.{} {}",
+            response.code, response.esc[0], response.esc[1], response.esc[2], response.message
+        );
+        self.rules
+            .iter()
+            .find(|rule| {
+                (rule.codes.is_empty() || rule.codes.contains(&response.code))
+                    && rule.patterns.iter().any(|pattern| pattern.is_match(&text))
+            })
+            .map(|rule| ResponseClass {
+                category: rule.category,
+                rule: rule.id.clone(),
+                retry_after: self.retry_after(&response.message),
+            })
+    }
+
+    // Returns None when the default retry schedule should be used
+    pub fn retry_delay(&self, class: &ResponseClass, retry_num: u32) -> Option<Duration> {
+        if let Some(retry_after) = class.retry_after {
+            Some(retry_after)
+        } else {
+            self.retry.get(&class.category).and_then(|schedule| {
+                schedule
+                    .get(std::cmp::min(retry_num as usize, schedule.len() - 1))
+                    .copied()
+            })
+        }
+    }
+
+    // Parses hints such as "try again in 4 hours" or "retry after 300 seconds"
+    fn retry_after(&self, message: &str) -> Option<Duration> {
+        let captures = self.retry_after.captures(message)?;
+        let amount = captures.get(1)?.as_str().parse::<u64>().ok()?;
+        let unit = captures.get(2)?.as_str().to_ascii_lowercase();
+        let seconds = if unit.starts_with('h') {
+            amount * 3600
+        } else if unit.starts_with('m') {
+            amount * 60
+        } else {
+            amount
+        };
+
+        (seconds > 0).then(|| Duration::from_secs(seconds).min(self.max_retry_after))
+    }
+}
+
+fn parse_response_rule(config: &mut Config, id: &str) -> Option<ResponseRule> {
+    if !config
+        .property::<bool>(("queue.classifier.rule", id, "enable"))
+        .unwrap_or(true)
+    {
+        return None;
+    }
+
+    let category =
+        config.property_require::<ResponseCategory>(("queue.classifier.rule", id, "category"))?;
+    let mut patterns = Vec::new();
+    for (key, value) in config
+        .values(("queue.classifier.rule", id, "match"))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect::<Vec<_>>()
+    {
+        match RegexBuilder::new(&value).case_insensitive(true).build() {
+            Ok(regex) => patterns.push(regex),
+            Err(err) => {
+                config.new_parse_error(key, format!("Invalid regular expression: {err}"));
+            }
+        }
+    }
+    if patterns.is_empty() {
+        config.new_parse_error(
+            ("queue.classifier.rule", id, "match"),
+            "At least one pattern is required",
+        );
+        return None;
+    }
+
+    let codes = config
+        .properties::<u16>(("queue.classifier.rule", id, "code"))
+        .into_iter()
+        .map(|(_, code)| code)
+        .collect::<Vec<_>>();
+    if let Some(code) = codes.iter().find(|code| !(400..500).contains(*code)) {
+        config.new_parse_error(
+            ("queue.classifier.rule", id, "code"),
+            format!("Only transient (4xx) codes can be classified, found {code}"),
+        );
+        return None;
+    }
+
+    Some(ResponseRule {
+        id: id.to_string(),
+        category,
+        codes,
+        patterns,
+    })
+}
+
+impl ResponseCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResponseCategory::Greylisted => "greylist",
+            ResponseCategory::RateLimited => "rate-limit",
+            ResponseCategory::MailboxFull => "mailbox-full",
+            ResponseCategory::PolicyBlock => "policy",
+        }
+    }
+}
+
+impl ParseValue for ResponseCategory {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "greylist" => Ok(ResponseCategory::Greylisted),
+            "rate-limit" => Ok(ResponseCategory::RateLimited),
+            "mailbox-full" => Ok(ResponseCategory::MailboxFull),
+            "policy" => Ok(ResponseCategory::PolicyBlock),
+            _ => Err(format!("Invalid response category {:?}.", value)),
+        }
+    }
+}
+
+impl Default for ResponseClassifier {
+    fn default() -> Self {
+        let build = |pattern: &str| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .unwrap()
+        };
+
+        Self {
+            rules: BUILT_IN_RULES
+                .iter()
+                .map(|(id, category, patterns)| ResponseRule {
+                    id: id.to_string(),
+                    category: *category,
+                    codes: vec![],
+                    patterns: patterns.iter().map(|pattern| build(pattern)).collect(),
+                })
+                .collect(),
+            retry: [
+                (
+                    ResponseCategory::Greylisted,
+                    vec![Duration::from_secs(5 * 60), Duration::from_secs(15 * 60)],
+                ),
+                (
+                    ResponseCategory::RateLimited,
+                    vec![
+                        Duration::from_secs(15 * 60),
+                        Duration::from_secs(30 * 60),
+                        Duration::from_secs(60 * 60),
+                        Duration::from_secs(2 * 60 * 60),
+                        Duration::from_secs(4 * 60 * 60),
+                    ],
+                ),
+                (
+                    ResponseCategory::MailboxFull,
+                    vec![
+                        Duration::from_secs(60 * 60),
+                        Duration::from_secs(3 * 60 * 60),
+                        Duration::from_secs(6 * 60 * 60),
+                    ],
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            max_retry_after: Duration::from_secs(6 * 60 * 60),
+            retry_after: build(
+                r"\b(?:in|after)\s+(\d{1,6})\s*(seconds?|secs?|s|minutes?|mins?|m|hours?|hrs?|h)\b",
+            ),
+        }
+    }
+}
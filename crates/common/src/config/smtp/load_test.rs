@@ -0,0 +1,74 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use ahash::AHashSet;
+use utils::config::Config;
+
+#[derive(Clone)]
+pub struct LoadTestConfig {
+    pub enable: bool,
+    pub sink_domains: AHashSet<String>,
+    pub address: SocketAddr,
+    pub max_messages: usize,
+    pub max_concurrency: usize,
+}
+
+impl LoadTestConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let enable = config
+            .property_or_default("test-mode.enable", "false")
+            .unwrap_or(false);
+        let sink_domains = config
+            .values("test-mode.sink.domains")
+            .map(|(_, domain)| domain.trim().to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect::<AHashSet<_>>();
+
+        if enable {
+            config.new_build_warning(
+                "test-mode.enable",
+                "Test mode is enabled, do not use this configuration in production",
+            );
+        }
+
+        Self {
+            enable,
+            sink_domains,
+            address: SocketAddr::new(
+                config
+                    .property_or_default::<IpAddr>("test-mode.generator.ip", "127.0.0.1")
+                    .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                config
+                    .property_or_default("test-mode.generator.port", "25")
+                    .unwrap_or(25),
+            ),
+            max_messages: config
+                .property_or_default("test-mode.generator.max-messages", "100000")
+                .unwrap_or(100_000),
+            max_concurrency: config
+                .property_or_default("test-mode.generator.max-concurrency", "64")
+                .unwrap_or(64),
+        }
+    }
+
+    pub fn is_sink_domain(&self, domain: &str) -> bool {
+        self.enable && self.sink_domains.contains(domain)
+    }
+}
+
+impl Default for LoadTestConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            sink_domains: AHashSet::new(),
+            address: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 25),
+            max_messages: 100_000,
+            max_concurrency: 64,
+        }
+    }
+}
@@ -7,17 +7,20 @@
 use utils::config::{Config, Rate};
 
 pub mod auth;
+pub mod classifier;
+pub mod load_test;
 pub mod queue;
 pub mod report;
 pub mod resolver;
 pub mod session;
+pub mod system;
 pub mod throttle;
 
 use crate::expr::{Expression, tokenizer::TokenMap};
 
 use self::{
-    auth::MailAuthConfig, queue::QueueConfig, report::ReportConfig, resolver::Resolvers,
-    session::SessionConfig,
+    auth::MailAuthConfig, load_test::LoadTestConfig, queue::QueueConfig, report::ReportConfig,
+    resolver::Resolvers, session::SessionConfig, system::SystemMessageConfig,
 };
 
 use super::*;
@@ -29,6 +32,8 @@ pub struct SmtpConfig {
     pub resolvers: Resolvers,
     pub mail_auth: MailAuthConfig,
     pub report: ReportConfig,
+    pub system: SystemMessageConfig,
+    pub load_test: LoadTestConfig,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -158,6 +163,8 @@ impl SmtpConfig {
             resolvers: Resolvers::parse(config).await,
             mail_auth: MailAuthConfig::parse(config),
             report: ReportConfig::parse(config),
+            system: SystemMessageConfig::parse(config),
+            load_test: LoadTestConfig::parse(config),
         }
     }
 }
@@ -4,7 +4,9 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use ahash::AHashMap;
+use std::{net::IpAddr, time::Duration};
+
+use ahash::{AHashMap, AHashSet};
 use mail_auth::IpLookupStrategy;
 use mail_send::Credentials;
 use throttle::parse_queue_rate_limiter_key;
@@ -15,7 +17,7 @@ use crate::{
     expr::{if_block::IfBlock, *},
 };
 
-use self::throttle::parse_queue_rate_limiter;
+use self::{classifier::ResponseClassifier, throttle::parse_queue_rate_limiter};
 
 use super::*;
 
@@ -45,8 +47,20 @@ pub struct QueueConfig {
     pub quota: QueueQuotas,
     pub max_threads: usize,
 
+    // Scheduling
+    pub priority_aging: Duration,
+
     // Relay hosts
     pub relay_hosts: AHashMap<String, RelayHost>,
+
+    // IP warmup
+    pub warmup: AHashMap<IpAddr, IpWarmup>,
+
+    // Remote response classification
+    pub classifier: ResponseClassifier,
+
+    // Quarantine
+    pub quarantine: QueueQuarantine,
 }
 
 #[derive(Clone)]
@@ -55,6 +69,24 @@ pub struct QueueOutboundSourceIp {
     pub ipv6: IfBlock,
 }
 
+#[derive(Clone, Debug)]
+pub struct IpWarmup {
+    pub id: String,
+    pub ip: IpAddr,
+    pub schedule: Vec<u64>,
+    pub destinations: AHashSet<String>,
+    pub min_volume: u64,
+    pub max_bounce_rate: f64,
+    pub max_deferral_rate: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct QueueQuarantine {
+    pub hold: bool,
+    pub release_url: Option<String>,
+    pub release_expiry: Duration,
+}
+
 #[derive(Clone)]
 pub struct Dsn {
     pub name: IfBlock,
@@ -205,10 +237,18 @@ impl Default for QueueConfig {
                 mta_sts: IfBlock::new::<()>("queue.outbound.timeouts.mta-sts", [], "10m"),
             },
             max_threads: 25,
+            priority_aging: Duration::from_secs(5 * 60),
             inbound_limiters: QueueRateLimiters::default(),
             outbound_limiters: QueueRateLimiters::default(),
             quota: QueueQuotas::default(),
             relay_hosts: Default::default(),
+            warmup: Default::default(),
+            classifier: Default::default(),
+            quarantine: QueueQuarantine {
+                hold: false,
+                release_url: None,
+                release_expiry: Duration::from_secs(7 * 86400),
+            },
         }
     }
 }
@@ -325,6 +365,10 @@ impl QueueConfig {
             .property_or_default::<usize>("queue.threads.remote", "25")
             .unwrap_or(25)
             .max(1);
+        queue.priority_aging = config
+            .property_or_default::<Duration>("queue.priority.aging", "5m")
+            .unwrap_or(Duration::from_secs(5 * 60))
+            .max(Duration::from_secs(1));
         queue.inbound_limiters = parse_inbound_rate_limters(config);
         queue.outbound_limiters = parse_outbound_rate_limiters(config);
         queue.quota = parse_queue_quota(config);
@@ -351,6 +395,26 @@ impl QueueConfig {
             },
         );
 
+        // Parse IP warmup schedules
+        queue.warmup = parse_ip_warmup(config);
+
+        // Parse remote response classifier
+        queue.classifier = ResponseClassifier::parse(config);
+
+        // Parse quarantine settings
+        queue.quarantine = QueueQuarantine {
+            hold: config
+                .property_or_default("queue.quarantine.hold", "false")
+                .unwrap_or_default(),
+            release_url: config
+                .value("queue.quarantine.release.url")
+                .map(|url| url.trim_end_matches('/').to_string()),
+            release_expiry: config
+                .property_or_default::<Duration>("queue.quarantine.release.expiry", "7d")
+                .unwrap_or(Duration::from_secs(7 * 86400))
+                .max(Duration::from_secs(60)),
+        };
+
         queue
     }
 }
@@ -381,6 +445,74 @@ fn parse_relay_host(config: &mut Config, id: &str) -> Option<RelayHost> {
     })
 }
 
+fn parse_ip_warmup(config: &mut Config) -> AHashMap<IpAddr, IpWarmup> {
+    let mut warmup = AHashMap::new();
+
+    for id in config
+        .sub_keys("queue.warmup", ".address")
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+    {
+        if let Some(item) = parse_ip_warmup_item(config, &id) {
+            if let Some(existing) = warmup.get(&item.ip) {
+                let err = format!(
+                    "IP address {} is already being warmed up by {:?}",
+                    item.ip, existing.id
+                );
+                config.new_build_error(("queue.warmup", id.as_str(), "address"), err);
+            } else {
+                warmup.insert(item.ip, item);
+            }
+        }
+    }
+
+    warmup
+}
+
+fn parse_ip_warmup_item(config: &mut Config, id: &str) -> Option<IpWarmup> {
+    // Skip disabled schedules
+    if !config
+        .property::<bool>(("queue.warmup", id, "enable"))
+        .unwrap_or(true)
+    {
+        return None;
+    }
+
+    let ip = config.property_require::<IpAddr>(("queue.warmup", id, "address"))?;
+    let schedule = config
+        .properties::<u64>(("queue.warmup", id, "schedule"))
+        .into_iter()
+        .map(|(_, limit)| limit)
+        .collect::<Vec<_>>();
+    if schedule.is_empty() || schedule.contains(&0) {
+        config.new_parse_error(
+            ("queue.warmup", id, "schedule"),
+            "Warmup schedule must contain at least one non-zero daily limit",
+        );
+        return None;
+    }
+
+    Some(IpWarmup {
+        id: id.to_string(),
+        ip,
+        schedule,
+        destinations: config
+            .values(("queue.warmup", id, "destinations"))
+            .map(|(_, domain)| domain.trim().to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect(),
+        min_volume: config
+            .property_or_default::<u64>(("queue.warmup", id, "min-volume"), "10")
+            .unwrap_or(10),
+        max_bounce_rate: config
+            .property_or_default::<f64>(("queue.warmup", id, "max-bounce-rate"), "0.05")
+            .unwrap_or(0.05),
+        max_deferral_rate: config
+            .property_or_default::<f64>(("queue.warmup", id, "max-deferral-rate"), "0.1")
+            .unwrap_or(0.1),
+    })
+}
+
 fn parse_inbound_rate_limters(config: &mut Config) -> QueueRateLimiters {
     let mut throttle = QueueRateLimiters::default();
     let all_throttles = parse_queue_rate_limiter(
@@ -577,6 +709,26 @@ fn parse_queue_quota_item(config: &mut Config, prefix: impl AsKey, id: &str) ->
     }
 }
 
+impl IpWarmup {
+    pub fn daily_limit(&self, phase: u32) -> u64 {
+        self.schedule
+            .get(phase as usize)
+            .or_else(|| self.schedule.last())
+            .copied()
+            .unwrap_or(u64::MAX)
+    }
+
+    // Top destinations are counted individually while all other domains share
+    // a single bucket. Without a destination list every domain is counted individually.
+    pub fn destination<'x>(&self, rcpt_domain: &'x str) -> &'x str {
+        if self.destinations.is_empty() || self.destinations.contains(rcpt_domain) {
+            rcpt_domain
+        } else {
+            "*"
+        }
+    }
+}
+
 impl ParseValue for RequireOptional {
     fn parse_value(value: &str) -> Result<Self, String> {
         match value {
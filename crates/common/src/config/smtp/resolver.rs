@@ -27,7 +27,7 @@ use utils::{
     config::{Config, utils::ParseValue},
 };
 
-use crate::Server;
+use crate::{Server, listener::tls::certificate_hostname};
 
 pub struct Resolvers {
     pub dns: MessageAuthenticator,
@@ -294,6 +294,7 @@ impl Policy {
 
             if !self.mx.is_empty() {
                 self.mx.sort_unstable();
+                self.mx.dedup();
                 self.id = self.hash().to_string();
                 Some(self)
             } else {
@@ -327,6 +328,7 @@ impl Server {
                         .tls_certificates
                         .load()
                         .keys()
+                        .map(|key| certificate_hostname(key))
                         .filter(|key| {
                             !key.starts_with("mta-sts.")
                                 && !key.starts_with("autoconfig.")
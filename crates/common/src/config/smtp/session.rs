@@ -74,6 +74,7 @@ pub struct Extensions {
     pub future_release: IfBlock,
     pub deliver_by: IfBlock,
     pub mt_priority: IfBlock,
+    pub mt_priority_max: IfBlock,
 }
 
 #[derive(Clone)]
@@ -82,6 +83,7 @@ pub struct Auth {
     pub mechanisms: IfBlock,
     pub require: IfBlock,
     pub must_match_sender: IfBlock,
+    pub must_match_from: IfBlock,
     pub errors_max: IfBlock,
     pub errors_wait: IfBlock,
 }
@@ -124,6 +126,7 @@ pub enum AddressMapping {
 pub struct Data {
     pub script: IfBlock,
     pub spam_filter: IfBlock,
+    pub priority: IfBlock,
 
     // Limits
     pub max_messages: IfBlock,
@@ -187,6 +190,7 @@ pub enum Stage {
     Mail,
     Rcpt,
     Data,
+    Upload,
 }
 
 impl SessionConfig {
@@ -290,6 +294,11 @@ impl SessionConfig {
                 "session.extensions.mt-priority",
                 &mt_priority_vars,
             ),
+            (
+                &mut session.extensions.mt_priority_max,
+                "session.extensions.mt-priority-max",
+                &has_sender_vars,
+            ),
             (
                 &mut session.ehlo.script,
                 "session.ehlo.script",
@@ -335,6 +344,11 @@ impl SessionConfig {
                 "session.auth.must-match-sender",
                 &has_sender_vars,
             ),
+            (
+                &mut session.auth.must_match_from,
+                "session.auth.must-match-from",
+                &has_rcpt_vars,
+            ),
             (
                 &mut session.mail.script,
                 "session.mail.script",
@@ -410,6 +424,11 @@ impl SessionConfig {
                 "session.data.spam-filter",
                 &has_rcpt_vars,
             ),
+            (
+                &mut session.data.priority,
+                "session.data.priority",
+                &has_rcpt_vars,
+            ),
             (
                 &mut session.data.add_received,
                 "session.data.add-headers.received",
@@ -605,6 +624,7 @@ fn parse_stages(config: &mut Config, prefix: &str, id: &str) -> AHashSet<Stage>
             "mail" => Stage::Mail,
             "rcpt" => Stage::Rcpt,
             "data" => Stage::Data,
+            "upload" => Stage::Upload,
             _ => {
                 invalid.push(value);
                 continue;
@@ -684,6 +704,7 @@ impl Default for SessionConfig {
                     "false",
                 ),
                 must_match_sender: IfBlock::new::<()>("session.auth.must-match-sender", [], "true"),
+                must_match_from: IfBlock::new::<()>("session.auth.must-match-from", [], "false"),
                 errors_max: IfBlock::new::<()>("session.auth.errors.total", [], "3"),
                 errors_wait: IfBlock::new::<()>("session.auth.errors.wait", [], "5s"),
             },
@@ -721,6 +742,7 @@ impl Default for SessionConfig {
             data: Data {
                 script: IfBlock::empty("session.data.script"),
                 spam_filter: IfBlock::new::<()>("session.data.spam-filter", [], "true"),
+                priority: IfBlock::empty("session.data.priority"),
                 max_messages: IfBlock::new::<()>("session.data.limits.messages", [], "10"),
                 max_message_size: IfBlock::new::<()>("session.data.limits.size", [], "104857600"),
                 max_received_headers: IfBlock::new::<()>(
@@ -795,6 +817,11 @@ impl Default for SessionConfig {
                     [("!is_empty(authenticated_as)", "mixer")],
                     "false",
                 ),
+                mt_priority_max: IfBlock::new::<()>(
+                    "session.extensions.mt-priority-max",
+                    [("!is_empty(authenticated_as)", "5")],
+                    "0",
+                ),
             },
             mta_sts_policy: None,
             milters: Default::default(),
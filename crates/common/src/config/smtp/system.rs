@@ -0,0 +1,253 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::LazyLock;
+
+use utils::config::{Config, Rate};
+
+use crate::expr::{if_block::IfBlock, tokenizer::TokenMap};
+
+use super::*;
+
+pub const TEMPLATE_METRICS_ALERT: &str = "metrics-alert";
+pub const TEMPLATE_IMPERSONATION: &str = "impersonation";
+pub const TEMPLATE_ENCRYPTION_FAILURE: &str = "encryption-failure";
+pub const TEMPLATE_QUARANTINE_RELEASE: &str = "quarantine-release";
+
+#[derive(Clone)]
+pub struct SystemMessageConfig {
+    pub sender_name: IfBlock,
+    pub sender_address: IfBlock,
+    pub sign: IfBlock,
+    pub rate: Option<Rate>,
+    pub default_language: String,
+    pub templates: Vec<SystemTemplate>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SystemTemplate {
+    pub name: String,
+    pub domain: Option<String>,
+    pub language: Option<String>,
+    pub subject: String,
+    pub body: String,
+}
+
+impl SystemMessageConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let rcpt_vars = TokenMap::default().with_variables(RCPT_DOMAIN_VARS);
+        let sender_vars = TokenMap::default().with_variables(SMTP_MAIL_FROM_VARS);
+        let mut system = SystemMessageConfig::default();
+
+        for (value, key, token_map) in [
+            (
+                &mut system.sender_name,
+                "system-message.sender.name",
+                &rcpt_vars,
+            ),
+            (
+                &mut system.sender_address,
+                "system-message.sender.address",
+                &rcpt_vars,
+            ),
+            (&mut system.sign, "system-message.sign", &sender_vars),
+        ] {
+            if let Some(if_block) = IfBlock::try_parse(config, key, token_map) {
+                *value = if_block;
+            }
+        }
+
+        system.rate = config
+            .property_or_default::<Option<Rate>>("system-message.rate-limit", "5/1h")
+            .unwrap_or_default();
+        system.default_language = config
+            .value("system-message.language")
+            .map(|v| v.trim().to_lowercase())
+            .unwrap_or_else(|| "en".to_string());
+
+        for id in config
+            .sub_keys("system-message.template", ".body")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+        {
+            let template = SystemTemplate {
+                name: config
+                    .value(("system-message.template", id.as_str(), "name"))
+                    .unwrap_or(id.as_str())
+                    .trim()
+                    .to_lowercase(),
+                domain: config
+                    .value(("system-message.template", id.as_str(), "domain"))
+                    .map(|v| v.trim().to_lowercase()),
+                language: config
+                    .value(("system-message.template", id.as_str(), "language"))
+                    .map(|v| v.trim().to_lowercase()),
+                subject: config
+                    .value(("system-message.template", id.as_str(), "subject"))
+                    .unwrap_or_default()
+                    .to_string(),
+                body: config
+                    .value(("system-message.template", id.as_str(), "body"))
+                    .unwrap_or_default()
+                    .to_string(),
+            };
+
+            if system.templates.iter().any(|t| {
+                t.name == template.name
+                    && t.domain == template.domain
+                    && t.language == template.language
+            }) {
+                config.new_build_error(
+                    ("system-message.template", id.as_str()),
+                    format!(
+                        "Duplicate template {:?} for this domain and language",
+                        template.name
+                    ),
+                );
+            } else {
+                system.templates.push(template);
+            }
+        }
+
+        system
+    }
+
+    // Returns the most specific template, domain overrides take precedence over language ones
+    pub fn template(
+        &self,
+        name: &str,
+        domain: &str,
+        language: Option<&str>,
+    ) -> Option<&SystemTemplate> {
+        let language = language.unwrap_or(self.default_language.as_str());
+
+        self.templates
+            .iter()
+            .filter(|t| {
+                t.name == name
+                    && t.domain.as_ref().is_none_or(|d| d == domain)
+                    && t.language.as_ref().is_none_or(|l| l == language)
+            })
+            .max_by_key(|t| (t.domain.is_some(), t.language.is_some()))
+            .or_else(|| BUILT_IN_TEMPLATES.iter().find(|t| t.name == name))
+    }
+}
+
+impl SystemTemplate {
+    pub fn render(&self, variables: &[(&str, &str)]) -> (String, String) {
+        (
+            render_template(&self.subject, variables),
+            render_template(&self.body, variables),
+        )
+    }
+}
+
+// Replaces {{name}} placeholders, unknown placeholders are left untouched
+pub fn render_template(template: &str, variables: &[(&str, &str)]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(end) = rest.find("}}") {
+            let name = rest[2..end].trim();
+            if let Some((_, value)) = variables.iter().find(|(key, _)| *key == name) {
+                result.push_str(value);
+            } else {
+                result.push_str(&rest[..end + 2]);
+            }
+            rest = &rest[end + 2..];
+        } else {
+            break;
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+static BUILT_IN_TEMPLATES: LazyLock<Vec<SystemTemplate>> = LazyLock::new(|| {
+    vec![
+        SystemTemplate {
+            name: TEMPLATE_METRICS_ALERT.to_string(),
+            domain: None,
+            language: None,
+            subject: "{{subject}}".to_string(),
+            body: "{{body}}".to_string(),
+        },
+        SystemTemplate {
+            name: TEMPLATE_IMPERSONATION.to_string(),
+            domain: None,
+            language: None,
+            subject: "Your account is being accessed by an administrator".to_string(),
+            body: concat!(
+                "The administrator {{admin}} has been granted {{access}} access to ",
+                "your account {{account}} until {{expires}}.\n\n",
+                "Reason: {{reason}}\n\n",
+                "If you did not request assistance, please contact your ",
+                "system administrator.\n"
+            )
+            .to_string(),
+        },
+        SystemTemplate {
+            name: TEMPLATE_ENCRYPTION_FAILURE.to_string(),
+            domain: None,
+            language: None,
+            subject: "Incoming messages for {{account}} cannot be encrypted".to_string(),
+            body: concat!(
+                "A message for {{account}} could not be encrypted at rest after ",
+                "{{attempts}} delivery attempts and is being held in the queue ",
+                "with id {{queue_id}}.\n\n",
+                "Error: {{reason}}\n\n",
+                "Please upload valid encryption certificates or ask your system ",
+                "administrator to disable encryption at rest for this account.\n"
+            )
+            .to_string(),
+        },
+        SystemTemplate {
+            name: TEMPLATE_QUARANTINE_RELEASE.to_string(),
+            domain: None,
+            language: None,
+            subject: "A message from {{from}} was quarantined".to_string(),
+            body: concat!(
+                "A message from {{from}} addressed to {{rcpt}} was quarantined ",
+                "and is being held in the queue with id {{queue_id}}.\n\n",
+                "If you trust this message you can release it to your mailbox ",
+                "by opening the link below before {{expires}}:\n\n",
+                "{{release_url}}\n\n",
+                "The link can only be used once. Messages that are not released ",
+                "are deleted when they expire from the queue.\n"
+            )
+            .to_string(),
+        },
+    ]
+});
+
+impl Default for SystemMessageConfig {
+    fn default() -> Self {
+        Self {
+            sender_name: IfBlock::new::<()>("system-message.sender.name", [], "'Mail System'"),
+            sender_address: IfBlock::new::<()>(
+                "system-message.sender.address",
+                [],
+                "'no-reply@' + rcpt_domain",
+            ),
+            sign: IfBlock::new::<()>(
+                "system-message.sign",
+                [],
+                "['rsa-' + sender_domain, 'ed25519-' + sender_domain]",
+            ),
+            rate: Rate {
+                requests: 5,
+                period: std::time::Duration::from_secs(3600),
+            }
+            .into(),
+            default_language: "en".to_string(),
+            templates: Vec::new(),
+        }
+    }
+}
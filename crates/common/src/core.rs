@@ -183,6 +183,20 @@ impl Server {
         })
     }
 
+    pub fn get_global_sieve_script(
+        &self,
+        name: &str,
+        domain: &str,
+        session_id: u64,
+    ) -> Option<&Arc<Sieve>> {
+        // Domain scripts take precedence over server-wide ones
+        self.core
+            .sieve
+            .untrusted_scripts
+            .get(&format!("{name}@{domain}"))
+            .or_else(|| self.get_untrusted_sieve_script(name, session_id))
+    }
+
     pub fn get_relay_host(&self, name: &str, session_id: u64) -> Option<&RelayHost> {
         self.core.smtp.queue.relay_hosts.get(name).or_else(|| {
             trc::event!(
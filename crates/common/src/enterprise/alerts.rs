@@ -8,13 +8,6 @@
  *
  */
 
-use mail_builder::{
-    MessageBuilder,
-    headers::{
-        HeaderType,
-        address::{Address, EmailAddress},
-    },
-};
 use trc::{Collector, MetricType, TOTAL_EVENT_COUNT, TelemetryEvent};
 
 use super::{AlertContent, AlertContentToken, AlertMethod};
@@ -26,9 +19,11 @@ use std::fmt::Write;
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct AlertMessage {
+    pub from_name: Option<String>,
     pub from: String,
     pub to: Vec<String>,
-    pub body: Vec<u8>,
+    pub subject: String,
+    pub body: String,
 }
 
 struct CollectorResolver;
@@ -59,31 +54,11 @@ impl Server {
                         body,
                     } => {
                         messages.push(AlertMessage {
+                            from_name: from_name.clone(),
                             from: from_addr.clone(),
                             to: to.clone(),
-                            body: MessageBuilder::new()
-                                .from(Address::Address(EmailAddress {
-                                    name: from_name.as_ref().map(|s| s.into()),
-                                    email: from_addr.as_str().into(),
-                                }))
-                                .header(
-                                    "To",
-                                    HeaderType::Address(Address::List(
-                                        to.iter()
-                                            .map(|to| {
-                                                Address::Address(EmailAddress {
-                                                    name: None,
-                                                    email: to.as_str().into(),
-                                                })
-                                            })
-                                            .collect(),
-                                    )),
-                                )
-                                .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
-                                .subject(subject.build())
-                                .text_body(body.build())
-                                .write_to_vec()
-                                .unwrap_or_default(),
+                            subject: subject.build(),
+                            body: body.build(),
                         });
                     }
                     AlertMethod::Event { message } => {
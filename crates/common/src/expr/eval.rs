@@ -4,7 +4,11 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{cmp::Ordering, fmt::Display};
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    time::{Duration, Instant},
+};
 
 use compact_str::{CompactString, ToCompactString, format_compact};
 use hyper::StatusCode;
@@ -15,7 +19,7 @@ use crate::Server;
 use super::{
     BinaryOperator, Constant, Expression, ExpressionItem, Setting, StringCow, UnaryOperator,
     Variable,
-    functions::{FUNCTIONS, ResolveVariable},
+    functions::{ASYNC_FUNCTIONS, FUNCTIONS, ResolveVariable},
     if_block::IfBlock,
 };
 
@@ -41,7 +45,7 @@ impl Server {
             resolver,
             core: self,
             expr: if_block,
-            captures: Vec::new(),
+            state: EvalState::new(self, false),
             session_id,
         })
         .eval()
@@ -97,7 +101,7 @@ impl Server {
             resolver,
             core: self,
             expr,
-            captures: &mut Vec::new(),
+            state: &mut EvalState::new(self, false),
             session_id,
         })
         .eval()
@@ -137,24 +141,78 @@ impl Server {
             }
         }
     }
+
+    pub async fn eval_expr_with_trace<'x, V: ResolveVariable>(
+        &'x self,
+        expr: &'x Expression,
+        resolver: &'x V,
+        session_id: u64,
+    ) -> (trc::Result<Variable<'x>>, Vec<FunctionCall>) {
+        let mut state = EvalState::new(self, true);
+        let result = (EvalContext {
+            resolver,
+            core: self,
+            expr,
+            state: &mut state,
+            session_id,
+        })
+        .eval()
+        .await;
+
+        (result, state.trace.unwrap_or_default())
+    }
 }
 
 struct EvalContext<'x, V: ResolveVariable, T, C> {
     resolver: &'x V,
     core: &'x Server,
     expr: &'x T,
-    captures: C,
+    state: C,
     session_id: u64,
 }
 
-impl<'x, V: ResolveVariable> EvalContext<'x, V, IfBlock, Vec<CompactString>> {
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FunctionCall {
+    pub function: &'static str,
+    pub arguments: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub elapsed: u64,
+}
+
+struct EvalState {
+    captures: Vec<CompactString>,
+    calls: u32,
+    max_calls: u32,
+    call_timeout: Duration,
+    deadline: Instant,
+    trace: Option<Vec<FunctionCall>>,
+}
+
+impl EvalState {
+    fn new(server: &Server, with_trace: bool) -> Self {
+        let limits = &server.core.network.eval_limits;
+        EvalState {
+            captures: Vec::new(),
+            calls: 0,
+            max_calls: limits.max_calls,
+            call_timeout: limits.call_timeout,
+            deadline: Instant::now() + limits.timeout,
+            trace: with_trace.then(Vec::new),
+        }
+    }
+}
+
+impl<'x, V: ResolveVariable> EvalContext<'x, V, IfBlock, EvalState> {
     async fn eval(&mut self) -> trc::Result<Variable<'x>> {
         for if_then in &self.expr.if_then {
             if (EvalContext {
                 resolver: self.resolver,
                 core: self.core,
                 expr: &if_then.expr,
-                captures: &mut self.captures,
+                state: &mut self.state,
                 session_id: self.session_id,
             })
             .eval()
@@ -165,7 +223,7 @@ impl<'x, V: ResolveVariable> EvalContext<'x, V, IfBlock, Vec<CompactString>> {
                     resolver: self.resolver,
                     core: self.core,
                     expr: &if_then.then,
-                    captures: &mut self.captures,
+                    state: &mut self.state,
                     session_id: self.session_id,
                 })
                 .eval()
@@ -177,7 +235,7 @@ impl<'x, V: ResolveVariable> EvalContext<'x, V, IfBlock, Vec<CompactString>> {
             resolver: self.resolver,
             core: self.core,
             expr: &self.expr.default,
-            captures: &mut self.captures,
+            state: &mut self.state,
             session_id: self.session_id,
         })
         .eval()
@@ -185,7 +243,7 @@ impl<'x, V: ResolveVariable> EvalContext<'x, V, IfBlock, Vec<CompactString>> {
     }
 }
 
-impl<'x, V: ResolveVariable> EvalContext<'x, V, Expression, &mut Vec<CompactString>> {
+impl<'x, V: ResolveVariable> EvalContext<'x, V, Expression, &mut EvalState> {
     async fn eval(&mut self) -> trc::Result<Variable<'x>> {
         let mut stack = Vec::new();
         let mut exprs = self.expr.items.iter();
@@ -203,7 +261,8 @@ impl<'x, V: ResolveVariable> EvalContext<'x, V, Expression, &mut Vec<CompactStri
                 }
                 ExpressionItem::Capture(v) => {
                     stack.push(Variable::String(StringCow::Owned(
-                        self.captures
+                        self.state
+                            .captures
                             .get(*v as usize)
                             .map(|v| v.as_str())
                             .unwrap_or_default()
@@ -267,12 +326,8 @@ impl<'x, V: ResolveVariable> EvalContext<'x, V, Expression, &mut Vec<CompactStri
                     let result = if let Some((_, fnc, _)) = FUNCTIONS.get(*id as usize) {
                         (fnc)(arguments)
                     } else {
-                        Box::pin(self.core.eval_fnc(
-                            *id - FUNCTIONS.len() as u32,
-                            arguments,
-                            self.session_id,
-                        ))
-                        .await?
+                        self.eval_async_fnc(*id - FUNCTIONS.len() as u32, arguments)
+                            .await?
                     };
 
                     stack.push(result);
@@ -302,23 +357,86 @@ impl<'x, V: ResolveVariable> EvalContext<'x, V, Expression, &mut Vec<CompactStri
                     stack.push(Variable::Array(items));
                 }
                 ExpressionItem::Regex(regex) => {
-                    self.captures.clear();
+                    self.state.captures.clear();
                     let value = stack.pop().unwrap_or_default().into_string();
 
                     if let Some(captures_) = regex.captures(value.as_ref()) {
                         for capture in captures_.iter() {
-                            self.captures
+                            self.state
+                                .captures
                                 .push(capture.map_or("", |m| m.as_str()).to_compact_string());
                         }
                     }
 
-                    stack.push(Variable::Integer(!self.captures.is_empty() as i64));
+                    stack.push(Variable::Integer(!self.state.captures.is_empty() as i64));
                 }
             }
         }
 
         Ok(stack.pop().unwrap_or_default())
     }
+
+    async fn eval_async_fnc(
+        &mut self,
+        fnc_id: u32,
+        arguments: Vec<Variable<'x>>,
+    ) -> trc::Result<Variable<'x>> {
+        let name = ASYNC_FUNCTIONS
+            .iter()
+            .find_map(|(name, id, _)| (*id == fnc_id).then_some(*name))
+            .unwrap_or_default();
+
+        // Enforce the per-evaluation budget
+        let state = &mut *self.state;
+        state.calls += 1;
+        let remaining = state.deadline.saturating_duration_since(Instant::now());
+        if state.calls > state.max_calls || remaining.is_zero() {
+            return Err(trc::EventType::Eval(EvalEvent::BudgetExceeded)
+                .into_err()
+                .id(name)
+                .details(if state.calls > state.max_calls {
+                    "Maximum number of function calls exceeded"
+                } else {
+                    "Evaluation time limit exceeded"
+                })
+                .ctx(trc::Key::Total, state.max_calls));
+        }
+
+        let trace_arguments = state.trace.as_ref().map(|_| {
+            arguments
+                .iter()
+                .map(|arg| arg.to_string().as_str().to_string())
+                .collect::<Vec<_>>()
+        });
+        let time = Instant::now();
+        let result = match tokio::time::timeout(
+            state.call_timeout.min(remaining),
+            Box::pin(self.core.eval_fnc(fnc_id, arguments, self.session_id)),
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => Err(trc::EventType::Eval(EvalEvent::FunctionTimeout)
+                .into_err()
+                .id(name)
+                .ctx(trc::Key::Elapsed, time.elapsed())),
+        };
+
+        if let (Some(trace), Some(arguments)) = (&mut self.state.trace, trace_arguments) {
+            trace.push(FunctionCall {
+                function: name,
+                arguments,
+                result: result
+                    .as_ref()
+                    .ok()
+                    .map(|result| result.to_string().as_str().to_string()),
+                error: result.as_ref().err().map(|err| err.to_string()),
+                elapsed: time.elapsed().as_millis() as u64,
+            });
+        }
+
+        result
+    }
 }
 
 impl Expression {
@@ -4,15 +4,16 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{cmp::Ordering, net::IpAddr, vec::IntoIter};
+use std::{cmp::Ordering, net::IpAddr, time::Duration, vec::IntoIter};
 
 use compact_str::{CompactString, ToCompactString};
 use directory::backend::RcptType;
 use mail_auth::IpLookupStrategy;
 use store::{Deserialize, Rows, Value, dispatch::lookup::KeyValue};
 use trc::AddContext;
+use utils::config::Rate;
 
-use crate::{Server, expr::StringCow};
+use crate::{KV_RATE_LIMIT_EXPR, Server, expr::StringCow};
 
 use super::*;
 
@@ -46,7 +47,7 @@ impl Server {
                     .caused_by(trc::location!())
                     .map(|v| (v != RcptType::Invalid).into())
             }
-            F_KEY_GET => {
+            F_KEY_GET | F_KV_GET => {
                 let store = params.next_as_string();
                 let key = params.next_as_string();
 
@@ -102,7 +103,55 @@ impl Server {
                     .map(Variable::Integer)
                     .caused_by(trc::location!())
             }
-            F_DNS_QUERY => self.dns_query(params).await,
+            F_DNS_QUERY => {
+                let entry = params.next_as_string();
+                let record_type = params.next_as_string();
+
+                self.dns_query(entry.as_str(), record_type.as_str()).await
+            }
+            F_DNS_LOOKUP => {
+                let record_type = params.next_as_string();
+                let entry = params.next_as_string();
+
+                self.dns_query(entry.as_str(), record_type.as_str()).await
+            }
+            F_DNS_EXISTS => {
+                let record_type = params.next_as_string();
+                let entry = params.next_as_string();
+
+                match self.dns_query(entry.as_str(), record_type.as_str()).await {
+                    Ok(Variable::Array(values)) => Ok((!values.is_empty()).into()),
+                    Ok(value) => Ok((!value.is_empty()).into()),
+                    Err(err)
+                        if err.matches(trc::EventType::MailAuth(
+                            trc::MailAuthEvent::DnsRecordNotFound,
+                        )) =>
+                    {
+                        Ok(false.into())
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            F_RATE_OK => {
+                let bucket = params.next_as_string();
+                let rate = Rate {
+                    requests: params.next_as_integer().max(0) as u64,
+                    period: Duration::from_millis(params.next_as_integer().max(0) as u64),
+                };
+
+                if rate.requests == 0 || rate.period.as_secs() == 0 {
+                    return Err(trc::EventType::Eval(trc::EvalEvent::Error)
+                        .into_err()
+                        .details("Invalid rate limit")
+                        .ctx(trc::Key::Key, bucket.as_str().to_string()));
+                }
+
+                self.in_memory_store()
+                    .is_rate_allowed(KV_RATE_LIMIT_EXPR, bucket.as_bytes(), &rate, false)
+                    .await
+                    .caused_by(trc::location!())
+                    .map(|v| v.is_none().into())
+            }
             F_SQL_QUERY => self.sql_query(params, session_id).await,
             _ => Ok(Variable::default()),
         }
@@ -170,17 +219,14 @@ impl Server {
         }
     }
 
-    async fn dns_query<'x>(&self, mut arguments: FncParams<'x>) -> trc::Result<Variable<'x>> {
-        let entry = arguments.next_as_string();
-        let record_type = arguments.next_as_string();
-
-        if record_type.as_str().eq_ignore_ascii_case("ip") {
+    async fn dns_query<'x>(&self, entry: &str, record_type: &str) -> trc::Result<Variable<'x>> {
+        if record_type.eq_ignore_ascii_case("ip") {
             self.core
                 .smtp
                 .resolvers
                 .dns
                 .ip_lookup(
-                    entry.as_ref(),
+                    entry,
                     IpLookupStrategy::Ipv4thenIpv6,
                     10,
                     Some(&self.inner.cache.dns_ipv4),
@@ -195,12 +241,12 @@ impl Server {
                         .collect::<Vec<_>>()
                         .into()
                 })
-        } else if record_type.as_str().eq_ignore_ascii_case("mx") {
+        } else if record_type.eq_ignore_ascii_case("mx") {
             self.core
                 .smtp
                 .resolvers
                 .dns
-                .mx_lookup(entry.as_str(), Some(&self.inner.cache.dns_mx))
+                .mx_lookup(entry, Some(&self.inner.cache.dns_mx))
                 .await
                 .map_err(|err| trc::Error::from(err).caused_by(trc::location!()))
                 .map(|result| {
@@ -218,22 +264,22 @@ impl Server {
                         .collect::<Vec<_>>()
                         .into()
                 })
-        } else if record_type.as_str().eq_ignore_ascii_case("txt") {
+        } else if record_type.eq_ignore_ascii_case("txt") {
             self.core
                 .smtp
                 .resolvers
                 .dns
-                .txt_raw_lookup(entry.as_str())
+                .txt_raw_lookup(entry)
                 .await
                 .map_err(|err| trc::Error::from(err).caused_by(trc::location!()))
                 .map(|result| Variable::from(CompactString::from_utf8(result).unwrap_or_default()))
-        } else if record_type.as_str().eq_ignore_ascii_case("ptr") {
+        } else if record_type.eq_ignore_ascii_case("ptr") {
             self.core
                 .smtp
                 .resolvers
                 .dns
                 .ptr_lookup(
-                    entry.as_str().parse::<IpAddr>().map_err(|err| {
+                    entry.parse::<IpAddr>().map_err(|err| {
                         trc::EventType::Eval(trc::EvalEvent::Error)
                             .into_err()
                             .details("Failed to parse IP address")
@@ -250,12 +296,12 @@ impl Server {
                         .collect::<Vec<_>>()
                         .into()
                 })
-        } else if record_type.as_str().eq_ignore_ascii_case("ipv4") {
+        } else if record_type.eq_ignore_ascii_case("ipv4") {
             self.core
                 .smtp
                 .resolvers
                 .dns
-                .ipv4_lookup(entry.as_str(), Some(&self.inner.cache.dns_ipv4))
+                .ipv4_lookup(entry, Some(&self.inner.cache.dns_ipv4))
                 .await
                 .map_err(|err| trc::Error::from(err).caused_by(trc::location!()))
                 .map(|result| {
@@ -265,12 +311,12 @@ impl Server {
                         .collect::<Vec<_>>()
                         .into()
                 })
-        } else if record_type.as_str().eq_ignore_ascii_case("ipv6") {
+        } else if record_type.eq_ignore_ascii_case("ipv6") {
             self.core
                 .smtp
                 .resolvers
                 .dns
-                .ipv6_lookup(entry.as_str(), Some(&self.inner.cache.dns_ipv6))
+                .ipv6_lookup(entry, Some(&self.inner.cache.dns_ipv6))
                 .await
                 .map_err(|err| trc::Error::from(err).caused_by(trc::location!()))
                 .map(|result| {
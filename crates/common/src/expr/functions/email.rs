@@ -102,3 +102,13 @@ pub(crate) fn fn_email_part(v: Vec<Variable>) -> Variable {
             .unwrap_or_default(),
     })
 }
+
+pub(crate) fn fn_local_part(mut v: Vec<Variable>) -> Variable {
+    v.push(Variable::from("local"));
+    fn_email_part(v)
+}
+
+pub(crate) fn fn_domain_part(mut v: Vec<Variable>) -> Variable {
+    v.push(Variable::from("domain"));
+    fn_email_part(v)
+}
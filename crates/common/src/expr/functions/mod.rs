@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use super::{StringCow, Variable};
+use super::{Constant, StringCow, Variable};
 
 pub mod array;
 pub mod asynch;
@@ -43,6 +43,8 @@ pub(crate) const FUNCTIONS: &[(&str, fn(Vec<Variable>) -> Variable, u32)] = &[
     ("is_intersect", array::fn_is_intersect, 2),
     ("is_email", email::fn_is_email, 1),
     ("email_part", email::fn_email_part, 2),
+    ("local_part", email::fn_local_part, 1),
+    ("domain_part", email::fn_domain_part, 1),
     ("is_empty", misc::fn_is_empty, 1),
     ("is_number", misc::fn_is_number, 1),
     ("is_ip_addr", misc::fn_is_ip_addr, 1),
@@ -78,6 +80,8 @@ pub(crate) const FUNCTIONS: &[(&str, fn(Vec<Variable>) -> Variable, u32)] = &[
     ("split_n", text::fn_split_n, 3),
     ("split_words", text::fn_split_words, 1),
     ("hash", text::fn_hash, 2),
+    ("idna_to_ascii", text::fn_idna_to_ascii, 1),
+    ("idna_to_unicode", text::fn_idna_to_unicode, 1),
     ("if_then", misc::fn_if_then, 3),
 ];
 
@@ -90,6 +94,10 @@ pub const F_COUNTER_INCR: u32 = 5;
 pub const F_COUNTER_GET: u32 = 6;
 pub const F_SQL_QUERY: u32 = 7;
 pub const F_DNS_QUERY: u32 = 8;
+pub const F_DNS_EXISTS: u32 = 9;
+pub const F_DNS_LOOKUP: u32 = 10;
+pub const F_KV_GET: u32 = 11;
+pub const F_RATE_OK: u32 = 12;
 
 pub const ASYNC_FUNCTIONS: &[(&str, u32, u32)] = &[
     ("is_local_domain", F_IS_LOCAL_DOMAIN, 2),
@@ -101,4 +109,50 @@ pub const ASYNC_FUNCTIONS: &[(&str, u32, u32)] = &[
     ("counter_get", F_COUNTER_GET, 2),
     ("dns_query", F_DNS_QUERY, 2),
     ("sql_query", F_SQL_QUERY, 3),
+    ("dns_exists", F_DNS_EXISTS, 2),
+    ("dns_lookup", F_DNS_LOOKUP, 2),
+    ("kv_get", F_KV_GET, 2),
+    ("rate_ok", F_RATE_OK, 3),
 ];
+
+const DNS_RECORD_TYPES: &[&str] = &["ip", "ipv4", "ipv6", "mx", "txt", "ptr"];
+
+pub(crate) fn validate_async_fnc(id: u32, args: &[Option<&Constant>]) -> Result<(), String> {
+    let arg = |idx: usize| args.get(idx).copied().flatten();
+    let (name, record_type) = match id {
+        F_DNS_QUERY => ("dns_query", arg(1)),
+        F_DNS_EXISTS => ("dns_exists", arg(0)),
+        F_DNS_LOOKUP => ("dns_lookup", arg(0)),
+        F_RATE_OK => {
+            return match (arg(1), arg(2)) {
+                (Some(Constant::Integer(limit)), _) if *limit <= 0 => Err(format!(
+                    "Expression function \"rate_ok\" expected a positive limit, got {limit}"
+                )),
+                (_, Some(Constant::Integer(window))) if *window < 1000 => Err(
+                    "Expression function \"rate_ok\" expected a window of at least one second"
+                        .to_string(),
+                ),
+                (Some(Constant::String(_) | Constant::Float(_)), _)
+                | (_, Some(Constant::String(_) | Constant::Float(_))) => Err(
+                    "Expression function \"rate_ok\" expected integer limit and window".to_string(),
+                ),
+                _ => Ok(()),
+            };
+        }
+        _ => return Ok(()),
+    };
+
+    match record_type {
+        Some(Constant::String(record_type))
+            if !DNS_RECORD_TYPES
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(record_type.as_str())) =>
+        {
+            Err(format!(
+                "Expression function {name:?} does not support DNS record type {:?}",
+                record_type.as_str()
+            ))
+        }
+        _ => Ok(()),
+    }
+}
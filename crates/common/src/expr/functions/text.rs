@@ -356,3 +356,19 @@ pub(crate) fn fn_hash(v: Vec<Variable>) -> Variable {
         _ => Variable::default(),
     }
 }
+
+pub(crate) fn fn_idna_to_ascii(mut v: Vec<Variable>) -> Variable {
+    v.remove(0).transform(|s| {
+        idna::domain_to_ascii(s.as_str())
+            .map(|s| Variable::from(CompactString::from(s)))
+            .unwrap_or_default()
+    })
+}
+
+pub(crate) fn fn_idna_to_unicode(mut v: Vec<Variable>) -> Variable {
+    v.remove(0)
+        .transform(|s| match idna::domain_to_unicode(s.as_str()) {
+            (s, Ok(_)) => Variable::from(CompactString::from(s)),
+            (_, Err(_)) => Variable::default(),
+        })
+}
@@ -38,6 +38,7 @@ pub const V_HEADERS: u32 = 23;
 pub const V_METHOD: u32 = 24;
 pub const V_ASN: u32 = 25;
 pub const V_COUNTRY: u32 = 26;
+pub const V_TLS_FINGERPRINT: u32 = 27;
 
 pub const VARIABLES_MAP: &[(&str, u32)] = &[
     ("rcpt", V_RECIPIENT),
@@ -67,6 +68,7 @@ pub const VARIABLES_MAP: &[(&str, u32)] = &[
     ("method", V_METHOD),
     ("asn", V_ASN),
     ("country", V_COUNTRY),
+    ("tls_fingerprint", V_TLS_FINGERPRINT),
 ];
 
 use compact_str::CompactString;
@@ -4,7 +4,11 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use super::{BinaryOperator, Expression, ExpressionItem, Token, tokenizer::Tokenizer};
+use super::{
+    BinaryOperator, Constant, Expression, ExpressionItem, Token,
+    functions::{FUNCTIONS, validate_async_fnc},
+    tokenizer::Tokenizer,
+};
 
 pub struct ExpressionParser<'x> {
     pub(crate) tokenizer: Tokenizer<'x>,
@@ -92,10 +96,16 @@ impl<'x> ExpressionParser<'x> {
                             let expr = match *id {
                                 ID_ARRAY_ACCESS => ExpressionItem::ArrayAccess,
                                 ID_ARRAY_BUILD => ExpressionItem::ArrayBuild(*num_args),
-                                id => ExpressionItem::Function {
-                                    id,
-                                    num_args: *num_args,
-                                },
+                                id => {
+                                    if let Some(fnc_id) = id.checked_sub(FUNCTIONS.len() as u32) {
+                                        validate_async_fnc(fnc_id, &self.constant_args(*num_args))?;
+                                    }
+
+                                    ExpressionItem::Function {
+                                        id,
+                                        num_args: *num_args,
+                                    }
+                                }
                             };
 
                             self.operator_stack.pop();
@@ -231,6 +241,25 @@ impl<'x> ExpressionParser<'x> {
         }
     }
 
+    fn constant_args(&self, num_args: u32) -> Vec<Option<&Constant>> {
+        // Walk back through the output to find where each argument starts
+        let mut args = vec![None; num_args as usize];
+        let mut pos = self.output.len();
+        for arg in args.iter_mut().rev() {
+            let end = pos;
+            let mut depth = 0;
+            while pos > 0 && depth < 1 {
+                pos -= 1;
+                depth += stack_effect(&self.output[pos]);
+            }
+            if let (1, Some(ExpressionItem::Constant(constant))) = (end - pos, self.output.get(pos))
+            {
+                *arg = Some(constant);
+            }
+        }
+        args
+    }
+
     fn inc_arg_count(&mut self) {
         if let Some(x) = self.arg_count.last_mut() {
             *x = x.saturating_add(1);
@@ -276,3 +305,20 @@ impl BinaryOperator {
         }
     }
 }
+
+fn stack_effect(item: &ExpressionItem) -> i32 {
+    match item {
+        ExpressionItem::Variable(_)
+        | ExpressionItem::Global(_)
+        | ExpressionItem::Setting(_)
+        | ExpressionItem::Capture(_)
+        | ExpressionItem::Constant(_) => 1,
+        ExpressionItem::BinaryOperator(_) | ExpressionItem::ArrayAccess => -1,
+        ExpressionItem::UnaryOperator(_)
+        | ExpressionItem::Regex(_)
+        | ExpressionItem::JmpIf { .. } => 0,
+        ExpressionItem::Function { num_args, .. } | ExpressionItem::ArrayBuild(num_args) => {
+            1 - *num_args as i32
+        }
+    }
+}
@@ -17,9 +17,12 @@ use store::{BlobStore, InMemoryStore, Store};
 use tokio::sync::mpsc;
 use utils::map::bitmap::Bitmap;
 
-use crate::config::smtp::{
-    report::AggregateFrequency,
-    resolver::{Policy, Tlsa},
+use crate::config::{
+    maintenance::MaintenanceTask,
+    smtp::{
+        report::AggregateFrequency,
+        resolver::{Policy, Tlsa},
+    },
 };
 
 pub enum HousekeeperEvent {
@@ -27,7 +30,14 @@ pub enum HousekeeperEvent {
         provider_id: String,
         renew_at: Instant,
     },
+    OcspReschedule {
+        refresh_at: Instant,
+    },
+    TlsTicketKeysReschedule {
+        refresh_at: Instant,
+    },
     Purge(PurgeType),
+    RunTask(MaintenanceTask),
     ReloadSettings,
     Exit,
 }
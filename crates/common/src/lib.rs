@@ -26,9 +26,17 @@ use config::{
 };
 use ipc::{BroadcastEvent, HousekeeperEvent, QueueEvent, ReportingEvent, StateEvent};
 use jmap_proto::types::value::AclGrant;
-use listener::{asn::AsnGeoLookupData, blocked::Security, tls::AcmeProviders};
+use listener::{
+    asn::AsnGeoLookupData,
+    blocked::Security,
+    tickets::TicketKeys,
+    tls::{AcmeProviders, ClientFingerprintStats, TlsHandshakeStats},
+};
 use mail_auth::{MX, Txt};
-use manager::webadmin::{Resource, WebAdminManager};
+use manager::{
+    maintenance::RunningTask,
+    webadmin::{Resource, WebAdminManager},
+};
 use nlp::bayes::{TokenHash, Weights};
 use parking_lot::{Mutex, RwLock};
 use rustls::sign::CertifiedKey;
@@ -38,6 +46,8 @@ use std::{
     sync::{Arc, atomic::AtomicBool},
     time::Duration,
 };
+use storage::backlog::IndexBacklog;
+use telemetry::load_test::LoadTestStats;
 use tinyvec::TinyVec;
 use tokio::sync::{Notify, Semaphore, mpsc};
 use tokio_rustls::TlsConnector;
@@ -71,7 +81,7 @@ pub static USER_AGENT: &str = "Stalwart/1.0.0";
 pub static DAEMON_NAME: &str = concat!("Stalwart v", env!("CARGO_PKG_VERSION"),);
 pub static PROD_ID: &str = "-//Stalwart Labs Ltd.//Stalwart Server//EN";
 
-pub const DATABASE_SCHEMA_VERSION: u32 = 1;
+pub const DATABASE_SCHEMA_VERSION: u32 = 2;
 
 pub const LONG_1D_SLUMBER: Duration = Duration::from_secs(60 * 60 * 24);
 pub const LONG_1Y_SLUMBER: Duration = Duration::from_secs(60 * 60 * 24 * 365);
@@ -105,6 +115,27 @@ pub const KV_LOCK_EMAIL_TASK: u8 = 23;
 pub const KV_LOCK_HOUSEKEEPER: u8 = 24;
 pub const KV_LOCK_DAV: u8 = 25;
 pub const KV_SIEVE_ID: u8 = 26;
+pub const KV_IP_WARMUP: u8 = 27;
+pub const KV_IP_WARMUP_COUNTER: u8 = 28;
+pub const KV_LOCK_IP_WARMUP: u8 = 29;
+pub const KV_LOCK_INGEST_HOOK: u8 = 30;
+pub const KV_BLOB_PACK_DELETIONS: u8 = 31;
+pub const KV_LOCK_BLOB_PACK: u8 = 32;
+pub const KV_RATE_LIMIT_EXPR: u8 = 33;
+pub const KV_REMOTE_CONTENT: u8 = 34;
+pub const KV_RATE_LIMIT_REMOTE_CONTENT: u8 = 35;
+pub const KV_RATE_LIMIT_ENCRYPT: u8 = 36;
+pub const KV_INTEGRITY_REPORT: u8 = 37;
+pub const KV_LOCK_INTEGRITY: u8 = 38;
+pub const KV_UPLOAD_SCAN: u8 = 39;
+pub const KV_RATE_LIMIT_SYSTEM_MESSAGE: u8 = 40;
+pub const KV_MAINTENANCE: u8 = 41;
+pub const KV_DELIVERY_LOOP: u8 = 42;
+pub const KV_IMPERSONATION: u8 = 43;
+pub const KV_ENCRYPTION_FAILURE: u8 = 44;
+pub const KV_MAINTENANCE_TASK: u8 = 45;
+pub const KV_TLS_TICKET_KEYS: u8 = 46;
+pub const KV_QUARANTINE_RELEASE: u8 = 47;
 
 pub const IDX_UID: u8 = 0;
 pub const IDX_EMAIL: u8 = 1;
@@ -125,6 +156,7 @@ pub struct Inner {
 pub struct Data {
     pub tls_certificates: ArcSwap<AHashMap<String, Arc<CertifiedKey>>>,
     pub tls_self_signed_cert: Option<Arc<CertifiedKey>>,
+    pub tls_ticket_keys: ArcSwap<TicketKeys>,
 
     pub blocked_ips: RwLock<AHashSet<IpAddr>>,
 
@@ -137,6 +169,11 @@ pub struct Data {
 
     pub webadmin: WebAdminManager,
     pub logos: Mutex<AHashMap<String, Option<Resource<Vec<u8>>>>>,
+    pub tls_fingerprints: Mutex<AHashMap<String, ClientFingerprintStats>>,
+    pub tls_handshakes: Mutex<TlsHandshakeStats>,
+    pub running_tasks: Mutex<Vec<RunningTask>>,
+    pub load_test: LoadTestStats,
+    pub index_backlog: IndexBacklog,
 
     pub smtp_connectors: TlsConnectors,
 }
@@ -189,9 +226,25 @@ pub struct MessagesCache {
     pub items: Vec<MessageCache>,
     pub index: AHashMap<u32, u32>,
     pub keywords: Vec<String>,
+    pub counters: AHashMap<u32, MailboxCounters>,
     pub size: u64,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct MailboxCounters {
+    pub total_emails: u32,
+    pub unread_emails: u32,
+    pub unread_threads: u32,
+    pub threads: AHashMap<u32, ThreadCounters>,
+    pub change_id: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThreadCounters {
+    pub total: u32,
+    pub unread: u32,
+}
+
 #[derive(Debug, Clone)]
 pub struct MessageCache {
     pub document_id: u32,
@@ -795,6 +848,19 @@ impl<T> CacheSwap<T> {
     }
 }
 
+impl MailboxCounters {
+    pub fn total_threads(&self) -> u32 {
+        self.threads.len() as u32
+    }
+
+    pub fn has_same_counts(&self, other: &MailboxCounters) -> bool {
+        self.total_emails == other.total_emails
+            && self.unread_emails == other.unread_emails
+            && self.unread_threads == other.unread_threads
+            && self.threads == other.threads
+    }
+}
+
 impl MailboxCache {
     pub fn parent_id(&self) -> Option<u32> {
         if self.parent_id != u32::MAX {
@@ -61,6 +61,81 @@ impl Server {
         })
     }
 
+    pub(crate) async fn load_tlsa_state(
+        &self,
+        provider: &AcmeProvider,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        self.read_if_exists(provider, "tlsa", provider.domains.as_slice())
+            .await
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .details("Failed to load TLSA state")
+            })
+    }
+
+    pub(crate) async fn store_tlsa_state(
+        &self,
+        provider: &AcmeProvider,
+        state: &[u8],
+    ) -> trc::Result<()> {
+        self.write(provider, "tlsa", provider.domains.as_slice(), state)
+            .await
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .details("Failed to store TLSA state")
+            })
+    }
+
+    pub(crate) async fn load_next_key(
+        &self,
+        provider: &AcmeProvider,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        self.read_if_exists(provider, "next-key", provider.domains.as_slice())
+            .await
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .details("Failed to load next key")
+            })
+    }
+
+    pub(crate) async fn store_next_key(
+        &self,
+        provider: &AcmeProvider,
+        key: &[u8],
+    ) -> trc::Result<()> {
+        self.write(provider, "next-key", provider.domains.as_slice(), key)
+            .await
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .details("Failed to store next key")
+            })
+    }
+
+    pub(crate) async fn load_excluded_domains(
+        &self,
+        provider: &AcmeProvider,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        self.read_if_exists(provider, "excluded", provider.domains.as_slice())
+            .await
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .details("Failed to load excluded domains")
+            })
+    }
+
+    pub(crate) async fn store_excluded_domains(
+        &self,
+        provider: &AcmeProvider,
+        domains: &[u8],
+    ) -> trc::Result<()> {
+        self.write(provider, "excluded", provider.domains.as_slice(), domains)
+            .await
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .details("Failed to store excluded domains")
+            })
+    }
+
     async fn read_if_exists(
         &self,
         provider: &AcmeProvider,
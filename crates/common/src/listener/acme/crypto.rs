@@ -0,0 +1,181 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+use store::{Deserialize, Value, blake3};
+use trc::{AcmeEvent, EventType};
+
+// Archives always end with a marker byte that has the high bit set, so values
+// written before encryption was enabled are told apart by their last byte
+const ENCRYPTED_MARKER: u8 = 0x01;
+
+// Certificates written to the in-memory store are encrypted with the first
+// key, all keys are tried on reads so they can be rotated
+#[derive(Default, Clone)]
+pub struct AcmeStorageKeys {
+    keys: Vec<[u8; 32]>,
+}
+
+#[derive(Debug)]
+pub(crate) struct StoredValue(pub Vec<u8>);
+
+impl AcmeStorageKeys {
+    pub fn new<'x>(keys: impl IntoIterator<Item = &'x str>) -> Self {
+        AcmeStorageKeys {
+            keys: keys
+                .into_iter()
+                .map(|key| blake3::derive_key("acme storage key", key.as_bytes()))
+                .collect(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    // The domain is authenticated so a value cannot be served for another name
+    pub fn encrypt(&self, domain: &str, mut bytes: Vec<u8>) -> trc::Result<Vec<u8>> {
+        let Some(key) = self.keys.first() else {
+            return Ok(bytes);
+        };
+
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| crypto_error("Failed to generate nonce"))?;
+        build_key(key)?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(domain.as_bytes()),
+                &mut bytes,
+            )
+            .map_err(|_| crypto_error("Failed to encrypt certificate"))?;
+
+        let mut value = Vec::with_capacity(NONCE_LEN + bytes.len() + 1);
+        value.extend_from_slice(&nonce);
+        value.extend_from_slice(&bytes);
+        value.push(ENCRYPTED_MARKER);
+        Ok(value)
+    }
+
+    // Values stored in plain text are returned as is
+    pub fn decrypt(&self, domain: &str, value: Vec<u8>) -> trc::Result<Vec<u8>> {
+        let Some((&ENCRYPTED_MARKER, contents)) = value.split_last() else {
+            return Ok(value);
+        };
+        let (nonce, contents) = contents
+            .split_at_checked(NONCE_LEN)
+            .ok_or_else(|| crypto_error("Encrypted certificate is truncated"))?;
+        if !self.is_enabled() {
+            return Err(crypto_error(
+                "Certificate is encrypted but no storage key is configured",
+            ));
+        }
+
+        for key in &self.keys {
+            let mut bytes = contents.to_vec();
+            let nonce = Nonce::try_assume_unique_for_key(nonce)
+                .map_err(|_| crypto_error("Invalid nonce"))?;
+            if let Ok(plain_len) = build_key(key)?
+                .open_in_place(nonce, Aad::from(domain.as_bytes()), &mut bytes)
+                .map(|plain| plain.len())
+            {
+                bytes.truncate(plain_len);
+                return Ok(bytes);
+            }
+        }
+
+        Err(crypto_error(
+            "Failed to decrypt certificate with any of the configured storage keys",
+        ))
+    }
+}
+
+fn build_key(key: &[u8; 32]) -> trc::Result<LessSafeKey> {
+    UnboundKey::new(&AES_256_GCM, key)
+        .map(LessSafeKey::new)
+        .map_err(|_| crypto_error("Invalid storage key"))
+}
+
+fn crypto_error(details: &'static str) -> trc::Error {
+    EventType::Acme(AcmeEvent::Error)
+        .caused_by(trc::location!())
+        .details(details)
+}
+
+impl Deserialize for StoredValue {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        Ok(StoredValue(bytes.to_vec()))
+    }
+
+    fn deserialize_owned(bytes: Vec<u8>) -> trc::Result<Self> {
+        Ok(StoredValue(bytes))
+    }
+}
+
+impl From<Value<'static>> for StoredValue {
+    fn from(value: Value<'static>) -> Self {
+        match value {
+            Value::Blob(bytes) => StoredValue(bytes.into_owned()),
+            Value::Text(text) => StoredValue(text.into_owned().into_bytes()),
+            _ => StoredValue(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AcmeStorageKeys;
+
+    #[test]
+    fn storage_keys() {
+        // Archives end with a byte that has the high bit set
+        let value = b"serialized certificate\x80".to_vec();
+
+        // Plain text values are read as is, with or without keys
+        let plain = AcmeStorageKeys::default();
+        let keys = AcmeStorageKeys::new(["secret"]);
+        assert_eq!(plain.encrypt("example.org", value.clone()).unwrap(), value);
+        assert_eq!(keys.decrypt("example.org", value.clone()).unwrap(), value);
+
+        // Encrypted values
+        let encrypted = keys.encrypt("example.org", value.clone()).unwrap();
+        assert_ne!(encrypted, value);
+        assert_ne!(
+            keys.encrypt("example.org", value.clone()).unwrap(),
+            encrypted
+        );
+        assert_eq!(
+            keys.decrypt("example.org", encrypted.clone()).unwrap(),
+            value
+        );
+        assert!(keys.decrypt("example.net", encrypted.clone()).is_err());
+        assert!(plain.decrypt("example.org", encrypted.clone()).is_err());
+        assert!(
+            AcmeStorageKeys::new(["other"])
+                .decrypt("example.org", encrypted.clone())
+                .is_err()
+        );
+
+        // After a rotation the previous key is still used for reads
+        let rotated = AcmeStorageKeys::new(["new secret", "secret"]);
+        assert_eq!(
+            rotated.decrypt("example.org", encrypted.clone()).unwrap(),
+            value
+        );
+        let encrypted = rotated.encrypt("example.org", value.clone()).unwrap();
+        assert!(keys.decrypt("example.org", encrypted.clone()).is_err());
+        assert_eq!(
+            AcmeStorageKeys::new(["new secret"])
+                .decrypt("example.org", encrypted)
+                .unwrap(),
+            value
+        );
+    }
+}
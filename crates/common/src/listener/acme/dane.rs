@@ -0,0 +1,159 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use rcgen::{KeyPair, PKCS_ECDSA_P256_SHA256};
+use rustls::sign::CertifiedKey;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use trc::{AcmeEvent, AddContext, EventType};
+use x509_parser::parse_x509_certificate;
+
+use crate::Server;
+
+use super::{AcmeProvider, dns::DnsWebhook};
+
+#[derive(Clone)]
+pub struct DaneSettings {
+    pub webhook: DnsWebhook,
+    pub hosts: Vec<String>,
+    pub pre_publish: bool,
+}
+
+#[derive(Serialize)]
+struct TlsaUpdateRequest<'x> {
+    provider: &'x str,
+    names: &'x [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old_digest: Option<&'x str>,
+    new_digest: &'x str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_digest: Option<&'x str>,
+    records: &'x [String],
+}
+
+impl Server {
+    // Sends the TLSA 3 1 1 records of the installed certificate to the DANE hook
+    // whenever its key changes. When pre-publishing, the record of the key that will
+    // be used for the next renewal is included so both coexist during the rollover.
+    pub(crate) async fn publish_tlsa(&self, provider: &AcmeProvider, cert: &CertifiedKey) {
+        if let Err(err) = self.try_publish_tlsa(provider, cert).await {
+            trc::event!(
+                Acme(AcmeEvent::TlsaPublishFailed),
+                Id = provider.id.to_string(),
+                CausedBy = err,
+            );
+        }
+    }
+
+    async fn try_publish_tlsa(
+        &self,
+        provider: &AcmeProvider,
+        cert: &CertifiedKey,
+    ) -> trc::Result<()> {
+        let Some(dane) = &provider.dane else {
+            return Ok(());
+        };
+        let digest = cert
+            .cert
+            .first()
+            .and_then(|cert| parse_x509_certificate(cert).ok())
+            .map(|(_, cert)| format!("{:x}", Sha256::digest(cert.subject_pki.raw)))
+            .ok_or_else(|| {
+                EventType::Acme(AcmeEvent::Error)
+                    .caused_by(trc::location!())
+                    .details("Failed to parse certificate")
+            })?;
+
+        // The next key is replaced once a certificate has been issued for it
+        let next_digest = if dane.pre_publish {
+            let next_key = match self.load_next_key(provider).await? {
+                Some(next_key) if next_key_digest(&next_key).is_some_and(|next| next != digest) => {
+                    next_key
+                }
+                _ => {
+                    let next_key = KeyPair::generate(&PKCS_ECDSA_P256_SHA256)
+                        .map_err(|err| {
+                            EventType::Acme(AcmeEvent::Error)
+                                .caused_by(trc::location!())
+                                .reason(err)
+                                .details("Failed to generate key pair")
+                        })?
+                        .serialize_pem()
+                        .into_bytes();
+                    self.store_next_key(provider, &next_key).await?;
+                    next_key
+                }
+            };
+            next_key_digest(&next_key)
+        } else {
+            None
+        };
+
+        // Nothing to do if the records have already been published
+        let state = if let Some(next_digest) = &next_digest {
+            format!("{digest} {next_digest}")
+        } else {
+            digest.clone()
+        };
+        let previous = self
+            .load_tlsa_state(provider)
+            .await?
+            .map(|previous| String::from_utf8_lossy(&previous).into_owned());
+        if previous.as_deref() == Some(state.as_str()) {
+            return Ok(());
+        }
+
+        let names = dane
+            .hosts
+            .iter()
+            .map(|host| format!("_25._tcp.{host}"))
+            .collect::<Vec<_>>();
+        let records = [Some(&digest), next_digest.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|digest| format!("3 1 1 {digest}"))
+            .collect::<Vec<_>>();
+        dane.webhook
+            .post(TlsaUpdateRequest {
+                provider: &provider.id,
+                names: &names,
+                old_digest: previous
+                    .as_deref()
+                    .and_then(|previous| previous.split(' ').next())
+                    .filter(|previous| *previous != digest),
+                new_digest: &digest,
+                next_digest: next_digest.as_deref(),
+                records: &records,
+            })
+            .await
+            .map_err(|err| {
+                EventType::Acme(AcmeEvent::Error)
+                    .caused_by(trc::location!())
+                    .reason(err)
+                    .details("DANE hook request failed")
+            })?;
+        self.store_tlsa_state(provider, state.as_bytes())
+            .await
+            .caused_by(trc::location!())?;
+
+        trc::event!(
+            Acme(AcmeEvent::TlsaPublished),
+            Id = provider.id.to_string(),
+            Hostname = names.as_slice(),
+            Details = records.as_slice(),
+        );
+
+        Ok(())
+    }
+}
+
+// Returns the SHA-256 digest of the SubjectPublicKeyInfo of a PEM encoded key pair
+fn next_key_digest(pem: &[u8]) -> Option<String> {
+    std::str::from_utf8(pem)
+        .ok()
+        .and_then(|pem| KeyPair::from_pem(pem).ok())
+        .map(|key| format!("{:x}", Sha256::digest(key.public_key_der())))
+}
@@ -73,6 +73,10 @@ impl Account {
         })?;
         let eab = if let Some(eab) = &provider.eab {
             eab_sign(&key_pair, &eab.kid, &eab.hmac_key, &directory.new_account)
+                .map_err(|err| {
+                    err.details("Failed to sign external account binding")
+                        .ctx_unique(trc::Key::Id, provider.id.to_string())
+                })
                 .caused_by(trc::location!())?
                 .into()
         } else {
@@ -93,7 +97,16 @@ impl Account {
             &directory.new_account,
             &payload,
         )?;
-        let response = https(&directory.new_account, Method::POST, Some(body)).await?;
+        let response = https(&directory.new_account, Method::POST, Some(body))
+            .await
+            .map_err(|err| {
+                err.details(if provider.eab.is_some() {
+                    "Account registration failed, check the external account binding credentials"
+                } else {
+                    "Account registration failed"
+                })
+                .ctx_unique(trc::Key::Id, provider.id.to_string())
+            })?;
         let kid = get_header(&response, "Location")?;
         Ok(Account {
             key_pair,
@@ -189,11 +202,11 @@ impl Account {
         })?;
 
         Archiver::new(SerializedCert {
-            certificate: cert.serialize_der().map_err(|err| {
+            certificates: vec![cert.serialize_der().map_err(|err| {
                 trc::EventType::Acme(trc::AcmeEvent::Error)
                     .caused_by(trc::location!())
                     .reason(err)
-            })?,
+            })?],
             private_key: cert.serialize_private_key_der(),
         })
         .untrusted()
@@ -205,10 +218,41 @@ impl Account {
     rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone, serde::Serialize, Deserialize,
 )]
 pub struct SerializedCert {
+    // Leaf certificate first, followed by the intermediates in chain order
+    pub certificates: Vec<Vec<u8>>,
+    pub private_key: Vec<u8>,
+}
+
+// Single certificate layout written by earlier versions
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone)]
+pub struct SerializedCertV1 {
     pub certificate: Vec<u8>,
     pub private_key: Vec<u8>,
 }
 
+impl SerializedCert {
+    // Parses the private key followed by the full chain returned on finalization
+    pub fn from_pem(pem: &[u8]) -> trc::Result<Self> {
+        let mut pems = pem::parse_many(pem).map_err(|err| {
+            trc::EventType::Acme(trc::AcmeEvent::Error)
+                .reason(err)
+                .caused_by(trc::location!())
+        })?;
+        if pems.len() < 2 {
+            return Err(trc::EventType::Acme(trc::AcmeEvent::Error)
+                .caused_by(trc::location!())
+                .ctx(trc::Key::Size, pems.len())
+                .details("Too few PEMs"));
+        }
+        let private_key = pems.remove(0).into_contents();
+
+        Ok(SerializedCert {
+            certificates: pems.into_iter().map(|p| p.into_contents()).collect(),
+            private_key,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Directory {
@@ -385,3 +429,15 @@ impl AuthStatus {
         }
     }
 }
+
+impl Auth {
+    // Wildcard identifiers are returned without the "*." prefix
+    pub fn domain(&self) -> String {
+        let Identifier::Dns(domain) = &self.identifier;
+        if self.wildcard.unwrap_or_default() {
+            format!("*.{domain}")
+        } else {
+            domain.clone()
+        }
+    }
+}
@@ -0,0 +1,121 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use dns_update::{DnsRecord, DnsUpdater};
+use hyper::HeaderMap;
+use serde::Serialize;
+
+#[derive(Clone)]
+pub enum DnsChallengeUpdater {
+    Provider(DnsUpdater),
+    Webhook(DnsWebhook),
+}
+
+#[derive(Clone)]
+pub struct DnsWebhook {
+    pub url: String,
+    pub headers: HeaderMap,
+    pub timeout: Duration,
+    pub tls_allow_invalid_certs: bool,
+}
+
+#[derive(Serialize)]
+struct WebhookRequest<'x> {
+    action: &'static str,
+    domain: &'x str,
+    txt_value: &'x str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttl: Option<u32>,
+    origin: &'x str,
+}
+
+impl DnsChallengeUpdater {
+    pub async fn create(
+        &self,
+        name: &str,
+        txt_value: &str,
+        ttl: u32,
+        origin: &str,
+    ) -> Result<(), String> {
+        match self {
+            DnsChallengeUpdater::Provider(updater) => updater
+                .create(
+                    name,
+                    DnsRecord::TXT {
+                        content: txt_value.to_string(),
+                    },
+                    ttl,
+                    origin,
+                )
+                .await
+                .map_err(|err| err.to_string()),
+            DnsChallengeUpdater::Webhook(webhook) => {
+                webhook
+                    .post(WebhookRequest {
+                        action: "create",
+                        domain: name,
+                        txt_value,
+                        ttl: Some(ttl),
+                        origin,
+                    })
+                    .await
+            }
+        }
+    }
+
+    pub async fn delete(&self, name: &str, txt_value: &str, origin: &str) -> Result<(), String> {
+        match self {
+            DnsChallengeUpdater::Provider(updater) => updater
+                .delete(name, origin)
+                .await
+                .map_err(|err| err.to_string()),
+            DnsChallengeUpdater::Webhook(webhook) => {
+                webhook
+                    .post(WebhookRequest {
+                        action: "delete",
+                        domain: name,
+                        txt_value,
+                        ttl: None,
+                        origin,
+                    })
+                    .await
+            }
+        }
+    }
+}
+
+impl DnsWebhook {
+    pub(super) async fn post(&self, request: impl Serialize) -> Result<(), String> {
+        let body = serde_json::to_string(&request)
+            .map_err(|err| format!("Failed to serialize request: {err}"))?;
+
+        let response = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .danger_accept_invalid_certs(self.tls_allow_invalid_certs)
+            .build()
+            .map_err(|err| format!("Failed to create HTTP client: {err}"))?
+            .post(&self.url)
+            .headers(self.headers.clone())
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| format!("Webhook request to {} failed: {err}", self.url))?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Webhook request to {} failed with code {}: {}",
+                self.url,
+                response.status().as_u16(),
+                response.status().canonical_reason().unwrap_or("Unknown")
+            ))
+        }
+    }
+}
@@ -0,0 +1,71 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use trc::AcmeEvent;
+
+use crate::Server;
+
+use super::AcmeProvider;
+
+impl Server {
+    // Domains of the provider that were dropped from its certificate after
+    // failing authorization, they are skipped until re-enabled by an administrator
+    pub async fn acme_excluded_domains(&self, provider: &AcmeProvider) -> trc::Result<Vec<String>> {
+        Ok(self
+            .load_excluded_domains(provider)
+            .await?
+            .map(|domains| {
+                String::from_utf8_lossy(&domains)
+                    .lines()
+                    .filter(|domain| provider.domains.iter().any(|d| d == domain))
+                    .map(|domain| domain.to_string())
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    pub(crate) async fn acme_exclude_domains(
+        &self,
+        provider: &AcmeProvider,
+        domains: &[String],
+    ) -> trc::Result<()> {
+        let mut excluded = self.acme_excluded_domains(provider).await?;
+        for domain in domains {
+            if !excluded.contains(domain) {
+                excluded.push(domain.clone());
+            }
+        }
+        self.store_excluded_domains(provider, excluded.join("\n").as_bytes())
+            .await?;
+
+        trc::event!(
+            Acme(AcmeEvent::DomainsExcluded),
+            Id = provider.id.to_string(),
+            Hostname = domains,
+            Total = excluded.len(),
+        );
+
+        Ok(())
+    }
+
+    // Returns false if the domain was not excluded
+    pub async fn acme_include_domain(
+        &self,
+        provider: &AcmeProvider,
+        domain: &str,
+    ) -> trc::Result<bool> {
+        let mut excluded = self.acme_excluded_domains(provider).await?;
+        let num_excluded = excluded.len();
+        excluded.retain(|excluded| !excluded.eq_ignore_ascii_case(domain));
+        if excluded.len() != num_excluded {
+            self.store_excluded_domains(provider, excluded.join("\n").as_bytes())
+                .await
+                .map(|_| true)
+        } else {
+            Ok(false)
+        }
+    }
+}
@@ -5,20 +5,35 @@
  */
 
 pub mod cache;
+pub mod crypto;
+pub mod dane;
 pub mod directory;
+pub mod dns;
+pub mod exclude;
 pub mod jose;
+pub mod ocsp;
 pub mod order;
 pub mod resolver;
 
-use std::{fmt::Debug, sync::Arc, time::Duration};
+use std::{
+    fmt::Debug,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 
 use arc_swap::ArcSwap;
-use dns_update::DnsUpdater;
 use rustls::sign::CertifiedKey;
 
 use crate::Server;
 
-use self::directory::{Account, ChallengeType};
+use self::{
+    dane::DaneSettings,
+    directory::{Account, ChallengeType},
+    dns::DnsChallengeUpdater,
+};
 
 pub struct AcmeProvider {
     pub id: String,
@@ -27,9 +42,14 @@ pub struct AcmeProvider {
     pub contact: Vec<String>,
     pub challenge: ChallengeSettings,
     pub eab: Option<EabSettings>,
+    pub dane: Option<DaneSettings>,
+    pub listeners: Vec<String>,
     renew_before: chrono::Duration,
+    auth_attempts: u32,
     account_key: ArcSwap<Vec<u8>>,
+    account_key_pinned: bool,
     default: bool,
+    renew_at: AtomicU64,
 }
 
 #[derive(Clone)]
@@ -43,7 +63,7 @@ pub enum ChallengeSettings {
     Http01,
     TlsAlpn01,
     Dns01 {
-        updater: DnsUpdater,
+        updater: DnsChallengeUpdater,
         origin: Option<String>,
         polling_interval: Duration,
         propagation_timeout: Duration,
@@ -64,7 +84,11 @@ impl AcmeProvider {
         contact: Vec<String>,
         challenge: ChallengeSettings,
         eab: Option<EabSettings>,
+        dane: Option<DaneSettings>,
+        listeners: Vec<String>,
+        account_key: Option<Vec<u8>>,
         renew_before: Duration,
+        auth_attempts: u32,
         default: bool,
     ) -> trc::Result<Self> {
         Ok(AcmeProvider {
@@ -81,24 +105,41 @@ impl AcmeProvider {
                 })
                 .collect(),
             renew_before: chrono::Duration::from_std(renew_before).unwrap(),
+            auth_attempts: auth_attempts.max(1),
             domains,
-            account_key: Default::default(),
+            account_key_pinned: account_key.is_some(),
+            account_key: ArcSwap::from_pointee(account_key.unwrap_or_default()),
             challenge,
             eab,
+            dane,
+            listeners,
             default,
+            renew_at: AtomicU64::new(0),
         })
     }
+
+    // Unix timestamp of the next scheduled renewal on this node, if any
+    pub fn renewal_due(&self) -> Option<u64> {
+        Some(self.renew_at.load(Ordering::Relaxed)).filter(|renew_at| *renew_at != 0)
+    }
+
+    pub fn schedule_renewal(&self, renew_at: u64) {
+        self.renew_at.store(renew_at, Ordering::Relaxed);
+    }
 }
 
 impl Server {
     pub async fn init_acme(&self, provider: &AcmeProvider) -> trc::Result<Duration> {
-        // Load account key from cache or generate a new one
-        if let Some(account_key) = self.load_account(provider).await? {
-            provider.account_key.store(Arc::new(account_key));
-        } else {
-            let account_key = Account::generate_key_pair();
-            self.store_account(provider, &account_key).await?;
-            provider.account_key.store(Arc::new(account_key));
+        // Use the configured account key, otherwise load it from the store or
+        // generate a new one so all nodes share the same ACME account
+        if !provider.account_key_pinned {
+            if let Some(account_key) = self.load_account(provider).await? {
+                provider.account_key.store(Arc::new(account_key));
+            } else {
+                let account_key = Account::generate_key_pair();
+                self.store_account(provider, &account_key).await?;
+                provider.account_key.store(Arc::new(account_key));
+            }
         }
 
         // Load certificate from cache or request a new one
@@ -136,6 +177,12 @@ impl ChallengeSettings {
     }
 }
 
+// HTTP-01 proofs share KV_ACME with TLS-ALPN-01 certificates and OCSP
+// responses, a separate namespace keeps the public route from reading those.
+pub fn http_challenge_key(token: &str) -> String {
+    format!("http-01:{token}")
+}
+
 impl Debug for StaticResolver {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("StaticResolver").finish()
@@ -151,9 +198,14 @@ impl Clone for AcmeProvider {
             contact: self.contact.clone(),
             challenge: self.challenge.clone(),
             renew_before: self.renew_before,
+            auth_attempts: self.auth_attempts,
             account_key: ArcSwap::from_pointee(self.account_key.load().as_ref().clone()),
+            account_key_pinned: self.account_key_pinned,
             eab: self.eab.clone(),
+            dane: self.dane.clone(),
+            listeners: self.listeners.clone(),
             default: self.default,
+            renew_at: AtomicU64::new(self.renew_at.load(Ordering::Relaxed)),
         }
     }
 }
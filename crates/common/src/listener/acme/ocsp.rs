@@ -0,0 +1,525 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use ahash::AHashMap;
+use chrono::NaiveDateTime;
+use hyper::header::{CONTENT_TYPE, USER_AGENT};
+use rustls::sign::CertifiedKey;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use store::{
+    Serialize,
+    dispatch::lookup::KeyValue,
+    write::{AlignedBytes, Archive, Archiver, now},
+};
+use trc::{AcmeEvent, EventType, event::conv::AssertSuccess};
+use x509_parser::{
+    certificate::X509Certificate,
+    extensions::{GeneralName, ParsedExtension},
+    oid_registry::OID_PKIX_ACCESS_DESCRIPTOR_OCSP,
+    parse_x509_certificate,
+};
+
+use crate::{KV_ACME, Server};
+
+const OCSP_MIN_REFRESH: u64 = 3600;
+const OCSP_DEFAULT_VALIDITY: u64 = 86400;
+
+// DER encoded OIDs for SHA-1 and id-pkix-ocsp-basic
+const OID_SHA1: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+const OID_OCSP_BASIC: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone)]
+pub struct SerializedOcsp {
+    pub serial: Vec<u8>,
+    pub response: Vec<u8>,
+    pub this_update: u64,
+    pub next_update: u64,
+}
+
+struct OcspRequest {
+    url: String,
+    serial: Vec<u8>,
+    fingerprint: String,
+    request: Vec<u8>,
+}
+
+impl Server {
+    // Staples OCSP responses to all served certificates, returning when the next refresh is due
+    pub async fn refresh_ocsp(&self) -> Option<Duration> {
+        // Group the names served by each certificate
+        let mut certificates: Vec<(Arc<CertifiedKey>, Vec<String>)> = Vec::new();
+        for (name, cert) in self.inner.data.tls_certificates.load().iter() {
+            if let Some((_, names)) = certificates
+                .iter_mut()
+                .find(|(other, _)| Arc::ptr_eq(other, cert))
+            {
+                names.push(name.clone());
+            } else {
+                certificates.push((cert.clone(), vec![name.clone()]));
+            }
+        }
+
+        let now = now();
+        let mut refresh_in: Option<u64> = None;
+        let mut stapled = Vec::new();
+        for (cert, names) in certificates {
+            let ocsp = match self.fetch_ocsp(&cert, now).await {
+                Ok(Some((url, ocsp))) => {
+                    trc::event!(
+                        Acme(AcmeEvent::OcspStapled),
+                        Hostname = names,
+                        Url = url,
+                        ValidFrom = trc::Value::Timestamp(ocsp.this_update),
+                        ValidTo = trc::Value::Timestamp(ocsp.next_update),
+                    );
+                    ocsp
+                }
+                Ok(None) => continue,
+                Err(err) => {
+                    // Failures keep serving the certificate as is and retry later
+                    trc::error!(
+                        err.details("Failed to refresh OCSP response.")
+                            .ctx(trc::Key::Hostname, names)
+                    );
+                    refresh_in =
+                        Some(refresh_in.map_or(OCSP_MIN_REFRESH, |r| r.min(OCSP_MIN_REFRESH)));
+                    continue;
+                }
+            };
+
+            let cert_refresh_in = ocsp.refresh_at().saturating_sub(now).max(OCSP_MIN_REFRESH);
+            refresh_in = Some(refresh_in.map_or(cert_refresh_in, |r| r.min(cert_refresh_in)));
+            if cert.ocsp.as_ref() != Some(&ocsp.response) {
+                let mut new_cert = cert.as_ref().clone();
+                new_cert.ocsp = Some(ocsp.response);
+                stapled.push((cert, Arc::new(new_cert)));
+            }
+        }
+
+        // Replace the stapled certificates, leaving any that changed in the meantime untouched
+        if !stapled.is_empty() {
+            let mut certificates = self.inner.data.tls_certificates.load().as_ref().clone();
+            for cert in certificates.values_mut() {
+                if let Some((_, new_cert)) = stapled.iter().find(|(old, _)| Arc::ptr_eq(old, cert))
+                {
+                    *cert = new_cert.clone();
+                }
+            }
+            self.inner.data.tls_certificates.store(certificates.into());
+        }
+
+        refresh_in.map(Duration::from_secs)
+    }
+
+    pub(crate) async fn staple_cached_ocsp(&self, cert: &mut CertifiedKey) {
+        if let Some(leaf) = cert.cert.first() {
+            if let Some(ocsp) = self.load_ocsp(&fingerprint(leaf)).await {
+                cert.ocsp = Some(ocsp.response);
+            }
+        }
+    }
+
+    pub(crate) async fn staple_cached_ocsp_all(
+        &self,
+        certificates: &mut AHashMap<String, Arc<CertifiedKey>>,
+    ) {
+        let mut stapled: Vec<(Arc<CertifiedKey>, Arc<CertifiedKey>)> = Vec::new();
+        for cert in certificates.values_mut() {
+            if cert.ocsp.is_some() {
+                continue;
+            }
+            if let Some((_, new_cert)) = stapled.iter().find(|(old, _)| Arc::ptr_eq(old, cert)) {
+                *cert = new_cert.clone();
+                continue;
+            }
+            let mut new_cert = cert.as_ref().clone();
+            self.staple_cached_ocsp(&mut new_cert).await;
+            if new_cert.ocsp.is_some() {
+                let new_cert = Arc::new(new_cert);
+                stapled.push((cert.clone(), new_cert.clone()));
+                *cert = new_cert;
+            }
+        }
+    }
+
+    // Returns the cached response unless it is due for a refresh
+    async fn fetch_ocsp(
+        &self,
+        cert: &CertifiedKey,
+        now: u64,
+    ) -> trc::Result<Option<(String, SerializedOcsp)>> {
+        let Some(request) = OcspRequest::build(cert)? else {
+            return Ok(None);
+        };
+
+        let ocsp = match self.load_ocsp(&request.fingerprint).await {
+            Some(ocsp) if ocsp.refresh_at() > now => ocsp,
+            _ => {
+                let ocsp = request.fetch().await?;
+                self.store_ocsp(&request.fingerprint, &ocsp).await?;
+                ocsp
+            }
+        };
+
+        Ok(Some((request.url, ocsp)))
+    }
+
+    async fn load_ocsp(&self, fingerprint: &str) -> Option<SerializedOcsp> {
+        match self
+            .in_memory_store()
+            .key_get::<Archive<AlignedBytes>>(KeyValue::<()>::build_key(
+                KV_ACME,
+                ocsp_key(fingerprint),
+            ))
+            .await
+            .and_then(|ocsp| {
+                ocsp.map(|ocsp| ocsp.deserialize::<SerializedOcsp>())
+                    .transpose()
+            }) {
+            Ok(Some(ocsp)) if ocsp.next_update > now() => Some(ocsp),
+            Ok(_) => None,
+            Err(err) => {
+                trc::error!(
+                    err.details("Failed to load OCSP response")
+                        .ctx(trc::Key::Id, fingerprint.to_string())
+                );
+                None
+            }
+        }
+    }
+
+    async fn store_ocsp(&self, fingerprint: &str, ocsp: &SerializedOcsp) -> trc::Result<()> {
+        self.in_memory_store()
+            .key_set(
+                KeyValue::with_prefix(
+                    KV_ACME,
+                    ocsp_key(fingerprint),
+                    Archiver::new(ocsp.clone()).untrusted().serialize()?,
+                )
+                .expires(ocsp.next_update.saturating_sub(now()).max(1)),
+            )
+            .await
+    }
+}
+
+impl SerializedOcsp {
+    fn refresh_at(&self) -> u64 {
+        // Refresh halfway through the validity period
+        self.this_update + (self.next_update.saturating_sub(self.this_update) / 2)
+    }
+}
+
+impl OcspRequest {
+    fn build(cert: &CertifiedKey) -> trc::Result<Option<Self>> {
+        let (leaf, issuer) = match cert.cert.as_slice() {
+            [leaf, issuer, ..] => (parse_der_cert(leaf)?, parse_der_cert(issuer)?),
+            _ => return Ok(None),
+        };
+
+        // Obtain the responder URL from the Authority Information Access extension
+        let Some(url) = leaf.extensions().iter().find_map(|ext| {
+            if let ParsedExtension::AuthorityInfoAccess(aia) = ext.parsed_extension() {
+                aia.iter().find_map(|desc| match &desc.access_location {
+                    GeneralName::URI(uri)
+                        if desc.access_method == OID_PKIX_ACCESS_DESCRIPTOR_OCSP =>
+                    {
+                        Some(uri.to_string())
+                    }
+                    _ => None,
+                })
+            } else {
+                None
+            }
+        }) else {
+            return Ok(None);
+        };
+
+        // Build OCSPRequest (RFC 6960) for a single CertID
+        let serial = leaf.raw_serial().to_vec();
+        let cert_id = der(
+            0x30,
+            &[
+                der(0x30, &[der(0x06, OID_SHA1), vec![0x05, 0x00]].concat()),
+                der(0x04, &Sha1::digest(issuer.subject().as_raw())),
+                der(
+                    0x04,
+                    &Sha1::digest(issuer.public_key().subject_public_key.data.as_ref()),
+                ),
+                der(0x02, &serial),
+            ]
+            .concat(),
+        );
+        let request = der(0x30, &der(0x30, &der(0x30, &der(0x30, &cert_id))));
+
+        Ok(Some(OcspRequest {
+            url,
+            serial,
+            fingerprint: fingerprint(&cert.cert[0]),
+            request,
+        }))
+    }
+
+    async fn fetch(&self) -> trc::Result<SerializedOcsp> {
+        let response = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|err| EventType::Acme(AcmeEvent::OcspError).from_http_error(err))?
+            .post(&self.url)
+            .header(USER_AGENT, crate::USER_AGENT)
+            .header(CONTENT_TYPE, "application/ocsp-request")
+            .body(self.request.clone())
+            .send()
+            .await
+            .map_err(|err| EventType::Acme(AcmeEvent::OcspError).from_http_error(err))?
+            .assert_success(EventType::Acme(AcmeEvent::OcspError))
+            .await?
+            .bytes()
+            .await
+            .map_err(|err| EventType::Acme(AcmeEvent::OcspError).from_http_error(err))?;
+
+        parse_ocsp_response(&response, &self.serial)
+            .map(|(this_update, next_update)| SerializedOcsp {
+                serial: self.serial.clone(),
+                response: response.to_vec(),
+                this_update,
+                next_update: next_update.unwrap_or(this_update + OCSP_DEFAULT_VALIDITY),
+            })
+            .map_err(|reason| {
+                EventType::Acme(AcmeEvent::OcspError)
+                    .into_err()
+                    .ctx(trc::Key::Url, self.url.clone())
+                    .reason(reason)
+            })
+    }
+}
+
+fn ocsp_key(fingerprint: &str) -> String {
+    format!("ocsp:{fingerprint}")
+}
+
+// Responses are cached by the SHA-256 fingerprint of the leaf certificate
+fn fingerprint(leaf: &[u8]) -> String {
+    Sha256::digest(leaf)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn parse_der_cert(der: &[u8]) -> trc::Result<X509Certificate<'_>> {
+    parse_x509_certificate(der)
+        .map(|(_, cert)| cert)
+        .map_err(|err| {
+            EventType::Acme(AcmeEvent::OcspError)
+                .reason(err)
+                .caused_by(trc::location!())
+        })
+}
+
+// Returns the thisUpdate and nextUpdate times of a "good" SingleResponse matching the serial number
+fn parse_ocsp_response(response: &[u8], serial: &[u8]) -> Result<(u64, Option<u64>), &'static str> {
+    const INVALID: &str = "Invalid OCSP response";
+
+    // OCSPResponse
+    let (_, response, _) = read_tlv(response, 0x30).ok_or(INVALID)?;
+    let (_, status, response) = read_tlv(response, 0x0a).ok_or(INVALID)?;
+    if status != [0] {
+        return Err("OCSP responder returned an error status");
+    }
+    let (_, response_bytes, _) = read_tlv(response, 0xa0).ok_or(INVALID)?;
+    let (_, response_bytes, _) = read_tlv(response_bytes, 0x30).ok_or(INVALID)?;
+    let (_, response_type, response_bytes) = read_tlv(response_bytes, 0x06).ok_or(INVALID)?;
+    if response_type != OID_OCSP_BASIC {
+        return Err("Unsupported OCSP response type");
+    }
+    let (_, basic, _) = read_tlv(response_bytes, 0x04).ok_or(INVALID)?;
+
+    // BasicOCSPResponse -> ResponseData
+    let (_, basic, _) = read_tlv(basic, 0x30).ok_or(INVALID)?;
+    let (_, mut data, _) = read_tlv(basic, 0x30).ok_or(INVALID)?;
+    if let Some((_, _, rest)) = read_tlv(data, 0xa0) {
+        data = rest;
+    }
+    let (_, _, data) = read_tlv(data, 0xa1)
+        .or_else(|| read_tlv(data, 0xa2))
+        .ok_or(INVALID)?;
+    let (_, _, data) = read_tlv(data, 0x18).ok_or(INVALID)?;
+    let (_, mut responses, _) = read_tlv(data, 0x30).ok_or(INVALID)?;
+
+    // SingleResponse
+    while let Some((_, single, rest)) = read_tlv(responses, 0x30) {
+        responses = rest;
+
+        let (_, cert_id, single) = read_tlv(single, 0x30).ok_or(INVALID)?;
+        let (_, _, cert_id) = read_tlv(cert_id, 0x30).ok_or(INVALID)?;
+        let (_, _, cert_id) = read_tlv(cert_id, 0x04).ok_or(INVALID)?;
+        let (_, _, cert_id) = read_tlv(cert_id, 0x04).ok_or(INVALID)?;
+        let (_, cert_serial, _) = read_tlv(cert_id, 0x02).ok_or(INVALID)?;
+        if cert_serial != serial {
+            continue;
+        }
+
+        let (tag, _, single) = read_any_tlv(single).ok_or(INVALID)?;
+        match tag {
+            0x80 => {}
+            0xa1 => return Err("Certificate has been revoked"),
+            _ => return Err("Certificate status is unknown"),
+        }
+        let (_, this_update, single) = read_tlv(single, 0x18).ok_or(INVALID)?;
+        let this_update = parse_generalized_time(this_update).ok_or(INVALID)?;
+        let next_update = match read_tlv(single, 0xa0) {
+            Some((_, next_update, _)) => {
+                let (_, next_update, _) = read_tlv(next_update, 0x18).ok_or(INVALID)?;
+                Some(parse_generalized_time(next_update).ok_or(INVALID)?)
+            }
+            None => None,
+        };
+
+        return Ok((this_update, next_update));
+    }
+
+    Err("OCSP response does not include the certificate")
+}
+
+fn read_tlv(data: &[u8], expected_tag: u8) -> Option<(u8, &[u8], &[u8])> {
+    read_any_tlv(data).filter(|(tag, _, _)| *tag == expected_tag)
+}
+
+fn read_any_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&len, mut data) = data.split_first()?;
+    let len = if len & 0x80 == 0 {
+        len as usize
+    } else {
+        let num_bytes = (len & 0x7f) as usize;
+        if num_bytes == 0 || num_bytes > 4 || data.len() < num_bytes {
+            return None;
+        }
+        let len = data[..num_bytes]
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        data = &data[num_bytes..];
+        len
+    };
+
+    if data.len() >= len {
+        Some((tag, &data[..len], &data[len..]))
+    } else {
+        None
+    }
+}
+
+fn parse_generalized_time(value: &[u8]) -> Option<u64> {
+    NaiveDateTime::parse_from_str(std::str::from_utf8(value.get(..14)?).ok()?, "%Y%m%d%H%M%S")
+        .ok()
+        .map(|dt| dt.and_utc().timestamp() as u64)
+}
+
+fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut der = Vec::with_capacity(contents.len() + 6);
+    der.push(tag);
+    if contents.len() < 0x80 {
+        der.push(contents.len() as u8);
+    } else {
+        let len = (contents.len() as u32).to_be_bytes();
+        let skip = len.iter().take_while(|byte| **byte == 0).count();
+        der.push(0x80 | (len.len() - skip) as u8);
+        der.extend_from_slice(&len[skip..]);
+    }
+    der.extend_from_slice(contents);
+    der
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_response(serial: &[u8], status: Vec<u8>, next_update: Option<&str>) -> Vec<u8> {
+        let cert_id = der(
+            0x30,
+            &[
+                der(0x30, &[der(0x06, OID_SHA1), vec![0x05, 0x00]].concat()),
+                der(0x04, &[0u8; 20]),
+                der(0x04, &[0u8; 20]),
+                der(0x02, serial),
+            ]
+            .concat(),
+        );
+        let mut single = [cert_id, status, der(0x18, b"20250101000000Z")].concat();
+        if let Some(next_update) = next_update {
+            single.extend(der(0xa0, &der(0x18, next_update.as_bytes())));
+        }
+        let data = der(
+            0x30,
+            &[
+                der(0xa2, &der(0x04, &[0u8; 20])),
+                der(0x18, b"20250101000000Z"),
+                der(0x30, &der(0x30, &single)),
+            ]
+            .concat(),
+        );
+        let basic = der(0x30, &[data, der(0x30, &[]), der(0x03, &[0])].concat());
+        der(
+            0x30,
+            &[
+                der(0x0a, &[0]),
+                der(
+                    0xa0,
+                    &der(
+                        0x30,
+                        &[der(0x06, OID_OCSP_BASIC), der(0x04, &basic)].concat(),
+                    ),
+                ),
+            ]
+            .concat(),
+        )
+    }
+
+    #[test]
+    fn parse_ocsp() {
+        let serial = [0x03, 0x9a, 0x41];
+
+        assert_eq!(
+            parse_ocsp_response(
+                &build_response(&serial, vec![0x80, 0x00], Some("20250108000000Z")),
+                &serial
+            ),
+            Ok((1735689600, Some(1736294400)))
+        );
+        assert_eq!(
+            parse_ocsp_response(&build_response(&serial, vec![0x80, 0x00], None), &serial),
+            Ok((1735689600, None))
+        );
+        assert_eq!(
+            parse_ocsp_response(
+                &build_response(&serial, der(0xa1, &der(0x18, b"20241231000000Z")), None),
+                &serial
+            ),
+            Err("Certificate has been revoked")
+        );
+        assert_eq!(
+            parse_ocsp_response(&build_response(&serial, vec![0x80, 0x00], None), &[0x01]),
+            Err("OCSP response does not include the certificate")
+        );
+        assert_eq!(
+            parse_ocsp_response(&[0x30, 0x03, 0x0a, 0x01, 0x06], &serial),
+            Err("OCSP responder returned an error status")
+        );
+    }
+
+    #[test]
+    fn der_length() {
+        let contents = vec![0u8; 300];
+        let encoded = der(0x04, &contents);
+        assert_eq!(&encoded[..4], &[0x04, 0x82, 0x01, 0x2c]);
+        assert_eq!(
+            read_tlv(&encoded, 0x04),
+            Some((0x04, &contents[..], &[][..]))
+        );
+    }
+}
@@ -3,24 +3,25 @@
 use chrono::{DateTime, TimeZone, Utc};
 
 use compact_str::CompactString;
-use dns_update::DnsRecord;
-use futures::future::try_join_all;
-use rcgen::{CertificateParams, DistinguishedName, PKCS_ECDSA_P256_SHA256};
-use rustls::crypto::ring::sign::any_ecdsa_type;
-use rustls::sign::CertifiedKey;
+use futures::future::join_all;
+use rcgen::{CertificateParams, DistinguishedName, KeyPair, PKCS_ECDSA_P256_SHA256};
+use rustls::crypto::ring::sign::any_supported_type;
+use rustls::sign::{CertifiedKey, SigningKey};
 use rustls_pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use store::ahash::AHashMap;
 use store::dispatch::lookup::KeyValue;
+use store::rand::{Rng, rng};
 use trc::{AcmeEvent, EventType};
 use x509_parser::parse_x509_certificate;
 
-use crate::listener::acme::ChallengeSettings;
 use crate::listener::acme::directory::Identifier;
+use crate::listener::acme::{ChallengeSettings, http_challenge_key};
 use crate::{KV_ACME, Server};
 
 use super::AcmeProvider;
-use super::directory::{Account, AuthStatus, Directory, OrderStatus};
+use super::directory::{Account, AuthStatus, Directory, OrderStatus, SerializedCert};
 
 impl Server {
     pub(crate) async fn process_cert(
@@ -29,15 +30,18 @@ impl Server {
         pem: Vec<u8>,
         cached: bool,
     ) -> trc::Result<Duration> {
-        let (cert, validity) = parse_cert(&pem)?;
+        let (mut cert, validity) = parse_cert(&pem)?;
 
-        self.set_cert(provider, Arc::new(cert));
+        self.staple_cached_ocsp(&mut cert).await;
+        let cert = Arc::new(cert);
+        self.set_cert(provider, cert.clone());
 
-        let renew_at = (validity[1] - provider.renew_before - Utc::now())
+        let renewal_date = renewal_date(&validity, provider.renew_before);
+        provider.schedule_renewal(renewal_date.timestamp().max(0) as u64);
+        let renew_at = (renewal_date - Utc::now())
             .max(chrono::Duration::zero())
             .to_std()
             .unwrap_or_default();
-        let renewal_date = validity[1] - provider.renew_before;
 
         trc::event!(
             Acme(AcmeEvent::ProcessCert),
@@ -45,12 +49,19 @@ impl Server {
             Hostname = provider.domains.as_slice(),
             ValidFrom = trc::Value::Timestamp(validity[0].timestamp() as u64),
             ValidTo = trc::Value::Timestamp(validity[1].timestamp() as u64),
+        );
+
+        trc::event!(
+            Acme(AcmeEvent::RenewScheduled),
+            Id = provider.id.to_string(),
+            Hostname = provider.domains.as_slice(),
             Due = trc::Value::Timestamp(renewal_date.timestamp() as u64),
         );
 
         if !cached {
             self.store_cert(provider, &pem).await?;
         }
+        self.publish_tlsa(provider, &cert).await;
 
         Ok(renew_at)
     }
@@ -84,32 +95,140 @@ impl Server {
         }
     }
 
+    // Places a single order without backing off, used for renewals requested by an administrator
+    pub async fn renew_now(&self, provider: &AcmeProvider) -> trc::Result<Duration> {
+        match self.order(provider).await {
+            Ok(pem) => self.process_cert(provider, pem, false).await,
+            Err(err) => Err(err
+                .details("Failed to renew certificate")
+                .ctx_unique(trc::Key::Id, provider.id.to_string())
+                .ctx_unique(trc::Key::Hostname, provider.domains.as_slice())),
+        }
+    }
+
     async fn order(&self, provider: &AcmeProvider) -> trc::Result<Vec<u8>> {
         let directory = Directory::discover(&provider.directory_url).await?;
         let account = Account::create_with_keypair(directory, provider).await?;
 
-        let mut params = CertificateParams::new(provider.domains.clone());
+        // Domains that keep failing authorization are dropped from the order so the
+        // certificate can still be issued for the remaining ones
+        let excluded = self.acme_excluded_domains(provider).await?;
+        let mut domains = provider
+            .domains
+            .iter()
+            .filter(|domain| !excluded.contains(domain))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut failures: AHashMap<String, u32> = AHashMap::new();
+        let mut backoff = 0;
+
+        loop {
+            if domains.is_empty() {
+                return Err(EventType::Acme(AcmeEvent::OrderInvalid)
+                    .into_err()
+                    .details("All domains are excluded"));
+            }
+
+            let (failed, err) = match self.order_domains(provider, &account, &domains).await? {
+                OrderOutcome::Issued(pem) => return Ok(pem),
+                OrderOutcome::AuthFailed { domains, err } => (domains, err),
+            };
+
+            let mut exclude = Vec::new();
+            for domain in failed {
+                let attempts = failures.entry(domain.clone()).or_default();
+                *attempts += 1;
+                if *attempts >= provider.auth_attempts {
+                    exclude.push(domain);
+                }
+            }
+
+            if exclude.len() >= domains.len() {
+                return Err(err);
+            } else if !exclude.is_empty() {
+                domains.retain(|domain| !exclude.contains(domain));
+                self.acme_exclude_domains(provider, &exclude).await?;
+            } else {
+                let delay = 1u64 << backoff.min(8);
+                trc::event!(
+                    Acme(AcmeEvent::RenewBackoff),
+                    Id = provider.id.to_string(),
+                    Hostname = domains.as_slice(),
+                    Total = backoff,
+                    NextRetry = delay,
+                    CausedBy = err,
+                );
+                backoff += 1;
+                tokio::time::sleep(Duration::from_secs(delay)).await;
+            }
+        }
+    }
+
+    async fn order_domains(
+        &self,
+        provider: &AcmeProvider,
+        account: &Account,
+        domains: &[String],
+    ) -> trc::Result<OrderOutcome> {
+        let mut params = CertificateParams::new(domains.to_vec());
         params.distinguished_name = DistinguishedName::new();
         params.alg = &PKCS_ECDSA_P256_SHA256;
+
+        // Use the pre-published key, its TLSA record is already in place
+        if provider.dane.as_ref().is_some_and(|dane| dane.pre_publish) {
+            params.key_pair = self
+                .load_next_key(provider)
+                .await?
+                .and_then(|pem| KeyPair::from_pem(std::str::from_utf8(&pem).ok()?).ok());
+        }
+
         let cert = rcgen::Certificate::from_params(params).map_err(|err| {
             EventType::Acme(AcmeEvent::Error)
                 .caused_by(trc::location!())
                 .reason(err)
         })?;
 
-        let (order_url, mut order) = account.new_order(provider.domains.clone()).await?;
+        let (order_url, mut order) = account.new_order(domains.to_vec()).await?;
         loop {
             match order.status {
                 OrderStatus::Pending => {
-                    let auth_futures = order
-                        .authorizations
-                        .iter()
-                        .map(|url| self.authorize(provider, &account, url));
-                    try_join_all(auth_futures).await?;
+                    let results = if matches!(provider.challenge, ChallengeSettings::Dns01 { .. }) {
+                        // Apex and wildcard authorizations share the same TXT record name
+                        let mut results = Vec::with_capacity(order.authorizations.len());
+                        for url in &order.authorizations {
+                            results.push(self.authorize(provider, account, url).await);
+                        }
+                        results
+                    } else {
+                        join_all(
+                            order
+                                .authorizations
+                                .iter()
+                                .map(|url| self.authorize(provider, account, url)),
+                        )
+                        .await
+                    };
+
+                    // Report which domains failed so they can be excluded from the next order
+                    let mut failed = Vec::new();
+                    let mut last_err = None;
+                    for (url, result) in order.authorizations.iter().zip(results) {
+                        if let Err(err) = result {
+                            failed.push(account.auth(url).await?.domain());
+                            last_err = Some(err);
+                        }
+                    }
+                    if let Some(err) = last_err {
+                        return Ok(OrderOutcome::AuthFailed {
+                            domains: failed,
+                            err,
+                        });
+                    }
+
                     trc::event!(
                         Acme(AcmeEvent::AuthCompleted),
                         Id = provider.id.to_string(),
-                        Hostname = provider.domains.as_slice(),
+                        Hostname = domains,
                     );
                     order = account.order(&order_url).await?;
                 }
@@ -118,7 +237,7 @@ impl Server {
                         trc::event!(
                             Acme(AcmeEvent::OrderProcessing),
                             Id = provider.id.to_string(),
-                            Hostname = provider.domains.as_slice(),
+                            Hostname = domains,
                             Total = i,
                         );
 
@@ -138,7 +257,7 @@ impl Server {
                     trc::event!(
                         Acme(AcmeEvent::OrderReady),
                         Id = provider.id.to_string(),
-                        Hostname = provider.domains.as_slice(),
+                        Hostname = domains,
                     );
 
                     let csr = cert.serialize_request_der().map_err(|err| {
@@ -152,7 +271,7 @@ impl Server {
                     trc::event!(
                         Acme(AcmeEvent::OrderValid),
                         Id = provider.id.to_string(),
-                        Hostname = provider.domains.as_slice(),
+                        Hostname = domains,
                     );
 
                     let pem = [
@@ -161,7 +280,7 @@ impl Server {
                         &account.certificate(certificate).await?,
                     ]
                     .concat();
-                    return Ok(pem.into_bytes());
+                    return Ok(OrderOutcome::Issued(pem.into_bytes()));
                 }
                 OrderStatus::Invalid => {
                     return Err(EventType::Acme(AcmeEvent::OrderInvalid).into_err());
@@ -175,6 +294,39 @@ impl Server {
         provider: &AcmeProvider,
         account: &Account,
         url: &String,
+    ) -> trc::Result<()> {
+        let mut dns_record = None;
+        let result = self
+            .authorize_challenge(provider, account, url, &mut dns_record)
+            .await;
+
+        // Remove the TXT record whether or not the authorization succeeded
+        if let (Some(record), ChallengeSettings::Dns01 { updater, .. }) =
+            (dns_record, &provider.challenge)
+        {
+            if let Err(err) = updater
+                .delete(&record.name, &record.value, &record.origin)
+                .await
+            {
+                trc::event!(
+                    Acme(AcmeEvent::DnsRecordDeletionFailed),
+                    Hostname = record.name,
+                    Reason = err,
+                    Details = record.origin,
+                    Id = provider.id.to_string(),
+                );
+            }
+        }
+
+        result
+    }
+
+    async fn authorize_challenge(
+        &self,
+        provider: &AcmeProvider,
+        account: &Account,
+        url: &String,
+        dns_record: &mut Option<DnsChallengeRecord>,
     ) -> trc::Result<()> {
         let auth = account.auth(url).await?;
         let (domain, challenge_url) = match auth.status {
@@ -217,7 +369,10 @@ impl Server {
                                 KeyValue::with_prefix(
                                     KV_ACME,
                                     &domain,
-                                    account.tls_alpn_key(challenge, domain.clone())?,
+                                    self.core.acme.storage_keys.encrypt(
+                                        &domain,
+                                        account.tls_alpn_key(challenge, domain.clone())?,
+                                    )?,
                                 )
                                 .expires(3600),
                             )
@@ -228,7 +383,7 @@ impl Server {
                             .key_set(
                                 KeyValue::with_prefix(
                                     KV_ACME,
-                                    &challenge.token,
+                                    http_challenge_key(&challenge.token),
                                     account.http_proof(challenge)?,
                                 )
                                 .expires(3600),
@@ -252,29 +407,19 @@ impl Server {
                             .to_string();
 
                         // First try deleting the record
-                        if let Err(err) = updater.delete(&name, &origin).await {
+                        if let Err(err) = updater.delete(&name, &dns_proof, &origin).await {
                             // Errors are expected if the record does not exist
                             trc::event!(
                                 Acme(AcmeEvent::DnsRecordDeletionFailed),
                                 Hostname = name.to_string(),
-                                Reason = err.to_string(),
+                                Reason = err,
                                 Details = origin.to_string(),
                                 Id = provider.id.to_string(),
                             );
                         }
 
                         // Create the record
-                        if let Err(err) = updater
-                            .create(
-                                &name,
-                                DnsRecord::TXT {
-                                    content: dns_proof.clone(),
-                                },
-                                *ttl,
-                                &origin,
-                            )
-                            .await
-                        {
+                        if let Err(err) = updater.create(&name, &dns_proof, *ttl, &origin).await {
                             return Err(EventType::Acme(AcmeEvent::DnsRecordCreationFailed)
                                 .ctx(trc::Key::Id, provider.id.to_string())
                                 .ctx(trc::Key::Hostname, name)
@@ -288,6 +433,11 @@ impl Server {
                             Details = origin.to_string(),
                             Id = provider.id.to_string(),
                         );
+                        *dns_record = Some(DnsChallengeRecord {
+                            name: name.clone(),
+                            origin: origin.clone(),
+                            value: dns_proof.clone(),
+                        });
 
                         // Wait for changes to propagate
                         let wait_until = Instant::now() + *propagation_timeout;
@@ -392,31 +542,38 @@ impl Server {
     }
 }
 
-fn parse_cert(pem: &[u8]) -> trc::Result<(CertifiedKey, [DateTime<Utc>; 2])> {
-    let mut pems = pem::parse_many(pem).map_err(|err| {
+// Renew after two thirds of the validity window or `renew_before` ahead of
+// expiry, whichever comes first, minus some jitter so that certificates issued
+// together are not all renewed at the same time.
+fn renewal_date(validity: &[DateTime<Utc>; 2], renew_before: chrono::Duration) -> DateTime<Utc> {
+    let lifetime = (validity[1] - validity[0]).max(chrono::Duration::zero());
+    let due = std::cmp::min(validity[0] + lifetime * 2 / 3, validity[1] - renew_before);
+    let max_jitter = std::cmp::min(lifetime / 50, chrono::Duration::hours(12)).num_seconds();
+
+    if max_jitter > 0 {
+        due - chrono::Duration::seconds(rng().random_range(0..max_jitter))
+    } else {
+        due
+    }
+}
+
+pub(super) fn parse_private_key(der: &[u8]) -> trc::Result<Arc<dyn SigningKey>> {
+    // RSA keys are still required by some CAs and corporate policies
+    any_supported_type(&PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(der))).map_err(|err| {
         EventType::Acme(AcmeEvent::Error)
             .reason(err)
+            .details("Unsupported private key algorithm, expected RSA, ECDSA or EdDSA")
             .caused_by(trc::location!())
-    })?;
-    if pems.len() < 2 {
-        return Err(EventType::Acme(AcmeEvent::Error)
-            .caused_by(trc::location!())
-            .ctx(trc::Key::Size, pems.len())
-            .details("Too few PEMs"));
-    }
-    let pk = match any_ecdsa_type(&PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(
-        pems.remove(0).contents(),
-    ))) {
-        Ok(pk) => pk,
-        Err(err) => {
-            return Err(EventType::Acme(AcmeEvent::Error)
-                .reason(err)
-                .caused_by(trc::location!()));
-        }
-    };
-    let cert_chain: Vec<CertificateDer> = pems
+    })
+}
+
+pub(super) fn parse_cert(pem: &[u8]) -> trc::Result<(CertifiedKey, [DateTime<Utc>; 2])> {
+    let cert = SerializedCert::from_pem(pem)?;
+    let pk = parse_private_key(&cert.private_key)?;
+    let cert_chain: Vec<CertificateDer> = cert
+        .certificates
         .into_iter()
-        .map(|p| CertificateDer::from(p.into_contents()))
+        .map(CertificateDer::from)
         .collect();
     let validity = match parse_x509_certificate(&cert_chain[0]) {
         Ok((_, cert)) => {
@@ -436,3 +593,17 @@ fn parse_cert(pem: &[u8]) -> trc::Result<(CertifiedKey, [DateTime<Utc>; 2])> {
     let cert = CertifiedKey::new(cert_chain, pk);
     Ok((cert, validity))
 }
+
+enum OrderOutcome {
+    Issued(Vec<u8>),
+    AuthFailed {
+        domains: Vec<String>,
+        err: trc::Error,
+    },
+}
+
+struct DnsChallengeRecord {
+    name: String,
+    origin: String,
+    value: String,
+}
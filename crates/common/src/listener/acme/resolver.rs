@@ -6,18 +6,20 @@
 
 use super::{
     AcmeProvider, StaticResolver,
-    directory::{ACME_TLS_ALPN_NAME, SerializedCert},
+    crypto::StoredValue,
+    directory::{ACME_TLS_ALPN_NAME, SerializedCert, SerializedCertV1},
+    order::parse_private_key,
 };
-use crate::{KV_ACME, Server};
+use crate::{KV_ACME, Server, listener::tls::insert_certificate};
 use rustls::{
     ServerConfig,
-    crypto::ring::sign::any_ecdsa_type,
     server::{ClientHello, ResolvesServerCert},
     sign::CertifiedKey,
 };
-use rustls_pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls_pki_types::CertificateDer;
 use std::sync::Arc;
 use store::{
+    Deserialize,
     dispatch::lookup::KeyValue,
     write::{AlignedBytes, Archive},
 };
@@ -25,59 +27,52 @@ use trc::AcmeEvent;
 
 impl Server {
     pub(crate) fn set_cert(&self, provider: &AcmeProvider, cert: Arc<CertifiedKey>) {
-        // Add certificates
+        // Add certificates, wildcards are stored as ".domain", only the listeners
+        // referencing the provider are updated when it is bound to any
         let mut certificates = self.inner.data.tls_certificates.load().as_ref().clone();
         for domain in provider.domains.iter() {
-            certificates.insert(
-                domain
-                    .strip_prefix("*.")
-                    .unwrap_or(domain.as_str())
-                    .to_string(),
+            insert_certificate(
+                &mut certificates,
+                &provider.listeners,
+                domain.strip_prefix('*').unwrap_or(domain.as_str()),
                 cert.clone(),
             );
         }
 
         // Add default certificate
         if provider.default {
-            certificates.insert("*".to_string(), cert);
+            insert_certificate(&mut certificates, &provider.listeners, "*", cert);
         }
 
         self.inner.data.tls_certificates.store(certificates.into());
     }
 
     pub(crate) async fn build_acme_certificate(&self, domain: &str) -> Option<Arc<CertifiedKey>> {
-        match self
+        // Certificates are encrypted when storage keys are configured
+        let cert = self
             .in_memory_store()
-            .key_get::<Archive<AlignedBytes>>(KeyValue::<()>::build_key(KV_ACME, domain))
+            .key_get::<StoredValue>(KeyValue::<()>::build_key(KV_ACME, domain))
             .await
-        {
-            Ok(Some(cert_)) => match cert_.unarchive::<SerializedCert>() {
-                Ok(cert) => {
-                    match any_ecdsa_type(&PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(
-                        cert.private_key.as_ref(),
-                    ))) {
-                        Ok(key) => Some(Arc::new(CertifiedKey::new(
-                            vec![CertificateDer::from(cert.certificate.to_vec())],
-                            key,
-                        ))),
-                        Err(err) => {
-                            trc::event!(
-                                Acme(AcmeEvent::Error),
-                                Domain = domain.to_string(),
-                                Reason = err.to_string(),
-                                Details = "Failed to parse private key"
-                            );
-                            None
-                        }
-                    }
-                }
+            .and_then(|cert| {
+                cert.map(|cert| {
+                    self.core
+                        .acme
+                        .storage_keys
+                        .decrypt(domain, cert.0)
+                        .and_then(<Archive<AlignedBytes> as Deserialize>::deserialize_owned)
+                })
+                .transpose()
+            });
 
+        match cert {
+            Ok(Some(cert)) => match parse_serialized_cert(&cert) {
+                Ok(cert) => Some(Arc::new(cert)),
                 Err(err) => {
                     trc::event!(
                         Acme(AcmeEvent::Error),
                         Domain = domain.to_string(),
                         CausedBy = err,
-                        Details = "Failed to unarchive certificate"
+                        Details = "Failed to load certificate"
                     );
                     None
                 }
@@ -98,6 +93,33 @@ impl Server {
     }
 }
 
+fn parse_serialized_cert(cert: &Archive<AlignedBytes>) -> trc::Result<CertifiedKey> {
+    // Values written by earlier versions only contain the leaf certificate
+    let (chain, private_key) = match cert.unarchive::<SerializedCert>() {
+        Ok(cert) => (
+            cert.certificates
+                .iter()
+                .map(|cert| CertificateDer::from(cert.to_vec()))
+                .collect::<Vec<_>>(),
+            cert.private_key.as_ref(),
+        ),
+        Err(_) => {
+            let cert = cert.unarchive::<SerializedCertV1>()?;
+            (
+                vec![CertificateDer::from(cert.certificate.to_vec())],
+                cert.private_key.as_ref(),
+            )
+        }
+    };
+    if chain.is_empty() {
+        return Err(trc::EventType::Acme(AcmeEvent::Error)
+            .caused_by(trc::location!())
+            .details("Empty certificate chain"));
+    }
+
+    parse_private_key(private_key).map(|key| CertifiedKey::new(chain, key))
+}
+
 impl ResolvesServerCert for StaticResolver {
     fn resolve(&self, _: ClientHello) -> Option<Arc<CertifiedKey>> {
         self.key.clone()
@@ -121,3 +143,110 @@ impl IsTlsAlpnChallenge for ClientHello<'_> {
         self.alpn().into_iter().flatten().eq([ACME_TLS_ALPN_NAME])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rustls_pemfile::{certs, private_key};
+    use rustls_pki_types::PrivateKeyDer;
+    use store::{
+        Deserialize,
+        write::{AlignedBytes, Archive, Archiver},
+    };
+
+    use super::{SerializedCert, SerializedCertV1, parse_serialized_cert};
+
+    #[test]
+    fn rsa_certificate() {
+        let certificate = certs(
+            &mut include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../../tests/resources/tls_cert.pem"
+            ))
+            .as_slice(),
+        )
+        .next()
+        .unwrap()
+        .unwrap();
+        let private_key = private_key(
+            &mut include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../../tests/resources/tls_privatekey.pem"
+            ))
+            .as_slice(),
+        )
+        .unwrap()
+        .unwrap();
+        assert!(matches!(private_key, PrivateKeyDer::Pkcs8(_)));
+
+        // Round-trip the RSA key through the stored representation
+        let bytes = Archiver::new(SerializedCert {
+            certificates: vec![certificate.to_vec()],
+            private_key: private_key.secret_der().to_vec(),
+        })
+        .untrusted()
+        .serialize()
+        .unwrap();
+        let cert = parse_serialized_cert(
+            &<Archive<AlignedBytes> as Deserialize>::deserialize(&bytes).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(cert.cert, vec![certificate.clone()]);
+        assert_eq!(cert.key.algorithm(), rustls::SignatureAlgorithm::RSA);
+
+        // Unsupported keys are reported
+        let bytes = Archiver::new(SerializedCert {
+            certificates: vec![certificate.to_vec()],
+            private_key: vec![0x30, 0x03, 0x02, 0x01, 0x00],
+        })
+        .untrusted()
+        .serialize()
+        .unwrap();
+        assert!(
+            parse_serialized_cert(
+                &<Archive<AlignedBytes> as Deserialize>::deserialize(&bytes).unwrap()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn full_chain() {
+        // Private key, leaf and intermediate as returned by the CA on finalization
+        let cert = SerializedCert::from_pem(include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../tests/resources/acme/fullchain.pem"
+        )))
+        .unwrap();
+        assert_eq!(cert.certificates.len(), 2);
+
+        let bytes = Archiver::new(cert.clone()).untrusted().serialize().unwrap();
+        let key = parse_serialized_cert(
+            &<Archive<AlignedBytes> as Deserialize>::deserialize(&bytes).unwrap(),
+        )
+        .unwrap();
+        assert!(key.cert.len() >= 2);
+        assert_eq!(
+            key.cert
+                .iter()
+                .map(|cert| cert.to_vec())
+                .collect::<Vec<_>>(),
+            cert.certificates
+        );
+        assert_eq!(key.key.algorithm(), rustls::SignatureAlgorithm::ECDSA);
+
+        // Values stored by earlier versions only contain the leaf certificate
+        let bytes = Archiver::new(SerializedCertV1 {
+            certificate: cert.certificates[0].clone(),
+            private_key: cert.private_key.clone(),
+        })
+        .untrusted()
+        .serialize()
+        .unwrap();
+        let key = parse_serialized_cert(
+            &<Archive<AlignedBytes> as Deserialize>::deserialize(&bytes).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(key.cert.len(), 1);
+        assert_eq!(key.cert[0].to_vec(), cert.certificates[0]);
+    }
+}
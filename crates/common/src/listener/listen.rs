@@ -13,7 +13,7 @@ use std::{
 use proxy_header::io::ProxiedStream;
 use rustls::crypto::ring::cipher_suite::TLS13_AES_128_GCM_SHA256;
 use tokio::{net::TcpStream, sync::watch};
-use tokio_rustls::server::TlsStream;
+use tokio_rustls::{LazyConfigAcceptor, server::TlsStream};
 use trc::{EventType, HttpEvent, ImapEvent, ManageSieveEvent, Pop3Event, SmtpEvent};
 use utils::{UnwrapFailure, config::Config};
 
@@ -26,6 +26,7 @@ use crate::{
 use super::{
     ServerInstance, SessionData, SessionManager, SessionStream, TcpAcceptor,
     limiter::{ConcurrencyLimiter, LimiterResult},
+    tls::ClientFingerprint,
 };
 
 impl Listener {
@@ -45,6 +46,7 @@ impl Listener {
             acceptor,
             shutdown_rx,
             span_id_gen: self.span_id_gen,
+            banner: self.banner,
         });
         let is_tls = matches!(instance.acceptor, TcpAcceptor::Tls { implicit, .. } if implicit);
         let is_https = is_tls && self.protocol == ServerProtocol::Http;
@@ -246,6 +248,7 @@ impl BuildSession for Arc<ServerInstance> {
                 remote_port,
                 protocol: self.protocol,
                 instance: self.clone(),
+                tls_fingerprint: None,
             }
             .into()
         } else {
@@ -363,42 +366,86 @@ impl ServerInstance {
         stream: T,
         session_id: u64,
     ) -> Result<TlsStream<T>, ()> {
+        self.tls_accept_with_fingerprint(stream, session_id)
+            .await
+            .map(|(stream, _)| stream)
+    }
+
+    pub async fn tls_accept_with_fingerprint<T: SessionStream>(
+        &self,
+        stream: T,
+        session_id: u64,
+    ) -> Result<(TlsStream<T>, Option<String>), ()> {
         match &self.acceptor {
-            TcpAcceptor::Tls { acceptor, .. } => match acceptor.accept(stream).await {
-                Ok(stream) => {
-                    trc::event!(
-                        Tls(trc::TlsEvent::Handshake),
-                        ListenerId = self.id.clone(),
-                        SpanId = session_id,
-                        Version = format!(
-                            "{:?}",
-                            stream
-                                .get_ref()
-                                .1
-                                .protocol_version()
-                                .unwrap_or(rustls::ProtocolVersion::TLSv1_3)
-                        ),
-                        Details = format!(
-                            "{:?}",
-                            stream
-                                .get_ref()
-                                .1
-                                .negotiated_cipher_suite()
-                                .unwrap_or(TLS13_AES_128_GCM_SHA256)
-                        )
-                    );
-                    Ok(stream)
-                }
-                Err(err) => {
-                    trc::event!(
-                        Tls(trc::TlsEvent::HandshakeError),
-                        ListenerId = self.id.clone(),
-                        SpanId = session_id,
-                        Reason = err.to_string(),
-                    );
-                    Err(())
+            TcpAcceptor::Tls {
+                acceptor,
+                config,
+                fingerprint,
+                ..
+            } => {
+                let (result, fingerprint) = if *fingerprint {
+                    match LazyConfigAcceptor::new(Default::default(), stream).await {
+                        Ok(start_handshake) => {
+                            let fingerprint = start_handshake.client_hello().fingerprint();
+                            (
+                                start_handshake.into_stream(config.clone()).await,
+                                Some(fingerprint),
+                            )
+                        }
+                        Err(err) => (Err(err), None),
+                    }
+                } else {
+                    (acceptor.accept(stream).await, None)
+                };
+
+                match result {
+                    Ok(stream) => {
+                        self.acceptor.track_handshake(&self.id, stream.get_ref().1);
+
+                        trc::event!(
+                            Tls(trc::TlsEvent::Handshake),
+                            ListenerId = self.id.clone(),
+                            SpanId = session_id,
+                            Version = format!(
+                                "{:?}",
+                                stream
+                                    .get_ref()
+                                    .1
+                                    .protocol_version()
+                                    .unwrap_or(rustls::ProtocolVersion::TLSv1_3)
+                            ),
+                            Details = format!(
+                                "{:?}",
+                                stream
+                                    .get_ref()
+                                    .1
+                                    .negotiated_cipher_suite()
+                                    .unwrap_or(TLS13_AES_128_GCM_SHA256)
+                            )
+                        );
+
+                        if let Some(fingerprint) = &fingerprint {
+                            trc::event!(
+                                Tls(trc::TlsEvent::ClientFingerprint),
+                                ListenerId = self.id.clone(),
+                                SpanId = session_id,
+                                Id = fingerprint.clone(),
+                            );
+                        }
+
+                        Ok((stream, fingerprint))
+                    }
+                    Err(err) => {
+                        trc::event!(
+                            Tls(trc::TlsEvent::HandshakeError),
+                            ListenerId = self.id.clone(),
+                            SpanId = session_id,
+                            Reason = err.to_string(),
+                        );
+                        Err(())
+                    }
                 }
-            },
+            }
             TcpAcceptor::Plain => {
                 trc::event!(
                     Tls(trc::TlsEvent::NotConfigured),
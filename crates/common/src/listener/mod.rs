@@ -19,11 +19,14 @@ use utils::{config::ipmask::IpAddrMask, snowflake::SnowflakeIdGenerator};
 
 use crate::{
     Server,
-    config::server::ServerProtocol,
+    config::server::{ListenerBanner, ServerProtocol},
     expr::{functions::ResolveVariable, *},
 };
 
-use self::limiter::{ConcurrencyLimiter, InFlight};
+use self::{
+    limiter::{ConcurrencyLimiter, InFlight},
+    tls::CertificateResolver,
+};
 
 pub mod acme;
 pub mod asn;
@@ -31,6 +34,7 @@ pub mod blocked;
 pub mod limiter;
 pub mod listen;
 pub mod stream;
+pub mod tickets;
 pub mod tls;
 
 pub struct ServerInstance {
@@ -41,6 +45,7 @@ pub struct ServerInstance {
     pub proxy_networks: Vec<IpAddrMask>,
     pub shutdown_rx: watch::Receiver<bool>,
     pub span_id_gen: Arc<SnowflakeIdGenerator>,
+    pub banner: ListenerBanner,
 }
 
 #[derive(Default)]
@@ -49,6 +54,8 @@ pub enum TcpAcceptor {
         config: Arc<ServerConfig>,
         acceptor: TlsAcceptor,
         implicit: bool,
+        fingerprint: bool,
+        resolver: Option<Arc<CertificateResolver>>,
     },
     #[default]
     Plain,
@@ -59,7 +66,7 @@ pub enum TcpAcceptorResult<IO>
 where
     IO: AsyncRead + AsyncWrite + Unpin,
 {
-    Tls(Accept<IO>),
+    Tls(Accept<IO>, Option<String>),
     Plain(IO),
     Close,
 }
@@ -74,11 +81,18 @@ pub struct SessionData<T: SessionStream> {
     pub session_id: u64,
     pub in_flight: InFlight,
     pub instance: Arc<ServerInstance>,
+    pub tls_fingerprint: Option<String>,
 }
 
 pub trait SessionStream: AsyncRead + AsyncWrite + Unpin + 'static + Sync + Send {
     fn is_tls(&self) -> bool;
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>);
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        None
+    }
+    fn peer_certificate(&self) -> Option<&[u8]> {
+        None
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -111,8 +125,13 @@ pub trait SessionManager: Sync + Send + 'static + Clone {
                     .accept(session.stream, acme_core, &session.instance)
                     .await
                 {
-                    TcpAcceptorResult::Tls(accept) => match accept.await {
+                    TcpAcceptorResult::Tls(accept, tls_fingerprint) => match accept.await {
                         Ok(stream) => {
+                            session
+                                .instance
+                                .acceptor
+                                .track_handshake(&session.instance.id, stream.get_ref().1);
+
                             // Generate sessionId
                             session.session_id = session.instance.span_id_gen.generate();
                             session_id = session.session_id;
@@ -130,6 +149,16 @@ pub trait SessionManager: Sync + Send + 'static + Clone {
                             )
                             .send_with_metrics();
 
+                            if let Some(tls_fingerprint) = &tls_fingerprint {
+                                trc::event!(
+                                    Tls(trc::TlsEvent::ClientFingerprint),
+                                    ListenerId = session.instance.id.clone(),
+                                    SpanId = session.session_id,
+                                    RemoteIp = session.remote_ip,
+                                    Id = tls_fingerprint.clone(),
+                                );
+                            }
+
                             manager
                                 .handle(SessionData {
                                     stream,
@@ -141,6 +170,7 @@ pub trait SessionManager: Sync + Send + 'static + Clone {
                                     session_id: session.session_id,
                                     in_flight: session.in_flight,
                                     instance: session.instance,
+                                    tls_fingerprint,
                                 })
                                 .await;
                         }
@@ -231,6 +261,7 @@ impl<T: SessionStream> ResolveVariable for SessionData<T> {
             V_LISTENER => self.instance.id.as_str().into(),
             V_PROTOCOL => self.protocol.as_str().into(),
             V_TLS => self.stream.is_tls().into(),
+            V_TLS_FINGERPRINT => self.tls_fingerprint.as_deref().unwrap_or_default().into(),
             _ => crate::expr::Variable::default(),
         }
     }
@@ -244,11 +275,15 @@ impl Debug for TcpAcceptor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Tls {
-                config, implicit, ..
+                config,
+                implicit,
+                fingerprint,
+                ..
             } => f
                 .debug_struct("Tls")
                 .field("config", config)
                 .field("implicit", implicit)
+                .field("fingerprint", fingerprint)
                 .finish(),
             Self::Plain => write!(f, "Plain"),
         }
@@ -62,6 +62,18 @@ impl<T: SessionStream> SessionStream for TlsStream<T> {
             .into(),
         )
     }
+
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.get_ref().1.alpn_protocol()
+    }
+
+    fn peer_certificate(&self) -> Option<&[u8]> {
+        self.get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .map(|cert| cert.as_ref())
+    }
 }
 
 impl SessionStream for ProxiedStream<TcpStream> {
@@ -0,0 +1,433 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    fmt::{self, Formatter},
+    sync::Arc,
+    time::Duration,
+};
+
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+use rustls::server::ProducesTickets;
+use store::{
+    Serialize,
+    dispatch::lookup::KeyValue,
+    write::{AlignedBytes, Archive, Archiver, now},
+};
+use trc::{AddContext, TlsEvent};
+use utils::config::Config;
+
+use crate::{Inner, KV_LOCK_HOUSEKEEPER, KV_TLS_TICKET_KEYS, Server};
+
+const TICKET_KEYS_ID: &[u8] = b"tls-tickets";
+const KEY_NAME_LEN: usize = 16;
+const TICKET_KEYS_MIN_REFRESH: u64 = 60;
+
+// TLS 1.3 does not allow ticket lifetimes over 7 days
+const MAX_TICKET_LIFETIME: u64 = 7 * 86400;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TicketKeySettings {
+    pub rotate: Duration,
+    pub grace_period: Duration,
+}
+
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone, PartialEq, Eq)]
+pub struct TicketKey {
+    pub name: [u8; KEY_NAME_LEN],
+    pub secret: [u8; 32],
+    pub created_at: u64,
+    // Zero while the key issues new tickets, retired keys only decrypt them
+    pub expires_at: u64,
+}
+
+// Ticket keys are rotated independently of certificates and shared by all
+// nodes, so sessions can be resumed after a renewal or on another node
+#[derive(
+    rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug, Clone, Default, PartialEq, Eq,
+)]
+pub struct TicketKeys {
+    pub keys: Vec<TicketKey>,
+}
+
+pub struct SessionTicketer {
+    inner: Arc<Inner>,
+}
+
+impl TicketKeySettings {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = TicketKeySettings::default();
+        TicketKeySettings {
+            rotate: config
+                .property_or_default::<Duration>("server.tls.session-tickets.rotate", "12h")
+                .filter(|rotate| rotate.as_secs() >= TICKET_KEYS_MIN_REFRESH)
+                .unwrap_or(default.rotate),
+            grace_period: config
+                .property_or_default("server.tls.session-tickets.grace-period", "12h")
+                .unwrap_or(default.grace_period),
+        }
+    }
+
+    // Tickets are encrypted with the current key, which is retired at the next
+    // rotation and kept for the grace period
+    pub fn lifetime(&self) -> u32 {
+        self.grace_period.as_secs().min(MAX_TICKET_LIFETIME) as u32
+    }
+}
+
+impl Default for TicketKeySettings {
+    fn default() -> Self {
+        Self {
+            rotate: Duration::from_secs(12 * 3600),
+            grace_period: Duration::from_secs(12 * 3600),
+        }
+    }
+}
+
+impl TicketKey {
+    pub fn generate(now: u64) -> trc::Result<Self> {
+        let mut key = TicketKey {
+            name: [0u8; KEY_NAME_LEN],
+            secret: [0u8; 32],
+            created_at: now,
+            expires_at: 0,
+        };
+        let rng = SystemRandom::new();
+        if rng.fill(&mut key.name).is_err() || rng.fill(&mut key.secret).is_err() {
+            return Err(trc::StoreEvent::CryptoError
+                .into_err()
+                .caused_by(trc::location!())
+                .details("Failed to generate TLS ticket key"));
+        }
+        Ok(key)
+    }
+
+    fn is_valid(&self, now: u64) -> bool {
+        self.expires_at == 0 || self.expires_at > now
+    }
+
+    fn aead_key(&self) -> Option<LessSafeKey> {
+        UnboundKey::new(&AES_256_GCM, &self.secret)
+            .map(LessSafeKey::new)
+            .ok()
+    }
+}
+
+impl TicketKeys {
+    pub fn generate(now: u64) -> trc::Result<Self> {
+        TicketKey::generate(now).map(|key| TicketKeys { keys: vec![key] })
+    }
+
+    pub fn current(&self) -> Option<&TicketKey> {
+        self.keys.first().filter(|key| key.expires_at == 0)
+    }
+
+    pub fn is_due(&self, settings: &TicketKeySettings, now: u64) -> bool {
+        self.current()
+            .is_none_or(|key| key.created_at + settings.rotate.as_secs() <= now)
+    }
+
+    pub fn next_rotation(&self, settings: &TicketKeySettings, now: u64) -> Duration {
+        Duration::from_secs(
+            self.current()
+                .map_or(0, |key| {
+                    (key.created_at + settings.rotate.as_secs()).saturating_sub(now)
+                })
+                .max(TICKET_KEYS_MIN_REFRESH),
+        )
+    }
+
+    // Replaces the key issuing tickets, the previous one keeps decrypting
+    // them until the grace period ends. The keys are left unchanged when a new
+    // key cannot be generated.
+    pub fn rotate(&mut self, settings: &TicketKeySettings, now: u64) -> trc::Result<()> {
+        let key = TicketKey::generate(now)?;
+        self.retire(settings, now);
+        self.keys.insert(0, key);
+        self.purge(now);
+        Ok(())
+    }
+
+    // Adds the keys of another set that are not known yet, any of them that
+    // was issuing tickets is retired
+    pub fn merge(&mut self, other: &TicketKeys, settings: &TicketKeySettings, now: u64) {
+        let expires_at = now + settings.grace_period.as_secs();
+        for key in &other.keys {
+            if !self.keys.iter().any(|known| known.name == key.name) {
+                let mut key = key.clone();
+                if key.expires_at == 0 {
+                    key.expires_at = expires_at;
+                }
+                self.keys.push(key);
+            }
+        }
+        self.purge(now);
+    }
+
+    pub fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        let key = self.current()?;
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).ok()?;
+        let mut contents = plain.to_vec();
+        key.aead_key()?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&key.name),
+                &mut contents,
+            )
+            .ok()?;
+
+        let mut ticket = Vec::with_capacity(KEY_NAME_LEN + NONCE_LEN + contents.len());
+        ticket.extend_from_slice(&key.name);
+        ticket.extend_from_slice(&nonce);
+        ticket.extend_from_slice(&contents);
+        Some(ticket)
+    }
+
+    pub fn decrypt(&self, ticket: &[u8], now: u64) -> Option<Vec<u8>> {
+        let (name, ticket) = ticket.split_at_checked(KEY_NAME_LEN)?;
+        let (nonce, contents) = ticket.split_at_checked(NONCE_LEN)?;
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key.name == name && key.is_valid(now))?;
+        let mut contents = contents.to_vec();
+        let plain_len = key
+            .aead_key()?
+            .open_in_place(
+                Nonce::try_assume_unique_for_key(nonce).ok()?,
+                Aad::from(&key.name),
+                &mut contents,
+            )
+            .ok()?
+            .len();
+        contents.truncate(plain_len);
+        Some(contents)
+    }
+
+    // The previous key keeps issuing tickets until the next refresh when
+    // rotation fails
+    fn rotate_or_keep(&mut self, settings: &TicketKeySettings, now: u64) {
+        match self.rotate(settings, now) {
+            Ok(_) => {
+                trc::event!(Tls(TlsEvent::TicketKeysRotated), Total = self.keys.len());
+            }
+            Err(err) => {
+                trc::error!(err.details("Failed to rotate TLS ticket keys."));
+            }
+        }
+    }
+
+    fn retire(&mut self, settings: &TicketKeySettings, now: u64) {
+        let expires_at = now + settings.grace_period.as_secs();
+        for key in &mut self.keys {
+            if key.expires_at == 0 {
+                key.expires_at = expires_at;
+            }
+        }
+    }
+
+    fn purge(&mut self, now: u64) {
+        self.keys.retain(|key| key.is_valid(now));
+    }
+}
+
+impl SessionTicketer {
+    pub fn new(inner: Arc<Inner>) -> Self {
+        Self { inner }
+    }
+}
+
+impl ProducesTickets for SessionTicketer {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.inner.shared_core.load().network.tls_tickets.lifetime()
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        self.inner.data.tls_ticket_keys.load().encrypt(plain)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        self.inner
+            .data
+            .tls_ticket_keys
+            .load()
+            .decrypt(cipher, now())
+    }
+}
+
+impl std::fmt::Debug for SessionTicketer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionTicketer").finish()
+    }
+}
+
+impl Server {
+    // Installs the ticket keys shared by the cluster, rotating them when due,
+    // and returns when they have to be refreshed again
+    pub async fn refresh_tls_ticket_keys(&self) -> Duration {
+        let settings = &self.core.network.tls_tickets;
+        let now = now();
+        let mut keys = self.inner.data.tls_ticket_keys.load().as_ref().clone();
+        match self.sync_tls_ticket_keys(&keys, now).await {
+            Ok(Some(shared)) => {
+                let local = std::mem::replace(&mut keys, shared);
+                keys.merge(&local, settings, now);
+            }
+            Ok(None) => (),
+            Err(err) => {
+                // Keys are rotated locally until the in-memory store is reachable
+                trc::error!(err.details("Failed to synchronize TLS ticket keys."));
+                if keys.is_due(settings, now) {
+                    keys.rotate_or_keep(settings, now);
+                }
+            }
+        }
+
+        let refresh_in = keys.next_rotation(settings, now);
+        self.inner.data.tls_ticket_keys.store(Arc::new(keys));
+        refresh_in
+    }
+
+    async fn sync_tls_ticket_keys(
+        &self,
+        local: &TicketKeys,
+        now: u64,
+    ) -> trc::Result<Option<TicketKeys>> {
+        let settings = &self.core.network.tls_tickets;
+        let shared = self.load_tls_ticket_keys().await?;
+        if shared
+            .as_ref()
+            .is_some_and(|keys| !keys.is_due(settings, now))
+        {
+            return Ok(shared);
+        }
+
+        // Only one node rotates the keys, the others pick them up on their next refresh
+        if !self
+            .in_memory_store()
+            .try_lock(KV_LOCK_HOUSEKEEPER, TICKET_KEYS_ID, TICKET_KEYS_MIN_REFRESH)
+            .await?
+        {
+            return Ok(shared);
+        }
+
+        // The local keys are published when none are stored yet
+        let mut keys = shared.unwrap_or_else(|| local.clone());
+        if keys.is_due(settings, now) {
+            keys.rotate_or_keep(settings, now);
+        }
+        let result = self.store_tls_ticket_keys(&keys).await;
+
+        if let Err(err) = self
+            .in_memory_store()
+            .remove_lock(KV_LOCK_HOUSEKEEPER, TICKET_KEYS_ID)
+            .await
+        {
+            trc::error!(err.details("Failed to delete TLS ticket keys lock."));
+        }
+
+        result.map(|_| Some(keys))
+    }
+
+    async fn load_tls_ticket_keys(&self) -> trc::Result<Option<TicketKeys>> {
+        self.in_memory_store()
+            .key_get::<Archive<AlignedBytes>>(KeyValue::<()>::build_key(
+                KV_TLS_TICKET_KEYS,
+                TICKET_KEYS_ID,
+            ))
+            .await
+            .and_then(|keys| {
+                keys.map(|keys| keys.deserialize::<TicketKeys>())
+                    .transpose()
+            })
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .details("Failed to load TLS ticket keys")
+            })
+    }
+
+    async fn store_tls_ticket_keys(&self, keys: &TicketKeys) -> trc::Result<()> {
+        let settings = &self.core.network.tls_tickets;
+        self.in_memory_store()
+            .key_set(
+                KeyValue::with_prefix(
+                    KV_TLS_TICKET_KEYS,
+                    TICKET_KEYS_ID,
+                    Archiver::new(keys.clone()).untrusted().serialize()?,
+                )
+                .expires(settings.rotate.as_secs() + settings.grace_period.as_secs()),
+            )
+            .await
+            .add_context(|err| {
+                err.caused_by(trc::location!())
+                    .details("Failed to store TLS ticket keys")
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{TicketKeySettings, TicketKeys};
+
+    #[test]
+    fn ticket_keys() {
+        let settings = TicketKeySettings {
+            rotate: Duration::from_secs(3600),
+            grace_period: Duration::from_secs(1800),
+        };
+        let now = 1_000_000;
+        let mut keys = TicketKeys::generate(now).unwrap();
+        let ticket = keys.encrypt(b"session state").unwrap();
+        assert_eq!(
+            keys.decrypt(&ticket, now).as_deref(),
+            Some(&b"session state"[..])
+        );
+        assert!(keys.decrypt(&ticket[..20], now).is_none());
+        assert!(!keys.is_due(&settings, now + 3599));
+        assert_eq!(
+            keys.next_rotation(&settings, now + 600),
+            Duration::from_secs(3000)
+        );
+
+        // Retired keys decrypt tickets during the grace period only
+        let now = now + 3600;
+        assert!(keys.is_due(&settings, now));
+        keys.rotate(&settings, now).unwrap();
+        assert_eq!(keys.keys.len(), 2);
+        assert!(!keys.is_due(&settings, now));
+        let new_ticket = keys.encrypt(b"new session state").unwrap();
+        assert_ne!(new_ticket[..16], ticket[..16]);
+        assert!(keys.decrypt(&ticket, now + 1799).is_some());
+        assert!(keys.decrypt(&ticket, now + 1800).is_none());
+        assert!(keys.decrypt(&new_ticket, now + 1800).is_some());
+        keys.rotate(&settings, now + 3600).unwrap();
+        assert_eq!(keys.keys.len(), 2);
+        assert!(keys.decrypt(&ticket, now + 3600).is_none());
+        assert!(keys.decrypt(&new_ticket, now + 3600).is_some());
+
+        // Keys generated by a node before joining the cluster are retired
+        let local = TicketKeys::generate(now).unwrap();
+        let local_ticket = local.encrypt(b"local session state").unwrap();
+        let mut shared = keys.clone();
+        shared.merge(&local, &settings, now);
+        assert_eq!(shared.current(), keys.current());
+        assert!(shared.decrypt(&local_ticket, now + 1799).is_some());
+        assert!(shared.decrypt(&local_ticket, now + 1800).is_none());
+        let shared_ticket = shared.encrypt(b"shared session state").unwrap();
+        assert!(keys.decrypt(&shared_ticket, now).is_some());
+        assert!(local.decrypt(&shared_ticket, now).is_none());
+    }
+}
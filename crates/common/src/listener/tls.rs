@@ -12,11 +12,12 @@ use std::{
 
 use ahash::AHashMap;
 use rustls::{
-    SupportedProtocolVersion,
+    ProtocolVersion, ServerConnection, SupportedProtocolVersion,
     server::{ClientHello, ResolvesServerCert},
     sign::CertifiedKey,
     version::{TLS12, TLS13},
 };
+use sha2::{Digest, Sha256};
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio_rustls::{Accept, LazyConfigAcceptor};
 
@@ -26,6 +27,7 @@ use super::{
     ServerInstance, SessionStream, TcpAcceptor, TcpAcceptorResult,
     acme::{
         AcmeProvider,
+        crypto::AcmeStorageKeys,
         resolver::{IsTlsAlpnChallenge, build_acme_static_resolver},
     },
 };
@@ -36,16 +38,61 @@ pub static TLS12_VERSION: &[&SupportedProtocolVersion] = &[&TLS12];
 #[derive(Default, Clone)]
 pub struct AcmeProviders {
     pub providers: AHashMap<String, AcmeProvider>,
+    pub storage_keys: AcmeStorageKeys,
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClientFingerprintStats {
+    pub sessions: u64,
+    pub rejected: u64,
+}
+
+const MAX_TRACKED_FINGERPRINTS: usize = 10_000;
+
+// How the certificate served during a handshake was selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SniMatch {
+    Exact,
+    Wildcard,
+    Default,
+    None,
+}
+
+impl SniMatch {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SniMatch::Exact => "exact",
+            SniMatch::Wildcard => "wildcard",
+            SniMatch::Default => "default",
+            SniMatch::None => "none",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TlsHandshakeKey {
+    pub listener_id: String,
+    pub version: &'static str,
+    pub sni: SniMatch,
+}
+
+#[derive(Debug, Default)]
+pub struct TlsHandshakeStats {
+    pub handshakes: AHashMap<TlsHandshakeKey, u64>,
+    pub acme_challenges: AHashMap<String, u64>,
+}
+
+// Listeners configured with "tls.certificate" resolve the certificates bound to
+// them first and fall back to the ones shared by all listeners
 #[derive(Clone)]
 pub struct CertificateResolver {
     pub inner: Arc<Inner>,
+    pub listener_id: Option<String>,
 }
 
 impl CertificateResolver {
-    pub fn new(inner: Arc<Inner>) -> Self {
-        Self { inner }
+    pub fn new(inner: Arc<Inner>, listener_id: Option<String>) -> Self {
+        Self { inner, listener_id }
     }
 }
 
@@ -59,43 +106,158 @@ impl CertificateResolver {
     pub(crate) fn resolve_certificate(&self, name: Option<&str>) -> Option<Arc<CertifiedKey>> {
         let certs = self.inner.data.tls_certificates.load();
 
-        name.map_or_else(
-            || certs.get("*"),
-            |name| {
-                certs
-                    .get(name)
-                    .or_else(|| {
-                        // Try with a wildcard certificate
-                        name.split_once('.')
-                            .and_then(|(_, domain)| certs.get(domain))
-                    })
-                    .or_else(|| {
-                        trc::event!(
-                            Tls(trc::TlsEvent::CertificateNotFound),
-                            Hostname = name.to_string(),
-                        );
-                        certs.get("*")
-                    })
-            },
-        )
-        .or_else(|| match certs.len().cmp(&1) {
-            Ordering::Equal => certs.values().next(),
-            Ordering::Greater => {
-                trc::event!(
-                    Tls(trc::TlsEvent::MultipleCertificatesAvailable),
-                    Total = certs.len(),
-                );
-                certs.values().next()
-            }
-            Ordering::Less => {
-                trc::event!(
-                    Tls(trc::TlsEvent::NoCertificatesAvailable),
-                    Total = certs.len(),
-                );
-                self.inner.data.tls_self_signed_cert.as_ref()
+        certs
+            .resolve_sni(self.listener_id.as_deref(), name)
+            .or_else(|| match certs.len().cmp(&1) {
+                Ordering::Equal => certs.values().next(),
+                Ordering::Greater => {
+                    trc::event!(
+                        Tls(trc::TlsEvent::MultipleCertificatesAvailable),
+                        Total = certs.len(),
+                    );
+                    certs.values().next()
+                }
+                Ordering::Less => {
+                    trc::event!(
+                        Tls(trc::TlsEvent::NoCertificatesAvailable),
+                        Total = certs.len(),
+                    );
+                    self.inner.data.tls_self_signed_cert.as_ref()
+                }
+            })
+            .cloned()
+    }
+
+    // Handshakes served by the fallback certificates are counted as not matching
+    pub fn track_handshake(&self, listener_id: &str, conn: &ServerConnection) {
+        let version = match conn.protocol_version() {
+            Some(ProtocolVersion::TLSv1_3) => "TLSv1.3",
+            Some(ProtocolVersion::TLSv1_2) => "TLSv1.2",
+            _ => "unknown",
+        };
+        let (_, sni) = self
+            .inner
+            .data
+            .tls_certificates
+            .load()
+            .match_sni(self.listener_id.as_deref(), conn.server_name());
+
+        *self
+            .inner
+            .data
+            .tls_handshakes
+            .lock()
+            .handshakes
+            .entry(TlsHandshakeKey {
+                listener_id: listener_id.to_string(),
+                version,
+                sni,
+            })
+            .or_default() += 1;
+    }
+}
+
+pub trait ResolveSni {
+    fn resolve_sni(
+        &self,
+        listener_id: Option<&str>,
+        name: Option<&str>,
+    ) -> Option<&Arc<CertifiedKey>>;
+    fn match_sni(
+        &self,
+        listener_id: Option<&str>,
+        name: Option<&str>,
+    ) -> (Option<&Arc<CertifiedKey>>, SniMatch);
+}
+
+impl ResolveSni for AHashMap<String, Arc<CertifiedKey>> {
+    fn resolve_sni(
+        &self,
+        listener_id: Option<&str>,
+        name: Option<&str>,
+    ) -> Option<&Arc<CertifiedKey>> {
+        let (cert, sni_match) = self.match_sni(listener_id, name);
+        if let (Some(name), SniMatch::Default | SniMatch::None) = (name, sni_match) {
+            trc::event!(
+                Tls(trc::TlsEvent::CertificateNotFound),
+                Hostname = name.trim_end_matches('.').to_string(),
+            );
+        }
+
+        cert
+    }
+
+    // Resolves the certificate for an SNI name trying, in order, an exact match,
+    // a wildcard certificate for the parent domain and the default certificate.
+    // Wildcard certificates are stored under ".domain" and only cover a single
+    // label, a certificate for "domain" is never served for its subdomains.
+    // At each step the certificates bound to the listener are tried first.
+    fn match_sni(
+        &self,
+        listener_id: Option<&str>,
+        name: Option<&str>,
+    ) -> (Option<&Arc<CertifiedKey>>, SniMatch) {
+        let scopes = [listener_id, None];
+        let scopes = &scopes[usize::from(listener_id.is_none())..];
+
+        if let Some(name) = name {
+            let name = name.trim_end_matches('.');
+            for scope in scopes {
+                if let Some(cert) = get_scoped(self, *scope, name) {
+                    return (Some(cert), SniMatch::Exact);
+                } else if let Some(cert) = name
+                    .find('.')
+                    .map(|pos| &name[pos..])
+                    .filter(|domain| domain[1..].contains('.'))
+                    .and_then(|domain| get_scoped(self, *scope, domain))
+                {
+                    return (Some(cert), SniMatch::Wildcard);
+                }
             }
-        })
-        .cloned()
+        }
+
+        scopes
+            .iter()
+            .find_map(|scope| get_scoped(self, *scope, "*"))
+            .map_or((None, SniMatch::None), |cert| {
+                (Some(cert), SniMatch::Default)
+            })
+    }
+}
+
+fn get_scoped<'x>(
+    certs: &'x AHashMap<String, Arc<CertifiedKey>>,
+    listener_id: Option<&str>,
+    name: &str,
+) -> Option<&'x Arc<CertifiedKey>> {
+    match listener_id {
+        Some(listener_id) => certs.get(&listener_certificate_key(listener_id, name)),
+        None => certs.get(name),
+    }
+}
+
+// Certificates bound to listeners are stored as "listener-id/name"
+pub fn listener_certificate_key(listener_id: &str, name: &str) -> String {
+    format!("{listener_id}/{name}")
+}
+
+// Returns the host name a certificate is stored under, without the listener it is bound to
+pub fn certificate_hostname(key: &str) -> &str {
+    key.rsplit_once('/').map_or(key, |(_, name)| name)
+}
+
+pub(crate) fn insert_certificate(
+    certificates: &mut AHashMap<String, Arc<CertifiedKey>>,
+    listeners: &[String],
+    name: &str,
+    cert: Arc<CertifiedKey>,
+) {
+    if listeners.is_empty() {
+        certificates.insert(name.to_string(), cert);
+    } else {
+        for listener_id in listeners {
+            certificates.insert(listener_certificate_key(listener_id, name), cert.clone());
+        }
     }
 }
 
@@ -114,75 +276,84 @@ impl TcpAcceptor {
                 config,
                 acceptor,
                 implicit,
-            } if *implicit => match enable_acme {
-                None => TcpAcceptorResult::Tls(acceptor.accept(stream)),
-                Some(core) => {
-                    match LazyConfigAcceptor::new(Default::default(), stream).await {
-                        Ok(start_handshake) => {
-                            if core.has_acme_tls_providers()
+                fingerprint,
+                ..
+            } if *implicit => {
+                if enable_acme.is_none() && !*fingerprint {
+                    return TcpAcceptorResult::Tls(acceptor.accept(stream), None);
+                }
+
+                match LazyConfigAcceptor::new(Default::default(), stream).await {
+                    Ok(start_handshake) => {
+                        if let Some(core) = enable_acme.filter(|core| {
+                            core.has_acme_tls_providers()
                                 && start_handshake.client_hello().is_tls_alpn_challenge()
+                        }) {
+                            core.track_acme_challenge(&instance.id);
+
+                            let key = match start_handshake.client_hello().server_name() {
+                                Some(domain) => {
+                                    let key = core.build_acme_certificate(domain).await;
+
+                                    trc::event!(
+                                        Acme(trc::AcmeEvent::ClientSuppliedSni),
+                                        ListenerId = instance.id.clone(),
+                                        Domain = domain.to_string(),
+                                        Result = key.is_some(),
+                                    );
+
+                                    key
+                                }
+                                None => {
+                                    trc::event!(
+                                        Acme(trc::AcmeEvent::ClientMissingSni),
+                                        ListenerId = instance.id.clone(),
+                                    );
+
+                                    None
+                                }
+                            };
+
+                            match start_handshake
+                                .into_stream(build_acme_static_resolver(key))
+                                .await
                             {
-                                let key = match start_handshake.client_hello().server_name() {
-                                    Some(domain) => {
-                                        let key = core.build_acme_certificate(domain).await;
-
-                                        trc::event!(
-                                            Acme(trc::AcmeEvent::ClientSuppliedSni),
-                                            ListenerId = instance.id.clone(),
-                                            Domain = domain.to_string(),
-                                            Result = key.is_some(),
-                                        );
-
-                                        key
-                                    }
-                                    None => {
-                                        trc::event!(
-                                            Acme(trc::AcmeEvent::ClientMissingSni),
-                                            ListenerId = instance.id.clone(),
-                                        );
-
-                                        None
-                                    }
-                                };
-
-                                match start_handshake
-                                    .into_stream(build_acme_static_resolver(key))
-                                    .await
-                                {
-                                    Ok(mut tls) => {
-                                        trc::event!(
-                                            Acme(trc::AcmeEvent::TlsAlpnReceived),
-                                            ListenerId = instance.id.clone(),
-                                        );
-
-                                        let _ = tls.shutdown().await;
-                                    }
-                                    Err(err) => {
-                                        trc::event!(
-                                            Acme(trc::AcmeEvent::TlsAlpnError),
-                                            ListenerId = instance.id.clone(),
-                                            Reason = err.to_string(),
-                                        );
-                                    }
+                                Ok(mut tls) => {
+                                    trc::event!(
+                                        Acme(trc::AcmeEvent::TlsAlpnReceived),
+                                        ListenerId = instance.id.clone(),
+                                    );
+
+                                    let _ = tls.shutdown().await;
+                                }
+                                Err(err) => {
+                                    trc::event!(
+                                        Acme(trc::AcmeEvent::TlsAlpnError),
+                                        ListenerId = instance.id.clone(),
+                                        Reason = err.to_string(),
+                                    );
                                 }
-                            } else {
-                                return TcpAcceptorResult::Tls(
-                                    start_handshake.into_stream(config.clone()),
-                                );
                             }
-                        }
-                        Err(err) => {
-                            trc::event!(
-                                Tls(trc::TlsEvent::HandshakeError),
-                                ListenerId = instance.id.clone(),
-                                Reason = err.to_string(),
+                        } else {
+                            let fingerprint =
+                                fingerprint.then(|| start_handshake.client_hello().fingerprint());
+                            return TcpAcceptorResult::Tls(
+                                start_handshake.into_stream(config.clone()),
+                                fingerprint,
                             );
                         }
                     }
-
-                    TcpAcceptorResult::Close
+                    Err(err) => {
+                        trc::event!(
+                            Tls(trc::TlsEvent::HandshakeError),
+                            ListenerId = instance.id.clone(),
+                            Reason = err.to_string(),
+                        );
+                    }
                 }
-            },
+
+                TcpAcceptorResult::Close
+            }
             _ => TcpAcceptorResult::Plain(stream),
         }
     }
@@ -190,6 +361,127 @@ impl TcpAcceptor {
     pub fn is_tls(&self) -> bool {
         matches!(self, TcpAcceptor::Tls { .. })
     }
+
+    pub fn track_handshake(&self, listener_id: &str, conn: &ServerConnection) {
+        if let TcpAcceptor::Tls {
+            resolver: Some(resolver),
+            ..
+        } = self
+        {
+            resolver.track_handshake(listener_id, conn);
+        }
+    }
+}
+
+impl Server {
+    // TLS-ALPN-01 challenges are counted separately from client handshakes
+    pub fn track_acme_challenge(&self, listener_id: &str) {
+        let mut stats = self.inner.data.tls_handshakes.lock();
+        if let Some(count) = stats.acme_challenges.get_mut(listener_id) {
+            *count += 1;
+        } else {
+            stats.acme_challenges.insert(listener_id.to_string(), 1);
+        }
+    }
+
+    pub fn track_tls_fingerprint(&self, fingerprint: &str, is_rejected: bool) {
+        let mut fingerprints = self.inner.data.tls_fingerprints.lock();
+        if let Some(stats) = fingerprints.get_mut(fingerprint) {
+            if is_rejected {
+                stats.rejected += 1;
+            } else {
+                stats.sessions += 1;
+            }
+        } else if fingerprints.len() < MAX_TRACKED_FINGERPRINTS {
+            fingerprints.insert(
+                fingerprint.to_string(),
+                ClientFingerprintStats {
+                    sessions: u64::from(!is_rejected),
+                    rejected: u64::from(is_rejected),
+                },
+            );
+        }
+    }
+}
+
+pub trait ClientFingerprint {
+    fn fingerprint(&self) -> String;
+}
+
+impl ClientFingerprint for ClientHello<'_> {
+    // JA4-style fingerprint computed from the ClientHello fields exposed by rustls:
+    // version, SNI presence, cipher and signature algorithm counts, the first ALPN
+    // protocol, followed by truncated hashes of the sorted ciphers and the ordered
+    // signature algorithms.
+    fn fingerprint(&self) -> String {
+        let mut ciphers = self
+            .cipher_suites()
+            .iter()
+            .map(|c| u16::from(*c))
+            .filter(|c| !is_grease(*c))
+            .collect::<Vec<_>>();
+        let signatures = self
+            .signature_schemes()
+            .iter()
+            .map(|s| u16::from(*s))
+            .filter(|s| !is_grease(*s))
+            .collect::<Vec<_>>();
+        let version = if ciphers.iter().any(|c| (0x1301..=0x1305).contains(c)) {
+            "13"
+        } else {
+            "12"
+        };
+        let sni = if self.server_name().is_some() {
+            'd'
+        } else {
+            'i'
+        };
+        let alpn = self
+            .alpn()
+            .and_then(|mut alpn| alpn.next())
+            .filter(|proto| !proto.is_empty())
+            .map(|proto| {
+                let first = proto[0];
+                let last = proto[proto.len() - 1];
+                if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+                    format!("{}{}", first as char, last as char)
+                } else {
+                    format!("{:x}{:x}", first >> 4, last & 0x0f)
+                }
+            })
+            .unwrap_or_else(|| "00".to_string());
+        let cipher_count = ciphers.len().min(99);
+        let signature_count = signatures.len().min(99);
+        ciphers.sort_unstable();
+
+        format!(
+            "t{version}{sni}{cipher_count:02}{signature_count:02}{alpn}_{}_{}",
+            truncated_hash(&ciphers),
+            truncated_hash(&signatures)
+        )
+    }
+}
+
+#[inline(always)]
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+fn truncated_hash(values: &[u16]) -> String {
+    if !values.is_empty() {
+        let mut hasher = Sha256::new();
+        for (pos, value) in values.iter().enumerate() {
+            if pos > 0 {
+                hasher.update(b",");
+            }
+            hasher.update(format!("{value:04x}").as_bytes());
+        }
+        let mut hash = format!("{:x}", hasher.finalize());
+        hash.truncate(12);
+        hash
+    } else {
+        "000000000000".to_string()
+    }
 }
 
 impl<IO> TcpAcceptorResult<IO>
@@ -198,7 +490,7 @@ where
 {
     pub fn unwrap_tls(self) -> Accept<IO> {
         match self {
-            TcpAcceptorResult::Tls(accept) => accept,
+            TcpAcceptorResult::Tls(accept, _) => accept,
             _ => panic!("unwrap_tls called on non-TLS acceptor"),
         }
     }
@@ -209,3 +501,119 @@ impl std::fmt::Debug for CertificateResolver {
         f.debug_struct("CertificateResolver").finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ahash::AHashMap;
+
+    use crate::config::server::tls::build_self_signed_cert;
+
+    use super::{ResolveSni, SniMatch};
+
+    #[test]
+    fn resolve_sni() {
+        let cert = build_self_signed_cert(vec!["example.org".to_string()]).unwrap();
+        let mut certs = AHashMap::new();
+        for name in [
+            "mail.example.org",
+            "example.org",
+            ".example.org",
+            "b.example.org",
+            ".b.example.org",
+            "example.net",
+            "*",
+        ] {
+            certs.insert(name.to_string(), Arc::new(cert.clone()));
+        }
+
+        for (sni, expected) in [
+            // Exact match
+            (Some("mail.example.org"), Some("mail.example.org")),
+            (Some("example.org"), Some("example.org")),
+            (Some("mail.example.org."), Some("mail.example.org")),
+            (Some("b.example.org"), Some("b.example.org")),
+            // Wildcard for the parent domain
+            (Some("mta-sts.example.org"), Some(".example.org")),
+            (Some("a.b.example.org"), Some(".b.example.org")),
+            // Wildcards only cover a single label
+            (Some("a.c.example.org"), Some("*")),
+            (Some("x.y.z.example.org"), Some("*")),
+            // Non-wildcard certificates are not served for subdomains
+            (Some("mta-sts.example.net"), Some("*")),
+            // Unknown domains and SNI-less connections use the default certificate
+            (Some("example.com"), Some("*")),
+            (Some("org"), Some("*")),
+            (None, Some("*")),
+        ] {
+            assert_eq!(
+                certs.resolve_sni(None, sni).map(Arc::as_ptr),
+                expected.map(|name| Arc::as_ptr(&certs[name])),
+                "sni: {sni:?}"
+            );
+        }
+
+        // No default certificate
+        certs.remove("*");
+        assert!(certs.resolve_sni(None, None).is_none());
+        assert!(certs.resolve_sni(None, Some("a.c.example.org")).is_none());
+        assert!(certs.resolve_sni(None, Some("foo.example.org")).is_some());
+        assert!(certs.resolve_sni(None, Some("foo.example.net")).is_none());
+
+        // Handshakes are labeled with the kind of match
+        for (sni, expected) in [
+            (Some("example.org"), SniMatch::Exact),
+            (Some("mta-sts.example.org"), SniMatch::Wildcard),
+            (Some("example.com"), SniMatch::None),
+            (None, SniMatch::None),
+        ] {
+            assert_eq!(certs.match_sni(None, sni).1, expected, "sni: {sni:?}");
+        }
+        certs.insert("*".to_string(), Arc::new(cert));
+        assert_eq!(
+            certs.match_sni(None, Some("example.com")).1,
+            SniMatch::Default
+        );
+        assert_eq!(certs.match_sni(None, None).1, SniMatch::Default);
+
+        // Certificates bound to a listener take precedence over shared ones
+        for name in ["smtp/mail.example.org", "smtp/.b.example.org", "imap/*"] {
+            certs.insert(name.to_string(), Arc::new(cert.clone()));
+        }
+        for (listener_id, sni, expected) in [
+            ("smtp", Some("mail.example.org"), "smtp/mail.example.org"),
+            ("imap", Some("mail.example.org"), "mail.example.org"),
+            ("smtp", Some("a.b.example.org"), "smtp/.b.example.org"),
+            ("smtp", Some("b.example.org"), "b.example.org"),
+            // Shared wildcards are preferred over the listener's default
+            ("imap", Some("mta-sts.example.org"), ".example.org"),
+            ("imap", Some("example.com"), "imap/*"),
+            ("imap", None, "imap/*"),
+            ("smtp", Some("example.com"), "*"),
+        ] {
+            assert_eq!(
+                certs.resolve_sni(Some(listener_id), sni).map(Arc::as_ptr),
+                Some(Arc::as_ptr(&certs[expected])),
+                "listener: {listener_id}, sni: {sni:?}"
+            );
+        }
+        assert_eq!(certs.match_sni(Some("imap"), None).1, SniMatch::Default);
+        assert_eq!(
+            certs.match_sni(Some("smtp"), Some("mail.example.org")).1,
+            SniMatch::Exact
+        );
+
+        // Bound certificates are never served to other listeners
+        assert_eq!(
+            certs
+                .resolve_sni(Some("pop3"), Some("a.b.example.org"))
+                .map(Arc::as_ptr),
+            Some(Arc::as_ptr(&certs[".b.example.org"]))
+        );
+        assert_eq!(
+            certs.resolve_sni(None, None).map(Arc::as_ptr),
+            Some(Arc::as_ptr(&certs["*"]))
+        );
+    }
+}
@@ -19,7 +19,10 @@ use store::{
 use tokio::sync::{Notify, Semaphore, mpsc};
 use utils::{
     Semver, UnwrapFailure,
-    config::{Config, ConfigKey},
+    config::{
+        Config, ConfigError, ConfigKey,
+        secret::{MASTER_KEY_ENV, MASTER_KEY_FILE_ENV, MasterKey},
+    },
     failed,
 };
 
@@ -65,6 +68,8 @@ Options:
   -i, --import <PATH>              Import store data from a specific path
   -o, --console                    Open the store console
   -I, --init <PATH>                Initialize a new server at a specific path
+      --encrypt-secret <VALUE>     Encrypt a setting value with the master key ('-' reads stdin)
+      --rotate-master-key <PATH>   Re-encrypt all settings with the master key stored at PATH
   -h, --help                       Print help
   -V, --version                    Print version
 "#
@@ -74,6 +79,7 @@ Options:
 enum StoreOp {
     Export(BackupParams),
     Import(PathBuf),
+    RotateMasterKey(PathBuf),
     Console,
     None,
 }
@@ -122,6 +128,13 @@ impl BootManager {
                     ("console" | "o", None) => {
                         import_export = StoreOp::Console;
                     }
+                    ("encrypt-secret", Some(value)) => {
+                        encrypt_secret(value);
+                        std::process::exit(0);
+                    }
+                    ("rotate-master-key", Some(value)) => {
+                        import_export = StoreOp::RotateMasterKey(value.into());
+                    }
                     (_, None) => {
                         failed(&format!("Unrecognized command '{key}', try '--help'."));
                     }
@@ -166,6 +179,9 @@ impl BootManager {
         // Resolve file and configuration macros
         config.resolve_macros(&["file", "cfg"]).await;
 
+        // Decrypt sealed secrets
+        resolve_secrets(&mut config);
+
         // Load stores
         let mut stores = Stores::parse(&mut config).await;
         let local_patterns = Patterns::parse(&mut config);
@@ -220,6 +236,7 @@ impl BootManager {
 
                 config.keys.entry(key).or_insert(value);
             }
+            resolve_secrets(&mut config);
         }
 
         // Parse telemetry
@@ -477,6 +494,24 @@ impl BootManager {
                     .await;
                 std::process::exit(0);
             }
+            StoreOp::RotateMasterKey(path) => {
+                let current_key = MasterKey::global()
+                    .failed("Failed to obtain master key")
+                    .failed(&format!(
+                        "No master key was provided in {MASTER_KEY_ENV} or {MASTER_KEY_FILE_ENV}"
+                    ));
+                let new_key =
+                    MasterKey::new(&std::fs::read(&path).failed("Failed to read new master key"));
+                let total = manager
+                    .rotate_master_key(current_key, &new_key)
+                    .await
+                    .failed("Failed to re-encrypt settings");
+
+                eprintln!(
+                    "✅ Re-encrypted {total} settings, update {MASTER_KEY_ENV} or {MASTER_KEY_FILE_ENV} before restarting the server."
+                );
+                std::process::exit(0);
+            }
             StoreOp::Console => {
                 // Store console
                 store_console(
@@ -524,6 +559,44 @@ pub fn build_ipc(config: &mut Config, has_pubsub: bool) -> (Ipc, IpcReceivers) {
     )
 }
 
+fn resolve_secrets(config: &mut Config) {
+    config.resolve_secrets();
+
+    // Refuse to start with settings that are still encrypted
+    let sealed_keys = config.sealed_keys();
+    if !sealed_keys.is_empty() {
+        let mut message = "Failed to decrypt the following settings:".to_string();
+        for key in sealed_keys {
+            message.push_str("\n  - ");
+            message.push_str(key);
+            if let Some(ConfigError::Macro { error }) = config.errors.get(key) {
+                message.push_str(": ");
+                message.push_str(error);
+            }
+        }
+        failed(&message);
+    }
+}
+
+fn encrypt_secret(value: String) {
+    let master_key = MasterKey::global()
+        .failed("Failed to obtain master key")
+        .failed(&format!(
+            "No master key was provided in {MASTER_KEY_ENV} or {MASTER_KEY_FILE_ENV}"
+        ));
+    let value = if value == "-" {
+        let mut value = String::new();
+        std::io::stdin()
+            .read_line(&mut value)
+            .failed("Failed to read value from stdin");
+        value.trim_end_matches(['\r', '\n']).to_string()
+    } else {
+        value
+    };
+
+    println!("{}", master_key.seal(&value));
+}
+
 fn quickstart(path: impl Into<PathBuf>) {
     let path = path.into();
 
@@ -19,7 +19,10 @@ use store::{
 use trc::AddContext;
 use utils::{
     Semver,
-    config::{Config, ConfigKey},
+    config::{
+        Config, ConfigKey,
+        secret::{MasterKey, is_sealed},
+    },
     glob::GlobPattern,
 };
 
@@ -63,9 +66,9 @@ impl ConfigManager {
             ..Default::default()
         };
         config.resolve_all_macros().await;
-        self.extend_config(&mut config, prefix)
-            .await
-            .map(|_| config)
+        self.extend_config(&mut config, prefix).await?;
+        config.resolve_secrets();
+        Ok(config)
     }
 
     pub(crate) async fn extend_config(&self, config: &mut Config, prefix: &str) -> trc::Result<()> {
@@ -329,6 +332,33 @@ impl ConfigManager {
             })
     }
 
+    pub async fn rotate_master_key(
+        &self,
+        current_key: &MasterKey,
+        new_key: &MasterKey,
+    ) -> trc::Result<usize> {
+        let mut keys = Vec::new();
+        for (key, value) in self.list("", false).await? {
+            if is_sealed(&value) {
+                let value = current_key.unseal(&value).map_err(|reason| {
+                    trc::EventType::Config(trc::ConfigEvent::ParseError)
+                        .into_err()
+                        .details("Failed to decrypt setting")
+                        .ctx(trc::Key::Key, key.clone())
+                        .ctx(trc::Key::Reason, reason)
+                })?;
+                keys.push(ConfigKey {
+                    value: new_key.seal(&value),
+                    key,
+                });
+            }
+        }
+
+        let total = keys.len();
+        self.set(keys, true).await?;
+        Ok(total)
+    }
+
     pub async fn update_spam_rules(
         &self,
         force_update: bool,
@@ -0,0 +1,265 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::Local;
+use store::{
+    Serialize,
+    dispatch::lookup::KeyValue,
+    write::{AlignedBytes, Archive, Archiver, now},
+};
+use trc::AddContext;
+
+use crate::{Inner, KV_MAINTENANCE_TASK, Server, config::maintenance::MaintenanceTask};
+
+const MAX_TASK_HISTORY: usize = 50;
+const CONCURRENCY_RETRY: Duration = Duration::from_secs(60);
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+)]
+pub struct TaskRun {
+    pub started_at: u64,
+    pub elapsed_ms: u64,
+    pub node_id: u64,
+    pub trigger: TaskTrigger,
+    pub status: TaskRunStatus,
+}
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskTrigger {
+    Scheduled,
+    Manual,
+}
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum TaskRunStatus {
+    Completed,
+    BudgetExceeded,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Default)]
+struct TaskHistory {
+    runs: Vec<TaskRun>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunningTask {
+    pub id: u64,
+    pub task: MaintenanceTask,
+    pub started_at: u64,
+}
+
+// Removes the task from the list of running tasks once it finishes or is cancelled
+pub struct RunningTaskGuard {
+    inner: Arc<Inner>,
+    id: u64,
+}
+
+impl Server {
+    // Returns the time to wait before a scheduled task is allowed to start
+    pub fn start_maintenance_task(
+        &self,
+        task: MaintenanceTask,
+    ) -> Result<RunningTaskGuard, Duration> {
+        let maintenance = &self.core.network.maintenance;
+        if let Some(wait) = maintenance.time_to_window(task, Local::now()) {
+            trc::event!(
+                Housekeeper(trc::HousekeeperEvent::Deferred),
+                Type = task.id(),
+                Reason = "Outside of the maintenance window",
+                NextRetry = trc::Value::Timestamp(now() + wait.as_secs()),
+            );
+            return Err(wait);
+        }
+
+        let mut running = self.inner.data.running_tasks.lock();
+        let policy = maintenance.policy(task);
+        if maintenance
+            .max_concurrency
+            .is_some_and(|max| running.len() >= max)
+            || policy
+                .max_concurrency
+                .is_some_and(|max| running.iter().filter(|r| r.task == task).count() >= max)
+        {
+            trc::event!(
+                Housekeeper(trc::HousekeeperEvent::Deferred),
+                Type = task.id(),
+                Reason = "Too many maintenance tasks running",
+                NextRetry = trc::Value::Timestamp(now() + CONCURRENCY_RETRY.as_secs()),
+            );
+            return Err(CONCURRENCY_RETRY);
+        }
+
+        Ok(self.register_running_task(&mut running, task))
+    }
+
+    // Manually triggered tasks ignore maintenance windows and concurrency limits
+    pub fn force_maintenance_task(&self, task: MaintenanceTask) -> RunningTaskGuard {
+        let mut running = self.inner.data.running_tasks.lock();
+        self.register_running_task(&mut running, task)
+    }
+
+    fn register_running_task(
+        &self,
+        running: &mut Vec<RunningTask>,
+        task: MaintenanceTask,
+    ) -> RunningTaskGuard {
+        let id = self.inner.data.span_id_gen.generate();
+        running.push(RunningTask {
+            id,
+            task,
+            started_at: now(),
+        });
+        RunningTaskGuard {
+            inner: self.inner.clone(),
+            id,
+        }
+    }
+
+    pub fn running_maintenance_tasks(&self, task: MaintenanceTask) -> Vec<RunningTask> {
+        self.inner
+            .data
+            .running_tasks
+            .lock()
+            .iter()
+            .filter(|running| running.task == task)
+            .copied()
+            .collect()
+    }
+
+    // Paused flags and the task history live in the in-memory store so they are
+    // shared by all nodes in the cluster
+    pub async fn is_maintenance_task_paused(&self, task: MaintenanceTask) -> trc::Result<bool> {
+        self.in_memory_store()
+            .key_get::<String>(KeyValue::<()>::build_key(
+                KV_MAINTENANCE_TASK,
+                task_key(b'p', task),
+            ))
+            .await
+            .caused_by(trc::location!())
+            .map(|value| value.is_some())
+    }
+
+    pub async fn set_maintenance_task_paused(
+        &self,
+        task: MaintenanceTask,
+        paused: bool,
+    ) -> trc::Result<()> {
+        let result = if paused {
+            self.in_memory_store()
+                .key_set(KeyValue::with_prefix(
+                    KV_MAINTENANCE_TASK,
+                    task_key(b'p', task),
+                    now().to_string().into_bytes(),
+                ))
+                .await
+        } else {
+            self.in_memory_store()
+                .key_delete(KeyValue::<()>::build_key(
+                    KV_MAINTENANCE_TASK,
+                    task_key(b'p', task),
+                ))
+                .await
+        };
+
+        result.caused_by(trc::location!())
+    }
+
+    pub async fn maintenance_task_history(
+        &self,
+        task: MaintenanceTask,
+    ) -> trc::Result<Vec<TaskRun>> {
+        self.in_memory_store()
+            .key_get::<Archive<AlignedBytes>>(KeyValue::<()>::build_key(
+                KV_MAINTENANCE_TASK,
+                task_key(b'h', task),
+            ))
+            .await
+            .caused_by(trc::location!())?
+            .map(|history| {
+                history
+                    .deserialize::<TaskHistory>()
+                    .map(|history| history.runs)
+            })
+            .transpose()
+            .caused_by(trc::location!())
+            .map(|runs| runs.unwrap_or_default())
+    }
+
+    // Tasks only run on one node at a time, so updates to the history do not race
+    pub async fn record_maintenance_task_run(
+        &self,
+        task: MaintenanceTask,
+        run: TaskRun,
+    ) -> trc::Result<()> {
+        let mut runs = self.maintenance_task_history(task).await?;
+        runs.insert(0, run);
+        runs.truncate(MAX_TASK_HISTORY);
+
+        self.in_memory_store()
+            .key_set(KeyValue::with_prefix(
+                KV_MAINTENANCE_TASK,
+                task_key(b'h', task),
+                Archiver::new(TaskHistory { runs })
+                    .serialize()
+                    .caused_by(trc::location!())?,
+            ))
+            .await
+            .caused_by(trc::location!())
+    }
+}
+
+impl Drop for RunningTaskGuard {
+    fn drop(&mut self) {
+        self.inner
+            .data
+            .running_tasks
+            .lock()
+            .retain(|running| running.id != self.id);
+    }
+}
+
+fn task_key(class: u8, task: MaintenanceTask) -> Vec<u8> {
+    let id = task.id();
+    let mut key = Vec::with_capacity(id.len() + 1);
+    key.push(class);
+    key.extend_from_slice(id.as_bytes());
+    key
+}
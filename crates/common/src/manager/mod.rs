@@ -17,6 +17,7 @@ pub mod backup;
 pub mod boot;
 pub mod config;
 pub mod console;
+pub mod maintenance;
 pub mod reload;
 pub mod restore;
 pub mod webadmin;
@@ -4,10 +4,12 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::time::Instant;
+
 use ahash::AHashMap;
 use arc_swap::ArcSwap;
 use store::Stores;
-use utils::config::Config;
+use utils::config::{Config, ConfigError};
 
 use crate::{
     Core, Server,
@@ -15,6 +17,7 @@ use crate::{
         server::{Listeners, tls::parse_certificates},
         telemetry::Telemetry,
     },
+    ipc::HousekeeperEvent,
     listener::blocked::{BLOCKED_IP_KEY, BlockedIps},
 };
 
@@ -41,12 +44,53 @@ impl Server {
 
     pub async fn reload_certificates(&self) -> trc::Result<ReloadResult> {
         let mut config = self.core.storage.config.build_config("certificate").await?;
+        // Listeners may bind certificates to themselves
+        self.core
+            .storage
+            .config
+            .extend_config(&mut config, "server.listener")
+            .await?;
         let mut certificates = self.inner.data.tls_certificates.load().as_ref().clone();
 
         parse_certificates(&mut config, &mut certificates, &mut Default::default());
+        self.staple_cached_ocsp_all(&mut certificates).await;
+
+        // Certificates that failed to load keep serving their previous version
+        for cert_id in config
+            .sub_keys("certificate", ".cert")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+        {
+            let prefix = format!("certificate.{cert_id}");
+            if let Some(
+                ConfigError::Parse { error }
+                | ConfigError::Build { error }
+                | ConfigError::Macro { error },
+            ) = config.errors.iter().find_map(|(key, err)| {
+                (key == &prefix || key.starts_with(&format!("{prefix}."))).then_some(err)
+            }) {
+                trc::event!(
+                    Tls(trc::TlsEvent::CertificateReloadError),
+                    Id = cert_id,
+                    Reason = error.clone(),
+                );
+            } else {
+                trc::event!(Tls(trc::TlsEvent::CertificateReloaded), Id = cert_id);
+            }
+        }
 
         self.inner.data.tls_certificates.store(certificates.into());
 
+        // Fetch OCSP responses for any new certificates
+        self.inner
+            .ipc
+            .housekeeper_tx
+            .send(HousekeeperEvent::OcspReschedule {
+                refresh_at: Instant::now(),
+            })
+            .await
+            .ok();
+
         Ok(config.into())
     }
 
@@ -116,6 +160,7 @@ impl Server {
         for (cert_id, cert) in new_certificates {
             current_certificates.insert(cert_id, cert);
         }
+        self.staple_cached_ocsp_all(&mut current_certificates).await;
         self.inner
             .data
             .tls_certificates
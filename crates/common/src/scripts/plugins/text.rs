@@ -6,6 +6,7 @@
 
 use nlp::tokenizers::types::{TokenType, TypesTokenizer};
 use sieve::{FunctionMap, runtime::Variable};
+use utils::suffixlist::org_domain;
 
 use crate::scripts::functions::{ApplyString, text::tokenize_words};
 
@@ -68,7 +69,7 @@ pub fn exec_domain_part(ctx: PluginContext<'_>) -> trc::Result<Variable> {
 
     Ok(v[0].transform(|domain| {
         match part {
-            DomainPart::Sld => psl::domain_str(domain),
+            DomainPart::Sld => Some(org_domain(domain)),
             DomainPart::Tld => domain.rsplit_once('.').map(|(_, tld)| tld),
             DomainPart::Host => domain.split_once('.').map(|(host, _)| host),
         }
@@ -0,0 +1,165 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use ahash::AHashMap;
+use directory::Directory;
+use parking_lot::Mutex;
+use trc::{Collector, MetricType, TaskQueueEvent};
+
+use crate::Server;
+
+/// Number of messages waiting in the task queue to be added to the full-text
+/// index. The task queue itself is durable, this is an in-memory view that is
+/// rebuilt every time the queue is scanned.
+#[derive(Default)]
+pub struct IndexBacklog {
+    accounts: Mutex<AHashMap<u32, u64>>,
+    total: AtomicU64,
+    drained: AtomicU64,
+    is_high: AtomicBool,
+}
+
+impl IndexBacklog {
+    pub fn enqueued(&self, account_id: u32) {
+        *self.accounts.lock().entry(account_id).or_default() += 1;
+        self.total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn drained(&self, account_id: u32) {
+        let mut accounts = self.accounts.lock();
+        if let Some(pending) = accounts.get_mut(&account_id) {
+            *pending = pending.saturating_sub(1);
+            if *pending == 0 {
+                accounts.remove(&account_id);
+            }
+        }
+        let _ = self
+            .total
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                Some(total.saturating_sub(1))
+            });
+        self.drained.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn refresh(&self, accounts: AHashMap<u32, u64>) {
+        self.total.store(accounts.values().sum(), Ordering::Relaxed);
+        *self.accounts.lock() = accounts;
+    }
+
+    pub fn pending(&self, account_id: u32) -> u64 {
+        self.accounts
+            .lock()
+            .get(&account_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    pub fn total_drained(&self) -> u64 {
+        self.drained.load(Ordering::Relaxed)
+    }
+
+    pub fn top_accounts(&self, limit: usize) -> Vec<(u32, u64)> {
+        let mut accounts = self
+            .accounts
+            .lock()
+            .iter()
+            .map(|(account_id, pending)| (*account_id, *pending))
+            .collect::<Vec<_>>();
+        accounts.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+        accounts.truncate(limit);
+        accounts
+    }
+}
+
+impl Server {
+    pub fn has_index_pending(&self, account_id: u32) -> bool {
+        self.inner.data.index_backlog.pending(account_id) > 0
+    }
+
+    pub fn is_index_backlogged(&self, account_id: u32) -> bool {
+        self.core
+            .jmap
+            .index_backlog_max
+            .is_some_and(|max| self.inner.data.index_backlog.pending(account_id) >= max)
+    }
+
+    pub async fn is_address_index_backlogged(
+        &self,
+        directory: &Directory,
+        address: &str,
+        session_id: u64,
+    ) -> trc::Result<bool> {
+        if self.core.jmap.index_backlog_max.is_some() {
+            self.email_to_id(directory, address, session_id)
+                .await
+                .map(|account_id| account_id.is_some_and(|id| self.is_index_backlogged(id)))
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn update_index_backlog(&self) {
+        let backlog = &self.inner.data.index_backlog;
+        let total = backlog.total();
+        Collector::update_gauge(MetricType::IndexQueueCount, total);
+
+        // Alert only when the threshold is crossed, not on every scan
+        if let Some(threshold) = self.core.jmap.index_backlog_alert {
+            if total >= threshold {
+                if !backlog.is_high.swap(true, Ordering::Relaxed) {
+                    trc::event!(
+                        TaskQueue(TaskQueueEvent::BacklogHigh),
+                        Total = total,
+                        Limit = threshold,
+                    );
+                }
+            } else if backlog.is_high.swap(false, Ordering::Relaxed) {
+                trc::event!(
+                    TaskQueue(TaskQueueEvent::BacklogCleared),
+                    Total = total,
+                    Limit = threshold,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ahash::AHashMap;
+
+    use super::IndexBacklog;
+
+    #[test]
+    fn index_backlog_accounting() {
+        let backlog = IndexBacklog::default();
+        for _ in 0..3 {
+            backlog.enqueued(1);
+        }
+        backlog.enqueued(2);
+        assert_eq!(backlog.total(), 4);
+        assert_eq!(backlog.pending(1), 3);
+        assert_eq!(backlog.top_accounts(1), vec![(1, 3)]);
+
+        backlog.drained(2);
+        backlog.drained(2);
+        assert_eq!(backlog.pending(2), 0);
+        assert_eq!(backlog.total(), 2);
+        assert_eq!(backlog.total_drained(), 2);
+
+        // A scan of the queue replaces the in-memory estimate
+        backlog.refresh(AHashMap::from_iter([(3, 5)]));
+        assert_eq!(backlog.total(), 5);
+        assert_eq!(backlog.pending(1), 0);
+        assert_eq!(backlog.pending(3), 5);
+    }
+}
@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod backlog;
 pub mod blob;
 pub mod index;
 pub mod state;
@@ -0,0 +1,138 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::Server;
+
+const MAX_SAMPLES: usize = 100_000;
+const HISTOGRAM_BUCKETS: &[u64] = &[1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LoadTestStage {
+    Receive,
+    Filter,
+    Encrypt,
+    Store,
+}
+
+#[derive(Default)]
+pub struct LoadTestStats {
+    samples: Mutex<[Vec<u64>; 4]>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadTestStageReport {
+    pub stage: Option<LoadTestStage>,
+    pub count: u64,
+    pub min: u64,
+    pub max: u64,
+    pub mean: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub histogram: Vec<LoadTestBucket>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadTestBucket {
+    pub le: Option<u64>,
+    pub count: u64,
+}
+
+impl LoadTestStage {
+    pub const ALL: [LoadTestStage; 4] = [
+        LoadTestStage::Receive,
+        LoadTestStage::Filter,
+        LoadTestStage::Encrypt,
+        LoadTestStage::Store,
+    ];
+}
+
+impl LoadTestStats {
+    pub fn record(&self, stage: LoadTestStage, elapsed: Duration) {
+        let mut samples = self.samples.lock();
+        let samples = &mut samples[stage as usize];
+        if samples.len() < MAX_SAMPLES {
+            samples.push(elapsed.as_micros() as u64);
+        }
+    }
+
+    pub fn reset(&self) {
+        for samples in self.samples.lock().iter_mut() {
+            samples.clear();
+        }
+    }
+
+    pub fn report(&self) -> Vec<LoadTestStageReport> {
+        let samples = self.samples.lock().clone();
+        LoadTestStage::ALL
+            .into_iter()
+            .zip(samples)
+            .map(|(stage, samples)| {
+                let mut report = LoadTestStageReport::from_samples(samples);
+                report.stage = Some(stage);
+                report
+            })
+            .collect()
+    }
+}
+
+impl LoadTestStageReport {
+    /// Builds a report from latency samples expressed in microseconds.
+    /// Histogram bucket bounds are expressed in milliseconds.
+    pub fn from_samples(mut samples: Vec<u64>) -> Self {
+        if samples.is_empty() {
+            return LoadTestStageReport::default();
+        }
+        samples.sort_unstable();
+
+        let count = samples.len();
+        let percentile = |p: usize| samples[((count * p).div_ceil(100)).saturating_sub(1)];
+        let mut histogram = HISTOGRAM_BUCKETS
+            .iter()
+            .map(|le| LoadTestBucket {
+                le: Some(*le),
+                count: 0,
+            })
+            .chain([LoadTestBucket { le: None, count: 0 }])
+            .collect::<Vec<_>>();
+        for sample in &samples {
+            let idx = HISTOGRAM_BUCKETS
+                .iter()
+                .position(|le| *sample <= le * 1000)
+                .unwrap_or(HISTOGRAM_BUCKETS.len());
+            histogram[idx].count += 1;
+        }
+
+        LoadTestStageReport {
+            stage: None,
+            count: count as u64,
+            min: samples[0],
+            max: samples[count - 1],
+            mean: samples.iter().sum::<u64>() / count as u64,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            histogram,
+        }
+    }
+}
+
+impl Server {
+    #[inline(always)]
+    pub fn record_load_test_stage(&self, stage: LoadTestStage, elapsed: Duration) {
+        if self.core.smtp.load_test.enable {
+            self.inner.data.load_test.record(stage, elapsed);
+        }
+    }
+}
@@ -6,7 +6,7 @@
 
 use prometheus::{
     TextEncoder,
-    proto::{Bucket, Counter, Gauge, Histogram, Metric, MetricFamily, MetricType},
+    proto::{Bucket, Counter, Gauge, Histogram, LabelPair, Metric, MetricFamily, MetricType},
 };
 use trc::{Collector, atomics::histogram::AtomicHistogram};
 
@@ -52,6 +52,52 @@ impl Server {
             metrics.push(metric);
         }
 
+        // Add TLS handshake counters, these are labeled so they are not tracked as events
+        {
+            let stats = self.inner.data.tls_handshakes.lock();
+            if !stats.handshakes.is_empty() {
+                let mut metric = MetricFamily::default();
+                metric.set_name("tls_handshakes".into());
+                metric
+                    .set_help("TLS handshakes by listener, protocol version and SNI match".into());
+                metric.set_field_type(MetricType::COUNTER);
+                metric.set_metric(
+                    stats
+                        .handshakes
+                        .iter()
+                        .map(|(key, value)| {
+                            let mut m = new_counter(*value);
+                            m.set_label(vec![
+                                new_label("listener", &key.listener_id),
+                                new_label("version", key.version),
+                                new_label("sni", key.sni.as_str()),
+                            ]);
+                            m
+                        })
+                        .collect(),
+                );
+                metrics.push(metric);
+            }
+            if !stats.acme_challenges.is_empty() {
+                let mut metric = MetricFamily::default();
+                metric.set_name("tls_acme_challenge_handshakes".into());
+                metric.set_help("TLS-ALPN-01 challenge handshakes by listener".into());
+                metric.set_field_type(MetricType::COUNTER);
+                metric.set_metric(
+                    stats
+                        .acme_challenges
+                        .iter()
+                        .map(|(listener_id, value)| {
+                            let mut m = new_counter(*value);
+                            m.set_label(vec![new_label("listener", listener_id)]);
+                            m
+                        })
+                        .collect(),
+                );
+                metrics.push(metric);
+            }
+        }
+
         TextEncoder::new().encode_to_string(&metrics).map_err(|e| {
             trc::EventType::Telemetry(trc::TelemetryEvent::OtelExporterError).reason(e)
         })
@@ -79,6 +125,13 @@ fn new_counter(value: u64) -> Metric {
     m
 }
 
+fn new_label(name: &str, value: &str) -> LabelPair {
+    let mut label = LabelPair::default();
+    label.set_name(name.into());
+    label.set_value(value.into());
+    label
+}
+
 fn new_gauge(value: u64) -> Metric {
     let mut m = Metric::default();
     let mut gauge = Gauge::default();
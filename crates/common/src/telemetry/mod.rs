@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod load_test;
 pub mod metrics;
 pub mod tracers;
 pub mod webhooks;
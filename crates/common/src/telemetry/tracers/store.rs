@@ -16,7 +16,7 @@ use store::{
     write::{BatchBuilder, TelemetryClass, ValueClass, key::DeserializeBigEndian},
 };
 use trc::{
-    AddContext, AuthEvent, Event, EventDetails, EventType, Key, MessageIngestEvent,
+    AddContext, AuthEvent, EncryptEvent, Event, EventDetails, EventType, Key, MessageIngestEvent,
     OutgoingReportEvent, QueueEvent, Value,
     ipc::subscriber::SubscriberBuilder,
     serializers::binary::{deserialize_events, serialize_events},
@@ -374,6 +374,7 @@ impl StoreTracer {
                             | MessageIngestEvent::Spam
                             | MessageIngestEvent::Duplicate
                             | MessageIngestEvent::Error
+                            | MessageIngestEvent::EncryptionFailed
                     ) | EventType::Smtp(_)
                         | EventType::Delivery(_)
                         | EventType::MtaSts(_)
@@ -394,6 +395,8 @@ impl StoreTracer {
                                 | QueueEvent::RateLimitExceeded
                                 | QueueEvent::ConcurrencyLimitExceeded
                                 | QueueEvent::QuotaExceeded
+                                | QueueEvent::Quarantined
+                                | QueueEvent::QuarantineReleased
                         )
                         | EventType::Limit(_)
                         | EventType::Tls(_)
@@ -419,11 +422,14 @@ impl StoreTracer {
                                 | AuthEvent::Failed
                                 | AuthEvent::TooManyAttempts
                                 | AuthEvent::Error
+                                | AuthEvent::ProtocolDisabled
+                                | AuthEvent::ProtocolsChanged
                         )
                         | EventType::Sieve(_)
                         | EventType::Milter(_)
                         | EventType::MtaHook(_)
                         | EventType::Security(_)
+                        | EventType::Encrypt(EncryptEvent::Failed)
                 )
         })
     }
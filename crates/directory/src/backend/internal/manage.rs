@@ -10,8 +10,9 @@ use super::{
 };
 use crate::{
     MemberOf, Permission, PermissionGrant, Permissions, Principal, PrincipalData, PrincipalQuota,
-    QueryBy, ROLE_ADMIN, ROLE_TENANT_ADMIN, ROLE_USER, Type, backend::RcptType,
-    core::principal::build_search_index,
+    QueryBy, ROLE_ADMIN, ROLE_TENANT_ADMIN, ROLE_USER, Type,
+    backend::RcptType,
+    core::{principal::build_search_index, protocols::Protocol, senders::SenderGrant},
 };
 use ahash::{AHashMap, AHashSet};
 use compact_str::CompactString;
@@ -43,6 +44,20 @@ pub struct UpdatePrincipal<'x> {
     create_domains: bool,
 }
 
+// Principals are listed ordered by name, either skipping an offset or
+// resuming after the last name returned by a previous call
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ListPrincipals<'x> {
+    pub filter: Option<&'x str>,
+    pub domain: Option<&'x str>,
+    pub tenant_id: Option<u32>,
+    pub types: &'x [Type],
+    pub fetch: bool,
+    pub after: Option<&'x str>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
 #[derive(Debug, Default, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct ChangedPrincipals(AHashMap<u32, ChangedPrincipal>);
@@ -86,6 +101,10 @@ pub trait ManageDirectory: Sized {
         page: usize,
         limit: usize,
     ) -> trc::Result<PrincipalList<Principal>>;
+    async fn query_principals(
+        &self,
+        params: ListPrincipals<'_>,
+    ) -> trc::Result<PrincipalList<Principal>>;
     async fn count_principals(
         &self,
         filter: Option<&str>,
@@ -386,6 +405,16 @@ impl ManageDirectory for Store {
                 .data
                 .push(PrincipalData::ExternalMembers(urls));
         }
+        if let Some(senders) = principal_set.take_str_array(PrincipalField::Senders) {
+            principal_create
+                .data
+                .push(PrincipalData::Senders(validate_senders(senders)?));
+        }
+        if let Some(protocols) = principal_set.take_str_array(PrincipalField::EnabledProtocols) {
+            principal_create
+                .data
+                .push(PrincipalData::Protocols(validate_protocols(protocols)?));
+        }
         if let Some(quotas) = principal_set.take_int_array(PrincipalField::Quota) {
             let mut principal_quotas = Vec::new();
 
@@ -531,6 +560,12 @@ impl ManageDirectory for Store {
             .await
             .caused_by(trc::location!())?;
         principal_create.id = principal_id;
+
+        // Make sure the list does not forward back to itself
+        if principal_create.typ == Type::List {
+            validate_list_cycles(self, &principal_create).await?;
+        }
+
         let mut batch = BatchBuilder::new();
         let pinfo_name = PrincipalInfo::new(principal_id, principal_create.typ, tenant_id);
         let pinfo_email = PrincipalInfo::new(principal_id, principal_create.typ, None);
@@ -966,6 +1001,13 @@ impl ManageDirectory for Store {
             Type::Role => &[Type::Role][..],
         };
         let mut valid_domains = AHashSet::new();
+        let validate_cycles = principal_type == Type::List
+            && changes.iter().any(|c| {
+                matches!(
+                    c.field,
+                    PrincipalField::Emails | PrincipalField::ExternalMembers
+                )
+            });
 
         // Process changes
         for change in changes {
@@ -1773,6 +1815,125 @@ impl ManageDirectory for Store {
                         ));
                     }
                 }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::Senders,
+                    PrincipalValue::StringList(items),
+                ) => {
+                    principal
+                        .data
+                        .retain(|v| !matches!(v, PrincipalData::Senders(_)));
+                    if !items.is_empty() {
+                        principal
+                            .data
+                            .push(PrincipalData::Senders(validate_senders(items)?));
+                    }
+
+                    // Sender grants changed, update changed principals
+                    changed_principals.add_change(principal_id, principal_type, change.field);
+                }
+                (
+                    PrincipalAction::AddItem,
+                    PrincipalField::Senders,
+                    PrincipalValue::String(item),
+                ) => {
+                    let item = validate_senders(vec![item])?.pop().unwrap();
+                    if let Some(senders) = principal.data.iter_mut().find_map(|v| {
+                        if let PrincipalData::Senders(senders) = v {
+                            Some(senders)
+                        } else {
+                            None
+                        }
+                    }) {
+                        if !senders.contains(&item) {
+                            senders.push(item);
+                        }
+                    } else {
+                        principal.data.push(PrincipalData::Senders(vec![item]));
+                    }
+
+                    // Sender grants changed, update changed principals
+                    changed_principals.add_change(principal_id, principal_type, change.field);
+                }
+                (
+                    PrincipalAction::RemoveItem,
+                    PrincipalField::Senders,
+                    PrincipalValue::String(item),
+                ) => {
+                    let item = SenderGrant::parse(&item)
+                        .map(|grant| grant.to_string())
+                        .unwrap_or(item);
+                    for data in &mut principal.data {
+                        if let PrincipalData::Senders(senders) = data {
+                            senders.retain(|v| *v != item);
+                            break;
+                        }
+                    }
+
+                    // Sender grants changed, update changed principals
+                    changed_principals.add_change(principal_id, principal_type, change.field);
+                }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::EnabledProtocols,
+                    PrincipalValue::StringList(items),
+                ) => {
+                    principal
+                        .data
+                        .retain(|v| !matches!(v, PrincipalData::Protocols(_)));
+                    if !items.is_empty() {
+                        principal
+                            .data
+                            .push(PrincipalData::Protocols(validate_protocols(items)?));
+                    }
+
+                    // Enabled protocols changed, update changed principals
+                    changed_principals.add_change(principal_id, principal_type, change.field);
+                }
+                (
+                    PrincipalAction::AddItem,
+                    PrincipalField::EnabledProtocols,
+                    PrincipalValue::String(item),
+                ) => {
+                    let item = validate_protocols(vec![item])?.pop().unwrap();
+                    if let Some(protocols) = principal.data.iter_mut().find_map(|v| {
+                        if let PrincipalData::Protocols(protocols) = v {
+                            Some(protocols)
+                        } else {
+                            None
+                        }
+                    }) {
+                        if !protocols.contains(&item) {
+                            protocols.push(item);
+                        }
+                    } else {
+                        principal.data.push(PrincipalData::Protocols(vec![item]));
+                    }
+
+                    // Enabled protocols changed, update changed principals
+                    changed_principals.add_change(principal_id, principal_type, change.field);
+                }
+                (
+                    PrincipalAction::RemoveItem,
+                    PrincipalField::EnabledProtocols,
+                    PrincipalValue::String(item),
+                ) => {
+                    let item = Protocol::parse(&item)
+                        .map(|protocol| protocol.as_str().to_string())
+                        .unwrap_or(item);
+                    principal.data.retain_mut(|data| {
+                        if let PrincipalData::Protocols(protocols) = data {
+                            protocols.retain(|v| *v != item);
+                            // An empty list means all protocols are enabled
+                            !protocols.is_empty()
+                        } else {
+                            true
+                        }
+                    });
+
+                    // Enabled protocols changed, update changed principals
+                    changed_principals.add_change(principal_id, principal_type, change.field);
+                }
                 (PrincipalAction::Set, PrincipalField::Urls, PrincipalValue::StringList(items)) => {
                     principal
                         .data
@@ -1865,6 +2026,10 @@ impl ManageDirectory for Store {
             }
         }
 
+        if validate_cycles {
+            validate_list_cycles(self, &principal).await?;
+        }
+
         if update_principal {
             build_search_index(
                 &mut batch,
@@ -1902,7 +2067,24 @@ impl ManageDirectory for Store {
         page: usize,
         limit: usize,
     ) -> trc::Result<PrincipalList<Principal>> {
-        let filter = if let Some(filter) = filter.filter(|f| !f.trim().is_empty()) {
+        self.query_principals(ListPrincipals {
+            filter,
+            tenant_id,
+            types,
+            fetch,
+            offset: page.saturating_sub(1) * limit,
+            limit,
+            ..Default::default()
+        })
+        .await
+    }
+
+    async fn query_principals(
+        &self,
+        params: ListPrincipals<'_>,
+    ) -> trc::Result<PrincipalList<Principal>> {
+        let mut offset = params.offset;
+        let mut filter = if let Some(filter) = params.filter.filter(|f| !f.trim().is_empty()) {
             let mut matches = RoaringBitmap::new();
 
             for token in WordTokenizer::new(filter, MAX_TOKEN_LENGTH) {
@@ -1954,14 +2136,59 @@ impl ManageDirectory for Store {
             None
         };
 
+        // Principals with at least one address in the domain
+        if let Some(domain) = params.domain.filter(|d| !d.trim().is_empty()) {
+            let suffix = format!("@{}", domain.trim().to_lowercase());
+            let mut domain_matches = RoaringBitmap::new();
+            self.iterate(
+                IterateParams::new(
+                    ValueKey::from(ValueClass::Directory(DirectoryClass::EmailToId(vec![0u8]))),
+                    ValueKey::from(ValueClass::Directory(DirectoryClass::EmailToId(vec![
+                            u8::MAX;
+                            10
+                        ]))),
+                ),
+                |key, value| {
+                    if key
+                        .get(1..)
+                        .is_some_and(|email| email.ends_with(suffix.as_bytes()))
+                    {
+                        domain_matches.insert(
+                            PrincipalInfo::deserialize(value)
+                                .caused_by(trc::location!())?
+                                .id,
+                        );
+                    }
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+            if let Some(matches) = &mut filter {
+                *matches &= domain_matches;
+            } else {
+                filter = Some(domain_matches);
+            }
+            if filter.as_ref().is_some_and(|matches| matches.is_empty()) {
+                return Ok(PrincipalList {
+                    total: 0,
+                    items: vec![],
+                });
+            }
+        }
+
         let from_key = ValueKey::from(ValueClass::Directory(DirectoryClass::NameToId(vec![])));
         let to_key = ValueKey::from(ValueClass::Directory(DirectoryClass::NameToId(vec![
             u8::MAX;
             10
         ])));
 
-        let max_items = if limit > 0 { limit } else { usize::MAX };
-        let mut offset = page.saturating_sub(1) * limit;
+        let max_items = if params.limit > 0 {
+            params.limit
+        } else {
+            usize::MAX
+        };
         let mut result = PrincipalList {
             items: Vec::new(),
             total: 0,
@@ -1971,21 +2198,22 @@ impl ManageDirectory for Store {
             |key, value| {
                 let pt = PrincipalInfo::deserialize(value).caused_by(trc::location!())?;
 
-                if (types.is_empty() || types.contains(&pt.typ))
-                    && pt.has_tenant_access(tenant_id)
+                if (params.types.is_empty() || params.types.contains(&pt.typ))
+                    && pt.has_tenant_access(params.tenant_id)
                     && filter.as_ref().is_none_or(|filter| filter.contains(pt.id))
                 {
+                    let name = key.get(1..).unwrap_or_default();
                     result.total += 1;
-                    if offset == 0 {
-                        if result.items.len() < max_items {
-                            let mut principal = Principal::new(pt.id, pt.typ);
-                            principal.name =
-                                String::from_utf8_lossy(key.get(1..).unwrap_or_default())
-                                    .into_owned();
-                            result.items.push(principal);
+                    if params.after.is_none_or(|after| name > after.as_bytes()) {
+                        if offset == 0 {
+                            if result.items.len() < max_items {
+                                let mut principal = Principal::new(pt.id, pt.typ);
+                                principal.name = String::from_utf8_lossy(name).into_owned();
+                                result.items.push(principal);
+                            }
+                        } else {
+                            offset -= 1;
                         }
-                    } else {
-                        offset -= 1;
                     }
                 }
 
@@ -1995,23 +2223,21 @@ impl ManageDirectory for Store {
         .await
         .caused_by(trc::location!())?;
 
-        if fetch && !result.items.is_empty() {
+        if params.fetch && !result.items.is_empty() {
             let mut items = Vec::with_capacity(result.items.len());
 
             for principal in result.items {
                 items.push(
-                    self.query(QueryBy::Id(principal.id), fetch)
+                    self.query(QueryBy::Id(principal.id), params.fetch)
                         .await
                         .caused_by(trc::location!())?
                         .ok_or_else(|| not_found(principal.name().to_string()))?,
                 );
             }
             result.items = items;
-
-            Ok(result)
-        } else {
-            Ok(result)
         }
+
+        Ok(result)
     }
 
     async fn count_principals(
@@ -2194,6 +2420,16 @@ impl ManageDirectory for Store {
                         result.set(PrincipalField::Urls, compact_strings);
                     }
                 }
+                PrincipalData::Senders(compact_strings) => {
+                    if fields.is_empty() || fields.contains(&PrincipalField::Senders) {
+                        result.set(PrincipalField::Senders, compact_strings);
+                    }
+                }
+                PrincipalData::Protocols(compact_strings) => {
+                    if fields.is_empty() || fields.contains(&PrincipalField::EnabledProtocols) {
+                        result.set(PrincipalField::EnabledProtocols, compact_strings);
+                    }
+                }
                 PrincipalData::PrincipalQuota(principal_quotas_) => {
                     principal_quotas = principal_quotas_;
                 }
@@ -2467,6 +2703,74 @@ fn validate_member_of(
     }
 }
 
+// Rejects lists that would forward messages back to themselves through the
+// external members of other lists
+async fn validate_list_cycles(store: &Store, list: &Principal) -> trc::Result<()> {
+    let external_members = |principal: &Principal| {
+        principal
+            .data
+            .iter()
+            .find_map(|data| {
+                if let PrincipalData::ExternalMembers(members) = data {
+                    Some(members.clone())
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_default()
+    };
+
+    let mut seen = AHashSet::from_iter([list.id]);
+    let mut pending = vec![(
+        external_members(list),
+        vec![
+            list.emails
+                .first()
+                .map(|email| email.as_str())
+                .unwrap_or_else(|| list.name())
+                .to_string(),
+        ],
+    )];
+    while let Some((addresses, path)) = pending.pop() {
+        for address in addresses {
+            let address = address.to_lowercase();
+            if list.emails.contains(&address) {
+                return Err(error(
+                    "Mail loop detected",
+                    format!(
+                        "Forwarding cycle detected: {} -> {address}",
+                        path.join(" -> ")
+                    )
+                    .into(),
+                ));
+            }
+
+            // Addresses removed from the list still point to it until the update is written
+            if let Some(next_id) = store
+                .get_value::<PrincipalInfo>(ValueKey::from(ValueClass::Directory(
+                    DirectoryClass::EmailToId(address.as_bytes().to_vec()),
+                )))
+                .await
+                .caused_by(trc::location!())?
+                .filter(|pinfo| pinfo.typ == Type::List && seen.insert(pinfo.id))
+                .map(|pinfo| pinfo.id)
+            {
+                if let Some(next) = store
+                    .get_principal(next_id)
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    let mut path = path.clone();
+                    path.push(address);
+                    pending.push((external_members(&next), path));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
 impl ChangedPrincipals {
     pub fn new() -> Self {
         Self::default()
@@ -2492,7 +2796,9 @@ impl ChangedPrincipals {
                     | PrincipalField::Tenant
                     | PrincipalField::Roles
                     | PrincipalField::EnabledPermissions
-                    | PrincipalField::DisabledPermissions,
+                    | PrincipalField::DisabledPermissions
+                    | PrincipalField::Senders
+                    | PrincipalField::EnabledProtocols,
             ) | (
                 Type::Tenant | Type::Role | Type::ApiKey | Type::OauthClient,
                 PrincipalField::MemberOf
@@ -2513,7 +2819,7 @@ impl ChangedPrincipals {
                     (
                         PrincipalField::EnabledPermissions | PrincipalField::DisabledPermissions,
                         Type::Role | Type::Tenant
-                    )
+                    ) | (PrincipalField::Senders, Type::Group)
                 ));
         }
     }
@@ -2605,6 +2911,42 @@ pub fn err_missing(field: impl Into<trc::Value>) -> trc::Error {
     trc::ManageEvent::MissingParameter.ctx(trc::Key::Key, field)
 }
 
+fn validate_senders(items: Vec<String>) -> trc::Result<Vec<String>> {
+    let mut senders = Vec::with_capacity(items.len());
+    for item in items {
+        if let Some(grant) = SenderGrant::parse(&item) {
+            let grant = grant.to_string();
+            if !senders.contains(&grant) {
+                senders.push(grant);
+            }
+        } else {
+            return Err(error(
+                "Invalid sender",
+                format!("{item:?} is not a valid address or domain").into(),
+            ));
+        }
+    }
+    Ok(senders)
+}
+
+fn validate_protocols(items: Vec<String>) -> trc::Result<Vec<String>> {
+    let mut protocols = Vec::with_capacity(items.len());
+    for item in items {
+        if let Some(protocol) = Protocol::parse(&item) {
+            let protocol = protocol.as_str().to_string();
+            if !protocols.contains(&protocol) {
+                protocols.push(protocol);
+            }
+        } else {
+            return Err(error(
+                "Invalid protocol",
+                format!("{item:?} is not a supported protocol").into(),
+            ));
+        }
+    }
+    Ok(protocols)
+}
+
 pub fn err_exists(field: impl Into<trc::Value>, value: impl Into<trc::Value>) -> trc::Error {
     trc::ManageEvent::AlreadyExists
         .ctx(trc::Key::Key, field)
@@ -111,6 +111,8 @@ pub enum PrincipalField {
     Picture,
     Urls,
     ExternalMembers,
+    Senders,
+    EnabledProtocols,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -198,6 +200,8 @@ impl PrincipalField {
             PrincipalField::Picture => 14,
             PrincipalField::Urls => 15,
             PrincipalField::ExternalMembers => 16,
+            PrincipalField::Senders => 17,
+            PrincipalField::EnabledProtocols => 18,
         }
     }
 
@@ -220,6 +224,8 @@ impl PrincipalField {
             14 => Some(PrincipalField::Picture),
             15 => Some(PrincipalField::Urls),
             16 => Some(PrincipalField::ExternalMembers),
+            17 => Some(PrincipalField::Senders),
+            18 => Some(PrincipalField::EnabledProtocols),
             _ => None,
         }
     }
@@ -243,6 +249,8 @@ impl PrincipalField {
             PrincipalField::Picture => "picture",
             PrincipalField::Urls => "urls",
             PrincipalField::ExternalMembers => "externalMembers",
+            PrincipalField::Senders => "senders",
+            PrincipalField::EnabledProtocols => "enabledProtocols",
         }
     }
 
@@ -265,6 +273,8 @@ impl PrincipalField {
             "picture" => Some(PrincipalField::Picture),
             "urls" => Some(PrincipalField::Urls),
             "externalMembers" => Some(PrincipalField::ExternalMembers),
+            "senders" => Some(PrincipalField::Senders),
+            "enabledProtocols" => Some(PrincipalField::EnabledProtocols),
             _ => None,
         }
     }
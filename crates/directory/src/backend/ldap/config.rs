@@ -88,6 +88,10 @@ impl LdapDirectory {
                 .values((&prefix, "attributes.email-alias"))
                 .map(|(_, v)| v.to_string())
                 .collect(),
+            attr_protocols: config
+                .values((&prefix, "attributes.protocols"))
+                .map(|(_, v)| v.to_string())
+                .collect(),
             attrs_principal: vec!["objectClass".to_string()],
         };
 
@@ -100,6 +104,7 @@ impl LdapDirectory {
             &mappings.attr_groups,
             &mappings.attr_email_address,
             &mappings.attr_email_alias,
+            &mappings.attr_protocols,
         ] {
             mappings.attrs_principal.extend(attr.iter().cloned());
         }
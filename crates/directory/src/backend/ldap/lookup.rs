@@ -18,6 +18,7 @@ use crate::{
             manage::{self, ManageDirectory, UpdatePrincipal},
         },
     },
+    core::protocols::Protocol,
 };
 
 use super::{LdapDirectory, LdapMappings};
@@ -390,6 +391,11 @@ impl LdapMappings {
                 if let Ok(quota) = value.into_iter().next().unwrap_or_default().parse::<u64>() {
                     principal.quota = quota.into();
                 }
+            } else if self.attr_protocols.contains(&attr) {
+                let protocols = Protocol::parse_list(value);
+                if !protocols.is_empty() {
+                    principal.data.push(PrincipalData::Protocols(protocols));
+                }
             } else if self.attr_type.contains(&attr) {
                 for value in value {
                     match value.to_ascii_lowercase().as_str() {
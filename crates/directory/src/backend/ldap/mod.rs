@@ -33,6 +33,7 @@ pub struct LdapMappings {
     attr_email_address: Vec<String>,
     attr_email_alias: Vec<String>,
     attr_quota: Vec<String>,
+    attr_protocols: Vec<String>,
     attrs_principal: Vec<String>,
 }
 
@@ -10,6 +10,7 @@ use utils::config::{Config, utils::AsKey};
 use crate::{
     Principal, PrincipalData, ROLE_ADMIN, ROLE_USER, Type,
     backend::internal::manage::ManageDirectory,
+    core::{protocols::Protocol, senders::SenderGrant},
 };
 
 use super::{EmailType, MemoryDirectory};
@@ -133,6 +134,46 @@ impl MemoryDirectory {
                 }
             }
 
+            // Parse sender grants
+            let mut senders = Vec::new();
+            for sender in config
+                .values((prefix.as_str(), "principals", lookup_id, "sender"))
+                .map(|(_, s)| s.to_string())
+                .collect::<Vec<_>>()
+            {
+                if let Some(grant) = SenderGrant::parse(&sender) {
+                    senders.push(grant.to_string());
+                } else {
+                    config.new_parse_error(
+                        (prefix.as_str(), "principals", lookup_id, "sender"),
+                        format!("Invalid sender {sender:?}"),
+                    );
+                }
+            }
+            if !senders.is_empty() {
+                principal.data.push(PrincipalData::Senders(senders));
+            }
+
+            // Parse enabled protocols
+            let mut protocols = Vec::new();
+            for protocol in config
+                .values((prefix.as_str(), "principals", lookup_id, "protocol"))
+                .map(|(_, s)| s.to_string())
+                .collect::<Vec<_>>()
+            {
+                if let Some(protocol) = Protocol::parse(&protocol) {
+                    protocols.push(protocol.as_str().to_string());
+                } else {
+                    config.new_parse_error(
+                        (prefix.as_str(), "principals", lookup_id, "protocol"),
+                        format!("Invalid protocol {protocol:?}"),
+                    );
+                }
+            }
+            if !protocols.is_empty() {
+                principal.data.push(PrincipalData::Protocols(protocols));
+            }
+
             principal.name = name.as_str().into();
             for (_, secret) in config.values((prefix.as_str(), "principals", lookup_id, "secret")) {
                 principal.secrets.push(secret.into());
@@ -48,6 +48,10 @@ impl SqlDirectory {
                 .value((&prefix, "columns.class"))
                 .unwrap_or_default()
                 .to_string(),
+            column_protocols: config
+                .value((&prefix, "columns.protocols"))
+                .unwrap_or_default()
+                .to_string(),
             ..Default::default()
         };
 
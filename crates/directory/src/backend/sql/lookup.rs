@@ -14,6 +14,7 @@ use crate::{
             manage::{self, ManageDirectory, UpdatePrincipal},
         },
     },
+    core::protocols::Protocol,
 };
 
 use mail_send::Credentials;
@@ -302,6 +303,13 @@ impl SqlMappings {
                     if let Value::Integer(quota) = value {
                         principal.quota = (quota as u64).into();
                     }
+                } else if name.eq_ignore_ascii_case(&self.column_protocols) {
+                    if let Value::Text(text) = value {
+                        let protocols = Protocol::parse_list([text.as_ref()]);
+                        if !protocols.is_empty() {
+                            principal.data.push(PrincipalData::Protocols(protocols));
+                        }
+                    }
                 }
             }
         }
@@ -27,4 +27,5 @@ pub(crate) struct SqlMappings {
     column_email: String,
     column_quota: String,
     column_type: String,
+    column_protocols: String,
 }
@@ -10,7 +10,9 @@ pub mod cache;
 pub mod config;
 pub mod dispatch;
 pub mod principal;
+pub mod protocols;
 pub mod secret;
+pub mod senders;
 
 impl Permission {
     pub fn description(&self) -> &'static str {
@@ -246,6 +248,8 @@ impl Permission {
             Permission::DavCalQuery => "Search for calendar entries matching criteria",
             Permission::DavCalMultiGet => "Retrieve multiple calendar entries in a single request",
             Permission::DavCalFreeBusyQuery => "Query free/busy time information for scheduling",
+            Permission::ManageIngestHooks => "Manage mailbox ingestion webhooks",
+            Permission::JmapRemoteContent => "Load remote email content through the server",
         }
     }
 }
@@ -293,6 +293,18 @@ impl Principal {
             }
         }
 
+        let protocols = external.enabled_protocols();
+        if !protocols.is_empty() && protocols != self.enabled_protocols() {
+            let protocols = protocols.to_vec();
+            self.data
+                .retain(|v| !matches!(v, PrincipalData::Protocols(_)));
+            self.data.push(PrincipalData::Protocols(protocols.clone()));
+            updates.push(PrincipalUpdate::set(
+                PrincipalField::EnabledProtocols,
+                PrincipalValue::StringList(protocols),
+            ));
+        }
+
         if external.quota.is_some() && self.quota != external.quota {
             self.quota = external.quota;
             updates.push(PrincipalUpdate::set(
@@ -1122,7 +1134,9 @@ impl<'de> serde::Deserialize<'de> for PrincipalSet {
                         | PrincipalField::EnabledPermissions
                         | PrincipalField::DisabledPermissions
                         | PrincipalField::Urls
-                        | PrincipalField::ExternalMembers => {
+                        | PrincipalField::ExternalMembers
+                        | PrincipalField::Senders
+                        | PrincipalField::EnabledProtocols => {
                             match map.next_value::<StringOrMany>()? {
                                 StringOrMany::One(v) => PrincipalValue::StringList(vec![v]),
                                 StringOrMany::Many(v) => {
@@ -1407,6 +1421,8 @@ impl Permission {
                 | Permission::DavCalQuery
                 | Permission::DavCalMultiGet
                 | Permission::DavCalFreeBusyQuery
+                | Permission::ManageIngestHooks
+                | Permission::JmapRemoteContent
         )
     }
 
@@ -0,0 +1,73 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use crate::{Principal, PrincipalData};
+
+/// A protocol a principal can be restricted to. Principals without a list of
+/// enabled protocols are allowed to authenticate using any of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Imap,
+    Pop3,
+    SmtpSubmission,
+    Jmap,
+    ManageSieve,
+    Dav,
+}
+
+impl Protocol {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "imap" => Some(Protocol::Imap),
+            "pop3" => Some(Protocol::Pop3),
+            "smtp-submission" => Some(Protocol::SmtpSubmission),
+            "jmap" => Some(Protocol::Jmap),
+            "managesieve" => Some(Protocol::ManageSieve),
+            "dav" => Some(Protocol::Dav),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::Imap => "imap",
+            Protocol::Pop3 => "pop3",
+            Protocol::SmtpSubmission => "smtp-submission",
+            Protocol::Jmap => "jmap",
+            Protocol::ManageSieve => "managesieve",
+            Protocol::Dav => "dav",
+        }
+    }
+
+    // External directories may return the protocols as a single comma separated value
+    pub fn parse_list(values: impl IntoIterator<Item = impl AsRef<str>>) -> Vec<String> {
+        let mut protocols = Vec::new();
+        for value in values {
+            for protocol in value.as_ref().split(',').filter_map(Protocol::parse) {
+                let protocol = protocol.as_str().to_string();
+                if !protocols.contains(&protocol) {
+                    protocols.push(protocol);
+                }
+            }
+        }
+        protocols
+    }
+}
+
+impl Principal {
+    pub fn enabled_protocols(&self) -> &[String] {
+        self.data
+            .iter()
+            .find_map(|item| {
+                if let PrincipalData::Protocols(items) = item {
+                    items.as_slice().into()
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,101 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::fmt::Display;
+
+use utils::sanitize_email;
+
+use crate::{Principal, PrincipalData};
+
+/// An entry of the sender authorization matrix, granting a principal permission
+/// to use an address (`user@example.org`) or a whole domain (`@example.org`) as
+/// envelope sender and in the From header. Entries ending with `;dkim` request
+/// messages to be signed with the DKIM keys of the granted domain.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SenderGrant {
+    pub address: String,
+    pub dkim: bool,
+}
+
+impl SenderGrant {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split(';');
+        let address = parts.next()?.trim();
+        let mut dkim = false;
+        for option in parts {
+            if option.trim().eq_ignore_ascii_case("dkim") {
+                dkim = true;
+            } else {
+                return None;
+            }
+        }
+
+        let address = if let Some(domain) = address.strip_prefix('@').or_else(|| {
+            if !address.contains('@') {
+                Some(address)
+            } else {
+                None
+            }
+        }) {
+            sanitize_email(&format!("postmaster@{domain}"))
+                .map(|address| address.strip_prefix("postmaster").unwrap().to_string())?
+        } else {
+            sanitize_email(address)?
+        };
+
+        Some(SenderGrant { address, dkim })
+    }
+
+    pub fn is_domain(&self) -> bool {
+        self.address.starts_with('@')
+    }
+
+    pub fn domain(&self) -> &str {
+        self.address
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default()
+    }
+
+    pub fn matches(&self, address: &str) -> bool {
+        if self.is_domain() {
+            address.ends_with(self.address.as_str())
+        } else {
+            address == self.address
+        }
+    }
+}
+
+impl Display for SenderGrant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.dkim {
+            write!(f, "{};dkim", self.address)
+        } else {
+            f.write_str(&self.address)
+        }
+    }
+}
+
+impl Principal {
+    pub fn senders(&self) -> &[String] {
+        self.data
+            .iter()
+            .find_map(|item| {
+                if let PrincipalData::Senders(items) = item {
+                    items.as_slice().into()
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn sender_grants(&self) -> impl Iterator<Item = SenderGrant> + '_ {
+        self.senders()
+            .iter()
+            .filter_map(|sender| SenderGrant::parse(sender))
+    }
+}
@@ -57,6 +57,8 @@ pub enum PrincipalData {
     Urls(Vec<String>),
     PrincipalQuota(Vec<PrincipalQuota>),
     Language(String),
+    Senders(Vec<String>),
+    Protocols(Vec<String>),
 }
 
 #[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Debug, Clone, PartialEq, Eq)]
@@ -373,6 +375,9 @@ pub enum Permission {
     DavCalQuery,
     DavCalMultiGet,
     DavCalFreeBusyQuery,
+
+    ManageIngestHooks,
+    JmapRemoteContent,
     // WARNING: add new ids at the end (TODO: use static ids)
 }
 
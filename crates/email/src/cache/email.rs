@@ -6,14 +6,18 @@
 
 use crate::message::metadata::{ArchivedMessageData, MessageData};
 use common::{
-    MessageCache, MessageStoreCache, MessageUidCache, MessagesCache, Server, auth::AccessToken,
-    sharing::EffectiveAcl,
+    MailboxCounters, MessageCache, MessageStoreCache, MessageUidCache, MessagesCache, Server,
+    auth::AccessToken, sharing::EffectiveAcl,
 };
-use jmap_proto::types::{
-    acl::Acl,
-    collection::Collection,
-    keyword::{Keyword, OTHER},
+use jmap_proto::{
+    response::status::MailboxCounts,
+    types::{
+        acl::Acl,
+        collection::Collection,
+        keyword::{Keyword, OTHER, SEEN},
+    },
 };
+use std::collections::hash_map::Entry;
 use store::{ahash::AHashMap, roaring::RoaringBitmap, write::Archive};
 use trc::AddContext;
 use utils::map::bitmap::Bitmap;
@@ -23,16 +27,23 @@ pub(crate) async fn update_email_cache(
     account_id: u32,
     changed_ids: &AHashMap<u32, bool>,
     store_cache: &MessageStoreCache,
+    change_id: u64,
 ) -> trc::Result<MessagesCache> {
     let mut new_cache = MessagesCache {
         index: AHashMap::with_capacity(store_cache.emails.items.len()),
         items: Vec::with_capacity(store_cache.emails.items.len()),
         size: 0,
-        change_id: 0,
+        change_id,
         keywords: store_cache.emails.keywords.clone(),
+        counters: store_cache.emails.counters.clone(),
     };
 
     for (document_id, is_update) in changed_ids {
+        // Remove the previous version from the mailbox counters
+        if let Some(item) = store_cache.email_by_id(document_id) {
+            update_counters(&mut new_cache.counters, item, change_id, false);
+        }
+
         if *is_update {
             if let Some(archive) = server
                 .get_archive(account_id, Collection::Email, *document_id)
@@ -44,6 +55,14 @@ pub(crate) async fn update_email_cache(
                     *document_id,
                     archive.to_unarchived::<MessageData>()?,
                 );
+                if let Some(idx) = new_cache.index.get(document_id) {
+                    update_counters(
+                        &mut new_cache.counters,
+                        &new_cache.items[*idx as usize],
+                        change_id,
+                        true,
+                    );
+                }
             }
         }
     }
@@ -74,6 +93,7 @@ pub(crate) async fn full_email_cache_build(
         items: Vec::with_capacity(16),
         index: AHashMap::with_capacity(16),
         keywords: Vec::new(),
+        counters: AHashMap::new(),
         size: 0,
         change_id: 0,
     };
@@ -97,10 +117,60 @@ pub(crate) async fn full_email_cache_build(
 
     cache.items.shrink_to_fit();
     cache.index.shrink_to_fit();
+    cache.counters = build_counters(&cache.items);
 
     Ok(cache)
 }
 
+pub fn build_counters(items: &[MessageCache]) -> AHashMap<u32, MailboxCounters> {
+    let mut counters = AHashMap::new();
+    for item in items {
+        update_counters(&mut counters, item, item.change_id, true);
+    }
+    counters
+}
+
+pub fn update_counters(
+    counters: &mut AHashMap<u32, MailboxCounters>,
+    item: &MessageCache,
+    change_id: u64,
+    is_insert: bool,
+) {
+    let is_unread = item.keywords & (1 << SEEN) == 0;
+    for mailbox in &item.mailboxes {
+        // Counters of empty mailboxes are kept so the change is reported to clients
+        let counters = counters.entry(mailbox.mailbox_id).or_default();
+        counters.change_id = counters.change_id.max(change_id);
+
+        if is_insert {
+            let thread = counters.threads.entry(item.thread_id).or_default();
+            thread.total += 1;
+            counters.total_emails += 1;
+            if is_unread {
+                thread.unread += 1;
+                counters.unread_emails += 1;
+                if thread.unread == 1 {
+                    counters.unread_threads += 1;
+                }
+            }
+        } else if let Entry::Occupied(mut entry) = counters.threads.entry(item.thread_id) {
+            let thread = entry.get_mut();
+            thread.total = thread.total.saturating_sub(1);
+            counters.total_emails = counters.total_emails.saturating_sub(1);
+            if is_unread && thread.unread > 0 {
+                thread.unread -= 1;
+                counters.unread_emails = counters.unread_emails.saturating_sub(1);
+                if thread.unread == 0 {
+                    counters.unread_threads = counters.unread_threads.saturating_sub(1);
+                }
+            }
+            if thread.total == 0 {
+                entry.remove();
+            }
+        }
+    }
+}
+
 fn insert_item(
     cache: &mut MessagesCache,
     document_id: u32,
@@ -167,6 +237,13 @@ pub trait MessageCacheAccess {
 
     fn email_document_ids(&self) -> RoaringBitmap;
 
+    fn mailbox_counters(&self, mailbox_id: u32) -> Option<&MailboxCounters>;
+
+    fn changed_mailbox_counts(
+        &self,
+        since_change_id: u64,
+    ) -> impl Iterator<Item = (u32, MailboxCounts)>;
+
     fn shared_messages(
         &self,
         access_token: &AccessToken,
@@ -260,6 +337,31 @@ impl MessageCacheAccess for MessageStoreCache {
         RoaringBitmap::from_iter(self.emails.index.keys())
     }
 
+    fn mailbox_counters(&self, mailbox_id: u32) -> Option<&MailboxCounters> {
+        self.emails.counters.get(&mailbox_id)
+    }
+
+    fn changed_mailbox_counts(
+        &self,
+        since_change_id: u64,
+    ) -> impl Iterator<Item = (u32, MailboxCounts)> {
+        self.emails
+            .counters
+            .iter()
+            .filter(move |(_, counters)| counters.change_id >= since_change_id)
+            .map(|(mailbox_id, counters)| {
+                (
+                    *mailbox_id,
+                    MailboxCounts {
+                        total_emails: counters.total_emails,
+                        unread_emails: counters.unread_emails,
+                        total_threads: counters.total_threads(),
+                        unread_threads: counters.unread_threads,
+                    },
+                )
+            })
+    }
+
     fn email_by_id(&self, id: &u32) -> Option<&MessageCache> {
         self.emails
             .index
@@ -6,9 +6,9 @@
 
 use std::{collections::hash_map::Entry, sync::Arc, time::Instant};
 
-use common::{CacheSwap, MessageStoreCache, Server};
-use email::{full_email_cache_build, update_email_cache};
-use jmap_proto::types::collection::SyncCollection;
+use common::{CacheSwap, MailboxCounters, MessageStoreCache, MessagesCache, Server};
+use email::{MessageCacheAccess, build_counters, full_email_cache_build, update_email_cache};
+use jmap_proto::{response::status::MailboxCounts, types::collection::SyncCollection};
 use mailbox::{full_mailbox_cache_build, update_mailbox_cache};
 use store::{
     ahash::AHashMap,
@@ -25,6 +25,17 @@ pub trait MessageCacheFetch: Sync + Send {
         &self,
         account_id: u32,
     ) -> impl Future<Output = trc::Result<Arc<MessageStoreCache>>> + Send;
+
+    fn get_mailbox_counts(
+        &self,
+        account_id: u32,
+        since_change_id: u64,
+    ) -> impl Future<Output = trc::Result<Vec<(u32, MailboxCounts)>>> + Send;
+
+    fn repair_mailbox_counters(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
 }
 
 impl MessageCacheFetch for Server {
@@ -162,9 +173,14 @@ impl MessageCacheFetch for Server {
         }
 
         if !changed_items.is_empty() {
-            let mut email_cache =
-                update_email_cache(self, account_id, &changed_items, &cache).await?;
-            email_cache.change_id = changes.item_change_id.unwrap_or(changes.to_change_id);
+            let email_cache = update_email_cache(
+                self,
+                account_id,
+                &changed_items,
+                &cache,
+                changes.item_change_id.unwrap_or(changes.to_change_id),
+            )
+            .await?;
             cache.emails = Arc::new(email_cache);
         }
 
@@ -192,6 +208,75 @@ impl MessageCacheFetch for Server {
 
         Ok(cache)
     }
+
+    async fn get_mailbox_counts(
+        &self,
+        account_id: u32,
+        since_change_id: u64,
+    ) -> trc::Result<Vec<(u32, MailboxCounts)>> {
+        self.get_cached_messages(account_id)
+            .await
+            .map(|cache| cache.changed_mailbox_counts(since_change_id).collect())
+    }
+
+    async fn repair_mailbox_counters(&self, account_id: u32) -> trc::Result<()> {
+        let cache = self
+            .get_cached_messages(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        let Some(cache_) = self.inner.cache.messages.get(&account_id) else {
+            return Ok(());
+        };
+
+        // Lock for updates
+        let _permit = cache.update_lock.acquire().await;
+        let cache = cache_.load_full();
+
+        // Compare the incremental counters against a full scan
+        let mut counters = build_counters(&cache.emails.items);
+        let mut repaired_ids = Vec::new();
+        for (mailbox_id, expected) in counters.iter_mut() {
+            match cache.emails.counters.get(mailbox_id) {
+                Some(current) if current.has_same_counts(expected) => {
+                    expected.change_id = current.change_id;
+                }
+                _ => {
+                    repaired_ids.push(*mailbox_id);
+                    expected.change_id = cache.emails.change_id;
+                }
+            }
+        }
+        for (mailbox_id, current) in cache.emails.counters.iter() {
+            if !counters.contains_key(mailbox_id) {
+                let mut expected = MailboxCounters {
+                    change_id: current.change_id,
+                    ..Default::default()
+                };
+                if !current.has_same_counts(&expected) {
+                    repaired_ids.push(*mailbox_id);
+                    expected.change_id = cache.emails.change_id;
+                }
+                counters.insert(*mailbox_id, expected);
+            }
+        }
+
+        if !repaired_ids.is_empty() {
+            let mut new_cache = cache.as_ref().clone();
+            new_cache.emails = Arc::new(MessagesCache {
+                counters,
+                ..cache.emails.as_ref().clone()
+            });
+            cache_.update(Arc::new(new_cache));
+
+            trc::event!(
+                Purge(trc::PurgeEvent::MailboxCountersRepaired),
+                AccountId = account_id,
+                MailboxId = repaired_ids,
+            );
+        }
+
+        Ok(())
+    }
 }
 
 async fn full_cache_build(
@@ -175,6 +175,8 @@ impl MailboxDestroy for Server {
                 .with_collection(Collection::Mailbox)
                 .delete_document(document_id)
                 .clear(Property::EmailIds)
+                .clear(Property::Parameters)
+                .clear(Property::DeadLetters)
                 .custom(ObjectIndexBuilder::<_, ()>::new().with_current(mailbox))
                 .caused_by(trc::location!())?;
         } else {
@@ -5,7 +5,7 @@
  */
 
 use super::*;
-use crate::cache::MessageCacheFetch;
+use crate::cache::{MessageCacheFetch, mailbox::MailboxCacheAccess};
 use common::{Server, config::jmap::settings::SpecialUse, storage::index::ObjectIndexBuilder};
 use jmap_proto::types::collection::Collection;
 use std::future::Future;
@@ -23,6 +23,12 @@ pub trait MailboxFnc: Sync + Send {
         account_id: u32,
         path: &str,
     ) -> impl Future<Output = trc::Result<Option<u32>>> + Send;
+
+    fn mailbox_catch_all(
+        &self,
+        account_id: u32,
+        address: &str,
+    ) -> impl Future<Output = trc::Result<Option<u32>>> + Send;
 }
 
 impl MailboxFnc for Server {
@@ -151,4 +157,52 @@ impl MailboxFnc for Server {
 
         Ok(Some(next_parent_id - 1))
     }
+
+    // Returns the mailbox named after the local part of a catch-all recipient,
+    // creating it unless the catch-all folder already holds the maximum allowed
+    async fn mailbox_catch_all(&self, account_id: u32, address: &str) -> trc::Result<Option<u32>> {
+        let Some(local_part) = address
+            .rsplit_once('@')
+            .map(|(local_part, _)| local_part.trim())
+            .filter(|local_part| !local_part.is_empty() && !local_part.contains('/'))
+        else {
+            return Ok(None);
+        };
+        let folder = &self.core.jmap.mail_catch_all_folder;
+        let path = if !folder.is_empty() {
+            format!("{folder}/{local_part}")
+        } else {
+            local_part.to_string()
+        };
+
+        let cache = self
+            .get_cached_messages(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        if let Some(mailbox) = cache.mailbox_by_path(&path) {
+            return Ok(Some(mailbox.document_id));
+        }
+
+        // Cap the number of mailboxes to avoid unbounded growth from dictionary attacks
+        let parent_id = if !folder.is_empty() {
+            cache
+                .mailbox_by_path(folder)
+                .map(|mailbox| mailbox.document_id)
+        } else {
+            Some(u32::MAX)
+        };
+        let num_mailboxes = parent_id.map_or(0, |parent_id| {
+            cache
+                .mailboxes
+                .items
+                .iter()
+                .filter(|mailbox| mailbox.parent_id == parent_id)
+                .count()
+        });
+        if num_mailboxes >= self.core.jmap.mail_catch_all_max_mailboxes {
+            return Ok(None);
+        }
+
+        self.mailbox_create_path(account_id, &path).await
+    }
 }
@@ -5,8 +5,10 @@
  */
 
 use super::{
+    encrypt::EncryptStoredMessage,
     index::{MAX_ID_LENGTH, MAX_SORT_FIELD_LENGTH, TrimTextValue, VisitText},
-    ingest::{EmailIngest, IngestedEmail, ThreadResult},
+    ingest::{EmailIngest, IngestedEmail, ThreadResult, remove_contents},
+    integrity::{EmailIntegrity, IntegrityReason},
     metadata::{MessageData, MessageMetadata},
 };
 use crate::mailbox::UidMailbox;
@@ -22,7 +24,7 @@ use jmap_proto::{
         property::Property,
     },
 };
-use mail_parser::{HeaderName, HeaderValue, parsers::fields::thread::thread_name};
+use mail_parser::{HeaderName, HeaderValue, MessageParser, parsers::fields::thread::thread_name};
 use store::{
     BlobClass,
     write::{BatchBuilder, TaskQueueClass, ValueClass},
@@ -76,6 +78,40 @@ impl EmailCopy for Server {
             ))));
         };
 
+        // Messages copied from other accounts are encrypted with the parameters
+        // of the destination account
+        if from_account_id != account_id {
+            let raw_message = self
+                .blob_store()
+                .get_blob(metadata.blob_hash.as_slice(), 0..usize::MAX)
+                .await
+                .caused_by(trc::location!())?;
+            if let Some(message) = raw_message
+                .as_deref()
+                .and_then(|raw_message| MessageParser::new().parse(raw_message))
+            {
+                if let Some(raw_message) = self
+                    .encrypt_new_message(account_id, &message, &mailboxes, session_id)
+                    .await?
+                {
+                    let mut message =
+                        MessageParser::new().parse(&raw_message).ok_or_else(|| {
+                            trc::StoreEvent::CryptoError
+                                .into_err()
+                                .caused_by(trc::location!())
+                                .reason("Failed to parse encrypted e-mail message.")
+                        })?;
+                    remove_contents(&mut message);
+                    let blob_id = self
+                        .put_blob(account_id, &raw_message, false)
+                        .await
+                        .caused_by(trc::location!())?;
+                    metadata =
+                        MessageMetadata::from_message(message, blob_id.hash, metadata.received_at);
+                }
+            }
+        }
+
         // Check quota
         match self
             .has_available_quota(resource_token, metadata.size as u64)
@@ -213,6 +249,17 @@ impl EmailCopy for Server {
                 true,
             )
             .caused_by(trc::location!())?;
+        batch.set(
+            Property::Integrity,
+            self.message_integrity(
+                account_id,
+                document_id,
+                None,
+                blob_hash.clone(),
+                IntegrityReason::Copy,
+                account_id.into(),
+            )?,
+        );
 
         // Insert and obtain ids
         let change_id = self
@@ -223,6 +270,7 @@ impl EmailCopy for Server {
             .last_change_id(account_id)?;
 
         // Request FTS index
+        self.inner.data.index_backlog.enqueued(account_id);
         self.notify_task_queue();
 
         // Update response
@@ -4,38 +4,163 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{borrow::Cow, collections::BTreeSet, fmt::Display, io::Cursor};
+use std::{borrow::Cow, collections::BTreeSet, fmt::Display, io::Write, time::SystemTime};
 
-use aes::cipher::{BlockEncryptMut, KeyIvInit, block_padding::Pkcs7};
+use aes::{
+    Aes128, Aes192, Aes256,
+    cipher::{
+        BlockDecrypt, BlockDecryptMut, BlockEncrypt, BlockEncryptMut, KeyIvInit,
+        block_padding::Pkcs7,
+    },
+};
+use aes_gcm::{Aes128Gcm, Aes256Gcm, KeyInit, Nonce, aead::Aead};
 
 use mail_builder::{encoders::base64::base64_encode_mime, mime::make_boundary};
-use mail_parser::{Message, MimeHeaders, PartType, decoders::base64::base64_decode};
+use mail_parser::{
+    DateTime, HeaderName, Message, MimeHeaders, PartType, decoders::base64::base64_decode,
+};
 use openpgp::{
-    parse::Parse,
-    serialize::stream,
-    types::{KeyFlags, SymmetricAlgorithm},
+    KeyHandle, Packet,
+    cert::{CertParser, Preferences},
+    crypto::SessionKey,
+    packet::{PKESK, SKESK, Tag},
+    parse::{
+        PacketParser, PacketParserResult, Parse,
+        stream::{DecryptionHelper, DecryptorBuilder, MessageStructure, VerificationHelper},
+    },
+    policy::Policy,
+    serialize::{SerializeInto, stream},
+    types::{CompressionAlgorithm, KeyFlags, RevocationStatus, SymmetricAlgorithm},
 };
+use p256::elliptic_curve::sec1::ToEncodedPoint;
 use rand::{RngCore, SeedableRng, rngs::StdRng};
-use rasn::types::{ObjectIdentifier, OctetString};
+use rasn::types::{Any, BitString, ObjectIdentifier, OctetString, Oid};
 use rasn_cms::{
-    AlgorithmIdentifier, CONTENT_DATA, CONTENT_ENVELOPED_DATA, EncryptedContent,
-    EncryptedContentInfo, EncryptedKey, EnvelopedData, IssuerAndSerialNumber,
-    KeyTransRecipientInfo, RecipientIdentifier, RecipientInfo,
+    AlgorithmIdentifier, CONTENT_DATA, CONTENT_ENVELOPED_DATA, CONTENT_SIGNED_DATA, CmsVersion,
+    ContentInfo, EncryptedContent, EncryptedContentInfo, EncryptedKey, EnvelopedData,
+    IssuerAndSerialNumber, KeyAgreeRecipientIdentifier, KeyAgreeRecipientInfo,
+    KeyTransRecipientInfo, OriginatorIdentifierOrKey, OriginatorInfo, OriginatorPublicKey,
+    RecipientEncryptedKey, RecipientIdentifier, RecipientInfo, RecipientInfos,
+    UnprotectedAttributes,
     algorithms::{AES128_CBC, AES256_CBC, RSA},
     pkcs7_compat::EncapsulatedContentInfo,
 };
-use rsa::{Pkcs1v15Encrypt, RsaPublicKey, pkcs1::DecodeRsaPublicKey};
+use rayon::prelude::*;
+use ring::aead::{Aad, CHACHA20_POLY1305 as CHACHA20_POLY1305_AEAD, LessSafeKey, UnboundKey};
+use rsa::{
+    Oaep, Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey,
+    pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey},
+    pkcs8::DecodePrivateKey,
+    traits::PublicKeyParts,
+};
 use sequoia_openpgp as openpgp;
-use store::{Deserialize, write::Archive};
+use sha2::{Digest, Sha256, Sha384};
+use store::write::now;
+
+use super::pkcs12::try_parse_pkcs12;
 
 const P: openpgp::policy::StandardPolicy<'static> = openpgp::policy::StandardPolicy::new();
 
+// id-RSAES-OAEP (RFC 8017)
+const RSAES_OAEP: &Oid = Oid::const_new(&[1, 2, 840, 113549, 1, 1, 7]);
+
+// RSAES-OAEP-params using SHA-256 and MGF1 with SHA-256 (RFC 4055)
+const RSAES_OAEP_SHA256_PARAMS: &[u8] = &[
+    0x30, 0x2f, 0xa0, 0x0f, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+    0x01, 0x05, 0x00, 0xa1, 0x1c, 0x30, 0x1a, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01,
+    0x01, 0x08, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00,
+];
+
+// id-ct-authEnvelopedData (RFC 5083)
+const CONTENT_AUTH_ENVELOPED_DATA: &Oid = Oid::const_new(&[1, 2, 840, 113549, 1, 9, 16, 1, 23]);
+
+// id-aes128-GCM and id-aes256-GCM (RFC 5084)
+const AES128_GCM: &Oid = Oid::const_new(&[2, 16, 840, 1, 101, 3, 4, 1, 6]);
+const AES256_GCM: &Oid = Oid::const_new(&[2, 16, 840, 1, 101, 3, 4, 1, 46]);
+
+// id-alg-AEADChaCha20Poly1305 (RFC 8103)
+const CHACHA20_POLY1305: &Oid = Oid::const_new(&[1, 2, 840, 113549, 1, 9, 16, 3, 18]);
+
+// id-ecPublicKey (RFC 5480)
+const EC_PUBLIC_KEY: &Oid = Oid::const_new(&[1, 2, 840, 10045, 2, 1]);
+
+// secp256r1 and secp384r1 named curves (RFC 5480)
+const EC_CURVE_P256: &Oid = Oid::const_new(&[1, 2, 840, 10045, 3, 1, 7]);
+const EC_CURVE_P384: &Oid = Oid::const_new(&[1, 3, 132, 0, 34]);
+
+// dhSinglePass-stdDH-sha256kdf-scheme and dhSinglePass-stdDH-sha384kdf-scheme (RFC 5753)
+const ECDH_SHA256_KDF: &Oid = Oid::const_new(&[1, 3, 132, 1, 11, 1]);
+const ECDH_SHA384_KDF: &Oid = Oid::const_new(&[1, 3, 132, 1, 11, 2]);
+
+// id-aes128-wrap, id-aes192-wrap and id-aes256-wrap (RFC 3394)
+const AES128_WRAP: &Oid = Oid::const_new(&[2, 16, 840, 1, 101, 3, 4, 1, 5]);
+const AES192_WRAP: &Oid = Oid::const_new(&[2, 16, 840, 1, 101, 3, 4, 1, 25]);
+const AES256_WRAP: &Oid = Oid::const_new(&[2, 16, 840, 1, 101, 3, 4, 1, 45]);
+
+// id-ce-keyUsage and id-ce-extKeyUsage (RFC 5280)
+const KEY_USAGE: &Oid = Oid::const_new(&[2, 5, 29, 15]);
+const EXT_KEY_USAGE: &Oid = Oid::const_new(&[2, 5, 29, 37]);
+
+// id-kp-emailProtection and anyExtendedKeyUsage (RFC 5280)
+const KP_EMAIL_PROTECTION: &Oid = Oid::const_new(&[1, 3, 6, 1, 5, 5, 7, 3, 4]);
+const ANY_EXTENDED_KEY_USAGE: &Oid = Oid::const_new(&[2, 5, 29, 37, 0]);
+
+// keyEncipherment, dataEncipherment and keyAgreement bits of the keyUsage extension
+const KEY_USAGE_KEY_ENCIPHERMENT: usize = 2;
+const KEY_USAGE_DATA_ENCIPHERMENT: usize = 3;
+const KEY_USAGE_KEY_AGREEMENT: usize = 4;
+
+// CBC IVs are one AES block, GCM and ChaCha20-Poly1305 use 96-bit nonces
+const CBC_IV_LEN: usize = 16;
+const GCM_NONCE_LEN: usize = 12;
+const GCM_TAG_LEN: usize = 16;
+
+// Certificates expiring within this period produce a warning
+const CERT_EXPIRY_WARNING: u64 = 30 * 86400;
+
+// Messages larger than this are encrypted in chunks rather than copied and
+// encrypted at once, chunks are a multiple of the AES block size
+pub const ENCRYPT_STREAM_THRESHOLD: usize = 1024 * 1024;
+const ENCRYPT_CHUNK_SIZE: usize = 64 * 1024;
+const ENCRYPT_CHANNEL_SIZE: usize = 4;
+
 #[derive(Debug)]
 pub enum EncryptMessageError {
     AlreadyEncrypted,
     Error(String),
 }
 
+#[derive(Debug)]
+pub enum DecryptMessageError {
+    NotEncrypted,
+    NoMatchingKey,
+    Error(String),
+}
+
+#[allow(clippy::large_enum_variant)]
+pub enum DecryptionKey {
+    Rsa(RsaPrivateKey),
+    P256(p256::SecretKey),
+    P384(p384::SecretKey),
+    Pgp(openpgp::Cert),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CertParseError {
+    MixedMethods,
+    MethodMismatch,
+    InvalidBase64,
+    InvalidX509(String),
+    InvalidPgp(String),
+    InvalidPkcs12(String),
+    Pkcs12WrongPassword,
+    Pkcs12UnsupportedMac(String),
+    Pkcs12UnsupportedEncryption(String),
+    AmbiguousCertificates,
+    NoCertificates,
+}
+
 #[derive(
     rkyv::Serialize,
     rkyv::Deserialize,
@@ -43,6 +168,8 @@ pub enum EncryptMessageError {
     Debug,
     Clone,
     Copy,
+    PartialEq,
+    Eq,
     serde::Serialize,
     serde::Deserialize,
 )]
@@ -50,6 +177,11 @@ pub enum EncryptMessageError {
 pub enum Algorithm {
     Aes128,
     Aes256,
+    #[serde(alias = "smime-128-gcm")]
+    Aes128Gcm,
+    #[serde(alias = "smime-256-gcm")]
+    Aes256Gcm,
+    Chacha20Poly1305,
 }
 
 #[derive(
@@ -69,6 +201,45 @@ pub enum EncryptionMethod {
     SMIME,
 }
 
+#[derive(
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[rkyv(derive(Clone, Copy))]
+pub enum RsaPadding {
+    #[default]
+    Pkcs1v15,
+    Oaep,
+}
+
+#[derive(
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[rkyv(derive(Clone, Copy))]
+pub enum Compression {
+    None,
+    Zip,
+    Zlib,
+}
+
 #[derive(
     Clone,
     rkyv::Serialize,
@@ -79,6 +250,23 @@ pub enum EncryptionMethod {
     serde::Deserialize,
 )]
 pub struct EncryptionParams {
+    pub method: EncryptionMethod,
+    pub algo: Algorithm,
+    #[serde(default)]
+    pub padding: RsaPadding,
+    pub certs: Vec<Vec<u8>>,
+    #[serde(default)]
+    pub exclude_mailboxes: Vec<u32>,
+    #[serde(default)]
+    pub max_encrypt_size: Option<u64>,
+    // OpenPGP only, when unset text-heavy messages are compressed with ZLIB
+    #[serde(default)]
+    pub compression: Option<Compression>,
+}
+
+// Encryption parameters as archived by schema version 1
+#[derive(rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, Debug)]
+pub struct EncryptionParamsV1 {
     pub method: EncryptionMethod,
     pub algo: Algorithm,
     pub certs: Vec<Vec<u8>>,
@@ -98,21 +286,128 @@ pub struct EncryptionParams {
 pub enum EncryptionType {
     PGP {
         algo: Algorithm,
+        #[serde(default)]
         certs: String,
+        #[serde(default)]
+        #[serde(rename = "excludeMailboxes")]
+        exclude_mailboxes: Vec<u32>,
+        #[serde(default)]
+        #[serde(rename = "fetchWkd")]
+        fetch_wkd: bool,
+        #[serde(default)]
+        #[serde(rename = "maxEncryptSize")]
+        max_encrypt_size: Option<u64>,
+        #[serde(default)]
+        compression: Option<Compression>,
     },
     SMIME {
         algo: Algorithm,
+        #[serde(default)]
+        padding: RsaPadding,
         certs: String,
+        #[serde(default)]
+        #[serde(rename = "certificatePassword")]
+        certificate_password: Option<String>,
+        #[serde(default)]
+        #[serde(rename = "excludeMailboxes")]
+        exclude_mailboxes: Vec<u32>,
+        #[serde(default)]
+        #[serde(rename = "maxEncryptSize")]
+        max_encrypt_size: Option<u64>,
+    },
+    #[default]
+    Disabled,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum EncryptionSummary {
+    PGP {
+        algo: Algorithm,
+        certificates: Vec<CertificateInfo>,
+        #[serde(rename = "excludeMailboxes")]
+        exclude_mailboxes: Vec<u32>,
+        #[serde(rename = "maxEncryptSize")]
+        max_encrypt_size: u64,
+        compression: Option<Compression>,
+        #[serde(rename = "updatedAt")]
+        updated_at: Option<u64>,
+    },
+    SMIME {
+        algo: Algorithm,
+        padding: RsaPadding,
+        certificates: Vec<CertificateInfo>,
+        #[serde(rename = "excludeMailboxes")]
+        exclude_mailboxes: Vec<u32>,
+        #[serde(rename = "maxEncryptSize")]
+        max_encrypt_size: u64,
+        #[serde(rename = "updatedAt")]
+        updated_at: Option<u64>,
     },
     #[default]
     Disabled,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateInfo {
+    pub fingerprint: String,
+    pub subject: Option<String>,
+    pub valid_from: Option<u64>,
+    pub expires_at: Option<u64>,
+    pub revoked: bool,
+}
+
+#[derive(rasn::AsnType, rasn::Encode, rasn::Decode, Debug, Clone, PartialEq)]
+pub struct AuthEnvelopedData {
+    pub version: CmsVersion,
+    #[rasn(tag(0))]
+    pub originator_info: Option<OriginatorInfo>,
+    pub recipient_infos: RecipientInfos,
+    pub auth_encrypted_content_info: EncryptedContentInfo,
+    #[rasn(tag(1))]
+    pub auth_attrs: Option<UnprotectedAttributes>,
+    pub mac: OctetString,
+    #[rasn(tag(2))]
+    pub unauth_attrs: Option<UnprotectedAttributes>,
+}
+
+#[derive(rasn::AsnType, rasn::Encode, rasn::Decode, Debug, Clone, PartialEq)]
+pub struct EccCmsSharedInfo {
+    pub key_info: AlgorithmIdentifier,
+    #[rasn(tag(explicit(0)))]
+    pub entity_u_info: Option<OctetString>,
+    #[rasn(tag(explicit(2)))]
+    pub supp_pub_info: OctetString,
+}
+
+#[derive(rasn::AsnType, rasn::Encode, rasn::Decode, Debug, Clone, PartialEq)]
+pub struct GcmParameters {
+    pub nonce: OctetString,
+    pub icv_len: Option<u32>,
+}
+
+// Certificates are kept in their original encoding, other certificate formats
+// that may be present in the bundle are skipped
+#[derive(rasn::AsnType, rasn::Decode, Debug, Clone, PartialEq)]
+struct Pkcs7SignedData {
+    version: u8,
+    digest_algorithms: Any,
+    content_info: Any,
+    #[rasn(tag(0))]
+    certificates: Option<Vec<Any>>,
+    #[rasn(tag(1))]
+    crls: Option<Vec<Any>>,
+    signer_infos: Any,
+}
+
 #[allow(async_fn_in_trait)]
 pub trait EncryptMessage {
     async fn encrypt(
         &self,
-        params: &ArchivedEncryptionParams,
+        identities: &[ArchivedEncryptionParams],
+        signing_key: Option<&[u8]>,
     ) -> Result<Vec<u8>, EncryptMessageError>;
     fn is_encrypted(&self) -> bool;
 }
@@ -120,288 +415,68 @@ pub trait EncryptMessage {
 impl EncryptMessage for Message<'_> {
     async fn encrypt(
         &self,
-        params: &ArchivedEncryptionParams,
+        identities: &[ArchivedEncryptionParams],
+        signing_key: Option<&[u8]>,
     ) -> Result<Vec<u8>, EncryptMessageError> {
-        let root = self.root_part();
-        let raw_message = self.raw_message();
-        let mut outer_message = Vec::with_capacity((raw_message.len() as f64 * 1.5) as usize);
-        let mut inner_message = Vec::with_capacity(raw_message.len());
+        let Some(params) = identities.first() else {
+            return Err(EncryptMessageError::Error(
+                "No encryption identities are configured".to_string(),
+            ));
+        };
+        let method = EncryptionMethod::from(&params.method);
+        let algo = Algorithm::from(params.algo);
+        let size = self.raw_message().len();
+        let num_certs = identities
+            .iter()
+            .map(|identity| identity.certs.len())
+            .sum::<usize>();
 
-        // Move MIME headers and body to inner message
-        for header in root.headers() {
-            (if header.name.is_mime_header() {
-                &mut inner_message
-            } else {
-                &mut outer_message
-            })
-            .extend_from_slice(
-                &raw_message[header.offset_field() as usize..header.offset_end() as usize],
+        if self.is_encrypted() {
+            trc::event!(
+                Encrypt(trc::EncryptEvent::AlreadyEncrypted),
+                Type = method.to_string(),
+                Size = size,
             );
+            return Err(EncryptMessageError::AlreadyEncrypted);
         }
-        inner_message.extend_from_slice(b"\r\n");
-        inner_message.extend_from_slice(&raw_message[root.raw_body_offset() as usize..]);
-
-        // Encrypt inner message
-        match params.method {
-            ArchivedEncryptionMethod::PGP => {
-                // Prepare encrypted message
-                let boundary = make_boundary("_");
-                outer_message.extend_from_slice(
-                    concat!(
-                        "Content-Type: multipart/encrypted;\r\n\t",
-                        "protocol=\"application/pgp-encrypted\";\r\n\t",
-                        "boundary=\""
-                    )
-                    .as_bytes(),
-                );
-                outer_message.extend_from_slice(boundary.as_bytes());
-                outer_message.extend_from_slice(
-                    concat!(
-                        "\"\r\n\r\n",
-                        "OpenPGP/MIME message (Automatically encrypted by Stalwart)\r\n\r\n",
-                        "--"
-                    )
-                    .as_bytes(),
-                );
-                outer_message.extend_from_slice(boundary.as_bytes());
-                outer_message.extend_from_slice(
-                    concat!(
-                        "\r\nContent-Type: application/pgp-encrypted\r\n\r\n",
-                        "Version: 1\r\n\r\n--"
-                    )
-                    .as_bytes(),
-                );
-                outer_message.extend_from_slice(boundary.as_bytes());
-                outer_message.extend_from_slice(
-                    concat!(
-                        "\r\nContent-Type: application/octet-stream; name=\"encrypted.asc\"\r\n",
-                        "Content-Disposition: inline; filename=\"encrypted.asc\"\r\n\r\n"
-                    )
-                    .as_bytes(),
-                );
-
-                let certs = params
-                    .certs
-                    .iter()
-                    .map(openpgp::Cert::from_bytes)
-                    .collect::<Result<Vec<_>, _>>()
-                    .map_err(|err| {
-                        EncryptMessageError::Error(format!(
-                            "Failed to parse OpenPGP public key: {}",
-                            err
-                        ))
-                    })?;
-
-                // Encrypt contents (TODO: use rayon)
-                let algo = params.algo;
-                let encrypted_contents = tokio::task::spawn_blocking(move || {
-                    // Parse public key
-                    let mut keys = Vec::with_capacity(certs.len());
-                    let policy = openpgp::policy::StandardPolicy::new();
-
-                    for cert in &certs {
-                        for key in cert
-                            .keys()
-                            .with_policy(&policy, None)
-                            .supported()
-                            .alive()
-                            .revoked(false)
-                            .key_flags(KeyFlags::empty().set_transport_encryption())
-                        {
-                            keys.push(key);
-                        }
-                    }
-
-                    // Compose a writer stack corresponding to the output format and
-                    // packet structure we want.
-                    let mut sink = Vec::with_capacity(inner_message.len());
-
-                    // Stream an OpenPGP message.
-                    let message = stream::Armorer::new(stream::Message::new(&mut sink))
-                        .build()
-                        .map_err(|err| {
-                            EncryptMessageError::Error(format!("Failed to create armorer: {}", err))
-                        })?;
-                    let message = stream::Encryptor::for_recipients(message, keys)
-                        .symmetric_algo(match algo {
-                            ArchivedAlgorithm::Aes128 => SymmetricAlgorithm::AES128,
-                            ArchivedAlgorithm::Aes256 => SymmetricAlgorithm::AES256,
-                        })
-                        .build()
-                        .map_err(|err| {
-                            EncryptMessageError::Error(format!(
-                                "Failed to build encryptor: {}",
-                                err
-                            ))
-                        })?;
-                    let mut message =
-                        stream::LiteralWriter::new(message).build().map_err(|err| {
-                            EncryptMessageError::Error(format!(
-                                "Failed to create literal writer: {}",
-                                err
-                            ))
-                        })?;
-                    std::io::copy(&mut Cursor::new(inner_message), &mut message).map_err(
-                        |err| {
-                            EncryptMessageError::Error(format!(
-                                "Failed to encrypt message: {}",
-                                err
-                            ))
-                        },
-                    )?;
-                    message.finalize().map_err(|err| {
-                        EncryptMessageError::Error(format!("Failed to finalize message: {}", err))
-                    })?;
-
-                    String::from_utf8(sink).map_err(|err| {
-                        EncryptMessageError::Error(format!(
-                            "Failed to convert encrypted message to UTF-8: {}",
-                            err
-                        ))
-                    })
-                })
-                .await
-                .map_err(|err| {
-                    EncryptMessageError::Error(format!("Failed to encrypt message: {}", err))
-                })??;
-                outer_message.extend_from_slice(encrypted_contents.as_bytes());
-                outer_message.extend_from_slice(b"\r\n--");
-                outer_message.extend_from_slice(boundary.as_bytes());
-                outer_message.extend_from_slice(b"--\r\n");
-            }
-            ArchivedEncryptionMethod::SMIME => {
-                // Generate random IV
-                let mut rng = StdRng::from_entropy();
-                let mut iv = vec![0u8; 16];
-                rng.fill_bytes(&mut iv);
-
-                // Generate random key
-                let mut key = vec![0u8; params.algo.key_size()];
-                rng.fill_bytes(&mut key);
-
-                // Encrypt contents (TODO: use rayon)
-                let algo = params.algo;
-                let (encrypted_contents, key, iv) = tokio::task::spawn_blocking(move || {
-                    (algo.encrypt(&key, &iv, &inner_message), key, iv)
-                })
-                .await
-                .map_err(|err| {
-                    EncryptMessageError::Error(format!("Failed to encrypt message: {}", err))
-                })?;
-
-                // Encrypt key using public keys
-                #[allow(clippy::mutable_key_type)]
-                let mut recipient_infos = BTreeSet::new();
-                for cert in params.certs.iter() {
-                    let cert =
-                        rasn::der::decode::<rasn_pkix::Certificate>(cert).map_err(|err| {
-                            EncryptMessageError::Error(format!(
-                                "Failed to parse certificate: {}",
-                                err
-                            ))
-                        })?;
-
-                    let public_key = RsaPublicKey::from_pkcs1_der(
-                        cert.tbs_certificate
-                            .subject_public_key_info
-                            .subject_public_key
-                            .as_raw_slice(),
-                    )
-                    .map_err(|err| {
-                        EncryptMessageError::Error(format!("Failed to parse public key: {}", err))
-                    })?;
-                    let encrypted_key = public_key
-                        .encrypt(&mut rng, Pkcs1v15Encrypt, &key[..])
-                        .map_err(|err| {
-                            EncryptMessageError::Error(format!("Failed to encrypt key: {}", err))
-                        })
-                        .unwrap();
-
-                    recipient_infos.insert(RecipientInfo::KeyTransRecipientInfo(
-                        KeyTransRecipientInfo {
-                            version: 0.into(),
-                            rid: RecipientIdentifier::IssuerAndSerialNumber(
-                                IssuerAndSerialNumber {
-                                    issuer: cert.tbs_certificate.issuer,
-                                    serial_number: cert.tbs_certificate.serial_number,
-                                },
-                            ),
-                            key_encryption_algorithm: AlgorithmIdentifier {
-                                algorithm: RSA.into(),
-                                parameters: Some(
-                                    rasn::der::encode(&())
-                                        .map_err(|err| {
-                                            EncryptMessageError::Error(format!(
-                                                "Failed to encode RSA algorithm identifier: {}",
-                                                err
-                                            ))
-                                        })?
-                                        .into(),
-                                ),
-                            },
-                            encrypted_key: EncryptedKey::from(encrypted_key),
-                        },
-                    ));
-                }
-
-                let pkcs7 = rasn::der::encode(&EncapsulatedContentInfo {
-                    content_type: CONTENT_ENVELOPED_DATA.into(),
-                    content: Some(
-                        rasn::der::encode(&EnvelopedData {
-                            version: 0.into(),
-                            originator_info: None,
-                            recipient_infos,
-                            encrypted_content_info: EncryptedContentInfo {
-                                content_type: CONTENT_DATA.into(),
-                                content_encryption_algorithm: AlgorithmIdentifier {
-                                    algorithm: params.algo.to_algorithm_identifier(),
-                                    parameters: Some(
-                                        rasn::der::encode(&OctetString::from(iv))
-                                            .map_err(|err| {
-                                                EncryptMessageError::Error(format!(
-                                                    "Failed to encode IV: {}",
-                                                    err
-                                                ))
-                                            })?
-                                            .into(),
-                                    ),
-                                },
-                                encrypted_content: Some(EncryptedContent::from(encrypted_contents)),
-                            },
-                            unprotected_attrs: None,
-                        })
-                        .map_err(|err| {
-                            EncryptMessageError::Error(format!(
-                                "Failed to encode EnvelopedData: {}",
-                                err
-                            ))
-                        })?
-                        .into(),
-                    ),
-                })
-                .map_err(|err| {
-                    EncryptMessageError::Error(format!("Failed to encode ContentInfo: {}", err))
-                })?;
 
-                // Generate message
-                outer_message.extend_from_slice(
-                    concat!(
-                        "Content-Type: application/pkcs7-mime;\r\n",
-                        "\tname=\"smime.p7m\";\r\n",
-                        "\tsmime-type=enveloped-data\r\n",
-                        "Content-Disposition: attachment;\r\n",
-                        "\tfilename=\"smime.p7m\"\r\n",
-                        "Content-Transfer-Encoding: base64\r\n\r\n"
-                    )
-                    .as_bytes(),
+        // A message is either an OpenPGP or an S/MIME message, identities of both
+        // kinds are rejected when saved
+        let result = if identities
+            .iter()
+            .any(|identity| EncryptionMethod::from(&identity.method) != method)
+        {
+            Err(EncryptMessageError::Error(
+                "Identities using OpenPGP and S/MIME cannot be combined in the same message"
+                    .to_string(),
+            ))
+        } else {
+            encrypt_message(self, identities, signing_key).await
+        };
+        match &result {
+            Ok(_) => {
+                trc::event!(
+                    Encrypt(trc::EncryptEvent::Success),
+                    Type = method.to_string(),
+                    Details = algo.to_string(),
+                    Total = num_certs,
+                    Size = size,
                 );
-                base64_encode_mime(&pkcs7, &mut outer_message, false).map_err(|err| {
-                    EncryptMessageError::Error(format!("Failed to base64 encode PKCS7: {}", err))
-                })?;
             }
+            Err(EncryptMessageError::Error(err)) => {
+                trc::event!(
+                    Encrypt(trc::EncryptEvent::Failed),
+                    Type = method.to_string(),
+                    Details = algo.to_string(),
+                    Total = num_certs,
+                    Size = size,
+                    Reason = err.clone(),
+                );
+            }
+            Err(EncryptMessageError::AlreadyEncrypted) => {}
         }
 
-        Ok(outer_message)
+        result
     }
 
     fn is_encrypted(&self) -> bool {
@@ -413,13 +488,18 @@ impl EncryptMessage for Message<'_> {
                 .map(|s| s.as_ref())
                 .unwrap_or_default();
 
+            // Signed-only messages are readable, so only enveloped data counts as encrypted
             (main_type.eq_ignore_ascii_case("application")
-                && (sub_type.eq_ignore_ascii_case("pkcs7-mime")
-                    || sub_type.eq_ignore_ascii_case("pkcs7-signature")
+                && (((sub_type.eq_ignore_ascii_case("pkcs7-mime")
+                    || sub_type.eq_ignore_ascii_case("x-pkcs7-mime"))
+                    && ct.attribute("smime-type").is_none_or(|smime_type| {
+                        smime_type.eq_ignore_ascii_case("enveloped-data")
+                            || smime_type.eq_ignore_ascii_case("authEnveloped-data")
+                    }))
                     || (sub_type.eq_ignore_ascii_case("octet-stream")
                         && self.attachment_name().is_some_and(|name| {
                             name.rsplit_once('.')
-                                .is_some_and(|(_, ext)| ["p7m", "p7s", "p7c", "p7z"].contains(&ext))
+                                .is_some_and(|(_, ext)| ext.eq_ignore_ascii_case("p7m"))
                         }))))
                 || (main_type.eq_ignore_ascii_case("multipart")
                     && sub_type.eq_ignore_ascii_case("encrypted"))
@@ -445,7 +525,17 @@ impl EncryptMessage for Message<'_> {
 
             match text_part {
                 Some(text) if self.parts.len() == 1 || is_multipart => {
-                    if text.trim_start().starts_with("-----BEGIN PGP MESSAGE-----") {
+                    // Inline PGP messages may be preceded by unencrypted text
+                    if (is_multipart
+                        || self.content_type().is_none_or(|ct| {
+                            ct.c_type.eq_ignore_ascii_case("text")
+                                && ct
+                                    .c_subtype
+                                    .as_ref()
+                                    .is_none_or(|st| st.eq_ignore_ascii_case("plain"))
+                        }))
+                        && has_inline_pgp_armor(text)
+                    {
                         return true;
                     }
                 }
@@ -457,102 +547,2300 @@ impl EncryptMessage for Message<'_> {
     }
 }
 
-impl ArchivedAlgorithm {
-    fn key_size(&self) -> usize {
-        match self {
-            ArchivedAlgorithm::Aes128 => 16,
-            ArchivedAlgorithm::Aes256 => 32,
-        }
-    }
+// Identities share the cipher and compression, saving an identity updates them for
+// all identities of the account. The message is encrypted to all their certificates.
+async fn encrypt_message(
+    message: &Message<'_>,
+    identities: &[ArchivedEncryptionParams],
+    signing_key: Option<&[u8]>,
+) -> Result<Vec<u8>, EncryptMessageError> {
+    let params = &identities[0];
+    let root = message.root_part();
+    let raw_message = message.raw_message();
+    let mut outer_message = Vec::with_capacity((raw_message.len() as f64 * 1.5) as usize);
+    let mut inner_parts: Vec<&[u8]> = Vec::new();
 
-    fn to_algorithm_identifier(self) -> ObjectIdentifier {
-        match self {
-            ArchivedAlgorithm::Aes128 => AES128_CBC.into(),
-            ArchivedAlgorithm::Aes256 => AES256_CBC.into(),
+    // Move content headers and body to inner message, other headers keep their order
+    // and casing. MIME-Version is required on the outer message and is copied.
+    for header in root.headers() {
+        let raw_header = &raw_message[header.offset_field() as usize..header.offset_end() as usize];
+        match header.name {
+            HeaderName::ContentType
+            | HeaderName::ContentTransferEncoding
+            | HeaderName::ContentDisposition
+            | HeaderName::ContentId
+            | HeaderName::ContentDescription => {
+                inner_parts.push(raw_header);
+            }
+            HeaderName::MimeVersion => {
+                inner_parts.push(raw_header);
+                outer_message.extend_from_slice(raw_header);
+            }
+            _ => {
+                outer_message.extend_from_slice(raw_header);
+            }
         }
     }
+    inner_parts.push(b"\r\n");
+    inner_parts.push(&raw_message[root.raw_body_offset() as usize..]);
 
-    fn encrypt(&self, key: &[u8], iv: &[u8], contents: &[u8]) -> Vec<u8> {
-        match self {
-            ArchivedAlgorithm::Aes128 => cbc::Encryptor::<aes::Aes128>::new(key.into(), iv.into())
-                .encrypt_padded_vec_mut::<Pkcs7>(contents),
-            ArchivedAlgorithm::Aes256 => cbc::Encryptor::<aes::Aes256>::new(key.into(), iv.into())
-                .encrypt_padded_vec_mut::<Pkcs7>(contents),
-        }
-    }
-}
+    // Large messages are encrypted from the original in chunks, authenticated S/MIME
+    // ciphers need the whole plaintext at once and always use the inner message copy
+    let inner_len = inner_parts.iter().map(|part| part.len()).sum::<usize>();
+    let is_streamed = inner_len > ENCRYPT_STREAM_THRESHOLD
+        && (matches!(params.method, ArchivedEncryptionMethod::PGP)
+            || !params.algo.is_authenticated());
+    let inner_message = (!is_streamed).then(|| inner_parts.concat());
 
-pub fn try_parse_certs(
-    expected_method: EncryptionMethod,
-    cert: Vec<u8>,
-) -> Result<Vec<Vec<u8>>, Cow<'static, str>> {
-    // Check if it's a PEM file
-    let (method, certs) = if let Some(result) = try_parse_pem(&cert)? {
-        result
-    } else if rasn::der::decode::<rasn_pkix::Certificate>(&cert[..]).is_ok() {
-        (EncryptionMethod::SMIME, vec![cert])
-    } else if let Ok(cert_) = openpgp::Cert::from_bytes(&cert[..]) {
-        if !has_pgp_keys(cert_) {
-            (EncryptionMethod::PGP, vec![cert])
-        } else {
-            return Err("Could not find any suitable keys in certificate".into());
-        }
-    } else {
-        return Err("Could not find any valid certificates".into());
-    };
+    // Encrypt inner message
+    match params.method {
+        ArchivedEncryptionMethod::PGP => {
+            // Prepare encrypted message
+            let boundary = make_boundary("_");
+            outer_message.extend_from_slice(
+                concat!(
+                    "Content-Type: multipart/encrypted;\r\n\t",
+                    "protocol=\"application/pgp-encrypted\";\r\n\t",
+                    "boundary=\""
+                )
+                .as_bytes(),
+            );
+            outer_message.extend_from_slice(boundary.as_bytes());
+            outer_message.extend_from_slice(
+                concat!(
+                    "\"\r\n\r\n",
+                    "OpenPGP/MIME message (Automatically encrypted by Stalwart)\r\n\r\n",
+                    "--"
+                )
+                .as_bytes(),
+            );
+            outer_message.extend_from_slice(boundary.as_bytes());
+            outer_message.extend_from_slice(
+                concat!(
+                    "\r\nContent-Type: application/pgp-encrypted\r\n",
+                    "Content-Description: PGP/MIME version identification\r\n\r\n",
+                    "Version: 1\r\n\r\n--"
+                )
+                .as_bytes(),
+            );
+            outer_message.extend_from_slice(boundary.as_bytes());
+            outer_message.extend_from_slice(
+                concat!(
+                    "\r\nContent-Type: application/octet-stream; name=\"encrypted.asc\"\r\n",
+                    "Content-Description: OpenPGP encrypted message\r\n",
+                    "Content-Disposition: inline; filename=\"encrypted.asc\"\r\n\r\n"
+                )
+                .as_bytes(),
+            );
 
-    if method == expected_method {
-        Ok(certs)
-    } else {
-        Err("No valid certificates found for the selected encryption".into())
-    }
-}
+            let certs = identity_certs(identities)
+                .map(|(cert, _)| openpgp::Cert::from_bytes(cert))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| {
+                    EncryptMessageError::Error(format!(
+                        "Failed to parse OpenPGP public key: {}",
+                        err
+                    ))
+                })?;
 
-fn has_pgp_keys(cert: openpgp::Cert) -> bool {
-    cert.keys()
-        .with_policy(&P, None)
-        .supported()
-        .alive()
-        .revoked(false)
-        .key_flags(KeyFlags::empty().set_transport_encryption())
-        .next()
-        .is_some()
-}
+            // Sign the message before encrypting it, if a signing key is configured
+            let signing_key = signing_key
+                .map(|key| {
+                    openpgp::Cert::from_bytes(key).map_err(|err| {
+                        EncryptMessageError::Error(format!(
+                            "Failed to parse OpenPGP signing key: {}",
+                            err
+                        ))
+                    })
+                })
+                .transpose()?;
 
-#[allow(clippy::type_complexity)]
-fn try_parse_pem(
-    bytes_: &[u8],
-) -> Result<Option<(EncryptionMethod, Vec<Vec<u8>>)>, Cow<'static, str>> {
-    if let Some(internal) = std::str::from_utf8(bytes_)
-        .ok()
-        .and_then(|cert| cert.strip_prefix("-----STALWART CERTIFICATE-----"))
-    {
-        return base64_decode(internal.as_bytes())
-            .ok_or(Cow::from("Failed to decode base64"))
-            .and_then(|bytes| {
-                Archive::deserialize_owned(bytes)
-                    .and_then(|arch| arch.deserialize::<EncryptionParams>())
-                    .map_err(|_| Cow::from("Failed to deserialize internal certificate"))
-            })
-            .map(|params| Some((params.method, params.certs)));
-    }
+            // Compression only pays off for text, attachments are usually compressed already
+            let compression = match params.compression.as_ref() {
+                Some(ArchivedCompression::None) => None,
+                Some(ArchivedCompression::Zip) => Some(CompressionAlgorithm::Zip),
+                Some(ArchivedCompression::Zlib) => Some(CompressionAlgorithm::Zlib),
+                None => is_text_heavy(message).then_some(CompressionAlgorithm::Zlib),
+            };
 
-    let mut bytes = bytes_.iter().enumerate();
-    let mut buf = vec![];
-    let mut method = None;
-    let mut certs = vec![];
+            let algo = params.algo;
+            if let Some(inner_message) = inner_message {
+                outer_message = tokio::task::spawn_blocking(move || {
+                    let policy = openpgp::policy::StandardPolicy::new();
 
-    loop {
-        // Find start of PEM block
-        let mut start_pos = 0;
-        for (pos, &ch) in bytes.by_ref() {
-            if ch.is_ascii_whitespace() {
-                continue;
-            } else if ch == b'-' {
-                start_pos = pos;
-                break;
-            } else {
-                return Ok(None);
+                    // The armored output is written directly into the outer message,
+                    // converting its bare LF line endings
+                    let mut sink = CrLfWriter(&mut outer_message);
+                    let mut message = pgp_literal_writer(
+                        &mut sink,
+                        &certs,
+                        signing_key.as_ref(),
+                        &policy,
+                        algo,
+                        compression,
+                    )?;
+                    message.write_all(&inner_message).map_err(|err| {
+                        EncryptMessageError::Error(format!("Failed to encrypt message: {}", err))
+                    })?;
+                    drop(inner_message);
+                    message.finalize().map_err(|err| {
+                        EncryptMessageError::Error(format!("Failed to finalize message: {}", err))
+                    })?;
+
+                    Ok(outer_message)
+                })
+                .await
+                .map_err(|err| {
+                    EncryptMessageError::Error(format!("Failed to encrypt message: {}", err))
+                })??;
+            } else {
+                outer_message = encrypt_streamed(&inner_parts, move |chunks| {
+                    let policy = openpgp::policy::StandardPolicy::new();
+                    let mut sink = CrLfWriter(&mut outer_message);
+                    let mut message = pgp_literal_writer(
+                        &mut sink,
+                        &certs,
+                        signing_key.as_ref(),
+                        &policy,
+                        algo,
+                        compression,
+                    )?;
+                    for chunk in chunks {
+                        message.write_all(&chunk).map_err(|err| {
+                            EncryptMessageError::Error(format!(
+                                "Failed to encrypt message: {}",
+                                err
+                            ))
+                        })?;
+                    }
+                    message.finalize().map_err(|err| {
+                        EncryptMessageError::Error(format!("Failed to finalize message: {}", err))
+                    })?;
+
+                    Ok(outer_message)
+                })
+                .await??;
+            }
+            outer_message.extend_from_slice(b"\r\n--");
+            outer_message.extend_from_slice(boundary.as_bytes());
+            outer_message.extend_from_slice(b"--\r\n");
+        }
+        ArchivedEncryptionMethod::SMIME => {
+            // Generate random IV
+            let mut rng = StdRng::from_entropy();
+            let mut iv = vec![0u8; params.algo.iv_size()];
+            rng.fill_bytes(&mut iv);
+
+            // Generate random key
+            let mut key = vec![0u8; params.algo.key_size()];
+            rng.fill_bytes(&mut key);
+
+            // Encrypt contents and wrap the key for each recipient in parallel
+            let algo = params.algo;
+            let certs = identity_certs(identities)
+                .map(|(cert, padding)| (cert.to_vec(), matches!(padding, ArchivedRsaPadding::Oaep)))
+                .collect::<Vec<_>>();
+            // Streamed contents are encrypted once the envelope has been written
+            let (encrypted_contents, recipient_infos, iv, key) =
+                tokio::task::spawn_blocking(move || {
+                    let encrypted_contents = inner_message
+                        .map(|inner_message| algo.encrypt(&key, &iv, &inner_message))
+                        .transpose();
+                    let recipient_infos = certs
+                        .par_iter()
+                        .map_init(StdRng::from_entropy, |rng, (cert, use_oaep)| {
+                            wrap_smime_key(rng, cert, &key, *use_oaep)
+                        })
+                        .collect::<Vec<_>>();
+                    (encrypted_contents, recipient_infos, iv, key)
+                })
+                .await
+                .map_err(|err| {
+                    EncryptMessageError::Error(format!("Failed to encrypt message: {}", err))
+                })?;
+            let encrypted_contents = encrypted_contents.map_err(|err| {
+                EncryptMessageError::Error(format!("Failed to encrypt message: {}", err))
+            })?;
+
+            // Errors are reported in certificate order, the set orders recipients
+            #[allow(clippy::mutable_key_type)]
+            let recipient_infos = recipient_infos
+                .into_iter()
+                .filter_map(Result::transpose)
+                .collect::<Result<BTreeSet<_>, _>>()?;
+            if recipient_infos.is_empty() {
+                return Err(EncryptMessageError::Error(
+                    "None of the certificates are allowed to be used for encryption".to_string(),
+                ));
+            }
+
+            // The encoded parameters must carry exactly the IV used for encryption
+            let iv_len = iv.len();
+            let parameters = params
+                .algo
+                .to_algorithm_parameters(iv.clone())
+                .map_err(|err| {
+                    EncryptMessageError::Error(format!("Failed to encode IV: {}", err))
+                })?;
+            if params
+                .algo
+                .parse_algorithm_parameters(&parameters)
+                .is_none_or(|iv| iv.len() != iv_len)
+            {
+                return Err(EncryptMessageError::Error(format!(
+                    "Invalid IV length {iv_len} for {}",
+                    Algorithm::from(params.algo)
+                )));
+            }
+
+            let encrypted_content_info = EncryptedContentInfo {
+                content_type: CONTENT_DATA.into(),
+                content_encryption_algorithm: AlgorithmIdentifier {
+                    algorithm: params.algo.to_algorithm_identifier(),
+                    parameters: Some(parameters.into()),
+                },
+                encrypted_content: None,
+            };
+
+            // Key agreement recipients require version 2 (RFC 5652, section 6.1)
+            let envelope_version = if recipient_infos
+                .iter()
+                .any(|ri| matches!(ri, RecipientInfo::KeyAgreeRecipientInfo(_)))
+            {
+                2
+            } else {
+                0
+            };
+
+            // Authenticated ciphers are wrapped in AuthEnvelopedData (RFC 5083)
+            let (content_type, smime_type, content) = if params.algo.is_authenticated() {
+                // Authenticated ciphers are never streamed
+                let mut encrypted_contents = encrypted_contents.unwrap_or_default();
+                let mac = encrypted_contents
+                    .split_off(encrypted_contents.len().saturating_sub(GCM_TAG_LEN));
+                (
+                    CONTENT_AUTH_ENVELOPED_DATA,
+                    "authEnveloped-data",
+                    rasn::der::encode(&AuthEnvelopedData {
+                        version: 0.into(),
+                        originator_info: None,
+                        recipient_infos,
+                        auth_encrypted_content_info: EncryptedContentInfo {
+                            encrypted_content: Some(EncryptedContent::from(encrypted_contents)),
+                            ..encrypted_content_info
+                        },
+                        auth_attrs: None,
+                        mac: OctetString::from(mac),
+                        unauth_attrs: None,
+                    })
+                    .map_err(|err| {
+                        EncryptMessageError::Error(format!(
+                            "Failed to encode AuthEnvelopedData: {}",
+                            err
+                        ))
+                    })?,
+                )
+            } else {
+                (
+                    CONTENT_ENVELOPED_DATA,
+                    "enveloped-data",
+                    rasn::der::encode(&EnvelopedData {
+                        version: envelope_version.into(),
+                        originator_info: None,
+                        recipient_infos,
+                        encrypted_content_info: EncryptedContentInfo {
+                            encrypted_content: encrypted_contents.map(EncryptedContent::from),
+                            ..encrypted_content_info
+                        },
+                        unprotected_attrs: None,
+                    })
+                    .map_err(|err| {
+                        EncryptMessageError::Error(format!(
+                            "Failed to encode EnvelopedData: {}",
+                            err
+                        ))
+                    })?,
+                )
+            };
+
+            // Streamed envelopes are encoded without their contents, the header of the
+            // encrypted content is appended and the ciphertext follows it
+            let (content, content_len) = if is_streamed {
+                let encrypted_len = (inner_len / CBC_IV_LEN + 1) * CBC_IV_LEN;
+                enveloped_data_prefix(&content, encrypted_len).ok_or_else(|| {
+                    EncryptMessageError::Error("Failed to encode EnvelopedData".to_string())
+                })?
+            } else {
+                let content_len = content.len();
+                (content, content_len)
+            };
+
+            // The ContentInfo header is written separately to avoid copying the
+            // encoded envelope into a second buffer
+            let header = content_info_header(content_type, content_len).map_err(|err| {
+                EncryptMessageError::Error(format!("Failed to encode ContentInfo: {}", err))
+            })?;
+
+            // Generate message
+            outer_message.extend_from_slice(
+                concat!(
+                    "Content-Type: application/pkcs7-mime;\r\n",
+                    "\tname=\"smime.p7m\";\r\n",
+                    "\tsmime-type="
+                )
+                .as_bytes(),
+            );
+            outer_message.extend_from_slice(smime_type.as_bytes());
+            outer_message.extend_from_slice(
+                concat!(
+                    "\r\nContent-Disposition: attachment;\r\n",
+                    "\tfilename=\"smime.p7m\"\r\n",
+                    "Content-Transfer-Encoding: base64\r\n\r\n"
+                )
+                .as_bytes(),
+            );
+            // Streamed contents are encrypted and encoded after the envelope
+            let algo = params.algo;
+            let encode = move |outer_message: &mut Vec<u8>, chunks: Option<PlaintextChunks>| {
+                let mut writer = Base64MimeWriter::new(outer_message);
+                writer.write_all(&header)?;
+                writer.write_all(&content)?;
+                match (chunks, algo) {
+                    (Some(chunks), ArchivedAlgorithm::Aes128) => write_cbc_encrypted(
+                        cbc::Encryptor::<aes::Aes128>::new(
+                            key.as_slice().into(),
+                            iv.as_slice().into(),
+                        ),
+                        chunks,
+                        &mut writer,
+                    )?,
+                    (Some(chunks), _) => write_cbc_encrypted(
+                        cbc::Encryptor::<aes::Aes256>::new(
+                            key.as_slice().into(),
+                            iv.as_slice().into(),
+                        ),
+                        chunks,
+                        &mut writer,
+                    )?,
+                    (None, _) => (),
+                }
+                writer.finish()
+            };
+            outer_message = if is_streamed {
+                encrypt_streamed(&inner_parts, move |chunks| {
+                    encode(&mut outer_message, Some(chunks)).map(|_| outer_message)
+                })
+                .await?
+            } else {
+                encode(&mut outer_message, None).map(|_| outer_message)
+            }
+            .map_err(|err| {
+                EncryptMessageError::Error(format!("Failed to encode PKCS7: {}", err))
+            })?;
+        }
+    }
+
+    Ok(outer_message)
+}
+
+pub trait DecryptMessage {
+    fn decrypt(&self, private_keys: &[DecryptionKey]) -> Result<Vec<u8>, DecryptMessageError>;
+}
+
+impl DecryptMessage for Message<'_> {
+    fn decrypt(&self, private_keys: &[DecryptionKey]) -> Result<Vec<u8>, DecryptMessageError> {
+        let root = self.root_part();
+        let Some(ct) = root.content_type() else {
+            return Err(DecryptMessageError::NotEncrypted);
+        };
+        let main_type = ct.c_type.as_ref();
+        let sub_type = ct
+            .c_subtype
+            .as_ref()
+            .map(|s| s.as_ref())
+            .unwrap_or_default();
+
+        let inner_message = if main_type.eq_ignore_ascii_case("multipart")
+            && sub_type.eq_ignore_ascii_case("encrypted")
+        {
+            // The second part contains the OpenPGP message (RFC 3156, section 4)
+            let part = match &root.body {
+                PartType::Multipart(parts) => parts.get(1).and_then(|id| self.part(*id)),
+                _ => None,
+            }
+            .ok_or_else(|| DecryptMessageError::Error("Missing OpenPGP message".to_string()))?;
+            decrypt_pgp(part.contents(), private_keys)?
+        } else if main_type.eq_ignore_ascii_case("application")
+            && (sub_type.eq_ignore_ascii_case("pkcs7-mime")
+                || sub_type.eq_ignore_ascii_case("x-pkcs7-mime"))
+        {
+            decrypt_smime(root.contents(), private_keys)?
+        } else {
+            return Err(DecryptMessageError::NotEncrypted);
+        };
+
+        // Reverse the header split performed by encrypt(), the content headers
+        // and MIME-Version are taken from the decrypted part
+        let raw_message = self.raw_message();
+        let mut message = Vec::with_capacity(raw_message.len());
+        for header in root.headers() {
+            if !matches!(
+                header.name,
+                HeaderName::ContentType
+                    | HeaderName::ContentTransferEncoding
+                    | HeaderName::ContentDisposition
+                    | HeaderName::ContentId
+                    | HeaderName::ContentDescription
+                    | HeaderName::MimeVersion
+            ) {
+                message.extend_from_slice(
+                    &raw_message[header.offset_field() as usize..header.offset_end() as usize],
+                );
+            }
+        }
+        message.extend_from_slice(&inner_message);
+
+        Ok(message)
+    }
+}
+
+impl DecryptionKey {
+    // Accepts PEM encoded PKCS#8, PKCS#1 or SEC1 keys and OpenPGP secret keys
+    pub fn try_parse(bytes: &[u8]) -> Result<Self, DecryptMessageError> {
+        let pem = std::str::from_utf8(bytes)
+            .ok()
+            .map(|pem| pem.trim())
+            .filter(|pem| pem.starts_with("-----BEGIN ") && !pem.starts_with("-----BEGIN PGP"));
+
+        if let Some(pem) = pem {
+            if let Ok(key) = RsaPrivateKey::from_pkcs8_pem(pem) {
+                Ok(DecryptionKey::Rsa(key))
+            } else if let Ok(key) = RsaPrivateKey::from_pkcs1_pem(pem) {
+                Ok(DecryptionKey::Rsa(key))
+            } else if let Ok(key) = p256::SecretKey::from_pkcs8_pem(pem) {
+                Ok(DecryptionKey::P256(key))
+            } else if let Ok(key) = p256::SecretKey::from_sec1_pem(pem) {
+                Ok(DecryptionKey::P256(key))
+            } else if let Ok(key) = p384::SecretKey::from_pkcs8_pem(pem) {
+                Ok(DecryptionKey::P384(key))
+            } else if let Ok(key) = p384::SecretKey::from_sec1_pem(pem) {
+                Ok(DecryptionKey::P384(key))
+            } else {
+                Err(DecryptMessageError::Error(
+                    "Unsupported private key format".to_string(),
+                ))
+            }
+        } else {
+            let cert = openpgp::Cert::from_bytes(bytes).map_err(|err| {
+                DecryptMessageError::Error(format!("Failed to parse OpenPGP key: {}", err))
+            })?;
+            if cert.is_tsk() {
+                Ok(DecryptionKey::Pgp(cert))
+            } else {
+                Err(DecryptMessageError::Error(
+                    "OpenPGP key does not contain secret key material".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+fn decrypt_smime(
+    contents: &[u8],
+    private_keys: &[DecryptionKey],
+) -> Result<Vec<u8>, DecryptMessageError> {
+    let content_info = rasn::der::decode::<EncapsulatedContentInfo>(contents).map_err(|err| {
+        DecryptMessageError::Error(format!("Failed to decode ContentInfo: {}", err))
+    })?;
+    let content = content_info
+        .content
+        .ok_or_else(|| DecryptMessageError::Error("Missing enveloped data".to_string()))?;
+    let content_type: &Oid = &content_info.content_type;
+    let (recipient_infos, encrypted_content_info, mac) = if content_type
+        == CONTENT_AUTH_ENVELOPED_DATA
+    {
+        let data = rasn::der::decode::<AuthEnvelopedData>(content.as_bytes()).map_err(|err| {
+            DecryptMessageError::Error(format!("Failed to decode AuthEnvelopedData: {}", err))
+        })?;
+        (
+            data.recipient_infos,
+            data.auth_encrypted_content_info,
+            Some(data.mac),
+        )
+    } else if content_type == CONTENT_ENVELOPED_DATA {
+        let data = rasn::der::decode::<EnvelopedData>(content.as_bytes()).map_err(|err| {
+            DecryptMessageError::Error(format!("Failed to decode EnvelopedData: {}", err))
+        })?;
+        (data.recipient_infos, data.encrypted_content_info, None)
+    } else {
+        return Err(DecryptMessageError::NotEncrypted);
+    };
+
+    // Obtain the cipher and its IV or nonce
+    let algorithm = &encrypted_content_info.content_encryption_algorithm;
+    let algo =
+        ArchivedAlgorithm::from_algorithm_identifier(&algorithm.algorithm).ok_or_else(|| {
+            DecryptMessageError::Error("Unsupported content encryption algorithm".to_string())
+        })?;
+    let iv = algorithm
+        .parameters
+        .as_ref()
+        .and_then(|params| algo.parse_algorithm_parameters(params.as_bytes()))
+        .ok_or_else(|| {
+            DecryptMessageError::Error("Invalid content encryption parameters".to_string())
+        })?;
+    let mut encrypted_contents = encrypted_content_info
+        .encrypted_content
+        .map(|contents| contents.to_vec())
+        .ok_or_else(|| DecryptMessageError::Error("Missing encrypted content".to_string()))?;
+    match (algo.is_authenticated(), mac) {
+        (true, Some(mac)) => encrypted_contents.extend_from_slice(&mac),
+        (false, None) => (),
+        _ => {
+            return Err(DecryptMessageError::Error(
+                "Content encryption algorithm does not match the envelope".to_string(),
+            ));
+        }
+    }
+
+    // Unwrapping with the wrong RSA key can succeed by chance, so each candidate
+    // key is tried until the contents decrypt
+    let mut has_candidates = false;
+    for key in recipient_infos.iter().flat_map(|info| {
+        private_keys
+            .iter()
+            .filter_map(move |private_key| unwrap_smime_key(info, private_key))
+    }) {
+        has_candidates = true;
+        if let Ok(contents) = decrypt_contents(&algo, &key, &iv, &encrypted_contents) {
+            return Ok(contents);
+        }
+    }
+
+    Err(if has_candidates {
+        DecryptMessageError::Error("Failed to decrypt message contents".to_string())
+    } else {
+        DecryptMessageError::NoMatchingKey
+    })
+}
+
+fn unwrap_smime_key(info: &RecipientInfo, private_key: &DecryptionKey) -> Option<Vec<u8>> {
+    match (info, private_key) {
+        (RecipientInfo::KeyTransRecipientInfo(info), DecryptionKey::Rsa(private_key)) => {
+            let algorithm: &Oid = &info.key_encryption_algorithm.algorithm;
+            if algorithm == RSA {
+                private_key
+                    .decrypt(Pkcs1v15Encrypt, &info.encrypted_key[..])
+                    .ok()
+            } else if algorithm == RSAES_OAEP
+                && info
+                    .key_encryption_algorithm
+                    .parameters
+                    .as_ref()
+                    .is_some_and(|params| params.as_bytes() == RSAES_OAEP_SHA256_PARAMS)
+            {
+                private_key
+                    .decrypt(Oaep::new::<sha2::Sha256>(), &info.encrypted_key[..])
+                    .ok()
+            } else {
+                None
+            }
+        }
+        (
+            RecipientInfo::KeyAgreeRecipientInfo(info),
+            DecryptionKey::P256(_) | DecryptionKey::P384(_),
+        ) => {
+            let OriginatorIdentifierOrKey::OriginatorPublicKey(originator) = &info.originator
+            else {
+                return None;
+            };
+            let ephemeral_key = originator.public_key.as_raw_slice();
+            let shared_secret = match private_key {
+                DecryptionKey::P256(private_key) => p256::ecdh::diffie_hellman(
+                    private_key.to_nonzero_scalar(),
+                    p256::PublicKey::from_sec1_bytes(ephemeral_key)
+                        .ok()?
+                        .as_affine(),
+                )
+                .raw_secret_bytes()
+                .to_vec(),
+                DecryptionKey::P384(private_key) => p384::ecdh::diffie_hellman(
+                    private_key.to_nonzero_scalar(),
+                    p384::PublicKey::from_sec1_bytes(ephemeral_key)
+                        .ok()?
+                        .as_affine(),
+                )
+                .raw_secret_bytes()
+                .to_vec(),
+                _ => return None,
+            };
+
+            // Derive the key-encryption key as done by wrap_smime_key_ecdh
+            let wrap_algorithm = rasn::der::decode::<AlgorithmIdentifier>(
+                info.key_encryption_algorithm
+                    .parameters
+                    .as_ref()?
+                    .as_bytes(),
+            )
+            .ok()?;
+            let wrap_oid: &Oid = &wrap_algorithm.algorithm;
+            let key_size = if wrap_oid == AES128_WRAP {
+                16
+            } else if wrap_oid == AES192_WRAP {
+                24
+            } else if wrap_oid == AES256_WRAP {
+                32
+            } else {
+                return None;
+            };
+            let shared_info = rasn::der::encode(&EccCmsSharedInfo {
+                key_info: wrap_algorithm,
+                entity_u_info: info.user_keying_material.clone(),
+                supp_pub_info: OctetString::from(((key_size * 8) as u32).to_be_bytes().to_vec()),
+            })
+            .ok()?;
+            let kdf_algorithm: &Oid = &info.key_encryption_algorithm.algorithm;
+            let kek = if kdf_algorithm == ECDH_SHA256_KDF {
+                x963_kdf::<Sha256>(&shared_secret, &shared_info, key_size)
+            } else if kdf_algorithm == ECDH_SHA384_KDF {
+                x963_kdf::<Sha384>(&shared_secret, &shared_info, key_size)
+            } else {
+                return None;
+            };
+
+            info.recipient_encrypted_keys
+                .iter()
+                .find_map(|key| aes_key_unwrap(&kek, &key.encrypted_key))
+        }
+        _ => None,
+    }
+}
+
+fn decrypt_pgp(
+    contents: &[u8],
+    private_keys: &[DecryptionKey],
+) -> Result<Vec<u8>, DecryptMessageError> {
+    let certs = private_keys
+        .iter()
+        .filter_map(|key| match key {
+            DecryptionKey::Pgp(cert) => Some(cert),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if certs.is_empty() {
+        return Err(DecryptMessageError::NoMatchingKey);
+    }
+
+    let mut decryptor = DecryptorBuilder::from_bytes(contents)
+        .and_then(|builder| builder.with_policy(&P, None, PgpDecryptHelper { certs }))
+        .map_err(|err| {
+            DecryptMessageError::Error(format!("Failed to decrypt OpenPGP message: {}", err))
+        })?;
+    let mut message = Vec::with_capacity(contents.len());
+    std::io::copy(&mut decryptor, &mut message).map_err(|err| {
+        DecryptMessageError::Error(format!("Failed to decrypt OpenPGP message: {}", err))
+    })?;
+
+    Ok(message)
+}
+
+struct PgpDecryptHelper<'x> {
+    certs: Vec<&'x openpgp::Cert>,
+}
+
+// Signatures are not verified, the decrypted message is returned as-is
+impl VerificationHelper for PgpDecryptHelper<'_> {
+    fn get_certs(&mut self, _: &[KeyHandle]) -> openpgp::Result<Vec<openpgp::Cert>> {
+        Ok(vec![])
+    }
+
+    fn check(&mut self, _: MessageStructure) -> openpgp::Result<()> {
+        Ok(())
+    }
+}
+
+impl DecryptionHelper for PgpDecryptHelper<'_> {
+    fn decrypt(
+        &mut self,
+        pkesks: &[PKESK],
+        _: &[SKESK],
+        sym_algo: Option<SymmetricAlgorithm>,
+        decrypt: &mut dyn FnMut(Option<SymmetricAlgorithm>, &SessionKey) -> bool,
+    ) -> openpgp::Result<Option<openpgp::Cert>> {
+        for cert in &self.certs {
+            for key in cert
+                .keys()
+                .unencrypted_secret()
+                .with_policy(&P, None)
+                .key_flags(pgp_encryption_flags())
+            {
+                let mut keypair = key.key().clone().into_keypair()?;
+                for pkesk in pkesks {
+                    if pkesk
+                        .decrypt(&mut keypair, sym_algo)
+                        .is_some_and(|(algo, session_key)| decrypt(algo, &session_key))
+                    {
+                        return Ok(Some((*cert).clone()));
+                    }
+                }
+            }
+        }
+
+        Err(openpgp::Error::MissingSessionKey("No matching OpenPGP key".to_string()).into())
+    }
+}
+
+// Certificates shared by several identities are only used once, RSA padding
+// is configured per identity
+fn identity_certs(
+    identities: &[ArchivedEncryptionParams],
+) -> impl Iterator<Item = (&[u8], &ArchivedRsaPadding)> {
+    let mut seen = Vec::new();
+    identities
+        .iter()
+        .flat_map(|identity| {
+            identity
+                .certs
+                .iter()
+                .map(move |cert| (cert.as_slice(), &identity.padding))
+        })
+        .filter(move |(cert, _)| {
+            if seen.contains(cert) {
+                false
+            } else {
+                seen.push(*cert);
+                true
+            }
+        })
+}
+
+fn has_inline_pgp_armor(text: &str) -> bool {
+    let mut lines = text.lines().map(|line| line.trim());
+    lines.any(|line| line == "-----BEGIN PGP MESSAGE-----")
+        && lines.any(|line| line == "-----END PGP MESSAGE-----")
+}
+
+// Text parts make up at least half of the message
+fn is_text_heavy(message: &Message<'_>) -> bool {
+    let text_size = message
+        .parts
+        .iter()
+        .filter(|part| matches!(part.body, PartType::Text(_) | PartType::Html(_)))
+        .map(|part| part.raw_end_offset().saturating_sub(part.raw_body_offset()) as usize)
+        .sum::<usize>();
+
+    text_size * 2 >= message.raw_message().len()
+}
+
+impl ArchivedEncryptionParams {
+    pub fn is_excluded(&self, mailbox_ids: &[u32]) -> bool {
+        !mailbox_ids.is_empty()
+            && mailbox_ids.iter().all(|mailbox_id| {
+                self.exclude_mailboxes
+                    .iter()
+                    .any(|id| id.to_native() == *mailbox_id)
+            })
+    }
+
+    // The account override can lower the server limit but never raise it
+    pub fn max_encrypt_size(&self, server_max_size: usize) -> u64 {
+        let server_max_size = server_max_size as u64;
+        self.max_encrypt_size
+            .as_ref()
+            .map_or(server_max_size, |size| {
+                size.to_native().min(server_max_size)
+            })
+    }
+}
+
+pub trait EncryptionIdentities {
+    fn is_excluded(&self, mailbox_ids: &[u32]) -> bool;
+    fn max_encrypt_size(&self, server_max_size: usize) -> u64;
+}
+
+// Messages are left unencrypted only when no identity wants to receive them
+impl EncryptionIdentities for [ArchivedEncryptionParams] {
+    fn is_excluded(&self, mailbox_ids: &[u32]) -> bool {
+        self.iter()
+            .all(|identity| identity.is_excluded(mailbox_ids))
+    }
+
+    fn max_encrypt_size(&self, server_max_size: usize) -> u64 {
+        self.iter()
+            .map(|identity| identity.max_encrypt_size(server_max_size))
+            .max()
+            .unwrap_or(server_max_size as u64)
+    }
+}
+
+impl From<EncryptionParamsV1> for EncryptionParams {
+    fn from(params: EncryptionParamsV1) -> Self {
+        EncryptionParams {
+            method: params.method,
+            algo: params.algo,
+            padding: RsaPadding::default(),
+            certs: params.certs,
+            exclude_mailboxes: Vec::new(),
+            max_encrypt_size: None,
+            compression: None,
+        }
+    }
+}
+
+impl Algorithm {
+    pub fn key_size(&self) -> usize {
+        match self {
+            Algorithm::Aes128 | Algorithm::Aes128Gcm => 16,
+            Algorithm::Aes256 | Algorithm::Aes256Gcm | Algorithm::Chacha20Poly1305 => 32,
+        }
+    }
+
+    pub fn iv_size(&self) -> usize {
+        if self.is_authenticated() {
+            GCM_NONCE_LEN
+        } else {
+            CBC_IV_LEN
+        }
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        matches!(
+            self,
+            Algorithm::Aes128Gcm | Algorithm::Aes256Gcm | Algorithm::Chacha20Poly1305
+        )
+    }
+}
+
+impl From<ArchivedAlgorithm> for Algorithm {
+    fn from(algo: ArchivedAlgorithm) -> Self {
+        match algo {
+            ArchivedAlgorithm::Aes128 => Algorithm::Aes128,
+            ArchivedAlgorithm::Aes256 => Algorithm::Aes256,
+            ArchivedAlgorithm::Aes128Gcm => Algorithm::Aes128Gcm,
+            ArchivedAlgorithm::Aes256Gcm => Algorithm::Aes256Gcm,
+            ArchivedAlgorithm::Chacha20Poly1305 => Algorithm::Chacha20Poly1305,
+        }
+    }
+}
+
+impl From<&ArchivedEncryptionMethod> for EncryptionMethod {
+    fn from(method: &ArchivedEncryptionMethod) -> Self {
+        match method {
+            ArchivedEncryptionMethod::PGP => EncryptionMethod::PGP,
+            ArchivedEncryptionMethod::SMIME => EncryptionMethod::SMIME,
+        }
+    }
+}
+
+impl From<&ArchivedRsaPadding> for RsaPadding {
+    fn from(padding: &ArchivedRsaPadding) -> Self {
+        match padding {
+            ArchivedRsaPadding::Pkcs1v15 => RsaPadding::Pkcs1v15,
+            ArchivedRsaPadding::Oaep => RsaPadding::Oaep,
+        }
+    }
+}
+
+impl From<&ArchivedCompression> for Compression {
+    fn from(compression: &ArchivedCompression) -> Self {
+        match compression {
+            ArchivedCompression::None => Compression::None,
+            ArchivedCompression::Zip => Compression::Zip,
+            ArchivedCompression::Zlib => Compression::Zlib,
+        }
+    }
+}
+
+impl ArchivedAlgorithm {
+    fn key_size(&self) -> usize {
+        Algorithm::from(*self).key_size()
+    }
+
+    fn iv_size(&self) -> usize {
+        Algorithm::from(*self).iv_size()
+    }
+
+    fn is_authenticated(&self) -> bool {
+        Algorithm::from(*self).is_authenticated()
+    }
+
+    fn to_algorithm_identifier(self) -> ObjectIdentifier {
+        match self {
+            ArchivedAlgorithm::Aes128 => AES128_CBC.into(),
+            ArchivedAlgorithm::Aes256 => AES256_CBC.into(),
+            ArchivedAlgorithm::Aes128Gcm => AES128_GCM.into(),
+            ArchivedAlgorithm::Aes256Gcm => AES256_GCM.into(),
+            ArchivedAlgorithm::Chacha20Poly1305 => CHACHA20_POLY1305.into(),
+        }
+    }
+
+    fn to_algorithm_parameters(self, iv: Vec<u8>) -> Result<Vec<u8>, rasn::error::EncodeError> {
+        match self {
+            ArchivedAlgorithm::Aes128Gcm | ArchivedAlgorithm::Aes256Gcm => {
+                rasn::der::encode(&GcmParameters {
+                    nonce: OctetString::from(iv),
+                    icv_len: Some(GCM_TAG_LEN as u32),
+                })
+            }
+            // CBC IVs and AEADChaCha20Poly1305Nonce are bare octet strings
+            _ => rasn::der::encode(&OctetString::from(iv)),
+        }
+    }
+
+    fn from_algorithm_identifier(oid: &Oid) -> Option<Self> {
+        if oid == AES128_CBC {
+            Some(ArchivedAlgorithm::Aes128)
+        } else if oid == AES256_CBC {
+            Some(ArchivedAlgorithm::Aes256)
+        } else if oid == AES128_GCM {
+            Some(ArchivedAlgorithm::Aes128Gcm)
+        } else if oid == AES256_GCM {
+            Some(ArchivedAlgorithm::Aes256Gcm)
+        } else if oid == CHACHA20_POLY1305 {
+            Some(ArchivedAlgorithm::Chacha20Poly1305)
+        } else {
+            None
+        }
+    }
+
+    fn parse_algorithm_parameters(self, params: &[u8]) -> Option<Vec<u8>> {
+        let iv = match self {
+            ArchivedAlgorithm::Aes128Gcm | ArchivedAlgorithm::Aes256Gcm => {
+                let params = rasn::der::decode::<GcmParameters>(params).ok()?;
+                if params.icv_len.is_some_and(|len| len != GCM_TAG_LEN as u32) {
+                    return None;
+                }
+                params.nonce
+            }
+            _ => rasn::der::decode::<OctetString>(params).ok()?,
+        };
+
+        if iv.len() == self.iv_size() {
+            Some(iv.to_vec())
+        } else {
+            None
+        }
+    }
+
+    fn encrypt(&self, key: &[u8], iv: &[u8], contents: &[u8]) -> Result<Vec<u8>, aes_gcm::Error> {
+        match self {
+            ArchivedAlgorithm::Aes128 => {
+                Ok(cbc::Encryptor::<aes::Aes128>::new(key.into(), iv.into())
+                    .encrypt_padded_vec_mut::<Pkcs7>(contents))
+            }
+            ArchivedAlgorithm::Aes256 => {
+                Ok(cbc::Encryptor::<aes::Aes256>::new(key.into(), iv.into())
+                    .encrypt_padded_vec_mut::<Pkcs7>(contents))
+            }
+            ArchivedAlgorithm::Aes128Gcm => {
+                Aes128Gcm::new(key.into()).encrypt(Nonce::from_slice(iv), contents)
+            }
+            ArchivedAlgorithm::Aes256Gcm => {
+                Aes256Gcm::new(key.into()).encrypt(Nonce::from_slice(iv), contents)
+            }
+            ArchivedAlgorithm::Chacha20Poly1305 => {
+                // Returns the ciphertext followed by the tag, the nonce is sent as a parameter
+                let key = LessSafeKey::new(
+                    UnboundKey::new(&CHACHA20_POLY1305_AEAD, key).map_err(|_| aes_gcm::Error)?,
+                );
+                let nonce =
+                    ring::aead::Nonce::try_assume_unique_for_key(iv).map_err(|_| aes_gcm::Error)?;
+                let mut contents = contents.to_vec();
+                key.seal_in_place_append_tag(nonce, Aad::empty(), &mut contents)
+                    .map_err(|_| aes_gcm::Error)?;
+                Ok(contents)
+            }
+        }
+    }
+}
+
+// Expects the ciphertext followed by the tag for authenticated ciphers, keys
+// of the wrong size are rejected
+fn decrypt_contents(
+    algo: &ArchivedAlgorithm,
+    key: &[u8],
+    iv: &[u8],
+    contents: &[u8],
+) -> Result<Vec<u8>, aes_gcm::Error> {
+    match algo {
+        ArchivedAlgorithm::Aes128 => cbc::Decryptor::<aes::Aes128>::new_from_slices(key, iv)
+            .map_err(|_| aes_gcm::Error)?
+            .decrypt_padded_vec_mut::<Pkcs7>(contents)
+            .map_err(|_| aes_gcm::Error),
+        ArchivedAlgorithm::Aes256 => cbc::Decryptor::<aes::Aes256>::new_from_slices(key, iv)
+            .map_err(|_| aes_gcm::Error)?
+            .decrypt_padded_vec_mut::<Pkcs7>(contents)
+            .map_err(|_| aes_gcm::Error),
+        ArchivedAlgorithm::Aes128Gcm => Aes128Gcm::new_from_slice(key)
+            .map_err(|_| aes_gcm::Error)?
+            .decrypt(Nonce::from_slice(iv), contents),
+        ArchivedAlgorithm::Aes256Gcm => Aes256Gcm::new_from_slice(key)
+            .map_err(|_| aes_gcm::Error)?
+            .decrypt(Nonce::from_slice(iv), contents),
+        ArchivedAlgorithm::Chacha20Poly1305 => {
+            let key = LessSafeKey::new(
+                UnboundKey::new(&CHACHA20_POLY1305_AEAD, key).map_err(|_| aes_gcm::Error)?,
+            );
+            let nonce =
+                ring::aead::Nonce::try_assume_unique_for_key(iv).map_err(|_| aes_gcm::Error)?;
+            let mut contents = contents.to_vec();
+            let len = key
+                .open_in_place(nonce, Aad::empty(), &mut contents)
+                .map_err(|_| aes_gcm::Error)?
+                .len();
+            contents.truncate(len);
+            Ok(contents)
+        }
+    }
+}
+
+// Number of bytes encoded at a time, a multiple of the 57 bytes that fit in a MIME line
+pub const BASE64_MIME_BLOCK: usize = 57 * 1024;
+
+// Encodes data written to it as line-wrapped base64 without buffering the whole input
+pub struct Base64MimeWriter<'x> {
+    output: &'x mut Vec<u8>,
+    block: Vec<u8>,
+}
+
+impl<'x> Base64MimeWriter<'x> {
+    pub fn new(output: &'x mut Vec<u8>) -> Self {
+        Base64MimeWriter {
+            output,
+            block: Vec::with_capacity(BASE64_MIME_BLOCK),
+        }
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.flush_block(true)
+    }
+
+    fn flush_block(&mut self, is_last: bool) -> std::io::Result<()> {
+        let start = self.output.len();
+        base64_encode_mime(&self.block, &mut *self.output, false)?;
+        self.block.clear();
+
+        // Full blocks end on a line boundary, terminate them with a single CRLF
+        if !is_last {
+            while self.output.len() >= start + 2 && self.output.ends_with(b"\r\n") {
+                self.output.truncate(self.output.len() - 2);
+            }
+            self.output.extend_from_slice(b"\r\n");
+        }
+
+        Ok(())
+    }
+}
+
+impl Write for Base64MimeWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // Blocks are only encoded once more data follows, so the last block
+        // is always encoded with its padding and trailing line break
+        if self.block.len() == BASE64_MIME_BLOCK && !buf.is_empty() {
+            self.flush_block(false)?;
+        }
+        let len = buf.len().min(BASE64_MIME_BLOCK - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Plaintext chunks sent to a streamed encryption running on the blocking thread pool
+struct PlaintextChunks(tokio::sync::mpsc::Receiver<Vec<u8>>);
+
+impl Iterator for PlaintextChunks {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.blocking_recv()
+    }
+}
+
+// Runs a streamed encryption on the blocking thread pool. The parts are copied into it
+// one chunk at a time, so only a few chunks of the plaintext are held in memory.
+async fn encrypt_streamed<T: Send + 'static>(
+    parts: &[&[u8]],
+    encrypt: impl FnOnce(PlaintextChunks) -> T + Send + 'static,
+) -> Result<T, EncryptMessageError> {
+    let (tx, rx) = tokio::sync::mpsc::channel(ENCRYPT_CHANNEL_SIZE);
+    let task = tokio::task::spawn_blocking(move || encrypt(PlaintextChunks(rx)));
+    for chunk in parts
+        .iter()
+        .flat_map(|part| part.chunks(ENCRYPT_CHUNK_SIZE))
+    {
+        // The receiver is dropped when encryption fails, the error is returned by the task
+        if tx.send(chunk.to_vec()).await.is_err() {
+            break;
+        }
+    }
+    drop(tx);
+
+    task.await
+        .map_err(|err| EncryptMessageError::Error(format!("Failed to encrypt message: {}", err)))
+}
+
+// Encrypts the chunks using CBC with PKCS#7 padding
+fn write_cbc_encrypted<E: BlockEncryptMut>(
+    mut encryptor: E,
+    chunks: impl Iterator<Item = Vec<u8>>,
+    writer: &mut impl Write,
+) -> std::io::Result<()> {
+    let block_size = E::block_size();
+    let mut buf = Vec::with_capacity(ENCRYPT_CHUNK_SIZE + block_size);
+    for chunk in chunks {
+        buf.extend_from_slice(&chunk);
+        let len = buf.len() - buf.len() % block_size;
+        for block in buf[..len].chunks_exact_mut(block_size) {
+            encryptor.encrypt_block_mut(block.into());
+        }
+        writer.write_all(&buf[..len])?;
+        buf.drain(..len);
+    }
+
+    let len = buf.len();
+    buf.resize(len + block_size, 0);
+    writer.write_all(
+        encryptor
+            .encrypt_padded_mut::<Pkcs7>(&mut buf, len)
+            .map_err(|_| std::io::Error::other("Invalid padding"))?,
+    )
+}
+
+// Takes the DER encoding of an EnvelopedData without encrypted content and returns it
+// followed by the header of an encrypted content of the given length, together with
+// the total length of the EnvelopedData once the encrypted content is appended
+pub fn enveloped_data_prefix(der: &[u8], content_len: usize) -> Option<(Vec<u8>, usize)> {
+    // The EncryptedContentInfo is the last element when there are no unprotected attributes
+    let (header_len, body_len) = der_header(der)?;
+    let body = der.get(header_len..header_len + body_len)?;
+    let mut pos = 0;
+    let mut last = 0;
+    while pos < body.len() {
+        let (header_len, len) = der_header(&body[pos..])?;
+        last = pos;
+        pos += header_len + len;
+    }
+    let (header_len, info_len) = der_header(body.get(last..)?)?;
+    let info = body.get(last + header_len..last + header_len + info_len)?;
+
+    let info_len = info.len() + 1 + der_length_size(content_len) + content_len;
+    let envelope_len = last + 1 + der_length_size(info_len) + info_len;
+    let mut prefix = Vec::with_capacity(der.len() + 20);
+    prefix.push(0x30);
+    write_der_length(&mut prefix, envelope_len);
+    prefix.extend_from_slice(&body[..last]);
+    prefix.push(0x30);
+    write_der_length(&mut prefix, info_len);
+    prefix.extend_from_slice(info);
+    // encryptedContent [0] IMPLICIT OCTET STRING
+    prefix.push(0x80);
+    write_der_length(&mut prefix, content_len);
+
+    Some((prefix, 1 + der_length_size(envelope_len) + envelope_len))
+}
+
+// Returns the length of the header and contents of a DER element with a single byte tag
+fn der_header(der: &[u8]) -> Option<(usize, usize)> {
+    let len = *der.get(1)?;
+    if len < 0x80 {
+        Some((2, len as usize))
+    } else {
+        let num_bytes = (len & 0x7f) as usize;
+        if num_bytes == 0 || num_bytes > size_of::<usize>() {
+            return None;
+        }
+        let len = der
+            .get(2..2 + num_bytes)?
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        Some((2 + num_bytes, len))
+    }
+}
+
+// Returns the DER encoded ContentInfo header that precedes an encoded content of the given length
+pub fn content_info_header(
+    content_type: &Oid,
+    content_len: usize,
+) -> Result<Vec<u8>, rasn::error::EncodeError> {
+    let oid = rasn::der::encode(&ObjectIdentifier::from(content_type))?;
+    let explicit_len = 1 + der_length_size(content_len) + content_len;
+    let mut header = Vec::with_capacity(oid.len() + 20);
+    header.push(0x30);
+    write_der_length(&mut header, oid.len() + explicit_len);
+    header.extend_from_slice(&oid);
+    header.push(0xa0);
+    write_der_length(&mut header, content_len);
+    Ok(header)
+}
+
+fn der_length_size(len: usize) -> usize {
+    if len < 0x80 {
+        1
+    } else {
+        1 + (usize::BITS - len.leading_zeros()).div_ceil(8) as usize
+    }
+}
+
+fn write_der_length(buf: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        buf.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let num_bytes = der_length_size(len) - 1;
+        buf.push(0x80 | num_bytes as u8);
+        buf.extend_from_slice(&bytes[bytes.len() - num_bytes..]);
+    }
+}
+
+struct CrLfWriter<'x>(&'x mut Vec<u8>);
+
+impl Write for CrLfWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for line in buf.split_inclusive(|ch| *ch == b'\n') {
+            if let Some(line) = line.strip_suffix(b"\n") {
+                self.0.extend_from_slice(line);
+                self.0.extend_from_slice(b"\r\n");
+            } else {
+                self.0.extend_from_slice(line);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn try_parse_certs(
+    expected_method: EncryptionMethod,
+    cert: Vec<u8>,
+) -> Result<Vec<Vec<u8>>, CertParseError> {
+    try_parse_certs_with_password(expected_method, cert, None)
+}
+
+pub fn try_parse_certs_with_password(
+    expected_method: EncryptionMethod,
+    cert: Vec<u8>,
+    password: Option<&str>,
+) -> Result<Vec<Vec<u8>>, CertParseError> {
+    // Check if it's a PEM file
+    let (method, certs) = if let Some(result) = try_parse_pem(&cert)? {
+        result
+    } else if let Some(certs) = try_parse_der(&cert, password)? {
+        (EncryptionMethod::SMIME, certs)
+    } else if let Some(certs) = base64_decode(&cert)
+        .map(|bytes| try_parse_der(&bytes, password))
+        .transpose()?
+        .flatten()
+    {
+        // Binary certificates and bundles uploaded as base64 text
+        (EncryptionMethod::SMIME, certs)
+    } else if openpgp::PacketPile::from_bytes(&cert[..]).is_ok() {
+        (EncryptionMethod::PGP, try_parse_pgp_block(1, &cert)?)
+    } else {
+        return Err(CertParseError::NoCertificates);
+    };
+
+    if method == expected_method {
+        Ok(certs)
+    } else {
+        Err(CertParseError::MethodMismatch)
+    }
+}
+
+// Parses a DER encoded X.509 certificate, PKCS#7 bundle (.p7b) or PKCS#12 bundle (.p12/.pfx)
+fn try_parse_der(
+    bytes: &[u8],
+    password: Option<&str>,
+) -> Result<Option<Vec<Vec<u8>>>, CertParseError> {
+    if rasn::der::decode::<rasn_pkix::Certificate>(bytes).is_ok() {
+        Ok(Some(vec![bytes.to_vec()]))
+    } else if let Some(certs) = try_parse_pkcs7(bytes)? {
+        Ok(Some(certs))
+    } else {
+        try_parse_pkcs12(bytes, password)
+    }
+}
+
+// Extracts the certificates from a degenerate PKCS#7 SignedData structure, returns None
+// if the bytes are not a PKCS#7 bundle.
+fn try_parse_pkcs7(bytes: &[u8]) -> Result<Option<Vec<Vec<u8>>>, CertParseError> {
+    let signed_data = match rasn::der::decode::<ContentInfo>(bytes) {
+        Ok(content_info) if &*content_info.content_type == CONTENT_SIGNED_DATA => {
+            rasn::der::decode::<Pkcs7SignedData>(content_info.content.as_bytes())
+                .map_err(|err| CertParseError::InvalidX509(format!("PKCS#7 bundle: {err}")))?
+        }
+        _ => return Ok(None),
+    };
+
+    let certs = signed_data
+        .certificates
+        .into_iter()
+        .flatten()
+        .filter_map(|cert| {
+            rasn::der::decode::<rasn_pkix::Certificate>(cert.as_bytes())
+                .ok()
+                .map(|x509| (cert.as_bytes().to_vec(), x509))
+        })
+        .collect::<Vec<_>>();
+
+    // Bundles usually carry the issuing CAs next to the end-entity certificate, which
+    // is the only one used for encryption. When the bundle has no certificates marked as
+    // end-entity, the ones that did not issue any other certificate in the bundle are used.
+    let mut end_entity = certs
+        .iter()
+        .filter(|(_, x509)| !is_ca_certificate(x509))
+        .collect::<Vec<_>>();
+    if end_entity.is_empty() {
+        end_entity = certs
+            .iter()
+            .enumerate()
+            .filter(|(pos, (_, x509))| {
+                !certs.iter().enumerate().any(|(other_pos, (_, other))| {
+                    other_pos != *pos
+                        && other.tbs_certificate.issuer == x509.tbs_certificate.subject
+                })
+            })
+            .map(|(_, cert)| cert)
+            .collect();
+    }
+
+    match end_entity.as_slice() {
+        [(cert, _)] => Ok(Some(vec![cert.clone()])),
+        [] => Err(CertParseError::NoCertificates),
+        _ => Err(CertParseError::AmbiguousCertificates),
+    }
+}
+
+fn is_ca_certificate(x509: &rasn_pkix::Certificate) -> bool {
+    x509.tbs_certificate
+        .extensions
+        .iter()
+        .flatten()
+        .filter(|extension| {
+            let oid: &[u32] = &extension.extn_id;
+            oid == [2, 5, 29, 19]
+        })
+        .any(|extension| {
+            rasn::der::decode::<rasn_pkix::BasicConstraints>(&extension.extn_value)
+                .is_ok_and(|constraints| constraints.ca)
+        })
+}
+
+// Returns the fingerprint shown by key management tools, the OpenPGP key fingerprint
+// or the SHA-256 digest of a DER encoded X.509 certificate
+pub fn fingerprint(method: EncryptionMethod, cert: &[u8]) -> String {
+    match method {
+        EncryptionMethod::PGP => openpgp::Cert::from_bytes(cert)
+            .map(|cert| cert.fingerprint().to_hex())
+            .unwrap_or_else(|_| sha256_hex(cert)),
+        EncryptionMethod::SMIME => sha256_hex(cert),
+    }
+}
+
+// Exports certificates in a text format accepted when uploading them, OpenPGP keys
+// are stored as uploaded and only binary keys need to be armored
+pub fn export_certs<'x>(
+    method: EncryptionMethod,
+    certs: impl IntoIterator<Item = &'x [u8]>,
+) -> String {
+    let mut export = Vec::new();
+    for cert in certs {
+        match method {
+            EncryptionMethod::PGP if cert.starts_with(b"-----") => {
+                export.extend_from_slice(cert);
+            }
+            EncryptionMethod::PGP => {
+                if let Ok(armored) =
+                    openpgp::Cert::from_bytes(cert).and_then(|cert| cert.armored().to_vec())
+                {
+                    export.extend_from_slice(&armored);
+                }
+            }
+            EncryptionMethod::SMIME => {
+                export.extend_from_slice(b"-----BEGIN CERTIFICATE-----\r\n");
+                let _ = base64_encode_mime(cert, &mut export, false);
+                if !export.ends_with(b"\n") {
+                    export.extend_from_slice(b"\r\n");
+                }
+                export.extend_from_slice(b"-----END CERTIFICATE-----\r\n");
+            }
+        }
+        if !export.ends_with(b"\n") {
+            export.extend_from_slice(b"\r\n");
+        }
+    }
+
+    String::from_utf8(export).unwrap_or_default()
+}
+
+// Returns the e-mail addresses in the user IDs of an OpenPGP certificate
+pub fn pgp_user_id_addresses(cert: &[u8]) -> Vec<String> {
+    openpgp::Cert::from_bytes(cert)
+        .map(|cert| {
+            cert.userids()
+                .filter_map(|uid| {
+                    std::str::from_utf8(uid.userid().value())
+                        .ok()
+                        .and_then(user_id_address)
+                        .map(|address| address.to_string())
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn user_id_address(uid: &str) -> Option<&str> {
+    if let Some((_, address)) = uid.rsplit_once('<') {
+        address.strip_suffix('>')
+    } else {
+        Some(uid.trim())
+    }
+}
+
+pub fn certificate_info(method: EncryptionMethod, cert: &[u8]) -> CertificateInfo {
+    match method {
+        EncryptionMethod::PGP => {
+            if let Ok(cert) = openpgp::Cert::from_bytes(cert) {
+                let primary_key = cert.primary_key().key();
+                return CertificateInfo {
+                    fingerprint: cert.fingerprint().to_hex(),
+                    subject: cert.with_policy(&P, None).ok().and_then(|cert| {
+                        cert.primary_userid()
+                            .ok()
+                            .map(|uid| String::from_utf8_lossy(uid.userid().value()).into_owned())
+                    }),
+                    valid_from: unix_time(primary_key.creation_time()),
+                    expires_at: cert
+                        .with_policy(&P, None)
+                        .ok()
+                        .and_then(|cert| cert.primary_key().key_expiration_time())
+                        .and_then(unix_time),
+                    revoked: matches!(
+                        cert.revocation_status(&P, None),
+                        RevocationStatus::Revoked(_)
+                    ),
+                };
+            }
+        }
+        EncryptionMethod::SMIME => {
+            if let Ok(x509) = rasn::der::decode::<rasn_pkix::Certificate>(cert) {
+                let validity = &x509.tbs_certificate.validity;
+                return CertificateInfo {
+                    fingerprint: fingerprint(method, cert),
+                    subject: x509_name(&x509.tbs_certificate.subject),
+                    valid_from: x509_time(&validity.not_before),
+                    expires_at: x509_time(&validity.not_after),
+                    revoked: false,
+                };
+            }
+        }
+    }
+
+    CertificateInfo {
+        fingerprint: fingerprint(method, cert),
+        subject: None,
+        valid_from: None,
+        expires_at: None,
+        revoked: false,
+    }
+}
+
+// Rejects certificates outside their validity period, returns warnings
+// for certificates that are about to expire
+pub fn validate_certs(
+    method: EncryptionMethod,
+    certs: &[Vec<u8>],
+) -> Result<Vec<String>, Cow<'static, str>> {
+    let now = now();
+    let mut warnings = Vec::new();
+
+    for cert in certs {
+        let info = certificate_info(method, cert);
+        if info.revoked {
+            return Err(format!("Certificate {} has been revoked", info.fingerprint).into());
+        }
+        if let Some(reason) = (method == EncryptionMethod::SMIME)
+            .then(|| rasn::der::decode::<rasn_pkix::Certificate>(cert).ok())
+            .flatten()
+            .and_then(|x509| smime_key_usage_error(&x509))
+        {
+            return Err(format!(
+                "Certificate {} cannot be used for encryption: {reason}",
+                info.fingerprint
+            )
+            .into());
+        }
+        if let Some(valid_from) = info.valid_from.filter(|valid_from| *valid_from > now) {
+            return Err(format!(
+                "Certificate {} is not valid until {}",
+                info.fingerprint,
+                DateTime::from_timestamp(valid_from as i64).to_rfc3339()
+            )
+            .into());
+        }
+        if let Some(expires_at) = info.expires_at {
+            if expires_at <= now {
+                return Err(format!(
+                    "Certificate {} expired on {}",
+                    info.fingerprint,
+                    DateTime::from_timestamp(expires_at as i64).to_rfc3339()
+                )
+                .into());
+            } else if expires_at - now < CERT_EXPIRY_WARNING {
+                warnings.push(format!(
+                    "Certificate {} expires on {}",
+                    info.fingerprint,
+                    DateTime::from_timestamp(expires_at as i64).to_rfc3339()
+                ));
+            }
+        }
+    }
+
+    Ok(warnings)
+}
+
+// Returns the reason a certificate may not be used to encrypt messages. Certificates
+// without keyUsage or extendedKeyUsage extensions are unrestricted (RFC 8550)
+fn smime_key_usage_error(x509: &rasn_pkix::Certificate) -> Option<&'static str> {
+    let algorithm: &Oid = &x509
+        .tbs_certificate
+        .subject_public_key_info
+        .algorithm
+        .algorithm;
+    let is_ec = algorithm == EC_PUBLIC_KEY;
+
+    for extension in x509.tbs_certificate.extensions.iter().flatten() {
+        let oid: &Oid = &extension.extn_id;
+        if oid == KEY_USAGE {
+            let Ok(usage) = rasn::der::decode::<BitString>(&extension.extn_value) else {
+                return Some("invalid keyUsage extension");
+            };
+            let has_usage = |bit: usize| usage.get(bit).is_some_and(|bit| *bit);
+            if is_ec {
+                if !has_usage(KEY_USAGE_KEY_AGREEMENT) {
+                    return Some("keyUsage does not include keyAgreement");
+                }
+            } else if !has_usage(KEY_USAGE_KEY_ENCIPHERMENT)
+                && !has_usage(KEY_USAGE_DATA_ENCIPHERMENT)
+            {
+                return Some("keyUsage does not include keyEncipherment or dataEncipherment");
+            }
+        } else if oid == EXT_KEY_USAGE {
+            let Ok(purposes) = rasn::der::decode::<Vec<ObjectIdentifier>>(&extension.extn_value)
+            else {
+                return Some("invalid extendedKeyUsage extension");
+            };
+            if !purposes.iter().any(|purpose| {
+                let purpose: &Oid = purpose;
+                purpose == KP_EMAIL_PROTECTION || purpose == ANY_EXTENDED_KEY_USAGE
+            }) {
+                return Some("extendedKeyUsage does not include emailProtection");
+            }
+        }
+    }
+
+    None
+}
+
+fn x509_time(time: &rasn_pkix::Time) -> Option<u64> {
+    let timestamp = match time {
+        rasn_pkix::Time::Utc(time) => time.timestamp(),
+        rasn_pkix::Time::General(time) => time.timestamp(),
+    };
+    u64::try_from(timestamp).ok()
+}
+
+fn x509_name(name: &rasn_pkix::Name) -> Option<String> {
+    let rasn_pkix::Name::RdnSequence(rdns) = name;
+    let mut result = String::new();
+
+    for attr in rdns.iter().flat_map(|rdn| rdn.iter()) {
+        let oid: &[u32] = &attr.r#type;
+        let key = match oid {
+            [2, 5, 4, 3] => "CN",
+            [2, 5, 4, 6] => "C",
+            [2, 5, 4, 7] => "L",
+            [2, 5, 4, 8] => "ST",
+            [2, 5, 4, 10] => "O",
+            [2, 5, 4, 11] => "OU",
+            [1, 2, 840, 113549, 1, 9, 1] => "emailAddress",
+            _ => continue,
+        };
+
+        // UTF8String, PrintableString, TeletexString or IA5String
+        let value = match attr.value.as_bytes() {
+            [0x0c | 0x13 | 0x14 | 0x16, len, value @ ..] if *len as usize == value.len() => value,
+            [0x0c | 0x13 | 0x14 | 0x16, 0x81, len, value @ ..] if *len as usize == value.len() => {
+                value
+            }
+            _ => continue,
+        };
+
+        if !result.is_empty() {
+            result.push_str(", ");
+        }
+        result.push_str(key);
+        result.push('=');
+        result.push_str(&String::from_utf8_lossy(value));
+    }
+
+    (!result.is_empty()).then_some(result)
+}
+
+fn unix_time(time: SystemTime) -> Option<u64> {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+        .map(|time| time.as_secs())
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(64);
+    for byte in Sha256::digest(bytes).iter() {
+        hex.push_str(&format!("{:02X}", byte));
+    }
+    hex
+}
+
+// Certificates whose key usage does not allow encryption are skipped, these may
+// have been stored before uploaded certificates were validated
+fn wrap_smime_key(
+    rng: &mut StdRng,
+    cert: &[u8],
+    key: &[u8],
+    use_oaep: bool,
+) -> Result<Option<RecipientInfo>, EncryptMessageError> {
+    let cert = rasn::der::decode::<rasn_pkix::Certificate>(cert).map_err(|err| {
+        EncryptMessageError::Error(format!("Failed to parse certificate: {}", err))
+    })?;
+    if smime_key_usage_error(&cert).is_some() {
+        return Ok(None);
+    }
+
+    let algorithm: &Oid = &cert
+        .tbs_certificate
+        .subject_public_key_info
+        .algorithm
+        .algorithm;
+    if algorithm == RSA {
+        wrap_smime_key_rsa(rng, cert, key, use_oaep).map(Some)
+    } else if algorithm == EC_PUBLIC_KEY {
+        wrap_smime_key_ecdh(rng, cert, key).map(Some)
+    } else {
+        Err(EncryptMessageError::Error(format!(
+            "Unsupported public key algorithm {}",
+            algorithm
+                .iter()
+                .map(|arc| arc.to_string())
+                .collect::<Vec<_>>()
+                .join(".")
+        )))
+    }
+}
+
+fn wrap_smime_key_rsa(
+    rng: &mut StdRng,
+    cert: rasn_pkix::Certificate,
+    key: &[u8],
+    use_oaep: bool,
+) -> Result<RecipientInfo, EncryptMessageError> {
+    let public_key = RsaPublicKey::from_pkcs1_der(
+        cert.tbs_certificate
+            .subject_public_key_info
+            .subject_public_key
+            .as_raw_slice(),
+    )
+    .map_err(|err| EncryptMessageError::Error(format!("Failed to parse public key: {}", err)))?;
+    let (encrypted_key, key_encryption_algorithm) = if use_oaep {
+        (
+            public_key.encrypt(rng, Oaep::new::<sha2::Sha256>(), key),
+            AlgorithmIdentifier {
+                algorithm: RSAES_OAEP.into(),
+                parameters: Some(RSAES_OAEP_SHA256_PARAMS.to_vec().into()),
+            },
+        )
+    } else {
+        (
+            public_key.encrypt(rng, Pkcs1v15Encrypt, key),
+            AlgorithmIdentifier {
+                algorithm: RSA.into(),
+                parameters: Some(
+                    rasn::der::encode(&())
+                        .map_err(|err| {
+                            EncryptMessageError::Error(format!(
+                                "Failed to encode RSA algorithm identifier: {}",
+                                err
+                            ))
+                        })?
+                        .into(),
+                ),
+            },
+        )
+    };
+    // Keys too small for the padding fail here rather than when they are parsed
+    let encrypted_key = encrypted_key.map_err(|err| {
+        EncryptMessageError::Error(format!(
+            "Failed to encrypt key for {}-bit RSA public key: {}",
+            public_key.size() * 8,
+            err
+        ))
+    })?;
+
+    Ok(RecipientInfo::KeyTransRecipientInfo(
+        KeyTransRecipientInfo {
+            version: 0.into(),
+            rid: RecipientIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+                issuer: cert.tbs_certificate.issuer,
+                serial_number: cert.tbs_certificate.serial_number,
+            }),
+            key_encryption_algorithm,
+            encrypted_key: EncryptedKey::from(encrypted_key),
+        },
+    ))
+}
+
+// Ephemeral-static ECDH key agreement (RFC 5753)
+fn wrap_smime_key_ecdh(
+    rng: &mut StdRng,
+    cert: rasn_pkix::Certificate,
+    key: &[u8],
+) -> Result<RecipientInfo, EncryptMessageError> {
+    let public_key_info = &cert.tbs_certificate.subject_public_key_info;
+    let curve = public_key_info
+        .algorithm
+        .parameters
+        .as_ref()
+        .and_then(|params| rasn::der::decode::<ObjectIdentifier>(params.as_bytes()).ok())
+        .ok_or_else(|| {
+            EncryptMessageError::Error("Missing elliptic curve parameters".to_string())
+        })?;
+    let public_key = public_key_info.subject_public_key.as_raw_slice();
+    let invalid_key = |err: p256::elliptic_curve::Error| {
+        EncryptMessageError::Error(format!("Failed to parse public key: {}", err))
+    };
+
+    // Derive the shared secret using an ephemeral key on the recipient's curve
+    let (kdf_algorithm, ephemeral_key, shared_secret) = if &*curve == EC_CURVE_P256 {
+        let public_key = p256::PublicKey::from_sec1_bytes(public_key).map_err(invalid_key)?;
+        let secret = p256::ecdh::EphemeralSecret::random(rng);
+        (
+            ECDH_SHA256_KDF,
+            secret
+                .public_key()
+                .to_encoded_point(false)
+                .as_bytes()
+                .to_vec(),
+            secret
+                .diffie_hellman(&public_key)
+                .raw_secret_bytes()
+                .to_vec(),
+        )
+    } else if &*curve == EC_CURVE_P384 {
+        let public_key = p384::PublicKey::from_sec1_bytes(public_key).map_err(invalid_key)?;
+        let secret = p384::ecdh::EphemeralSecret::random(rng);
+        (
+            ECDH_SHA384_KDF,
+            secret
+                .public_key()
+                .to_encoded_point(false)
+                .as_bytes()
+                .to_vec(),
+            secret
+                .diffie_hellman(&public_key)
+                .raw_secret_bytes()
+                .to_vec(),
+        )
+    } else {
+        return Err(EncryptMessageError::Error(
+            "Unsupported elliptic curve".to_string(),
+        ));
+    };
+
+    // The key wrap algorithm matches the content encryption key size
+    let wrap_algorithm = AlgorithmIdentifier {
+        algorithm: match key.len() {
+            16 => AES128_WRAP,
+            24 => AES192_WRAP,
+            32 => AES256_WRAP,
+            _ => {
+                return Err(EncryptMessageError::Error(
+                    "Unsupported content encryption key size".to_string(),
+                ));
+            }
+        }
+        .into(),
+        parameters: None,
+    };
+
+    // Derive the key-encryption key using the ANSI X9.63 KDF
+    let shared_info = rasn::der::encode(&EccCmsSharedInfo {
+        key_info: wrap_algorithm.clone(),
+        entity_u_info: None,
+        supp_pub_info: OctetString::from(((key.len() * 8) as u32).to_be_bytes().to_vec()),
+    })
+    .map_err(|err| {
+        EncryptMessageError::Error(format!("Failed to encode ECC-CMS-SharedInfo: {}", err))
+    })?;
+    let kek = if kdf_algorithm == ECDH_SHA256_KDF {
+        x963_kdf::<Sha256>(&shared_secret, &shared_info, key.len())
+    } else {
+        x963_kdf::<Sha384>(&shared_secret, &shared_info, key.len())
+    };
+
+    Ok(RecipientInfo::KeyAgreeRecipientInfo(
+        KeyAgreeRecipientInfo {
+            version: 3.into(),
+            originator: OriginatorIdentifierOrKey::OriginatorPublicKey(OriginatorPublicKey {
+                algorithm: AlgorithmIdentifier {
+                    algorithm: EC_PUBLIC_KEY.into(),
+                    parameters: None,
+                },
+                public_key: BitString::from_slice(&ephemeral_key),
+            }),
+            user_keying_material: None,
+            key_encryption_algorithm: AlgorithmIdentifier {
+                algorithm: kdf_algorithm.into(),
+                parameters: Some(
+                    rasn::der::encode(&wrap_algorithm)
+                        .map_err(|err| {
+                            EncryptMessageError::Error(format!(
+                                "Failed to encode key wrap algorithm identifier: {}",
+                                err
+                            ))
+                        })?
+                        .into(),
+                ),
+            },
+            recipient_encrypted_keys: vec![RecipientEncryptedKey {
+                rid: KeyAgreeRecipientIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+                    issuer: cert.tbs_certificate.issuer,
+                    serial_number: cert.tbs_certificate.serial_number,
+                }),
+                encrypted_key: EncryptedKey::from(aes_key_wrap(&kek, key)?),
+            }],
+        },
+    ))
+}
+
+// ANSI X9.63 key derivation function (SEC 1, section 3.6.1)
+fn x963_kdf<D: Digest>(shared_secret: &[u8], shared_info: &[u8], len: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(len);
+    let mut counter = 1u32;
+    while output.len() < len {
+        let mut hasher = D::new();
+        hasher.update(shared_secret);
+        hasher.update(counter.to_be_bytes());
+        hasher.update(shared_info);
+        output.extend_from_slice(&hasher.finalize());
+        counter += 1;
+    }
+    output.truncate(len);
+    output
+}
+
+// AES key wrap (RFC 3394), the key-encryption key size selects the cipher
+fn aes_key_wrap(kek: &[u8], key: &[u8]) -> Result<Vec<u8>, EncryptMessageError> {
+    let invalid_kek =
+        |_| EncryptMessageError::Error(format!("Invalid key-encryption key size {}", kek.len()));
+    match kek.len() {
+        16 => {
+            let cipher = Aes128::new_from_slice(kek).map_err(invalid_kek)?;
+            Ok(aes_key_wrap_with(|block| cipher.encrypt_block(block), key))
+        }
+        24 => {
+            let cipher = Aes192::new_from_slice(kek).map_err(invalid_kek)?;
+            Ok(aes_key_wrap_with(|block| cipher.encrypt_block(block), key))
+        }
+        _ => {
+            let cipher = Aes256::new_from_slice(kek).map_err(invalid_kek)?;
+            Ok(aes_key_wrap_with(|block| cipher.encrypt_block(block), key))
+        }
+    }
+}
+
+fn aes_key_wrap_with(encrypt_block: impl Fn(&mut aes::Block), key: &[u8]) -> Vec<u8> {
+    let mut iv = [0xA6u8; 8];
+    let mut blocks = key
+        .chunks(8)
+        .map(|chunk| chunk.to_vec())
+        .collect::<Vec<_>>();
+    let n = blocks.len() as u64;
+
+    for j in 0..6u64 {
+        for (i, block) in blocks.iter_mut().enumerate() {
+            let mut buf = aes::Block::default();
+            buf[..8].copy_from_slice(&iv);
+            buf[8..].copy_from_slice(block);
+            encrypt_block(&mut buf);
+
+            let t = n * j + i as u64 + 1;
+            for (iv, (b, t)) in iv.iter_mut().zip(buf[..8].iter().zip(t.to_be_bytes())) {
+                *iv = b ^ t;
+            }
+            block.copy_from_slice(&buf[8..]);
+        }
+    }
+
+    let mut output = Vec::with_capacity(key.len() + 8);
+    output.extend_from_slice(&iv);
+    for block in blocks {
+        output.extend_from_slice(&block);
+    }
+    output
+}
+
+// AES key unwrap (RFC 3394), returns None if the integrity check fails
+fn aes_key_unwrap(kek: &[u8], wrapped_key: &[u8]) -> Option<Vec<u8>> {
+    match kek.len() {
+        16 => {
+            let cipher = Aes128::new_from_slice(kek).ok()?;
+            aes_key_unwrap_with(|block| cipher.decrypt_block(block), wrapped_key)
+        }
+        24 => {
+            let cipher = Aes192::new_from_slice(kek).ok()?;
+            aes_key_unwrap_with(|block| cipher.decrypt_block(block), wrapped_key)
+        }
+        32 => {
+            let cipher = Aes256::new_from_slice(kek).ok()?;
+            aes_key_unwrap_with(|block| cipher.decrypt_block(block), wrapped_key)
+        }
+        _ => None,
+    }
+}
+
+fn aes_key_unwrap_with(
+    decrypt_block: impl Fn(&mut aes::Block),
+    wrapped_key: &[u8],
+) -> Option<Vec<u8>> {
+    if wrapped_key.len() < 24 || wrapped_key.len() % 8 != 0 {
+        return None;
+    }
+    let mut iv = [0u8; 8];
+    iv.copy_from_slice(&wrapped_key[..8]);
+    let mut blocks = wrapped_key[8..]
+        .chunks(8)
+        .map(|chunk| chunk.to_vec())
+        .collect::<Vec<_>>();
+    let n = blocks.len() as u64;
+
+    for j in (0..6u64).rev() {
+        for (i, block) in blocks.iter_mut().enumerate().rev() {
+            let t = n * j + i as u64 + 1;
+            let mut buf = aes::Block::default();
+            for (b, (iv, t)) in buf[..8].iter_mut().zip(iv.iter().zip(t.to_be_bytes())) {
+                *b = iv ^ t;
+            }
+            buf[8..].copy_from_slice(block);
+            decrypt_block(&mut buf);
+
+            iv.copy_from_slice(&buf[..8]);
+            block.copy_from_slice(&buf[8..]);
+        }
+    }
+
+    if iv == [0xA6u8; 8] {
+        Some(blocks.concat())
+    } else {
+        None
+    }
+}
+
+fn has_pgp_keys(cert: &openpgp::Cert) -> bool {
+    cert.keys()
+        .with_policy(&P, None)
+        .supported()
+        .alive()
+        .revoked(false)
+        .key_flags(pgp_encryption_flags())
+        .next()
+        .is_some()
+}
+
+// Picks a session key algorithm supported by every recipient, the configured
+// algorithm is used whenever possible. Recipients that do not advertise any
+// preferences accept all algorithms.
+pub fn pgp_symmetric_algorithm(
+    configured: SymmetricAlgorithm,
+    recipients: &[(String, Option<Vec<SymmetricAlgorithm>>)],
+) -> Result<SymmetricAlgorithm, EncryptMessageError> {
+    [
+        configured,
+        SymmetricAlgorithm::AES256,
+        SymmetricAlgorithm::AES192,
+        SymmetricAlgorithm::AES128,
+    ]
+    .into_iter()
+    .chain(
+        recipients
+            .iter()
+            .filter_map(|(_, algos)| algos.as_deref())
+            .flatten()
+            .copied(),
+    )
+    .filter(|algo| algo.is_supported() && P.symmetric_algorithm(*algo).is_ok())
+    .find(|algo| {
+        recipients
+            .iter()
+            .all(|(_, algos)| algos.as_ref().is_none_or(|algos| algos.contains(algo)))
+    })
+    .ok_or_else(|| {
+        EncryptMessageError::Error(format!(
+            "OpenPGP keys {} do not share a supported symmetric algorithm",
+            recipients
+                .iter()
+                .filter(|(_, algos)| algos.is_some())
+                .map(|(fingerprint, _)| fingerprint.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    })
+}
+
+fn pgp_encryption_flags() -> KeyFlags {
+    KeyFlags::empty()
+        .set_transport_encryption()
+        .set_storage_encryption()
+}
+
+// Returns the first signing-capable (sub)key with an unencrypted secret
+fn pgp_signing_keypair(
+    cert: &openpgp::Cert,
+    policy: &openpgp::policy::StandardPolicy<'_>,
+) -> Result<openpgp::crypto::KeyPair, EncryptMessageError> {
+    cert.keys()
+        .unencrypted_secret()
+        .with_policy(policy, None)
+        .supported()
+        .alive()
+        .revoked(false)
+        .for_signing()
+        .next()
+        .and_then(|key| key.key().clone().into_keypair().ok())
+        .ok_or_else(|| {
+            EncryptMessageError::Error(format!(
+                "OpenPGP key {} does not contain an unencrypted signing-capable key",
+                cert.fingerprint().to_hex()
+            ))
+        })
+}
+
+// Builds the OpenPGP writer stack that armors, encrypts and optionally compresses
+// and signs the literal data written to it
+fn pgp_literal_writer<'a, W: Write + Send + Sync + 'a>(
+    sink: W,
+    certs: &'a [openpgp::Cert],
+    signing_key: Option<&'a openpgp::Cert>,
+    policy: &'a openpgp::policy::StandardPolicy<'_>,
+    algo: ArchivedAlgorithm,
+    compression: Option<CompressionAlgorithm>,
+) -> Result<stream::Message<'a>, EncryptMessageError> {
+    // Parse public key
+    let mut keys = Vec::with_capacity(certs.len());
+    let mut preferences = Vec::with_capacity(certs.len());
+
+    for cert in certs {
+        // Session key algorithms advertised by the recipient, if any
+        preferences.push((
+            cert.fingerprint().to_hex(),
+            cert.with_policy(policy, None).ok().and_then(|cert| {
+                cert.preferred_symmetric_algorithms()
+                    .map(|algos| algos.to_vec())
+            }),
+        ));
+
+        // Only use (sub)keys that are allowed to encrypt
+        let num_keys = keys.len();
+        for key in cert
+            .keys()
+            .with_policy(policy, None)
+            .supported()
+            .alive()
+            .revoked(false)
+            .key_flags(pgp_encryption_flags())
+        {
+            keys.push(key);
+        }
+        if keys.len() == num_keys {
+            return Err(EncryptMessageError::Error(format!(
+                "OpenPGP key {} does not contain an encryption-capable subkey",
+                cert.fingerprint().to_hex()
+            )));
+        }
+    }
+
+    // Compose a writer stack corresponding to the output format and
+    // packet structure we want.
+    let message = stream::Armorer::new(stream::Message::new(sink))
+        .build()
+        .map_err(|err| EncryptMessageError::Error(format!("Failed to create armorer: {}", err)))?;
+    let symmetric_algo = pgp_symmetric_algorithm(
+        match algo {
+            ArchivedAlgorithm::Aes128 | ArchivedAlgorithm::Aes128Gcm => SymmetricAlgorithm::AES128,
+            ArchivedAlgorithm::Aes256
+            | ArchivedAlgorithm::Aes256Gcm
+            | ArchivedAlgorithm::Chacha20Poly1305 => SymmetricAlgorithm::AES256,
+        },
+        &preferences,
+    )?;
+    let message = stream::Encryptor::for_recipients(message, keys)
+        .symmetric_algo(symmetric_algo)
+        .build()
+        .map_err(|err| EncryptMessageError::Error(format!("Failed to build encryptor: {}", err)))?;
+    let message = if let Some(compression) = compression {
+        stream::Compressor::new(message)
+            .algo(compression)
+            .build()
+            .map_err(|err| {
+                EncryptMessageError::Error(format!("Failed to build compressor: {}", err))
+            })?
+    } else {
+        message
+    };
+    let message = if let Some(signing_key) = signing_key {
+        stream::Signer::new(message, pgp_signing_keypair(signing_key, policy)?)
+            .and_then(|signer| signer.build())
+            .map_err(|err| EncryptMessageError::Error(format!("Failed to build signer: {}", err)))?
+    } else {
+        message
+    };
+    stream::LiteralWriter::new(message).build().map_err(|err| {
+        EncryptMessageError::Error(format!("Failed to create literal writer: {}", err))
+    })
+}
+
+// Parses a block of OpenPGP data which may contain several keys, such as a
+// keyring exported from GnuPG, returning one certificate per key
+fn try_parse_pgp_block(block: usize, bytes: &[u8]) -> Result<Vec<Vec<u8>>, CertParseError> {
+    let keys = pgp_keys(bytes);
+    let error = |user_id: Option<&str>, reason: &str| {
+        if let Some(user_id) = user_id {
+            CertParseError::InvalidPgp(format!("block {block} ({user_id}): {reason}"))
+        } else {
+            CertParseError::InvalidPgp(format!("block {block}: {reason}"))
+        }
+    };
+
+    // Report keys that the OpenPGP parser does not support
+    if let Some(key) = keys.iter().find(|key| key.error.is_some()) {
+        return Err(error(
+            key.user_id.as_deref(),
+            key.error.as_deref().unwrap_or_default(),
+        ));
+    }
+
+    let mut keys = keys.into_iter();
+    let mut certs = Vec::new();
+    for cert in CertParser::from_bytes(bytes)
+        .map_err(|err| CertParseError::InvalidPgp(format!("block {block}: {err}")))?
+    {
+        let user_id = keys.next().and_then(|key| key.user_id);
+        let user_id = match &cert {
+            Ok(cert) => cert
+                .userids()
+                .next()
+                .map(|uid| String::from_utf8_lossy(uid.userid().value()).into_owned()),
+            Err(_) => user_id,
+        };
+        let reason = match cert {
+            Ok(cert) if has_pgp_keys(&cert) => {
+                certs.push(cert);
+                continue;
+            }
+            Ok(_) => "no encryption-capable keys found".to_string(),
+            Err(err) => err.to_string(),
+        };
+
+        return Err(error(user_id.as_deref(), &reason));
+    }
+
+    match certs.len() {
+        0 => Err(CertParseError::InvalidPgp(format!(
+            "block {block}: no OpenPGP keys found"
+        ))),
+        1 => Ok(vec![bytes.to_vec()]),
+        _ => certs
+            .into_iter()
+            .map(|cert| {
+                cert.armored()
+                    .to_vec()
+                    .map_err(|err| CertParseError::InvalidPgp(format!("block {block}: {err}")))
+            })
+            .collect(),
+    }
+}
+
+#[derive(Default)]
+struct PgpKey {
+    user_id: Option<String>,
+    error: Option<String>,
+}
+
+// Obtains the first user id of each key in a block, as well as the reason why
+// a key could not be parsed (unsupported keys are returned as unknown packets)
+fn pgp_keys(bytes: &[u8]) -> Vec<PgpKey> {
+    let mut keys: Vec<PgpKey> = Vec::new();
+    let mut ppr = match PacketParser::from_bytes(bytes) {
+        Ok(ppr) => ppr,
+        Err(_) => return keys,
+    };
+
+    while let PacketParserResult::Some(pp) = ppr {
+        let (packet, next) = match pp.next() {
+            Ok(result) => result,
+            Err(_) => break,
+        };
+        match packet {
+            Packet::PublicKey(_) | Packet::SecretKey(_) => keys.push(PgpKey::default()),
+            Packet::Unknown(packet) if matches!(packet.tag(), Tag::PublicKey | Tag::SecretKey) => {
+                let error = packet.error().to_string();
+                keys.push(PgpKey {
+                    user_id: None,
+                    error: Some(if error.to_lowercase().contains("version") {
+                        "unsupported key version".to_string()
+                    } else {
+                        error
+                    }),
+                });
+            }
+            Packet::UserID(uid) => {
+                if let Some(key) = keys.last_mut().filter(|key| key.user_id.is_none()) {
+                    key.user_id = Some(String::from_utf8_lossy(uid.value()).into_owned());
+                }
+            }
+            _ => (),
+        }
+        ppr = next;
+    }
+
+    keys
+}
+
+#[allow(clippy::type_complexity)]
+fn try_parse_pem(
+    bytes_: &[u8],
+) -> Result<Option<(EncryptionMethod, Vec<Vec<u8>>)>, CertParseError> {
+    let mut bytes = bytes_.iter().enumerate();
+    let mut buf = vec![];
+    let mut method = None;
+    let mut certs = vec![];
+    let mut block = 0;
+
+    loop {
+        // Find start of PEM block
+        let mut start_pos = 0;
+        for (pos, &ch) in bytes.by_ref() {
+            if ch.is_ascii_whitespace() {
+                continue;
+            } else if ch == b'-' {
+                start_pos = pos;
+                break;
+            } else {
+                return Ok(None);
             }
         }
 
@@ -576,15 +2864,16 @@ fn try_parse_pem(
 
         // Find type
         let tag = std::str::from_utf8(&buf).unwrap();
-        if tag.contains("CERTIFICATE") {
+        let is_pkcs7 = tag.contains("PKCS7");
+        if tag.contains("CERTIFICATE") || is_pkcs7 {
             if method.is_some_and(|m| m == EncryptionMethod::PGP) {
-                return Err("Cannot mix OpenPGP and S/MIME certificates".into());
+                return Err(CertParseError::MixedMethods);
             } else {
                 method = Some(EncryptionMethod::SMIME);
             }
         } else if tag.contains("PGP") {
             if method.is_some_and(|m| m == EncryptionMethod::SMIME) {
-                return Err("Cannot mix OpenPGP and S/MIME certificates".into());
+                return Err(CertParseError::MixedMethods);
             } else {
                 method = Some(EncryptionMethod::PGP);
             }
@@ -625,31 +2914,27 @@ fn try_parse_pem(
             }
         }
 
-        // Decode base64
-        let cert =
-            base64_decode(&buf).ok_or_else(|| Cow::from("Failed to decode base64 certificate."))?;
+        block += 1;
         match method.unwrap() {
-            EncryptionMethod::PGP => match openpgp::Cert::from_bytes(bytes_) {
-                Ok(cert) => {
-                    if !has_pgp_keys(cert) {
-                        return Err("Could not find any suitable keys in OpenPGP public key".into());
-                    }
-                    certs.push(
-                        bytes_
-                            .get(start_pos..end_pos + 1)
-                            .unwrap_or_default()
-                            .to_vec(),
-                    );
-                }
-                Err(err) => {
-                    return Err(format!("Failed to decode OpenPGP public key: {err}").into());
-                }
-            },
+            EncryptionMethod::PGP => {
+                // Armored blocks are decoded by the OpenPGP parser, which also
+                // handles armor headers and checksums
+                certs.extend(try_parse_pgp_block(
+                    block,
+                    bytes_.get(start_pos..end_pos + 1).unwrap_or_default(),
+                )?);
+            }
             EncryptionMethod::SMIME => {
-                if let Err(err) = rasn::der::decode::<rasn_pkix::Certificate>(&cert) {
-                    return Err(format!("Failed to decode X509 certificate: {err}").into());
+                let cert = base64_decode(&buf).ok_or(CertParseError::InvalidBase64)?;
+                if is_pkcs7 {
+                    certs.extend(try_parse_pkcs7(&cert)?.ok_or_else(|| {
+                        CertParseError::InvalidX509(format!("block {block}: invalid PKCS#7 bundle"))
+                    })?);
+                } else if let Err(err) = rasn::der::decode::<rasn_pkix::Certificate>(&cert) {
+                    return Err(CertParseError::InvalidX509(format!("block {block}: {err}")));
+                } else {
+                    certs.push(cert);
                 }
-                certs.push(cert);
             }
         }
         buf.clear();
@@ -658,6 +2943,65 @@ fn try_parse_pem(
     Ok(method.map(|method| (method, certs)))
 }
 
+impl CertParseError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            CertParseError::MixedMethods | CertParseError::MethodMismatch => {
+                "mixed-certificate-types"
+            }
+            CertParseError::Pkcs12WrongPassword => "invalid-credentials",
+            CertParseError::AmbiguousCertificates => "ambiguous-certificates",
+            _ => "no-valid-certificates",
+        }
+    }
+}
+
+impl Display for CertParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CertParseError::MixedMethods => write!(f, "Cannot mix OpenPGP and S/MIME certificates"),
+            CertParseError::MethodMismatch => {
+                write!(f, "No valid certificates found for the selected encryption")
+            }
+            CertParseError::InvalidBase64 => write!(f, "Failed to decode base64 certificate"),
+            CertParseError::InvalidX509(reason) => {
+                write!(f, "Failed to decode X509 certificate: {reason}")
+            }
+            CertParseError::InvalidPgp(reason) => write!(f, "{reason}"),
+            CertParseError::InvalidPkcs12(reason) => {
+                write!(f, "Failed to decode PKCS#12 bundle: {reason}")
+            }
+            CertParseError::Pkcs12WrongPassword => {
+                write!(f, "Incorrect password for PKCS#12 bundle")
+            }
+            CertParseError::Pkcs12UnsupportedMac(algo) => {
+                write!(f, "Unsupported PKCS#12 integrity algorithm {algo}")
+            }
+            CertParseError::Pkcs12UnsupportedEncryption(algo) => {
+                write!(f, "Unsupported PKCS#12 encryption algorithm {algo}")
+            }
+            CertParseError::AmbiguousCertificates => write!(
+                f,
+                "PKCS#7 bundle contains multiple end-entity certificates, upload only the certificate to encrypt to"
+            ),
+            CertParseError::NoCertificates => write!(
+                f,
+                concat!(
+                    "Could not find any valid certificates, accepted formats are ",
+                    "PEM (.pem, .crt, .asc), DER (.der, .cer), PKCS#7 (.p7b, .p7c), ",
+                    "PKCS#12 (.p12, .pfx) and binary OpenPGP keys (.gpg, .pgp)"
+                )
+            ),
+        }
+    }
+}
+
+impl From<CertParseError> for Cow<'static, str> {
+    fn from(err: CertParseError) -> Self {
+        Cow::Owned(err.to_string())
+    }
+}
+
 impl Display for EncryptionMethod {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -667,11 +3011,23 @@ impl Display for EncryptionMethod {
     }
 }
 
+impl Display for RsaPadding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RsaPadding::Pkcs1v15 => write!(f, "PKCS#1 v1.5"),
+            RsaPadding::Oaep => write!(f, "RSAES-OAEP"),
+        }
+    }
+}
+
 impl Display for Algorithm {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Algorithm::Aes128 => write!(f, "AES-128"),
             Algorithm::Aes256 => write!(f, "AES-256"),
+            Algorithm::Aes128Gcm => write!(f, "AES-128-GCM"),
+            Algorithm::Aes256Gcm => write!(f, "AES-256-GCM"),
+            Algorithm::Chacha20Poly1305 => write!(f, "ChaCha20-Poly1305"),
         }
     }
 }
@@ -6,11 +6,14 @@
 
 use super::metadata::MessageData;
 use crate::{cache::MessageCacheFetch, mailbox::*, message::metadata::MessageMetadata};
-use common::{KV_LOCK_PURGE_ACCOUNT, Server, storage::index::ObjectIndexBuilder};
+use common::{
+    KV_BLOB_PACK_DELETIONS, KV_LOCK_PURGE_ACCOUNT, Server, storage::index::ObjectIndexBuilder,
+};
 use jmap_proto::types::collection::VanishedCollection;
 use jmap_proto::types::{collection::Collection, property::Property};
 use std::future::Future;
 use std::time::Duration;
+use store::dispatch::lookup::KeyValue;
 use store::rand::prelude::SliceRandom;
 use store::write::key::DeserializeBigEndian;
 use store::write::now;
@@ -153,6 +156,14 @@ impl EmailDeletion for Server {
             );
         }
 
+        // Verify mailbox counters
+        if let Err(err) = self.repair_mailbox_counters(account_id).await {
+            trc::error!(
+                err.details("Failed to verify mailbox counters.")
+                    .account_id(account_id)
+            );
+        }
+
         // Purge changelogs
         if let Some(history) = self.core.jmap.changes_max_history {
             if let Err(err) = self.delete_changes(account_id, history).await {
@@ -275,6 +286,7 @@ impl EmailDeletion for Server {
             AccountId = account_id,
             Total = tombstoned_ids.len(),
         );
+        let tombstoned_count = tombstoned_ids.len();
 
         // Delete full-text index
         self.core
@@ -354,6 +366,28 @@ impl EmailDeletion for Server {
 
         self.commit_batch(batch).await?;
 
+        // Track deletions, blob packing is skipped for accounts that delete frequently
+        if self.core.jmap.mail_pack_enable {
+            if let Err(err) = self
+                .in_memory_store()
+                .counter_incr(
+                    KeyValue::with_prefix(
+                        KV_BLOB_PACK_DELETIONS,
+                        account_id.to_be_bytes(),
+                        tombstoned_count as i64,
+                    )
+                    .expires(self.core.jmap.mail_pack_deletions_window.as_secs()),
+                    false,
+                )
+                .await
+            {
+                trc::error!(
+                    err.details("Failed to update deletion counter.")
+                        .account_id(account_id)
+                );
+            }
+        }
+
         Ok(())
     }
 }
@@ -4,13 +4,16 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use common::Server;
+use common::{KV_DELIVERY_LOOP, Server};
 
 use directory::Permission;
 use jmap_proto::types::{state::StateChange, type_state::DataType};
 use mail_parser::MessageParser;
 use std::{borrow::Cow, future::Future};
-use store::ahash::AHashMap;
+use store::{
+    ahash::{AHashMap, AHashSet},
+    dispatch::lookup::KeyValue,
+};
 use utils::BlobHash;
 
 use crate::{mailbox::INBOX_ID, sieve::ingest::SieveScriptIngest};
@@ -23,6 +26,8 @@ pub struct IngestMessage {
     pub recipients: Vec<String>,
     pub message_blob: BlobHash,
     pub message_size: u64,
+    // Set when an operator allowed storing the message unencrypted
+    pub encrypt: bool,
     pub session_id: u64,
 }
 
@@ -36,6 +41,10 @@ pub enum LocalDeliveryStatus {
         code: [u8; 3],
         reason: Cow<'static, str>,
     },
+    EncryptionFailure {
+        account_id: u32,
+        reason: Cow<'static, str>,
+    },
 }
 
 pub struct LocalDeliveryResult {
@@ -124,6 +133,28 @@ impl MailDelivery for Server {
             }
         };
 
+        // Recipients this message was already delivered to, and its delivery counter id
+        let (delivered_to, message_id) = MessageParser::new()
+            .parse_headers(&raw_message)
+            .map(|headers| {
+                (
+                    if self.core.smtp.session.data.add_delivered_to {
+                        headers
+                            .header_values("Delivered-To")
+                            .filter_map(|value| value.as_text())
+                            .map(|value| value.trim().to_lowercase())
+                            .collect::<AHashSet<_>>()
+                    } else {
+                        AHashSet::new()
+                    },
+                    headers
+                        .message_id()
+                        .filter(|_| self.core.jmap.mail_loop_max_deliveries.is_some())
+                        .map(|id| id.to_string()),
+                )
+            })
+            .unwrap_or_default();
+
         // Obtain the UIDs for each recipient
         let mut uids: AHashMap<u32, usize> = AHashMap::with_capacity(message.recipients.len());
         let mut result = LocalDeliveryResult {
@@ -163,53 +194,143 @@ impl MailDelivery for Server {
                 continue;
             }
 
+            // Loop detection
+            let loop_key = message_id.as_ref().map(|message_id| {
+                let mut key = Vec::with_capacity(message_id.len() + 5);
+                key.push(KV_DELIVERY_LOOP);
+                key.extend_from_slice(&uid.to_be_bytes());
+                key.extend_from_slice(message_id.as_bytes());
+                key
+            });
+            let loop_reason = if delivered_to.contains(&rcpt) {
+                Some("Message was already delivered to this recipient.")
+            } else if let (Some(key), Some(max_deliveries)) =
+                (&loop_key, self.core.jmap.mail_loop_max_deliveries)
+            {
+                match self.in_memory_store().counter_get(key.clone()).await {
+                    Ok(count) if count as u64 >= max_deliveries => {
+                        Some("Message was delivered to this recipient too many times.")
+                    }
+                    Ok(_) => None,
+                    Err(err) => {
+                        trc::error!(
+                            err.details("Failed to obtain delivery counter.")
+                                .span_id(message.session_id)
+                                .caused_by(trc::location!())
+                        );
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            if let Some(reason) = loop_reason {
+                trc::event!(
+                    MessageIngest(trc::MessageIngestEvent::LoopDetected),
+                    To = rcpt,
+                    From = message.sender_address.clone(),
+                    Reason = reason,
+                    SpanId = message.session_id,
+                );
+
+                uids.insert(uid, result.status.len());
+                result.status.push(LocalDeliveryStatus::PermanentFailure {
+                    code: [5, 4, 6],
+                    reason: "Mail loop detected.".into(),
+                });
+                continue;
+            }
+
             // Obtain access token
             let status = match self.get_access_token(uid).await.and_then(|token| {
                 token
                     .assert_has_permission(Permission::EmailReceive)
                     .map(|_| token)
             }) {
-                Ok(access_token) => {
-                    // Check if there is an active sieve script
-                    match self.sieve_script_get_active(uid).await {
-                        Ok(None) => {
-                            // Ingest message
-                            self.email_ingest(IngestEmail {
-                                raw_message: &raw_message,
-                                message: MessageParser::new().parse(&raw_message),
-                                resource: access_token.as_resource_token(),
-                                mailbox_ids: vec![INBOX_ID],
-                                keywords: vec![],
-                                received_at: None,
-                                source: IngestSource::Smtp { deliver_to: &rcpt },
-                                spam_classify: access_token
-                                    .has_permission(Permission::SpamFilterClassify),
-                                spam_train: self.email_bayes_can_train(&access_token),
-                                session_id: message.session_id,
-                            })
-                            .await
-                        }
-                        Ok(Some(active_script)) => {
-                            self.sieve_script_ingest(
-                                &access_token,
-                                &raw_message,
+                // Deliveries to accounts in maintenance are deferred
+                Ok(access_token) => match self.assert_not_in_maintenance(&access_token).await {
+                    // Check if there is an active sieve script or a prelude to run
+                    Ok(_) => match self.sieve_script_get_active(uid).await {
+                        Ok(active_script) => match self
+                            .sieve_script_get_prelude(
                                 &message.sender_address,
                                 &rcpt,
                                 message.session_id,
-                                active_script,
-                                &mut result.autogenerated,
+                                active_script.as_ref(),
                             )
                             .await
-                        }
+                            .or(active_script)
+                        {
+                            None => {
+                                // Ingest message
+                                self.email_ingest(IngestEmail {
+                                    raw_message: &raw_message,
+                                    message: MessageParser::new().parse(&raw_message),
+                                    resource: access_token.as_resource_token(),
+                                    mailbox_ids: vec![INBOX_ID],
+                                    keywords: vec![],
+                                    received_at: None,
+                                    source: IngestSource::Smtp {
+                                        deliver_to: &rcpt,
+                                        catch_all: self
+                                            .is_catch_all_rcpt(
+                                                &access_token.emails,
+                                                &rcpt,
+                                                message.session_id,
+                                            )
+                                            .await,
+                                        encrypt: message.encrypt,
+                                    },
+                                    spam_classify: access_token
+                                        .has_permission(Permission::SpamFilterClassify),
+                                    spam_train: self.email_bayes_can_train(&access_token),
+                                    session_id: message.session_id,
+                                })
+                                .await
+                            }
+                            Some(active_script) => {
+                                self.sieve_script_ingest(
+                                    &access_token,
+                                    &raw_message,
+                                    &message.sender_address,
+                                    &rcpt,
+                                    message.encrypt,
+                                    message.session_id,
+                                    active_script,
+                                    &mut result.autogenerated,
+                                )
+                                .await
+                            }
+                        },
                         Err(err) => Err(err),
-                    }
-                }
+                    },
+                    Err(err) => Err(err),
+                },
 
                 Err(err) => Err(err),
             };
 
             let status = match status {
                 Ok(ingested_message) => {
+                    // Count deliveries of this message to the recipient
+                    if let Some(key) = loop_key {
+                        if let Err(err) = self
+                            .in_memory_store()
+                            .counter_incr(
+                                KeyValue::new(key, 1)
+                                    .expires(self.core.jmap.mail_loop_window.as_secs()),
+                                false,
+                            )
+                            .await
+                        {
+                            trc::error!(
+                                err.details("Failed to update delivery counter.")
+                                    .span_id(message.session_id)
+                                    .caused_by(trc::location!())
+                            );
+                        }
+                    }
+
                     // Notify state change
                     if ingested_message.change_id != u64::MAX {
                         self.broadcast_state_change(
@@ -236,6 +357,11 @@ impl MailDelivery for Server {
                                 reason: "Organization over quota.".into(),
                             }
                         }
+                        trc::EventType::Limit(trc::LimitEvent::Maintenance) => {
+                            LocalDeliveryStatus::TemporaryFailure {
+                                reason: "Mailbox in maintenance, try again later.".into(),
+                            }
+                        }
                         trc::EventType::Security(trc::SecurityEvent::Unauthorized) => {
                             LocalDeliveryStatus::PermanentFailure {
                                 code: [5, 5, 0],
@@ -258,6 +384,18 @@ impl MailDelivery for Server {
                                     .into(),
                             }
                         }
+                        // Messages that cannot be encrypted are kept in the queue until
+                        // the certificates are fixed or an operator intervenes
+                        trc::EventType::MessageIngest(
+                            trc::MessageIngestEvent::EncryptionFailed,
+                        ) => LocalDeliveryStatus::EncryptionFailure {
+                            account_id: uid,
+                            reason: err
+                                .value_as_str(trc::Key::Reason)
+                                .unwrap_or("Unknown error")
+                                .to_string()
+                                .into(),
+                        },
                         _ => LocalDeliveryStatus::TemporaryFailure {
                             reason: "Transient server failure.".into(),
                         },
@@ -0,0 +1,261 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::{
+    crypto::{EncryptMessage, EncryptMessageError, EncryptionIdentities, EncryptionParams},
+    ingest::remove_contents,
+    integrity::{EmailIntegrity, IntegrityReason},
+    metadata::{MessageData, MessageMetadata},
+};
+use common::Server;
+use jmap_proto::types::{
+    collection::{Collection, SyncCollection},
+    property::Property,
+};
+use mail_parser::{Message, MessageParser};
+use store::write::{BatchBuilder, TaskQueueClass, ValueClass};
+use trc::AddContext;
+use utils::BlobHash;
+
+pub trait EncryptStoredMessage: Sync + Send {
+    fn encrypt_stored_message(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        hash: &BlobHash,
+        raw_message: &[u8],
+    ) -> impl Future<Output = trc::Result<bool>> + Send;
+
+    fn encrypt_new_message(
+        &self,
+        account_id: u32,
+        message: &Message<'_>,
+        mailbox_ids: &[u32],
+        session_id: u64,
+    ) -> impl Future<Output = trc::Result<Option<Vec<u8>>>> + Send;
+}
+
+impl EncryptStoredMessage for Server {
+    async fn encrypt_stored_message(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        hash: &BlobHash,
+        raw_message: &[u8],
+    ) -> trc::Result<bool> {
+        // Encryption might have been disabled since the task was queued
+        let Some(params_) = self
+            .get_archive_by_property(account_id, Collection::Principal, 0, Property::Parameters)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(false);
+        };
+        let identities = params_
+            .unarchive::<Vec<EncryptionParams>>()
+            .caused_by(trc::location!())?;
+
+        // Obtain message metadata, skip messages that were deleted or replaced
+        let Some(metadata_) = self
+            .get_archive_by_property(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::BodyStructure,
+            )
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(false);
+        };
+        let metadata = metadata_
+            .unarchive::<MessageMetadata>()
+            .caused_by(trc::location!())?;
+        if BlobHash::from(&metadata.blob_hash) != *hash {
+            return Ok(false);
+        }
+        let Some(data_) = self
+            .get_archive(account_id, Collection::Email, document_id)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(false);
+        };
+        let data = data_
+            .unarchive::<MessageData>()
+            .caused_by(trc::location!())?;
+
+        // Large messages and messages filed only into excluded mailboxes
+        // are stored unencrypted
+        if raw_message.len() as u64 > identities.max_encrypt_size(self.core.jmap.encrypt_max_size) {
+            return Ok(false);
+        }
+        let mailbox_ids = data
+            .mailboxes
+            .iter()
+            .map(|m| m.mailbox_id.to_native())
+            .collect::<Vec<_>>();
+        if identities.is_excluded(&mailbox_ids) {
+            return Ok(false);
+        }
+
+        let message = MessageParser::new().parse(raw_message).ok_or_else(|| {
+            trc::StoreEvent::CryptoError
+                .into_err()
+                .caused_by(trc::location!())
+                .reason("Failed to parse e-mail message.")
+        })?;
+        let raw_message = match message
+            .encrypt(
+                identities,
+                self.core.jmap.encrypt_pgp_signing_key.as_deref(),
+            )
+            .await
+        {
+            Ok(raw_message) => raw_message,
+            Err(EncryptMessageError::Error(err)) => {
+                trc::bail!(
+                    trc::StoreEvent::CryptoError
+                        .into_err()
+                        .caused_by(trc::location!())
+                        .reason(err)
+                );
+            }
+            Err(EncryptMessageError::AlreadyEncrypted) => return Ok(false),
+        };
+        let mut message = MessageParser::new().parse(&raw_message).ok_or_else(|| {
+            trc::StoreEvent::CryptoError
+                .into_err()
+                .caused_by(trc::location!())
+                .reason("Failed to parse encrypted e-mail message.")
+        })?;
+        remove_contents(&mut message);
+
+        // Store encrypted blob
+        let blob_id = self
+            .put_blob(account_id, &raw_message, false)
+            .await
+            .caused_by(trc::location!())?;
+
+        // Obtain tenant id
+        let tenant_id = self
+            .get_access_token(account_id)
+            .await
+            .caused_by(trc::location!())?
+            .tenant
+            .map(|t| t.id);
+
+        // Record the new content hash along with the previous ones
+        let integrity = self.message_integrity(
+            account_id,
+            document_id,
+            self.message_integrity_get(account_id, document_id).await?,
+            blob_id.hash.clone(),
+            IntegrityReason::Encrypt,
+            None,
+        )?;
+
+        // Replace metadata and request the message to be reindexed
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Email)
+            .update_document(document_id)
+            .assert_value(Property::BodyStructure, &metadata_);
+        metadata
+            .index(&mut batch, account_id, tenant_id, false)
+            .caused_by(trc::location!())?;
+        MessageMetadata::from_message(
+            message,
+            blob_id.hash.clone(),
+            u64::from(metadata.received_at),
+        )
+        .index(&mut batch, account_id, tenant_id, true)
+        .caused_by(trc::location!())?;
+        batch
+            .set(Property::Integrity, integrity)
+            .log_item_update(SyncCollection::Email, data.thread_id.to_native().into())
+            .set(
+                ValueClass::TaskQueue(TaskQueueClass::IndexEmail {
+                    seq: self.generate_snowflake_id(),
+                    hash: blob_id.hash.clone(),
+                }),
+                vec![],
+            );
+        self.store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())?;
+
+        trc::event!(
+            Store(trc::StoreEvent::IntegrityUpdate),
+            AccountId = account_id,
+            DocumentId = document_id,
+            BlobId = blob_id.hash.to_hex(),
+            Reason = IntegrityReason::Encrypt.as_str(),
+        );
+
+        // Request FTS index
+        self.notify_task_queue();
+
+        Ok(true)
+    }
+
+    async fn encrypt_new_message(
+        &self,
+        account_id: u32,
+        message: &Message<'_>,
+        mailbox_ids: &[u32],
+        session_id: u64,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        if !self.core.jmap.encrypt {
+            return Ok(None);
+        }
+        let Some(params_) = self
+            .get_archive_by_property(account_id, Collection::Principal, 0, Property::Parameters)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(None);
+        };
+        let identities = params_
+            .unarchive::<Vec<EncryptionParams>>()
+            .caused_by(trc::location!())?;
+
+        // Large messages and messages filed only into excluded mailboxes
+        // are stored unencrypted
+        let message_len = message.raw_message.len() as u64;
+        let max_encrypt_size = identities.max_encrypt_size(self.core.jmap.encrypt_max_size);
+        if message_len > max_encrypt_size {
+            trc::event!(
+                MessageIngest(trc::MessageIngestEvent::EncryptionSkipped),
+                SpanId = session_id,
+                AccountId = account_id,
+                Size = message_len,
+                Limit = max_encrypt_size,
+            );
+            return Ok(None);
+        } else if identities.is_excluded(mailbox_ids) {
+            return Ok(None);
+        }
+
+        match message
+            .encrypt(
+                identities,
+                self.core.jmap.encrypt_pgp_signing_key.as_deref(),
+            )
+            .await
+        {
+            Ok(raw_message) => Ok(Some(raw_message)),
+            Err(EncryptMessageError::Error(err)) => Err(trc::MessageIngestEvent::EncryptionFailed
+                .into_err()
+                .account_id(account_id)
+                .caused_by(trc::location!())
+                .reason(err)),
+            Err(EncryptMessageError::AlreadyEncrypted) => Ok(None),
+        }
+    }
+}
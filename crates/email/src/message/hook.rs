@@ -0,0 +1,758 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, net::IpAddr, time::Duration};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use common::{KV_LOCK_INGEST_HOOK, Server, storage::index::ObjectIndexBuilder};
+use jmap_proto::types::{
+    blob::BlobId, collection::Collection, id::Id, keyword::Keyword, property::Property,
+    state::StateChange, type_state::DataType,
+};
+use mail_parser::{MessageParser, MimeHeaders};
+use ring::hmac;
+use store::{
+    BlobClass, BlobHash, Serialize, ValueKey,
+    write::{AlignedBytes, Archive, Archiver, BatchBuilder, TaskQueueClass, ValueClass, now},
+};
+use trc::{AddContext, MessageIngestEvent};
+use utils::is_public_ip;
+
+use super::{index::TrimTextValue, metadata::MessageData};
+
+const DEFAULT_HEADERS: &[&str] = &["From", "To", "Cc", "Subject", "Date", "Message-ID"];
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestHook {
+    pub url: String,
+    #[serde(default, skip_serializing)]
+    pub secret: String,
+    #[serde(default)]
+    pub headers: Vec<String>,
+    #[serde(default)]
+    pub keyword: Option<String>,
+}
+
+#[derive(
+    rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Default, PartialEq, Eq,
+)]
+pub struct IngestHookTask {
+    pub received_at: u64,
+    pub rcpt_to: String,
+    pub attempts: u32,
+    pub due: u64,
+}
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    serde::Serialize,
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestHookDeadLetter {
+    pub id: u64,
+    pub document_id: u32,
+    pub created: u64,
+    pub attempts: u32,
+    pub reason: String,
+    #[serde(skip)]
+    pub hash: BlobHash,
+    #[serde(skip)]
+    pub task: IngestHookTask,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Default)]
+pub struct IngestHookDeadLetters {
+    pub items: Vec<IngestHookDeadLetter>,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestHookPayload<'x> {
+    pub account_id: String,
+    pub mailbox_id: String,
+    pub email_id: String,
+    pub blob_id: String,
+    pub received_at: u64,
+    pub envelope: IngestHookEnvelope<'x>,
+    pub headers: Vec<IngestHookHeader<'x>>,
+    pub text_body: String,
+    pub text_body_truncated: bool,
+    pub attachments: Vec<IngestHookAttachment<'x>>,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestHookEnvelope<'x> {
+    pub mail_from: Option<&'x str>,
+    pub rcpt_to: &'x str,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct IngestHookHeader<'x> {
+    pub name: &'x str,
+    pub value: String,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestHookAttachment<'x> {
+    pub blob_id: String,
+    pub name: Option<&'x str>,
+    #[serde(rename = "type")]
+    pub content_type: Option<String>,
+    pub size: usize,
+}
+
+pub trait IngestHooks: Sync + Send {
+    fn ingest_hook_get(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<IngestHook>>> + Send;
+
+    fn ingest_hook_mailboxes(
+        &self,
+        account_id: u32,
+        mailbox_ids: &[u32],
+    ) -> impl Future<Output = trc::Result<Vec<u32>>> + Send;
+
+    fn ingest_hook_run(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        mailbox_id: u32,
+        seq: u64,
+        hash: &BlobHash,
+        raw_message: &[u8],
+    ) -> impl Future<Output = trc::Result<Option<Duration>>> + Send;
+
+    fn ingest_hook_deliver(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+        document_id: u32,
+        hook: &IngestHook,
+        payload: &str,
+    ) -> impl Future<Output = Result<(), String>> + Send;
+
+    fn ingest_hook_retry(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+        id: Option<u64>,
+    ) -> impl Future<Output = trc::Result<usize>> + Send;
+
+    fn ingest_hook_dead_letters(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+    ) -> impl Future<Output = trc::Result<IngestHookDeadLetters>> + Send;
+
+    fn ingest_hook_update_dead_letters(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+        f: impl FnOnce(&mut IngestHookDeadLetters) + Send,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl IngestHooks for Server {
+    async fn ingest_hook_get(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+    ) -> trc::Result<Option<IngestHook>> {
+        self.get_archive_by_property(
+            account_id,
+            Collection::Mailbox,
+            mailbox_id,
+            Property::Parameters,
+        )
+        .await
+        .caused_by(trc::location!())?
+        .map(|hook| hook.deserialize::<IngestHook>())
+        .transpose()
+        .caused_by(trc::location!())
+    }
+
+    async fn ingest_hook_mailboxes(
+        &self,
+        account_id: u32,
+        mailbox_ids: &[u32],
+    ) -> trc::Result<Vec<u32>> {
+        let mut hooks = Vec::new();
+        for mailbox_id in mailbox_ids {
+            if self
+                .ingest_hook_get(account_id, *mailbox_id)
+                .await?
+                .is_some()
+            {
+                hooks.push(*mailbox_id);
+            }
+        }
+        Ok(hooks)
+    }
+
+    async fn ingest_hook_run(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        mailbox_id: u32,
+        seq: u64,
+        hash: &BlobHash,
+        raw_message: &[u8],
+    ) -> trc::Result<Option<Duration>> {
+        let class = ValueClass::TaskQueue(TaskQueueClass::IngestHook {
+            seq,
+            hash: hash.clone(),
+            mailbox_id,
+        });
+        let Some(task_) = self
+            .store()
+            .get_value::<Archive<AlignedBytes>>(ValueKey {
+                account_id,
+                collection: Collection::Email.into(),
+                document_id,
+                class: class.clone(),
+            })
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(None);
+        };
+        let mut task = task_
+            .deserialize::<IngestHookTask>()
+            .caused_by(trc::location!())?;
+        let now = now();
+        if task.due > now {
+            return Ok(Some(Duration::from_secs(task.due - now)));
+        }
+
+        // Messages deleted and hooks removed since ingestion are not notified
+        let Some(hook) = self.ingest_hook_get(account_id, mailbox_id).await? else {
+            return Ok(None);
+        };
+        let Some(data_) = self
+            .get_archive(account_id, Collection::Email, document_id)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(None);
+        };
+        let thread_id = data_
+            .unarchive::<MessageData>()
+            .caused_by(trc::location!())?
+            .thread_id
+            .to_native();
+
+        // Build payload
+        let Some(message) = MessageParser::new().parse(raw_message) else {
+            trc::event!(
+                MessageIngest(MessageIngestEvent::HookError),
+                AccountId = account_id,
+                MailboxId = mailbox_id,
+                DocumentId = document_id,
+                Reason = "Failed to parse message",
+            );
+            return Ok(None);
+        };
+        let blob_class = BlobClass::Linked {
+            account_id,
+            collection: Collection::Email.into(),
+            document_id,
+        };
+        let max_body = self.core.jmap.ingest_hook_max_body;
+        let text_body = message.body_text(0).unwrap_or_default();
+        let payload = match serde_json::to_string(&IngestHookPayload {
+            account_id: Id::from(account_id).to_string(),
+            mailbox_id: Id::from(mailbox_id).to_string(),
+            email_id: Id::from_parts(thread_id, document_id).to_string(),
+            blob_id: BlobId::new(hash.clone(), blob_class.clone()).to_string(),
+            received_at: task.received_at,
+            envelope: IngestHookEnvelope {
+                mail_from: message.return_address(),
+                rcpt_to: &task.rcpt_to,
+            },
+            headers: message
+                .root_part()
+                .headers
+                .iter()
+                .filter(|header| hook.wants_header(header.name.as_str()))
+                .filter_map(|header| {
+                    raw_message
+                        .get(header.offset_start as usize..header.offset_end as usize)
+                        .map(|value| IngestHookHeader {
+                            name: header.name.as_str(),
+                            value: String::from_utf8_lossy(value).trim().to_string(),
+                        })
+                })
+                .collect(),
+            text_body_truncated: text_body.len() > max_body,
+            text_body: text_body.trim_text(max_body).into_owned(),
+            attachments: message
+                .attachments
+                .iter()
+                .filter_map(|part_id| message.parts.get(*part_id as usize))
+                .map(|part| IngestHookAttachment {
+                    blob_id: BlobId::new_section(
+                        hash.clone(),
+                        blob_class.clone(),
+                        part.offset_body as usize,
+                        part.offset_end as usize,
+                        part.encoding as u8,
+                    )
+                    .to_string(),
+                    name: part.attachment_name(),
+                    content_type: part.content_type().map(|ct| {
+                        ct.subtype()
+                            .map(|st| format!("{}/{}", ct.ctype(), st))
+                            .unwrap_or_else(|| ct.ctype().to_string())
+                    }),
+                    size: part.len(),
+                })
+                .collect(),
+        }) {
+            Ok(payload) => payload,
+            Err(err) => {
+                trc::event!(
+                    MessageIngest(MessageIngestEvent::HookError),
+                    AccountId = account_id,
+                    MailboxId = mailbox_id,
+                    DocumentId = document_id,
+                    Reason = err.to_string(),
+                );
+                return Ok(None);
+            }
+        };
+
+        let reason = match self
+            .ingest_hook_deliver(account_id, mailbox_id, document_id, &hook, &payload)
+            .await
+        {
+            Ok(_) => return Ok(None),
+            Err(reason) => reason,
+        };
+
+        // Schedule the next attempt or move the notification to the dead-letter queue
+        let config = &self.core.jmap;
+        if task.retry(
+            now,
+            config.ingest_hook_retry_interval,
+            config.ingest_hook_attempts_max,
+        ) {
+            let next_retry = Duration::from_secs(task.due - now);
+            trc::event!(
+                MessageIngest(MessageIngestEvent::HookError),
+                AccountId = account_id,
+                MailboxId = mailbox_id,
+                DocumentId = document_id,
+                Url = hook.url.clone(),
+                Reason = reason,
+                NextRetry = next_retry,
+            );
+
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Email)
+                .update_document(document_id)
+                .set(
+                    class,
+                    Archiver::new(task)
+                        .serialize()
+                        .caused_by(trc::location!())?,
+                );
+            self.store()
+                .write(batch.build_all())
+                .await
+                .caused_by(trc::location!())?;
+
+            Ok(Some(next_retry))
+        } else {
+            trc::event!(
+                MessageIngest(MessageIngestEvent::HookDeadLetter),
+                AccountId = account_id,
+                MailboxId = mailbox_id,
+                DocumentId = document_id,
+                Url = hook.url.clone(),
+                Reason = reason.clone(),
+                Total = task.attempts,
+            );
+
+            let dead_letter = IngestHookDeadLetter {
+                id: self.generate_snowflake_id(),
+                document_id,
+                created: now,
+                attempts: task.attempts,
+                reason,
+                hash: hash.clone(),
+                task,
+            };
+            let max_dead_letters = config.ingest_hook_dead_letter_max;
+            self.ingest_hook_update_dead_letters(account_id, mailbox_id, move |items| {
+                items.push(dead_letter, max_dead_letters)
+            })
+            .await
+            .map(|_| None)
+        }
+    }
+
+    async fn ingest_hook_deliver(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+        document_id: u32,
+        hook: &IngestHook,
+        payload: &str,
+    ) -> Result<(), String> {
+        let url = hook
+            .validate_url(self.core.jmap.ingest_hook_allow_private_ips)
+            .map_err(|err| format!("Invalid hook URL {}: {err}", hook.url))?;
+        let timestamp = now();
+        let mut request = self
+            .core
+            .jmap
+            .ingest_hook_client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("X-Signature-Timestamp", timestamp.to_string());
+
+        // Add HMAC-SHA256 signature, the timestamp is signed along with the payload
+        // so receivers can reject replayed requests
+        if !hook.secret.is_empty() {
+            request = request.header(
+                "X-Signature",
+                ingest_hook_signature(&hook.secret, timestamp, payload),
+            );
+        }
+
+        // Send request
+        let response = request
+            .body(payload.to_string())
+            .send()
+            .await
+            .map_err(|err| format!("Hook request to {} failed: {err}", hook.url))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Hook request to {} failed with code {}: {}",
+                hook.url,
+                response.status().as_u16(),
+                response.status().canonical_reason().unwrap_or("Unknown")
+            ));
+        }
+
+        trc::event!(
+            MessageIngest(MessageIngestEvent::HookDelivered),
+            AccountId = account_id,
+            MailboxId = mailbox_id,
+            DocumentId = document_id,
+            Url = hook.url.clone(),
+        );
+
+        // Mark the message as notified
+        if let Some(keyword) = hook.keyword.as_deref().filter(|k| !k.is_empty()) {
+            if let Err(err) = self
+                .ingest_hook_add_keyword(account_id, document_id, Keyword::from(keyword))
+                .await
+            {
+                trc::error!(
+                    err.account_id(account_id)
+                        .document_id(document_id)
+                        .details("Failed to set ingest hook keyword")
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn ingest_hook_retry(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+        id: Option<u64>,
+    ) -> trc::Result<usize> {
+        // Move dead letters back to the task queue
+        let mut retry = Vec::new();
+        self.ingest_hook_update_dead_letters(account_id, mailbox_id, |items| {
+            items.items.retain(|item| {
+                if id.is_none_or(|id| id == item.id) {
+                    retry.push(item.clone());
+                    false
+                } else {
+                    true
+                }
+            });
+        })
+        .await?;
+        if retry.is_empty() {
+            return Ok(0);
+        }
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Email);
+        for item in &retry {
+            batch.update_document(item.document_id).set(
+                ValueClass::TaskQueue(TaskQueueClass::IngestHook {
+                    seq: self.generate_snowflake_id(),
+                    hash: item.hash.clone(),
+                    mailbox_id,
+                }),
+                Archiver::new(IngestHookTask {
+                    attempts: 0,
+                    due: 0,
+                    ..item.task.clone()
+                })
+                .serialize()
+                .caused_by(trc::location!())?,
+            );
+        }
+        self.store()
+            .write(batch.build_all())
+            .await
+            .caused_by(trc::location!())?;
+        self.notify_task_queue();
+
+        Ok(retry.len())
+    }
+
+    async fn ingest_hook_dead_letters(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+    ) -> trc::Result<IngestHookDeadLetters> {
+        self.get_archive_by_property(
+            account_id,
+            Collection::Mailbox,
+            mailbox_id,
+            Property::DeadLetters,
+        )
+        .await
+        .caused_by(trc::location!())?
+        .map(|items| items.deserialize::<IngestHookDeadLetters>())
+        .transpose()
+        .caused_by(trc::location!())
+        .map(|items| items.unwrap_or_default())
+    }
+
+    async fn ingest_hook_update_dead_letters(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+        f: impl FnOnce(&mut IngestHookDeadLetters) + Send,
+    ) -> trc::Result<()> {
+        // Serialize updates across nodes
+        let mut key = Vec::with_capacity(8);
+        key.extend_from_slice(&account_id.to_be_bytes());
+        key.extend_from_slice(&mailbox_id.to_be_bytes());
+        let mut attempts = 0;
+        while !self
+            .in_memory_store()
+            .try_lock(KV_LOCK_INGEST_HOOK, &key, 10)
+            .await
+            .caused_by(trc::location!())?
+        {
+            attempts += 1;
+            if attempts >= 50 {
+                return Err(trc::StoreEvent::UnexpectedError
+                    .into_err()
+                    .caused_by(trc::location!())
+                    .details("Timed out waiting for ingest hook lock"));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        let result = async {
+            let mut items = self
+                .ingest_hook_dead_letters(account_id, mailbox_id)
+                .await?;
+            f(&mut items);
+
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Mailbox)
+                .update_document(mailbox_id);
+            if !items.items.is_empty() {
+                batch.set(
+                    Property::DeadLetters,
+                    Archiver::new(items)
+                        .serialize()
+                        .caused_by(trc::location!())?,
+                );
+            } else {
+                batch.clear(Property::DeadLetters);
+            }
+            self.store()
+                .write(batch.build_all())
+                .await
+                .caused_by(trc::location!())
+                .map(|_| ())
+        }
+        .await;
+
+        let _ = self
+            .in_memory_store()
+            .remove_lock(KV_LOCK_INGEST_HOOK, &key)
+            .await;
+
+        result
+    }
+}
+
+trait IngestHookKeyword {
+    fn ingest_hook_add_keyword(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        keyword: Keyword,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl IngestHookKeyword for Server {
+    async fn ingest_hook_add_keyword(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        keyword: Keyword,
+    ) -> trc::Result<()> {
+        let Some(data_) = self
+            .get_archive(account_id, Collection::Email, document_id)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(());
+        };
+        let data = data_
+            .to_unarchived::<MessageData>()
+            .caused_by(trc::location!())?;
+        let mut new_data = data.deserialize().caused_by(trc::location!())?;
+        if !new_data.add_keyword(keyword) {
+            return Ok(());
+        }
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Email)
+            .update_document(document_id)
+            .custom(
+                ObjectIndexBuilder::new()
+                    .with_current(data)
+                    .with_changes(new_data),
+            )
+            .caused_by(trc::location!())?;
+        let change_id = self
+            .commit_batch(batch)
+            .await
+            .and_then(|ids| ids.last_change_id(account_id))
+            .caused_by(trc::location!())?;
+        self.broadcast_state_change(
+            StateChange::new(account_id, change_id)
+                .with_change(DataType::Email)
+                .with_change(DataType::Mailbox),
+        )
+        .await;
+
+        Ok(())
+    }
+}
+
+impl IngestHook {
+    // Hooks are only sent to HTTPS endpoints, the client refuses to connect to host
+    // names that resolve to private addresses
+    pub fn validate_url(&self, allow_private_ips: bool) -> Result<reqwest::Url, &'static str> {
+        let url = reqwest::Url::parse(&self.url).map_err(|_| "Invalid URL")?;
+        if url.scheme() != "https" {
+            return Err("Only HTTPS endpoints are supported");
+        }
+        let host = url
+            .host_str()
+            .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+            .filter(|host| !host.is_empty())
+            .ok_or("Invalid URL")?;
+        if !allow_private_ips && host.parse::<IpAddr>().is_ok_and(|ip| !is_public_ip(&ip)) {
+            return Err("Only public addresses are supported");
+        }
+
+        Ok(url)
+    }
+
+    pub fn wants_header(&self, name: &str) -> bool {
+        if self.headers.is_empty() {
+            DEFAULT_HEADERS
+                .iter()
+                .any(|header| header.eq_ignore_ascii_case(name))
+        } else {
+            self.headers
+                .iter()
+                .any(|header| header.eq_ignore_ascii_case(name))
+        }
+    }
+}
+
+impl IngestHookTask {
+    pub fn new(rcpt_to: impl Into<String>, received_at: u64) -> Self {
+        IngestHookTask {
+            received_at,
+            rcpt_to: rcpt_to.into(),
+            attempts: 0,
+            due: 0,
+        }
+    }
+
+    // Returns false once all attempts have been used, otherwise schedules the next
+    // attempt with an exponential backoff
+    pub fn retry(&mut self, now: u64, interval: Duration, max_attempts: u32) -> bool {
+        self.attempts += 1;
+        if self.attempts < max_attempts.max(1) {
+            self.due = now + (interval * 2u32.pow((self.attempts - 1).min(10))).as_secs();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl IngestHookDeadLetters {
+    pub fn push(&mut self, item: IngestHookDeadLetter, max_items: usize) {
+        self.items.push(item);
+        if self.items.len() > max_items {
+            let excess = self.items.len() - max_items;
+            self.items.drain(..excess);
+        }
+    }
+}
+
+pub fn ingest_hook_signature(secret: &str, timestamp: u64, payload: &str) -> String {
+    let mut context =
+        hmac::Context::with_key(&hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()));
+    context.update(timestamp.to_string().as_bytes());
+    context.update(b".");
+    context.update(payload.as_bytes());
+    STANDARD.encode(context.sign().as_ref())
+}
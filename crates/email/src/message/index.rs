@@ -43,6 +43,75 @@ impl MessageMetadata {
         &self.contents[0].parts[0]
     }
 
+    pub fn from_message(
+        message: mail_parser::Message<'_>,
+        blob_hash: BlobHash,
+        received_at: u64,
+    ) -> Self {
+        let mut has_attachments = false;
+        let mut preview = None;
+        let preview_part_id = message
+            .text_body
+            .first()
+            .or_else(|| message.html_body.first())
+            .copied()
+            .unwrap_or(u32::MAX);
+
+        for (part_id, part) in message.parts.iter().take(MAX_MESSAGE_PARTS).enumerate() {
+            let part_id = part_id as u32;
+            match &part.body {
+                mail_parser::PartType::Text(text) => {
+                    if part_id == preview_part_id {
+                        preview =
+                            preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH).into();
+                    }
+
+                    if !message.text_body.contains(&part_id)
+                        && !message.html_body.contains(&part_id)
+                    {
+                        has_attachments = true;
+                    }
+                }
+                mail_parser::PartType::Html(html) => {
+                    let text = html_to_text(html);
+                    if part_id == preview_part_id {
+                        preview =
+                            preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH).into();
+                    }
+
+                    if !message.text_body.contains(&part_id)
+                        && !message.html_body.contains(&part_id)
+                    {
+                        has_attachments = true;
+                    }
+                }
+                mail_parser::PartType::Binary(_) | mail_parser::PartType::Message(_)
+                    if !has_attachments =>
+                {
+                    has_attachments = true;
+                }
+                _ => {}
+            }
+        }
+
+        let root_part = message.root_part();
+        MessageMetadata {
+            preview: preview.unwrap_or_default().into_owned(),
+            size: message.raw_message.len() as u32,
+            raw_headers: message
+                .raw_message
+                .as_ref()
+                .get(root_part.offset_header as usize..root_part.offset_body as usize)
+                .unwrap_or_default()
+                .to_vec(),
+            contents: vec![],
+            received_at,
+            has_attachments,
+            blob_hash,
+        }
+        .with_contents(message)
+    }
+
     pub fn index(
         self,
         batch: &mut BatchBuilder,
@@ -59,6 +128,7 @@ impl MessageMetadata {
             // Delete metadata
             batch
                 .clear(Property::BodyStructure)
+                .clear(Property::Integrity)
                 .unindex(Property::Size, self.size.serialize())
                 .unindex(Property::ReceivedAt, (self.received_at).serialize());
         }
@@ -107,6 +177,23 @@ impl MessageMetadata {
     }
 
     fn index_headers(&self, batch: &mut BatchBuilder, set: bool) {
+        // Index the recipient targeted on catch-all deliveries
+        if let Some(original_to) = self
+            .root_part()
+            .headers
+            .iter()
+            .find(|header| header.name.as_str().eq_ignore_ascii_case("X-Original-To"))
+            .and_then(|header| header.value.as_text())
+            .map(|value| value.trim().to_lowercase())
+            .filter(|value| !value.is_empty() && value.len() < MAX_SORT_FIELD_LENGTH)
+        {
+            if set {
+                batch.index(Property::RcptTo, original_to.into_bytes());
+            } else {
+                batch.unindex(Property::RcptTo, original_to.into_bytes());
+            }
+        }
+
         let mut seen_headers = [false; 40];
         for header in self.root_part().headers.iter().rev() {
             if matches!(header.name, HeaderName::Other(_)) {
@@ -258,6 +345,7 @@ impl ArchivedMessageMetadata {
             // Delete metadata
             batch
                 .clear(Property::BodyStructure)
+                .clear(Property::Integrity)
                 .unindex(Property::Size, u32::from(self.size).serialize())
                 .unindex(
                     Property::ReceivedAt,
@@ -299,6 +387,23 @@ impl ArchivedMessageMetadata {
     }
 
     fn index_headers(&self, batch: &mut BatchBuilder, set: bool) {
+        // Index the recipient targeted on catch-all deliveries
+        if let Some(original_to) = self
+            .root_part()
+            .headers
+            .iter()
+            .find(|header| header.name.as_str().eq_ignore_ascii_case("X-Original-To"))
+            .and_then(|header| header.value.as_text())
+            .map(|value| value.trim().to_lowercase())
+            .filter(|value| !value.is_empty() && value.len() < MAX_SORT_FIELD_LENGTH)
+        {
+            if set {
+                batch.index(Property::RcptTo, original_to.into_bytes());
+            } else {
+                batch.unindex(Property::RcptTo, original_to.into_bytes());
+            }
+        }
+
         let mut seen_headers = [false; 40];
         for header in self.root_part().headers.iter().rev() {
             if matches!(header.name, ArchivedHeaderName::Other(_)) {
@@ -482,73 +587,12 @@ impl IndexMessage for BatchBuilder {
         // Index receivedAt
         self.index(Property::ReceivedAt, received_at.serialize());
 
-        let mut has_attachments = false;
-        let mut preview = None;
-        let preview_part_id = message
-            .text_body
-            .first()
-            .or_else(|| message.html_body.first())
-            .copied()
-            .unwrap_or(u32::MAX);
-
-        for (part_id, part) in message.parts.iter().take(MAX_MESSAGE_PARTS).enumerate() {
-            let part_id = part_id as u32;
-            match &part.body {
-                mail_parser::PartType::Text(text) => {
-                    if part_id == preview_part_id {
-                        preview =
-                            preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH).into();
-                    }
-
-                    if !message.text_body.contains(&part_id)
-                        && !message.html_body.contains(&part_id)
-                    {
-                        has_attachments = true;
-                    }
-                }
-                mail_parser::PartType::Html(html) => {
-                    let text = html_to_text(html);
-                    if part_id == preview_part_id {
-                        preview =
-                            preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH).into();
-                    }
-
-                    if !message.text_body.contains(&part_id)
-                        && !message.html_body.contains(&part_id)
-                    {
-                        has_attachments = true;
-                    }
-                }
-                mail_parser::PartType::Binary(_) | mail_parser::PartType::Message(_)
-                    if !has_attachments =>
-                {
-                    has_attachments = true;
-                }
-                _ => {}
-            }
-        }
-
         // Build metadata
-        let root_part = message.root_part();
-        let metadata = MessageMetadata {
-            preview: preview.unwrap_or_default().into_owned(),
-            size: message.raw_message.len() as u32,
-            raw_headers: message
-                .raw_message
-                .as_ref()
-                .get(root_part.offset_header as usize..root_part.offset_body as usize)
-                .unwrap_or_default()
-                .to_vec(),
-            contents: vec![],
-            received_at,
-            has_attachments,
-            blob_hash,
-        }
-        .with_contents(message);
+        let metadata = MessageMetadata::from_message(message, blob_hash, received_at);
         metadata.index_headers(self, true);
 
         // Store and index hasAttachment property
-        if has_attachments {
+        if metadata.has_attachments {
             self.tag(Property::HasAttachment, ());
         }
 
@@ -5,15 +5,16 @@
  */
 
 use super::{
-    crypto::{EncryptMessage, EncryptMessageError},
+    encrypt::EncryptStoredMessage,
     index::{MAX_SORT_FIELD_LENGTH, TrimTextValue},
 };
 use crate::{
     cache::{MessageCacheFetch, email::MessageCacheAccess},
-    mailbox::{INBOX_ID, JUNK_ID, UidMailbox},
+    mailbox::{INBOX_ID, JUNK_ID, UidMailbox, manage::MailboxFnc},
     message::{
-        crypto::EncryptionParams,
+        hook::{IngestHookTask, IngestHooks},
         index::{IndexMessage, MAX_ID_LENGTH, VisitText},
+        integrity::{EmailIntegrity, IntegrityReason},
         metadata::MessageData,
     },
 };
@@ -21,6 +22,7 @@ use common::{
     IDX_EMAIL, Server,
     auth::{AccessToken, ResourceToken},
     storage::index::ObjectIndexBuilder,
+    telemetry::load_test::LoadTestStage,
 };
 use directory::Permission;
 use jmap_proto::types::{
@@ -45,11 +47,11 @@ use std::{
     time::{Duration, Instant},
 };
 use store::{
-    BlobClass, IndexKey, IndexKeyPrefix, IterateParams, U32_LEN,
+    BlobClass, IndexKey, IndexKeyPrefix, IterateParams, Serialize, U32_LEN,
     ahash::AHashMap,
     query::Filter,
     roaring::RoaringBitmap,
-    write::{BatchBuilder, TaskQueueClass, ValueClass, key::DeserializeBigEndian, now},
+    write::{Archiver, BatchBuilder, TaskQueueClass, ValueClass, key::DeserializeBigEndian, now},
 };
 use store::{SerializeInfallible, rand::Rng};
 use trc::{AddContext, MessageIngestEvent};
@@ -79,9 +81,17 @@ pub struct IngestEmail<'x> {
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum IngestSource<'x> {
-    Smtp { deliver_to: &'x str },
-    Jmap,
-    Imap,
+    Smtp {
+        deliver_to: &'x str,
+        catch_all: bool,
+        encrypt: bool,
+    },
+    Jmap {
+        shared: bool,
+    },
+    Imap {
+        shared: bool,
+    },
     Restore,
 }
 
@@ -138,7 +148,11 @@ impl EmailIngest for Server {
         let mut extra_headers = String::new();
         let mut extra_headers_parsed = Vec::new();
         match params.source {
-            IngestSource::Smtp { deliver_to } => {
+            IngestSource::Smtp {
+                deliver_to,
+                catch_all,
+                ..
+            } => {
                 // Add delivered to header
                 if self.core.smtp.session.data.add_delivered_to {
                     extra_headers = format!("Delivered-To: {deliver_to}\r\n");
@@ -151,6 +165,19 @@ impl EmailIngest for Server {
                     });
                 }
 
+                // Preserve the address targeted on catch-all deliveries
+                if catch_all {
+                    let offset_field = extra_headers.len();
+                    let _ = write!(&mut extra_headers, "X-Original-To: {deliver_to}\r\n");
+                    extra_headers_parsed.push(Header {
+                        name: HeaderName::Other("X-Original-To".into()),
+                        value: HeaderValue::Text(deliver_to.into()),
+                        offset_field: offset_field as u32,
+                        offset_start: (offset_field + 14) as u32,
+                        offset_end: extra_headers.len() as u32,
+                    });
+                }
+
                 // Spam classification and training
                 if params.spam_classify
                     && self.core.spam.enabled
@@ -288,8 +315,27 @@ impl EmailIngest for Server {
                         params.keywords.push(Keyword::Junk);
                     }
                 }
+
+                // File catch-all deliveries into a mailbox named after the targeted address
+                if catch_all
+                    && self.core.jmap.mail_catch_all_auto_file
+                    && params.mailbox_ids == [INBOX_ID]
+                {
+                    match self.mailbox_catch_all(account_id, deliver_to).await {
+                        Ok(Some(mailbox_id)) => {
+                            params.mailbox_ids[0] = mailbox_id;
+                        }
+                        Ok(None) => (),
+                        Err(err) => {
+                            trc::error!(
+                                err.span_id(params.session_id)
+                                    .details("Failed to obtain catch-all mailbox")
+                            );
+                        }
+                    }
+                }
             }
-            IngestSource::Jmap | IngestSource::Imap
+            IngestSource::Jmap { .. } | IngestSource::Imap { .. }
                 if params.spam_train && self.core.spam.enabled =>
             {
                 if params.keywords.contains(&Keyword::Junk) {
@@ -436,68 +482,40 @@ impl EmailIngest for Server {
             root_part.headers = extra_headers_parsed;
         }
 
-        // Encrypt message
+        // Encrypt message, appends made by the account owner are only encrypted
+        // when enabled while messages written by anyone else always are
         let do_encrypt = match params.source {
-            IngestSource::Jmap | IngestSource::Imap => {
-                self.core.jmap.encrypt && self.core.jmap.encrypt_append
+            IngestSource::Jmap { shared } | IngestSource::Imap { shared } => {
+                shared || self.core.jmap.encrypt_append
             }
-            IngestSource::Smtp { .. } => self.core.jmap.encrypt,
+            IngestSource::Smtp { encrypt, .. } => encrypt,
             IngestSource::Restore => false,
         };
-        if do_encrypt && !message.is_encrypted() {
-            if let Some(encrypt_params_) = self
-                .get_archive_by_property(account_id, Collection::Principal, 0, Property::Parameters)
-                .await
-                .caused_by(trc::location!())?
+        if do_encrypt {
+            let encrypt_start = Instant::now();
+            if let Some(new_raw_message) = self
+                .encrypt_new_message(account_id, &message, &params.mailbox_ids, params.session_id)
+                .await?
             {
-                let encrypt_params = encrypt_params_
-                    .unarchive::<EncryptionParams>()
-                    .caused_by(trc::location!())?;
-                match message.encrypt(encrypt_params).await {
-                    Ok(new_raw_message) => {
-                        raw_message = Cow::from(new_raw_message);
-                        raw_message_len = raw_message.len() as u64;
-                        message = MessageParser::default()
-                            .parse(raw_message.as_ref())
-                            .ok_or_else(|| {
-                                trc::EventType::MessageIngest(trc::MessageIngestEvent::Error)
-                                    .ctx(trc::Key::Code, 550)
-                                    .ctx(
-                                        trc::Key::Reason,
-                                        "Failed to parse encrypted e-mail message.",
-                                    )
-                            })?;
-
-                        // Remove contents from parsed message
-                        for part in &mut message.parts {
-                            match &mut part.body {
-                                PartType::Text(txt) | PartType::Html(txt) => {
-                                    *txt = Cow::from("");
-                                }
-                                PartType::Binary(bin) | PartType::InlineBinary(bin) => {
-                                    *bin = Cow::from(&[][..]);
-                                }
-                                PartType::Message(_) => {
-                                    part.body = PartType::Binary(Cow::from(&[][..]));
-                                }
-                                PartType::Multipart(_) => (),
-                            }
-                        }
-                    }
-                    Err(EncryptMessageError::Error(err)) => {
-                        trc::bail!(
-                            trc::StoreEvent::CryptoError
-                                .into_err()
-                                .caused_by(trc::location!())
-                                .reason(err)
-                        );
-                    }
-                    _ => unreachable!(),
-                }
+                self.record_load_test_stage(LoadTestStage::Encrypt, encrypt_start.elapsed());
+                raw_message = Cow::from(new_raw_message);
+                raw_message_len = raw_message.len() as u64;
+                message = MessageParser::default()
+                    .parse(raw_message.as_ref())
+                    .ok_or_else(|| {
+                        trc::MessageIngestEvent::EncryptionFailed
+                            .into_err()
+                            .account_id(account_id)
+                            .reason("Failed to parse encrypted e-mail message.")
+                    })?;
+
+                // Remove contents from parsed message
+                remove_contents(&mut message);
             }
         }
 
         // Store blob
+        let store_start = Instant::now();
         let blob_id = self
             .put_blob(account_id, raw_message.as_ref(), false)
             .await
@@ -515,6 +533,29 @@ impl EmailIngest for Server {
             imap_uids.push(uid);
         }
 
+        // Mailboxes with ingestion hooks are notified from the task queue
+        let ingest_hooks: Vec<(u32, &str)> = match params.source {
+            IngestSource::Smtp { deliver_to, .. }
+                if deliver_to
+                    .rsplit_once('@')
+                    .is_some_and(|(_, domain)| self.core.jmap.is_ingest_hook_allowed(domain)) =>
+            {
+                self.ingest_hook_mailboxes(account_id, &params.mailbox_ids)
+                    .await
+                    .unwrap_or_else(|err| {
+                        trc::error!(
+                            err.span_id(params.session_id)
+                                .details("Failed to obtain mailbox ingestion hooks")
+                        );
+                        vec![]
+                    })
+                    .into_iter()
+                    .map(|mailbox_id| (mailbox_id, deliver_to))
+                    .collect()
+            }
+            _ => vec![],
+        };
+
         // Build write batch
         let mut batch = BatchBuilder::new();
         let mailbox_ids_event = mailbox_ids
@@ -531,6 +572,7 @@ impl EmailIngest for Server {
         }
 
         let seq = self.generate_snowflake_id();
+        let received_at = params.received_at.unwrap_or_else(now);
         let document_id = self
             .store()
             .assign_document_ids(account_id, Collection::Email, 1)
@@ -549,9 +591,20 @@ impl EmailIngest for Server {
                     keywords: params.keywords,
                     thread_id,
                 },
-                params.received_at.unwrap_or_else(now),
+                received_at,
             )
             .caused_by(trc::location!())?
+            .set(
+                Property::Integrity,
+                self.message_integrity(
+                    account_id,
+                    document_id,
+                    None,
+                    blob_id.hash.clone(),
+                    IntegrityReason::Ingest,
+                    (!matches!(params.source, IngestSource::Smtp { .. })).then_some(account_id),
+                )?,
+            )
             .set(
                 ValueClass::TaskQueue(TaskQueueClass::IndexEmail {
                     seq,
@@ -572,6 +625,20 @@ impl EmailIngest for Server {
             );
         }
 
+        // Request mailbox ingestion hooks
+        for (mailbox_id, deliver_to) in ingest_hooks {
+            batch.set(
+                ValueClass::TaskQueue(TaskQueueClass::IngestHook {
+                    seq,
+                    hash: blob_id.hash.clone(),
+                    mailbox_id,
+                }),
+                Archiver::new(IngestHookTask::new(deliver_to, received_at))
+                    .serialize()
+                    .caused_by(trc::location!())?,
+            );
+        }
+
         // Insert and obtain ids
         let change_id = self
             .store()
@@ -580,8 +647,12 @@ impl EmailIngest for Server {
             .caused_by(trc::location!())?
             .last_change_id(account_id)?;
         let id = Id::from_parts(thread_id, document_id);
+        if matches!(params.source, IngestSource::Smtp { .. }) {
+            self.record_load_test_stage(LoadTestStage::Store, store_start.elapsed());
+        }
 
         // Request FTS index
+        self.inner.data.index_backlog.enqueued(account_id);
         self.notify_task_queue();
 
         trc::event!(
@@ -592,8 +663,8 @@ impl EmailIngest for Server {
                     } else {
                         MessageIngestEvent::Spam
                     },
-                IngestSource::Jmap | IngestSource::Restore => MessageIngestEvent::JmapAppend,
-                IngestSource::Imap => MessageIngestEvent::ImapAppend,
+                IngestSource::Jmap { .. } | IngestSource::Restore => MessageIngestEvent::JmapAppend,
+                IngestSource::Imap { .. } => MessageIngestEvent::ImapAppend,
             }),
             SpanId = params.session_id,
             AccountId = account_id,
@@ -880,3 +951,20 @@ impl From<IngestedEmail> for Object<Value> {
             .with_property(Property::Size, email.size)
     }
 }
+
+pub(super) fn remove_contents(message: &mut Message<'_>) {
+    for part in &mut message.parts {
+        match &mut part.body {
+            PartType::Text(txt) | PartType::Html(txt) => {
+                *txt = Cow::from("");
+            }
+            PartType::Binary(bin) | PartType::InlineBinary(bin) => {
+                *bin = Cow::from(&[][..]);
+            }
+            PartType::Message(_) => {
+                part.body = PartType::Binary(Cow::from(&[][..]));
+            }
+            PartType::Multipart(_) => (),
+        }
+    }
+}
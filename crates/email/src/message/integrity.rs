@@ -0,0 +1,652 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    borrow::Cow,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use common::{KV_INTEGRITY_REPORT, KV_LOCK_INTEGRITY, Server};
+use jmap_proto::types::{collection::Collection, property::Property};
+use ring::hmac;
+use store::{
+    Serialize,
+    dispatch::lookup::KeyValue,
+    rand::prelude::SliceRandom,
+    write::{AlignedBytes, Archive, Archiver, now},
+};
+use trc::AddContext;
+use utils::BlobHash;
+
+use super::metadata::MessageMetadata;
+
+const REPORT_KEY: &[u8] = b"report";
+const AUDIT_KEY: &[u8] = b"audit";
+const REPORT_MAX_ENTRIES: usize = 1000;
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Default)]
+pub struct MessageIntegrity {
+    pub changes: Vec<IntegrityChange>,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityChange {
+    pub hash: BlobHash,
+    pub reason: IntegrityReason,
+    pub actor: Option<u32>,
+    pub changed_at: u64,
+    pub seal: Option<Vec<u8>>,
+}
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum IntegrityReason {
+    Ingest,
+    Copy,
+    Encrypt,
+}
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum IntegrityFailure {
+    HashMismatch,
+    MetadataMismatch,
+    InvalidSeal,
+    BlobMissing,
+}
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum IntegritySource {
+    Audit,
+    Download,
+}
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityReportEntry {
+    pub account_id: u32,
+    pub document_id: u32,
+    pub blob_hash: String,
+    pub failure: IntegrityFailure,
+    pub source: IntegritySource,
+    pub reason: Option<IntegrityReason>,
+    pub actor: Option<u32>,
+    pub changed_at: u64,
+    pub detected_at: u64,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Default)]
+pub struct IntegrityReport {
+    pub items: Vec<IntegrityReportEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrityStatus {
+    Valid,
+    NotRecorded,
+    Failed(IntegrityFailure),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntegrityCheck {
+    pub status: IntegrityStatus,
+    pub bytes_read: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IntegrityAuditStats {
+    pub checked: u64,
+    pub failed: u64,
+    pub skipped: u64,
+    pub bytes_read: u64,
+}
+
+pub trait EmailIntegrity: Sync + Send {
+    fn message_integrity(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        previous: Option<MessageIntegrity>,
+        hash: BlobHash,
+        reason: IntegrityReason,
+        actor: Option<u32>,
+    ) -> trc::Result<Vec<u8>>;
+
+    fn message_integrity_get(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<MessageIntegrity>>> + Send;
+
+    fn verify_message_integrity(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        contents: Option<&[u8]>,
+    ) -> impl Future<Output = trc::Result<IntegrityCheck>> + Send;
+
+    fn report_integrity_failure(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        failure: IntegrityFailure,
+        source: IntegritySource,
+    ) -> impl Future<Output = ()> + Send;
+
+    fn audit_messages(&self) -> impl Future<Output = ()> + Send;
+
+    fn audit_messages_sample(
+        &self,
+    ) -> impl Future<Output = trc::Result<IntegrityAuditStats>> + Send;
+
+    fn integrity_report(
+        &self,
+    ) -> impl Future<Output = trc::Result<Vec<IntegrityReportEntry>>> + Send;
+
+    fn integrity_update_report(
+        &self,
+        f: impl FnOnce(&mut Vec<IntegrityReportEntry>) + Send,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl EmailIntegrity for Server {
+    fn message_integrity(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        previous: Option<MessageIntegrity>,
+        hash: BlobHash,
+        reason: IntegrityReason,
+        actor: Option<u32>,
+    ) -> trc::Result<Vec<u8>> {
+        let mut integrity = previous.unwrap_or_default();
+        let mut change = IntegrityChange {
+            hash,
+            reason,
+            actor,
+            changed_at: now(),
+            seal: None,
+        };
+
+        // Each seal covers the previous one so the change history cannot be rewritten
+        if let Some(key) = &self.core.jmap.mail_integrity_seal_key {
+            let previous = integrity
+                .changes
+                .last()
+                .and_then(|change| change.seal.as_deref());
+            change.seal = change.sign(key, account_id, document_id, previous).into();
+        }
+        integrity.changes.push(change);
+
+        Archiver::new(integrity)
+            .serialize()
+            .caused_by(trc::location!())
+    }
+
+    async fn message_integrity_get(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> trc::Result<Option<MessageIntegrity>> {
+        self.get_archive_by_property(
+            account_id,
+            Collection::Email,
+            document_id,
+            Property::Integrity,
+        )
+        .await
+        .caused_by(trc::location!())?
+        .map(|integrity| integrity.deserialize::<MessageIntegrity>())
+        .transpose()
+        .caused_by(trc::location!())
+    }
+
+    async fn verify_message_integrity(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        contents: Option<&[u8]>,
+    ) -> trc::Result<IntegrityCheck> {
+        let mut check = IntegrityCheck {
+            status: IntegrityStatus::NotRecorded,
+            bytes_read: 0,
+        };
+
+        // Messages stored before integrity records were introduced cannot be verified
+        let Some(integrity) = self.message_integrity_get(account_id, document_id).await? else {
+            return Ok(check);
+        };
+        let Some(current) = integrity.current() else {
+            return Ok(check);
+        };
+        let Some(metadata_) = self
+            .get_archive_by_property(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::BodyStructure,
+            )
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(check);
+        };
+        let metadata = metadata_
+            .unarchive::<MessageMetadata>()
+            .caused_by(trc::location!())?;
+
+        check.status = if BlobHash::from(&metadata.blob_hash) != current.hash {
+            IntegrityStatus::Failed(IntegrityFailure::MetadataMismatch)
+        } else if !integrity.has_valid_seals(
+            self.core.jmap.mail_integrity_seal_key.as_deref(),
+            account_id,
+            document_id,
+        ) {
+            IntegrityStatus::Failed(IntegrityFailure::InvalidSeal)
+        } else {
+            let blob = if let Some(contents) = contents {
+                Some(Cow::Borrowed(contents))
+            } else {
+                self.blob_store()
+                    .get_blob(current.hash.as_slice(), 0..usize::MAX)
+                    .await
+                    .caused_by(trc::location!())?
+                    .map(Cow::Owned)
+            };
+
+            if let Some(blob) = blob {
+                check.bytes_read = blob.len();
+                if BlobHash::generate(&blob) == current.hash {
+                    IntegrityStatus::Valid
+                } else {
+                    IntegrityStatus::Failed(IntegrityFailure::HashMismatch)
+                }
+            } else {
+                IntegrityStatus::Failed(IntegrityFailure::BlobMissing)
+            }
+        };
+
+        if let IntegrityStatus::Failed(failure) = check.status {
+            trc::event!(
+                Store(trc::StoreEvent::IntegrityMismatch),
+                AccountId = account_id,
+                DocumentId = document_id,
+                BlobId = current.hash.to_hex(),
+                Reason = failure.as_str(),
+            );
+        }
+
+        Ok(check)
+    }
+
+    async fn report_integrity_failure(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        failure: IntegrityFailure,
+        source: IntegritySource,
+    ) {
+        let current = match self.message_integrity_get(account_id, document_id).await {
+            Ok(integrity) => integrity.and_then(|integrity| integrity.current().cloned()),
+            Err(err) => {
+                trc::error!(
+                    err.account_id(account_id)
+                        .document_id(document_id)
+                        .details("Failed to obtain message integrity record")
+                );
+                None
+            }
+        };
+        let entry = IntegrityReportEntry {
+            account_id,
+            document_id,
+            blob_hash: current
+                .as_ref()
+                .map(|change| change.hash.to_hex())
+                .unwrap_or_default(),
+            failure,
+            source,
+            reason: current.as_ref().map(|change| change.reason),
+            actor: current.as_ref().and_then(|change| change.actor),
+            changed_at: current.as_ref().map_or(0, |change| change.changed_at),
+            detected_at: now(),
+        };
+
+        if let Err(err) = self
+            .integrity_update_report(|items| {
+                items.retain(|item| {
+                    item.account_id != account_id || item.document_id != document_id
+                });
+                items.push(entry);
+                if items.len() > REPORT_MAX_ENTRIES {
+                    items.drain(..items.len() - REPORT_MAX_ENTRIES);
+                }
+            })
+            .await
+        {
+            trc::error!(
+                err.account_id(account_id)
+                    .document_id(document_id)
+                    .details("Failed to update integrity report")
+            );
+        }
+    }
+
+    async fn audit_messages(&self) {
+        // Only one node audits messages at a time
+        match self
+            .in_memory_store()
+            .try_lock(KV_LOCK_INTEGRITY, AUDIT_KEY, 86400)
+            .await
+        {
+            Ok(true) => (),
+            Ok(false) => {
+                trc::event!(Purge(trc::PurgeEvent::InProgress), Type = "integrity_audit");
+                return;
+            }
+            Err(err) => {
+                trc::error!(err.details("Failed to lock integrity audit."));
+                return;
+            }
+        }
+
+        let start_time = Instant::now();
+        match self.audit_messages_sample().await {
+            Ok(stats) => {
+                trc::event!(
+                    Store(trc::StoreEvent::IntegrityAudit),
+                    Total = stats.checked,
+                    TotalFailures = stats.failed,
+                    Details = stats.skipped,
+                    Size = stats.bytes_read,
+                    Elapsed = start_time.elapsed(),
+                );
+            }
+            Err(err) => {
+                trc::error!(err.details("Failed to audit messages."));
+            }
+        }
+
+        if let Err(err) = self
+            .in_memory_store()
+            .remove_lock(KV_LOCK_INTEGRITY, AUDIT_KEY)
+            .await
+        {
+            trc::error!(err.details("Failed to delete lock."));
+        }
+    }
+
+    async fn audit_messages_sample(&self) -> trc::Result<IntegrityAuditStats> {
+        let config = &self.core.jmap;
+        let mut stats = IntegrityAuditStats::default();
+        let Some(account_ids) = self
+            .get_document_ids(u32::MAX, Collection::Principal)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(stats);
+        };
+        let mut account_ids: Vec<u32> = account_ids.into_iter().collect();
+        account_ids.shuffle(&mut store::rand::rng());
+
+        // Spread the sample across accounts
+        let per_account = config
+            .mail_audit_sample_size
+            .div_ceil(account_ids.len().max(1))
+            .max(1);
+        let start_time = Instant::now();
+
+        'outer: for account_id in account_ids {
+            let Some(document_ids) = self
+                .get_document_ids(account_id, Collection::Email)
+                .await
+                .caused_by(trc::location!())?
+            else {
+                continue;
+            };
+            let mut document_ids: Vec<u32> = document_ids.into_iter().collect();
+            document_ids.shuffle(&mut store::rand::rng());
+
+            for document_id in document_ids.into_iter().take(per_account) {
+                if stats.checked + stats.skipped >= config.mail_audit_sample_size as u64
+                    || stats.bytes_read >= config.mail_audit_max_bytes as u64
+                {
+                    break 'outer;
+                }
+
+                match self
+                    .verify_message_integrity(account_id, document_id, None)
+                    .await
+                {
+                    Ok(check) => {
+                        stats.bytes_read += check.bytes_read as u64;
+                        match check.status {
+                            IntegrityStatus::Valid => {
+                                stats.checked += 1;
+                            }
+                            IntegrityStatus::NotRecorded => {
+                                stats.skipped += 1;
+                            }
+                            IntegrityStatus::Failed(failure) => {
+                                stats.checked += 1;
+                                stats.failed += 1;
+                                self.report_integrity_failure(
+                                    account_id,
+                                    document_id,
+                                    failure,
+                                    IntegritySource::Audit,
+                                )
+                                .await;
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        trc::error!(
+                            err.account_id(account_id)
+                                .document_id(document_id)
+                                .details("Failed to verify message integrity.")
+                        );
+                    }
+                }
+
+                // Throttle blob reads so the audit does not compete with live traffic
+                let expected = Duration::from_secs_f64(
+                    stats.bytes_read as f64 / config.mail_audit_rate as f64,
+                );
+                let elapsed = start_time.elapsed();
+                if expected > elapsed {
+                    tokio::time::sleep(expected - elapsed).await;
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+
+    async fn integrity_report(&self) -> trc::Result<Vec<IntegrityReportEntry>> {
+        self.in_memory_store()
+            .key_get::<Archive<AlignedBytes>>(KeyValue::<()>::build_key(
+                KV_INTEGRITY_REPORT,
+                REPORT_KEY,
+            ))
+            .await
+            .caused_by(trc::location!())?
+            .map(|items| {
+                items
+                    .deserialize::<IntegrityReport>()
+                    .map(|items| items.items)
+            })
+            .transpose()
+            .caused_by(trc::location!())
+            .map(|items| items.unwrap_or_default())
+    }
+
+    async fn integrity_update_report(
+        &self,
+        f: impl FnOnce(&mut Vec<IntegrityReportEntry>) + Send,
+    ) -> trc::Result<()> {
+        // Serialize updates across nodes
+        let mut attempts = 0;
+        while !self
+            .in_memory_store()
+            .try_lock(KV_LOCK_INTEGRITY, REPORT_KEY, 10)
+            .await
+            .caused_by(trc::location!())?
+        {
+            attempts += 1;
+            if attempts >= 50 {
+                return Err(trc::StoreEvent::UnexpectedError
+                    .into_err()
+                    .caused_by(trc::location!())
+                    .details("Timed out waiting for integrity report lock"));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        let result = async {
+            let mut items = self.integrity_report().await?;
+            f(&mut items);
+
+            if !items.is_empty() {
+                self.in_memory_store()
+                    .key_set(KeyValue::with_prefix(
+                        KV_INTEGRITY_REPORT,
+                        REPORT_KEY,
+                        Archiver::new(IntegrityReport { items })
+                            .serialize()
+                            .caused_by(trc::location!())?,
+                    ))
+                    .await
+            } else {
+                self.in_memory_store()
+                    .key_delete(KeyValue::<()>::build_key(KV_INTEGRITY_REPORT, REPORT_KEY))
+                    .await
+            }
+        }
+        .await;
+
+        let _ = self
+            .in_memory_store()
+            .remove_lock(KV_LOCK_INTEGRITY, REPORT_KEY)
+            .await;
+
+        result
+    }
+}
+
+impl IntegrityChange {
+    fn sign(
+        &self,
+        key: &str,
+        account_id: u32,
+        document_id: u32,
+        previous: Option<&[u8]>,
+    ) -> Vec<u8> {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes());
+        let mut context = hmac::Context::with_key(&key);
+        context.update(&account_id.to_be_bytes());
+        context.update(&document_id.to_be_bytes());
+        context.update(self.hash.as_slice());
+        context.update(self.reason.as_str().as_bytes());
+        context.update(&self.actor.unwrap_or(u32::MAX).to_be_bytes());
+        context.update(&self.changed_at.to_be_bytes());
+        if let Some(previous) = previous {
+            context.update(previous);
+        }
+        context.sign().as_ref().to_vec()
+    }
+}
+
+impl MessageIntegrity {
+    pub fn current(&self) -> Option<&IntegrityChange> {
+        self.changes.last()
+    }
+
+    fn has_valid_seals(&self, key: Option<&str>, account_id: u32, document_id: u32) -> bool {
+        // Seals can only be verified when a key is configured
+        let Some(key) = key else {
+            return true;
+        };
+        let mut previous = None;
+        for change in &self.changes {
+            if let Some(seal) = &change.seal {
+                if &change.sign(key, account_id, document_id, previous) != seal {
+                    return false;
+                }
+            }
+            previous = change.seal.as_deref();
+        }
+        true
+    }
+}
+
+impl IntegrityReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntegrityReason::Ingest => "ingest",
+            IntegrityReason::Copy => "copy",
+            IntegrityReason::Encrypt => "encrypt",
+        }
+    }
+}
+
+impl IntegrityFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntegrityFailure::HashMismatch => "Message contents do not match the recorded hash",
+            IntegrityFailure::MetadataMismatch => {
+                "Message metadata does not match the recorded hash"
+            }
+            IntegrityFailure::InvalidSeal => "Integrity record seal is invalid",
+            IntegrityFailure::BlobMissing => "Message blob is missing",
+        }
+    }
+}
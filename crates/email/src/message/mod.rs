@@ -9,6 +9,12 @@ pub mod copy;
 pub mod crypto;
 pub mod delete;
 pub mod delivery;
+pub mod encrypt;
+pub mod hook;
 pub mod index;
+pub mod integrity;
 pub mod ingest;
 pub mod metadata;
+pub mod pack;
+pub mod pkcs12;
+pub mod wkd;
@@ -0,0 +1,320 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{KV_BLOB_PACK_DELETIONS, KV_LOCK_BLOB_PACK, Server};
+use jmap_proto::types::{collection::Collection, property::Property};
+use store::{
+    IndexKey, IterateParams, SerializeInfallible, U32_LEN,
+    ahash::AHashSet,
+    dispatch::lookup::KeyValue,
+    rand::prelude::SliceRandom,
+    roaring::RoaringBitmap,
+    write::{
+        key::DeserializeBigEndian,
+        now,
+        pack::{BLOB_PACK_MAX_ENTRIES, BlobPackSummary},
+    },
+};
+use trc::{AddContext, Collector, MetricType};
+use utils::BlobHash;
+
+use super::metadata::MessageMetadata;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BlobPackStats {
+    pub packed_blobs: u64,
+    pub packs: u64,
+    pub wasted_size: u64,
+}
+
+impl BlobPackStats {
+    /// Blob store objects (and the requests needed to write and delete them)
+    /// avoided by storing the packed blobs in a single object per pack.
+    pub fn saved_objects(&self) -> u64 {
+        self.packed_blobs.saturating_sub(self.packs)
+    }
+}
+
+pub trait EmailPacking: Sync + Send {
+    fn pack_accounts(&self) -> impl Future<Output = ()> + Send;
+
+    fn pack_account(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<BlobPackStats>> + Send;
+
+    fn blob_pack_stats(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<BlobPackStats>> + Send;
+}
+
+impl EmailPacking for Server {
+    async fn pack_accounts(&self) {
+        if let Ok(Some(account_ids)) = self.get_document_ids(u32::MAX, Collection::Principal).await
+        {
+            let mut account_ids: Vec<u32> = account_ids.into_iter().collect();
+
+            // Shuffle account ids
+            account_ids.shuffle(&mut store::rand::rng());
+
+            // Gauges report the totals of all accounts, including the ones that were
+            // not packed during this run
+            let mut stats = BlobPackStats::default();
+            for account_id in account_ids {
+                // Lock account
+                let account_stats = match self
+                    .core
+                    .storage
+                    .lookup
+                    .try_lock(KV_LOCK_BLOB_PACK, &account_id.to_be_bytes(), 3600)
+                    .await
+                {
+                    Ok(true) => {
+                        let result = self.pack_account(account_id).await.map_err(|err| {
+                            err.details("Failed to pack messages.")
+                                .account_id(account_id)
+                        });
+
+                        // Delete lock
+                        if let Err(err) = self
+                            .in_memory_store()
+                            .remove_lock(KV_LOCK_BLOB_PACK, &account_id.to_be_bytes())
+                            .await
+                        {
+                            trc::error!(
+                                err.details("Failed to delete lock.").account_id(account_id)
+                            );
+                        }
+
+                        match result {
+                            Ok(account_stats) => Ok(account_stats),
+                            Err(err) => {
+                                trc::error!(err);
+                                self.blob_pack_stats(account_id).await
+                            }
+                        }
+                    }
+                    Ok(false) => {
+                        trc::event!(Purge(trc::PurgeEvent::InProgress), AccountId = account_id);
+                        self.blob_pack_stats(account_id).await
+                    }
+                    Err(err) => {
+                        trc::error!(
+                            err.details("Failed to lock account.")
+                                .account_id(account_id)
+                        );
+                        self.blob_pack_stats(account_id).await
+                    }
+                };
+
+                match account_stats {
+                    Ok(account_stats) => {
+                        stats.packed_blobs += account_stats.packed_blobs;
+                        stats.packs += account_stats.packs;
+                        stats.wasted_size += account_stats.wasted_size;
+                    }
+                    Err(err) => {
+                        trc::error!(
+                            err.details("Failed to obtain blob pack statistics.")
+                                .account_id(account_id)
+                        );
+                    }
+                }
+            }
+
+            Collector::update_gauge(MetricType::BlobPackedCount, stats.packed_blobs);
+            Collector::update_gauge(MetricType::BlobPackCount, stats.packs);
+            Collector::update_gauge(MetricType::BlobPackWastedSize, stats.wasted_size);
+            Collector::update_gauge(MetricType::BlobPackSavedObjects, stats.saved_objects());
+        }
+    }
+
+    async fn pack_account(&self, account_id: u32) -> trc::Result<BlobPackStats> {
+        let store = self.store();
+        let blob_store = self.blob_store();
+        let config = &self.core.jmap;
+
+        // Rewrite packs that contain too many deleted messages
+        for (pack_hash, pack) in store
+            .blob_packs(account_id)
+            .await
+            .caused_by(trc::location!())?
+        {
+            if pack.needs_rewrite(config.mail_pack_rewrite_threshold) {
+                if let Some(summary) = store
+                    .blob_pack_rewrite(blob_store, account_id, &pack_hash)
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    trc::event!(
+                        Purge(trc::PurgeEvent::BlobPackRewritten),
+                        AccountId = account_id,
+                        Id = pack_hash.to_hex(),
+                        Total = summary.count,
+                        Size = summary.size,
+                        Details = pack.wasted_size(),
+                    );
+                }
+            }
+        }
+
+        // Accounts that delete messages frequently would cause constant rewrites
+        let deletions = self
+            .in_memory_store()
+            .counter_get(KeyValue::<()>::build_key(
+                KV_BLOB_PACK_DELETIONS,
+                account_id.to_be_bytes(),
+            ))
+            .await
+            .caused_by(trc::location!())?;
+        if deletions >= config.mail_pack_max_deletions as i64 {
+            trc::event!(
+                Purge(trc::PurgeEvent::BlobPackSkipped),
+                AccountId = account_id,
+                Total = deletions,
+            );
+        } else {
+            // Obtain messages older than the threshold
+            let mut document_ids = RoaringBitmap::new();
+            store
+                .iterate(
+                    IterateParams::new(
+                        IndexKey {
+                            account_id,
+                            collection: Collection::Email.into(),
+                            document_id: 0,
+                            field: Property::ReceivedAt.into(),
+                            key: 0u64.serialize(),
+                        },
+                        IndexKey {
+                            account_id,
+                            collection: Collection::Email.into(),
+                            document_id: u32::MAX,
+                            field: Property::ReceivedAt.into(),
+                            key: now()
+                                .saturating_sub(config.mail_pack_min_age.as_secs())
+                                .serialize(),
+                        },
+                    )
+                    .no_values()
+                    .ascending(),
+                    |key, _| {
+                        document_ids.insert(
+                            key.deserialize_be_u32(key.len() - U32_LEN)
+                                .caused_by(trc::location!())?,
+                        );
+
+                        Ok(true)
+                    },
+                )
+                .await
+                .caused_by(trc::location!())?;
+
+            // Collect small messages that have not been packed yet
+            let mut seen = AHashSet::new();
+            let mut pending = Vec::new();
+            let mut pending_size = 0;
+            for document_id in document_ids {
+                let Some(metadata_) = self
+                    .get_archive_by_property(
+                        account_id,
+                        Collection::Email,
+                        document_id,
+                        Property::BodyStructure,
+                    )
+                    .await
+                    .caused_by(trc::location!())?
+                else {
+                    continue;
+                };
+                let metadata = metadata_
+                    .unarchive::<MessageMetadata>()
+                    .caused_by(trc::location!())?;
+                let size = u32::from(metadata.size) as usize;
+                let hash = BlobHash::from(&metadata.blob_hash);
+                if size > config.mail_pack_max_message_size
+                    || !seen.insert(hash.clone())
+                    || store.blob_pack_location(&hash).await?.is_some()
+                {
+                    continue;
+                }
+
+                pending.push(hash);
+                pending_size += size;
+                if pending_size >= config.mail_pack_max_size
+                    || pending.len() == BLOB_PACK_MAX_ENTRIES
+                {
+                    self.pack_blobs(account_id, std::mem::take(&mut pending))
+                        .await?;
+                    pending_size = 0;
+                }
+            }
+            if !pending.is_empty() {
+                self.pack_blobs(account_id, pending).await?;
+            }
+        }
+
+        self.blob_pack_stats(account_id).await
+    }
+
+    async fn blob_pack_stats(&self, account_id: u32) -> trc::Result<BlobPackStats> {
+        let mut stats = BlobPackStats::default();
+        for (_, pack) in self
+            .store()
+            .blob_packs(account_id)
+            .await
+            .caused_by(trc::location!())?
+        {
+            stats.packed_blobs += pack.live_count() as u64;
+            stats.packs += 1;
+            stats.wasted_size += pack.wasted_size();
+        }
+
+        Ok(stats)
+    }
+}
+
+trait EmailPackBlobs {
+    async fn pack_blobs(
+        &self,
+        account_id: u32,
+        hashes: Vec<BlobHash>,
+    ) -> trc::Result<Option<BlobPackSummary>>;
+}
+
+impl EmailPackBlobs for Server {
+    async fn pack_blobs(
+        &self,
+        account_id: u32,
+        hashes: Vec<BlobHash>,
+    ) -> trc::Result<Option<BlobPackSummary>> {
+        let summary = self
+            .store()
+            .blob_pack(
+                self.blob_store(),
+                account_id,
+                hashes,
+                self.core.jmap.mail_pack_min_messages,
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        if let Some(summary) = &summary {
+            trc::event!(
+                Purge(trc::PurgeEvent::BlobPacked),
+                AccountId = account_id,
+                Id = summary.pack.to_hex(),
+                Total = summary.count,
+                Size = summary.size,
+            );
+        }
+
+        Ok(summary)
+    }
+}
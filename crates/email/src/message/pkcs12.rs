@@ -0,0 +1,359 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::num::NonZeroU32;
+
+use aes::{
+    Aes128, Aes192, Aes256,
+    cipher::{BlockDecryptMut, KeyIvInit, block_padding::Pkcs7},
+};
+use rasn::types::{Any, ObjectIdentifier, OctetString, Oid};
+use rasn_cms::{AlgorithmIdentifier, ContentInfo, EncryptedData};
+use ring::{digest, hmac, pbkdf2};
+
+use super::crypto::CertParseError;
+
+// PKCS#7 content types (RFC 2315)
+const CONTENT_DATA: &Oid = Oid::const_new(&[1, 2, 840, 113549, 1, 7, 1]);
+const CONTENT_ENCRYPTED_DATA: &Oid = Oid::const_new(&[1, 2, 840, 113549, 1, 7, 6]);
+
+// Bag types (RFC 7292)
+const CERT_BAG: &Oid = Oid::const_new(&[1, 2, 840, 113549, 1, 12, 10, 1, 3]);
+const X509_CERTIFICATE: &Oid = Oid::const_new(&[1, 2, 840, 113549, 1, 9, 22, 1]);
+
+// DER encoded localKeyId attribute type, only present on the certificate
+// that matches the private key in the bundle
+const LOCAL_KEY_ID: &[u8] = &[
+    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x15,
+];
+
+// Password-based encryption (RFC 8018)
+const PBES2: &Oid = Oid::const_new(&[1, 2, 840, 113549, 1, 5, 13]);
+const PBKDF2: &Oid = Oid::const_new(&[1, 2, 840, 113549, 1, 5, 12]);
+const HMAC_SHA1: &Oid = Oid::const_new(&[1, 2, 840, 113549, 2, 7]);
+const HMAC_SHA256: &Oid = Oid::const_new(&[1, 2, 840, 113549, 2, 9]);
+const HMAC_SHA384: &Oid = Oid::const_new(&[1, 2, 840, 113549, 2, 10]);
+const HMAC_SHA512: &Oid = Oid::const_new(&[1, 2, 840, 113549, 2, 11]);
+const AES128_CBC: &Oid = Oid::const_new(&[2, 16, 840, 1, 101, 3, 4, 1, 2]);
+const AES192_CBC: &Oid = Oid::const_new(&[2, 16, 840, 1, 101, 3, 4, 1, 22]);
+const AES256_CBC: &Oid = Oid::const_new(&[2, 16, 840, 1, 101, 3, 4, 1, 42]);
+
+// Digest algorithms used for the integrity MAC
+const SHA1: &Oid = Oid::const_new(&[1, 3, 14, 3, 2, 26]);
+const SHA256: &Oid = Oid::const_new(&[2, 16, 840, 1, 101, 3, 4, 2, 1]);
+const SHA384: &Oid = Oid::const_new(&[2, 16, 840, 1, 101, 3, 4, 2, 2]);
+const SHA512: &Oid = Oid::const_new(&[2, 16, 840, 1, 101, 3, 4, 2, 3]);
+
+#[derive(rasn::AsnType, rasn::Decode, Debug, Clone, PartialEq)]
+struct Pfx {
+    version: u8,
+    auth_safe: ContentInfo,
+    mac_data: Option<MacData>,
+}
+
+#[derive(rasn::AsnType, rasn::Decode, Debug, Clone, PartialEq)]
+struct MacData {
+    mac: DigestInfo,
+    mac_salt: OctetString,
+    iterations: Option<u32>,
+}
+
+#[derive(rasn::AsnType, rasn::Decode, Debug, Clone, PartialEq)]
+struct DigestInfo {
+    digest_algorithm: AlgorithmIdentifier,
+    digest: OctetString,
+}
+
+#[derive(rasn::AsnType, rasn::Decode, Debug, Clone, PartialEq)]
+struct SafeBag {
+    bag_id: ObjectIdentifier,
+    #[rasn(tag(explicit(0)))]
+    bag_value: Any,
+    bag_attributes: Option<Any>,
+}
+
+#[derive(rasn::AsnType, rasn::Decode, Debug, Clone, PartialEq)]
+struct CertBag {
+    cert_id: ObjectIdentifier,
+    #[rasn(tag(explicit(0)))]
+    cert_value: OctetString,
+}
+
+#[derive(rasn::AsnType, rasn::Decode, Debug, Clone, PartialEq)]
+struct Pbes2Params {
+    key_derivation_func: AlgorithmIdentifier,
+    encryption_scheme: AlgorithmIdentifier,
+}
+
+#[derive(rasn::AsnType, rasn::Decode, Debug, Clone, PartialEq)]
+struct Pbkdf2Params {
+    salt: OctetString,
+    iteration_count: u32,
+    key_length: Option<u32>,
+    prf: Option<AlgorithmIdentifier>,
+}
+
+// Extracts the certificates from a PKCS#12 (.p12/.pfx) bundle, returns None if the
+// bytes are not a PKCS#12 bundle. Private keys are never decrypted.
+pub fn try_parse_pkcs12(
+    bytes: &[u8],
+    password: Option<&str>,
+) -> Result<Option<Vec<Vec<u8>>>, CertParseError> {
+    let pfx = match rasn::der::decode::<Pfx>(bytes) {
+        Ok(pfx) if pfx.version == 3 && &*pfx.auth_safe.content_type == CONTENT_DATA => pfx,
+        _ => return Ok(None),
+    };
+    let auth_safe = decode::<OctetString>(pfx.auth_safe.content.as_bytes())?;
+    let password = password.unwrap_or_default();
+
+    // Verify the integrity MAC, which also tells apart a wrong password
+    if let Some(mac_data) = &pfx.mac_data {
+        verify_mac(mac_data, password, &auth_safe)?;
+    }
+
+    let mut certs = Vec::new();
+    let mut leaf_certs = Vec::new();
+    for content_info in decode::<Vec<ContentInfo>>(&auth_safe)? {
+        let safe_contents = if &*content_info.content_type == CONTENT_DATA {
+            decode::<OctetString>(content_info.content.as_bytes())?.to_vec()
+        } else if &*content_info.content_type == CONTENT_ENCRYPTED_DATA {
+            let encrypted_data = decode::<EncryptedData>(content_info.content.as_bytes())?;
+            decrypt(
+                &encrypted_data
+                    .encrypted_content_info
+                    .content_encryption_algorithm,
+                password,
+                encrypted_data
+                    .encrypted_content_info
+                    .encrypted_content
+                    .as_deref()
+                    .unwrap_or_default(),
+            )?
+        } else {
+            // Public-key protected contents are not supported
+            continue;
+        };
+
+        for bag in decode::<Vec<SafeBag>>(&safe_contents)? {
+            if &*bag.bag_id != CERT_BAG {
+                continue;
+            }
+            let cert_bag = decode::<CertBag>(bag.bag_value.as_bytes())?;
+            if &*cert_bag.cert_id != X509_CERTIFICATE {
+                continue;
+            }
+            let cert = cert_bag.cert_value.to_vec();
+            if bag.bag_attributes.is_some_and(|attributes| {
+                attributes
+                    .as_bytes()
+                    .windows(LOCAL_KEY_ID.len())
+                    .any(|window| window == LOCAL_KEY_ID)
+            }) {
+                leaf_certs.push(cert);
+            } else {
+                certs.push(cert);
+            }
+        }
+    }
+
+    // Only the certificates matching the bundle's private key are used for encryption,
+    // the rest of the chain belongs to the issuing CAs
+    if !leaf_certs.is_empty() {
+        Ok(Some(leaf_certs))
+    } else if !certs.is_empty() {
+        Ok(Some(certs.into_iter().take(1).collect()))
+    } else {
+        Err(CertParseError::NoCertificates)
+    }
+}
+
+fn verify_mac(mac_data: &MacData, password: &str, contents: &[u8]) -> Result<(), CertParseError> {
+    let algorithm: &Oid = &mac_data.mac.digest_algorithm.algorithm;
+    let (digest, hmac) = if algorithm == SHA1 {
+        (
+            &digest::SHA1_FOR_LEGACY_USE_ONLY,
+            hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+        )
+    } else if algorithm == SHA256 {
+        (&digest::SHA256, hmac::HMAC_SHA256)
+    } else if algorithm == SHA384 {
+        (&digest::SHA384, hmac::HMAC_SHA384)
+    } else if algorithm == SHA512 {
+        (&digest::SHA512, hmac::HMAC_SHA512)
+    } else {
+        return Err(CertParseError::Pkcs12UnsupportedMac(oid_to_string(
+            algorithm,
+        )));
+    };
+
+    // Passwords are encoded as a null-terminated BMPString, an empty password may
+    // also have been encoded as an empty string
+    let mut encoded_password = password
+        .encode_utf16()
+        .flat_map(|ch| ch.to_be_bytes())
+        .collect::<Vec<_>>();
+    encoded_password.extend_from_slice(&[0, 0]);
+    let mut passwords = vec![encoded_password];
+    if password.is_empty() {
+        passwords.push(vec![]);
+    }
+
+    let iterations = mac_data.iterations.unwrap_or(1);
+    for password in passwords {
+        let key = pkcs12_kdf(
+            digest,
+            &password,
+            &mac_data.mac_salt,
+            3,
+            iterations,
+            digest.output_len(),
+        );
+        if hmac::verify(&hmac::Key::new(hmac, &key), contents, &mac_data.mac.digest).is_ok() {
+            return Ok(());
+        }
+    }
+
+    Err(CertParseError::Pkcs12WrongPassword)
+}
+
+fn decrypt(
+    algorithm: &AlgorithmIdentifier,
+    password: &str,
+    contents: &[u8],
+) -> Result<Vec<u8>, CertParseError> {
+    let unsupported = |algorithm: &Oid| {
+        Err(CertParseError::Pkcs12UnsupportedEncryption(oid_to_string(
+            algorithm,
+        )))
+    };
+
+    // Legacy PKCS#12 PBE schemes (RC2, 3DES) are not supported
+    if &*algorithm.algorithm != PBES2 {
+        return unsupported(&algorithm.algorithm);
+    }
+    let params = decode::<Pbes2Params>(parameters(algorithm)?)?;
+    if &*params.key_derivation_func.algorithm != PBKDF2 {
+        return unsupported(&params.key_derivation_func.algorithm);
+    }
+    let kdf_params = decode::<Pbkdf2Params>(parameters(&params.key_derivation_func)?)?;
+    let prf = match &kdf_params.prf {
+        Some(prf) if &*prf.algorithm == HMAC_SHA256 => pbkdf2::PBKDF2_HMAC_SHA256,
+        Some(prf) if &*prf.algorithm == HMAC_SHA384 => pbkdf2::PBKDF2_HMAC_SHA384,
+        Some(prf) if &*prf.algorithm == HMAC_SHA512 => pbkdf2::PBKDF2_HMAC_SHA512,
+        Some(prf) if &*prf.algorithm != HMAC_SHA1 => return unsupported(&prf.algorithm),
+        _ => pbkdf2::PBKDF2_HMAC_SHA1,
+    };
+    let cipher: &Oid = &params.encryption_scheme.algorithm;
+    let key_len = if cipher == AES128_CBC {
+        16
+    } else if cipher == AES192_CBC {
+        24
+    } else if cipher == AES256_CBC {
+        32
+    } else {
+        return unsupported(cipher);
+    };
+    let iv = decode::<OctetString>(parameters(&params.encryption_scheme)?)?;
+
+    let mut key = vec![0u8; key_len];
+    pbkdf2::derive(
+        prf,
+        NonZeroU32::new(kdf_params.iteration_count).unwrap_or(NonZeroU32::MIN),
+        &kdf_params.salt,
+        password.as_bytes(),
+        &mut key,
+    );
+
+    match key_len {
+        16 => cbc::Decryptor::<Aes128>::new_from_slices(&key, &iv)
+            .map_err(|_| invalid("Invalid initialization vector"))?
+            .decrypt_padded_vec_mut::<Pkcs7>(contents),
+        24 => cbc::Decryptor::<Aes192>::new_from_slices(&key, &iv)
+            .map_err(|_| invalid("Invalid initialization vector"))?
+            .decrypt_padded_vec_mut::<Pkcs7>(contents),
+        _ => cbc::Decryptor::<Aes256>::new_from_slices(&key, &iv)
+            .map_err(|_| invalid("Invalid initialization vector"))?
+            .decrypt_padded_vec_mut::<Pkcs7>(contents),
+    }
+    .map_err(|_| CertParseError::Pkcs12WrongPassword)
+}
+
+// Key derivation for the integrity MAC (RFC 7292, Appendix B.2)
+fn pkcs12_kdf(
+    algorithm: &'static digest::Algorithm,
+    password: &[u8],
+    salt: &[u8],
+    id: u8,
+    iterations: u32,
+    len: usize,
+) -> Vec<u8> {
+    let u = algorithm.output_len();
+    let v = algorithm.block_len();
+    let fill = |bytes: &[u8]| -> Vec<u8> {
+        bytes
+            .iter()
+            .copied()
+            .cycle()
+            .take(bytes.len().div_ceil(v) * v)
+            .collect()
+    };
+    let mut i = fill(salt);
+    i.extend(fill(password));
+
+    let mut result = Vec::with_capacity(len.div_ceil(u) * u);
+    while result.len() < len {
+        let mut ctx = digest::Context::new(algorithm);
+        ctx.update(&vec![id; v]);
+        ctx.update(&i);
+        let mut a = ctx.finish();
+        for _ in 1..iterations {
+            a = digest::digest(algorithm, a.as_ref());
+        }
+        result.extend_from_slice(a.as_ref());
+
+        // Ij = (Ij + B + 1) mod 2^(v*8)
+        let b = a
+            .as_ref()
+            .iter()
+            .copied()
+            .cycle()
+            .take(v)
+            .collect::<Vec<_>>();
+        for block in i.chunks_mut(v) {
+            let mut carry = 1u16;
+            for (x, y) in block.iter_mut().zip(b.iter()).rev() {
+                let sum = *x as u16 + *y as u16 + carry;
+                *x = sum as u8;
+                carry = sum >> 8;
+            }
+        }
+    }
+    result.truncate(len);
+    result
+}
+
+fn parameters(algorithm: &AlgorithmIdentifier) -> Result<&[u8], CertParseError> {
+    algorithm
+        .parameters
+        .as_ref()
+        .map(|params| params.as_bytes())
+        .ok_or_else(|| invalid("Missing algorithm parameters"))
+}
+
+fn decode<T: rasn::Decode>(bytes: &[u8]) -> Result<T, CertParseError> {
+    rasn::der::decode::<T>(bytes).map_err(|err| invalid(err.to_string()))
+}
+
+fn invalid(reason: impl Into<String>) -> CertParseError {
+    CertParseError::InvalidPkcs12(reason.into())
+}
+
+fn oid_to_string(oid: &Oid) -> String {
+    oid.iter()
+        .map(|arc| arc.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
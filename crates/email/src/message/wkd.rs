@@ -0,0 +1,167 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{borrow::Cow, time::Duration};
+
+use reqwest::{StatusCode, Url, redirect::Policy};
+use ring::digest::{SHA1_FOR_LEGACY_USE_ONLY, digest};
+
+use super::crypto::{EncryptionMethod, pgp_user_id_addresses, try_parse_certs};
+
+const ZBASE32_ALPHABET: &[u8] = b"ybndrfg8ejkmcpqxot1uwisza345h769";
+
+// Returns the advanced and direct Web Key Directory lookup URLs for an address
+pub fn wkd_urls(address: &str) -> Option<[Url; 2]> {
+    let (local, domain) = address.trim().rsplit_once('@')?;
+    let domain = domain.to_lowercase();
+    if local.is_empty() || domain.is_empty() {
+        return None;
+    }
+    let hash = zbase32(digest(&SHA1_FOR_LEGACY_USE_ONLY, local.to_lowercase().as_bytes()).as_ref());
+
+    let mut advanced = Url::parse(&format!(
+        "https://openpgpkey.{domain}/.well-known/openpgpkey/{domain}/hu/{hash}"
+    ))
+    .ok()?;
+    let mut direct = Url::parse(&format!(
+        "https://{domain}/.well-known/openpgpkey/hu/{hash}"
+    ))
+    .ok()?;
+    advanced.query_pairs_mut().append_pair("l", local);
+    direct.query_pairs_mut().append_pair("l", local);
+
+    Some([advanced, direct])
+}
+
+pub async fn fetch_wkd_certs(
+    address: &str,
+    timeout: Duration,
+    max_size: usize,
+) -> Result<Vec<Vec<u8>>, Cow<'static, str>> {
+    let urls = wkd_urls(address)
+        .ok_or_else(|| format!("Invalid e-mail address {address:?} for WKD lookup"))?;
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .redirect(Policy::limited(3))
+        .build()
+        .map_err(|err| format!("Failed to create HTTP client: {err}"))?;
+
+    // The direct method is only used when the advanced method fails
+    let mut errors = Vec::with_capacity(2);
+    for url in urls {
+        match wkd_fetch(&client, url, max_size).await {
+            Ok(bytes) => return wkd_certs(address, bytes),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    Err(format!(
+        "Could not fetch an OpenPGP key for {address} using WKD: {}",
+        errors.join("; ")
+    )
+    .into())
+}
+
+// Keeps the keys that have a user ID for the requested address
+pub fn wkd_certs(address: &str, bytes: Vec<u8>) -> Result<Vec<Vec<u8>>, Cow<'static, str>> {
+    let certs = try_parse_certs(EncryptionMethod::PGP, bytes)?
+        .into_iter()
+        .filter(|cert| has_user_id(cert, address))
+        .collect::<Vec<_>>();
+
+    if !certs.is_empty() {
+        Ok(certs)
+    } else {
+        Err(format!("The OpenPGP key published for {address} has no matching user ID").into())
+    }
+}
+
+async fn wkd_fetch(client: &reqwest::Client, url: Url, max_size: usize) -> Result<Vec<u8>, String> {
+    let host = url.host_str().unwrap_or_default().to_string();
+    let mut response = client.get(url).send().await.map_err(|err| {
+        if err.is_timeout() {
+            format!("request to {host} timed out")
+        } else {
+            format!("request to {host} failed: {}", error_chain(&err))
+        }
+    })?;
+
+    match response.status() {
+        StatusCode::OK => (),
+        StatusCode::NOT_FOUND => return Err(format!("no key published at {host}")),
+        status => {
+            return Err(format!(
+                "{host} returned code {}: {}",
+                status.as_u16(),
+                status.canonical_reason().unwrap_or("Unknown")
+            ));
+        }
+    }
+
+    // Stop reading as soon as the size limit is exceeded
+    let too_large = || format!("key published at {host} exceeds {max_size} bytes");
+    if response
+        .content_length()
+        .is_some_and(|len| len > max_size as u64)
+    {
+        return Err(too_large());
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|err| format!("failed to read key from {host}: {}", error_chain(&err)))?
+    {
+        if bytes.len() + chunk.len() > max_size {
+            return Err(too_large());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    if !bytes.is_empty() {
+        Ok(bytes)
+    } else {
+        Err(format!("no key published at {host}"))
+    }
+}
+
+fn has_user_id(cert: &[u8], address: &str) -> bool {
+    pgp_user_id_addresses(cert)
+        .iter()
+        .any(|email| email.eq_ignore_ascii_case(address.trim()))
+}
+
+// TLS and connection errors are only described by the source of the error
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+    message
+}
+
+fn zbase32(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            result.push(ZBASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        result.push(ZBASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    result
+}
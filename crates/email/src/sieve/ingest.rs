@@ -14,7 +14,14 @@ use crate::{
     },
 };
 use common::{
-    Server, auth::AccessToken, config::jmap::settings::SpecialUse, scripts::plugins::PluginContext,
+    Server,
+    auth::AccessToken,
+    config::jmap::settings::SpecialUse,
+    expr::{
+        V_RECIPIENT, V_RECIPIENT_DOMAIN, V_SENDER, V_SENDER_DOMAIN, Variable,
+        functions::ResolveVariable,
+    },
+    scripts::plugins::PluginContext,
 };
 use directory::{Permission, QueryBy};
 use jmap_proto::types::{collection::Collection, id::Id, keyword::Keyword, property::Property};
@@ -46,6 +53,7 @@ pub trait SieveScriptIngest: Sync + Send {
         raw_message: &[u8],
         envelope_from: &str,
         envelope_to: &str,
+        encrypt: bool,
         session_id: u64,
         active_script: ActiveScript,
         autogenerated: &mut Vec<AutogeneratedMessage>,
@@ -62,6 +70,14 @@ pub trait SieveScriptIngest: Sync + Send {
         name: &str,
     ) -> impl Future<Output = trc::Result<Option<Sieve>>> + Send;
 
+    fn sieve_script_get_prelude(
+        &self,
+        envelope_from: &str,
+        envelope_to: &str,
+        session_id: u64,
+        active_script: Option<&ActiveScript>,
+    ) -> impl Future<Output = Option<ActiveScript>> + Send;
+
     fn sieve_script_compile(
         &self,
         account_id: u32,
@@ -77,6 +93,7 @@ impl SieveScriptIngest for Server {
         raw_message: &[u8],
         envelope_from: &str,
         envelope_to: &str,
+        encrypt: bool,
         session_id: u64,
         active_script: ActiveScript,
         autogenerated: &mut Vec<AutogeneratedMessage>,
@@ -103,25 +120,45 @@ impl SieveScriptIngest for Server {
         let mut instance = self.core.sieve.untrusted_runtime.filter_parsed(message);
 
         // Set account name and email
-        let mail_from = self
+        let emails = self
             .core
             .storage
             .directory
             .query(QueryBy::Id(account_id), false)
             .await
             .caused_by(trc::location!())?
-            .and_then(|p| {
+            .map(|p| {
                 instance.set_user_full_name(p.description().unwrap_or_else(|| p.name()));
-                p.emails.into_iter().next()
-            });
-
-        // Set account address
-        let mail_from = mail_from.unwrap_or_else(|| envelope_to.into());
+                p.emails
+            })
+            .unwrap_or_default();
+        let catch_all = self
+            .is_catch_all_rcpt(&emails, envelope_to, session_id)
+            .await;
+
+        // Set account address, replies to catch-all deliveries are sent from
+        // the targeted address when it matches one of the configured patterns
+        let mail_from = if catch_all
+            && self
+                .core
+                .jmap
+                .mail_catch_all_reply_from
+                .iter()
+                .any(|pattern| pattern.matches(&envelope_to.to_lowercase()))
+        {
+            envelope_to.to_string()
+        } else {
+            emails
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| envelope_to.into())
+        };
         instance.set_user_address(&mail_from);
 
         // Set envelope
         instance.set_envelope(Envelope::From, envelope_from);
         instance.set_envelope(Envelope::To, envelope_to);
+        let rcpt_domain = domain_part(envelope_to).to_lowercase();
 
         let mut input = Input::script(
             active_script.script_name.to_string(),
@@ -160,9 +197,11 @@ impl SieveScriptIngest for Server {
                             }
                         }
                         sieve::Script::Global(name_) => {
-                            if let Some(script) =
-                                self.get_untrusted_sieve_script(&name_.to_lowercase(), session_id)
-                            {
+                            if let Some(script) = self.get_global_sieve_script(
+                                &name_.to_lowercase(),
+                                &rcpt_domain,
+                                session_id,
+                            ) {
                                 input = Input::script(name, script.clone());
                             } else {
                                 input = false.into();
@@ -395,10 +434,20 @@ impl SieveScriptIngest for Server {
                                     SpanId = session_id
                                 );
 
+                                // Forwarded copies keep a trace of this delivery for loop detection
+                                let raw_message = if self.core.smtp.session.data.add_delivered_to {
+                                    let mut raw_message =
+                                        format!("Delivered-To: {envelope_to}\r\n").into_bytes();
+                                    raw_message.extend_from_slice(&message.raw_message);
+                                    raw_message
+                                } else {
+                                    message.raw_message.to_vec()
+                                };
+
                                 autogenerated.push(AutogeneratedMessage {
                                     sender_address: mail_from.clone(),
                                     recipients,
-                                    message: message.raw_message.to_vec(),
+                                    message: raw_message,
                                 });
                             } else {
                                 trc::event!(
@@ -512,6 +561,8 @@ impl SieveScriptIngest for Server {
                         received_at: None,
                         source: IngestSource::Smtp {
                             deliver_to: envelope_to,
+                            catch_all,
+                            encrypt,
                         },
                         spam_classify: access_token.has_permission(Permission::SpamFilterClassify),
                         spam_train: can_spam_train,
@@ -572,6 +623,89 @@ impl SieveScriptIngest for Server {
         }
     }
 
+    async fn sieve_script_get_prelude(
+        &self,
+        envelope_from: &str,
+        envelope_to: &str,
+        session_id: u64,
+        active_script: Option<&ActiveScript>,
+    ) -> Option<ActiveScript> {
+        if self.core.sieve.prelude.is_empty() {
+            return None;
+        }
+
+        let prelude = self
+            .eval_if::<String, _>(
+                &self.core.sieve.prelude,
+                &PreludeResolver {
+                    envelope_from,
+                    envelope_to,
+                },
+                session_id,
+            )
+            .await
+            .filter(|prelude| !prelude.is_empty())?
+            .to_lowercase();
+
+        // Global scripts that are missing or failed to compile must not break deliveries,
+        // fall back to the user's script alone.
+        if self
+            .get_global_sieve_script(
+                &prelude,
+                &domain_part(envelope_to).to_lowercase(),
+                session_id,
+            )
+            .is_none()
+        {
+            trc::event!(
+                Sieve(SieveEvent::PreludeError),
+                Id = prelude,
+                To = envelope_to.to_string(),
+                Details = "Sieve prelude is missing or failed to compile.",
+                SpanId = session_id
+            );
+            return None;
+        }
+
+        // The prelude runs first and may use 'stop' to prevent the user's script from
+        // overriding its actions.
+        let mut script = format!("require \"include\";\r\ninclude :global \"{prelude}\";\r\n");
+        if let Some(active_script) = active_script {
+            script.push_str(&format!(
+                "include :personal \"{}\";\r\n",
+                active_script
+                    .script_name
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+            ));
+        }
+
+        match self
+            .core
+            .sieve
+            .untrusted_compiler
+            .compile(script.as_bytes())
+        {
+            Ok(script) => Some(ActiveScript {
+                document_id: active_script.map_or(u32::MAX, |s| s.document_id),
+                version: active_script.map_or(ArchiveVersion::Unversioned, |s| s.version),
+                // Empty names are not valid script names, so this cannot clash with an include
+                script_name: String::new(),
+                script: Arc::new(script),
+            }),
+            Err(err) => {
+                trc::event!(
+                    Sieve(SieveEvent::PreludeError),
+                    Id = prelude,
+                    To = envelope_to.to_string(),
+                    Reason = err.to_string(),
+                    SpanId = session_id
+                );
+                None
+            }
+        }
+    }
+
     async fn sieve_script_get_by_name(
         &self,
         account_id: u32,
@@ -722,3 +856,28 @@ pub struct CompiledScript {
     pub name: String,
     pub version: ArchiveVersion,
 }
+
+struct PreludeResolver<'x> {
+    envelope_from: &'x str,
+    envelope_to: &'x str,
+}
+
+impl ResolveVariable for PreludeResolver<'_> {
+    fn resolve_variable(&self, variable: u32) -> Variable<'_> {
+        match variable {
+            V_RECIPIENT => self.envelope_to.into(),
+            V_RECIPIENT_DOMAIN => domain_part(self.envelope_to).into(),
+            V_SENDER => self.envelope_from.into(),
+            V_SENDER_DOMAIN => domain_part(self.envelope_from).into(),
+            _ => "".into(),
+        }
+    }
+
+    fn resolve_global(&self, _: &str) -> Variable<'_> {
+        Variable::Integer(0)
+    }
+}
+
+fn domain_part(address: &str) -> &str {
+    address.rsplit_once('@').map_or("", |(_, domain)| domain)
+}
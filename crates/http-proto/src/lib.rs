@@ -32,7 +32,7 @@ pub enum HttpResponseBody {
     Text(String),
     Binary(Vec<u8>),
     Stream(http_body_util::combinators::BoxBody<hyper::body::Bytes, hyper::Error>),
-    WebsocketUpgrade(String),
+    WebsocketUpgrade(Option<String>),
     Empty,
 }
 
@@ -55,6 +55,14 @@ pub struct HttpSessionData {
     pub remote_port: u16,
     pub is_tls: bool,
     pub session_id: u64,
+    pub client_cert: Option<Arc<ClientCertificate>>,
+}
+
+// Identity of a TLS client certificate that was verified by the listener
+#[derive(Debug, Clone, Default)]
+pub struct ClientCertificate {
+    pub subject: String,
+    pub emails: Vec<String>,
 }
 
 pub struct DownloadResponse {
@@ -108,7 +108,7 @@ impl HttpResponse {
         self
     }
 
-    pub fn with_websocket_upgrade(mut self, derived_key: String) -> Self {
+    pub fn with_websocket_upgrade(mut self, derived_key: Option<String>) -> Self {
         self.body = HttpResponseBody::WebsocketUpgrade(derived_key);
         self
     }
@@ -183,7 +183,7 @@ impl HttpResponse {
                     .boxed(),
             ),
             HttpResponseBody::Stream(stream) => self.builder.body(stream),
-            HttpResponseBody::WebsocketUpgrade(derived_key) => self
+            HttpResponseBody::WebsocketUpgrade(Some(derived_key)) => self
                 .builder
                 .header(header::CONNECTION, "upgrade")
                 .header(header::UPGRADE, "websocket")
@@ -194,6 +194,13 @@ impl HttpResponse {
                         .map_err(|never| match never {})
                         .boxed(),
                 ),
+            HttpResponseBody::WebsocketUpgrade(None) => {
+                self.builder.header("Sec-WebSocket-Protocol", "jmap").body(
+                    Full::new(Bytes::new())
+                        .map_err(|never| match never {})
+                        .boxed(),
+                )
+            }
         }
         .unwrap()
     }
@@ -6,7 +6,11 @@
 
 use std::sync::Arc;
 
-use common::{HttpAuthCache, Server, auth::AuthRequest, listener::limiter::InFlight};
+use common::{
+    HttpAuthCache, Server,
+    auth::{AuthRequest, impersonate::IMPERSONATION_TOKEN_PREFIX},
+    listener::limiter::InFlight,
+};
 use http_proto::{HttpRequest, HttpSessionData};
 use hyper::header;
 use mail_parser::decoders::base64::base64_decode;
@@ -60,6 +64,24 @@ impl Authenticator for Server {
                 self.is_http_anonymous_request_allowed(&session.remote_ip)
                     .await?;
 
+                // Impersonation tokens are validated on every request so revocations
+                // take effect immediately, they are never cached
+                if token.starts_with(IMPERSONATION_TOKEN_PREFIX) {
+                    let access_token = self
+                        .authenticate_impersonation(token, session.session_id)
+                        .await
+                        .map_err(|err| {
+                            err.ctx(trc::Key::ListenerId, session.instance.id.clone())
+                                .ctx(trc::Key::RemotePort, session.remote_port)
+                                .ctx(trc::Key::RemoteIp, session.remote_ip)
+                        })?;
+
+                    return self
+                        .is_http_authenticated_request_allowed(&access_token)
+                        .await
+                        .map(|in_flight| (in_flight, access_token));
+                }
+
                 decode_bearer_token(token, allow_api_access).ok_or_else(|| {
                     trc::AuthEvent::Error
                         .into_err()
@@ -79,14 +101,18 @@ impl Authenticator for Server {
                     .caused_by(trc::location!()));
             };
 
-            // Authenticate
+            // Authenticate, failures are rate limited by remote IP and account
             let access_token = self
                 .authenticate(&AuthRequest::from_credentials(
                     credentials,
                     session.session_id,
                     session.remote_ip,
                 ))
-                .await?;
+                .await
+                .map_err(|err| {
+                    err.ctx(trc::Key::ListenerId, session.instance.id.clone())
+                        .ctx(trc::Key::RemotePort, session.remote_port)
+                })?;
 
             // Cache credentials
             self.inner.cache.http_auth.insert(
@@ -0,0 +1,89 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{Server, auth::AccessToken};
+use http_proto::{ClientCertificate, HttpSessionData};
+use x509_parser::{
+    certificate::X509Certificate,
+    der_parser::asn1_rs::FromDer,
+    extensions::{GeneralName, ParsedExtension},
+};
+
+pub trait ClientCertificateGate: Sync + Send {
+    fn requires_client_certificate(&self, path: &str) -> bool;
+
+    fn assert_client_certificate(
+        &self,
+        session: &HttpSessionData,
+        access_token: Option<&AccessToken>,
+    ) -> trc::Result<()>;
+}
+
+impl ClientCertificateGate for Server {
+    fn requires_client_certificate(&self, path: &str) -> bool {
+        self.core
+            .jmap
+            .http_client_cert_require
+            .iter()
+            .any(|prefix| path.starts_with(prefix.as_str()))
+    }
+
+    fn assert_client_certificate(
+        &self,
+        session: &HttpSessionData,
+        access_token: Option<&AccessToken>,
+    ) -> trc::Result<()> {
+        let Some(client_cert) = &session.client_cert else {
+            return Err(trc::SecurityEvent::ClientCertificateRequired
+                .into_err()
+                .details("A valid TLS client certificate is required to access this resource."));
+        };
+
+        // Optionally bind the certificate to the authenticated account
+        if let Some(access_token) =
+            access_token.filter(|_| self.core.jmap.http_client_cert_match_email)
+        {
+            if !client_cert.emails.iter().any(|email| {
+                access_token
+                    .emails
+                    .iter()
+                    .any(|account_email| account_email.eq_ignore_ascii_case(email))
+            }) {
+                return Err(trc::SecurityEvent::ClientCertificateRequired
+                    .into_err()
+                    .details("The TLS client certificate does not belong to this account.")
+                    .ctx(trc::Key::AccountName, access_token.name.clone())
+                    .ctx(trc::Key::Id, client_cert.subject.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn parse_client_certificate(der: &[u8]) -> Option<ClientCertificate> {
+    let (_, cert) = X509Certificate::from_der(der).ok()?;
+    let mut emails = Vec::new();
+    for email in cert.subject().iter_email() {
+        if let Ok(email) = email.as_str() {
+            emails.push(email.to_lowercase());
+        }
+    }
+    for ext in cert.extensions() {
+        if let ParsedExtension::SubjectAlternativeName(san) = ext.parsed_extension() {
+            for name in &san.general_names {
+                if let GeneralName::RFC822Name(email) = name {
+                    emails.push(email.to_lowercase());
+                }
+            }
+        }
+    }
+
+    Some(ClientCertificate {
+        subject: cert.subject().to_string(),
+        emails,
+    })
+}
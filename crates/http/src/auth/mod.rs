@@ -5,4 +5,5 @@
  */
 
 pub mod authenticate;
+pub mod client_cert;
 pub mod oauth;
@@ -180,6 +180,8 @@ impl TokenResponse {
 #[derive(Debug)]
 pub struct FormData {
     fields: VecMap<String, String>,
+    // Multipart values that are not valid UTF-8, such as DER encoded files
+    binary: VecMap<String, Vec<u8>>,
 }
 
 impl FormData {
@@ -188,36 +190,44 @@ impl FormData {
         max_len: usize,
         session_id: u64,
     ) -> trc::Result<Self> {
-        match (
-            req.headers()
-                .get(CONTENT_TYPE)
-                .and_then(|h| h.to_str().ok())
-                .and_then(|val| val.parse::<mime::Mime>().ok()),
-            fetch_body(req, max_len, session_id).await,
-        ) {
-            (Some(content_type), Some(body)) => {
-                let mut fields = VecMap::new();
-                if let Some(boundary) = content_type.get_param(mime::BOUNDARY) {
-                    for mut field in
-                        form_data::FormData::new(&body[..], boundary.as_str()).flatten()
-                    {
-                        let value = String::from_utf8_lossy(&field.bytes().unwrap_or_default())
-                            .into_owned();
-                        fields.append(field.name, value);
-                    }
-                } else {
-                    for (key, value) in http_proto::form_urlencoded::parse(&body) {
-                        fields.append(key.into_owned(), value.into_owned());
-                    }
-                }
-                Ok(FormData { fields })
-            }
+        let content_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+            .map(|h| h.to_string());
+
+        match (content_type, fetch_body(req, max_len, session_id).await) {
+            (Some(content_type), Some(body)) => Self::from_body(&content_type, &body),
             _ => Err(trc::ResourceEvent::BadParameters
                 .into_err()
                 .details("Invalid post request")),
         }
     }
 
+    pub fn from_body(content_type: &str, body: &[u8]) -> trc::Result<Self> {
+        let content_type = content_type.parse::<mime::Mime>().map_err(|_| {
+            trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Invalid post request")
+        })?;
+        let mut fields = VecMap::new();
+        let mut binary = VecMap::new();
+        if let Some(boundary) = content_type.get_param(mime::BOUNDARY) {
+            for mut field in form_data::FormData::new(body, boundary.as_str()).flatten() {
+                // Binary values are kept as-is rather than being lossily converted to text
+                match String::from_utf8(field.bytes().unwrap_or_default()) {
+                    Ok(value) => fields.append(field.name, value),
+                    Err(err) => binary.append(field.name, err.into_bytes()),
+                }
+            }
+        } else {
+            for (key, value) in http_proto::form_urlencoded::parse(body) {
+                fields.append(key.into_owned(), value.into_owned());
+            }
+        }
+        Ok(FormData { fields, binary })
+    }
+
     pub fn get(&self, key: &str) -> Option<&str> {
         self.fields.get(key).map(|v| v.as_str())
     }
@@ -226,8 +236,15 @@ impl FormData {
         self.fields.remove(key)
     }
 
+    pub fn remove_bytes(&mut self, key: &str) -> Option<Vec<u8>> {
+        self.binary
+            .remove(key)
+            .or_else(|| self.fields.remove(key).map(String::into_bytes))
+    }
+
     pub fn has_field(&self, key: &str) -> bool {
         self.fields.get(key).is_some_and(|v| !v.is_empty())
+            || self.binary.get(key).is_some_and(|v| !v.is_empty())
     }
 
     pub fn fields(&self) -> impl Iterator<Item = (&String, &String)> {
@@ -205,6 +205,7 @@ impl FormHandler for Server {
                     recipients: form.rcpt_to.clone(),
                     message_blob,
                     message_size,
+                    encrypt: true,
                     session_id: session.session_id,
                 })
                 .await
@@ -215,7 +216,8 @@ impl FormHandler for Server {
                         has_success = true;
                     }
                     LocalDeliveryStatus::TemporaryFailure { reason }
-                    | LocalDeliveryStatus::PermanentFailure { reason, .. } => {
+                    | LocalDeliveryStatus::PermanentFailure { reason, .. }
+                    | LocalDeliveryStatus::EncryptionFailure { reason, .. } => {
                         failure = Some(reason)
                     }
                 }
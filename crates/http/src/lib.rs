@@ -8,6 +8,7 @@ pub mod auth;
 pub mod autoconfig;
 pub mod form;
 pub mod management;
+pub mod quarantine;
 pub mod request;
 
 use std::sync::Arc;
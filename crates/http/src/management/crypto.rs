@@ -6,39 +6,107 @@
 
 use std::{future::Future, sync::Arc};
 
+use base64::{Engine, engine::general_purpose::STANDARD};
 use common::{Server, auth::AccessToken};
-use directory::backend::internal::manage;
-use email::message::crypto::{
-    Algorithm, ArchivedAlgorithm, ArchivedEncryptionMethod, EncryptMessage, EncryptMessageError,
-    EncryptionMethod, EncryptionParams, EncryptionType, try_parse_certs,
+use directory::{
+    Permission, Type,
+    backend::internal::manage::{self, ManageDirectory, not_found},
 };
-use http_proto::*;
+use email::message::{
+    crypto::{
+        Algorithm, ArchivedEncryptionParams, Compression, EncryptMessage, EncryptMessageError,
+        EncryptionMethod, EncryptionParams, EncryptionSummary, EncryptionType, RsaPadding,
+        certificate_info, export_certs, fingerprint, pgp_user_id_addresses,
+        try_parse_certs_with_password, validate_certs,
+    },
+    wkd::fetch_wkd_certs,
+};
+use http_proto::{request::decode_path_element, *};
+use hyper::{Method, header::CONTENT_TYPE};
 use jmap_proto::types::{collection::Collection, property::Property};
-use mail_builder::encoders::base64::base64_encode_mime;
 use mail_parser::MessageParser;
 use serde_json::json;
+use services::index::Indexer;
 use store::{
-    Deserialize, Serialize,
-    write::{AlignedBytes, Archive, Archiver, BatchBuilder},
+    Deserialize, Serialize, SerializeInfallible, ValueKey,
+    write::{AlignedBytes, Archive, Archiver, BatchBuilder, ValueClass, now},
 };
 use trc::AddContext;
 
+use crate::auth::oauth::FormData;
+
+pub struct EncryptionUpdate {
+    pub num_certs: usize,
+    pub fingerprints: Vec<String>,
+    pub summary: EncryptionSummary,
+    pub warnings: Vec<String>,
+}
+
 pub trait CryptoHandler: Sync + Send {
     fn handle_crypto_get(
         &self,
         access_token: Arc<AccessToken>,
     ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
 
+    fn handle_crypto_identities_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
     fn handle_crypto_post(
         &self,
+        req: &HttpRequest,
         access_token: Arc<AccessToken>,
         body: Option<Vec<u8>>,
     ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_crypto_identity_delete(
+        &self,
+        access_token: Arc<AccessToken>,
+        cert_fingerprint: &str,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_manage_crypto(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn get_encryption_params(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<Vec<EncryptionSummary>>> + Send;
+
+    fn set_encryption_params(
+        &self,
+        account_id: u32,
+        request: EncryptionType,
+    ) -> impl Future<Output = trc::Result<EncryptionUpdate>> + Send;
+
+    fn remove_encryption_identity(
+        &self,
+        account_id: u32,
+        cert_fingerprint: &str,
+    ) -> impl Future<Output = trc::Result<Vec<EncryptionSummary>>> + Send;
+
+    fn handle_crypto_existing_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_crypto_existing_post(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
 }
 
 impl CryptoHandler for Server {
     async fn handle_crypto_get(&self, access_token: Arc<AccessToken>) -> trc::Result<HttpResponse> {
-        let ec = if let Some(params_) = self
+        // Clients predating multiple identities are shown a single identity
+        // holding the certificates of all of them
+        let encryption = if let Some(params_) = self
             .get_archive_by_property(
                 access_token.primary_id(),
                 Collection::Principal,
@@ -47,61 +115,226 @@ impl CryptoHandler for Server {
             )
             .await?
         {
-            let params = params_
-                .unarchive::<EncryptionParams>()
-                .caused_by(trc::location!())?;
-            let algo = match &params.algo {
-                ArchivedAlgorithm::Aes128 => Algorithm::Aes128,
-                ArchivedAlgorithm::Aes256 => Algorithm::Aes256,
-            };
-            let method = match &params.method {
-                ArchivedEncryptionMethod::PGP => EncryptionMethod::PGP,
-                ArchivedEncryptionMethod::SMIME => EncryptionMethod::SMIME,
-            };
-            let mut certs = Vec::new();
-            certs.extend_from_slice(b"-----STALWART CERTIFICATE-----\r\n");
-            let _ = base64_encode_mime(&params_.into_inner(), &mut certs, false);
-            certs.extend_from_slice(b"\r\n");
-            let certs = String::from_utf8(certs).unwrap_or_default();
-
-            match method {
-                EncryptionMethod::PGP => EncryptionType::PGP { algo, certs },
-                EncryptionMethod::SMIME => EncryptionType::SMIME { algo, certs },
-            }
+            encryption_type(
+                params_
+                    .unarchive::<Vec<EncryptionParams>>()
+                    .caused_by(trc::location!())?,
+            )
         } else {
             EncryptionType::Disabled
         };
 
         Ok(JsonResponse::new(json!({
-            "data": ec,
+            "data": encryption,
+        }))
+        .into_http_response())
+    }
+
+    async fn handle_crypto_identities_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<HttpResponse> {
+        Ok(JsonResponse::new(json!({
+            "data": self.get_encryption_params(access_token.primary_id()).await?,
         }))
         .into_http_response())
     }
 
     async fn handle_crypto_post(
         &self,
+        req: &HttpRequest,
         access_token: Arc<AccessToken>,
         body: Option<Vec<u8>>,
     ) -> trc::Result<HttpResponse> {
-        let request = serde_json::from_slice::<EncryptionType>(body.as_deref().unwrap_or_default())
-            .map_err(|err| trc::ResourceEvent::BadParameters.into_err().reason(err))?;
+        let request = parse_encryption_request(req, body)?;
+
+        self.set_encryption_params(access_token.primary_id(), request)
+            .await
+            .map(update_response)
+    }
 
-        let (method, algo, mut certs) = match request {
-            EncryptionType::PGP { algo, certs } => (EncryptionMethod::PGP, algo, certs),
-            EncryptionType::SMIME { algo, certs } => (EncryptionMethod::SMIME, algo, certs),
+    async fn handle_crypto_identity_delete(
+        &self,
+        access_token: Arc<AccessToken>,
+        cert_fingerprint: &str,
+    ) -> trc::Result<HttpResponse> {
+        Ok(JsonResponse::new(json!({
+            "data": self
+                .remove_encryption_identity(access_token.primary_id(), cert_fingerprint)
+                .await?,
+        }))
+        .into_http_response())
+    }
+
+    async fn handle_manage_crypto(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let Some(name) = path.get(1) else {
+            return Err(trc::ResourceEvent::NotFound.into_err());
+        };
+        let name = decode_path_element(name);
+        let account_id = self
+            .core
+            .storage
+            .data
+            .get_principal_info(name.as_ref())
+            .await?
+            .filter(|p| {
+                p.typ == Type::Individual && p.has_tenant_access(access_token.tenant.map(|t| t.id))
+            })
+            .map(|p| p.id)
+            .ok_or_else(|| not_found(name.to_string()))?;
+
+        match req.method() {
+            &Method::GET => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::IndividualGet)?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.get_encryption_params(account_id).await?,
+                }))
+                .into_http_response())
+            }
+            &Method::POST => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::IndividualUpdate)?;
+
+                let request = parse_encryption_request(req, body)?;
+
+                self.set_encryption_params(account_id, request)
+                    .await
+                    .map(update_response)
+            }
+            &Method::DELETE => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::IndividualUpdate)?;
+
+                if let Some(cert_fingerprint) = path.get(2) {
+                    Ok(JsonResponse::new(json!({
+                        "data": self
+                            .remove_encryption_identity(account_id, cert_fingerprint)
+                            .await?,
+                    }))
+                    .into_http_response())
+                } else {
+                    // Parameters are removed without being read, so this also works when they are corrupted
+                    self.set_encryption_params(account_id, EncryptionType::Disabled)
+                        .await
+                        .map(update_response)
+                }
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+
+    async fn get_encryption_params(&self, account_id: u32) -> trc::Result<Vec<EncryptionSummary>> {
+        if let Some(params_) = self
+            .get_archive_by_property(account_id, Collection::Principal, 0, Property::Parameters)
+            .await?
+        {
+            // The time of the last update is stored next to the parameters
+            let updated_at = self
+                .core
+                .storage
+                .data
+                .get_value::<u64>(ValueKey {
+                    account_id,
+                    collection: Collection::Principal.into(),
+                    document_id: 0,
+                    class: ValueClass::Property(Property::EncryptionUpdatedAt.into()),
+                })
+                .await?;
+
+            Ok(params_
+                .unarchive::<Vec<EncryptionParams>>()
+                .caused_by(trc::location!())?
+                .iter()
+                .map(|identity| {
+                    encryption_summary(identity, updated_at, self.core.jmap.encrypt_max_size)
+                })
+                .collect())
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    async fn set_encryption_params(
+        &self,
+        account_id: u32,
+        request: EncryptionType,
+    ) -> trc::Result<EncryptionUpdate> {
+        let (
+            method,
+            algo,
+            padding,
+            mut certs,
+            password,
+            exclude_mailboxes,
+            fetch_wkd,
+            max_encrypt_size,
+            compression,
+        ) = match request {
+            EncryptionType::PGP {
+                algo,
+                certs,
+                exclude_mailboxes,
+                fetch_wkd,
+                max_encrypt_size,
+                compression,
+            } => (
+                EncryptionMethod::PGP,
+                algo,
+                RsaPadding::default(),
+                certs,
+                None,
+                exclude_mailboxes,
+                fetch_wkd,
+                max_encrypt_size,
+                compression,
+            ),
+            EncryptionType::SMIME {
+                algo,
+                padding,
+                certs,
+                certificate_password,
+                exclude_mailboxes,
+                max_encrypt_size,
+            } => (
+                EncryptionMethod::SMIME,
+                algo,
+                padding,
+                certs,
+                certificate_password,
+                exclude_mailboxes,
+                false,
+                max_encrypt_size,
+                None,
+            ),
             EncryptionType::Disabled => {
                 // Disable encryption at rest
                 let mut batch = BatchBuilder::new();
                 batch
-                    .with_account_id(access_token.primary_id())
+                    .with_account_id(account_id)
                     .with_collection(Collection::Principal)
                     .update_document(0)
-                    .clear(Property::Parameters);
+                    .clear(Property::Parameters)
+                    .clear(Property::EncryptionUpdatedAt)
+                    .clear(Property::EncryptionTotal);
                 self.core.storage.data.write(batch.build_all()).await?;
-                return Ok(JsonResponse::new(json!({
-                    "data": (),
-                }))
-                .into_http_response());
+
+                // Invalidate the access token so the session reflects the new status
+                self.increment_revision(account_id).await;
+
+                return Ok(EncryptionUpdate {
+                    num_certs: 0,
+                    fingerprints: vec![],
+                    summary: EncryptionSummary::Disabled,
+                    warnings: vec![],
+                });
             }
         };
         if !certs.ends_with("\n") {
@@ -115,43 +348,465 @@ impl CryptoHandler for Server {
             ));
         }
 
-        // Parse certificates
-        let certs = try_parse_certs(method, certs.into_bytes())
-            .map_err(|err| manage::error(err, None::<u32>))?;
+        // OpenPGP uses its own packet format, AEAD ciphers are only available for S/MIME
+        if method == EncryptionMethod::PGP
+            && matches!(
+                algo,
+                Algorithm::Aes128Gcm | Algorithm::Aes256Gcm | Algorithm::Chacha20Poly1305
+            )
+        {
+            return Err(crypto_error(
+                "AES-GCM and ChaCha20-Poly1305 are only supported for S/MIME encryption",
+                "unsupported-algorithm",
+            ));
+        }
+
+        // Parse certificates or fetch them from the Web Key Directory of the account's domain
+        let access_token = self
+            .get_access_token(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        let certs = if fetch_wkd {
+            let address = access_token.emails.first().ok_or_else(|| {
+                crypto_error(
+                    "Account has no e-mail address to look up",
+                    "no-email-address",
+                )
+            })?;
+            fetch_wkd_certs(
+                address,
+                self.core.jmap.encrypt_wkd_timeout,
+                self.core.jmap.encrypt_wkd_max_size,
+            )
+            .await
+            .map_err(|err| crypto_error(err, "no-valid-certificates"))?
+        } else {
+            try_parse_certs_with_password(method, certs.into_bytes(), password.as_deref())
+                .map_err(|err| crypto_error(err.to_string(), err.code()))?
+        };
+        let mut warnings = validate_certs(method, &certs)
+            .map_err(|err| crypto_error(err, "no-valid-certificates"))?;
+
+        // OpenPGP keys should carry one of the account's addresses, otherwise mail could
+        // be encrypted to a third party. Administrators allowing external keys only get a warning.
+        if method == EncryptionMethod::PGP {
+            for cert in &certs {
+                if !pgp_user_id_addresses(cert).iter().any(|address| {
+                    access_token
+                        .emails
+                        .iter()
+                        .any(|email| email.eq_ignore_ascii_case(address))
+                }) {
+                    let message = format!(
+                        "Key {} has no user ID matching the account's e-mail addresses",
+                        fingerprint(method, cert)
+                    );
+                    if self.core.jmap.encrypt_pgp_require_user_id {
+                        return Err(crypto_error(message, "user-id-mismatch"));
+                    } else {
+                        warnings.push(message);
+                    }
+                }
+            }
+        }
         let num_certs = certs.len();
-        let params = Archiver::new(EncryptionParams {
+        let fingerprints = certs.iter().map(|cert| fingerprint(method, cert)).collect();
+        let updated_at = now();
+        let identity = EncryptionParams {
             method,
             algo,
+            padding,
             certs,
-        })
-        .serialize()
-        .caused_by(trc::location!())?;
+            exclude_mailboxes,
+            max_encrypt_size,
+            compression,
+        };
+
+        // Identities are appended to the ones already configured, uploading the same
+        // certificates again replaces the settings of their identity
+        let mut identities = if let Some(params_) = self
+            .get_archive_by_property(account_id, Collection::Principal, 0, Property::Parameters)
+            .await?
+        {
+            params_
+                .deserialize::<Vec<EncryptionParams>>()
+                .caused_by(trc::location!())?
+        } else {
+            vec![]
+        };
+        if identities.iter().any(|existing| existing.method != method) {
+            return Err(crypto_error(
+                "Cannot combine OpenPGP and S/MIME identities, disable encryption before switching methods",
+                "mixed-certificate-types",
+            ));
+        }
+        let index = if let Some(index) = identities
+            .iter()
+            .position(|existing| existing.certs == identity.certs)
+        {
+            identities[index] = identity;
+            index
+        } else {
+            identities.push(identity);
+            identities.len() - 1
+        };
+
+        // Each message is encrypted once for all identities, so the cipher and
+        // compression are account-wide and saving an identity updates the others
+        for existing in identities.iter_mut() {
+            if existing.algo != algo || existing.compression != compression {
+                existing.algo = algo;
+                existing.compression = compression;
+                warnings.push(format!(
+                    "Encryption settings of identity {} were updated to match",
+                    existing
+                        .certs
+                        .first()
+                        .map(|cert| fingerprint(method, cert))
+                        .unwrap_or_default()
+                ));
+            }
+        }
+        let params = Archiver::new(identities)
+            .serialize()
+            .caused_by(trc::location!())?;
 
-        // Try a test encryption
+        // Try a test encryption with the new identity
+        let params_ = <Archive<AlignedBytes> as Deserialize>::deserialize(params.as_slice())?;
+        let identity = &params_.unarchive::<Vec<EncryptionParams>>()?[index];
+        let summary =
+            encryption_summary(identity, Some(updated_at), self.core.jmap.encrypt_max_size);
         if let Err(EncryptMessageError::Error(message)) = MessageParser::new()
             .parse("Subject: test\r\ntest\r\n".as_bytes())
             .unwrap()
             .encrypt(
-                <Archive<AlignedBytes> as Deserialize>::deserialize(params.as_slice())?
-                    .unarchive::<EncryptionParams>()?,
+                std::slice::from_ref(identity),
+                self.core.jmap.encrypt_pgp_signing_key.as_deref(),
             )
             .await
         {
-            return Err(manage::error(message, None::<u32>));
+            return Err(crypto_error(message, "encryption-test-failed"));
         }
 
         // Save encryption params
         let mut batch = BatchBuilder::new();
         batch
-            .with_account_id(access_token.primary_id())
+            .with_account_id(account_id)
             .with_collection(Collection::Principal)
             .update_document(0)
-            .set(Property::Parameters, params);
+            .set(Property::Parameters, params)
+            .set(Property::EncryptionUpdatedAt, updated_at.serialize());
+        self.core
+            .storage
+            .data
+            .write(batch.build_all())
+            .await
+            .map_err(|err| {
+                trc::error!(err.details("Failed to save encryption parameters"));
+                crypto_error("Failed to save encryption parameters", "storage-error")
+            })?;
+        self.increment_revision(account_id).await;
+
+        Ok(EncryptionUpdate {
+            num_certs,
+            fingerprints,
+            summary,
+            warnings,
+        })
+    }
+
+    async fn remove_encryption_identity(
+        &self,
+        account_id: u32,
+        cert_fingerprint: &str,
+    ) -> trc::Result<Vec<EncryptionSummary>> {
+        let mut identities = if let Some(params_) = self
+            .get_archive_by_property(account_id, Collection::Principal, 0, Property::Parameters)
+            .await?
+        {
+            params_
+                .deserialize::<Vec<EncryptionParams>>()
+                .caused_by(trc::location!())?
+        } else {
+            vec![]
+        };
+
+        // Identities are removed when any of their certificates matches the fingerprint
+        let num_identities = identities.len();
+        identities.retain(|identity| {
+            !identity.certs.iter().any(|cert| {
+                fingerprint(identity.method, cert).eq_ignore_ascii_case(cert_fingerprint)
+            })
+        });
+        if identities.len() == num_identities {
+            return Err(not_found(cert_fingerprint.to_string()));
+        } else if identities.is_empty() {
+            // Removing the last identity disables encryption at rest
+            self.set_encryption_params(account_id, EncryptionType::Disabled)
+                .await?;
+            return Ok(vec![]);
+        }
+
+        let updated_at = now();
+        let params = Archiver::new(identities)
+            .serialize()
+            .caused_by(trc::location!())?;
+        let params_ = <Archive<AlignedBytes> as Deserialize>::deserialize(params.as_slice())?;
+        let summaries = params_
+            .unarchive::<Vec<EncryptionParams>>()?
+            .iter()
+            .map(|identity| {
+                encryption_summary(identity, Some(updated_at), self.core.jmap.encrypt_max_size)
+            })
+            .collect();
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Principal)
+            .update_document(0)
+            .set(Property::Parameters, params)
+            .set(Property::EncryptionUpdatedAt, updated_at.serialize());
         self.core.storage.data.write(batch.build_all()).await?;
+        self.increment_revision(account_id).await;
+
+        Ok(summaries)
+    }
+
+    async fn handle_crypto_existing_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<HttpResponse> {
+        let progress = self
+            .encrypt_existing_progress(access_token.primary_id())
+            .await?;
 
         Ok(JsonResponse::new(json!({
-            "data": num_certs,
+            "data": {
+                "total": progress.total,
+                "pending": progress.pending,
+            },
         }))
         .into_http_response())
     }
+
+    async fn handle_crypto_existing_post(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<HttpResponse> {
+        let account_id = access_token.primary_id();
+
+        // Make sure Encryption is enabled
+        if !self.core.jmap.encrypt {
+            return Err(manage::unsupported(
+                "Encryption-at-rest has been disabled by the system administrator",
+            ));
+        }
+        if self
+            .get_archive_by_property(account_id, Collection::Principal, 0, Property::Parameters)
+            .await?
+            .is_none()
+        {
+            return Err(manage::error(
+                "Encryption-at-rest is not enabled for this account",
+                None::<u32>,
+            ));
+        }
+
+        // Only one encryption task can run at a time
+        if self.encrypt_existing_progress(account_id).await?.pending > 0 {
+            return Err(manage::error(
+                "Existing messages are already being encrypted",
+                None::<u32>,
+            ));
+        }
+
+        let total = self.encrypt_existing(account_id).await?;
+
+        Ok(JsonResponse::new(json!({
+            "data": total,
+        }))
+        .into_http_response())
+    }
+}
+
+// Certificates can also be uploaded as files using a multipart form, in which case the
+// remaining form fields hold the encryption settings
+fn parse_encryption_request(
+    req: &HttpRequest,
+    body: Option<Vec<u8>>,
+) -> trc::Result<EncryptionType> {
+    let body = body.unwrap_or_default();
+    match req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+    {
+        Some(content_type)
+            if content_type
+                .to_ascii_lowercase()
+                .starts_with("multipart/form-data") =>
+        {
+            encryption_request_from_form(FormData::from_body(content_type, &body)?)
+        }
+        _ => serde_json::from_slice::<EncryptionType>(&body).map_err(|err| {
+            trc::EventType::Resource(trc::ResourceEvent::BadParameters).from_json_error(err)
+        }),
+    }
+}
+
+fn encryption_request_from_form(mut form: FormData) -> trc::Result<EncryptionType> {
+    let mut request = serde_json::Map::new();
+    if let Some(certs) = form.remove_bytes("certificate") {
+        // Binary files such as DER or PKCS#7 bundles are passed on as base64 text
+        let certs = String::from_utf8(certs).unwrap_or_else(|err| STANDARD.encode(err.as_bytes()));
+        request.insert("certs".to_string(), certs.into());
+    }
+
+    let mut exclude_mailboxes = Vec::new();
+    for (key, value) in form.fields().filter(|(_, value)| !value.is_empty()) {
+        let value = match key.as_str() {
+            "fetchWkd" => matches!(value.as_str(), "true" | "on" | "1").into(),
+            "maxEncryptSize" => value
+                .parse::<u64>()
+                .map_err(|_| invalid_form_field(key))?
+                .into(),
+            "excludeMailboxes" => {
+                for mailbox_id in value.split(',') {
+                    exclude_mailboxes.push(
+                        mailbox_id
+                            .trim()
+                            .parse::<u32>()
+                            .map_err(|_| invalid_form_field(key))?,
+                    );
+                }
+                continue;
+            }
+            _ => value.as_str().into(),
+        };
+        request.insert(key.to_string(), value);
+    }
+    if !exclude_mailboxes.is_empty() {
+        request.insert("excludeMailboxes".to_string(), exclude_mailboxes.into());
+    }
+
+    serde_json::from_value::<EncryptionType>(request.into()).map_err(|err| {
+        trc::EventType::Resource(trc::ResourceEvent::BadParameters).from_json_error(err)
+    })
+}
+
+fn invalid_form_field(field: &str) -> trc::Error {
+    trc::ResourceEvent::BadParameters
+        .into_err()
+        .details(format!("Invalid value for form field {field:?}"))
+}
+
+// Errors carry a machine-readable code so API clients don't have to parse the message
+fn crypto_error(details: impl Into<trc::Value>, code: &'static str) -> trc::Error {
+    manage::error(details, None::<u32>).ctx(trc::Key::Code, code)
+}
+
+fn update_response(update: EncryptionUpdate) -> HttpResponse {
+    JsonResponse::new(if matches!(update.summary, EncryptionSummary::Disabled) {
+        json!({
+            "data": (),
+        })
+    } else if update.warnings.is_empty() {
+        json!({
+            "data": update.num_certs,
+            "fingerprints": update.fingerprints,
+            "summary": update.summary,
+        })
+    } else {
+        json!({
+            "data": update.num_certs,
+            "fingerprints": update.fingerprints,
+            "summary": update.summary,
+            "warnings": update.warnings,
+        })
+    })
+    .into_http_response()
+}
+
+fn encryption_type(identities: &[ArchivedEncryptionParams]) -> EncryptionType {
+    let Some(params) = identities.first() else {
+        return EncryptionType::Disabled;
+    };
+    let algo = Algorithm::from(params.algo);
+    let method = EncryptionMethod::from(&params.method);
+    let certs = export_certs(
+        method,
+        identities
+            .iter()
+            .flat_map(|identity| identity.certs.iter().map(|cert| cert.as_slice())),
+    );
+    let exclude_mailboxes = params
+        .exclude_mailboxes
+        .iter()
+        .map(|id| id.to_native())
+        .collect();
+    let max_encrypt_size = params
+        .max_encrypt_size
+        .as_ref()
+        .map(|size| size.to_native());
+
+    match method {
+        EncryptionMethod::PGP => EncryptionType::PGP {
+            algo,
+            certs,
+            exclude_mailboxes,
+            fetch_wkd: false,
+            max_encrypt_size,
+            compression: params.compression.as_ref().map(Compression::from),
+        },
+        EncryptionMethod::SMIME => EncryptionType::SMIME {
+            algo,
+            padding: RsaPadding::from(&params.padding),
+            certs,
+            certificate_password: None,
+            exclude_mailboxes,
+            max_encrypt_size,
+        },
+    }
+}
+
+fn encryption_summary(
+    params: &ArchivedEncryptionParams,
+    updated_at: Option<u64>,
+    server_max_size: usize,
+) -> EncryptionSummary {
+    let algo = Algorithm::from(params.algo);
+    let method = EncryptionMethod::from(&params.method);
+    let padding = RsaPadding::from(&params.padding);
+    let certificates = params
+        .certs
+        .iter()
+        .map(|cert| certificate_info(method, cert.as_slice()))
+        .collect();
+    let exclude_mailboxes = params
+        .exclude_mailboxes
+        .iter()
+        .map(|id| id.to_native())
+        .collect();
+    let max_encrypt_size = params.max_encrypt_size(server_max_size);
+    let compression = params.compression.as_ref().map(Compression::from);
+
+    match method {
+        EncryptionMethod::PGP => EncryptionSummary::PGP {
+            algo,
+            certificates,
+            exclude_mailboxes,
+            max_encrypt_size,
+            compression,
+            updated_at,
+        },
+        EncryptionMethod::SMIME => EncryptionSummary::SMIME {
+            algo,
+            padding,
+            certificates,
+            exclude_mailboxes,
+            max_encrypt_size,
+            updated_at,
+        },
+    }
 }
@@ -10,7 +10,11 @@ use common::{Server, auth::AccessToken, config::smtp::auth::simple_pem_parse};
 use directory::{Permission, backend::internal::manage};
 use hyper::Method;
 use mail_auth::{
-    common::crypto::{Ed25519Key, RsaKey, Sha256},
+    AuthenticatedMessage,
+    common::{
+        crypto::{Ed25519Key, RsaKey, Sha256},
+        headers::HeaderWriter,
+    },
     dkim::generate::DkimKeyPair,
 };
 use mail_builder::encoders::base64::base64_encode;
@@ -19,11 +23,14 @@ use pkcs8::Document;
 use rsa::pkcs1::DecodeRsaPublicKey;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use smtp::inbound::DkimSign;
 use store::write::now;
 
 use http_proto::{request::decode_path_element, *};
 use std::future::Future;
 
+use super::troubleshoot::AuthResult;
+
 #[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
 pub enum Algorithm {
     Rsa,
@@ -57,6 +64,11 @@ pub trait DkimManagement: Sync + Send {
         body: Option<Vec<u8>>,
     ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
 
+    fn handle_test_signature(
+        &self,
+        path: Vec<&str>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
     fn create_dkim_key(
         &self,
         algo: Algorithm,
@@ -75,6 +87,12 @@ impl DkimManagement for Server {
         access_token: &AccessToken,
     ) -> trc::Result<HttpResponse> {
         match *req.method() {
+            Method::GET if path.get(2).is_some_and(|p| *p == "test") => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::DkimSignatureGet)?;
+
+                self.handle_test_signature(path).await
+            }
             Method::GET => {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::DkimSignatureGet)?;
@@ -179,6 +197,74 @@ impl DkimManagement for Server {
         .into_http_response())
     }
 
+    async fn handle_test_signature(&self, path: Vec<&str>) -> trc::Result<HttpResponse> {
+        let signature_id = match path.get(1) {
+            Some(signature_id) => decode_path_element(signature_id),
+            None => {
+                return Err(trc::ResourceEvent::NotFound.into_err());
+            }
+        };
+        let domain = self
+            .core
+            .storage
+            .config
+            .get(&format!("signature.{signature_id}.domain"))
+            .await?
+            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+        let signer = self.get_dkim_signer(&signature_id, 0).ok_or_else(|| {
+            manage::error(
+                "Failed to build signature, check the configuration logs",
+                signature_id.to_string().into(),
+            )
+        })?;
+
+        // Sign a sample message using the active configuration
+        let message = format!(
+            concat!(
+                "From: postmaster@{domain}\r\n",
+                "To: postmaster@{domain}\r\n",
+                "Date: {date}\r\n",
+                "Subject: DKIM self-test\r\n",
+                "Message-ID: <{id}.dkim-test@{domain}>\r\n",
+                "MIME-Version: 1.0\r\n",
+                "Content-Type: text/plain; charset=utf-8\r\n",
+                "\r\n",
+                "This message was signed to verify the DKIM configuration.\r\n"
+            ),
+            domain = domain,
+            date = DateTime::from_timestamp(now() as i64).to_rfc822(),
+            id = now(),
+        );
+        let signature = signer
+            .sign(message.as_bytes())
+            .map_err(|err| trc::Error::from(err).caused_by(trc::location!()))?
+            .to_header();
+
+        // Verify the signed message against the published public key
+        let signed_message = format!("{signature}{message}");
+        let auth_message =
+            AuthenticatedMessage::parse_with_opts(signed_message.as_bytes(), true)
+                .ok_or_else(|| manage::error("Failed to parse signed message", None::<u32>))?;
+        let result = self
+            .core
+            .smtp
+            .resolvers
+            .dns
+            .verify_dkim(self.inner.cache.build_auth_parameters(&auth_message))
+            .await
+            .first()
+            .map(|output| AuthResult::from(output.result()))
+            .unwrap_or(AuthResult::None);
+
+        Ok(JsonResponse::new(json!({
+            "data": {
+                "signature": signature,
+                "result": result,
+            },
+        }))
+        .into_http_response())
+    }
+
     async fn create_dkim_key(
         &self,
         algo: Algorithm,
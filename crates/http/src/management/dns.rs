@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use common::{Server, auth::AccessToken};
+use common::{Server, auth::AccessToken, listener::tls::certificate_hostname};
 use directory::{
     Permission,
     backend::internal::manage::{self},
@@ -240,6 +240,7 @@ impl DnsManagement for Server {
 
         // Add TLSA records
         for (name, key) in self.inner.data.tls_certificates.load().iter() {
+            let name = certificate_hostname(name);
             if !name.ends_with(domain_name)
                 || name.starts_with("mta-sts.")
                 || name.starts_with("autoconfig.")
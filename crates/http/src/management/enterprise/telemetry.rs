@@ -30,6 +30,7 @@ use hyper::{
 };
 use mail_parser::DateTime;
 use serde_json::json;
+use smtp::queue::parse_queue_id;
 use std::future::Future;
 use store::ahash::{AHashMap, AHashSet};
 use trc::{
@@ -39,7 +40,10 @@ use trc::{
 };
 use utils::{snowflake::SnowflakeIdGenerator, url_params::UrlParams};
 
-use crate::management::Timestamp;
+use crate::management::{
+    Timestamp,
+    paging::{CursorScope, Paging},
+};
 
 pub trait TelemetryApi: Sync + Send {
     fn handle_telemetry_api_request(
@@ -70,14 +74,19 @@ impl TelemetryApi for Server {
                 access_token.assert_has_permission(Permission::TracingList)?;
 
                 let page: usize = params.parse("page").unwrap_or(0);
-                let limit: usize = params.parse("limit").unwrap_or(0);
+                let paging = Paging::parse(&params, CursorScope::Trace, 0)?;
+                let cursor = paging.cursor_u64()?;
+                let limit = paging.limit;
                 let mut tracing_query = Vec::new();
                 if let Some(typ) = params.parse("type") {
                     tracing_query.push(TracingQuery::EventType(typ));
                 }
-                if let Some(queue_id) = params.parse("queue_id") {
+                if let Some(queue_id) = params.get("queue_id").and_then(parse_queue_id) {
                     tracing_query.push(TracingQuery::QueueId(queue_id));
                 }
+                if let Some(domain) = params.get("domain").filter(|domain| !domain.is_empty()) {
+                    tracing_query.push(TracingQuery::Keywords(domain.to_lowercase()));
+                }
                 if let Some(query) = params.get("filter") {
                     let mut buf = String::with_capacity(query.len());
                     let mut in_quote = false;
@@ -128,15 +137,30 @@ impl TelemetryApi for Server {
                     .store;
                 let span_ids = store.query_spans(&tracing_query, after, before).await?;
 
-                let (total, span_ids) = if limit > 0 {
-                    let offset = page.saturating_sub(1) * limit;
+                // Spans are sorted newest first, the cursor holds the last span id returned
+                let (total, span_ids): (_, Vec<_>) = if limit > 0 {
+                    let offset = if cursor.is_none() {
+                        page.saturating_sub(1) * limit
+                    } else {
+                        0
+                    };
                     (
                         span_ids.len(),
-                        span_ids.into_iter().skip(offset).take(limit).collect(),
+                        span_ids
+                            .into_iter()
+                            .filter(|span_id| cursor.is_none_or(|cursor| *span_id < cursor))
+                            .skip(offset)
+                            .take(limit)
+                            .collect(),
                     )
                 } else {
                     (span_ids.len(), span_ids)
                 };
+                let cursor = paging.next_cursor(CursorScope::Trace, span_ids.len(), || {
+                    span_ids
+                        .last()
+                        .map(|span_id| span_id.to_be_bytes().to_vec())
+                });
 
                 if values && !span_ids.is_empty() {
                     let mut values = Vec::with_capacity(span_ids.len());
@@ -157,10 +181,12 @@ impl TelemetryApi for Server {
                         }
                     }
 
+                    let items = paging.select(json!(JsonEventSerializer::new(values).with_spans()));
                     Ok(JsonResponse::new(json!({
                             "data": {
-                                "items": JsonEventSerializer::new(values).with_spans(),
+                                "items": items,
                                 "total": total,
+                                "cursor": cursor,
                             },
                     }))
                     .into_http_response())
@@ -169,6 +195,7 @@ impl TelemetryApi for Server {
                             "data": {
                                 "items": span_ids,
                                 "total": total,
+                                "cursor": cursor,
                             },
                     }))
                     .into_http_response())
@@ -0,0 +1,238 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{Server, auth::AccessToken};
+use directory::backend::internal::manage;
+use email::message::hook::{IngestHook, IngestHooks};
+use hyper::Method;
+use jmap_proto::types::{collection::Collection, id::Id, property::Property};
+use serde_json::json;
+use store::{
+    Serialize,
+    write::{Archiver, BatchBuilder},
+};
+use trc::AddContext;
+use utils::url_params::UrlParams;
+
+use http_proto::*;
+
+pub trait IngestHookManagement: Sync + Send {
+    fn handle_ingest_hook_request(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+        body: Option<Vec<u8>>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl IngestHookManagement for Server {
+    async fn handle_ingest_hook_request(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+        body: Option<Vec<u8>>,
+    ) -> trc::Result<HttpResponse> {
+        let params = UrlParams::new(req.uri().query());
+
+        // Hooks can be managed on the user's own account or on any group they belong to
+        let account_id = if let Some(account_id) = params.get("accountId") {
+            Id::from_bytes(account_id.as_bytes())
+                .map(|id| id.document_id())
+                .ok_or_else(|| {
+                    trc::ResourceEvent::BadParameters
+                        .into_err()
+                        .details(account_id.to_string())
+                })?
+        } else {
+            access_token.primary_id()
+        };
+        if !access_token.is_member(account_id) {
+            return Err(trc::ResourceEvent::NotFound.into_err());
+        }
+
+        // Enforce domain policy
+        let account_token = self.get_access_token(account_id).await?;
+        if !account_token.emails.iter().any(|email| {
+            email
+                .rsplit_once('@')
+                .is_some_and(|(_, domain)| self.core.jmap.is_ingest_hook_allowed(domain))
+        }) {
+            return Err(manage::unsupported(
+                "Mailbox ingestion hooks are not available for this account",
+            ));
+        }
+
+        let mailbox_id = match path.get(2) {
+            Some(mailbox_id) => {
+                let mailbox_id = Id::from_bytes(mailbox_id.as_bytes())
+                    .map(|id| id.document_id())
+                    .ok_or_else(|| {
+                        trc::ResourceEvent::BadParameters
+                            .into_err()
+                            .details(mailbox_id.to_string())
+                    })?;
+                if !self
+                    .get_document_ids(account_id, Collection::Mailbox)
+                    .await?
+                    .is_some_and(|ids| ids.contains(mailbox_id))
+                {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                }
+                Some(mailbox_id)
+            }
+            None => None,
+        };
+
+        match (mailbox_id, path.get(3).copied(), req.method()) {
+            (None, None, &Method::GET) => {
+                let mut items = Vec::new();
+                for mailbox_id in self
+                    .get_document_ids(account_id, Collection::Mailbox)
+                    .await?
+                    .unwrap_or_default()
+                {
+                    if let Some(hook) = self.ingest_hook_get(account_id, mailbox_id).await? {
+                        items.push(json!({
+                            "mailboxId": Id::from(mailbox_id),
+                            "hook": hook,
+                        }));
+                    }
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "total": items.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(mailbox_id), None, &Method::GET) => {
+                let hook = self
+                    .ingest_hook_get(account_id, mailbox_id)
+                    .await?
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": hook,
+                }))
+                .into_http_response())
+            }
+            (Some(mailbox_id), None, &Method::POST) => {
+                let hook =
+                    serde_json::from_slice::<IngestHook>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| trc::ResourceEvent::BadParameters.into_err().reason(err))?;
+
+                // Validate hook
+                if let Err(details) =
+                    hook.validate_url(self.core.jmap.ingest_hook_allow_private_ips)
+                {
+                    return Err(manage::error("Invalid hook URL", Some(details)));
+                } else if hook
+                    .keyword
+                    .as_deref()
+                    .is_some_and(|keyword| keyword.chars().any(|ch| ch.is_whitespace()))
+                {
+                    return Err(manage::error(
+                        "Invalid keyword",
+                        Some("Keywords cannot contain whitespace"),
+                    ));
+                }
+
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Mailbox)
+                    .update_document(mailbox_id)
+                    .set(
+                        Property::Parameters,
+                        Archiver::new(hook)
+                            .serialize()
+                            .caused_by(trc::location!())?,
+                    );
+                self.store().write(batch.build_all()).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some(mailbox_id), None, &Method::DELETE) => {
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Mailbox)
+                    .update_document(mailbox_id)
+                    .clear(Property::Parameters);
+                self.store().write(batch.build_all()).await?;
+                self.ingest_hook_update_dead_letters(account_id, mailbox_id, |items| {
+                    items.items.clear()
+                })
+                .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some(mailbox_id), Some("dead-letter"), &Method::GET) => {
+                let items = self
+                    .ingest_hook_dead_letters(account_id, mailbox_id)
+                    .await?
+                    .items;
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "total": items.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(mailbox_id), Some("dead-letter"), &Method::DELETE) => {
+                let id = path.get(4).and_then(|id| id.parse::<u64>().ok());
+                self.ingest_hook_update_dead_letters(account_id, mailbox_id, |items| {
+                    if let Some(id) = id {
+                        items.items.retain(|item| item.id != id);
+                    } else {
+                        items.items.clear();
+                    }
+                })
+                .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some(mailbox_id), Some("dead-letter"), &Method::POST) => {
+                // Failed notifications are queued again and delivered using the
+                // current hook configuration
+                if self
+                    .ingest_hook_get(account_id, mailbox_id)
+                    .await?
+                    .is_none()
+                {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                }
+                let id = path.get(4).and_then(|id| id.parse::<u64>().ok());
+                let queued = self.ingest_hook_retry(account_id, mailbox_id, id).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "queued": queued,
+                    },
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, time::Duration};
+
+use common::{
+    Server,
+    auth::{AccessToken, impersonate::ImpersonationSession},
+    config::smtp::system::TEMPLATE_IMPERSONATION,
+};
+use directory::Permission;
+use http_proto::{request::decode_path_element, *};
+use hyper::Method;
+use mail_parser::DateTime;
+use serde::Deserialize;
+use serde_json::json;
+use smtp::reporting::system::{SystemMessage, SystemMessages};
+use trc::AddContext;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImpersonationRequest {
+    pub account: String,
+    #[serde(default = "default_read_only")]
+    pub read_only: bool,
+    #[serde(default)]
+    pub duration: Option<u64>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+pub trait ImpersonationManagement: Sync + Send {
+    fn handle_manage_impersonation(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        session: &HttpSessionData,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ImpersonationManagement for Server {
+    async fn handle_manage_impersonation(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        session: &HttpSessionData,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        // Validate the access token
+        access_token.assert_has_permission(Permission::Impersonate)?;
+
+        match (path.get(1), req.method()) {
+            (None, &Method::GET) => {
+                let sessions = self
+                    .list_impersonations()
+                    .await?
+                    .into_iter()
+                    .filter(|session| can_manage(access_token, session))
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "total": sessions.len(),
+                        "items": sessions,
+                    },
+                }))
+                .into_http_response())
+            }
+            (None, &Method::POST) => {
+                let request = serde_json::from_slice::<ImpersonationRequest>(
+                    body.as_deref().unwrap_or_default(),
+                )
+                .map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters).from_json_error(err)
+                })?;
+
+                let (impersonation, token) = self
+                    .create_impersonation(
+                        access_token,
+                        request.account.trim(),
+                        request.read_only,
+                        request.duration.map(Duration::from_secs),
+                        request.reason,
+                    )
+                    .await?;
+
+                // Let the account owner know someone else has access to their mailbox
+                if self.core.jmap.impersonation.notify {
+                    let target = self
+                        .get_access_token(impersonation.account_id)
+                        .await
+                        .caused_by(trc::location!())?;
+                    let expires =
+                        DateTime::from_timestamp(impersonation.expires_at as i64).to_rfc3339();
+                    self.send_system_message(
+                        &SystemMessage {
+                            template: TEMPLATE_IMPERSONATION,
+                            from_name: None,
+                            from_addr: None,
+                            language: None,
+                            variables: &[
+                                ("admin", impersonation.admin_name.as_str()),
+                                ("account", impersonation.account_name.as_str()),
+                                ("access", impersonation.access_type()),
+                                ("expires", expires.as_str()),
+                                (
+                                    "reason",
+                                    impersonation.reason.as_deref().unwrap_or("Not provided"),
+                                ),
+                            ],
+                        },
+                        target.emails.iter(),
+                        session.session_id,
+                    )
+                    .await;
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "token": token,
+                        "session": impersonation,
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(id), &Method::DELETE) => {
+                let id = decode_path_element(id);
+                if self
+                    .list_impersonations()
+                    .await?
+                    .iter()
+                    .any(|session| session.id == id && can_manage(access_token, session))
+                    && self
+                        .revoke_impersonation(id.as_ref(), access_token)
+                        .await?
+                        .is_some()
+                {
+                    Ok(JsonResponse::new(json!({
+                        "data": (),
+                    }))
+                    .into_http_response())
+                } else {
+                    Err(trc::ResourceEvent::NotFound.into_err())
+                }
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+// Tenant administrators only see the sessions they created
+fn can_manage(access_token: &AccessToken, session: &ImpersonationSession) -> bool {
+    access_token.tenant.is_none() || session.admin_id == access_token.primary_id
+}
+
+fn default_read_only() -> bool {
+    true
+}
@@ -20,6 +20,10 @@ use std::future::Future;
 use tokio::sync::oneshot;
 use utils::url_params::UrlParams;
 
+use super::{
+    Timestamp,
+    paging::{CursorScope, Paging, invalid_cursor},
+};
 use http_proto::*;
 
 #[derive(Serialize)]
@@ -56,15 +60,45 @@ impl LogManagement for Server {
             .ok_or_else(|| manage::unsupported("Tracer log path not configured"))?;
 
         let params = UrlParams::new(req.uri().query());
-        let filter = params.get("filter").unwrap_or_default().to_string();
+        let paging = Paging::parse(&params, CursorScope::Log, 100)?;
+        let cursor = paging
+            .cursor
+            .as_ref()
+            .map(|cursor| {
+                cursor
+                    .split_first_chunk::<8>()
+                    .and_then(|(timestamp, count)| {
+                        Some((
+                            i64::from_be_bytes(*timestamp),
+                            u32::from_be_bytes(count.try_into().ok()?) as usize,
+                        ))
+                    })
+                    .ok_or_else(invalid_cursor)
+            })
+            .transpose()?;
         let page: usize = params.parse("page").unwrap_or(0);
-        let limit: usize = params.parse("limit").unwrap_or(100);
-        let offset = page.saturating_sub(1) * limit;
+        let query = LogQuery {
+            filter: params.get("filter").unwrap_or_default().to_string(),
+            level: params.get("level").map(|level| level.to_uppercase()),
+            before: params
+                .parse::<Timestamp>("before")
+                .map(|t| t.into_inner() as i64),
+            after: params
+                .parse::<Timestamp>("after")
+                .map(|t| t.into_inner() as i64),
+            offset: if cursor.is_none() {
+                page.saturating_sub(1) * paging.limit
+            } else {
+                0
+            },
+            cursor,
+            limit: paging.limit,
+        };
 
         // TODO: Use worker pool
         let (tx, rx) = oneshot::channel();
         tokio::task::spawn_blocking(move || {
-            let _ = tx.send(read_log_files(path, &filter, offset, limit));
+            let _ = tx.send(read_log_files(path, query));
         });
 
         let (total, items) = rx
@@ -81,30 +115,60 @@ impl LogManagement for Server {
                     .caused_by(trc::location!())
             })?;
 
+        // Entries only have second precision, so the cursor also records how many
+        // entries sharing the timestamp of the last one have already been returned
+        let cursor = paging.next_cursor(CursorScope::Log, items.len(), || {
+            let last = items.last()?;
+            let timestamp = DateTime::parse_from_rfc3339(&last.timestamp)
+                .ok()?
+                .timestamp();
+            let mut count = items
+                .iter()
+                .rev()
+                .take_while(|entry| entry.timestamp == last.timestamp)
+                .count();
+            if count == items.len() {
+                count += cursor
+                    .filter(|(cursor, _)| *cursor == timestamp)
+                    .map_or(0, |(_, count)| count);
+            }
+            let mut key = timestamp.to_be_bytes().to_vec();
+            key.extend_from_slice(&(count as u32).to_be_bytes());
+            Some(key)
+        });
+
         Ok(JsonResponse::new(json!({
             "data": {
-                "items": items,
+                "items": paging.select(json!(items)),
                 "total": total,
+                "cursor": cursor,
             },
         }))
         .into_http_response())
     }
 }
 
-fn read_log_files(
-    path: impl AsRef<Path>,
-    filter: &str,
-    mut offset: usize,
+struct LogQuery {
+    filter: String,
+    level: Option<String>,
+    before: Option<i64>,
+    after: Option<i64>,
+    cursor: Option<(i64, usize)>,
+    offset: usize,
     limit: usize,
-) -> io::Result<(usize, Vec<LogEntry>)> {
+}
+
+fn read_log_files(path: impl AsRef<Path>, query: LogQuery) -> io::Result<(usize, Vec<LogEntry>)> {
     let mut logs = fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
     let mut total = 0;
+    let mut offset = query.offset;
+    let mut cursor_skip = query.cursor.map_or(0, |(_, count)| count);
 
     // Sort the entries by file name in reverse order.
     logs.sort_by_key(|b| std::cmp::Reverse(b.file_name()));
 
     // Iterate and print the file names.
-    let mut entries = Vec::with_capacity(limit);
+    let mut entries = Vec::with_capacity(query.limit);
     let mut logs = logs.into_iter();
     while let Some(log) = logs.next() {
         if log.file_type()?.is_file() {
@@ -112,22 +176,48 @@ fn read_log_files(
 
             while let Some(line) = rev_lines.next() {
                 let line = line.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                if filter.is_empty() || line.contains(filter) {
-                    total += 1;
-                    if offset == 0 {
-                        if let Some(entry) = LogEntry::from_line(&line) {
-                            entries.push(entry);
-                            if entries.len() == limit {
-                                if rev_lines.next().is_some() || logs.next().is_some() {
-                                    total += limit;
-                                }
-
-                                return Ok((total, entries));
-                            }
+                if !query.filter.is_empty() && !line.contains(&query.filter) {
+                    continue;
+                }
+                let Some(entry) = LogEntry::from_line(&line) else {
+                    continue;
+                };
+                let timestamp = DateTime::parse_from_rfc3339(&entry.timestamp)
+                    .map(|timestamp| timestamp.timestamp())
+                    .unwrap_or_default();
+                if query
+                    .level
+                    .as_ref()
+                    .is_some_and(|level| *level != entry.level)
+                    || query.before.is_some_and(|before| timestamp >= before)
+                    || query.after.is_some_and(|after| timestamp <= after)
+                {
+                    continue;
+                }
+
+                total += 1;
+
+                // Skip entries newer than the cursor, or returned along with it
+                if let Some((cursor, _)) = query.cursor {
+                    if timestamp > cursor {
+                        continue;
+                    } else if timestamp == cursor && cursor_skip > 0 {
+                        cursor_skip -= 1;
+                        continue;
+                    }
+                }
+
+                if offset == 0 {
+                    entries.push(entry);
+                    if entries.len() == query.limit {
+                        if rev_lines.next().is_some() || logs.next().is_some() {
+                            total += query.limit;
                         }
-                    } else {
-                        offset -= 1;
+
+                        return Ok((total, entries));
                     }
+                } else {
+                    offset -= 1;
                 }
             }
         }
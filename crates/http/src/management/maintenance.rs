@@ -0,0 +1,190 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    Server,
+    auth::{AccessToken, maintenance::MaintenanceTarget},
+    config::maintenance::MaintenanceTask,
+    ipc::HousekeeperEvent,
+};
+use directory::{
+    Permission, Type,
+    backend::internal::manage::{ManageDirectory, not_found},
+};
+use http_proto::{request::decode_path_element, *};
+use hyper::Method;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::stores::ManageStore;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MaintenanceRequest {
+    #[serde(default)]
+    pub ttl: Option<u64>,
+}
+
+pub trait MaintenanceManagement: Sync + Send {
+    fn handle_manage_maintenance(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl MaintenanceManagement for Server {
+    async fn handle_manage_maintenance(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let (typ, permission) = match path.get(1).copied() {
+            Some("task") => return handle_manage_task(self, req, path, access_token).await,
+            Some("account") => (Type::Individual, Permission::IndividualUpdate),
+            Some("domain") => (Type::Domain, Permission::DomainUpdate),
+            _ => return Err(trc::ResourceEvent::NotFound.into_err()),
+        };
+        let Some(name) = path.get(2) else {
+            return Err(trc::ResourceEvent::NotFound.into_err());
+        };
+
+        // Validate the access token
+        access_token.assert_has_permission(permission)?;
+
+        let name = decode_path_element(name).trim().to_lowercase();
+        let principal_id = self
+            .core
+            .storage
+            .data
+            .get_principal_info(&name)
+            .await?
+            .filter(|p| p.typ == typ && p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+            .map(|p| p.id)
+            .ok_or_else(|| not_found(name.clone()))?;
+        let target = if typ == Type::Domain {
+            MaintenanceTarget::Domain(&name)
+        } else {
+            MaintenanceTarget::Account(principal_id)
+        };
+
+        match req.method() {
+            &Method::GET => Ok(JsonResponse::new(json!({
+                "data": self.get_maintenance(target).await?,
+            }))
+            .into_http_response()),
+            &Method::POST => {
+                let request = match body.as_deref() {
+                    Some(body) if !body.is_empty() => {
+                        serde_json::from_slice::<MaintenanceRequest>(body).map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?
+                    }
+                    _ => MaintenanceRequest::default(),
+                };
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.set_maintenance(target, request.ttl.filter(|ttl| *ttl > 0)).await?,
+                }))
+                .into_http_response())
+            }
+            &Method::DELETE => {
+                self.clear_maintenance(target).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+async fn handle_manage_task(
+    server: &Server,
+    req: &HttpRequest,
+    path: Vec<&str>,
+    access_token: &AccessToken,
+) -> trc::Result<HttpResponse> {
+    let task = match path.get(2) {
+        Some(id) => Some(
+            MaintenanceTask::parse(decode_path_element(id).as_ref())
+                .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?,
+        ),
+        None => None,
+    };
+
+    match (task, path.get(3).copied(), req.method()) {
+        (None, None, &Method::GET) => {
+            // Validate the access token
+            access_token.assert_has_permission(Permission::SettingsList)?;
+
+            let mut tasks = Vec::with_capacity(MaintenanceTask::ALL.len());
+            for task in MaintenanceTask::ALL {
+                tasks.push(task_status(server, task).await?);
+            }
+
+            Ok(JsonResponse::new(json!({
+                "data": tasks,
+            }))
+            .into_http_response())
+        }
+        (Some(task), None, &Method::GET) => {
+            // Validate the access token
+            access_token.assert_has_permission(Permission::SettingsList)?;
+
+            Ok(JsonResponse::new(json!({
+                "data": task_status(server, task).await?,
+            }))
+            .into_http_response())
+        }
+        (Some(task), Some(action @ ("pause" | "resume")), &Method::POST) => {
+            // Validate the access token
+            access_token.assert_has_permission(Permission::SettingsUpdate)?;
+
+            server
+                .set_maintenance_task_paused(task, action == "pause")
+                .await?;
+
+            Ok(JsonResponse::new(json!({
+                "data": task_status(server, task).await?,
+            }))
+            .into_http_response())
+        }
+        (Some(task), Some("run"), &Method::POST) => {
+            // Validate the access token
+            access_token.assert_has_permission(Permission::SettingsUpdate)?;
+
+            // Manual runs ignore maintenance windows and the paused flag
+            server
+                .housekeeper_request(HousekeeperEvent::RunTask(task))
+                .await
+        }
+        _ => Err(trc::ResourceEvent::NotFound.into_err()),
+    }
+}
+
+async fn task_status(server: &Server, task: MaintenanceTask) -> trc::Result<serde_json::Value> {
+    let running = server
+        .running_maintenance_tasks(task)
+        .into_iter()
+        .map(|running| running.started_at)
+        .collect::<Vec<_>>();
+
+    Ok(json!({
+        "id": task.id(),
+        "paused": server.is_maintenance_task_paused(task).await?,
+        "running": running,
+        "history": server.maintenance_task_history(task).await?,
+    }))
+}
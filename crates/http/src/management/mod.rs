@@ -7,16 +7,23 @@
 pub mod crypto;
 pub mod dkim;
 pub mod dns;
+pub mod hook;
+pub mod impersonate;
 #[cfg(feature = "enterprise")]
 pub mod enterprise;
 pub mod log;
+pub mod maintenance;
+pub mod paging;
 pub mod principal;
 pub mod queue;
 pub mod reload;
 pub mod report;
+pub mod senders;
 pub mod settings;
 pub mod spam;
 pub mod stores;
+pub mod system;
+pub mod tls;
 pub mod troubleshoot;
 
 use std::{str::FromStr, sync::Arc};
@@ -28,20 +35,26 @@ use dkim::DkimManagement;
 use dns::DnsManagement;
 #[cfg(feature = "enterprise")]
 use enterprise::telemetry::TelemetryApi;
+use hook::IngestHookManagement;
 use hyper::{Method, StatusCode, header};
+use impersonate::ImpersonationManagement;
 use jmap::api::{ToJmapHttpResponse, ToRequestError};
 use jmap_proto::error::request::RequestError;
 use log::LogManagement;
 use mail_parser::DateTime;
+use maintenance::MaintenanceManagement;
 use principal::PrincipalManager;
 use queue::QueueManagement;
 use reload::ManageReload;
 use report::ManageReports;
+use senders::SenderManagement;
 use serde::Serialize;
 use settings::ManageSettings;
 use spam::ManageSpamHandler;
 use store::write::now;
 use stores::ManageStore;
+use system::SystemMessageManagement;
+use tls::TlsManagement;
 use troubleshoot::TroubleshootApi;
 
 use crate::auth::oauth::auth::OAuthApiHandler;
@@ -70,6 +83,8 @@ pub enum ManagementApiError<'x> {
     Other {
         details: &'x str,
         reason: Option<&'x str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code: Option<&'x str>,
     },
 }
 
@@ -105,15 +120,33 @@ impl ManagementApi for Server {
                     .await
             }
             "dns" => self.handle_manage_dns(req, path, &access_token).await,
+            "tls" => self.handle_manage_tls(req, path, &access_token).await,
+            "senders" => self.handle_manage_senders(req, path, &access_token).await,
             "store" => {
                 self.handle_manage_store(req, path, body, session, &access_token)
                     .await
             }
             "reload" => self.handle_manage_reload(req, path, &access_token).await,
+            "maintenance" => {
+                self.handle_manage_maintenance(req, path, body, &access_token)
+                    .await
+            }
+            "impersonate" => {
+                self.handle_manage_impersonation(req, path, body, session, &access_token)
+                    .await
+            }
+            "system-message" => {
+                self.handle_manage_system_message(req, path, body, session, &access_token)
+                    .await
+            }
             "dkim" => {
                 self.handle_manage_dkim(req, path, body, &access_token)
                     .await
             }
+            "crypto" => {
+                self.handle_manage_crypto(req, path, body, &access_token)
+                    .await
+            }
             "update" => self.handle_manage_update(req, path, &access_token).await,
             "logs" if req.method() == Method::GET => {
                 self.handle_view_logs(req, &access_token).await
@@ -135,11 +168,39 @@ impl ManagementApi for Server {
                 self.handle_oauth_api_request(access_token, body).await
             }
             "account" => match (path.get(1).copied().unwrap_or_default(), req.method()) {
+                ("crypto", &Method::POST) if path.get(2) == Some(&"existing") => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::ManageEncryption)?;
+
+                    self.handle_crypto_existing_post(access_token).await
+                }
+                ("crypto", &Method::GET) if path.get(2) == Some(&"existing") => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::ManageEncryption)?;
+
+                    self.handle_crypto_existing_get(access_token).await
+                }
+                ("crypto", &Method::GET) if path.get(2) == Some(&"identities") => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::ManageEncryption)?;
+
+                    self.handle_crypto_identities_get(access_token).await
+                }
+                ("crypto", &Method::DELETE) if path.get(2) == Some(&"identities") => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::ManageEncryption)?;
+
+                    let Some(cert_fingerprint) = path.get(3) else {
+                        return Err(trc::ResourceEvent::NotFound.into_err());
+                    };
+                    self.handle_crypto_identity_delete(access_token, cert_fingerprint)
+                        .await
+                }
                 ("crypto", &Method::POST) => {
                     // Validate the access token
                     access_token.assert_has_permission(Permission::ManageEncryption)?;
 
-                    self.handle_crypto_post(access_token, body).await
+                    self.handle_crypto_post(req, access_token, body).await
                 }
                 ("crypto", &Method::GET) => {
                     // Validate the access token
@@ -159,6 +220,13 @@ impl ManagementApi for Server {
 
                     self.handle_account_auth_post(req, access_token, body).await
                 }
+                ("ingest-hook", _) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::ManageIngestHooks)?;
+
+                    self.handle_ingest_hook_request(req, path, &access_token, body)
+                        .await
+                }
                 _ => Err(trc::ResourceEvent::NotFound.into_err()),
             },
             "troubleshoot" => {
@@ -269,6 +337,7 @@ impl ToManageHttpResponse for &trc::Error {
                         details: self
                             .value_as_str(trc::Key::Details)
                             .unwrap_or("Unknown error"),
+                        code: self.value_as_str(trc::Key::Code),
                     },
                 }
             }
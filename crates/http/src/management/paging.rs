@@ -0,0 +1,131 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use directory::backend::internal::manage;
+use serde_json::Value;
+use utils::url_params::UrlParams;
+
+const CURSOR_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CursorScope {
+    Principal = 0,
+    QueueMessage = 1,
+    Trace = 2,
+    Log = 3,
+    Certificate = 4,
+}
+
+// Cursors hold the sort key of the last item returned instead of an offset, so a
+// listing can be resumed after items have been added or removed. They are opaque
+// to clients and bound to the listing that issued them.
+#[derive(Debug, Clone, Default)]
+pub struct Paging {
+    pub cursor: Option<Vec<u8>>,
+    pub limit: usize,
+    pub fields: Option<Vec<String>>,
+}
+
+impl Paging {
+    pub fn parse(
+        params: &UrlParams<'_>,
+        scope: CursorScope,
+        default_limit: usize,
+    ) -> trc::Result<Self> {
+        let cursor = match params.get("cursor").filter(|cursor| !cursor.is_empty()) {
+            Some(cursor) => URL_SAFE_NO_PAD
+                .decode(cursor)
+                .ok()
+                .filter(|bytes| bytes.len() > 2)
+                .filter(|bytes| bytes[0] == CURSOR_VERSION && bytes[1] == scope as u8)
+                .map(|bytes| bytes[2..].to_vec())
+                .ok_or_else(invalid_cursor)?
+                .into(),
+            None => None,
+        };
+
+        Ok(Paging {
+            cursor,
+            limit: params.parse("limit").unwrap_or(default_limit),
+            fields: params.get("fields").map(|fields| {
+                fields
+                    .split(',')
+                    .map(|field| field.trim())
+                    .filter(|field| !field.is_empty())
+                    .map(|field| field.to_string())
+                    .collect()
+            }),
+        })
+    }
+
+    pub fn cursor_u64(&self) -> trc::Result<Option<u64>> {
+        self.cursor
+            .as_ref()
+            .map(|cursor| {
+                cursor
+                    .as_slice()
+                    .try_into()
+                    .map(u64::from_be_bytes)
+                    .map_err(|_| invalid_cursor())
+            })
+            .transpose()
+    }
+
+    pub fn cursor_str(&self) -> trc::Result<Option<&str>> {
+        self.cursor
+            .as_ref()
+            .map(|cursor| std::str::from_utf8(cursor).map_err(|_| invalid_cursor()))
+            .transpose()
+    }
+
+    // A cursor is only issued when the page is full, a client has reached
+    // the end of the listing once the response has no cursor
+    pub fn next_cursor(
+        &self,
+        scope: CursorScope,
+        returned: usize,
+        last_key: impl FnOnce() -> Option<Vec<u8>>,
+    ) -> Option<String> {
+        if self.limit > 0 && returned >= self.limit {
+            last_key().map(|key| {
+                let mut bytes = Vec::with_capacity(key.len() + 2);
+                bytes.push(CURSOR_VERSION);
+                bytes.push(scope as u8);
+                bytes.extend_from_slice(&key);
+                URL_SAFE_NO_PAD.encode(bytes)
+            })
+        } else {
+            None
+        }
+    }
+
+    // Removes the properties of each item not requested in the fields parameter
+    pub fn select(&self, items: Value) -> Value {
+        match (&self.fields, items) {
+            (Some(fields), Value::Array(items)) => Value::Array(
+                items
+                    .into_iter()
+                    .map(|item| match item {
+                        Value::Object(mut item) => {
+                            item.retain(|key, _| fields.iter().any(|field| field == key));
+                            Value::Object(item)
+                        }
+                        item => item,
+                    })
+                    .collect(),
+            ),
+            (_, items) => items,
+        }
+    }
+}
+
+pub(super) fn invalid_cursor() -> trc::Error {
+    manage::error("Invalid or expired cursor", None::<u32>)
+        .ctx(trc::Key::Key, "cursor")
+        .ctx(trc::Key::Code, "invalidCursor")
+}
@@ -12,7 +12,8 @@ use directory::{
         SpecialSecrets,
         lookup::DirectoryStore,
         manage::{
-            self, ChangedPrincipals, ManageDirectory, PrincipalList, UpdatePrincipal, not_found,
+            self, ChangedPrincipals, ListPrincipals, ManageDirectory, PrincipalList,
+            UpdatePrincipal, not_found,
         },
     },
 };
@@ -24,6 +25,8 @@ use std::sync::Arc;
 use trc::AddContext;
 use utils::url_params::UrlParams;
 
+use super::paging::{CursorScope, Paging};
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
@@ -193,8 +196,9 @@ impl PrincipalManager for Server {
                 // List principal ids
                 let params = UrlParams::new(req.uri().query());
                 let filter = params.get("filter");
+                let domain = params.get("domain");
                 let page: usize = params.parse("page").unwrap_or(0);
-                let limit: usize = params.parse("limit").unwrap_or(0);
+                let paging = Paging::parse(&params, CursorScope::Principal, 0)?;
                 let count = params.get("count").is_some();
 
                 // Parse types
@@ -281,16 +285,29 @@ impl PrincipalManager for Server {
 
                 let principals = self
                     .store()
-                    .list_principals(
+                    .query_principals(ListPrincipals {
                         filter,
-                        tenant,
-                        &types,
-                        fields.len() != 1
+                        domain,
+                        tenant_id: tenant,
+                        types: &types,
+                        fetch: fields.len() != 1
                             || fields.first().is_none_or(|v| v != &PrincipalField::Name),
-                        page,
-                        limit,
-                    )
+                        after: paging.cursor_str()?,
+                        offset: if paging.cursor.is_none() {
+                            page.saturating_sub(1) * paging.limit
+                        } else {
+                            0
+                        },
+                        limit: paging.limit,
+                    })
                     .await?;
+                let cursor =
+                    paging.next_cursor(CursorScope::Principal, principals.items.len(), || {
+                        principals
+                            .items
+                            .last()
+                            .map(|principal| principal.name().as_bytes().to_vec())
+                    });
 
                 let principals: PrincipalList<PrincipalSet> = if !count {
                     let mut expanded = PrincipalList {
@@ -313,7 +330,11 @@ impl PrincipalManager for Server {
                 };
 
                 Ok(JsonResponse::new(json!({
-                        "data": principals,
+                        "data": {
+                            "items": principals.items,
+                            "total": principals.total,
+                            "cursor": cursor,
+                        },
                 }))
                 .into_http_response())
             }
@@ -588,7 +609,9 @@ impl PrincipalManager for Server {
                                 | PrincipalField::Members
                                 | PrincipalField::Lists
                                 | PrincipalField::Urls
-                                | PrincipalField::ExternalMembers => (),
+                                | PrincipalField::ExternalMembers
+                                | PrincipalField::Senders
+                                | PrincipalField::EnabledProtocols => (),
                                 PrincipalField::Tenant => {
                                     // Tenants are not allowed to change their tenantId
                                     if access_token.tenant.is_some() {
@@ -656,6 +679,13 @@ impl PrincipalManager for Server {
                             }
                         }
 
+                        // Keep track of protocol changes for auditing
+                        let protocol_changes = changes
+                            .iter()
+                            .filter(|change| change.field == PrincipalField::EnabledProtocols)
+                            .cloned()
+                            .collect::<Vec<_>>();
+
                         // Update principal
                         let changed_principals = self
                             .core
@@ -669,6 +699,31 @@ impl PrincipalManager for Server {
                             )
                             .await?;
 
+                        for change in protocol_changes {
+                            let protocols = match change.value {
+                                PrincipalValue::String(value) => vec![trc::Value::from(value)],
+                                PrincipalValue::StringList(values) => {
+                                    values.into_iter().map(trc::Value::from).collect()
+                                }
+                                PrincipalValue::Integer(_) | PrincipalValue::IntegerList(_) => {
+                                    vec![]
+                                }
+                            };
+
+                            trc::event!(
+                                Auth(trc::AuthEvent::ProtocolsChanged),
+                                AccountId = account_id,
+                                AccountName = name.to_string(),
+                                Source = access_token.name.clone(),
+                                Type = match change.action {
+                                    PrincipalAction::Set => "set",
+                                    PrincipalAction::AddItem => "addItem",
+                                    PrincipalAction::RemoveItem => "removeItem",
+                                },
+                                Value = protocols,
+                            );
+                        }
+
                         // Increment revision
                         self.increment_token_revision(changed_principals).await;
 
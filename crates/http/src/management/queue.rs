@@ -4,10 +4,10 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{future::Future, sync::atomic::Ordering};
+use std::{future::Future, net::IpAddr, sync::atomic::Ordering};
 
 use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
-use common::{Server, auth::AccessToken, ipc::QueueEvent};
+use common::{Server, auth::AccessToken, config::smtp::queue::IpWarmup, ipc::QueueEvent};
 
 use directory::{Permission, Type, backend::internal::manage::ManageDirectory};
 use hyper::Method;
@@ -20,22 +20,28 @@ use mail_parser::DateTime;
 use serde::{Deserializer, Serializer};
 use serde_json::json;
 use smtp::{
+    outbound::warmup::{IpWarmupManager, WarmupState, WarmupStatus},
     queue::{
         self, ArchivedMessage, ArchivedStatus, DisplayArchivedResponse, ErrorDetails, HostResponse,
-        QueueId, Status, spool::SmtpSpool,
+        MAIL_SKIP_ENCRYPTION, MAIL_TLS_NOT_REQUIRED, QueueId, RCPT_ENCRYPTION_FAILED,
+        RCPT_QUARANTINED, Status, parse_queue_id, spool::SmtpSpool,
     },
     reporting::{dmarc::DmarcReporting, tls::TlsReporting},
 };
+use smtp_proto::MAIL_REQUIRETLS;
 use store::{
     Deserialize, IterateParams, ValueKey,
     write::{
         AlignedBytes, Archive, QueueClass, ReportEvent, ValueClass, key::DeserializeBigEndian, now,
     },
 };
-use trc::AddContext;
+use trc::{AddContext, DeliveryEvent};
 use utils::url_params::UrlParams;
 
-use super::FutureTimestamp;
+use super::{
+    FutureTimestamp,
+    paging::{CursorScope, Paging},
+};
 use http_proto::{request::decode_path_element, *};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
@@ -52,6 +58,15 @@ pub struct Message {
     pub priority: i16,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub env_id: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    pub require_tls: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    pub tls_optional: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    pub skip_encryption: bool,
     pub blob_hash: String,
 }
 
@@ -79,6 +94,12 @@ pub struct Recipient {
     pub status: Status<String, String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub orcpt: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    pub encryption_failed: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    pub quarantined: bool,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -167,15 +188,17 @@ impl QueueManagement for Server {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::MessageQueueList)?;
 
-                let result = fetch_queued_messages(self, &params, &tenant_domains).await?;
+                let paging = Paging::parse(&params, CursorScope::QueueMessage, 0)?;
+                let result = fetch_queued_messages(self, &params, &paging, &tenant_domains).await?;
 
                 let queue_status = self.inner.data.queue_status.load(Ordering::Relaxed);
 
                 Ok(if !result.values.is_empty() {
                     JsonResponse::new(json!({
                             "data":{
-                                "items": result.values,
+                                "items": paging.select(json!(result.values)),
                                 "total": result.total,
+                                "cursor": result.cursor,
                                 "status": queue_status,
                             },
                     }))
@@ -184,6 +207,7 @@ impl QueueManagement for Server {
                             "data": {
                                 "items": result.ids,
                                 "total":  result.total,
+                                "cursor": result.cursor,
                                 "status": queue_status,
                             },
                     }))
@@ -194,10 +218,10 @@ impl QueueManagement for Server {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::MessageQueueGet)?;
 
-                if let Some(message_) = self
-                    .read_message_archive(queue_id.parse().unwrap_or_default())
-                    .await?
-                {
+                let Some(queue_id) = parse_queue_id(&queue_id) else {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                };
+                if let Some(message_) = self.read_message_archive(queue_id).await? {
                     let message = message_.unarchive::<queue::Message>()?;
                     if message.is_tenant_domain(&tenant_domains) {
                         return Ok(JsonResponse::new(json!({
@@ -216,7 +240,8 @@ impl QueueManagement for Server {
                     .parse::<FutureTimestamp>("at")
                     .map(|t| t.into_inner())
                     .unwrap_or_else(now);
-                let result = fetch_queued_messages(self, &params, &tenant_domains).await?;
+                let paging = Paging::parse(&params, CursorScope::QueueMessage, 0)?;
+                let result = fetch_queued_messages(self, &params, &paging, &tenant_domains).await?;
 
                 let found = !result.ids.is_empty();
                 if found {
@@ -267,19 +292,24 @@ impl QueueManagement for Server {
                     .map(|t| t.into_inner())
                     .unwrap_or_else(now);
                 let item = params.get("filter");
+                let skip_encryption = params.parse::<bool>("skip-encryption").unwrap_or_default();
 
-                if let Some(mut message) = self
-                    .read_message(queue_id.parse().unwrap_or_default())
-                    .await
-                    .filter(|message| {
-                        tenant_domains
-                            .as_ref()
-                            .is_none_or(|domains| message.has_domain(domains))
-                    })
-                {
+                let Some(queue_id) = parse_queue_id(&queue_id) else {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                };
+                if let Some(mut message) = self.read_message(queue_id).await.filter(|message| {
+                    tenant_domains
+                        .as_ref()
+                        .is_none_or(|domains| message.has_domain(domains))
+                }) {
                     let prev_event = message.next_event().unwrap_or_default();
                     let mut found = false;
 
+                    // Operators may allow storing messages that cannot be encrypted as-is
+                    if skip_encryption {
+                        message.flags |= MAIL_SKIP_ENCRYPTION;
+                    }
+
                     for domain in &mut message.domains {
                         if matches!(
                             domain.status,
@@ -316,7 +346,8 @@ impl QueueManagement for Server {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::MessageQueueDelete)?;
 
-                let result = fetch_queued_messages(self, &params, &tenant_domains).await?;
+                let paging = Paging::parse(&params, CursorScope::QueueMessage, 0)?;
+                let result = fetch_queued_messages(self, &params, &paging, &tenant_domains).await?;
 
                 let found = !result.ids.is_empty();
                 if found {
@@ -360,15 +391,14 @@ impl QueueManagement for Server {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::MessageQueueDelete)?;
 
-                if let Some(mut message) = self
-                    .read_message(queue_id.parse().unwrap_or_default())
-                    .await
-                    .filter(|message| {
-                        tenant_domains
-                            .as_ref()
-                            .is_none_or(|domains| message.has_domain(domains))
-                    })
-                {
+                let Some(queue_id) = parse_queue_id(&queue_id) else {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                };
+                if let Some(mut message) = self.read_message(queue_id).await.filter(|message| {
+                    tenant_domains
+                        .as_ref()
+                        .is_none_or(|domains| message.has_domain(domains))
+                }) {
                     let mut found = false;
                     let prev_event = message.next_event().unwrap_or_default();
 
@@ -591,11 +621,147 @@ impl QueueManagement for Server {
                 }))
                 .into_http_response())
             }
+            ("warmup", None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MessageQueueList)?;
+
+                let mut items = Vec::with_capacity(self.core.smtp.queue.warmup.len());
+                for config in self.core.smtp.queue.warmup.values() {
+                    items.push(warmup_report(self, config).await?);
+                }
+
+                Ok(JsonResponse::new(json!({
+                        "data": {
+                            "items": items,
+                            "total": items.len(),
+                        },
+                }))
+                .into_http_response())
+            }
+            ("warmup", Some(ip), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MessageQueueGet)?;
+
+                let config = ip
+                    .parse::<IpAddr>()
+                    .ok()
+                    .and_then(|ip| self.core.smtp.queue.warmup.get(&ip))
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                Ok(JsonResponse::new(json!({
+                        "data": warmup_report(self, config).await?,
+                }))
+                .into_http_response())
+            }
+            ("warmup", Some(ip), &Method::PATCH) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MessageQueueUpdate)?;
+
+                let config = ip
+                    .parse::<IpAddr>()
+                    .ok()
+                    .and_then(|ip| self.core.smtp.queue.warmup.get(&ip))
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+                let mut state = self.warmup_state(config).await?;
+                let event = match (params.get("action"), state.status) {
+                    (Some("pause"), WarmupStatus::Active) => {
+                        state.status = WarmupStatus::Paused;
+                        DeliveryEvent::WarmupPaused
+                    }
+                    (Some("resume"), WarmupStatus::Paused) => {
+                        state.status = WarmupStatus::Active;
+                        DeliveryEvent::WarmupResumed
+                    }
+                    (Some("pause" | "resume"), _) => {
+                        return Ok(JsonResponse::new(json!({
+                                "data": false,
+                        }))
+                        .into_http_response());
+                    }
+                    _ => {
+                        return Err(trc::ResourceEvent::BadParameters
+                            .into_err()
+                            .details("Invalid action, expected 'pause' or 'resume'"));
+                    }
+                };
+                self.warmup_set_state(config, &state).await?;
+
+                trc::event!(
+                    Delivery(event),
+                    Id = config.id.clone(),
+                    LocalIp = config.ip,
+                    Total = state.phase,
+                    AccountId = access_token.primary_id(),
+                );
+
+                Ok(JsonResponse::new(json!({
+                        "data": true,
+                }))
+                .into_http_response())
+            }
+            ("warmup", Some(ip), &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MessageQueueUpdate)?;
+
+                let config = ip
+                    .parse::<IpAddr>()
+                    .ok()
+                    .and_then(|ip| self.core.smtp.queue.warmup.get(&ip))
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                // Restart the ramp from the first phase
+                self.warmup_set_state(
+                    config,
+                    &WarmupState {
+                        day: now() / 86400,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+                Ok(JsonResponse::new(json!({
+                        "data": true,
+                }))
+                .into_http_response())
+            }
             _ => Err(trc::ResourceEvent::NotFound.into_err()),
         }
     }
 }
 
+async fn warmup_report(server: &Server, config: &IpWarmup) -> trc::Result<serde_json::Value> {
+    let state = server.warmup_state(config).await?;
+    let today = now() / 86400;
+    let mut destinations = serde_json::Map::new();
+    for destination in config
+        .destinations
+        .iter()
+        .map(|d| d.as_str())
+        .chain((!config.destinations.is_empty()).then_some("*"))
+    {
+        destinations.insert(
+            destination.to_string(),
+            json!(
+                server
+                    .warmup_counters(config.ip, today, destination)
+                    .await?
+            ),
+        );
+    }
+
+    Ok(json!({
+        "id": config.id,
+        "ip": config.ip,
+        "phase": state.phase,
+        "phases": config.schedule.len(),
+        "status": state.status,
+        "dailyLimit": config.daily_limit(state.phase),
+        "today": server.warmup_counters(config.ip, today, "").await?,
+        "destinations": destinations,
+        "updated": DateTime::from_timestamp(state.updated as i64).to_rfc3339(),
+    }))
+}
+
 impl From<&ArchivedMessage> for Message {
     fn from(message: &ArchivedMessage) -> Self {
         let now = now();
@@ -607,6 +773,9 @@ impl From<&ArchivedMessage> for Message {
             size: message.size.into(),
             priority: message.priority.into(),
             env_id: message.env_id.as_ref().map(|id| id.to_string()),
+            require_tls: (u64::from(message.flags) & MAIL_REQUIRETLS) != 0,
+            tls_optional: (u64::from(message.flags) & MAIL_TLS_NOT_REQUIRED) != 0,
+            skip_encryption: (u64::from(message.flags) & MAIL_SKIP_ENCRYPTION) != 0,
             domains: message
                 .domains
                 .iter()
@@ -649,6 +818,9 @@ impl From<&ArchivedMessage> for Message {
                                 }
                             },
                             orcpt: rcpt.orcpt.as_ref().map(|orcpt| orcpt.to_string()),
+                            encryption_failed: (u64::from(rcpt.flags) & RCPT_ENCRYPTION_FAILED)
+                                != 0,
+                            quarantined: (u64::from(rcpt.flags) & RCPT_QUARANTINED) != 0,
                         })
                         .collect(),
                     expires: DateTime::from_timestamp(u64::from(domain.expires) as i64),
@@ -663,14 +835,17 @@ struct QueuedMessages {
     ids: Vec<u64>,
     values: Vec<Message>,
     total: usize,
+    cursor: Option<String>,
 }
 
 async fn fetch_queued_messages(
     server: &Server,
     params: &UrlParams<'_>,
+    paging: &Paging,
     tenant_domains: &Option<Vec<String>>,
 ) -> trc::Result<QueuedMessages> {
     let text = params.get("text");
+    let text_queue_id = text.and_then(parse_queue_id);
     let from = params.get("from");
     let to = params.get("to");
     let before = params
@@ -679,9 +854,12 @@ async fn fetch_queued_messages(
     let after = params
         .parse::<FutureTimestamp>("after")
         .map(|t| t.into_inner());
+    let domain = params.get("domain").map(|domain| domain.to_lowercase());
+    let status = params.get("status");
     let page = params.parse::<usize>("page").unwrap_or_default();
-    let limit = params.parse::<usize>("limit").unwrap_or_default();
+    let limit = paging.limit;
     let values = params.has_key("values");
+    let cursor = paging.cursor_u64()?;
 
     let range_start = params.parse::<u64>("range-start").unwrap_or_default();
     let range_end = params.parse::<u64>("range-end").unwrap_or(u64::MAX);
@@ -691,13 +869,24 @@ async fn fetch_queued_messages(
         ids: Vec::new(),
         values: Vec::new(),
         total: 0,
+        cursor: None,
     };
     let from_key = ValueKey::from(ValueClass::Queue(QueueClass::Message(range_start)));
     let to_key = ValueKey::from(ValueClass::Queue(QueueClass::Message(range_end)));
-    let has_filters =
-        text.is_some() || from.is_some() || to.is_some() || before.is_some() || after.is_some();
-    let mut offset = page.saturating_sub(1) * limit;
+    let has_filters = text.is_some()
+        || from.is_some()
+        || to.is_some()
+        || before.is_some()
+        || after.is_some()
+        || domain.is_some()
+        || status.is_some();
+    let mut offset = if cursor.is_none() {
+        page.saturating_sub(1) * limit
+    } else {
+        0
+    };
     let mut total_returned = 0;
+    let mut last_id = None;
 
     server
         .core
@@ -718,7 +907,8 @@ async fn fetch_queued_messages(
                         || (text
                             .as_ref()
                             .map(|text| {
-                                message.return_path.contains(text)
+                                text_queue_id.is_some_and(|id| id == message.queue_id.to_native())
+                                    || message.return_path.contains(text)
                                     || message
                                         .recipients
                                         .iter()
@@ -739,20 +929,38 @@ async fn fetch_queued_messages(
                                 .is_none_or(|before| message.next_delivery_event() < *before)
                             && after
                                 .as_ref()
-                                .is_none_or(|after| message.next_delivery_event() > *after)));
+                                .is_none_or(|after| message.next_delivery_event() > *after)
+                            && domain.as_ref().is_none_or(|domain| {
+                                message.domains.iter().any(|d| d.domain.as_str() == domain)
+                            })
+                            && status.is_none_or(|status| {
+                                message.domains.iter().any(|d| {
+                                    matches!(
+                                        (&d.status, status),
+                                        (ArchivedStatus::Scheduled, "scheduled")
+                                            | (ArchivedStatus::Completed(_), "completed")
+                                            | (ArchivedStatus::TemporaryFailure(_), "temp_fail")
+                                            | (ArchivedStatus::PermanentFailure(_), "perm_fail")
+                                    )
+                                })
+                            })));
 
                 if matches {
-                    if offset == 0 {
-                        if limit == 0 || total_returned < limit {
-                            if values {
-                                result.values.push(Message::from(message));
-                            } else {
-                                result.ids.push(key.deserialize_be_u64(0)?);
+                    let id = key.deserialize_be_u64(0)?;
+                    if cursor.is_none_or(|cursor| id > cursor) {
+                        if offset == 0 {
+                            if limit == 0 || total_returned < limit {
+                                if values {
+                                    result.values.push(Message::from(message));
+                                } else {
+                                    result.ids.push(id);
+                                }
+                                last_id = Some(id);
+                                total_returned += 1;
                             }
-                            total_returned += 1;
+                        } else {
+                            offset -= 1;
                         }
-                    } else {
-                        offset -= 1;
                     }
 
                     result.total += 1;
@@ -762,8 +970,13 @@ async fn fetch_queued_messages(
             },
         )
         .await
-        .caused_by(trc::location!())
-        .map(|_| result)
+        .caused_by(trc::location!())?;
+
+    result.cursor = paging.next_cursor(CursorScope::QueueMessage, total_returned, || {
+        last_id.map(|id| id.to_be_bytes().to_vec())
+    });
+
+    Ok(result)
 }
 
 struct QueuedReports {
@@ -910,7 +1123,10 @@ fn parse_queued_report_id(id: &str) -> Option<QueueClass> {
     }
 }
 
-fn serialize_maybe_datetime<S>(value: &Option<DateTime>, serializer: S) -> Result<S::Ok, S::Error>
+pub(super) fn serialize_maybe_datetime<S>(
+    value: &Option<DateTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
@@ -920,7 +1136,9 @@ where
     }
 }
 
-fn deserialize_maybe_datetime<'de, D>(deserializer: D) -> Result<Option<DateTime>, D::Error>
+pub(super) fn deserialize_maybe_datetime<'de, D>(
+    deserializer: D,
+) -> Result<Option<DateTime>, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -963,6 +1181,10 @@ fn is_zero(num: &i16) -> bool {
     *num == 0
 }
 
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
 trait IsTenantDomain {
     fn is_tenant_domain(&self, tenant_domains: &Option<Vec<String>>) -> bool;
 }
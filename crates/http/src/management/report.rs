@@ -268,6 +268,43 @@ impl ManageReports for Server {
                     Err(trc::ResourceEvent::NotFound.into_err())
                 }
             }
+            ("tls-fingerprints", None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::IncomingReportList)?;
+
+                let params = UrlParams::new(req.uri().query());
+                let limit: usize = params.parse("limit").unwrap_or(25);
+                let mut fingerprints = self
+                    .inner
+                    .data
+                    .tls_fingerprints
+                    .lock()
+                    .iter()
+                    .map(|(fingerprint, stats)| (fingerprint.clone(), *stats))
+                    .collect::<Vec<_>>();
+                fingerprints.sort_unstable_by(|a, b| {
+                    b.1.rejected
+                        .cmp(&a.1.rejected)
+                        .then_with(|| b.1.sessions.cmp(&a.1.sessions))
+                });
+                let total = fingerprints.len();
+
+                Ok(JsonResponse::new(json!({
+                        "data": {
+                            "items": fingerprints
+                                .into_iter()
+                                .take(limit)
+                                .map(|(fingerprint, stats)| json!({
+                                    "fingerprint": fingerprint,
+                                    "sessions": stats.sessions,
+                                    "rejected": stats.rejected,
+                                }))
+                                .collect::<Vec<_>>(),
+                            "total": total,
+                        },
+                }))
+                .into_http_response())
+            }
             _ => Err(trc::ResourceEvent::NotFound.into_err()),
         }
     }
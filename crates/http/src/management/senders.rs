@@ -0,0 +1,172 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{Server, auth::AccessToken};
+use directory::{
+    Permission, Principal, Type,
+    backend::internal::manage::{ManageDirectory, PrincipalList},
+};
+use http_proto::{request::decode_path_element, *};
+use hyper::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::future::Future;
+use trc::AddContext;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizedSender {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub typ: Type,
+    pub address: String,
+    pub dkim: bool,
+    pub source: SenderSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub via: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SenderSource {
+    Email,
+    Grant,
+}
+
+pub trait SenderManagement: Sync + Send {
+    fn handle_manage_senders(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn authorized_senders(
+        &self,
+        domain: &str,
+        tenant_id: Option<u32>,
+    ) -> impl Future<Output = trc::Result<Vec<AuthorizedSender>>> + Send;
+}
+
+impl SenderManagement for Server {
+    async fn handle_manage_senders(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1), req.method()) {
+            (Some(domain), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::IndividualList)?;
+                access_token.assert_has_permission(Permission::GroupList)?;
+
+                let domain = decode_path_element(domain).trim().to_lowercase();
+                let senders = self
+                    .authorized_senders(&domain, access_token.tenant.map(|t| t.id))
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": PrincipalList {
+                        total: senders.len() as u64,
+                        items: senders,
+                    },
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+
+    async fn authorized_senders(
+        &self,
+        domain: &str,
+        tenant_id: Option<u32>,
+    ) -> trc::Result<Vec<AuthorizedSender>> {
+        let suffix = format!("@{domain}");
+        let principals = self
+            .store()
+            .list_principals(
+                None,
+                tenant_id,
+                &[Type::Individual, Type::Group],
+                true,
+                0,
+                0,
+            )
+            .await
+            .caused_by(trc::location!())?;
+        let mut senders = Vec::new();
+
+        for principal in principals.items {
+            // Addresses owned by the principal
+            for email in &principal.emails {
+                if email.ends_with(&suffix) {
+                    senders.push(AuthorizedSender {
+                        name: principal.name.clone(),
+                        typ: principal.typ,
+                        address: email.clone(),
+                        dkim: false,
+                        source: SenderSource::Email,
+                        via: None,
+                    });
+                }
+            }
+
+            // Addresses and domains granted explicitly
+            for grant in principal
+                .sender_grants()
+                .filter(|grant| grant.domain() == domain)
+            {
+                senders.push(AuthorizedSender {
+                    name: principal.name.clone(),
+                    typ: principal.typ,
+                    address: grant.address.clone(),
+                    dkim: grant.dkim,
+                    source: SenderSource::Grant,
+                    via: None,
+                });
+
+                // Grants are inherited by the group members
+                if principal.typ == Type::Group {
+                    senders.extend(group_members(self, &principal).await?.into_iter().map(
+                        |(name, typ)| AuthorizedSender {
+                            name,
+                            typ,
+                            address: grant.address.clone(),
+                            dkim: grant.dkim,
+                            source: SenderSource::Grant,
+                            via: principal.name.clone().into(),
+                        },
+                    ));
+                }
+            }
+        }
+
+        Ok(senders)
+    }
+}
+
+async fn group_members(server: &Server, group: &Principal) -> trc::Result<Vec<(String, Type)>> {
+    let mut members = Vec::new();
+    for member_id in server
+        .store()
+        .get_members(group.id())
+        .await
+        .caused_by(trc::location!())?
+    {
+        if let Some(member) = server
+            .store()
+            .get_principal(member_id)
+            .await
+            .caused_by(trc::location!())?
+        {
+            members.push((member.name, member.typ));
+        }
+    }
+    Ok(members)
+}
@@ -9,10 +9,17 @@ use directory::Permission;
 use hyper::Method;
 use serde_json::json;
 use store::ahash::AHashMap;
-use utils::{config::ConfigKey, map::vec_map::VecMap, url_params::UrlParams};
+use utils::{
+    config::{
+        ConfigKey,
+        secret::{SEALED_PLACEHOLDER, redact},
+    },
+    map::vec_map::VecMap,
+    url_params::UrlParams,
+};
 
 use http_proto::{request::decode_path_element, *};
-use std::future::Future;
+use std::{collections::BTreeMap, future::Future};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "type")]
@@ -85,7 +92,15 @@ impl ManageSettings for Server {
                     params.parse::<usize>("page").unwrap_or(0).saturating_sub(1) * limit;
                 let has_filter = !filter.is_empty();
 
-                let settings = self.core.storage.config.list(&prefix, true).await?;
+                let settings = self
+                    .core
+                    .storage
+                    .config
+                    .list(&prefix, true)
+                    .await?
+                    .into_iter()
+                    .map(|(key, value)| (key, redact(value)))
+                    .collect::<BTreeMap<_, _>>();
                 if !suffix.is_empty() && !settings.is_empty() {
                     // Obtain record ids
                     let mut total = 0;
@@ -210,6 +225,7 @@ impl ManageSettings for Server {
                     .into_iter()
                     .skip(offset)
                     .take(if limit == 0 { total } else { limit })
+                    .map(|(key, value)| (key, redact(value)))
                     .collect::<VecMap<_, _>>();
 
                 Ok(JsonResponse::new(json!({
@@ -238,7 +254,7 @@ impl ManageSettings for Server {
 
                 for key in keys {
                     if let Some(value) = self.core.storage.config.get(key).await? {
-                        results.insert(key.to_string(), value);
+                        results.insert(key.to_string(), redact(value));
                     }
                 }
                 for prefix in prefixes {
@@ -247,7 +263,15 @@ impl ManageSettings for Server {
                     } else {
                         prefix.to_string()
                     };
-                    results.extend(self.core.storage.config.list(&prefix, false).await?);
+                    results.extend(
+                        self.core
+                            .storage
+                            .config
+                            .list(&prefix, false)
+                            .await?
+                            .into_iter()
+                            .map(|(key, value)| (key, redact(value))),
+                    );
                 }
 
                 Ok(JsonResponse::new(json!({
@@ -329,14 +353,18 @@ impl ManageSettings for Server {
                                 .storage
                                 .config
                                 .set(
-                                    values.into_iter().map(|(key, value)| ConfigKey {
-                                        key: if let Some(prefix) = &prefix {
-                                            format!("{prefix}.{key}")
-                                        } else {
-                                            key
-                                        },
-                                        value,
-                                    }),
+                                    values
+                                        .into_iter()
+                                        // Redacted secrets sent back unchanged keep their stored value
+                                        .filter(|(_, value)| value != SEALED_PLACEHOLDER)
+                                        .map(|(key, value)| ConfigKey {
+                                            key: if let Some(prefix) = &prefix {
+                                                format!("{prefix}.{key}")
+                                            } else {
+                                                key
+                                            },
+                                            value,
+                                        }),
                                     true,
                                 )
                                 .await?;
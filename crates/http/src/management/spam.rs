@@ -6,7 +6,7 @@
 
 use std::net::IpAddr;
 
-use common::{Server, auth::AccessToken, config::spamfilter::SpamFilterAction, psl};
+use common::{Server, auth::AccessToken, config::spamfilter::SpamFilterAction};
 
 use compact_str::CompactString;
 use directory::{
@@ -27,6 +27,7 @@ use spam_filter::{
 };
 use std::future::Future;
 use store::ahash::AHashMap;
+use utils::suffixlist::org_domain;
 
 use http_proto::{request::decode_path_element, *};
 
@@ -205,7 +206,7 @@ impl ManageSpamHandler for Server {
                         dkim_output: &dkim_output,
                         rfc5321_mail_from_domain: mail_from_domain.unwrap_or(ehlo_domain.as_str()),
                         spf_output: &spf_mail_from_result,
-                        domain_suffix_fn: |domain| psl::domain_str(domain).unwrap_or(domain),
+                        domain_suffix_fn: org_domain,
                     }))
                     .await;
                 let dmarc_pass = matches!(dmarc_output.spf_result(), DmarcResult::Pass)
@@ -16,13 +16,15 @@ use directory::{
     Permission,
     backend::internal::manage::{self, ManageDirectory},
 };
-use email::message::{ingest::EmailIngest, metadata::MessageData};
+use email::message::{ingest::EmailIngest, integrity::EmailIntegrity, metadata::MessageData};
 use hyper::Method;
 use jmap_proto::types::{collection::Collection, property::Property};
 use serde_json::json;
 use services::index::Indexer;
 use store::{
-    Serialize, rand,
+    Serialize,
+    dispatch::latency::{clear_slow_operations, top_slow_operations},
+    rand,
     write::{Archiver, BatchBuilder, ValueClass},
 };
 use trc::AddContext;
@@ -263,6 +265,136 @@ impl ManageStore for Server {
                 }
             }
             // SPDX-SnippetEnd
+            (Some("integrity"), None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::BlobFetch)?;
+
+                let items = self.integrity_report().await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "total": items.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some("integrity"), id, None, &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::BlobFetch)?;
+
+                // Entries are identified by account and document id
+                let id = id
+                    .map(|id| {
+                        id.split_once('-')
+                            .and_then(|(account_id, document_id)| {
+                                Some((account_id.parse::<u32>().ok()?, document_id.parse().ok()?))
+                            })
+                            .ok_or_else(|| {
+                                trc::ResourceEvent::BadParameters
+                                    .into_err()
+                                    .details("Invalid report entry id")
+                            })
+                    })
+                    .transpose()?;
+                self.integrity_update_report(|items| {
+                    if let Some((account_id, document_id)) = id {
+                        items.retain(|item| {
+                            item.account_id != account_id || item.document_id != document_id
+                        });
+                    } else {
+                        items.clear();
+                    }
+                })
+                .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some("integrity"), Some("audit"), None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::BlobFetch)?;
+
+                let server = self.clone();
+                tokio::spawn(async move {
+                    server.audit_messages().await;
+                });
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some("slow-queries"), None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MetricsList)?;
+
+                let params = UrlParams::new(req.uri().query());
+                let limit = params.parse("limit").unwrap_or(50);
+                let items = top_slow_operations(limit)
+                    .into_iter()
+                    .map(|item| {
+                        json!({
+                            "operation": item.context.operation.as_str(),
+                            "subspace": item.context.subspace_name(),
+                            "accountId": item.context.account_id,
+                            "collection": item.context.collection,
+                            "count": item.count,
+                            "timeouts": item.timeouts,
+                            "maxElapsed": item.max_elapsed.as_millis() as u64,
+                            "avgElapsed": (item.total_elapsed / item.count as u32).as_millis() as u64,
+                            "lastSeen": item.last_seen,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "total": items.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some("slow-queries"), None, None, &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MetricsList)?;
+
+                clear_slow_operations();
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some("index-queue"), None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MetricsList)?;
+
+                let params = UrlParams::new(req.uri().query());
+                let backlog = &self.inner.data.index_backlog;
+                let accounts = backlog
+                    .top_accounts(params.parse("limit").unwrap_or(50))
+                    .into_iter()
+                    .map(|(account_id, pending)| {
+                        json!({
+                            "accountId": account_id,
+                            "pending": pending,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "pending": backlog.total(),
+                        "drained": backlog.total_drained(),
+                        "accounts": accounts,
+                    },
+                }))
+                .into_http_response())
+            }
             (Some("uids"), Some(account_id), None, &Method::DELETE) => {
                 let account_id = self
                     .core
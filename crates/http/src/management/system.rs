@@ -0,0 +1,93 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::collections::BTreeMap;
+
+use common::{Server, auth::AccessToken};
+use directory::Permission;
+use http_proto::{request::decode_path_element, *};
+use hyper::Method;
+use serde::Deserialize;
+use serde_json::json;
+use smtp::reporting::system::{SystemMessage, SystemMessages};
+use std::future::Future;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewRequest {
+    pub domain: String,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+}
+
+pub trait SystemMessageManagement: Sync + Send {
+    fn handle_manage_system_message(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        session: &HttpSessionData,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl SystemMessageManagement for Server {
+    async fn handle_manage_system_message(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        session: &HttpSessionData,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1), path.get(2).copied(), req.method()) {
+            (Some(name), Some("preview"), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsList)?;
+
+                let request = match serde_json::from_slice::<PreviewRequest>(
+                    body.as_deref().unwrap_or_default(),
+                ) {
+                    Ok(request) => request,
+                    Err(err) => {
+                        return Err(
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                        );
+                    }
+                };
+
+                let name = decode_path_element(name).trim().to_lowercase();
+                let variables = request
+                    .variables
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.as_str()))
+                    .collect::<Vec<_>>();
+                let rendered = self
+                    .render_system_message(
+                        &SystemMessage {
+                            template: &name,
+                            from_name: None,
+                            from_addr: None,
+                            language: request.language.as_deref(),
+                            variables: &variables,
+                        },
+                        &request.domain,
+                        session.session_id,
+                    )
+                    .await?
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": rendered,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
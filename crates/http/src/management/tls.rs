@@ -0,0 +1,333 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Instant};
+
+use common::{
+    Server,
+    auth::AccessToken,
+    ipc::{BroadcastEvent, HousekeeperEvent},
+    listener::tls::listener_certificate_key,
+};
+use directory::Permission;
+use hyper::Method;
+use mail_parser::DateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::future::Future;
+use utils::url_params::UrlParams;
+use x509_parser::parse_x509_certificate;
+
+use super::{
+    Timestamp,
+    paging::{CursorScope, Paging},
+    queue::{deserialize_maybe_datetime, serialize_maybe_datetime},
+};
+use http_proto::{request::decode_path_element, *};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CertificateStatus {
+    pub domains: Vec<String>,
+    pub source: CertificateSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub acme_provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub issuer: Option<String>,
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    #[serde(serialize_with = "serialize_maybe_datetime")]
+    pub not_before: Option<DateTime>,
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    #[serde(serialize_with = "serialize_maybe_datetime")]
+    pub not_after: Option<DateTime>,
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    #[serde(serialize_with = "serialize_maybe_datetime")]
+    pub renew_at: Option<DateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CertificateSource {
+    Static,
+    Acme,
+}
+
+pub trait TlsManagement: Sync + Send {
+    fn handle_manage_tls(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl TlsManagement for Server {
+    async fn handle_manage_tls(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (
+            path.get(1).copied(),
+            path.get(2).copied(),
+            path.get(3).copied(),
+            path.get(4).copied(),
+            req.method(),
+        ) {
+            (Some("certificates"), None, None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsList)?;
+
+                let params = UrlParams::new(req.uri().query());
+                let paging = Paging::parse(&params, CursorScope::Certificate, 0)?;
+                let after_domains = paging
+                    .cursor_str()?
+                    .map(|cursor| cursor.split(',').map(String::from).collect::<Vec<_>>());
+                let source = params.get("source");
+                let domain = params.get("domain").map(|domain| domain.to_lowercase());
+                let before = params.parse::<Timestamp>("before").map(|t| t.into_inner());
+                let after = params.parse::<Timestamp>("after").map(|t| t.into_inner());
+
+                let mut certificates = certificate_status(self);
+                certificates.retain(|status| {
+                    source.is_none_or(|source| {
+                        matches!(
+                            (status.source, source),
+                            (CertificateSource::Static, "static")
+                                | (CertificateSource::Acme, "acme")
+                        )
+                    }) && domain
+                        .as_ref()
+                        .is_none_or(|domain| status.domains.iter().any(|d| d.contains(domain)))
+                        && ((before.is_none() && after.is_none())
+                            || status.not_after.as_ref().is_some_and(|not_after| {
+                                let not_after = not_after.to_timestamp() as u64;
+                                before.is_none_or(|before| not_after < before)
+                                    && after.is_none_or(|after| not_after > after)
+                            }))
+                });
+                let total = certificates.len();
+                let items = certificates
+                    .into_iter()
+                    .filter(|status| {
+                        after_domains
+                            .as_ref()
+                            .is_none_or(|after_domains| &status.domains > after_domains)
+                    })
+                    .take(if paging.limit > 0 {
+                        paging.limit
+                    } else {
+                        usize::MAX
+                    })
+                    .collect::<Vec<_>>();
+                let cursor = paging.next_cursor(CursorScope::Certificate, items.len(), || {
+                    items
+                        .last()
+                        .map(|status| status.domains.join(",").into_bytes())
+                });
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": paging.select(json!(items)),
+                        "total": total,
+                        "cursor": cursor,
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some("acme"), Some(provider_id), Some("renew"), None, &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsReload)?;
+
+                let provider_id = decode_path_element(provider_id);
+                let provider = self
+                    .core
+                    .acme
+                    .providers
+                    .get(provider_id.as_ref())
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                trc::event!(
+                    Acme(trc::AcmeEvent::OrderStart),
+                    Id = provider.id.to_string(),
+                    Hostname = provider.domains.as_slice()
+                );
+                let renew_at = self.renew_now(provider).await?;
+
+                // Reschedule the renewal, staple an OCSP response to the new certificate
+                // and have the other nodes load it
+                for event in [
+                    HousekeeperEvent::AcmeReschedule {
+                        provider_id: provider.id.clone(),
+                        renew_at: Instant::now() + renew_at,
+                    },
+                    HousekeeperEvent::OcspReschedule {
+                        refresh_at: Instant::now(),
+                    },
+                ] {
+                    self.inner.ipc.housekeeper_tx.send(event).await.ok();
+                }
+                self.cluster_broadcast(BroadcastEvent::ReloadSettings).await;
+
+                let status = certificate_status(self)
+                    .into_iter()
+                    .find(|status| status.acme_provider.as_ref() == Some(&provider.id));
+                trc::event!(
+                    Acme(trc::AcmeEvent::OrderCompleted),
+                    Domain = provider.domains.as_slice(),
+                    Expires = trc::Value::Timestamp(
+                        status
+                            .as_ref()
+                            .and_then(|status| status.not_after.as_ref())
+                            .map_or(0, |not_after| not_after.to_timestamp() as u64)
+                    )
+                );
+
+                Ok(JsonResponse::new(json!({
+                    "data": status,
+                }))
+                .into_http_response())
+            }
+            (Some("acme"), Some(provider_id), Some("excluded"), None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsList)?;
+
+                let provider_id = decode_path_element(provider_id);
+                let provider = self
+                    .core
+                    .acme
+                    .providers
+                    .get(provider_id.as_ref())
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.acme_excluded_domains(provider).await?,
+                }))
+                .into_http_response())
+            }
+            (Some("acme"), Some(provider_id), Some("excluded"), Some(domain), &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SettingsReload)?;
+
+                let provider_id = decode_path_element(provider_id);
+                let provider = self
+                    .core
+                    .acme
+                    .providers
+                    .get(provider_id.as_ref())
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                // The domain is included again on the next renewal
+                if self
+                    .acme_include_domain(provider, decode_path_element(domain).as_ref())
+                    .await?
+                {
+                    Ok(JsonResponse::new(json!({
+                        "data": (),
+                    }))
+                    .into_http_response())
+                } else {
+                    Err(trc::ResourceEvent::NotFound.into_err())
+                }
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+fn certificate_status(server: &Server) -> Vec<CertificateStatus> {
+    // Group the names served by each certificate
+    let mut certificates: Vec<(Arc<_>, Vec<String>)> = Vec::new();
+    for (name, cert) in server.inner.data.tls_certificates.load().iter() {
+        if let Some((_, names)) = certificates
+            .iter_mut()
+            .find(|(other, _)| Arc::ptr_eq(other, cert))
+        {
+            names.push(name.clone());
+        } else {
+            certificates.push((cert.clone(), vec![name.clone()]));
+        }
+    }
+
+    let mut result = Vec::with_capacity(certificates.len());
+    for (cert, mut domains) in certificates {
+        domains.sort_unstable();
+        let mut status = CertificateStatus {
+            domains,
+            source: CertificateSource::Static,
+            acme_provider: None,
+            subject: None,
+            issuer: None,
+            not_before: None,
+            not_after: None,
+            renew_at: None,
+        };
+
+        if let Some(Ok((_, parsed))) = cert.cert.first().map(|cert| parse_x509_certificate(cert)) {
+            let validity = parsed.validity();
+            status.subject = parsed.subject().to_string().into();
+            status.issuer = parsed.issuer().to_string().into();
+            status.not_before = DateTime::from_timestamp(validity.not_before.timestamp()).into();
+            status.not_after = DateTime::from_timestamp(validity.not_after.timestamp()).into();
+        }
+
+        result.push((Some(cert), status));
+    }
+
+    // ACME certificates are stored under each of the provider's domains, wildcards as ".domain"
+    for provider in server.core.acme.providers.values() {
+        let renew_at = provider
+            .renewal_due()
+            .map(|renew_at| DateTime::from_timestamp(renew_at as i64));
+        let current = provider.domains.first().and_then(|domain| {
+            let name = domain.strip_prefix('*').unwrap_or(domain.as_str());
+            let certificates = server.inner.data.tls_certificates.load();
+            match provider.listeners.first() {
+                Some(listener_id) => certificates.get(&listener_certificate_key(listener_id, name)),
+                None => certificates.get(name),
+            }
+            .cloned()
+        });
+
+        if let Some((_, status)) = current.and_then(|current| {
+            result.iter_mut().find(|(cert, _)| {
+                cert.as_ref()
+                    .is_some_and(|cert| Arc::ptr_eq(cert, &current))
+            })
+        }) {
+            status.source = CertificateSource::Acme;
+            status.acme_provider = provider.id.clone().into();
+            status.renew_at = renew_at;
+        } else {
+            // No certificate has been issued yet
+            result.push((
+                None,
+                CertificateStatus {
+                    domains: provider.domains.clone(),
+                    source: CertificateSource::Acme,
+                    acme_provider: provider.id.clone().into(),
+                    subject: None,
+                    issuer: None,
+                    not_before: None,
+                    not_after: None,
+                    renew_at,
+                },
+            ));
+        }
+    }
+
+    let mut result = result
+        .into_iter()
+        .map(|(_, status)| status)
+        .collect::<Vec<_>>();
+    result.sort_unstable_by(|a, b| a.domains.cmp(&b.domains));
+    result
+}
@@ -14,7 +14,13 @@ use common::{
     Server,
     auth::{AccessToken, oauth::GrantType},
     config::smtp::resolver::{Policy, Tlsa},
-    psl,
+    expr::{
+        VARIABLES_MAP, Variable,
+        eval::FunctionCall,
+        functions::ResolveVariable,
+        parser::ExpressionParser,
+        tokenizer::{TokenMap, Tokenizer},
+    },
 };
 use directory::backend::internal::manage;
 use http_body_util::{StreamBody, combinators::BoxBody};
@@ -31,14 +37,18 @@ use mail_auth::{
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use smtp::outbound::{
-    client::{SmtpClient, StartTlsResult},
-    dane::{dnssec::TlsaLookup, verify::TlsaVerify},
-    lookup::{DnsLookup, ToNextHop},
-    mta_sts::{lookup::MtaStsLookup, verify::VerifyPolicy},
+use smtp::{
+    core::load_test::{LoadTestGenerator, LoadTestRequest},
+    outbound::{
+        client::{SmtpClient, StartTlsResult},
+        dane::{dnssec::TlsaLookup, verify::TlsaVerify},
+        lookup::{DnsLookup, ToNextHop},
+        mta_sts::{lookup::MtaStsLookup, verify::VerifyPolicy},
+    },
 };
+use store::ahash::AHashMap;
 use tokio::{io::AsyncWriteExt, sync::mpsc};
-use utils::url_params::UrlParams;
+use utils::{suffixlist::org_domain, url_params::UrlParams};
 
 use http_proto::{request::decode_path_element, *};
 
@@ -118,6 +128,45 @@ impl TroubleshootApi for Server {
                 }))
                 .into_http_response())
             }
+            ("expression", None, &Method::POST) => {
+                let request = serde_json::from_slice::<ExpressionTroubleshootRequest>(
+                    body.as_deref().unwrap_or_default(),
+                )
+                .map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters).from_json_error(err)
+                })?;
+
+                Ok(JsonResponse::new(json!({
+                        "data": expression_troubleshoot(self, request).await,
+                }))
+                .into_http_response())
+            }
+            ("load-test", None, &Method::POST) => {
+                if !self.core.smtp.load_test.enable {
+                    return Err(manage::error(
+                        "Test mode is not enabled",
+                        "Set test-mode.enable to true to run load tests".into(),
+                    ));
+                }
+
+                let request = serde_json::from_slice::<LoadTestRequest>(
+                    body.as_deref()
+                        .filter(|body| !body.is_empty())
+                        .unwrap_or(b"{}"),
+                )
+                .map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters).from_json_error(err)
+                })?;
+
+                Ok(JsonResponse::new(json!({
+                        "data": self.run_load_test(request).await?,
+                }))
+                .into_http_response())
+            }
+            ("load-test", None, &Method::GET) => Ok(JsonResponse::new(json!({
+                    "data": self.inner.data.load_test.report(),
+            }))
+            .into_http_response()),
             _ => Err(trc::ResourceEvent::NotFound.into_err()),
         }
     }
@@ -812,6 +861,84 @@ async fn delivery_troubleshoot(
     Ok(())
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct ExpressionTroubleshootRequest {
+    expression: String,
+    #[serde(default)]
+    variables: AHashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ExpressionTroubleshootResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    calls: Vec<FunctionCall>,
+    elapsed: u64,
+}
+
+struct ExpressionVariables(AHashMap<u32, String>);
+
+impl ResolveVariable for ExpressionVariables {
+    fn resolve_variable(&self, variable: u32) -> Variable<'_> {
+        self.0
+            .get(&variable)
+            .map(|value| Variable::from(value.as_str()))
+            .unwrap_or_default()
+    }
+
+    fn resolve_global(&self, _: &str) -> Variable<'_> {
+        Variable::Integer(0)
+    }
+}
+
+async fn expression_troubleshoot(
+    server: &Server,
+    request: ExpressionTroubleshootRequest,
+) -> ExpressionTroubleshootResponse {
+    let now = Instant::now();
+    let token_map = TokenMap::default()
+        .with_variables(&VARIABLES_MAP.iter().map(|(_, id)| *id).collect::<Vec<_>>());
+    let expr = match ExpressionParser::new(Tokenizer::new(&request.expression, &token_map)).parse()
+    {
+        Ok(expr) => expr,
+        Err(err) => {
+            return ExpressionTroubleshootResponse {
+                result: None,
+                error: err.into(),
+                calls: vec![],
+                elapsed: now.elapsed().as_millis() as u64,
+            };
+        }
+    };
+    let variables = ExpressionVariables(
+        request
+            .variables
+            .into_iter()
+            .filter_map(|(name, value)| {
+                VARIABLES_MAP
+                    .iter()
+                    .find(|(var_name, _)| *var_name == name)
+                    .map(|(_, id)| (*id, value))
+            })
+            .collect(),
+    );
+
+    let (result, calls) = server.eval_expr_with_trace(&expr, &variables, 0).await;
+    let (result, error) = match result {
+        Ok(result) => (result.to_string().to_string().into(), None),
+        Err(err) => (None, err.to_string().into()),
+    };
+
+    ExpressionTroubleshootResponse {
+        result,
+        error,
+        calls,
+        elapsed: now.elapsed().as_millis() as u64,
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct DmarcTroubleshootRequest {
     #[serde(rename = "remoteIp")]
@@ -974,7 +1101,7 @@ async fn dmarc_troubleshoot(
             dkim_output: &dkim_output,
             rfc5321_mail_from_domain: mail_from_domain.unwrap_or(ehlo_domain.as_str()),
             spf_output: &mail_spf_output,
-            domain_suffix_fn: |domain| psl::domain_str(domain).unwrap_or(domain),
+            domain_suffix_fn: org_domain,
         }))
         .await;
     let dmarc_pass = matches!(dmarc_output.spf_result(), DmarcResult::Pass)
@@ -0,0 +1,130 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::Server;
+use http_proto::*;
+use hyper::{Method, StatusCode};
+use mail_parser::DateTime;
+use smtp::queue::quarantine::{QuarantineRelease, QuarantinedMessage};
+
+pub trait QuarantineHandler: Sync + Send {
+    fn handle_quarantine_release(
+        &self,
+        req: &HttpRequest,
+        session: &HttpSessionData,
+        token: &str,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl QuarantineHandler for Server {
+    async fn handle_quarantine_release(
+        &self,
+        req: &HttpRequest,
+        session: &HttpSessionData,
+        token: &str,
+    ) -> trc::Result<HttpResponse> {
+        // Release links are opened without logging in
+        self.is_http_anonymous_request_allowed(&session.remote_ip)
+            .await?;
+
+        // Links are confirmed with a POST so that URL scanners cannot release messages
+        let result = match *req.method() {
+            Method::GET => self
+                .quarantined_message(token)
+                .await
+                .map(|message| confirm_page(&message)),
+            Method::POST => self
+                .release_quarantined_message(token, session.session_id)
+                .await
+                .map(|message| released_page(&message)),
+            _ => return Err(trc::ResourceEvent::NotFound.into_err()),
+        };
+
+        Ok(result
+            .unwrap_or_else(|err| {
+                let status = match err.event_type() {
+                    trc::EventType::Resource(trc::ResourceEvent::NotFound) => StatusCode::NOT_FOUND,
+                    trc::EventType::Auth(trc::AuthEvent::TokenExpired)
+                    | trc::EventType::Security(trc::SecurityEvent::Unauthorized) => {
+                        StatusCode::GONE
+                    }
+                    trc::EventType::Queue(trc::QueueEvent::Locked) => {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    }
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                let details = err
+                    .value_as_str(trc::Key::Details)
+                    .unwrap_or("The release link is invalid")
+                    .to_string();
+
+                trc::error!(err.span_id(session.session_id));
+
+                HtmlResponse::with_status(status, page("Message not released", &details, None))
+            })
+            .into_http_response()
+            .with_no_store())
+    }
+}
+
+fn confirm_page(message: &QuarantinedMessage) -> HtmlResponse {
+    HtmlResponse::new(page(
+        "Release quarantined message",
+        &format!(
+            "The message from {} to {} is held in quarantine. Only release it if you trust the sender. This link expires on {}.",
+            message.from,
+            message.rcpt,
+            DateTime::from_timestamp(message.expires as i64).to_rfc822()
+        ),
+        Some("Release message"),
+    ))
+}
+
+fn released_page(message: &QuarantinedMessage) -> HtmlResponse {
+    HtmlResponse::new(page(
+        "Message released",
+        &format!(
+            "The message from {} was released and will be delivered to {} shortly.",
+            message.from, message.rcpt
+        ),
+        None,
+    ))
+}
+
+fn page(title: &str, text: &str, action: Option<&str>) -> String {
+    let mut html = String::with_capacity(512);
+    html.push_str("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">");
+    html.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">");
+    html.push_str("<title>");
+    escape_html(title, &mut html);
+    html.push_str("</title></head><body><h1>");
+    escape_html(title, &mut html);
+    html.push_str("</h1><p>");
+    escape_html(text, &mut html);
+    html.push_str("</p>");
+    if let Some(action) = action {
+        html.push_str("<form method=\"post\"><button type=\"submit\">");
+        escape_html(action, &mut html);
+        html.push_str("</button></form>");
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn escape_html(text: &str, html: &mut String) {
+    for ch in text.chars() {
+        match ch {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            _ => html.push(ch),
+        }
+    }
+}
@@ -4,18 +4,24 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{net::IpAddr, sync::Arc};
+use std::{
+    net::IpAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 
 use common::{
     Inner, KV_ACME, Server,
     auth::{AccessToken, oauth::GrantType},
     core::BuildServer,
     ipc::StateEvent,
-    listener::{SessionData, SessionManager, SessionStream},
+    listener::{SessionData, SessionManager, SessionStream, acme::http_challenge_key},
     manager::webadmin::Resource,
 };
 use dav::{DavMethod, request::DavRequestHandler};
-use directory::Permission;
+use directory::{Permission, core::protocols::Protocol};
 use groupware::DavResourceName;
 use http_proto::{
     DownloadResponse, HttpContext, HttpRequest, HttpResponse, HttpResponseBody, HttpSessionData,
@@ -24,16 +30,19 @@ use http_proto::{
 use hyper::{
     Method, StatusCode, body,
     header::{self, CONTENT_TYPE},
-    server::conn::http1,
     service::service_fn,
 };
-use hyper_util::rt::TokioIo;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto,
+};
 use jmap::{
     api::{
         ToJmapHttpResponse, event_source::EventSourceHandler, request::RequestHandler,
         session::SessionHandler,
     },
     blob::{download::BlobDownload, upload::BlobUpload},
+    email::proxy::RemoteContentProxy,
     websocket::upgrade::WebSocketUpgrade,
 };
 use jmap_proto::{
@@ -41,6 +50,7 @@ use jmap_proto::{
     types::{blob::BlobId, id::Id},
 };
 use store::dispatch::lookup::KeyValue;
+use tokio::sync::Notify;
 use trc::SecurityEvent;
 use utils::url_params::UrlParams;
 
@@ -48,6 +58,7 @@ use crate::{
     HttpSessionManager,
     auth::{
         authenticate::{Authenticator, HttpHeaders},
+        client_cert::{ClientCertificateGate, parse_client_certificate},
         oauth::{
             FormData, auth::OAuthApiHandler, openid::OpenIdHandler,
             registration::ClientRegistrationHandler, token::TokenHandler,
@@ -56,6 +67,7 @@ use crate::{
     autoconfig::Autoconfig,
     form::FormHandler,
     management::{ManagementApi, ToManageHttpResponse, troubleshoot::TroubleshootApi},
+    quarantine::QuarantineHandler,
 };
 
 pub trait ParseHttp: Sync + Send {
@@ -94,6 +106,7 @@ impl ParseHttp for Server {
                         // Authenticate request
                         let (_in_flight, access_token) =
                             self.authenticate_headers(&req, &session, false).await?;
+                        access_token.assert_protocol_enabled(Protocol::Jmap)?;
 
                         let request = fetch_body(
                             &mut req,
@@ -123,6 +136,7 @@ impl ParseHttp for Server {
                         // Authenticate request
                         let (_in_flight, access_token) =
                             self.authenticate_headers(&req, &session, false).await?;
+                        access_token.assert_protocol_enabled(Protocol::Jmap)?;
 
                         if let (Some(_), Some(blob_id), Some(name)) = (
                             path.next().and_then(|p| Id::from_bytes(p.as_bytes())),
@@ -148,10 +162,32 @@ impl ParseHttp for Server {
                             };
                         }
                     }
+                    ("proxy", &Method::GET) => {
+                        // Authenticate request
+                        let (_in_flight, access_token) =
+                            self.authenticate_headers(&req, &session, false).await?;
+                        access_token.assert_protocol_enabled(Protocol::Jmap)?;
+
+                        if let (Some(account_id), Some(signature), Some(url)) = (
+                            path.next().and_then(|p| Id::from_bytes(p.as_bytes())),
+                            path.next(),
+                            path.next(),
+                        ) {
+                            return self
+                                .remote_content_download(
+                                    account_id.document_id(),
+                                    signature,
+                                    url,
+                                    &access_token,
+                                )
+                                .await;
+                        }
+                    }
                     ("upload", &Method::POST) => {
                         // Authenticate request
                         let (_in_flight, access_token) =
                             self.authenticate_headers(&req, &session, false).await?;
+                        access_token.assert_protocol_enabled(Protocol::Jmap)?;
 
                         if let Some(account_id) =
                             path.next().and_then(|p| Id::from_bytes(p.as_bytes()))
@@ -176,6 +212,7 @@ impl ParseHttp for Server {
                                             .unwrap_or("application/octet-stream"),
                                         &bytes,
                                         access_token,
+                                        &session,
                                     )
                                     .await?
                                     .into_http_response()),
@@ -187,13 +224,15 @@ impl ParseHttp for Server {
                         // Authenticate request
                         let (_in_flight, access_token) =
                             self.authenticate_headers(&req, &session, false).await?;
+                        access_token.assert_protocol_enabled(Protocol::Jmap)?;
 
                         return self.handle_event_source(req, access_token).await;
                     }
-                    ("ws", &Method::GET) => {
+                    ("ws", &Method::GET | &Method::CONNECT) => {
                         // Authenticate request
                         let (_in_flight, access_token) =
                             self.authenticate_headers(&req, &session, false).await?;
+                        access_token.assert_protocol_enabled(Protocol::Jmap)?;
 
                         return self
                             .upgrade_websocket_connection(req, access_token, session)
@@ -229,6 +268,7 @@ impl ParseHttp for Server {
                         // Authenticate request
                         let (_in_flight, access_token) =
                             self.authenticate_headers(&req, &session, false).await?;
+                        access_token.assert_protocol_enabled(Protocol::Dav)?;
 
                         self.handle_dav_request(req, access_token, &session, resource, method)
                             .await
@@ -244,6 +284,7 @@ impl ParseHttp for Server {
                     // Authenticate request
                     let (_in_flight, access_token) =
                         self.authenticate_headers(&req, &session, false).await?;
+                    access_token.assert_protocol_enabled(Protocol::Jmap)?;
 
                     return self
                         .handle_session_resource(ctx.resolve_response_url(self).await, access_token)
@@ -275,12 +316,21 @@ impl ParseHttp for Server {
                     return self.handle_oidc_metadata(req, session).await;
                 }
                 ("acme-challenge", &Method::GET) if self.has_acme_http_providers() => {
-                    if let Some(token) = path.next() {
+                    if let Some(token) = path.next().filter(|token| {
+                        // Tokens are base64url encoded (RFC 8555, Section 8.3)
+                        !token.is_empty()
+                            && token
+                                .bytes()
+                                .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, b'-' | b'_'))
+                    }) {
                         return match self
                             .core
                             .storage
                             .lookup
-                            .key_get::<String>(KeyValue::<()>::build_key(KV_ACME, token))
+                            .key_get::<String>(KeyValue::<()>::build_key(
+                                KV_ACME,
+                                http_challenge_key(token),
+                            ))
                             .await?
                         {
                             Some(proof) => Ok(Resource::new("text/plain", proof.into_bytes())
@@ -376,9 +426,19 @@ impl ParseHttp for Server {
                     return Ok(JsonProblemResponse(StatusCode::NO_CONTENT).into_http_response());
                 }
 
+                // Some endpoints are only reachable from devices holding a client certificate
+                let requires_cert = self.requires_client_certificate(req.uri().path());
+                if requires_cert {
+                    self.assert_client_certificate(&session, None)?;
+                }
+
                 // Authenticate user
                 match self.authenticate_headers(&req, &session, true).await {
                     Ok((_, access_token)) => {
+                        if requires_cert {
+                            self.assert_client_certificate(&session, Some(&access_token))?;
+                        }
+
                         return self
                             .handle_api_manage_request(&mut req, access_token, &session)
                             .await;
@@ -570,6 +630,11 @@ impl ParseHttp for Server {
 
                 // SPDX-SnippetEnd
             }
+            "quarantine" => {
+                if let ("release", Some(token)) = (path.next().unwrap_or_default(), path.next()) {
+                    return self.handle_quarantine_release(&req, &session, token).await;
+                }
+            }
             "form" => {
                 if let Some(form) = &self.core.network.contact_form {
                     match *req.method() {
@@ -625,14 +690,64 @@ impl ParseHttp for Server {
 async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionData<T>) {
     let _in_flight = session.in_flight;
     let is_tls = session.stream.is_tls();
-
-    if let Err(http_err) = http1::Builder::new()
-        .keep_alive(true)
-        .serve_connection(
-            TokioIo::new(session.stream),
-            service_fn(|req: hyper::Request<body::Incoming>| {
-                let instance = session.instance.clone();
+    let client_cert = session
+        .stream
+        .peer_certificate()
+        .and_then(parse_client_certificate)
+        .map(Arc::new);
+    let server = inner.build_server();
+    let config = &server.core.jmap;
+
+    // Build HTTP/1.1 and HTTP/2 connection settings
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .keep_alive(config.http_keep_alive)
+        .timer(TokioTimer::new())
+        .header_read_timeout(config.http_keep_alive_timeout);
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(config.http2_max_concurrent_streams)
+        .keep_alive_interval(config.http2_keep_alive_interval)
+        .keep_alive_timeout(config.http2_keep_alive_timeout)
+        .enable_connect_protocol();
+    let builder = if session.stream.alpn_protocol() == Some(b"h2".as_slice()) {
+        builder.http2_only()
+    } else if !config.http2_enable {
+        builder.http1_only()
+    } else {
+        builder
+    };
+
+    // Close the connection gracefully once the maximum number of requests is reached
+    let max_requests = config.http_max_requests;
+    let num_requests = Arc::new(AtomicU64::new(0));
+    let max_requests_reached = Arc::new(Notify::new());
+
+    let connection = builder.serve_connection_with_upgrades(
+        TokioIo::new(session.stream),
+        service_fn({
+            let instance = session.instance;
+            let session_id = session.session_id;
+            let session_remote_ip = session.remote_ip;
+            let remote_port = session.remote_port;
+            let local_ip = session.local_ip;
+            let local_port = session.local_port;
+            let client_cert = client_cert.clone();
+            let num_requests = num_requests.clone();
+            let max_requests_reached = max_requests_reached.clone();
+
+            move |req: hyper::Request<body::Incoming>| {
+                let instance = instance.clone();
                 let inner = inner.clone();
+                let client_cert = client_cert.clone();
+
+                if max_requests.is_some_and(|max_requests| {
+                    num_requests.fetch_add(1, Ordering::Relaxed) + 1 >= max_requests
+                }) {
+                    max_requests_reached.notify_one();
+                }
 
                 async move {
                     let server = inner.build_server();
@@ -641,11 +756,11 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
                     let remote_ip = if !server.core.jmap.http_use_forwarded {
                         trc::event!(
                             Http(trc::HttpEvent::RequestUrl),
-                            SpanId = session.session_id,
+                            SpanId = session_id,
                             Url = req.uri().to_string(),
                         );
 
-                        session.remote_ip
+                        session_remote_ip
                     } else if let Some(forwarded_for) = req
                         .headers()
                         .get(header::FORWARDED)
@@ -689,7 +804,7 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
                                 Security(trc::SecurityEvent::IpBlocked),
                                 ListenerId = instance.id.clone(),
                                 RemoteIp = forwarded_for,
-                                SpanId = session.session_id,
+                                SpanId = session_id,
                             );
 
                             return Ok::<_, hyper::Error>(
@@ -701,18 +816,15 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
 
                         trc::event!(
                             Http(trc::HttpEvent::RequestUrl),
-                            SpanId = session.session_id,
+                            SpanId = session_id,
                             RemoteIp = forwarded_for,
                             Url = req.uri().to_string(),
                         );
 
                         forwarded_for
                     } else {
-                        trc::event!(
-                            Http(trc::HttpEvent::XForwardedMissing),
-                            SpanId = session.session_id,
-                        );
-                        session.remote_ip
+                        trc::event!(Http(trc::HttpEvent::XForwardedMissing), SpanId = session_id);
+                        session_remote_ip
                     };
 
                     // Parse HTTP request
@@ -720,12 +832,13 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
                         req,
                         HttpSessionData {
                             instance,
-                            local_ip: session.local_ip,
-                            local_port: session.local_port,
+                            local_ip,
+                            local_port,
                             remote_ip,
-                            remote_port: session.remote_port,
+                            remote_port,
                             is_tls,
-                            session_id: session.session_id,
+                            session_id,
+                            client_cert,
                         },
                     ))
                     .await
@@ -733,14 +846,14 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
                         Ok(response) => response,
                         Err(err) => {
                             let response = err.into_http_response();
-                            trc::error!(err.span_id(session.session_id));
+                            trc::error!(err.span_id(session_id));
                             response
                         }
                     };
 
                     trc::event!(
                         Http(trc::HttpEvent::ResponseBody),
-                        SpanId = session.session_id,
+                        SpanId = session_id,
                         Contents = match response.body() {
                             HttpResponseBody::Text(value) =>
                                 trc::Value::String(value.as_str().into()),
@@ -767,16 +880,21 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
 
                     Ok::<_, hyper::Error>(response)
                 }
-            }),
-        )
-        .with_upgrades()
-        .await
-    {
-        match inner
-            .build_server()
-            .is_scanner_fail2banned(session.remote_ip)
-            .await
-        {
+            }
+        }),
+    );
+    tokio::pin!(connection);
+
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = max_requests_reached.notified() => {
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    };
+
+    if let Err(http_err) = result {
+        match server.is_scanner_fail2banned(session.remote_ip).await {
             Ok(true) => {
                 trc::event!(
                     Security(SecurityEvent::ScanBan),
@@ -132,6 +132,7 @@ pub enum ResponseCode {
     ReadOnly,
     ReadWrite,
     ServerBug,
+    TooBig,
     TryCreate,
     UidNext,
     UidNotSticky,
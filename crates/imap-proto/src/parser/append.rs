@@ -330,7 +330,9 @@ mod tests {
                     );
                 }
                 Err(err) => match err {
-                    Error::NeedsMoreData | Error::NeedsLiteral { .. } => (),
+                    Error::NeedsMoreData
+                    | Error::NeedsLiteral { .. }
+                    | Error::AppendLiteral { .. } => (),
                     Error::Error { response } => panic!("{:?}", response),
                 },
             }
@@ -87,6 +87,11 @@ impl CommandParser for Command {
     fn tokenize_brackets(&self) -> bool {
         matches!(self, Command::Fetch(_))
     }
+
+    #[inline(always)]
+    fn is_append(&self) -> bool {
+        matches!(self, Command::Append)
+    }
 }
 
 impl Flag {
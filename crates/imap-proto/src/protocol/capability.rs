@@ -22,6 +22,7 @@ pub enum Capability {
     Id,
     Children,
     MultiAppend,
+    AppendLimit(u64), //APPENDLIMIT=
     Binary,
     Unselect,
     ACL,
@@ -42,7 +43,8 @@ pub enum Capability {
     Move,
     CondStore,
     QResync,
-    LiteralPlus, //LITERAL+
+    LiteralPlus,  //LITERAL+
+    LiteralMinus, //LITERAL-
     UnAuthenticate,
     StatusSize, //STATUS=SIZE
     ObjectId,
@@ -87,6 +89,7 @@ impl Capability {
             Capability::CondStore => b"CONDSTORE",
             Capability::QResync => b"QRESYNC",
             Capability::LiteralPlus => b"LITERAL+",
+            Capability::LiteralMinus => b"LITERAL-",
             Capability::UnAuthenticate => b"UNAUTHENTICATE",
             Capability::StatusSize => b"STATUS=SIZE",
             Capability::ObjectId => b"OBJECTID",
@@ -96,6 +99,11 @@ impl Capability {
             Capability::Id => b"ID",
             Capability::Children => b"CHILDREN",
             Capability::MultiAppend => b"MULTIAPPEND",
+            Capability::AppendLimit(limit) => {
+                buf.extend_from_slice(b"APPENDLIMIT=");
+                buf.extend_from_slice(limit.to_string().as_bytes());
+                return;
+            }
             Capability::Binary => b"BINARY",
             Capability::Unselect => b"UNSELECT",
             Capability::ACL => b"ACL",
@@ -131,13 +139,25 @@ impl Capability {
         });
     }
 
-    pub fn all_capabilities(is_authenticated: bool, offer_tls: bool) -> Vec<Capability> {
+    pub fn name(&self) -> String {
+        let mut buf = Vec::with_capacity(16);
+        self.serialize(&mut buf);
+        String::from_utf8(buf)
+            .unwrap_or_default()
+            .to_ascii_uppercase()
+    }
+
+    pub fn all_capabilities(
+        is_authenticated: bool,
+        offer_tls: bool,
+        append_limit: u64,
+    ) -> Vec<Capability> {
         let mut capabilities = vec![
             Capability::IMAP4rev2,
             Capability::IMAP4rev1,
             Capability::Enable,
             Capability::SASLIR,
-            Capability::LiteralPlus,
+            Capability::LiteralMinus,
             Capability::Id,
             Capability::Utf8Accept,
             Capability::JmapAccess,
@@ -149,6 +169,7 @@ impl Capability {
                 Capability::Namespace,
                 Capability::Children,
                 Capability::MultiAppend,
+                Capability::AppendLimit(append_limit),
                 Capability::Binary,
                 Capability::Unselect,
                 Capability::ACL,
@@ -216,11 +237,17 @@ mod tests {
                 capabilities: vec![
                     Capability::IMAP4rev2,
                     Capability::StartTLS,
-                    Capability::LoginDisabled
+                    Capability::LoginDisabled,
+                    Capability::LiteralMinus,
+                    Capability::AppendLimit(1024)
                 ],
             }
             .serialize(),
-            concat!("* CAPABILITY IMAP4rev2 STARTTLS LOGINDISABLED\r\n",).as_bytes()
+            concat!(
+                "* CAPABILITY IMAP4rev2 STARTTLS LOGINDISABLED ",
+                "LITERAL- APPENDLIMIT=1024\r\n"
+            )
+            .as_bytes()
         );
     }
 }
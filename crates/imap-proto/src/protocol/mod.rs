@@ -379,6 +379,7 @@ impl ResponseCode {
             ResponseCode::ReadOnly => b"READ-ONLY",
             ResponseCode::ReadWrite => b"READ-WRITE",
             ResponseCode::ServerBug => b"SERVERBUG",
+            ResponseCode::TooBig => b"TOOBIG",
             ResponseCode::TryCreate => b"TRYCREATE",
             ResponseCode::UidNext => b"UIDNEXT",
             ResponseCode::UidNotSticky => b"UIDNOTSTICKY",
@@ -435,6 +436,7 @@ impl ResponseCode {
             ResponseCode::ReadOnly => "READ-ONLY",
             ResponseCode::ReadWrite => "READ-WRITE",
             ResponseCode::ServerBug => "SERVERBUG",
+            ResponseCode::TooBig => "TOOBIG",
             ResponseCode::TryCreate => "TRYCREATE",
             ResponseCode::UidNext => "UIDNEXT",
             ResponseCode::UidNotSticky => "UIDNOTSTICKY",
@@ -523,10 +525,16 @@ impl SerializeResponse for trc::Error {
                 trc::EventType::Store(trc::StoreEvent::NotFound) => {
                     Some(ResponseCode::NonExistent.as_str())
                 }
+                trc::EventType::Store(trc::StoreEvent::QueryTimeout) => {
+                    Some(ResponseCode::Unavailable.as_str())
+                }
                 trc::EventType::Store(_) => Some(ResponseCode::ContactAdmin.as_str()),
                 trc::EventType::Limit(trc::LimitEvent::Quota) => {
                     Some(ResponseCode::OverQuota.as_str())
                 }
+                trc::EventType::Limit(trc::LimitEvent::Maintenance) => {
+                    Some(ResponseCode::Unavailable.as_str())
+                }
                 trc::EventType::Limit(_) => Some(ResponseCode::Limit.as_str()),
                 trc::EventType::Auth(_) => Some(ResponseCode::AuthenticationFailed.as_str()),
                 trc::EventType::Security(_) => Some(ResponseCode::AuthorizationFailed.as_str()),
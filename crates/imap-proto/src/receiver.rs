@@ -10,10 +10,14 @@ use compact_str::{CompactString, format_compact};
 
 use super::{ResponseCode, ResponseType};
 
+// LITERAL- (RFC 7888) limit for non-synchronizing literals outside APPEND
+pub const MAX_NON_SYNC_LITERAL: u32 = 4096;
+
 #[derive(Debug, Clone)]
 pub enum Error {
     NeedsMoreData,
     NeedsLiteral { size: u32 },
+    AppendLiteral { size: u32, non_sync: bool },
     Error { response: trc::Error },
 }
 
@@ -27,6 +31,7 @@ pub struct Request<T: CommandParser> {
 pub trait CommandParser: Sized + Default {
     fn parse(bytes: &[u8], is_uid: bool) -> Option<Self>;
     fn tokenize_brackets(&self) -> bool;
+    fn is_append(&self) -> bool;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -62,6 +67,7 @@ pub enum State {
     Literal { non_sync: bool },
     LiteralSeek { size: u32, non_sync: bool },
     LiteralData { remaining: u32 },
+    Discard { remaining: u32 },
 }
 
 pub struct Receiver<T: CommandParser> {
@@ -70,6 +76,8 @@ pub struct Receiver<T: CommandParser> {
     pub state: State,
     pub max_request_size: usize,
     pub current_request_size: usize,
+    pub max_non_sync_size: u32,
+    pub check_append_literals: bool,
     pub start_state: State,
 }
 
@@ -94,6 +102,16 @@ impl<T: CommandParser> Receiver<T> {
         }
     }
 
+    pub fn with_max_non_sync_size(mut self, max_non_sync_size: u32) -> Self {
+        self.max_non_sync_size = max_non_sync_size;
+        self
+    }
+
+    pub fn with_append_checks(mut self) -> Self {
+        self.check_append_literals = true;
+        self
+    }
+
     pub fn error_reset(&mut self, message: impl Into<trc::Value>) -> Error {
         let request = std::mem::take(&mut self.request);
         let err = Error::err(
@@ -110,6 +128,42 @@ impl<T: CommandParser> Receiver<T> {
         err
     }
 
+    // Drops an APPEND request after its announced literal was rejected,
+    // non-synchronizing literals are already on their way and get discarded.
+    pub fn reject_literal(&mut self, non_sync: bool) -> Request<T> {
+        let request = std::mem::take(&mut self.request);
+        self.state = match self.state {
+            State::LiteralData { remaining } if non_sync => State::Discard { remaining },
+            _ if non_sync => State::Discard { remaining: 0 },
+            _ => self.start_state,
+        };
+        self.buf = Vec::with_capacity(10);
+        self.current_request_size = 0;
+        request
+    }
+
+    fn is_checked_append(&self) -> bool {
+        self.check_append_literals && self.request.command.is_append()
+    }
+
+    fn discard_literal(&mut self, size: u32, message: impl Into<trc::Value>) -> Error {
+        let request = std::mem::take(&mut self.request);
+        self.buf = Vec::with_capacity(10);
+        self.state = State::Discard { remaining: size };
+        self.current_request_size = 0;
+        Error::Error {
+            response: trc::ImapEvent::Error
+                .ctx(trc::Key::Details, message)
+                .ctx_opt(
+                    trc::Key::Id,
+                    (!request.tag.is_empty())
+                        .then(|| CompactString::from_string_buffer(request.tag)),
+                )
+                .ctx(trc::Key::Type, ResponseType::Bad)
+                .code(ResponseCode::TooBig),
+        }
+    }
+
     fn push_argument(&mut self, in_quote: bool) -> Result<(), Error> {
         if !self.buf.is_empty() {
             self.current_request_size += self.buf.len();
@@ -315,7 +369,10 @@ impl<T: CommandParser> Receiver<T> {
                                     .map_err(|_| {
                                     self.error_reset("Literal size is not a valid number.")
                                 })?;
-                                if self.current_request_size + size as usize > self.max_request_size
+                                if !non_sync
+                                    && !self.is_checked_append()
+                                    && self.current_request_size + size as usize
+                                        > self.max_request_size
                                 {
                                     return Err(self.error_reset(format_compact!(
                                         "Literal exceeds the maximum request size of {} bytes.",
@@ -354,13 +411,38 @@ impl<T: CommandParser> Receiver<T> {
                 }
                 State::LiteralSeek { size, non_sync } => {
                     if ch == b'\n' {
+                        // Oversized non-synchronizing literals are already in flight and get
+                        // discarded, checked APPEND literals are validated by the session instead
+                        if non_sync && !self.is_checked_append() {
+                            if self.current_request_size + size as usize > self.max_request_size {
+                                return Err(self.discard_literal(
+                                    size,
+                                    format_compact!(
+                                        "Literal exceeds the maximum request size of {} bytes.",
+                                        self.max_request_size
+                                    ),
+                                ));
+                            } else if size > self.max_non_sync_size
+                                && !self.request.command.is_append()
+                            {
+                                return Err(self.discard_literal(
+                                    size,
+                                    format_compact!(
+                                        "Non-synchronizing literals are limited to {} bytes.",
+                                        self.max_non_sync_size
+                                    ),
+                                ));
+                            }
+                        }
                         if size > 0 {
                             self.state = State::LiteralData { remaining: size };
                         } else {
                             self.state = State::Argument { last_ch: b' ' };
                             self.push_token(Token::Nil)?;
                         }
-                        if !non_sync {
+                        if self.is_checked_append() {
+                            return Err(Error::AppendLiteral { size, non_sync });
+                        } else if !non_sync {
                             return Err(Error::NeedsLiteral { size });
                         }
                     } else if !ch.is_ascii_whitespace() {
@@ -380,6 +462,37 @@ impl<T: CommandParser> Receiver<T> {
                         self.state = State::Argument { last_ch: b' ' };
                     }
                 }
+                State::Discard { remaining } => {
+                    if remaining > 0 {
+                        let skip = std::cmp::min(remaining as usize - 1, bytes.len());
+                        if skip > 0 {
+                            bytes.nth(skip - 1);
+                        }
+                        self.state = State::Discard {
+                            remaining: remaining - 1 - skip as u32,
+                        };
+                    } else if ch == b'\n' {
+                        // Skip any further non-synchronizing literals in the same command
+                        let next_literal = self
+                            .buf
+                            .strip_prefix(b"{")
+                            .and_then(|literal| literal.trim_ascii_end().strip_suffix(b"+}"))
+                            .and_then(|size| std::str::from_utf8(size).ok())
+                            .and_then(|size| size.parse::<u32>().ok());
+                        self.buf.clear();
+                        self.state = match next_literal {
+                            Some(remaining) => State::Discard { remaining },
+                            None => self.start_state,
+                        };
+                    } else {
+                        if ch == b'{' {
+                            self.buf.clear();
+                        }
+                        if self.buf.len() < 16 {
+                            self.buf.push(ch);
+                        }
+                    }
+                }
             }
         }
 
@@ -490,6 +603,8 @@ impl<T: CommandParser> Default for Receiver<T> {
             start_state: State::Start,
             max_request_size: 25 * 1024 * 1024,
             current_request_size: 0,
+            max_non_sync_size: u32::MAX,
+            check_append_literals: false,
         }
     }
 }
@@ -1032,4 +1147,51 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn receiver_literal_limits() {
+        let mut receiver = Receiver::<Command>::new()
+            .with_max_non_sync_size(super::MAX_NON_SYNC_LITERAL)
+            .with_append_checks();
+
+        // Oversized non-synchronizing literals are discarded
+        let frame = format!(
+            "a001 LOGIN {{5000+}}\r\n{} {{10+}}\r\n0123456789\r\na002 NOOP\r\n",
+            "a".repeat(5000)
+        );
+        let mut bytes = frame.as_bytes().iter();
+        match receiver.parse(&mut bytes) {
+            Err(Error::Error { response }) => {
+                assert_eq!(response.value_as_str(trc::Key::Id), Some("a001"));
+                assert_eq!(response.value_as_str(trc::Key::Code), Some("TOOBIG"));
+            }
+            result => panic!("Expected error, got: {:?}", result),
+        }
+        assert_eq!(receiver.parse(&mut bytes).unwrap().tag, "a002");
+
+        // APPEND literals are announced before being received
+        let mut bytes = b"a003 APPEND INBOX {5000+}\r\n".iter();
+        match receiver.parse(&mut bytes) {
+            Err(Error::AppendLiteral {
+                size: 5000,
+                non_sync: true,
+            }) => {}
+            result => panic!("Expected append literal, got: {:?}", result),
+        }
+        assert_eq!(receiver.reject_literal(true).tag, "a003");
+        let frame = format!("{}\r\na004 APPEND INBOX {{10}}\r\n", "a".repeat(5000));
+        let mut bytes = frame.as_bytes().iter();
+        match receiver.parse(&mut bytes) {
+            Err(Error::AppendLiteral {
+                size: 10,
+                non_sync: false,
+            }) => {}
+            result => panic!("Expected append literal, got: {:?}", result),
+        }
+        assert_eq!(receiver.reject_literal(false).tag, "a004");
+        assert_eq!(
+            receiver.parse(&mut b"a005 NOOP\r\n".iter()).unwrap().tag,
+            "a005"
+        );
+    }
 }
@@ -10,11 +10,13 @@ use common::{
     KV_RATE_LIMIT_IMAP,
     listener::{SessionResult, SessionStream},
 };
+use directory::core::protocols::Protocol;
 use imap_proto::{
-    Command, ResponseType, StatusResponse,
+    Command, ResponseCode, ResponseType, StatusResponse,
     receiver::{self, Request},
+    utf7::utf7_maybe_decode,
 };
-use trc::SecurityEvent;
+use trc::{AddContext, SecurityEvent};
 
 use super::{SelectedMailbox, Session, SessionData, State};
 
@@ -27,6 +29,20 @@ impl<T: SessionStream> Session<T> {
             Contents = trc::Value::from_maybe_string(bytes),
         );
 
+        // Terminate the session if IMAP was disabled for this account
+        if let State::Authenticated { data } | State::Selected { data, .. } = &self.state {
+            if self
+                .server
+                .is_protocol_revoked(data.account_id, Protocol::Imap, self.session_id)
+                .await
+            {
+                self.write_bytes(&b"* BYE protocol disabled for this account\r\n"[..])
+                    .await
+                    .ok();
+                return SessionResult::Close;
+            }
+        }
+
         let mut bytes = bytes.iter();
         let mut requests = Vec::with_capacity(2);
         let mut needs_literal = None;
@@ -50,6 +66,21 @@ impl<T: SessionStream> Session<T> {
                     needs_literal = size.into();
                     break;
                 }
+                Err(receiver::Error::AppendLiteral { size, non_sync }) => {
+                    match self.check_append_literal(size).await {
+                        Ok(_) if !non_sync => {
+                            needs_literal = size.into();
+                            break;
+                        }
+                        Ok(_) => {}
+                        Err(err) => {
+                            let request = self.receiver.reject_literal(non_sync);
+                            if !self.write_error(err.id(request.tag)).await {
+                                return SessionResult::Close;
+                            }
+                        }
+                    }
+                }
                 Err(receiver::Error::Error { response }) => {
                     // Check for port scanners
                     if matches!(
@@ -291,6 +322,54 @@ pub fn group_requests(
 }
 
 impl<T: SessionStream> Session<T> {
+    // Rejects oversized APPEND literals before the client sends them
+    async fn check_append_literal(&self, size: u32) -> trc::Result<()> {
+        let max_size = self.server.core.imap.max_request_size;
+        if self.receiver.current_request_size + size as usize > max_size {
+            return Err(trc::LimitEvent::SizeRequest
+                .into_err()
+                .details(format!(
+                    "Message exceeds the maximum append size of {max_size} bytes."
+                ))
+                .code(ResponseCode::TooBig));
+        }
+
+        // Quotas can only be checked once the destination mailbox is known
+        let (State::Authenticated { data } | State::Selected { data, .. }) = &self.state else {
+            return Ok(());
+        };
+        let Some(mailbox) = self
+            .receiver
+            .request
+            .tokens
+            .first()
+            .and_then(|token| token.clone().unwrap_string().ok())
+            .and_then(|name| data.get_mailbox_by_name(&utf7_maybe_decode(name, self.version)))
+        else {
+            return Ok(());
+        };
+        let access_token = self
+            .server
+            .get_access_token(mailbox.account_id)
+            .await
+            .caused_by(trc::location!())?;
+
+        self.server
+            .has_available_quota(&access_token.as_resource_token(), size as u64)
+            .await
+            .map_err(|err| {
+                if err.matches(trc::EventType::Limit(trc::LimitEvent::Quota)) {
+                    err.details("Disk quota exceeded.")
+                        .code(ResponseCode::OverQuota)
+                } else if err.matches(trc::EventType::Limit(trc::LimitEvent::TenantQuota)) {
+                    err.details("Organization disk quota exceeded.")
+                        .code(ResponseCode::OverQuota)
+                } else {
+                    err
+                }
+            })
+    }
+
     async fn is_allowed(&self, request: Request<Command>) -> trc::Result<Request<Command>> {
         let state = &self.state;
         // Rate limit request
@@ -316,7 +395,21 @@ impl<T: SessionStream> Session<T> {
         }
 
         match &request.command {
-            Command::Capability | Command::Noop | Command::Logout | Command::Id => Ok(request),
+            Command::Capability | Command::Noop | Command::Logout => Ok(request),
+            Command::Id => {
+                if !self
+                    .instance
+                    .banner
+                    .is_hidden("ID", self.is_tls, state.is_authenticated())
+                {
+                    Ok(request)
+                } else {
+                    Err(trc::ImapEvent::Error
+                        .into_err()
+                        .details("ID is not available.")
+                        .id(request.tag))
+                }
+            }
             Command::StartTls => {
                 if !self.is_tls {
                     if self.instance.acceptor.is_tls() {
@@ -346,7 +439,12 @@ impl<T: SessionStream> Session<T> {
             }
             Command::Login => {
                 if let State::NotAuthenticated { .. } = state {
-                    if self.is_tls || self.server.core.imap.allow_plain_auth {
+                    if (self.is_tls || self.server.core.imap.allow_plain_auth)
+                        && !self
+                            .instance
+                            .banner
+                            .is_hidden("AUTH=PLAIN", self.is_tls, false)
+                    {
                         Ok(request)
                     } else {
                         Err(trc::ImapEvent::Error
@@ -176,10 +176,12 @@ impl<T: SessionStream> SessionData<T> {
                         SpecialUse::Important => Some(Attribute::Important),
                         _ => None,
                     },
-                    total_messages: cache.in_mailbox(mailbox.document_id).count() as u64,
+                    total_messages: cache
+                        .mailbox_counters(mailbox.document_id)
+                        .map_or(0, |c| c.total_emails) as u64,
                     total_unseen: cache
-                        .in_mailbox_without_keyword(mailbox.document_id, &Keyword::Seen)
-                        .count() as u64,
+                        .mailbox_counters(mailbox.document_id)
+                        .map_or(0, |c| c.unread_emails) as u64,
                     total_deleted: cache
                         .in_mailbox_with_keyword(mailbox.document_id, &Keyword::Deleted)
                         .count() as u64,
@@ -12,13 +12,11 @@ use common::{
 };
 use imap_proto::{
     protocol::{ProtocolVersion, SerializeResponse},
-    receiver::Receiver,
+    receiver::{MAX_NON_SYNC_LITERAL, Receiver},
 };
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_rustls::server::TlsStream;
 
-use crate::{GREETING_WITH_TLS, GREETING_WITHOUT_TLS};
-
 use super::{ImapSessionManager, Session, State};
 
 impl SessionManager for ImapSessionManager {
@@ -121,13 +119,10 @@ impl<T: SessionStream> Session<T> {
     ) -> Result<Session<T>, ()> {
         // Write greeting
         let is_tls = session.stream.is_tls();
-        let greeting = if !is_tls && session.instance.acceptor.is_tls() {
-            &GREETING_WITH_TLS
-        } else {
-            &GREETING_WITHOUT_TLS
-        };
+        let server = manager.inner.build_server();
+        let greeting = crate::greeting(&server, &session.instance, is_tls);
 
-        if let Err(err) = session.stream.write_all(greeting).await {
+        if let Err(err) = session.stream.write_all(&greeting).await {
             trc::event!(
                 Network(trc::NetworkEvent::WriteError),
                 Reason = err.to_string(),
@@ -140,10 +135,11 @@ impl<T: SessionStream> Session<T> {
 
         // Split stream into read and write halves
         let (stream_rx, stream_tx) = tokio::io::split(session.stream);
-        let server = manager.inner.build_server();
 
         Ok(Session {
-            receiver: Receiver::with_max_request_size(server.core.imap.max_request_size),
+            receiver: Receiver::with_max_request_size(server.core.imap.max_request_size)
+                .with_max_non_sync_size(MAX_NON_SYNC_LITERAL)
+                .with_append_checks(),
             version: ProtocolVersion::Rev1,
             state: State::NotAuthenticated { auth_failures: 0 },
             is_tls,
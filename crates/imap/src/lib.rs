@@ -4,29 +4,59 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::sync::LazyLock;
+use std::borrow::Cow;
 
+use common::{Server, listener::ServerInstance};
 use imap_proto::{ResponseCode, StatusResponse, protocol::capability::Capability};
 
 pub mod core;
 pub mod op;
 
 static SERVER_GREETING: &str = "Stalwart IMAP4rev2 at your service.";
+static SERVER_GREETING_NO_PRODUCT: &str = "IMAP4rev2 at your service.";
 
-pub(crate) static GREETING_WITH_TLS: LazyLock<Vec<u8>> = LazyLock::new(|| {
-    StatusResponse::ok(SERVER_GREETING)
-        .with_code(ResponseCode::Capability {
-            capabilities: Capability::all_capabilities(false, true),
-        })
-        .into_bytes()
-});
+pub(crate) fn greeting(server: &Server, instance: &ServerInstance, is_tls: bool) -> Vec<u8> {
+    let greeting = instance
+        .banner
+        .greeting(
+            &server.core.network.server_name,
+            &instance.id,
+            instance.protocol,
+        )
+        .map(Cow::Owned)
+        .unwrap_or(Cow::Borrowed(if instance.banner.hide_product {
+            SERVER_GREETING_NO_PRODUCT
+        } else {
+            SERVER_GREETING
+        }));
 
-pub(crate) static GREETING_WITHOUT_TLS: LazyLock<Vec<u8>> = LazyLock::new(|| {
-    StatusResponse::ok(SERVER_GREETING)
+    StatusResponse::ok(greeting)
         .with_code(ResponseCode::Capability {
-            capabilities: Capability::all_capabilities(false, false),
+            capabilities: capabilities(server, instance, false, is_tls),
         })
         .into_bytes()
-});
+}
+
+pub(crate) fn capabilities(
+    server: &Server,
+    instance: &ServerInstance,
+    is_authenticated: bool,
+    is_tls: bool,
+) -> Vec<Capability> {
+    let mut capabilities = Capability::all_capabilities(
+        is_authenticated,
+        !is_tls && instance.acceptor.is_tls(),
+        server.core.imap.max_request_size as u64,
+    );
+    capabilities.retain(|capability| {
+        !instance
+            .banner
+            .is_hidden(&capability.name(), is_tls, is_authenticated)
+    });
+    if !is_authenticated && instance.banner.is_hidden("AUTH=PLAIN", is_tls, is_authenticated) {
+        capabilities.push(Capability::LoginDisabled);
+    }
+    capabilities
+}
 
 pub struct ImapError;
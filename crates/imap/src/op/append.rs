@@ -93,8 +93,20 @@ impl<T: SessionStream> SessionData<T> {
             .get_access_token(mailbox.account_id)
             .await
             .imap_ctx(&arguments.tag, trc::location!())?;
+
+        // Accounts in maintenance are read-only
+        self.server
+            .assert_not_in_maintenance(&access_token)
+            .await
+            .map_err(|err| err.id(arguments.tag.clone()))?;
         let resource_token = access_token.as_resource_token();
         let spam_train = self.server.email_bayes_can_train(&access_token);
+        let is_shared = self.account_id != account_id
+            && !self
+                .get_access_token()
+                .await
+                .imap_ctx(&arguments.tag, trc::location!())?
+                .is_member(account_id);
 
         // Append messages
         let mut response = StatusResponse::completed(Command::Append);
@@ -110,7 +122,7 @@ impl<T: SessionStream> SessionData<T> {
                     mailbox_ids: vec![mailbox_id],
                     keywords: message.flags.into_iter().map(Keyword::from).collect(),
                     received_at: message.received_at.map(|d| d as u64),
-                    source: IngestSource::Imap,
+                    source: IngestSource::Imap { shared: is_shared },
                     spam_classify: false,
                     spam_train,
                     session_id: self.session_id,
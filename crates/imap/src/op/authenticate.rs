@@ -7,12 +7,12 @@
 use common::{
     auth::{
         AuthRequest,
-        sasl::{sasl_decode_challenge_oauth, sasl_decode_challenge_plain},
+        sasl::{sasl_decode_challenge_oauth, sasl_decode_challenge_plain_authzid},
     },
     listener::{SessionStream, limiter::LimiterResult},
 };
 
-use directory::Permission;
+use directory::{Permission, core::protocols::Protocol};
 use imap_proto::{
     Command, ResponseCode, StatusResponse,
     protocol::{authenticate::Mechanism, capability::Capability},
@@ -29,7 +29,13 @@ impl<T: SessionStream> Session<T> {
         let mut args = request.parse_authenticate()?;
 
         match args.mechanism {
-            Mechanism::Plain | Mechanism::OAuthBearer | Mechanism::XOauth2 => {
+            Mechanism::Plain | Mechanism::OAuthBearer | Mechanism::XOauth2
+                if !self.instance.banner.is_hidden(
+                    &Capability::Auth(args.mechanism.clone()).name(),
+                    self.is_tls,
+                    false,
+                ) =>
+            {
                 if !args.params.is_empty() {
                     let challenge = base64_decode(args.params.pop().unwrap().as_bytes())
                         .ok_or_else(|| {
@@ -40,10 +46,11 @@ impl<T: SessionStream> Session<T> {
                                 .code(ResponseCode::Parse)
                         })?;
 
-                    let credentials = if args.mechanism == Mechanism::Plain {
-                        sasl_decode_challenge_plain(&challenge)
+                    let (credentials, authzid) = if args.mechanism == Mechanism::Plain {
+                        sasl_decode_challenge_plain_authzid(&challenge)
                     } else {
                         sasl_decode_challenge_oauth(&challenge)
+                            .map(|credentials| (credentials, None))
                     }
                     .ok_or_else(|| {
                         trc::AuthEvent::Error
@@ -52,7 +59,7 @@ impl<T: SessionStream> Session<T> {
                             .id(args.tag.clone())
                     })?;
 
-                    self.authenticate(credentials, args.tag).await
+                    self.authenticate(credentials, authzid, args.tag).await
                 } else {
                     self.receiver.request = receiver::Request {
                         tag: args.tag,
@@ -74,10 +81,11 @@ impl<T: SessionStream> Session<T> {
     pub async fn authenticate(
         &mut self,
         credentials: Credentials<String>,
+        authzid: Option<String>,
         tag: String,
     ) -> trc::Result<()> {
         // Authenticate
-        let access_token = self
+        let mut access_token = self
             .server
             .authenticate(&AuthRequest::from_credentials(
                 credentials,
@@ -98,13 +106,23 @@ impl<T: SessionStream> Session<T> {
                 }
 
                 err.id(tag.clone())
-            })
-            .and_then(|token| {
-                token
-                    .assert_has_permission(Permission::ImapAuthenticate)
-                    .map(|_| token)
             })?;
 
+        // Act on behalf of another account using the SASL authorization identity
+        if let Some(authzid) = authzid {
+            access_token = self
+                .server
+                .authorize_impersonation(&access_token, &authzid, self.session_id)
+                .await
+                .map_err(|err| err.id(tag.clone()))?;
+        }
+        access_token
+            .assert_has_permission(Permission::ImapAuthenticate)
+            .map_err(|err| err.id(tag.clone()))?;
+        access_token
+            .assert_protocol_enabled(Protocol::Imap)
+            .map_err(|err| err.id(tag.clone()))?;
+
         // Enforce concurrency limits
         let in_flight = match access_token.is_imap_request_allowed() {
             LimiterResult::Allowed(in_flight) => Some(in_flight),
@@ -127,9 +145,11 @@ impl<T: SessionStream> Session<T> {
         self.write_bytes(
             StatusResponse::ok("Authentication successful")
                 .with_code(ResponseCode::Capability {
-                    capabilities: Capability::all_capabilities(
+                    capabilities: crate::capabilities(
+                        &self.server,
+                        &self.instance,
                         true,
-                        !self.is_tls && self.instance.acceptor.is_tls(),
+                        self.is_tls,
                     ),
                 })
                 .with_tag(tag)
@@ -11,10 +11,7 @@ use common::listener::SessionStream;
 use directory::Permission;
 use imap_proto::{
     Command, StatusResponse,
-    protocol::{
-        ImapResponse,
-        capability::{Capability, Response},
-    },
+    protocol::{ImapResponse, capability::Response},
     receiver::Request,
 };
 
@@ -37,9 +34,11 @@ impl<T: SessionStream> Session<T> {
                 .with_tag(request.tag)
                 .serialize(
                     Response {
-                        capabilities: Capability::all_capabilities(
+                        capabilities: crate::capabilities(
+                            &self.server,
+                            &self.instance,
                             self.state.is_authenticated(),
-                            !self.is_tls && self.instance.acceptor.is_tls(),
+                            self.is_tls,
                         ),
                     }
                     .serialize(),
@@ -63,10 +62,14 @@ impl<T: SessionStream> Session<T> {
             StatusResponse::completed(Command::Id)
                 .with_tag(request.tag)
                 .serialize(
-                    concat!(
-                        "* ID (\"name\" \"Stalwart\" \"version\" \"1.0.0\" \"vendor\" \"Stalwart Labs Ltd.\" ",
-                        "\"support-url\" \"https://stalw.art\")\r\n"
-                    )
+                    if !self.instance.banner.hide_product {
+                        concat!(
+                            "* ID (\"name\" \"Stalwart\" \"version\" \"1.0.0\" \"vendor\" \"Stalwart Labs Ltd.\" ",
+                            "\"support-url\" \"https://stalw.art\")\r\n"
+                        )
+                    } else {
+                        "* ID NIL\r\n"
+                    }
                     .as_bytes()
                     .to_vec(),
                 ),
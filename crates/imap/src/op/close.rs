@@ -17,9 +17,20 @@ impl<T: SessionStream> Session<T> {
         let (data, mailbox) = self.state.select_data();
 
         if mailbox.is_select {
-            data.expunge(mailbox.clone(), None, op_start)
+            // Deleted messages are kept while the account is in maintenance
+            match data
+                .server
+                .assert_account_not_in_maintenance(mailbox.id.account_id)
                 .await
-                .caused_by(trc::location!())?;
+            {
+                Ok(_) => {
+                    data.expunge(mailbox.clone(), None, op_start)
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+                Err(err) if err.matches(trc::EventType::Limit(trc::LimitEvent::Maintenance)) => {}
+                Err(err) => return Err(err.caused_by(trc::location!())),
+            }
         }
 
         trc::event!(
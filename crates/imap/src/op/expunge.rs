@@ -64,6 +64,12 @@ impl<T: SessionStream> Session<T> {
                 .id(request.tag));
         }
 
+        // Accounts in maintenance are read-only
+        data.server
+            .assert_account_not_in_maintenance(mailbox.id.account_id)
+            .await
+            .map_err(|err| err.id(request.tag.clone()))?;
+
         // Parse sequence to operate on
         let sequence = match request.tokens.into_iter().next() {
             Some(Token::Argument(value)) if is_uid => {
@@ -19,6 +19,7 @@ impl<T: SessionStream> Session<T> {
                 username: arguments.username.to_string(),
                 secret: arguments.password.to_string(),
             },
+            None,
             arguments.tag,
         )
         .await
@@ -33,11 +33,13 @@ impl<T: SessionStream> Session<T> {
         })?;
 
         let op_start = Instant::now();
-        let is_select = request.command == Command::Select;
         let command = request.command;
         let arguments = request.parse_select(self.version)?;
         let data = self.state.session_data();
 
+        // Read-only impersonation sessions open mailboxes as if EXAMINE was used
+        let is_select = command == Command::Select && !data.access_token.is_read_only();
+
         // Refresh mailboxes
         data.synchronize_mailboxes(false)
             .await
@@ -104,6 +104,12 @@ impl<T: SessionStream> SessionData<T> {
                 .caused_by(trc::location!()));
         }
 
+        // Accounts in maintenance are read-only
+        self.server
+            .assert_account_not_in_maintenance(account_id)
+            .await
+            .map_err(|err| err.id(arguments.tag.clone()))?;
+
         // Filter out unchanged since ids
         let mut response_code = None;
         let mut unchanged_failed = false;
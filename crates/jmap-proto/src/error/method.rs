@@ -165,6 +165,10 @@ impl Serialize for MethodErrorWrapper {
                     "This server is temporarily unavailable.",
                 ),
             },
+            trc::EventType::Limit(trc::LimitEvent::Maintenance) => (
+                "serverUnavailable",
+                description.unwrap_or("The account is in maintenance, try again later."),
+            ),
             _ => (
                 "serverUnavailable",
                 concat!(
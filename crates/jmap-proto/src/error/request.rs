@@ -110,6 +110,10 @@ impl<'x> RequestError<'x> {
         )
     }
 
+    pub fn upload_rejected(detail: impl Into<Cow<'x, str>>) -> Self {
+        RequestError::blank(422, "Upload rejected", detail)
+    }
+
     pub fn over_quota() -> Self {
         RequestError::blank(
             403,
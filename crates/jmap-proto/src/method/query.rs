@@ -53,6 +53,10 @@ pub struct QueryResponse {
     #[serde(rename = "limit")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub limit: Option<usize>,
+
+    #[serde(rename = "indexPending")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index_pending: Option<bool>,
 }
 
 #[derive(Clone, Debug)]
@@ -781,6 +785,12 @@ impl<T: Into<u8> + Display + Clone + std::fmt::Debug> From<Filter> for FtsFilter
 impl FilterItem for Filter {
     fn filter_type(&self) -> FilterType {
         match self {
+            // The original recipient of catch-all deliveries is kept in the store index
+            Filter::Header(header)
+                if header.len() == 2 && header[0].eq_ignore_ascii_case("X-Original-To") =>
+            {
+                FilterType::Store
+            }
             Filter::Text(_)
             | Filter::From(_)
             | Filter::To(_)
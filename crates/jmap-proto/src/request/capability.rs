@@ -10,7 +10,7 @@ use utils::map::vec_map::VecMap;
 use crate::{
     parser::{JsonObjectParser, json::Parser},
     response::serialize::serialize_hex,
-    types::{id::Id, type_state::DataType},
+    types::{date::UTCDate, id::Id, type_state::DataType},
 };
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -48,6 +48,16 @@ struct Account {
     is_read_only: bool,
     #[serde(rename(serialize = "accountCapabilities"))]
     account_capabilities: VecMap<Capability, Capabilities>,
+    #[serde(rename(serialize = "maintenance"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    maintenance: Option<AccountMaintenance>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct AccountMaintenance {
+    #[serde(rename(serialize = "expiresAt"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<UTCDate>,
 }
 
 #[derive(Debug, Clone, Copy, serde::Serialize, Hash, PartialEq, Eq)]
@@ -72,6 +82,8 @@ pub enum Capability {
     Blob = 1 << 8,
     #[serde(rename(serialize = "urn:ietf:params:jmap:quota"))]
     Quota = 1 << 9,
+    #[serde(rename(serialize = "urn:stalwart:params:jmap:encryptionAtRest"))]
+    EncryptionAtRest = 1 << 10,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -85,6 +97,7 @@ pub enum Capabilities {
     SieveAccount(SieveAccountCapabilities),
     SieveSession(SieveSessionCapabilities),
     Blob(BlobCapabilities),
+    EncryptionAtRest(EncryptionAtRestCapabilities),
     Empty(EmptyCapabilities),
 }
 
@@ -176,6 +189,20 @@ pub struct BlobCapabilities {
     pub supported_digest_algorithms: Vec<&'static str>,
 }
 
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct EncryptionAtRestCapabilities {
+    #[serde(rename(serialize = "isEnabled"))]
+    pub is_enabled: bool,
+    #[serde(rename(serialize = "method"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(rename(serialize = "algorithm"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+    #[serde(rename(serialize = "numCertificates"))]
+    pub num_certificates: usize,
+}
+
 #[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct EmptyCapabilities {}
 
@@ -256,6 +283,26 @@ impl Session {
         );
     }
 
+    pub fn set_maintenance(&mut self, account_id: Id, expires_at: Option<u64>) {
+        if let Some(account) = self.accounts.get_mut(&account_id) {
+            account.is_read_only = true;
+            account.maintenance = Some(AccountMaintenance {
+                expires_at: expires_at.map(|expires_at| UTCDate::from_timestamp(expires_at as i64)),
+            });
+        }
+    }
+
+    pub fn set_account_capability(
+        &mut self,
+        account_id: Id,
+        capability: Capability,
+        value: Capabilities,
+    ) {
+        if let Some(account) = self.accounts.get_mut(&account_id) {
+            account.account_capabilities.set(capability, value);
+        }
+    }
+
     pub fn set_state(&mut self, state: u32) {
         self.state = state;
     }
@@ -276,6 +323,7 @@ impl Account {
             is_personal,
             is_read_only,
             account_capabilities: VecMap::new(),
+            maintenance: None,
         }
     }
 
@@ -10,7 +10,7 @@ use crate::{
     error::request::{RequestError, RequestErrorType, RequestLimitError},
     parser::{JsonObjectParser, Token, json::Parser},
     request::Call,
-    response::{Response, ResponseMethod, serialize::serialize_hex},
+    response::{Response, ResponseMethod, serialize::serialize_hex, status::MailboxCounts},
     types::{any_id::AnyId, id::Id, state::State, type_state::DataType},
 };
 use utils::map::vec_map::VecMap;
@@ -72,6 +72,9 @@ pub struct WebSocketStateChange {
     #[serde(rename = "@type")]
     pub type_: WebSocketStateChangeType,
     pub changed: VecMap<Id, VecMap<DataType, State>>,
+    #[serde(rename = "stalwart:mailboxCounts")]
+    #[serde(skip_serializing_if = "VecMap::is_empty")]
+    pub mailbox_counts: VecMap<Id, VecMap<Id, MailboxCounts>>,
     #[serde(rename = "pushState")]
     #[serde(skip_serializing_if = "Option::is_none")]
     push_state: Option<String>,
@@ -224,6 +227,7 @@ impl WebSocketStateChange {
         WebSocketStateChange {
             type_: WebSocketStateChangeType::StateChange,
             changed: VecMap::new(),
+            mailbox_counts: VecMap::new(),
             push_state,
         }
     }
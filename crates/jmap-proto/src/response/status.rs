@@ -18,6 +18,22 @@ pub struct StateChangeResponse {
     #[serde(rename = "@type")]
     pub type_: StateChangeType,
     pub changed: VecMap<Id, VecMap<DataType, State>>,
+    #[serde(rename = "stalwart:mailboxCounts")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "VecMap::is_empty")]
+    pub mailbox_counts: VecMap<Id, VecMap<Id, MailboxCounts>>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MailboxCounts {
+    #[serde(rename = "totalEmails")]
+    pub total_emails: u32,
+    #[serde(rename = "unreadEmails")]
+    pub unread_emails: u32,
+    #[serde(rename = "totalThreads")]
+    pub total_threads: u32,
+    #[serde(rename = "unreadThreads")]
+    pub unread_threads: u32,
 }
 
 impl StateChangeResponse {
@@ -25,6 +41,7 @@ impl StateChangeResponse {
         Self {
             type_: StateChangeType::StateChange,
             changed: VecMap::new(),
+            mailbox_counts: VecMap::new(),
         }
     }
 }
@@ -119,6 +119,12 @@ pub enum Property {
     WarnLimit,
     SoftLimit,
     Scope,
+    Integrity,
+    EmailTree,
+    IsParentMissing,
+    EncryptionUpdatedAt,
+    EncryptionTotal,
+    DeadLetters,
     Digest(DigestProperty),
     Data(DataProperty),
     _T(String),
@@ -364,6 +370,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x6c69_616d => Property::Email,
             0x6449_6c69_616d => Property::EmailId,
             0x0073_6449_6c69_616d => Property::EmailIds,
+            0x6565_7254_6c69_616d => Property::EmailTree,
             0x0065_706f_6c65_766e => Property::Envelope,
             0x7365_7269_7078 => Property::Expires,
             _ => return None,
@@ -881,6 +888,12 @@ impl Display for Property {
             Property::Used => write!(f, "used"),
             Property::HardLimit => write!(f, "hardLimit"),
             Property::Scope => write!(f, "scope"),
+            Property::Integrity => write!(f, "integrity"),
+            Property::EmailTree => write!(f, "emailTree"),
+            Property::IsParentMissing => write!(f, "isParentMissing"),
+            Property::EncryptionUpdatedAt => write!(f, "encryptionUpdatedAt"),
+            Property::EncryptionTotal => write!(f, "encryptionTotal"),
+            Property::DeadLetters => write!(f, "deadLetters"),
             Property::WarnLimit => write!(f, "warnLimit"),
             Property::SoftLimit => write!(f, "softLimit"),
             Property::_T(s) => write!(f, "{s}"),
@@ -994,6 +1007,12 @@ impl Property {
             Property::WarnLimit => "warnLimit",
             Property::SoftLimit => "softLimit",
             Property::Scope => "scope",
+            Property::Integrity => "integrity",
+            Property::EmailTree => "emailTree",
+            Property::IsParentMissing => "isParentMissing",
+            Property::EncryptionUpdatedAt => "encryptionUpdatedAt",
+            Property::EncryptionTotal => "encryptionTotal",
+            Property::DeadLetters => "deadLetters",
             Property::Data(data) => match data {
                 DataProperty::AsText => "data:asText",
                 DataProperty::AsBase64 => "data:asBase64",
@@ -1180,6 +1199,12 @@ impl From<&Property> for u8 {
             Property::WarnLimit => 101,
             Property::SoftLimit => 102,
             Property::Scope => 103,
+            Property::Integrity => 104,
+            Property::EmailTree => 105,
+            Property::IsParentMissing => 106,
+            Property::EncryptionUpdatedAt => 107,
+            Property::EncryptionTotal => 108,
+            Property::DeadLetters => 109,
             Property::Digest(_) | Property::Data(_) => unreachable!("invalid property"),
         }
     }
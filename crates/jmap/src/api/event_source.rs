@@ -10,6 +10,7 @@ use std::{
 };
 
 use common::{LONG_1D_SLUMBER, Server, auth::AccessToken};
+use email::cache::MessageCacheFetch;
 use http_body_util::{StreamBody, combinators::BoxBody};
 use hyper::{
     StatusCode,
@@ -99,6 +100,8 @@ impl EventSourceHandler for Server {
         };
         let mut response = StateChangeResponse::new();
         let throttle = self.core.jmap.event_source_throttle;
+        let server = self.clone();
+        let account_id = access_token.primary_id();
 
         // Register with state manager
         let mut change_rx = self
@@ -122,6 +125,28 @@ impl EventSourceHandler for Server {
                                     .get_mut_or_insert(state_change.account_id.into())
                                     .set(type_state, state_change.change_id.into());
                             }
+
+                            // Include the updated mailbox counters
+                            if state_change.account_id == account_id
+                                && state_change.types.contains(DataType::Email)
+                            {
+                                match server
+                                    .get_mailbox_counts(account_id, state_change.change_id)
+                                    .await
+                                {
+                                    Ok(counts) => {
+                                        let account_counts = response
+                                            .mailbox_counts
+                                            .get_mut_or_insert(account_id.into());
+                                        for (mailbox_id, counts) in counts {
+                                            account_counts.set(mailbox_id.into(), counts);
+                                        }
+                                    }
+                                    Err(err) => {
+                                        trc::error!(err.account_id(account_id));
+                                    }
+                                }
+                            }
                         }
                         Ok(None) => {
                             break;
@@ -143,6 +168,7 @@ impl EventSourceHandler for Server {
                             }
 
                             response.changed.clear();
+                            response.mailbox_counts.clear();
                                 ping.as_ref().map(|p| p.interval).unwrap_or(LONG_1D_SLUMBER)
                         } else {
                             throttle - elapsed
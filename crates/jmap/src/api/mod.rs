@@ -88,12 +88,16 @@ impl ToRequestError for trc::Error {
                         .unwrap_or_default() as usize,
                 ),
                 trc::LimitEvent::TooManyRequests => RequestError::too_many_requests(),
+                trc::LimitEvent::Maintenance => RequestError::unavailable(),
             },
             trc::EventType::Auth(cause) => match cause {
                 trc::AuthEvent::MissingTotp => {
                     RequestError::blank(402, "TOTP code required", cause.message())
                 }
                 trc::AuthEvent::TooManyAttempts => RequestError::too_many_auth_attempts(),
+                trc::AuthEvent::ProtocolDisabled => {
+                    RequestError::blank(403, "Protocol disabled", details)
+                }
                 _ => RequestError::unauthorized(),
             },
             trc::EventType::Security(cause) => match cause {
@@ -102,8 +106,15 @@ impl ToRequestError for trc::Error {
                 | trc::SecurityEvent::AbuseBan
                 | trc::SecurityEvent::LoiterBan
                 | trc::SecurityEvent::IpBlocked => RequestError::too_many_auth_attempts(),
-                trc::SecurityEvent::Unauthorized => RequestError::forbidden(),
+                trc::SecurityEvent::Unauthorized | trc::SecurityEvent::UnauthorizedSender => {
+                    RequestError::forbidden()
+                }
+                trc::SecurityEvent::UploadRejected => RequestError::upload_rejected(details),
+                trc::SecurityEvent::ClientCertificateRequired => {
+                    RequestError::blank(403, "Client certificate required", details)
+                }
             },
+            trc::EventType::MtaHook(_) => RequestError::unavailable(),
             trc::EventType::Resource(cause) => match cause {
                 trc::ResourceEvent::NotFound => RequestError::not_found(),
                 trc::ResourceEvent::BadParameters => RequestError::blank(
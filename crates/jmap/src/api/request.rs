@@ -345,7 +345,9 @@ impl RequestHandler for Server {
             RequestMethod::UploadBlob(req) => {
                 access_token.assert_is_member(req.account_id)?;
 
-                self.blob_upload_many(req, access_token).await?.into()
+                self.blob_upload_many(req, access_token, session)
+                    .await?
+                    .into()
             }
             RequestMethod::Echo(req) => req.into(),
             RequestMethod::Error(error) => return Err(error),
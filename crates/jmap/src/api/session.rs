@@ -8,9 +8,12 @@ use std::sync::Arc;
 
 use common::{Server, auth::AccessToken};
 use directory::backend::internal::manage::ManageDirectory;
+use email::message::crypto::{
+    Algorithm, ArchivedAlgorithm, ArchivedEncryptionMethod, EncryptionMethod, EncryptionParams,
+};
 use jmap_proto::{
-    request::capability::{Capability, Session},
-    types::{acl::Acl, collection::Collection, id::Id},
+    request::capability::{Capabilities, Capability, EncryptionAtRestCapabilities, Session},
+    types::{acl::Acl, collection::Collection, id::Id, property::Property},
 };
 use std::future::Future;
 use trc::AddContext;
@@ -42,6 +45,57 @@ impl SessionHandler for Server {
             None,
             &self.core.jmap.capabilities.account,
         );
+        if let Some(status) = self
+            .account_maintenance(&access_token)
+            .await
+            .caused_by(trc::location!())?
+        {
+            session.set_maintenance(access_token.primary_id().into(), status.expires_at);
+        }
+        if self.core.jmap.encrypt && access_token.encrypted_at.is_some() {
+            if let Some(params_) = self
+                .get_archive_by_property(
+                    access_token.primary_id(),
+                    Collection::Principal,
+                    0,
+                    Property::Parameters,
+                )
+                .await
+                .caused_by(trc::location!())?
+            {
+                let identities = params_
+                    .unarchive::<Vec<EncryptionParams>>()
+                    .caused_by(trc::location!())?;
+
+                // All identities are expected to use the method of the first one
+                if let Some(params) = identities.first() {
+                    let method = match &params.method {
+                        ArchivedEncryptionMethod::PGP => EncryptionMethod::PGP,
+                        ArchivedEncryptionMethod::SMIME => EncryptionMethod::SMIME,
+                    };
+                    let algo = match &params.algo {
+                        ArchivedAlgorithm::Aes128 => Algorithm::Aes128,
+                        ArchivedAlgorithm::Aes256 => Algorithm::Aes256,
+                        ArchivedAlgorithm::Aes128Gcm => Algorithm::Aes128Gcm,
+                        ArchivedAlgorithm::Aes256Gcm => Algorithm::Aes256Gcm,
+                        ArchivedAlgorithm::Chacha20Poly1305 => Algorithm::Chacha20Poly1305,
+                    };
+                    session.set_account_capability(
+                        access_token.primary_id().into(),
+                        Capability::EncryptionAtRest,
+                        Capabilities::EncryptionAtRest(EncryptionAtRestCapabilities {
+                            is_enabled: true,
+                            method: method.to_string().into(),
+                            algorithm: algo.to_string().into(),
+                            num_certificates: identities
+                                .iter()
+                                .map(|identity| identity.certs.len())
+                                .sum(),
+                        }),
+                    );
+                }
+            }
+        }
 
         // Add secondary accounts
         for id in access_token.secondary_ids() {
@@ -71,6 +125,13 @@ impl SessionHandler for Server {
                 Some(&[Capability::Mail, Capability::Quota, Capability::Blob]),
                 &self.core.jmap.capabilities.account,
             );
+            if let Some(status) = self
+                .account_id_maintenance(*id)
+                .await
+                .caused_by(trc::location!())?
+            {
+                session.set_maintenance((*id).into(), status.expires_at);
+            }
         }
 
         Ok(session)
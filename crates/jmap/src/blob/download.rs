@@ -7,6 +7,7 @@
 use common::{Server, auth::AccessToken};
 use email::cache::MessageCacheFetch;
 use email::cache::email::MessageCacheAccess;
+use email::message::integrity::{EmailIntegrity, IntegritySource, IntegrityStatus};
 use jmap_proto::types::{acl::Acl, blob::BlobId, collection::Collection};
 use std::future::Future;
 use std::ops::Range;
@@ -94,7 +95,46 @@ impl BlobDownload for Server {
         if let Some(section) = &blob_id.section {
             self.get_blob_section(&blob_id.hash, section).await
         } else {
-            self.get_blob(&blob_id.hash, 0..usize::MAX).await
+            let contents = self.get_blob(&blob_id.hash, 0..usize::MAX).await?;
+
+            // Verify exported messages against their integrity record
+            if let (
+                Some(contents),
+                BlobClass::Linked {
+                    account_id,
+                    collection,
+                    document_id,
+                },
+            ) = (&contents, &blob_id.class)
+            {
+                if Collection::from(*collection) == Collection::Email {
+                    match self
+                        .verify_message_integrity(*account_id, *document_id, Some(contents))
+                        .await
+                    {
+                        Ok(check) => {
+                            if let IntegrityStatus::Failed(failure) = check.status {
+                                self.report_integrity_failure(
+                                    *account_id,
+                                    *document_id,
+                                    failure,
+                                    IntegritySource::Download,
+                                )
+                                .await;
+                            }
+                        }
+                        Err(err) => {
+                            trc::error!(
+                                err.account_id(*account_id)
+                                    .document_id(*document_id)
+                                    .details("Failed to verify message integrity")
+                            );
+                        }
+                    }
+                }
+            }
+
+            Ok(contents)
         }
     }
 
@@ -5,10 +5,12 @@
  */
 
 use jmap_proto::types::{blob::BlobId, id::Id};
+use scan::ScanStatus;
 
 pub mod copy;
 pub mod download;
 pub mod get;
+pub mod scan;
 pub mod upload;
 
 #[derive(Debug, serde::Serialize)]
@@ -20,4 +22,7 @@ pub struct UploadResponse {
     #[serde(rename(serialize = "type"))]
     c_type: String,
     size: usize,
+    #[serde(rename(serialize = "scanStatus"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scan_status: Option<ScanStatus>,
 }
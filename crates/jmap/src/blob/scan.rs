@@ -0,0 +1,338 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, net::IpAddr};
+
+use common::{KV_UPLOAD_SCAN, Server, auth::AccessToken};
+use mail_parser::{MessageParser, MimeHeaders};
+use smtp::inbound::hooks::upload::{UploadHooks, UploadScan, UploadScanResult};
+use store::{
+    Serialize,
+    dispatch::lookup::KeyValue,
+    write::{AlignedBytes, Archive, Archiver},
+};
+use trc::{AddContext, SecurityEvent};
+use utils::BlobHash;
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Clean,
+    Pending,
+    Rejected(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum ScanStatus {
+    #[serde(rename = "clean")]
+    Clean,
+    #[serde(rename = "pending")]
+    Pending,
+}
+
+#[derive(Clone, Copy)]
+pub struct ScanRequest<'x> {
+    pub login: &'x str,
+    pub account_id: u32,
+    pub remote_ip: Option<IpAddr>,
+    pub session_id: u64,
+}
+
+pub trait BlobScan: Sync + Send {
+    fn is_upload_scan_enabled(&self) -> bool;
+
+    fn upload_scan(
+        &self,
+        request: ScanRequest<'_>,
+        content_type: &str,
+        data: &[u8],
+    ) -> impl Future<Output = trc::Result<Option<ScanVerdict>>> + Send;
+
+    fn upload_scan_message(
+        &self,
+        request: ScanRequest<'_>,
+        message: &[u8],
+    ) -> impl Future<Output = trc::Result<Result<(), String>>> + Send;
+
+    fn upload_scan_verdict(
+        &self,
+        hash: &BlobHash,
+    ) -> impl Future<Output = trc::Result<Option<ScanVerdict>>> + Send;
+}
+
+impl BlobScan for Server {
+    fn is_upload_scan_enabled(&self) -> bool {
+        !self.core.jmap.upload_scan_block_types.is_empty()
+            || self.core.jmap.upload_scan_max_size > 0
+            || self.has_upload_hooks()
+    }
+
+    async fn upload_scan(
+        &self,
+        request: ScanRequest<'_>,
+        content_type: &str,
+        data: &[u8],
+    ) -> trc::Result<Option<ScanVerdict>> {
+        if !self.is_upload_scan_enabled() {
+            return Ok(None);
+        }
+
+        // Files that were already scanned are not scanned again
+        let hash = BlobHash::generate(data);
+        if let Some(verdict) = self.upload_scan_verdict(&hash).await? {
+            return Ok(Some(verdict));
+        }
+
+        // Large files are scanned in the background
+        if data.len() > self.core.jmap.upload_scan_async_size
+            && self.has_upload_hooks()
+            && upload_policy(self, content_type, data.len()).is_none()
+        {
+            store_verdict(self, &request, &hash, &ScanVerdict::Pending).await?;
+
+            let server = self.clone();
+            let login = request.login.to_string();
+            let account_id = request.account_id;
+            let remote_ip = request.remote_ip;
+            let session_id = request.session_id;
+            let content_type = content_type.to_string();
+            let data = data.to_vec();
+            tokio::spawn(async move {
+                let request = ScanRequest {
+                    login: &login,
+                    account_id,
+                    remote_ip,
+                    session_id,
+                };
+                if let Err(err) = scan(&server, request, &hash, &content_type, &data).await {
+                    // Discard the pending verdict so the file is scanned again on submission
+                    let _ = server
+                        .in_memory_store()
+                        .key_delete(KeyValue::<()>::build_key(KV_UPLOAD_SCAN, hash.as_ref()))
+                        .await;
+                    trc::error!(err.span_id(session_id).details("Upload scan failed"));
+                }
+            });
+
+            return Ok(Some(ScanVerdict::Pending));
+        }
+
+        scan(self, request, &hash, content_type, data)
+            .await
+            .map(Some)
+    }
+
+    async fn upload_scan_message(
+        &self,
+        request: ScanRequest<'_>,
+        message: &[u8],
+    ) -> trc::Result<Result<(), String>> {
+        if !self.is_upload_scan_enabled() {
+            return Ok(Ok(()));
+        }
+        let Some(message) = MessageParser::new().parse(message) else {
+            return Ok(Ok(()));
+        };
+
+        // Verdicts expire after the maximum age, so any attachment without a
+        // verdict is scanned again before it is sent.
+        for part in message
+            .attachments
+            .iter()
+            .filter_map(|part_id| message.parts.get(*part_id as usize))
+        {
+            let name = part.attachment_name().unwrap_or("untitled");
+            let contents = part.contents();
+            let hash = BlobHash::generate(contents);
+            let verdict = if let Some(verdict) = self.upload_scan_verdict(&hash).await? {
+                verdict
+            } else {
+                let content_type = part
+                    .content_type()
+                    .map(|ct| {
+                        ct.subtype()
+                            .map(|st| format!("{}/{}", ct.ctype(), st))
+                            .unwrap_or_else(|| ct.ctype().to_string())
+                    })
+                    .unwrap_or_else(|| "application/octet-stream".to_string());
+
+                scan(self, request, &hash, &content_type, contents).await?
+            };
+
+            match verdict {
+                ScanVerdict::Clean => (),
+                ScanVerdict::Pending => {
+                    return Ok(Err(format!(
+                        "Attachment {name:?} is still being scanned, please try again later."
+                    )));
+                }
+                ScanVerdict::Rejected(reason) => {
+                    return Ok(Err(format!("Attachment {name:?} was rejected: {reason}")));
+                }
+            }
+        }
+
+        Ok(Ok(()))
+    }
+
+    async fn upload_scan_verdict(&self, hash: &BlobHash) -> trc::Result<Option<ScanVerdict>> {
+        self.in_memory_store()
+            .key_get::<Archive<AlignedBytes>>(KeyValue::<()>::build_key(
+                KV_UPLOAD_SCAN,
+                hash.as_ref(),
+            ))
+            .await
+            .caused_by(trc::location!())?
+            .map(|verdict| verdict.deserialize::<ScanVerdict>())
+            .transpose()
+            .caused_by(trc::location!())
+    }
+}
+
+async fn scan(
+    server: &Server,
+    request: ScanRequest<'_>,
+    hash: &BlobHash,
+    content_type: &str,
+    data: &[u8],
+) -> trc::Result<ScanVerdict> {
+    let verdict = if let Some(reason) = upload_policy(server, content_type, data.len()) {
+        ScanVerdict::Rejected(reason)
+    } else {
+        match server
+            .run_upload_hooks(
+                &UploadScan {
+                    login: request.login,
+                    remote_ip: request.remote_ip,
+                    content_type,
+                    contents: data,
+                },
+                request.session_id,
+            )
+            .await
+        {
+            UploadScanResult::Accept => ScanVerdict::Clean,
+            UploadScanResult::Reject(reason) => ScanVerdict::Rejected(reason),
+            UploadScanResult::TempFail(reason) => {
+                return Err(trc::MtaHookEvent::Error
+                    .into_err()
+                    .reason(reason)
+                    .caused_by(trc::location!()));
+            }
+        }
+    };
+
+    store_verdict(server, &request, hash, &verdict).await?;
+
+    Ok(verdict)
+}
+
+async fn store_verdict(
+    server: &Server,
+    request: &ScanRequest<'_>,
+    hash: &BlobHash,
+    verdict: &ScanVerdict,
+) -> trc::Result<()> {
+    let expires = match verdict {
+        ScanVerdict::Pending => {
+            // Allow enough time for every scanner to time out
+            server
+                .core
+                .smtp
+                .session
+                .hooks
+                .iter()
+                .map(|hook| hook.timeout.as_secs())
+                .sum::<u64>()
+                + 60
+        }
+        _ => server.core.jmap.upload_scan_max_age.as_secs(),
+    };
+
+    if let ScanVerdict::Rejected(reason) = verdict {
+        trc::event!(
+            Security(SecurityEvent::UploadRejected),
+            SpanId = request.session_id,
+            AccountId = request.account_id,
+            Details = reason.clone(),
+        );
+    }
+
+    server
+        .in_memory_store()
+        .key_set(
+            KeyValue::with_prefix(
+                KV_UPLOAD_SCAN,
+                hash.as_ref(),
+                Archiver::new(verdict.clone())
+                    .serialize()
+                    .caused_by(trc::location!())?,
+            )
+            .expires(expires),
+        )
+        .await
+        .caused_by(trc::location!())
+}
+
+fn upload_policy(server: &Server, content_type: &str, size: usize) -> Option<String> {
+    let config = &server.core.jmap;
+    if config.upload_scan_max_size > 0 && size > config.upload_scan_max_size {
+        return Some(format!(
+            "File exceeds the maximum scannable size of {} bytes.",
+            config.upload_scan_max_size
+        ));
+    }
+
+    let content_type = content_type
+        .split_once(';')
+        .map_or(content_type, |(ct, _)| ct)
+        .trim()
+        .to_ascii_lowercase();
+    config
+        .upload_scan_block_types
+        .iter()
+        .any(|blocked| {
+            blocked.strip_suffix("/*").map_or_else(
+                || blocked == &content_type,
+                |prefix| {
+                    content_type
+                        .strip_prefix(prefix)
+                        .is_some_and(|rest| rest.starts_with('/'))
+                },
+            )
+        })
+        .then(|| format!("Files of type {content_type:?} are not allowed."))
+}
+
+impl<'x> ScanRequest<'x> {
+    pub fn new(access_token: &'x AccessToken) -> Self {
+        ScanRequest {
+            login: &access_token.name,
+            account_id: access_token.primary_id(),
+            remote_ip: None,
+            session_id: 0,
+        }
+    }
+
+    pub fn with_remote_ip(mut self, remote_ip: IpAddr) -> Self {
+        self.remote_ip = Some(remote_ip);
+        self
+    }
+
+    pub fn with_session_id(mut self, session_id: u64) -> Self {
+        self.session_id = session_id;
+        self
+    }
+}
+
+impl ScanVerdict {
+    pub fn status(&self) -> Option<ScanStatus> {
+        match self {
+            ScanVerdict::Clean => Some(ScanStatus::Clean),
+            ScanVerdict::Pending => Some(ScanStatus::Pending),
+            ScanVerdict::Rejected(_) => None,
+        }
+    }
+}
@@ -8,6 +8,7 @@ use std::sync::Arc;
 
 use common::{Server, auth::AccessToken};
 use directory::Permission;
+use http_proto::HttpSessionData;
 use jmap_proto::{
     error::set::SetError,
     method::upload::{
@@ -19,7 +20,11 @@ use jmap_proto::{
 
 use trc::AddContext;
 
-use super::{UploadResponse, download::BlobDownload};
+use super::{
+    UploadResponse,
+    download::BlobDownload,
+    scan::{BlobScan, ScanRequest, ScanVerdict},
+};
 use std::future::Future;
 
 #[cfg(feature = "test_mode")]
@@ -31,6 +36,7 @@ pub trait BlobUpload: Sync + Send {
         &self,
         request: BlobUploadRequest,
         access_token: &AccessToken,
+        session: &HttpSessionData,
     ) -> impl Future<Output = trc::Result<BlobUploadResponse>> + Send;
 
     fn blob_upload(
@@ -39,6 +45,7 @@ pub trait BlobUpload: Sync + Send {
         content_type: &str,
         data: &[u8],
         access_token: Arc<AccessToken>,
+        session: &HttpSessionData,
     ) -> impl Future<Output = trc::Result<UploadResponse>> + Send;
 }
 
@@ -47,6 +54,7 @@ impl BlobUpload for Server {
         &self,
         request: BlobUploadRequest,
         access_token: &AccessToken,
+        session: &HttpSessionData,
     ) -> trc::Result<BlobUploadResponse> {
         let mut response = BlobUploadResponse {
             account_id: request.account_id,
@@ -175,6 +183,26 @@ impl BlobUpload for Server {
                 continue 'outer;
             }
 
+            // Run the upload through the attachment policy and content scanners
+            if let Some(ScanVerdict::Rejected(reason)) = self
+                .upload_scan(
+                    ScanRequest::new(access_token)
+                        .with_remote_ip(session.remote_ip)
+                        .with_session_id(session.session_id),
+                    upload_object
+                        .type_
+                        .as_deref()
+                        .unwrap_or("application/octet-stream"),
+                    &data,
+                )
+                .await?
+            {
+                response
+                    .not_created
+                    .append(create_id, SetError::forbidden().with_description(reason));
+                continue 'outer;
+            }
+
             // Write blob
             response.created.insert(
                 create_id,
@@ -195,6 +223,7 @@ impl BlobUpload for Server {
         content_type: &str,
         data: &[u8],
         access_token: Arc<AccessToken>,
+        session: &HttpSessionData,
     ) -> trc::Result<UploadResponse> {
         // Limit concurrent uploads
         let _in_flight = self
@@ -238,6 +267,26 @@ impl BlobUpload for Server {
             return err;
         }
 
+        // Run the upload through the attachment policy and content scanners
+        let scan_status = match self
+            .upload_scan(
+                ScanRequest::new(&access_token)
+                    .with_remote_ip(session.remote_ip)
+                    .with_session_id(session.session_id),
+                content_type,
+                data,
+            )
+            .await
+            .caused_by(trc::location!())?
+        {
+            Some(ScanVerdict::Rejected(reason)) => {
+                return Err(trc::SecurityEvent::UploadRejected
+                    .into_err()
+                    .details(reason));
+            }
+            verdict => verdict.and_then(|verdict| verdict.status()),
+        };
+
         Ok(UploadResponse {
             account_id,
             blob_id: self
@@ -246,6 +295,7 @@ impl BlobUpload for Server {
                 .caused_by(trc::location!())?,
             c_type: content_type.to_string(),
             size: data.len(),
+            scan_status,
         })
     }
 }
@@ -7,11 +7,13 @@
 use super::{
     body::{ToBodyPart, truncate_html, truncate_plain},
     headers::IntoForm,
+    proxy::{RemoteContentProxy, rewrite_remote_images},
 };
 use crate::{
     blob::download::BlobDownload, changes::state::MessageCacheState, email::headers::HeaderToValue,
 };
 use common::{Server, auth::AccessToken};
+use directory::Permission;
 use email::{
     cache::{MessageCacheFetch, email::MessageCacheAccess},
     message::metadata::{ArchivedMetadataPartType, MessageMetadata},
@@ -94,6 +96,8 @@ impl EmailGet for Server {
         let fetch_html_body_values = request.arguments.fetch_html_body_values.unwrap_or(false);
         let fetch_all_body_values = request.arguments.fetch_all_body_values.unwrap_or(false);
         let max_body_value_bytes = request.arguments.max_body_value_bytes.unwrap_or(0);
+        let proxy_remote_content = self.core.jmap.remote_content_enable
+            && access_token.has_permission(Permission::JmapRemoteContent);
 
         let account_id = request.account_id.document_id();
         let cache = self
@@ -376,6 +380,14 @@ impl EmailGet for Server {
                                     ArchivedMetadataPartType::Text => {
                                         truncate_plain(contents.as_str(), max_body_value_bytes)
                                     }
+                                    ArchivedMetadataPartType::Html if proxy_remote_content => {
+                                        truncate_html(
+                                            &rewrite_remote_images(contents.as_str(), |url| {
+                                                self.remote_content_url(account_id, url)
+                                            }),
+                                            max_body_value_bytes,
+                                        )
+                                    }
                                     ArchivedMetadataPartType::Html => {
                                         truncate_html(contents.as_str(), max_body_value_bytes)
                                     }
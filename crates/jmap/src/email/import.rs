@@ -38,6 +38,7 @@ impl EmailImport for Server {
     ) -> trc::Result<ImportEmailResponse> {
         // Validate state
         let account_id = request.account_id.document_id();
+        self.assert_account_not_in_maintenance(account_id).await?;
         let cache = self.get_cached_messages(account_id).await?;
         let old_state: State = cache.assert_state(false, &request.if_in_state)?;
         let can_add_mailbox_ids = if access_token.is_shared(account_id) {
@@ -121,7 +122,9 @@ impl EmailImport for Server {
                     mailbox_ids,
                     keywords: email.keywords,
                     received_at: email.received_at.map(|r| r.into()),
-                    source: IngestSource::Jmap,
+                    source: IngestSource::Jmap {
+                        shared: !access_token.is_member(account_id),
+                    },
                     spam_classify: false,
                     spam_train: can_train_spam,
                     session_id: session.session_id,
@@ -10,6 +10,7 @@ pub mod get;
 pub mod headers;
 pub mod import;
 pub mod parse;
+pub mod proxy;
 pub mod query;
 pub mod set;
 pub mod snippet;
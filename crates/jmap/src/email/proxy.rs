@@ -0,0 +1,407 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    time::Instant,
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use common::{KV_RATE_LIMIT_REMOTE_CONTENT, KV_REMOTE_CONTENT, Server, auth::AccessToken};
+use directory::Permission;
+use http_proto::HttpResponse;
+use hyper::StatusCode;
+use jmap_proto::types::id::Id;
+use mail_auth::IpLookupStrategy;
+use smtp::outbound::lookup::DnsLookup;
+use store::{
+    Serialize, blake3,
+    dispatch::lookup::KeyValue,
+    write::{AlignedBytes, Archive, Archiver},
+};
+use trc::AddContext;
+use utils::{HttpLimitResponse, is_public_ip};
+
+const MAX_REDIRECTS: usize = 3;
+
+#[derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize, Debug, Clone)]
+pub struct RemoteContent {
+    pub content_type: String,
+    pub contents: Vec<u8>,
+}
+
+pub trait RemoteContentProxy: Sync + Send {
+    fn remote_content_url(&self, account_id: u32, url: &str) -> String;
+
+    fn remote_content_download(
+        &self,
+        account_id: u32,
+        signature: &str,
+        encoded_url: &str,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl RemoteContentProxy for Server {
+    fn remote_content_url(&self, account_id: u32, url: &str) -> String {
+        format!(
+            "/jmap/proxy/{}/{}/{}",
+            Id::from(account_id),
+            remote_content_signature(self, account_id, url).to_hex(),
+            URL_SAFE_NO_PAD.encode(url.as_bytes())
+        )
+    }
+
+    async fn remote_content_download(
+        &self,
+        account_id: u32,
+        signature: &str,
+        encoded_url: &str,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        if !self.core.jmap.remote_content_enable {
+            return Err(trc::ResourceEvent::NotFound.into_err());
+        }
+        access_token.assert_has_permission(Permission::JmapRemoteContent)?;
+
+        // Only URLs signed by this server can be fetched
+        let url = URL_SAFE_NO_PAD
+            .decode(encoded_url.as_bytes())
+            .ok()
+            .and_then(|url| String::from_utf8(url).ok())
+            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+        if !access_token.has_account_access(account_id)
+            || blake3::Hash::from_hex(signature).ok()
+                != Some(remote_content_signature(self, account_id, &url))
+        {
+            return Err(trc::ResourceEvent::NotFound.into_err());
+        }
+
+        // Obtain the contents from the cache or fetch them
+        let ttl = self.core.jmap.remote_content_cache_ttl.as_secs();
+        let key =
+            KeyValue::<()>::build_key(KV_REMOTE_CONTENT, blake3::hash(url.as_bytes()).as_bytes());
+        let content = if let Some(content) = self
+            .in_memory_store()
+            .key_get::<Archive<AlignedBytes>>(key.clone())
+            .await
+            .caused_by(trc::location!())?
+        {
+            content
+                .deserialize::<RemoteContent>()
+                .caused_by(trc::location!())?
+        } else {
+            if let Some(rate) = &self.core.jmap.remote_content_rate {
+                if !access_token.has_permission(Permission::UnlimitedRequests)
+                    && self
+                        .core
+                        .storage
+                        .lookup
+                        .is_rate_allowed(
+                            KV_RATE_LIMIT_REMOTE_CONTENT,
+                            &access_token.primary_id().to_be_bytes(),
+                            rate,
+                            false,
+                        )
+                        .await
+                        .caused_by(trc::location!())?
+                        .is_some()
+                {
+                    return Err(trc::LimitEvent::TooManyRequests.into_err());
+                }
+            }
+
+            let content = fetch_remote_content(self, &url).await?;
+            self.in_memory_store()
+                .key_set(
+                    KeyValue::new(
+                        key,
+                        Archiver::new(content.clone())
+                            .serialize()
+                            .caused_by(trc::location!())?,
+                    )
+                    .expires(ttl),
+                )
+                .await
+                .caused_by(trc::location!())?;
+            content
+        };
+
+        Ok(HttpResponse::new(StatusCode::OK)
+            .with_content_type(content.content_type)
+            .with_cache_control(format!("private, max-age={ttl}, immutable"))
+            .with_header("X-Content-Type-Options", "nosniff")
+            .with_binary_body(content.contents))
+    }
+}
+
+fn remote_content_signature(server: &Server, account_id: u32, url: &str) -> blake3::Hash {
+    let key = blake3::derive_key(
+        "stalwart remote content proxy",
+        server.core.oauth.oauth_key.as_bytes(),
+    );
+    let mut hasher = blake3::Hasher::new_keyed(&key);
+    hasher.update(&account_id.to_be_bytes());
+    hasher.update(url.as_bytes());
+    hasher.finalize()
+}
+
+async fn fetch_remote_content(server: &Server, url: &str) -> trc::Result<RemoteContent> {
+    let config = &server.core.jmap;
+    let start_time = Instant::now();
+    let mut url = reqwest::Url::parse(url).map_err(|err| {
+        trc::ResourceEvent::BadParameters
+            .into_err()
+            .details("Invalid URL")
+            .reason(err)
+    })?;
+
+    for _ in 0..=MAX_REDIRECTS {
+        // Resolve the remote address and make sure it is allowed
+        let host = url
+            .host_str()
+            .filter(|_| matches!(url.scheme(), "http" | "https"))
+            .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+            .ok_or_else(|| {
+                trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .details("Unsupported URL")
+                    .ctx(trc::Key::Url, url.to_string())
+            })?
+            .to_string();
+        let ip = if let Ok(ip) = host.parse::<IpAddr>() {
+            ip
+        } else {
+            server
+                .ip_lookup(&host, IpLookupStrategy::Ipv4thenIpv6, 1)
+                .await?
+                .first()
+                .copied()
+                .ok_or_else(|| {
+                    trc::ResourceEvent::NotFound
+                        .into_err()
+                        .details("Failed to resolve host")
+                        .ctx(trc::Key::Url, url.to_string())
+                })?
+        };
+        if !config.remote_content_allow_private_ips && !is_public_ip(&ip) {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Remote address is not allowed")
+                .ctx(trc::Key::Url, url.to_string())
+                .ctx(trc::Key::RemoteIp, ip));
+        }
+
+        // Redirects are followed manually so each hop is validated
+        let client_builder = reqwest::Client::builder()
+            .timeout(config.remote_content_timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .resolve(
+                &host,
+                SocketAddr::new(ip, url.port_or_known_default().unwrap_or(443)),
+            );
+
+        #[cfg(feature = "test_mode")]
+        let client_builder = client_builder.danger_accept_invalid_certs(true);
+
+        let response = client_builder
+            .build()
+            .unwrap_or_default()
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|err| {
+                trc::ResourceEvent::DownloadExternal
+                    .into_err()
+                    .ctx(trc::Key::Url, url.to_string())
+                    .reason(err)
+            })?;
+
+        if response.status().is_redirection() {
+            url = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| url.join(location).ok())
+                .ok_or_else(|| {
+                    trc::ResourceEvent::DownloadExternal
+                        .into_err()
+                        .details("Invalid redirect")
+                        .ctx(trc::Key::Url, url.to_string())
+                })?;
+            continue;
+        } else if !response.status().is_success() {
+            return Err(trc::ResourceEvent::DownloadExternal
+                .into_err()
+                .ctx(trc::Key::Url, url.to_string())
+                .ctx(trc::Key::Code, response.status().as_u16()));
+        }
+
+        // Only allow safe content types
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .and_then(|ct| ct.split(';').next())
+            .map(|ct| ct.trim().to_lowercase())
+            .unwrap_or_default();
+        if !config.remote_content_types.contains(&content_type) {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Content type is not allowed")
+                .ctx(trc::Key::Url, url.to_string())
+                .ctx(trc::Key::Contents, content_type));
+        }
+
+        let contents = response
+            .bytes_with_limit(config.remote_content_max_size)
+            .await
+            .map_err(|err| {
+                trc::ResourceEvent::DownloadExternal
+                    .into_err()
+                    .ctx(trc::Key::Url, url.to_string())
+                    .reason(err)
+            })?
+            .ok_or_else(|| {
+                trc::ResourceEvent::DownloadExternal
+                    .into_err()
+                    .details("Download exceeded maximum size")
+                    .ctx(trc::Key::Url, url.to_string())
+            })?;
+
+        trc::event!(
+            Resource(trc::ResourceEvent::DownloadExternal),
+            Url = url.to_string(),
+            Size = contents.len(),
+            Elapsed = start_time.elapsed(),
+        );
+
+        return Ok(RemoteContent {
+            content_type,
+            contents,
+        });
+    }
+
+    Err(trc::ResourceEvent::DownloadExternal
+        .into_err()
+        .details("Too many redirects")
+        .ctx(trc::Key::Url, url.to_string()))
+}
+
+// Rewrites the source of remote images so they are loaded through the proxy
+pub(crate) fn rewrite_remote_images(html: &str, rewrite: impl Fn(&str) -> String) -> String {
+    let mut result = String::with_capacity(html.len());
+    let mut pos = 0;
+
+    while let Some(tag_start) = html[pos..].find('<').map(|idx| pos + idx) {
+        let tag_end = match html[tag_start..].find('>') {
+            Some(idx) => tag_start + idx + 1,
+            None => break,
+        };
+        let tag = &html[tag_start..tag_end];
+        result.push_str(&html[pos..tag_start]);
+        pos = tag_end;
+
+        if tag.len() > 4
+            && tag[1..4].eq_ignore_ascii_case("img")
+            && tag[4..].starts_with(|ch: char| ch.is_ascii_whitespace() || ch == '/')
+        {
+            result.push_str(&rewrite_img_tag(tag, &rewrite));
+        } else {
+            result.push_str(tag);
+        }
+    }
+    result.push_str(&html[pos..]);
+
+    result
+}
+
+fn rewrite_img_tag(tag: &str, rewrite: &impl Fn(&str) -> String) -> String {
+    let mut result = String::with_capacity(tag.len() + 64);
+    let mut chars = tag.char_indices().peekable();
+    let mut last_pos = 0;
+
+    // Skip tag name
+    for (_, ch) in chars.by_ref() {
+        if ch.is_ascii_whitespace() {
+            break;
+        }
+    }
+
+    while let Some((name_start, ch)) = chars.next() {
+        if ch.is_ascii_whitespace() || ch == '/' || ch == '>' {
+            continue;
+        }
+
+        // Parse attribute name
+        let mut name_end = tag.len();
+        while let Some((pos, ch)) = chars.peek().copied() {
+            if ch.is_ascii_whitespace() || ch == '=' || ch == '>' || ch == '/' {
+                name_end = pos;
+                break;
+            }
+            chars.next();
+        }
+        let name = &tag[name_start..name_end];
+
+        // Parse attribute value
+        while chars.peek().is_some_and(|(_, ch)| ch.is_ascii_whitespace()) {
+            chars.next();
+        }
+        let mut value = None;
+        let mut attr_end = name_end;
+        if chars.peek().is_some_and(|(_, ch)| *ch == '=') {
+            chars.next();
+            while chars.peek().is_some_and(|(_, ch)| ch.is_ascii_whitespace()) {
+                chars.next();
+            }
+            if let Some((value_start, ch)) = chars.next() {
+                let (value_start, quote) = if ch == '"' || ch == '\'' {
+                    (value_start + 1, Some(ch))
+                } else {
+                    (value_start, None)
+                };
+                let mut value_end = tag.len() - 1;
+                attr_end = value_end;
+                for (pos, ch) in chars.by_ref() {
+                    if quote.map_or(ch.is_ascii_whitespace() || ch == '>', |quote| ch == quote) {
+                        value_end = pos;
+                        attr_end = pos + if quote.is_some() { 1 } else { 0 };
+                        break;
+                    }
+                }
+                value = tag.get(value_start..value_end);
+            }
+        }
+
+        if name.eq_ignore_ascii_case("src") {
+            if let Some(url) = value
+                .map(|url| url.trim().replace("&amp;", "&"))
+                .filter(|url| {
+                    url.get(..7)
+                        .is_some_and(|p| p.eq_ignore_ascii_case("http://"))
+                        || url
+                            .get(..8)
+                            .is_some_and(|p| p.eq_ignore_ascii_case("https://"))
+                })
+            {
+                result.push_str(&tag[last_pos..name_start]);
+                result.push_str("src=\"");
+                result.push_str(&rewrite(&url));
+                result.push('"');
+                last_pos = attr_end;
+            }
+        } else if name.eq_ignore_ascii_case("srcset") {
+            // Alternative sources would bypass the proxy
+            result.push_str(&tag[last_pos..name_start]);
+            last_pos = attr_end;
+        }
+    }
+    result.push_str(&tag[last_pos..]);
+
+    result
+}
@@ -40,6 +40,7 @@ impl EmailQuery for Server {
     ) -> trc::Result<QueryResponse> {
         let account_id = request.account_id.document_id();
         let mut filters = Vec::with_capacity(request.filter.len());
+        let mut has_fts_filter = false;
         let cached_messages = self
             .get_cached_messages(account_id)
             .await
@@ -48,6 +49,7 @@ impl EmailQuery for Server {
         for cond_group in std::mem::take(&mut request.filter).into_filter_group() {
             match cond_group {
                 FilterGroup::Fts(conds) => {
+                    has_fts_filter = true;
                     let mut fts_filters = Vec::with_capacity(filters.len());
                     for cond in conds {
                         match cond {
@@ -209,6 +211,15 @@ impl EmailQuery for Server {
                         Filter::After(date) => {
                             filters.push(query::Filter::gt(Property::ReceivedAt, date.serialize()))
                         }
+                        Filter::Header(header) => {
+                            // Only X-Original-To is handled by the store index
+                            if let Some(value) = header.get(1) {
+                                filters.push(query::Filter::eq(
+                                    Property::RcptTo,
+                                    value.trim().to_lowercase().into_bytes(),
+                                ));
+                            }
+                        }
                         Filter::MinSize(size) => {
                             filters.push(query::Filter::ge(Property::Size, size.serialize()))
                         }
@@ -303,10 +314,15 @@ impl EmailQuery for Server {
         if access_token.is_shared(account_id) {
             result_set.apply_mask(cached_messages.shared_messages(access_token, Acl::ReadItems));
         }
-        let (response, paginate) = self
+        let (mut response, paginate) = self
             .build_query_response(&result_set, cached_messages.get_state(false), &request)
             .await?;
 
+        // Recently delivered messages might not be searchable yet
+        if has_fts_filter && self.has_index_pending(account_id) {
+            response.index_pending = Some(true);
+        }
+
         if let Some(paginate) = paginate {
             // Parse sort criteria
             let mut comparators = Vec::with_capacity(request.sort.as_ref().map_or(1, |s| s.len()));
@@ -64,6 +64,7 @@ impl EmailSet for Server {
     ) -> trc::Result<SetResponse> {
         // Prepare response
         let account_id = request.account_id.document_id();
+        self.assert_account_not_in_maintenance(account_id).await?;
         let cache = self.get_cached_messages(account_id).await?;
         let mut response = self
             .prepare_set_response(&request, cache.assert_state(false, &request.if_in_state)?)
@@ -710,7 +711,9 @@ impl EmailSet for Server {
                     mailbox_ids: mailboxes,
                     keywords,
                     received_at,
-                    source: IngestSource::Jmap,
+                    source: IngestSource::Jmap {
+                        shared: !access_token.is_member(account_id),
+                    },
                     spam_classify: false,
                     spam_train: can_train_spam,
                     session_id: session.session_id,
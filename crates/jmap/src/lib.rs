@@ -118,6 +118,7 @@ impl JmapMethods for Server {
                     None
                 },
                 limit: if total > limit { Some(limit) } else { None },
+                index_pending: None,
             },
             if limit_total > 0 {
                 Pagination::new(
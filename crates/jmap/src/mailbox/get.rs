@@ -10,13 +10,11 @@ use jmap_proto::{
     method::get::{GetRequest, GetResponse, RequestArguments},
     types::{
         acl::Acl,
-        keyword::Keyword,
         property::Property,
         value::{Object, Value},
     },
 };
 use std::future::Future;
-use store::ahash::AHashSet;
 
 pub trait MailboxGet: Sync + Send {
     fn mailbox_get(
@@ -109,27 +107,25 @@ impl MailboxGet for Server {
                             Value::Null
                         }
                     }
-                    Property::TotalEmails => {
-                        Value::UnsignedInt(cache.in_mailbox(document_id).count() as u64)
-                    }
+                    Property::TotalEmails => Value::UnsignedInt(
+                        cache
+                            .mailbox_counters(document_id)
+                            .map_or(0, |c| c.total_emails) as u64,
+                    ),
                     Property::UnreadEmails => Value::UnsignedInt(
                         cache
-                            .in_mailbox_without_keyword(document_id, &Keyword::Seen)
-                            .count() as u64,
+                            .mailbox_counters(document_id)
+                            .map_or(0, |c| c.unread_emails) as u64,
                     ),
                     Property::TotalThreads => Value::UnsignedInt(
                         cache
-                            .in_mailbox(document_id)
-                            .map(|m| m.thread_id)
-                            .collect::<AHashSet<_>>()
-                            .len() as u64,
+                            .mailbox_counters(document_id)
+                            .map_or(0, |c| c.total_threads()) as u64,
                     ),
                     Property::UnreadThreads => Value::UnsignedInt(
                         cache
-                            .in_mailbox_without_keyword(document_id, &Keyword::Seen)
-                            .map(|m| m.thread_id)
-                            .collect::<AHashSet<_>>()
-                            .len() as u64,
+                            .mailbox_counters(document_id)
+                            .map_or(0, |c| c.unread_threads) as u64,
                     ),
                     Property::MyRights => {
                         if access_token.is_shared(account_id) {
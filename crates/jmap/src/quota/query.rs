@@ -37,6 +37,7 @@ impl QuotaQuery for Server {
             },
             total: Some(1),
             limit: None,
+            index_pending: None,
         })
 
         /*
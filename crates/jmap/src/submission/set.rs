@@ -45,7 +45,10 @@ use store::write::{BatchBuilder, now};
 use trc::AddContext;
 use utils::{BlobHash, map::vec_map::VecMap, sanitize_email};
 
-use crate::blob::download::BlobDownload;
+use crate::blob::{
+    download::BlobDownload,
+    scan::{BlobScan, ScanRequest},
+};
 use std::future::Future;
 
 pub trait EmailSubmissionSet: Sync + Send {
@@ -569,6 +572,20 @@ impl EmailSubmissionSet for Server {
                 .with_description("Blob for email not found.")));
         };
 
+        // Make sure the attachments have a recent verdict from the upload scanners
+        let access_token = self
+            .get_access_token(account_id)
+            .await
+            .caused_by(trc::location!())?;
+        if let Err(reason) = self
+            .upload_scan_message(ScanRequest::new(&access_token), &message)
+            .await?
+        {
+            return Ok(Err(
+                SetError::new(SetErrorType::ForbiddenToSend).with_description(reason)
+            ));
+        }
+
         // Remove BCC header if present
         if let Some(bcc_header) = bcc_header {
             let mut new_message = Vec::with_capacity(message.len());
@@ -581,15 +598,7 @@ impl EmailSubmissionSet for Server {
         let mut session = Session::<NullIo>::local(
             self.clone(),
             instance.clone(),
-            SessionData::local(
-                self.get_access_token(account_id)
-                    .await
-                    .caused_by(trc::location!())?,
-                None,
-                vec![],
-                vec![],
-                0,
-            ),
+            SessionData::local(access_token, None, vec![], vec![], 0),
         );
 
         // Spawn SMTP session to avoid overflowing the stack
@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use super::tree::ThreadTree;
 use crate::changes::state::StateManager;
 use common::Server;
 use email::cache::MessageCacheFetch;
@@ -61,9 +62,14 @@ impl ThreadGet for Server {
                 .map(Into::into)
                 .collect()
         };
-        let add_email_ids = request
-            .properties
-            .is_none_or(|p| p.unwrap().contains(&Property::EmailIds));
+        let properties = request.properties.map(|p| p.unwrap());
+        let add_email_ids = properties
+            .as_ref()
+            .is_none_or(|p| p.contains(&Property::EmailIds));
+        // The email tree is a vendor extension, only returned when explicitly requested
+        let add_email_tree = properties
+            .as_ref()
+            .is_some_and(|p| p.contains(&Property::EmailTree));
         let mut response = GetResponse {
             account_id: request.account_id.into(),
             state: self
@@ -78,25 +84,40 @@ impl ThreadGet for Server {
             let thread_id = id.document_id();
             if let Some(document_ids) = thread_map.remove(&thread_id) {
                 let mut thread = Object::with_capacity(2).with_property(Property::Id, id);
-                if add_email_ids {
+                if add_email_ids || add_email_tree {
                     let doc_count = document_ids.len() as usize;
-                    thread.append(
-                        Property::EmailIds,
-                        self.core
-                            .storage
-                            .data
-                            .sort(
-                                ResultSet::new(account_id, Collection::Email, document_ids),
-                                vec![Comparator::ascending(Property::ReceivedAt)],
-                                Pagination::new(doc_count, 0, None, 0),
-                            )
-                            .await
-                            .caused_by(trc::location!())?
-                            .ids
-                            .into_iter()
-                            .map(|id| Id::from_parts(thread_id, id as u32))
-                            .collect::<Vec<_>>(),
-                    );
+                    let document_ids = self
+                        .core
+                        .storage
+                        .data
+                        .sort(
+                            ResultSet::new(account_id, Collection::Email, document_ids),
+                            vec![Comparator::ascending(Property::ReceivedAt)],
+                            Pagination::new(doc_count, 0, None, 0),
+                        )
+                        .await
+                        .caused_by(trc::location!())?
+                        .ids
+                        .into_iter()
+                        .map(|id| id as u32)
+                        .collect::<Vec<_>>();
+
+                    if add_email_ids {
+                        thread.append(
+                            Property::EmailIds,
+                            document_ids
+                                .iter()
+                                .map(|&id| Id::from_parts(thread_id, id))
+                                .collect::<Vec<_>>(),
+                        );
+                    }
+                    if add_email_tree {
+                        let tree = self
+                            .thread_email_tree(account_id, thread_id, &document_ids)
+                            .await?;
+                        thread.append(Property::EmailTree, tree.nodes);
+                        thread.append(Property::IsTruncated, tree.is_truncated);
+                    }
                 }
                 response.list.push(thread);
             } else {
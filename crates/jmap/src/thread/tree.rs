@@ -0,0 +1,196 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::Server;
+use email::message::{
+    index::{MAX_ID_LENGTH, VisitTextArchived},
+    metadata::MessageMetadata,
+};
+use jmap_proto::types::{
+    collection::Collection,
+    id::Id,
+    property::Property,
+    value::{Object, Value},
+};
+use mail_parser::{ArchivedHeaderName, parsers::fields::thread::thread_name};
+use std::future::Future;
+use store::ahash::AHashMap;
+use trc::AddContext;
+
+pub trait ThreadTree: Sync + Send {
+    fn thread_email_tree(
+        &self,
+        account_id: u32,
+        thread_id: u32,
+        document_ids: &[u32],
+    ) -> impl Future<Output = trc::Result<EmailTree>> + Send;
+}
+
+pub struct EmailTree {
+    pub nodes: Vec<Value>,
+    pub is_truncated: bool,
+}
+
+#[derive(Default)]
+struct TreeNode {
+    document_id: u32,
+    message_ids: Vec<String>,
+    // Closest ancestor first
+    parent_ids: Vec<String>,
+    base_subject: String,
+    is_reply: bool,
+}
+
+impl ThreadTree for Server {
+    async fn thread_email_tree(
+        &self,
+        account_id: u32,
+        thread_id: u32,
+        document_ids: &[u32],
+    ) -> trc::Result<EmailTree> {
+        // Only the stored message metadata is read, message blobs are never fetched
+        let max_nodes = self.core.jmap.thread_max_tree_nodes;
+        let mut nodes = Vec::with_capacity(document_ids.len().min(max_nodes));
+        for &document_id in document_ids.iter().take(max_nodes) {
+            let Some(metadata_) = self
+                .get_archive_by_property(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    &Property::BodyStructure,
+                )
+                .await
+                .caused_by(trc::location!())?
+            else {
+                continue;
+            };
+            let metadata = metadata_
+                .unarchive::<MessageMetadata>()
+                .caused_by(trc::location!())?;
+
+            let mut node = TreeNode {
+                document_id,
+                ..Default::default()
+            };
+            let mut in_reply_to = Vec::new();
+            let mut references = Vec::new();
+            let mut subject = None;
+            for header in metadata.root_part().headers.iter() {
+                let ids = match header.name {
+                    ArchivedHeaderName::MessageId => &mut node.message_ids,
+                    ArchivedHeaderName::InReplyTo => &mut in_reply_to,
+                    ArchivedHeaderName::References => &mut references,
+                    ArchivedHeaderName::Subject if subject.is_none() => {
+                        header.value.visit_text(|text| {
+                            if subject.is_none() {
+                                subject = Some(text.trim().to_string());
+                            }
+                        });
+                        continue;
+                    }
+                    _ => continue,
+                };
+                header.value.visit_text(|id| {
+                    if !id.is_empty() && id.len() < MAX_ID_LENGTH {
+                        ids.push(id.to_string());
+                    }
+                });
+            }
+
+            // The last entry in References is the parent, In-Reply-To is used when
+            // References is missing or does not resolve to a message in the thread
+            node.parent_ids = references.into_iter().rev().collect();
+            for id in in_reply_to {
+                if !node.parent_ids.contains(&id) {
+                    node.parent_ids.push(id);
+                }
+            }
+            let subject = subject.unwrap_or_default();
+            node.base_subject = thread_name(&subject).to_string();
+            node.is_reply = node.base_subject != subject;
+            nodes.push(node);
+        }
+
+        let tree = build_tree(&nodes);
+        Ok(EmailTree {
+            nodes: nodes
+                .iter()
+                .zip(tree)
+                .map(|(node, (parent, is_parent_missing))| {
+                    Object::with_capacity(3)
+                        .with_property(
+                            Property::EmailId,
+                            Id::from_parts(thread_id, node.document_id),
+                        )
+                        .with_property(
+                            Property::ParentId,
+                            parent.map(|pos| Id::from_parts(thread_id, nodes[pos].document_id)),
+                        )
+                        .with_property(Property::IsParentMissing, is_parent_missing)
+                        .into()
+                })
+                .collect(),
+            is_truncated: document_ids.len() > max_nodes,
+        })
+    }
+}
+
+// Returns the position of each node's parent and whether its actual parent is
+// missing from the thread. Nodes are expected to be sorted by date.
+fn build_tree(nodes: &[TreeNode]) -> Vec<(Option<usize>, bool)> {
+    let mut by_message_id: AHashMap<&str, usize> = AHashMap::with_capacity(nodes.len());
+    for (pos, node) in nodes.iter().enumerate() {
+        for message_id in &node.message_ids {
+            by_message_id.entry(message_id.as_str()).or_insert(pos);
+        }
+    }
+
+    let mut parents: Vec<Option<usize>> = vec![None; nodes.len()];
+    let mut is_parent_missing = vec![false; nodes.len()];
+    for (pos, node) in nodes.iter().enumerate() {
+        is_parent_missing[pos] = node
+            .parent_ids
+            .first()
+            .is_some_and(|id| !by_message_id.contains_key(id.as_str()));
+
+        // Attach to the closest ancestor present in the thread, skipping any
+        // that would create a loop
+        parents[pos] = node
+            .parent_ids
+            .iter()
+            .filter_map(|id| by_message_id.get(id.as_str()).copied())
+            .find(|&parent| !is_ancestor(&parents, pos, parent));
+    }
+
+    // Replies without a resolvable parent are attached to the earliest message
+    // with the same base subject that is not a reply itself
+    for (pos, node) in nodes.iter().enumerate() {
+        if parents[pos].is_none() && (node.is_reply || !node.parent_ids.is_empty()) {
+            if let Some(root) = (0..pos).find(|&root| {
+                parents[root].is_none()
+                    && !nodes[root].is_reply
+                    && nodes[root].base_subject == node.base_subject
+            }) {
+                parents[pos] = Some(root);
+                is_parent_missing[pos] = true;
+            }
+        }
+    }
+
+    parents.into_iter().zip(is_parent_missing).collect()
+}
+
+fn is_ancestor(parents: &[Option<usize>], ancestor: usize, mut pos: usize) -> bool {
+    loop {
+        if pos == ancestor {
+            return true;
+        }
+        match parents[pos] {
+            Some(parent) => pos = parent,
+            None => return false,
+        }
+    }
+}
@@ -7,6 +7,7 @@
 use std::{sync::Arc, time::Instant};
 
 use common::{Server, auth::AccessToken};
+use email::cache::MessageCacheFetch;
 use futures_util::{SinkExt, StreamExt};
 use http_proto::HttpSessionData;
 use hyper::upgrade::Upgraded;
@@ -187,6 +188,28 @@ impl WebSocketHandler for Server {
                                 .get_mut_or_insert(state_change.account_id.into())
                                 .set(type_state, state_change.change_id.into());
                         }
+
+                        // Include the updated mailbox counters
+                        if state_change.account_id == access_token.primary_id()
+                            && types.contains(DataType::Email)
+                        {
+                            match self
+                                .get_mailbox_counts(state_change.account_id, state_change.change_id)
+                                .await
+                            {
+                                Ok(counts) => {
+                                    let account_counts = changes
+                                        .mailbox_counts
+                                        .get_mut_or_insert(state_change.account_id.into());
+                                    for (mailbox_id, counts) in counts {
+                                        account_counts.set(mailbox_id.into(), counts);
+                                    }
+                                }
+                                Err(err) => {
+                                    trc::error!(err.span_id(session.session_id));
+                                }
+                            }
+                        }
                     } else {
                         trc::event!(
                             Jmap(JmapEvent::WebsocketStop),
@@ -212,6 +235,7 @@ impl WebSocketHandler for Server {
                         );
                     }
                     changes.changed.clear();
+                    changes.mailbox_counts.clear();
                     last_changes_sent = Instant::now();
                     last_heartbeat = Instant::now();
                     next_event = heartbeat;
@@ -35,41 +35,64 @@ impl WebSocketUpgrade for Server {
         session: HttpSessionData,
     ) -> trc::Result<HttpResponse> {
         let headers = req.headers();
-        if headers
-            .get(hyper::header::CONNECTION)
-            .and_then(|h| h.to_str().ok())
-            != Some("Upgrade")
-            || headers
-                .get(hyper::header::UPGRADE)
+        let derived_key = if req.method() == hyper::Method::CONNECT {
+            // RFC 8441 extended CONNECT over HTTP/2
+            if req
+                .extensions()
+                .get::<hyper::ext::Protocol>()
+                .is_none_or(|protocol| protocol.as_str() != "websocket")
+                || headers
+                    .get("Sec-WebSocket-Version")
+                    .and_then(|h| h.to_str().ok())
+                    != Some("13")
+            {
+                return Err(trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .details("WebSocket upgrade failed")
+                    .ctx(
+                        trc::Key::Reason,
+                        "Missing or Invalid :protocol or Sec-WebSocket-Version headers.",
+                    ));
+            }
+
+            None
+        } else {
+            if headers
+                .get(hyper::header::CONNECTION)
                 .and_then(|h| h.to_str().ok())
-                != Some("websocket")
-        {
-            return Err(trc::ResourceEvent::BadParameters
-                .into_err()
-                .details("WebSocket upgrade failed")
-                .ctx(
-                    trc::Key::Reason,
-                    "Missing or Invalid Connection or Upgrade headers.",
-                ));
-        }
-        let derived_key = match (
-            headers
-                .get("Sec-WebSocket-Key")
-                .and_then(|h| h.to_str().ok()),
-            headers
-                .get("Sec-WebSocket-Version")
-                .and_then(|h| h.to_str().ok()),
-        ) {
-            (Some(key), Some("13")) => derive_accept_key(key.as_bytes()),
-            _ => {
+                != Some("Upgrade")
+                || headers
+                    .get(hyper::header::UPGRADE)
+                    .and_then(|h| h.to_str().ok())
+                    != Some("websocket")
+            {
                 return Err(trc::ResourceEvent::BadParameters
                     .into_err()
                     .details("WebSocket upgrade failed")
                     .ctx(
                         trc::Key::Reason,
-                        "Missing or Invalid Sec-WebSocket-Key headers.",
+                        "Missing or Invalid Connection or Upgrade headers.",
                     ));
             }
+            match (
+                headers
+                    .get("Sec-WebSocket-Key")
+                    .and_then(|h| h.to_str().ok()),
+                headers
+                    .get("Sec-WebSocket-Version")
+                    .and_then(|h| h.to_str().ok()),
+            ) {
+                (Some(key), Some("13")) => Some(derive_accept_key(key.as_bytes())),
+                _ => {
+                    return Err(trc::ResourceEvent::BadParameters
+                        .into_err()
+                        .details("WebSocket upgrade failed")
+                        .ctx(
+                            trc::Key::Reason,
+                            "Missing or Invalid Sec-WebSocket-Key headers.",
+                        ));
+                }
+            }
         };
 
         // Spawn WebSocket connection
@@ -104,6 +127,11 @@ impl WebSocketUpgrade for Server {
             }
         });
 
-        Ok(HttpResponse::new(StatusCode::SWITCHING_PROTOCOLS).with_websocket_upgrade(derived_key))
+        Ok(HttpResponse::new(if derived_key.is_some() {
+            StatusCode::SWITCHING_PROTOCOLS
+        } else {
+            StatusCode::OK
+        })
+        .with_websocket_upgrade(derived_key))
     }
 }
@@ -87,6 +87,10 @@ async fn main() -> std::io::Result<()> {
         };
     });
 
+    // Reload certificates on SIGHUP
+    #[cfg(not(target_env = "msvc"))]
+    spawn_certificate_reloader(init.inner.clone());
+
     // Start broadcast subscriber
     spawn_broadcast_subscriber(init.inner, shutdown_rx);
 
@@ -104,3 +108,28 @@ async fn main() -> std::io::Result<()> {
 
     Ok(())
 }
+
+#[cfg(not(target_env = "msvc"))]
+fn spawn_certificate_reloader(inner: std::sync::Arc<common::Inner>) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut h_hup = match signal(SignalKind::hangup()) {
+        Ok(h_hup) => h_hup,
+        Err(err) => {
+            trc::event!(
+                Server(trc::ServerEvent::ThreadError),
+                Details = "Unable to listen for SIGHUP",
+                Reason = err.to_string(),
+            );
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        while h_hup.recv().await.is_some() {
+            if let Err(err) = inner.build_server().reload_certificates().await {
+                trc::error!(err.details("Failed to reload certificates"));
+            }
+        }
+    });
+}
@@ -8,6 +8,7 @@ use common::{
     KV_RATE_LIMIT_IMAP,
     listener::{SessionResult, SessionStream},
 };
+use directory::core::protocols::Protocol;
 use imap_proto::receiver::{self, Request};
 use jmap_proto::types::{collection::Collection, property::Property};
 use store::query::Filter;
@@ -18,6 +19,24 @@ use super::{Command, ResponseCode, SerializeResponse, Session, State};
 
 impl<T: SessionStream> Session<T> {
     pub async fn ingest(&mut self, bytes: &[u8]) -> SessionResult {
+        // Terminate the session if ManageSieve was disabled for this account
+        if let State::Authenticated { access_token, .. } = &self.state {
+            if self
+                .server
+                .is_protocol_revoked(
+                    access_token.primary_id(),
+                    Protocol::ManageSieve,
+                    self.session_id,
+                )
+                .await
+            {
+                self.write(b"BYE \"protocol disabled for this account\"\r\n")
+                    .await
+                    .ok();
+                return SessionResult::Close;
+            }
+        }
+
         let mut bytes = bytes.iter();
         let mut requests = Vec::with_capacity(2);
         let mut needs_literal = None;
@@ -41,7 +60,7 @@ impl<T: SessionStream> Session<T> {
                         }
                     }
                 },
-                Err(receiver::Error::NeedsMoreData) => {
+                Err(receiver::Error::NeedsMoreData | receiver::Error::AppendLiteral { .. }) => {
                     break;
                 }
                 Err(receiver::Error::NeedsLiteral { size }) => {
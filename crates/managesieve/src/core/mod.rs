@@ -103,6 +103,10 @@ impl CommandParser for Command {
     fn tokenize_brackets(&self) -> bool {
         false
     }
+
+    fn is_append(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
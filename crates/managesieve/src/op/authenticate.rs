@@ -12,7 +12,7 @@ use common::{
     listener::{SessionStream, limiter::LimiterResult},
 };
 
-use directory::Permission;
+use directory::{Permission, core::protocols::Protocol};
 use imap_proto::{
     protocol::authenticate::Mechanism,
     receiver::{self, Request},
@@ -99,6 +99,7 @@ impl<T: SessionStream> Session<T> {
             .and_then(|token| {
                 token
                     .assert_has_permission(Permission::SieveAuthenticate)
+                    .and_then(|_| token.assert_protocol_enabled(Protocol::ManageSieve))
                     .map(|_| token)
             })?;
 
@@ -5,7 +5,7 @@
  */
 
 use common::Server;
-use email::message::crypto::EncryptionParams;
+use email::message::crypto::{Algorithm, EncryptionMethod, EncryptionParams, EncryptionParamsV1};
 use jmap_proto::types::{collection::Collection, property::Property};
 use store::{
     Deserialize, Serialize, ValueKey,
@@ -35,7 +35,7 @@ pub(crate) async fn migrate_encryption_params(
                 .update_document(0)
                 .set(
                     Property::Parameters,
-                    Archiver::new(legacy.0)
+                    Archiver::new(vec![legacy.0])
                         .serialize()
                         .caused_by(trc::location!())?,
                 );
@@ -67,16 +67,86 @@ pub(crate) async fn migrate_encryption_params(
     Ok(0)
 }
 
+pub(crate) async fn migrate_encryption_params_v2(
+    server: &Server,
+    account_id: u32,
+) -> trc::Result<u64> {
+    let Some(archive) = server
+        .store()
+        .get_value::<Archive<AlignedBytes>>(ValueKey {
+            account_id,
+            collection: Collection::Principal.into(),
+            document_id: 0,
+            class: ValueClass::Property(Property::Parameters.into()),
+        })
+        .await
+        .caused_by(trc::location!())?
+    else {
+        return Ok(0);
+    };
+
+    // Version 1 stored a single identity per account, accounts already converted
+    // by an interrupted migration are left as they are
+    let params = match archive.deserialize::<EncryptionParamsV1>() {
+        Ok(params) => EncryptionParams::from(params),
+        Err(_) if archive.deserialize::<Vec<EncryptionParams>>().is_ok() => {
+            return Ok(0);
+        }
+        Err(err) => {
+            return Err(err
+                .account_id(account_id)
+                .details("Failed to decode encryption params")
+                .caused_by(trc::location!()));
+        }
+    };
+
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(account_id)
+        .with_collection(Collection::Principal)
+        .update_document(0)
+        .set(
+            Property::Parameters,
+            Archiver::new(vec![params])
+                .serialize()
+                .caused_by(trc::location!())?,
+        );
+    server
+        .store()
+        .write(batch.build_all())
+        .await
+        .caused_by(trc::location!())?;
+
+    Ok(1)
+}
+
 struct LegacyEncryptionParams(EncryptionParams);
 
+#[derive(serde::Deserialize)]
+struct LegacyEncryptionParamsV1 {
+    method: EncryptionMethod,
+    algo: Algorithm,
+    certs: Vec<Vec<u8>>,
+}
+
 impl Deserialize for LegacyEncryptionParams {
     fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
         let version = *bytes
             .first()
             .ok_or_else(|| trc::StoreEvent::DataCorruption.caused_by(trc::location!()))?;
         match version {
-            1 if bytes.len() > 1 => bincode::deserialize(&bytes[1..])
-                .map(LegacyEncryptionParams)
+            1 if bytes.len() > 1 => bincode::deserialize::<LegacyEncryptionParamsV1>(&bytes[1..])
+                .map(|params| {
+                    LegacyEncryptionParams(EncryptionParams {
+                        method: params.method,
+                        algo: params.algo,
+                        padding: Default::default(),
+                        certs: params.certs,
+                        exclude_mailboxes: Vec::new(),
+                        max_encrypt_size: None,
+                        compression: None,
+                    })
+                })
                 .map_err(|err| {
                     trc::EventType::Store(trc::StoreEvent::DeserializeError)
                         .reason(err)
@@ -8,6 +8,7 @@ use std::time::Duration;
 
 use changelog::reset_changelog;
 use common::{DATABASE_SCHEMA_VERSION, KV_LOCK_HOUSEKEEPER, Server};
+use encryption::migrate_encryption_params_v2;
 use jmap_proto::types::{collection::Collection, property::Property};
 use principal::{migrate_principal, migrate_principals};
 use queue::migrate_queue;
@@ -40,20 +41,21 @@ const LOCK_WAIT_TIME_CORE: u64 = 5 * 60;
 const LOCK_RETRY_TIME: Duration = Duration::from_secs(30);
 
 pub async fn try_migrate(server: &Server) -> trc::Result<()> {
-    if server
+    let version = server
         .store()
         .get_value::<u32>(AnyKey {
             subspace: SUBSPACE_PROPERTY,
             key: vec![0u8],
         })
         .await
-        .caused_by(trc::location!())?
-        == Some(DATABASE_SCHEMA_VERSION)
-    {
+        .caused_by(trc::location!())?;
+    if version == Some(DATABASE_SCHEMA_VERSION) {
         return Ok(());
     }
 
-    if !is_new_install(server).await.caused_by(trc::location!())? {
+    if version == Some(1) {
+        migrate_v2(server).await.caused_by(trc::location!())?;
+    } else if !is_new_install(server).await.caused_by(trc::location!())? {
         let force_lock = std::env::var("FORCE_LOCK").is_ok();
         let in_memory = server.in_memory_store();
         let principal_ids;
@@ -225,6 +227,78 @@ pub async fn try_migrate(server: &Server) -> trc::Result<()> {
     Ok(())
 }
 
+async fn migrate_v2(server: &Server) -> trc::Result<()> {
+    let force_lock = std::env::var("FORCE_LOCK").is_ok();
+    let in_memory = server.in_memory_store();
+
+    loop {
+        if force_lock
+            || in_memory
+                .try_lock(KV_LOCK_HOUSEKEEPER, b"migrate_v2_lock", LOCK_WAIT_TIME_CORE)
+                .await
+                .caused_by(trc::location!())?
+        {
+            if in_memory
+                .key_get::<()>(KeyValue::<()>::build_key(
+                    KV_LOCK_HOUSEKEEPER,
+                    b"migrate_v2_done",
+                ))
+                .await
+                .caused_by(trc::location!())?
+                .is_none()
+            {
+                let mut num_params = 0;
+                for account_id in server
+                    .get_document_ids(u32::MAX, Collection::Principal)
+                    .await
+                    .caused_by(trc::location!())?
+                    .unwrap_or_default()
+                {
+                    num_params += migrate_encryption_params_v2(server, account_id)
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+
+                in_memory
+                    .key_set(
+                        KeyValue::new(
+                            KeyValue::<()>::build_key(KV_LOCK_HOUSEKEEPER, b"migrate_v2_done"),
+                            b"1".to_vec(),
+                        )
+                        .expires(86400),
+                    )
+                    .await
+                    .caused_by(trc::location!())?;
+
+                trc::event!(
+                    Server(trc::ServerEvent::Startup),
+                    Details =
+                        format!("Migrated {num_params} encryption params to schema version 2.")
+                );
+            } else {
+                trc::event!(
+                    Server(trc::ServerEvent::Startup),
+                    Details = format!("Migration completed by another node.",)
+                );
+            }
+
+            in_memory
+                .remove_lock(KV_LOCK_HOUSEKEEPER, b"migrate_v2_lock")
+                .await
+                .caused_by(trc::location!())?;
+
+            return Ok(());
+        } else {
+            trc::event!(
+                Server(trc::ServerEvent::Startup),
+                Details = format!("Migration lock busy, waiting 30 seconds.",)
+            );
+
+            tokio::time::sleep(LOCK_RETRY_TIME).await;
+        }
+    }
+}
+
 async fn is_new_install(server: &Server) -> trc::Result<bool> {
     for subspace in [
         SUBSPACE_QUEUE_MESSAGE,
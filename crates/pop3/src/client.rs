@@ -8,6 +8,7 @@ use common::{
     KV_RATE_LIMIT_IMAP,
     listener::{SessionResult, SessionStream},
 };
+use directory::core::protocols::Protocol;
 use mail_send::Credentials;
 use trc::{AddContext, SecurityEvent};
 
@@ -25,6 +26,20 @@ impl<T: SessionStream> Session<T> {
             Contents = trc::Value::from_maybe_string(bytes),
         );
 
+        // Terminate the session if POP3 was disabled for this account
+        if let State::Authenticated { access_token, .. } = &self.state {
+            if self
+                .server
+                .is_protocol_revoked(access_token.primary_id(), Protocol::Pop3, self.session_id)
+                .await
+            {
+                self.write_bytes(b"-ERR protocol disabled for this account\r\n")
+                    .await
+                    .ok();
+                return SessionResult::Close;
+            }
+        }
+
         let mut bytes = bytes.iter();
         let mut requests = Vec::with_capacity(2);
 
@@ -188,7 +203,14 @@ impl<T: SessionStream> Session<T> {
             | Command::Pass { .. }
             | Command::Apop { .. } => {
                 if let State::NotAuthenticated { username, .. } = &self.state {
-                    if self.stream.is_tls() || self.server.core.imap.allow_plain_auth {
+                    if (self.stream.is_tls() || self.server.core.imap.allow_plain_auth)
+                        && !self.is_mechanism_hidden(&Mechanism::Plain)
+                        && (matches!(command, Command::Auth { .. } | Command::Apop { .. })
+                            || !self
+                                .instance
+                                .banner
+                                .is_hidden("USER", self.stream.is_tls(), false))
+                    {
                         if !matches!(command, Command::Pass { .. }) || username.is_some() {
                             Ok(command)
                         } else {
@@ -207,9 +229,15 @@ impl<T: SessionStream> Session<T> {
                         .details("Already authenticated."))
                 }
             }
-            Command::Auth { .. } => {
+            Command::Auth { mechanism, .. } => {
                 if let State::NotAuthenticated { .. } = &self.state {
-                    Ok(command)
+                    if !self.is_mechanism_hidden(mechanism) {
+                        Ok(command)
+                    } else {
+                        Err(trc::Pop3Event::Error
+                            .into_err()
+                            .details("Authentication mechanism not supported."))
+                    }
                 } else {
                     Err(trc::Pop3Event::Error
                         .into_err()
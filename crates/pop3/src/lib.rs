@@ -20,7 +20,8 @@ pub mod op;
 pub mod protocol;
 pub mod session;
 
-static SERVER_GREETING: &str = "+OK Stalwart POP3 at your service.\r\n";
+static SERVER_GREETING: &str = "Stalwart POP3 at your service.";
+static SERVER_GREETING_NO_PRODUCT: &str = "POP3 at your service.";
 
 #[derive(Clone)]
 pub struct Pop3SessionManager {
@@ -11,7 +11,7 @@ use common::{
     },
     listener::{SessionStream, limiter::LimiterResult},
 };
-use directory::Permission;
+use directory::{Permission, core::protocols::Protocol};
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
 
@@ -97,6 +97,7 @@ impl<T: SessionStream> Session<T> {
             .and_then(|token| {
                 token
                     .assert_has_permission(Permission::Pop3Authenticate)
+                    .and_then(|_| token.assert_protocol_enabled(Protocol::Pop3))
                     .map(|_| token)
             })?;
 
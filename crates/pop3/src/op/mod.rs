@@ -4,10 +4,12 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::borrow::Cow;
+
 use common::listener::SessionStream;
 
 use crate::{
-    Session,
+    SERVER_GREETING, SERVER_GREETING_NO_PRODUCT, Session, State,
     protocol::{Mechanism, response::Response},
 };
 
@@ -17,31 +19,70 @@ pub mod fetch;
 pub mod list;
 
 impl<T: SessionStream> Session<T> {
+    pub async fn write_greeting(&mut self) -> trc::Result<()> {
+        let banner = &self.instance.banner;
+        let greeting = banner
+            .greeting(
+                &self.server.core.network.server_name,
+                &self.instance.id,
+                self.instance.protocol,
+            )
+            .map(Cow::Owned)
+            .unwrap_or(Cow::Borrowed(if banner.hide_product {
+                SERVER_GREETING_NO_PRODUCT
+            } else {
+                SERVER_GREETING
+            }));
+
+        self.write_ok(greeting).await
+    }
+
     pub async fn handle_capa(&mut self) -> trc::Result<()> {
-        let mechanisms = if self.stream.is_tls() || self.server.core.imap.allow_plain_auth {
+        let is_tls = self.stream.is_tls();
+        let mut mechanisms = if is_tls || self.server.core.imap.allow_plain_auth {
             vec![Mechanism::Plain, Mechanism::OAuthBearer, Mechanism::XOauth2]
         } else {
             vec![Mechanism::OAuthBearer, Mechanism::XOauth2]
         };
+        mechanisms.retain(|mechanism| !self.is_mechanism_hidden(mechanism));
 
         trc::event!(
             Pop3(trc::Pop3Event::Capabilities),
             SpanId = self.session_id,
-            Tls = self.stream.is_tls(),
+            Tls = is_tls,
             Strict = !self.server.core.imap.allow_plain_auth,
             Elapsed = trc::Value::Duration(0)
         );
 
+        let is_authenticated = matches!(self.state, State::Authenticated { .. });
         self.write_bytes(
             Response::Capability::<u32> {
+                user: mechanisms.contains(&Mechanism::Plain)
+                    && !self
+                        .instance
+                        .banner
+                        .is_hidden("USER", is_tls, is_authenticated),
+                implementation: !self.instance.banner.hide_product
+                    && !self
+                        .instance
+                        .banner
+                        .is_hidden("IMPLEMENTATION", is_tls, is_authenticated),
                 mechanisms,
-                stls: !self.stream.is_tls(),
+                stls: !is_tls,
             }
             .serialize(),
         )
         .await
     }
 
+    pub fn is_mechanism_hidden(&self, mechanism: &Mechanism) -> bool {
+        self.instance.banner.is_hidden(
+            &format!("AUTH={}", mechanism.as_str()),
+            self.stream.is_tls(),
+            false,
+        )
+    }
+
     pub async fn handle_stls(&mut self) -> trc::Result<()> {
         trc::event!(
             Pop3(trc::Pop3Event::StartTls),
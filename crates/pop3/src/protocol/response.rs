@@ -19,6 +19,8 @@ pub enum Response<T> {
     Capability {
         mechanisms: Vec<Mechanism>,
         stls: bool,
+        user: bool,
+        implementation: bool,
     },
 }
 
@@ -88,11 +90,16 @@ impl<T: Display> Response<T> {
                 buf.extend_from_slice(b".\r\n");
                 buf
             }
-            Response::Capability { mechanisms, stls } => {
+            Response::Capability {
+                mechanisms,
+                stls,
+                user,
+                implementation,
+            } => {
                 let mut buf = Vec::with_capacity(256);
                 buf.extend_from_slice(b"+OK Capability list follows\r\n");
                 if !mechanisms.is_empty() {
-                    if mechanisms.contains(&Mechanism::Plain) {
+                    if *user {
                         buf.extend_from_slice(b"USER\r\n");
                     }
                     buf.extend_from_slice(b"SASL");
@@ -114,11 +121,13 @@ impl<T: Display> Response<T> {
                     "EXPIRE NEVER",
                     "UIDL",
                     "UTF8",
-                    "IMPLEMENTATION Stalwart Server",
                 ] {
                     buf.extend_from_slice(capa.as_bytes());
                     buf.extend_from_slice(b"\r\n");
                 }
+                if *implementation {
+                    buf.extend_from_slice(b"IMPLEMENTATION Stalwart Server\r\n");
+                }
 
                 buf.extend_from_slice(b".\r\n");
                 buf
@@ -189,6 +198,8 @@ mod tests {
                 Response::Capability {
                     mechanisms: vec![Mechanism::Plain, Mechanism::CramMd5],
                     stls: true,
+                    user: true,
+                    implementation: true,
                 },
                 concat!(
                     "+OK Capability list follows\r\n",
@@ -13,7 +13,7 @@ use common::{
 use tokio_rustls::server::TlsStream;
 
 use crate::{
-    Pop3SessionManager, SERVER_GREETING, Session, State,
+    Pop3SessionManager, Session, State,
     protocol::{
         request::Parser,
         response::{Response, SerializeResponse},
@@ -43,10 +43,7 @@ impl SessionManager for Pop3SessionManager {
                 session_id: session.session_id,
             };
 
-            if session
-                .write_bytes(SERVER_GREETING.as_bytes())
-                .await
-                .is_ok()
+            if session.write_greeting().await.is_ok()
                 && session.handle_conn().await
                 && session.instance.acceptor.is_tls()
             {
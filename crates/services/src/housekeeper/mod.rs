@@ -13,22 +13,31 @@ use std::{
 
 use common::{
     Inner, KV_LOCK_HOUSEKEEPER, LONG_1D_SLUMBER, Server,
-    config::telemetry::OtelMetrics,
+    config::{maintenance::MaintenanceTask, telemetry::OtelMetrics},
     core::BuildServer,
     ipc::{BroadcastEvent, HousekeeperEvent, PurgeType},
+    manager::{
+        fetch_resource,
+        maintenance::{RunningTaskGuard, TaskRun, TaskRunStatus, TaskTrigger},
+    },
 };
 
 #[cfg(feature = "enterprise")]
-use common::telemetry::{
-    metrics::store::{MetricsStore, SharedMetricHistory},
-    tracers::store::TracingStore,
+use common::{
+    config::smtp::system::TEMPLATE_METRICS_ALERT,
+    telemetry::{
+        metrics::store::{MetricsStore, SharedMetricHistory},
+        tracers::store::TracingStore,
+    },
 };
 
-use email::message::delete::EmailDeletion;
-use smtp::reporting::SmtpReporting;
+use email::message::{delete::EmailDeletion, integrity::EmailIntegrity, pack::EmailPacking};
+#[cfg(feature = "enterprise")]
+use smtp::reporting::system::{SystemMessage, SystemMessages};
 use store::{PurgeStore, write::now};
 use tokio::sync::mpsc;
 use trc::{Collector, MetricType, PurgeEvent};
+use utils::suffixlist::{PublicSuffix, set_public_suffix};
 
 #[derive(PartialEq, Eq)]
 struct Action {
@@ -39,12 +48,17 @@ struct Action {
 #[derive(PartialEq, Eq, Debug)]
 enum ActionClass {
     Account,
+    BlobPack,
+    IntegrityAudit,
     Store(usize),
     Acme(String),
+    Ocsp,
+    TlsTicketKeys,
     OtelMetrics,
     #[cfg(feature = "enterprise")]
     InternalMetrics,
     CalculateMetrics,
+    PublicSuffix,
     #[cfg(feature = "enterprise")]
     AlertMetrics,
     #[cfg(feature = "enterprise")]
@@ -77,6 +91,22 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                 );
             }
 
+            // Message packing
+            if server.core.jmap.mail_pack_enable && server.core.network.roles.purge_accounts {
+                queue.schedule(
+                    Instant::now() + server.core.jmap.mail_pack_frequency.time_to_next(),
+                    ActionClass::BlobPack,
+                );
+            }
+
+            // Message integrity audit
+            if server.core.jmap.mail_audit_enable && server.core.network.roles.purge_accounts {
+                queue.schedule(
+                    Instant::now() + server.core.jmap.mail_audit_frequency.time_to_next(),
+                    ActionClass::IntegrityAudit,
+                );
+            }
+
             // Store purges
             if server.core.network.roles.purge_stores {
                 for (idx, schedule) in server.core.storage.purge_schedules.iter().enumerate() {
@@ -98,6 +128,11 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
             // Calculate expensive metrics
             queue.schedule(Instant::now(), ActionClass::CalculateMetrics);
 
+            // Public suffix list refresh
+            if server.core.network.public_suffix.is_some() {
+                queue.schedule(Instant::now(), ActionClass::PublicSuffix);
+            }
+
             // Add all ACME renewals to heap
             if server.core.network.roles.renew_acme {
                 for provider in server.core.acme.providers.values() {
@@ -117,6 +152,12 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                 }
             }
 
+            // Staple OCSP responses to all served certificates
+            queue.schedule(Instant::now(), ActionClass::Ocsp);
+
+            // Share TLS session ticket keys with other nodes
+            queue.schedule(Instant::now(), ActionClass::TlsTicketKeys);
+
             // SPDX-SnippetBegin
             // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
             // SPDX-License-Identifier: LicenseRef-SEL
@@ -171,6 +212,17 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                 _ => {}
                             }
 
+                            // Apply the ticket key rotation interval
+                            queue.remove_action(&ActionClass::TlsTicketKeys);
+                            queue.schedule(Instant::now(), ActionClass::TlsTicketKeys);
+
+                            // Download the public suffix list if mirrors were added
+                            if server.core.network.public_suffix.is_some()
+                                && !queue.has_action(&ActionClass::PublicSuffix)
+                            {
+                                queue.schedule(Instant::now(), ActionClass::PublicSuffix);
+                            }
+
                             // SPDX-SnippetBegin
                             // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
                             // SPDX-License-Identifier: LicenseRef-SEL
@@ -223,6 +275,17 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                         }
                                     };
                                 }
+
+                                // Staple OCSP responses to reloaded certificates
+                                server
+                                    .inner
+                                    .ipc
+                                    .housekeeper_tx
+                                    .send(HousekeeperEvent::OcspReschedule {
+                                        refresh_at: Instant::now(),
+                                    })
+                                    .await
+                                    .ok();
                             });
                         }
                         HousekeeperEvent::AcmeReschedule {
@@ -233,12 +296,38 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                             queue.remove_action(&action);
                             queue.schedule(renew_at, action);
                         }
+                        HousekeeperEvent::OcspReschedule { refresh_at } => {
+                            queue.remove_action(&ActionClass::Ocsp);
+                            queue.schedule(refresh_at, ActionClass::Ocsp);
+                        }
+                        HousekeeperEvent::TlsTicketKeysReschedule { refresh_at } => {
+                            queue.remove_action(&ActionClass::TlsTicketKeys);
+                            queue.schedule(refresh_at, ActionClass::TlsTicketKeys);
+                        }
                         HousekeeperEvent::Purge(purge) => {
                             let server = inner.build_server();
                             tokio::spawn(async move {
                                 server.purge(purge, 0).await;
                             });
                         }
+                        HousekeeperEvent::RunTask(task) => {
+                            let server = inner.build_server();
+                            let instances = if task == MaintenanceTask::PurgeStore {
+                                server.core.storage.purge_schedules.len()
+                            } else {
+                                1
+                            };
+                            for idx in 0..instances {
+                                let guard = server.force_maintenance_task(task);
+                                spawn_maintenance_task(
+                                    server.clone(),
+                                    task,
+                                    idx,
+                                    TaskTrigger::Manual,
+                                    guard,
+                                );
+                            }
+                        }
                         HousekeeperEvent::Exit => {
                             trc::event!(Housekeeper(trc::HousekeeperEvent::Stop));
 
@@ -277,11 +366,27 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                                     )
                                                 );
 
+                                                // Staple an OCSP response to the new certificate
+                                                server
+                                                    .inner
+                                                    .ipc
+                                                    .housekeeper_tx
+                                                    .send(HousekeeperEvent::OcspReschedule {
+                                                        refresh_at: Instant::now(),
+                                                    })
+                                                    .await
+                                                    .ok();
+
                                                 renew_at
                                             }
                                             Err(err) => {
-                                                trc::error!(
-                                                    err.details("Failed to renew certificates.")
+                                                provider.schedule_renewal(now() + 3600);
+                                                trc::event!(
+                                                    Acme(trc::AcmeEvent::RenewFailed),
+                                                    Id = provider_id.clone(),
+                                                    Hostname = provider.domains.as_slice(),
+                                                    NextRetry = trc::Value::Timestamp(now() + 3600),
+                                                    CausedBy = err,
                                                 );
 
                                                 Duration::from_secs(3600)
@@ -305,26 +410,157 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                     }
                                 });
                             }
+                            ActionClass::Ocsp => {
+                                trc::event!(Housekeeper(trc::HousekeeperEvent::Run), Type = "ocsp");
+
+                                let server = server.clone();
+                                tokio::spawn(async move {
+                                    if let Some(refresh_in) = server.refresh_ocsp().await {
+                                        server
+                                            .inner
+                                            .ipc
+                                            .housekeeper_tx
+                                            .send(HousekeeperEvent::OcspReschedule {
+                                                refresh_at: Instant::now() + refresh_in,
+                                            })
+                                            .await
+                                            .ok();
+                                    }
+                                });
+                            }
+                            ActionClass::TlsTicketKeys => {
+                                trc::event!(
+                                    Housekeeper(trc::HousekeeperEvent::Run),
+                                    Type = "tls-tickets"
+                                );
+
+                                let server = server.clone();
+                                tokio::spawn(async move {
+                                    let refresh_in = server.refresh_tls_ticket_keys().await;
+                                    server
+                                        .inner
+                                        .ipc
+                                        .housekeeper_tx
+                                        .send(HousekeeperEvent::TlsTicketKeysReschedule {
+                                            refresh_at: Instant::now() + refresh_in,
+                                        })
+                                        .await
+                                        .ok();
+                                });
+                            }
                             ActionClass::Account => {
+                                // Tasks outside of their maintenance window are retried once it opens
+                                let guard = match server
+                                    .start_maintenance_task(MaintenanceTask::PurgeAccounts)
+                                {
+                                    Ok(guard) => guard,
+                                    Err(retry_in) => {
+                                        queue.schedule(
+                                            Instant::now() + retry_in,
+                                            ActionClass::Account,
+                                        );
+                                        continue;
+                                    }
+                                };
+
                                 trc::event!(
                                     Housekeeper(trc::HousekeeperEvent::Run),
                                     Type = "purge_account"
                                 );
 
-                                let server = server.clone();
                                 queue.schedule(
                                     Instant::now()
                                         + server.core.jmap.account_purge_frequency.time_to_next(),
                                     ActionClass::Account,
                                 );
-                                tokio::spawn(async move {
-                                    server.purge(PurgeType::Account(None), 0).await;
-                                });
+                                spawn_maintenance_task(
+                                    server.clone(),
+                                    MaintenanceTask::PurgeAccounts,
+                                    0,
+                                    TaskTrigger::Scheduled,
+                                    guard,
+                                );
+                            }
+                            ActionClass::BlobPack => {
+                                let guard = match server
+                                    .start_maintenance_task(MaintenanceTask::BlobPack)
+                                {
+                                    Ok(guard) => guard,
+                                    Err(retry_in) => {
+                                        queue.schedule(
+                                            Instant::now() + retry_in,
+                                            ActionClass::BlobPack,
+                                        );
+                                        continue;
+                                    }
+                                };
+
+                                trc::event!(
+                                    Housekeeper(trc::HousekeeperEvent::Run),
+                                    Type = "blob_pack"
+                                );
+
+                                queue.schedule(
+                                    Instant::now()
+                                        + server.core.jmap.mail_pack_frequency.time_to_next(),
+                                    ActionClass::BlobPack,
+                                );
+                                spawn_maintenance_task(
+                                    server.clone(),
+                                    MaintenanceTask::BlobPack,
+                                    0,
+                                    TaskTrigger::Scheduled,
+                                    guard,
+                                );
+                            }
+                            ActionClass::IntegrityAudit => {
+                                let guard = match server
+                                    .start_maintenance_task(MaintenanceTask::IntegrityAudit)
+                                {
+                                    Ok(guard) => guard,
+                                    Err(retry_in) => {
+                                        queue.schedule(
+                                            Instant::now() + retry_in,
+                                            ActionClass::IntegrityAudit,
+                                        );
+                                        continue;
+                                    }
+                                };
+
+                                trc::event!(
+                                    Housekeeper(trc::HousekeeperEvent::Run),
+                                    Type = "integrity_audit"
+                                );
+
+                                queue.schedule(
+                                    Instant::now()
+                                        + server.core.jmap.mail_audit_frequency.time_to_next(),
+                                    ActionClass::IntegrityAudit,
+                                );
+                                spawn_maintenance_task(
+                                    server.clone(),
+                                    MaintenanceTask::IntegrityAudit,
+                                    0,
+                                    TaskTrigger::Scheduled,
+                                    guard,
+                                );
                             }
                             ActionClass::Store(idx) => {
-                                if let Some(schedule) =
-                                    server.core.storage.purge_schedules.get(idx).cloned()
+                                if let Some(schedule) = server.core.storage.purge_schedules.get(idx)
                                 {
+                                    let guard = match server
+                                        .start_maintenance_task(MaintenanceTask::PurgeStore)
+                                    {
+                                        Ok(guard) => guard,
+                                        Err(retry_in) => {
+                                            queue.schedule(
+                                                Instant::now() + retry_in,
+                                                ActionClass::Store(idx),
+                                            );
+                                            continue;
+                                        }
+                                    };
+
                                     trc::event!(
                                         Housekeeper(trc::HousekeeperEvent::Run),
                                         Type = "purge_store",
@@ -335,29 +571,13 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                         Instant::now() + schedule.cron.time_to_next(),
                                         ActionClass::Store(idx),
                                     );
-
-                                    let server = server.clone();
-                                    tokio::spawn(async move {
-                                        server
-                                            .purge(
-                                                match schedule.store {
-                                                    PurgeStore::Data(store) => {
-                                                        PurgeType::Data(store)
-                                                    }
-                                                    PurgeStore::Blobs { store, blob_store } => {
-                                                        PurgeType::Blobs { store, blob_store }
-                                                    }
-                                                    PurgeStore::Lookup(in_memory_store) => {
-                                                        PurgeType::Lookup {
-                                                            store: in_memory_store,
-                                                            prefix: None,
-                                                        }
-                                                    }
-                                                },
-                                                idx as u32,
-                                            )
-                                            .await;
-                                    });
+                                    spawn_maintenance_task(
+                                        server.clone(),
+                                        MaintenanceTask::PurgeStore,
+                                        idx,
+                                        TaskTrigger::Scheduled,
+                                        guard,
+                                    );
                                 }
                             }
                             ActionClass::OtelMetrics => {
@@ -484,6 +704,55 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                     }
                                 });
                             }
+                            ActionClass::PublicSuffix => {
+                                if let Some(public_suffix) =
+                                    server.core.network.public_suffix.clone()
+                                {
+                                    trc::event!(
+                                        Housekeeper(trc::HousekeeperEvent::Run),
+                                        Type = "public_suffix"
+                                    );
+
+                                    queue.schedule(
+                                        Instant::now() + public_suffix.refresh,
+                                        ActionClass::PublicSuffix,
+                                    );
+
+                                    tokio::spawn(async move {
+                                        // The current list is kept unless a mirror returns a valid one
+                                        for url in &public_suffix.urls {
+                                            trc::event!(
+                                                Resource(trc::ResourceEvent::DownloadExternal),
+                                                Url = url.clone(),
+                                            );
+
+                                            match fetch_resource(
+                                                url,
+                                                None,
+                                                public_suffix.timeout,
+                                                public_suffix.max_size,
+                                            )
+                                            .await
+                                            .and_then(|bytes| PublicSuffix::from_bytes(url, bytes))
+                                            {
+                                                Ok(list) => {
+                                                    set_public_suffix(list);
+                                                    return;
+                                                }
+                                                Err(err) => {
+                                                    trc::event!(
+                                                        Resource(trc::ResourceEvent::Error),
+                                                        Details =
+                                                            "Failed to refresh public suffix list",
+                                                        Url = url.clone(),
+                                                        CausedBy = err,
+                                                    );
+                                                }
+                                            }
+                                        }
+                                    });
+                                }
+                            }
 
                             // SPDX-SnippetBegin
                             // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
@@ -533,11 +802,18 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                     if let Some(messages) = server.process_alerts().await {
                                         for message in messages {
                                             server
-                                                .send_autogenerated(
-                                                    message.from,
-                                                    message.to.into_iter(),
-                                                    message.body,
-                                                    None,
+                                                .send_system_message(
+                                                    &SystemMessage {
+                                                        template: TEMPLATE_METRICS_ALERT,
+                                                        from_name: message.from_name.as_deref(),
+                                                        from_addr: Some(message.from.as_str()),
+                                                        language: None,
+                                                        variables: &[
+                                                            ("subject", message.subject.as_str()),
+                                                            ("body", message.body.as_str()),
+                                                        ],
+                                                    },
+                                                    message.to.iter(),
                                                     0,
                                                 )
                                                 .await;
@@ -599,6 +875,177 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
     });
 }
 
+fn spawn_maintenance_task(
+    server: Server,
+    task: MaintenanceTask,
+    store_idx: usize,
+    trigger: TaskTrigger,
+    guard: RunningTaskGuard,
+) {
+    tokio::spawn(async move {
+        match task {
+            MaintenanceTask::PurgeAccounts => {
+                server
+                    .run_maintenance_task(
+                        task,
+                        0,
+                        trigger,
+                        guard,
+                        server.purge(PurgeType::Account(None), 0),
+                    )
+                    .await;
+            }
+            MaintenanceTask::BlobPack => {
+                server
+                    .run_maintenance_task(task, 0, trigger, guard, server.pack_accounts())
+                    .await;
+            }
+            MaintenanceTask::IntegrityAudit => {
+                server
+                    .run_maintenance_task(task, 0, trigger, guard, server.audit_messages())
+                    .await;
+            }
+            MaintenanceTask::PurgeStore => {
+                if let Some(schedule) = server.core.storage.purge_schedules.get(store_idx).cloned()
+                {
+                    let purge = match schedule.store {
+                        PurgeStore::Data(store) => PurgeType::Data(store),
+                        PurgeStore::Blobs { store, blob_store } => {
+                            PurgeType::Blobs { store, blob_store }
+                        }
+                        PurgeStore::Lookup(in_memory_store) => PurgeType::Lookup {
+                            store: in_memory_store,
+                            prefix: None,
+                        },
+                    };
+
+                    server
+                        .run_maintenance_task(
+                            task,
+                            store_idx as u32,
+                            trigger,
+                            guard,
+                            server.purge(purge, store_idx as u32),
+                        )
+                        .await;
+                }
+            }
+        }
+    });
+}
+
+pub trait MaintenanceRunner: Sync + Send {
+    fn run_maintenance_task(
+        &self,
+        task: MaintenanceTask,
+        instance: u32,
+        trigger: TaskTrigger,
+        guard: RunningTaskGuard,
+        run: impl Future<Output = ()> + Send,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+impl MaintenanceRunner for Server {
+    async fn run_maintenance_task(
+        &self,
+        task: MaintenanceTask,
+        instance: u32,
+        trigger: TaskTrigger,
+        _guard: RunningTaskGuard,
+        run: impl Future<Output = ()> + Send,
+    ) {
+        // Paused tasks are skipped when scheduled but can still be started manually
+        if trigger == TaskTrigger::Scheduled {
+            match self.is_maintenance_task_paused(task).await {
+                Ok(false) => (),
+                Ok(true) => {
+                    trc::event!(Housekeeper(trc::HousekeeperEvent::Paused), Type = task.id());
+                    return;
+                }
+                Err(err) => {
+                    trc::error!(err.details("Failed to obtain task status."));
+                    return;
+                }
+            }
+        }
+
+        // Only one node in the cluster runs each task at a time
+        let policy = self.core.network.maintenance.policy(task);
+        let lock_name = [4u8]
+            .into_iter()
+            .chain(task.id().bytes())
+            .chain(instance.to_be_bytes())
+            .collect::<Vec<_>>();
+        match self
+            .in_memory_store()
+            .try_lock(
+                KV_LOCK_HOUSEKEEPER,
+                &lock_name,
+                policy
+                    .max_duration
+                    .map_or(86400, |max_duration| max_duration.as_secs() + 60),
+            )
+            .await
+        {
+            Ok(true) => (),
+            Ok(false) => {
+                trc::event!(Purge(PurgeEvent::InProgress), Details = task.id());
+                return;
+            }
+            Err(err) => {
+                trc::error!(err.details("Failed to lock task.").details(task.id()));
+                return;
+            }
+        }
+
+        let started_at = now();
+        let time = Instant::now();
+        let status = if let Some(max_duration) = policy.max_duration {
+            match tokio::time::timeout(max_duration, run).await {
+                Ok(_) => TaskRunStatus::Completed,
+                Err(_) => {
+                    trc::event!(
+                        Housekeeper(trc::HousekeeperEvent::BudgetExceeded),
+                        Type = task.id(),
+                        Elapsed = time.elapsed()
+                    );
+                    TaskRunStatus::BudgetExceeded
+                }
+            }
+        } else {
+            run.await;
+            TaskRunStatus::Completed
+        };
+
+        if let Err(err) = self
+            .record_maintenance_task_run(
+                task,
+                TaskRun {
+                    started_at,
+                    elapsed_ms: time.elapsed().as_millis() as u64,
+                    node_id: self.core.network.node_id,
+                    trigger,
+                    status,
+                },
+            )
+            .await
+        {
+            trc::error!(err.details("Failed to record task run."));
+        }
+
+        if let Err(err) = self
+            .in_memory_store()
+            .remove_lock(KV_LOCK_HOUSEKEEPER, &lock_name)
+            .await
+        {
+            trc::error!(
+                err.details("Failed to delete task lock.")
+                    .details(task.id())
+            );
+        }
+    }
+}
+
 pub trait Purge: Sync + Send {
     fn purge(&self, purge: PurgeType, store_idx: u32) -> impl Future<Output = ()> + Send;
 }
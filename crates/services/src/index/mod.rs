@@ -4,20 +4,26 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{sync::Arc, time::Instant};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use common::{Inner, KV_LOCK_EMAIL_TASK, Server, core::BuildServer};
+use common::{Inner, KV_LOCK_EMAIL_TASK, KV_RATE_LIMIT_ENCRYPT, Server, core::BuildServer};
 use directory::{Type, backend::internal::manage::ManageDirectory};
-use email::message::{bayes::EmailBayesTrain, index::IndexMessageText, metadata::MessageMetadata};
+use email::message::{
+    bayes::EmailBayesTrain, encrypt::EncryptStoredMessage, hook::IngestHooks,
+    index::IndexMessageText, metadata::MessageMetadata,
+};
 use jmap_proto::types::{collection::Collection, property::Property};
 use mail_parser::MessageParser;
 use store::{
-    IterateParams, SerializeInfallible, U32_LEN, U64_LEN, ValueKey,
+    Deserialize, IterateParams, SerializeInfallible, U32_LEN, U64_LEN, ValueKey,
     ahash::AHashMap,
     fts::index::FtsDocument,
     roaring::RoaringBitmap,
     write::{
-        BatchBuilder, BlobOp, TaskQueueClass, ValueClass,
+        AlignedBytes, Archive, BatchBuilder, BlobOp, TaskQueueClass, ValueClass,
         key::{DeserializeBigEndian, KeySerializer},
         now,
     },
@@ -40,10 +46,20 @@ pub struct EmailTask {
 pub enum EmailTaskAction {
     Index,
     BayesTrain { learn_spam: bool },
+    Encrypt,
+    IngestHook { mailbox_id: u32 },
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EncryptionProgress {
+    pub total: u64,
+    pub pending: u64,
 }
 
 const FTS_LOCK_EXPIRY: u64 = 60 * 5;
 const BAYES_LOCK_EXPIRY: u64 = 60 * 30;
+const ENCRYPT_LOCK_EXPIRY: u64 = 60 * 5;
+const HOOK_LOCK_EXPIRY: u64 = 60 * 5;
 
 pub fn spawn_email_queue_task(inner: Arc<Inner>) {
     tokio::spawn(async move {
@@ -56,8 +72,16 @@ pub fn spawn_email_queue_task(inner: Arc<Inner>) {
                 .email_task_queued(&mut locked_seq_ids)
                 .await;
 
-            // Wait for a signal to index more messages
-            rx.notified().await;
+            // Wait for a signal to index more messages or for deferred tasks to be retried
+            if let Some(retry_at) = locked_seq_ids.values().min() {
+                let _ = tokio::time::timeout(
+                    retry_at.saturating_duration_since(Instant::now()),
+                    rx.notified(),
+                )
+                .await;
+            } else {
+                rx.notified().await;
+            }
         }
     });
 }
@@ -74,6 +98,11 @@ pub trait Indexer: Sync + Send {
         account_id: Option<u32>,
         tenant_id: Option<u32>,
     ) -> impl Future<Output = trc::Result<()>> + Send;
+    fn encrypt_existing(&self, account_id: u32) -> impl Future<Output = trc::Result<u64>> + Send;
+    fn encrypt_existing_progress(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<EncryptionProgress>> + Send;
 }
 
 impl Indexer for Server {
@@ -99,8 +128,9 @@ impl Indexer for Server {
 
         // Retrieve entries pending to be indexed
         let mut entries = Vec::new();
+        let mut backlog: AHashMap<u32, u64> = AHashMap::new();
         let now = Instant::now();
-        let _ = self
+        let result = self
             .core
             .storage
             .data
@@ -108,6 +138,9 @@ impl Indexer for Server {
                 IterateParams::new(from_key, to_key).ascending().no_values(),
                 |key, _| {
                     let entry = EmailTask::deserialize(key)?;
+                    if matches!(entry.action, EmailTaskAction::Index) {
+                        *backlog.entry(entry.account_id).or_default() += 1;
+                    }
                     if locked_seq_ids
                         .get(&entry.seq)
                         .is_none_or(|expires| now >= *expires)
@@ -118,23 +151,62 @@ impl Indexer for Server {
                     Ok(true)
                 },
             )
-            .await
-            .map_err(|err| {
+            .await;
+
+        match result {
+            Ok(_) => {
+                // Queued tasks survive restarts, the backlog is rebuilt from the queue on every scan
+                self.inner.data.index_backlog.refresh(backlog);
+                self.update_index_backlog();
+            }
+            Err(err) => {
                 trc::error!(
                     err.caused_by(trc::location!())
                         .details("Failed to iterate over index emails")
                 );
-            });
+            }
+        }
 
         // Add entries to the index
         let mut unlock_events = Vec::with_capacity(entries.len());
+        let mut throttled_until = None;
         for event in entries {
             let op_start = Instant::now();
+
+            // Throttle the encryption of existing messages so other tasks are not starved
+            if let (EmailTaskAction::Encrypt, Some(rate)) =
+                (event.action, &self.core.jmap.encrypt_existing_rate)
+            {
+                if throttled_until.is_none() {
+                    match self
+                        .in_memory_store()
+                        .is_rate_allowed(KV_RATE_LIMIT_ENCRYPT, &[], rate, false)
+                        .await
+                    {
+                        Ok(Some(retry_in)) => {
+                            throttled_until = Some(Instant::now() + Duration::from_secs(retry_in));
+                        }
+                        Ok(None) => {}
+                        Err(err) => {
+                            trc::error!(
+                                err.caused_by(trc::location!())
+                                    .details("Failed to check encryption rate limit")
+                            );
+                        }
+                    }
+                }
+
+                if let Some(throttled_until) = throttled_until {
+                    locked_seq_ids.insert(event.seq, throttled_until);
+                    continue;
+                }
+            }
+
             // Lock index
             if !self.try_lock_index(&event).await {
                 locked_seq_ids.insert(
                     event.seq,
-                    Instant::now() + std::time::Duration::from_secs(event.lock_expiry() + 1),
+                    Instant::now() + Duration::from_secs(event.lock_expiry() + 1),
                 );
                 continue;
             }
@@ -259,10 +331,68 @@ impl Indexer for Server {
                         Elapsed = op_start.elapsed(),
                     );
                 }
+                EmailTaskAction::Encrypt => {
+                    match self
+                        .encrypt_stored_message(
+                            event.account_id,
+                            event.document_id,
+                            &event.hash,
+                            &raw_message,
+                        )
+                        .await
+                    {
+                        Ok(true) => {
+                            trc::event!(
+                                TaskQueue(TaskQueueEvent::Encrypt),
+                                AccountId = event.account_id,
+                                Collection = Collection::Email,
+                                DocumentId = event.document_id,
+                                Elapsed = op_start.elapsed(),
+                            );
+                        }
+                        Ok(false) => {}
+                        Err(err) => {
+                            trc::error!(
+                                err.account_id(event.account_id)
+                                    .document_id(event.document_id)
+                                    .details("Failed to encrypt email")
+                            );
+                        }
+                    }
+                }
+                EmailTaskAction::IngestHook { mailbox_id } => {
+                    match self
+                        .ingest_hook_run(
+                            event.account_id,
+                            event.document_id,
+                            mailbox_id,
+                            event.seq,
+                            &event.hash,
+                            &raw_message,
+                        )
+                        .await
+                    {
+                        Ok(Some(retry_in)) => {
+                            // Failed deliveries stay queued until the next attempt is due
+                            locked_seq_ids.insert(event.seq, Instant::now() + retry_in);
+                            continue;
+                        }
+                        Ok(None) => {}
+                        Err(err) => {
+                            trc::error!(
+                                err.account_id(event.account_id)
+                                    .document_id(event.document_id)
+                                    .details("Failed to run ingestion hook")
+                            );
+
+                            continue;
+                        }
+                    }
+                }
             }
 
             // Remove entry from queue
-            if let Err(err) = self
+            match self
                 .core
                 .storage
                 .data
@@ -276,14 +406,23 @@ impl Indexer for Server {
                 )
                 .await
             {
-                trc::error!(
-                    err.account_id(event.account_id)
-                        .document_id(event.document_id)
-                        .details("Failed to remove index email from queue.")
-                );
+                Ok(_) => {
+                    if matches!(event.action, EmailTaskAction::Index) {
+                        self.inner.data.index_backlog.drained(event.account_id);
+                    }
+                }
+                Err(err) => {
+                    trc::error!(
+                        err.account_id(event.account_id)
+                            .document_id(event.document_id)
+                            .details("Failed to remove index email from queue.")
+                    );
+                }
             }
         }
 
+        self.update_index_backlog();
+
         // Unlock entries
         for event in unlock_events {
             self.remove_index_lock(&event).await;
@@ -446,11 +585,147 @@ impl Indexer for Server {
 
         Ok(())
     }
+
+    async fn encrypt_existing(&self, account_id: u32) -> trc::Result<u64> {
+        // Obtain the blob of every message in the account
+        let mut hashes = Vec::new();
+        self.core
+            .storage
+            .data
+            .iterate(
+                IterateParams::new(
+                    ValueKey {
+                        account_id,
+                        collection: Collection::Email.into(),
+                        document_id: 0,
+                        class: ValueClass::Property(Property::BodyStructure.into()),
+                    },
+                    ValueKey {
+                        account_id,
+                        collection: Collection::Email.into(),
+                        document_id: u32::MAX,
+                        class: ValueClass::Property(Property::BodyStructure.into()),
+                    },
+                )
+                .ascending(),
+                |key, value| {
+                    let document_id = key.deserialize_be_u32(key.len() - U32_LEN)?;
+                    let metadata = <Archive<AlignedBytes> as Deserialize>::deserialize(value)?;
+                    hashes.push((
+                        document_id,
+                        BlobHash::from(&metadata.unarchive::<MessageMetadata>()?.blob_hash),
+                    ));
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        // Messages that are already encrypted are skipped when the task runs
+        let total = hashes.len() as u64;
+        let mut seq = self.generate_snowflake_id();
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Principal)
+            .update_document(0)
+            .set(Property::EncryptionTotal, total.serialize())
+            .with_collection(Collection::Email);
+
+        for (document_id, hash) in hashes {
+            batch.update_document(document_id).set(
+                ValueClass::TaskQueue(TaskQueueClass::EncryptEmail { hash, seq }),
+                0u64.serialize(),
+            );
+            seq += 1;
+
+            if batch.len() >= 2000 {
+                self.core.storage.data.write(batch.build_all()).await?;
+                batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Email);
+            }
+        }
+
+        if !batch.is_empty() {
+            self.core.storage.data.write(batch.build_all()).await?;
+        }
+
+        // Request encryption
+        self.notify_task_queue();
+
+        Ok(total)
+    }
+
+    async fn encrypt_existing_progress(&self, account_id: u32) -> trc::Result<EncryptionProgress> {
+        let total = self
+            .core
+            .storage
+            .data
+            .get_value::<u64>(ValueKey {
+                account_id,
+                collection: Collection::Principal.into(),
+                document_id: 0,
+                class: ValueClass::Property(Property::EncryptionTotal.into()),
+            })
+            .await
+            .caused_by(trc::location!())?
+            .unwrap_or_default();
+
+        // Count the messages pending to be encrypted
+        let mut pending = 0;
+        self.core
+            .storage
+            .data
+            .iterate(
+                IterateParams::new(
+                    ValueKey::<ValueClass> {
+                        account_id: 0,
+                        collection: 0,
+                        document_id: 0,
+                        class: ValueClass::TaskQueue(TaskQueueClass::EncryptEmail {
+                            seq: 0,
+                            hash: BlobHash::default(),
+                        }),
+                    },
+                    ValueKey::<ValueClass> {
+                        account_id: u32::MAX,
+                        collection: u8::MAX,
+                        document_id: u32::MAX,
+                        class: ValueClass::TaskQueue(TaskQueueClass::EncryptEmail {
+                            seq: u64::MAX,
+                            hash: BlobHash::default(),
+                        }),
+                    },
+                )
+                .ascending()
+                .no_values(),
+                |key, _| {
+                    let entry = EmailTask::deserialize(key)?;
+                    if entry.account_id == account_id
+                        && matches!(entry.action, EmailTaskAction::Encrypt)
+                    {
+                        pending += 1;
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(EncryptionProgress { total, pending })
+    }
 }
 
 impl EmailTask {
     fn remove_lock(&self) -> bool {
-        matches!(self.action, EmailTaskAction::Index)
+        matches!(
+            self.action,
+            EmailTaskAction::Index | EmailTaskAction::Encrypt | EmailTaskAction::IngestHook { .. }
+        )
     }
 
     fn lock_key(&self) -> Vec<u8> {
@@ -464,6 +739,16 @@ impl EmailTask {
                 .write_leb128(self.account_id)
                 .write_leb128(self.document_id)
                 .finalize(),
+            EmailTaskAction::Encrypt => KeySerializer::new((U32_LEN * 2) + 1)
+                .write(2u8)
+                .write_leb128(self.account_id)
+                .write_leb128(self.document_id)
+                .finalize(),
+            EmailTaskAction::IngestHook { mailbox_id } => KeySerializer::new(U64_LEN + U32_LEN + 1)
+                .write(3u8)
+                .write(self.seq)
+                .write(mailbox_id)
+                .finalize(),
         }
     }
 
@@ -471,6 +756,8 @@ impl EmailTask {
         match self.action {
             EmailTaskAction::Index => FTS_LOCK_EXPIRY,
             EmailTaskAction::BayesTrain { .. } => BAYES_LOCK_EXPIRY,
+            EmailTaskAction::Encrypt => ENCRYPT_LOCK_EXPIRY,
+            EmailTaskAction::IngestHook { .. } => HOOK_LOCK_EXPIRY,
         }
     }
 
@@ -485,6 +772,15 @@ impl EmailTask {
                 seq: self.seq,
                 learn_spam,
             },
+            EmailTaskAction::Encrypt => TaskQueueClass::EncryptEmail {
+                hash: self.hash.clone(),
+                seq: self.seq,
+            },
+            EmailTaskAction::IngestHook { mailbox_id } => TaskQueueClass::IngestHook {
+                hash: self.hash.clone(),
+                seq: self.seq,
+                mailbox_id,
+            },
         })
     }
 
@@ -497,6 +793,11 @@ impl EmailTask {
                 Some(0) => EmailTaskAction::Index,
                 Some(1) => EmailTaskAction::BayesTrain { learn_spam: true },
                 Some(2) => EmailTaskAction::BayesTrain { learn_spam: false },
+                Some(3) => EmailTaskAction::Encrypt,
+                Some(4) => EmailTaskAction::IngestHook {
+                    mailbox_id: key
+                        .deserialize_be_u32(U64_LEN + U32_LEN + U32_LEN + BLOB_HASH_LEN + 1)?,
+                },
                 _ => return Err(trc::Error::corrupted_key(key, None, trc::location!())),
             },
             hash: key
@@ -7,9 +7,13 @@
 use std::time::{Duration, Instant};
 
 use base64::Engine;
-use common::ipc::EncryptionKeys;
+use common::{Server, ipc::EncryptionKeys};
+use email::cache::MessageCacheFetch;
 
-use jmap_proto::{response::status::StateChangeResponse, types::id::Id};
+use jmap_proto::{
+    response::status::StateChangeResponse,
+    types::{id::Id, type_state::DataType},
+};
 use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
 use tokio::sync::mpsc;
 use trc::PushSubscriptionEvent;
@@ -17,7 +21,13 @@ use trc::PushSubscriptionEvent;
 use super::{Event, PushServer, ece::ece_encrypt};
 
 impl PushServer {
-    pub fn send(&mut self, id: Id, push_tx: mpsc::Sender<Event>, push_timeout: Duration) {
+    pub fn send(
+        &mut self,
+        id: Id,
+        server: Server,
+        push_tx: mpsc::Sender<Event>,
+        push_timeout: Duration,
+    ) {
         let url = self.url.clone();
         let keys = self.keys.clone();
         let state_changes = std::mem::take(&mut self.state_changes);
@@ -36,6 +46,31 @@ impl PushServer {
                 }
             }
 
+            // Include the updated mailbox counters of the subscription owner
+            let account_id = id.prefix_id();
+            if let Some(change_id) = state_changes
+                .iter()
+                .filter(|state_change| {
+                    state_change.account_id == account_id
+                        && state_change.types.contains(DataType::Email)
+                })
+                .map(|state_change| state_change.change_id)
+                .min()
+            {
+                match server.get_mailbox_counts(account_id, change_id).await {
+                    Ok(counts) => {
+                        let account_counts =
+                            response.mailbox_counts.get_mut_or_insert(account_id.into());
+                        for (mailbox_id, counts) in counts {
+                            account_counts.set(mailbox_id.into(), counts);
+                        }
+                    }
+                    Err(err) => {
+                        trc::error!(err.account_id(account_id));
+                    }
+                }
+            }
+
             push_tx
                 .send(
                     if http_request(
@@ -0,0 +1,370 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use common::{Server, telemetry::load_test::LoadTestStageReport};
+use mail_builder::headers::date::Date;
+use mail_send::smtp::AssertReply;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::outbound::client::SmtpClient;
+
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+const LINE_LENGTH: usize = 76;
+
+const HAM_WORDS: &[&str] = &[
+    "meeting", "project", "report", "schedule", "review", "update", "quarter", "budget", "design",
+    "release", "agenda", "notes", "draft", "proposal", "feedback", "team",
+];
+const SPAM_WORDS: &[&str] = &[
+    "FREE",
+    "WINNER",
+    "viagra",
+    "casino",
+    "CLICK",
+    "bitcoin",
+    "urgent",
+    "prize",
+    "lottery",
+    "guaranteed",
+    "cheap",
+    "offer",
+    "limited",
+    "$$$",
+    "100%",
+    "unsubscribe",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct LoadTestRequest {
+    pub messages: usize,
+    pub concurrency: usize,
+    pub messages_per_session: usize,
+    pub sender: String,
+    pub recipients: Vec<String>,
+    pub recipients_per_message: LoadTestRange,
+    pub sizes: Vec<LoadTestSize>,
+    pub spam_ratio: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LoadTestRange {
+    pub min: usize,
+    pub max: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LoadTestSize {
+    pub size: usize,
+    pub weight: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadTestReport {
+    pub sessions: u64,
+    pub accepted: u64,
+    pub deferred: u64,
+    pub rejected: u64,
+    pub errors: u64,
+    pub bytes: u64,
+    pub elapsed: u64,
+    pub messages_per_second: f64,
+    pub latency: LoadTestStageReport,
+    pub stages: Vec<LoadTestStageReport>,
+}
+
+#[derive(Default)]
+struct WorkerResult {
+    sessions: u64,
+    accepted: u64,
+    deferred: u64,
+    rejected: u64,
+    errors: u64,
+    bytes: u64,
+    latencies: Vec<u64>,
+}
+
+pub trait LoadTestGenerator: Sync + Send {
+    fn run_load_test(
+        &self,
+        request: LoadTestRequest,
+    ) -> impl Future<Output = trc::Result<LoadTestReport>> + Send;
+}
+
+impl LoadTestGenerator for Server {
+    async fn run_load_test(&self, mut request: LoadTestRequest) -> trc::Result<LoadTestReport> {
+        let config = &self.core.smtp.load_test;
+
+        // Never generate traffic unless test mode was explicitly enabled
+        if !config.enable {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Test mode is not enabled"));
+        }
+
+        // Validate request
+        if request.recipients.is_empty() {
+            if let Some(domain) = config.sink_domains.iter().next() {
+                request.recipients = (0..100).map(|n| format!("user{n}@{domain}")).collect();
+            } else {
+                return Err(trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .details("No recipients or sink domains configured"));
+            }
+        }
+        if request.messages == 0 || request.messages > config.max_messages {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Invalid number of messages")
+                .ctx(trc::Key::Limit, config.max_messages));
+        }
+        if request.concurrency == 0 || request.concurrency > config.max_concurrency {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Invalid concurrency")
+                .ctx(trc::Key::Limit, config.max_concurrency));
+        }
+        if request.sizes.iter().all(|s| s.weight == 0)
+            || request.recipients_per_message.min == 0
+            || request.recipients_per_message.min > request.recipients_per_message.max
+            || !(0.0..=1.0).contains(&request.spam_ratio)
+        {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Invalid message distribution"));
+        }
+        request.messages_per_session = request.messages_per_session.max(1);
+
+        // Reset per-stage statistics
+        self.inner.data.load_test.reset();
+
+        let request = Arc::new(request);
+        let counter = Arc::new(AtomicUsize::new(0));
+        let start_time = Instant::now();
+        let mut workers = Vec::with_capacity(request.concurrency);
+        for _ in 0..request.concurrency {
+            let server = self.clone();
+            let request = request.clone();
+            let counter = counter.clone();
+            workers.push(tokio::spawn(async move {
+                let mut result = WorkerResult::default();
+                while counter.load(Ordering::Relaxed) < request.messages {
+                    result.sessions += 1;
+                    if let Err(err) = run_session(&server, &request, &counter, &mut result).await {
+                        // Stop this worker rather than hammering a failing listener
+                        result.errors += 1;
+                        trc::event!(
+                            Smtp(trc::SmtpEvent::Error),
+                            Details = "Load test session failed",
+                            Reason = err.to_string(),
+                        );
+                        break;
+                    }
+                }
+                result
+            }));
+        }
+
+        let mut report = LoadTestReport::default();
+        let mut latencies = Vec::with_capacity(request.messages);
+        for worker in workers {
+            let result = worker.await.unwrap_or_default();
+            report.sessions += result.sessions;
+            report.accepted += result.accepted;
+            report.deferred += result.deferred;
+            report.rejected += result.rejected;
+            report.errors += result.errors;
+            report.bytes += result.bytes;
+            latencies.extend(result.latencies);
+        }
+        let elapsed = start_time.elapsed();
+        report.elapsed = elapsed.as_millis() as u64;
+        report.messages_per_second = report.accepted as f64 / elapsed.as_secs_f64().max(0.001);
+        report.latency = LoadTestStageReport::from_samples(latencies);
+        report.stages = self.inner.data.load_test.report();
+
+        Ok(report)
+    }
+}
+
+async fn run_session(
+    server: &Server,
+    request: &LoadTestRequest,
+    counter: &AtomicUsize,
+    result: &mut WorkerResult,
+) -> mail_send::Result<()> {
+    let mut client = SmtpClient::connect(
+        server.core.smtp.load_test.address,
+        SESSION_TIMEOUT,
+        server.inner.data.span_id_gen.generate(),
+    )
+    .await?;
+    client.read().await?.assert_code(220)?;
+    client
+        .cmd(b"EHLO loadtest.local\r\n")
+        .await?
+        .assert_code(250)?;
+
+    for _ in 0..request.messages_per_session {
+        let seq = counter.fetch_add(1, Ordering::Relaxed);
+        if seq >= request.messages {
+            break;
+        }
+        let (recipients, message) = build_message(request, seq);
+        let message_start = Instant::now();
+
+        client
+            .cmd(format!("MAIL FROM:<{}>\r\n", request.sender).as_bytes())
+            .await?
+            .assert_code(250)?;
+        let mut has_recipients = false;
+        for rcpt in &recipients {
+            let response = client
+                .cmd(format!("RCPT TO:<{rcpt}>\r\n").as_bytes())
+                .await?;
+            has_recipients |= response.code() == 250;
+        }
+        if !has_recipients {
+            result.rejected += 1;
+            client.cmd(b"RSET\r\n").await?.assert_code(250)?;
+            continue;
+        }
+        client.cmd(b"DATA\r\n").await?.assert_code(354)?;
+        client.write_message(&message).await?;
+        let response = client.read().await?;
+        match response.code() / 100 {
+            2 => {
+                result.accepted += 1;
+                result.bytes += message.len() as u64;
+                result
+                    .latencies
+                    .push(message_start.elapsed().as_micros() as u64);
+            }
+            4 => result.deferred += 1,
+            _ => result.rejected += 1,
+        }
+    }
+
+    client.quit().await;
+
+    Ok(())
+}
+
+fn build_message(request: &LoadTestRequest, seq: usize) -> (Vec<String>, Vec<u8>) {
+    let mut rng = rand::rng();
+
+    // Pick recipients
+    let num_rcpts = rng
+        .random_range(request.recipients_per_message.min..=request.recipients_per_message.max)
+        .min(request.recipients.len());
+    let offset = rng.random_range(0..request.recipients.len());
+    let recipients = (0..num_rcpts)
+        .map(|n| request.recipients[(offset + n) % request.recipients.len()].clone())
+        .collect::<Vec<_>>();
+
+    // Pick size
+    let total_weight = request.sizes.iter().map(|s| s.weight as u64).sum::<u64>();
+    let mut pick = rng.random_range(0..total_weight);
+    let mut size = 0;
+    for item in &request.sizes {
+        if pick < item.weight as u64 {
+            size = item.size;
+            break;
+        }
+        pick -= item.weight as u64;
+    }
+
+    // Build headers
+    let is_spam = rng.random_bool(request.spam_ratio);
+    let mut message = Vec::with_capacity(size + 512);
+    message.extend_from_slice(format!("From: <{}>\r\n", request.sender).as_bytes());
+    message.extend_from_slice(format!("To: <{}>\r\n", recipients.join(">, <")).as_bytes());
+    if is_spam {
+        message.extend_from_slice(
+            format!("Subject: ***FREE PRIZE*** CLAIM YOUR REWARD NOW #{seq}!!!\r\n").as_bytes(),
+        );
+    } else {
+        message.extend_from_slice(format!("Subject: Project update #{seq}\r\n").as_bytes());
+        message.extend_from_slice(
+            format!(
+                "Message-ID: <{seq}.{}@loadtest.local>\r\n",
+                rng.random::<u64>()
+            )
+            .as_bytes(),
+        );
+    }
+    message.extend_from_slice(format!("Date: {}\r\n", Date::now().to_rfc822()).as_bytes());
+    message.extend_from_slice(b"MIME-Version: 1.0\r\n");
+    message.extend_from_slice(b"Content-Type: text/plain; charset=utf-8\r\n\r\n");
+
+    // Build body
+    let words = if is_spam { SPAM_WORDS } else { HAM_WORDS };
+    let body_size = size.saturating_sub(message.len());
+    let mut line_len = 0;
+    let mut written = 0;
+    while written < body_size {
+        let word = if is_spam && rng.random_ratio(1, 20) {
+            "http://bit.ly/claim-now"
+        } else {
+            words[rng.random_range(0..words.len())]
+        };
+        if line_len + word.len() + 1 > LINE_LENGTH {
+            message.extend_from_slice(b"\r\n");
+            written += 2;
+            line_len = 0;
+        } else if line_len > 0 {
+            message.push(b' ');
+            written += 1;
+            line_len += 1;
+        }
+        message.extend_from_slice(word.as_bytes());
+        written += word.len();
+        line_len += word.len();
+    }
+    message.extend_from_slice(b"\r\n");
+
+    (recipients, message)
+}
+
+impl Default for LoadTestRequest {
+    fn default() -> Self {
+        Self {
+            messages: 100,
+            concurrency: 4,
+            messages_per_session: 1,
+            sender: "loadtest@loadtest.local".into(),
+            recipients: vec![],
+            recipients_per_message: LoadTestRange { min: 1, max: 1 },
+            sizes: vec![
+                LoadTestSize {
+                    size: 2 * 1024,
+                    weight: 50,
+                },
+                LoadTestSize {
+                    size: 16 * 1024,
+                    weight: 35,
+                },
+                LoadTestSize {
+                    size: 256 * 1024,
+                    weight: 15,
+                },
+            ],
+            spam_ratio: 0.0,
+        }
+    }
+}
@@ -30,6 +30,7 @@ use crate::{
     queue::{DomainPart, QueueId},
 };
 
+pub mod load_test;
 pub mod params;
 pub mod throttle;
 
@@ -74,6 +75,7 @@ pub struct SessionData {
     pub remote_ip_str: String,
     pub remote_port: u16,
     pub asn_geo_data: AsnGeoLookupResult,
+    pub tls_fingerprint: Option<String>,
     pub helo_domain: String,
 
     pub mail_from: Option<SessionAddress>,
@@ -90,6 +92,7 @@ pub struct SessionData {
     pub future_release: u64,
 
     pub valid_until: Instant,
+    pub data_started: Instant,
     pub bytes_left: usize,
     pub messages_sent: usize,
 
@@ -156,12 +159,14 @@ impl SessionData {
             remote_ip_str: remote_ip.to_string(),
             remote_port,
             asn_geo_data,
+            tls_fingerprint: None,
             helo_domain: String::new(),
             mail_from: None,
             rcpt_to: Vec::new(),
             authenticated_as: None,
             priority: 0,
             valid_until: Instant::now(),
+            data_started: Instant::now(),
             rcpt_errors: 0,
             rcpt_oks: 0,
             message: Vec::with_capacity(0),
@@ -280,6 +285,7 @@ impl SessionData {
             local_port: 0,
             session_id,
             asn_geo_data: AsnGeoLookupResult::default(),
+            tls_fingerprint: None,
             helo_domain: "localhost".into(),
             mail_from,
             rcpt_to,
@@ -292,6 +298,7 @@ impl SessionData {
             delivery_by: 0,
             future_release: 0,
             valid_until: Instant::now(),
+            data_started: Instant::now(),
             bytes_left: 0,
             messages_sent: 0,
             iprev: None,
@@ -12,7 +12,10 @@ use common::{
     listener::SessionStream,
 };
 
-use directory::Permission;
+use directory::{
+    Permission,
+    core::{protocols::Protocol, senders::SenderGrant},
+};
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
 use smtp_proto::{AUTH_LOGIN, AUTH_OAUTHBEARER, AUTH_PLAIN, AUTH_XOAUTH2, IntoString};
@@ -115,6 +118,9 @@ impl<T: SessionStream> Session<T> {
                 .and_then(|access_token| {
                     access_token
                         .assert_has_permission(Permission::EmailSend)
+                        .and_then(|_| {
+                            access_token.assert_protocol_enabled(Protocol::SmtpSubmission)
+                        })
                         .map(|_| access_token)
                 });
 
@@ -147,6 +153,11 @@ impl<T: SessionStream> Session<T> {
                             )
                             .await;
                         }
+                        trc::EventType::Auth(trc::AuthEvent::ProtocolDisabled) => {
+                            self.write(b"550 5.7.1 Protocol disabled for this account.\r\n")
+                                .await?;
+                            return Ok(false);
+                        }
                         trc::EventType::Security(trc::SecurityEvent::Unauthorized) => {
                             self.write(
                                 concat!(
@@ -209,6 +220,28 @@ impl<T: SessionStream> Session<T> {
         self.data.authenticated_as.is_some()
     }
 
+    pub fn hidden_auth_mechanisms(&self) -> u64 {
+        [
+            (AUTH_PLAIN, "AUTH=PLAIN"),
+            (AUTH_LOGIN, "AUTH=LOGIN"),
+            (AUTH_OAUTHBEARER, "AUTH=OAUTHBEARER"),
+            (AUTH_XOAUTH2, "AUTH=XOAUTH2"),
+        ]
+        .into_iter()
+        .filter(|(_, capability)| {
+            self.instance
+                .banner
+                .is_hidden(capability, self.stream.is_tls(), false)
+        })
+        .fold(0, |mechanisms, (mechanism, _)| mechanisms | mechanism)
+    }
+
+    pub fn is_capability_hidden(&self, capability: &str) -> bool {
+        self.instance
+            .banner
+            .is_hidden(capability, self.stream.is_tls(), self.is_authenticated())
+    }
+
     pub fn authenticated_emails(&self) -> &[String] {
         self.data
             .authenticated_as
@@ -216,4 +249,22 @@ impl<T: SessionStream> Session<T> {
             .map(|token| token.emails.as_slice())
             .unwrap_or_default()
     }
+
+    pub fn is_allowed_sender(&self, address_lcase: &str) -> bool {
+        self.authenticated_as().is_some_and(|authenticated_as| {
+            authenticated_as == address_lcase
+                || self.authenticated_emails().iter().any(|e| {
+                    e == address_lcase
+                        || (e.starts_with('@') && address_lcase.ends_with(e.as_str()))
+                })
+                || self.sender_grant(address_lcase).is_some()
+        })
+    }
+
+    pub fn sender_grant(&self, address_lcase: &str) -> Option<&SenderGrant> {
+        self.data
+            .authenticated_as
+            .as_ref()
+            .and_then(|token| token.sender_grant(address_lcase))
+    }
 }
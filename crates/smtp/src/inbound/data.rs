@@ -15,8 +15,8 @@ use common::{
         spamfilter::SpamFilterAction,
     },
     listener::SessionStream,
-    psl,
     scripts::ScriptModification,
+    telemetry::load_test::LoadTestStage,
 };
 
 use mail_auth::{
@@ -28,16 +28,20 @@ use mail_builder::headers::{date::Date, message_id::generate_message_id_header};
 use mail_parser::MessageParser;
 use sieve::runtime::Variable;
 use smtp_proto::{
-    MAIL_BY_RETURN, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
+    MAIL_BY_RETURN, MAIL_REQUIRETLS, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER,
+    RCPT_NOTIFY_SUCCESS,
 };
 use store::write::now;
-use trc::SmtpEvent;
-use utils::config::Rate;
+use trc::{SecurityEvent, SmtpEvent};
+use utils::{config::Rate, suffixlist::org_domain};
 
 use crate::{
     core::{Session, SessionAddress, State},
     inbound::milter::Modification,
-    queue::{self, Message, MessageSource, QueueEnvelope, Schedule, quota::HasQueueQuota},
+    queue::{
+        self, MAIL_QUARANTINED, MAIL_TLS_NOT_REQUIRED, Message, MessageSource, QueueEnvelope,
+        Schedule, quota::HasQueueQuota,
+    },
     reporting::analysis::AnalyzeReport,
     scripts::ScriptResult,
 };
@@ -46,6 +50,11 @@ use super::{ArcSeal, AuthResult, DkimSign};
 
 impl<T: SessionStream> Session<T> {
     pub async fn queue_message(&mut self) -> Cow<'static, [u8]> {
+        // Record time spent receiving the message
+        let filter_start = Instant::now();
+        self.server
+            .record_load_test_stage(LoadTestStage::Receive, self.data.data_started.elapsed());
+
         // Parse message
         let raw_message = std::mem::take(&mut self.data.message);
         let parsed_message = match MessageParser::new()
@@ -71,6 +80,15 @@ impl<T: SessionStream> Session<T> {
         let has_date_header = auth_message.has_date_header();
         let has_message_id_header = auth_message.has_message_id_header();
 
+        // RFC 8689 - TLS-Required: No
+        let is_tls_not_required = parsed_message
+            .root_part()
+            .headers
+            .iter()
+            .find(|h| h.name.as_str().eq_ignore_ascii_case("TLS-Required"))
+            .and_then(|h| h.value.as_text())
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("no"));
+
         // Loop detection
         let dc = &self.server.core.smtp.session.data;
         let ac = &self.server.core.smtp.mail_auth;
@@ -92,6 +110,48 @@ impl<T: SessionStream> Session<T> {
                 .into();
         }
 
+        // Make sure that the authenticated user is allowed to use the From header addresses
+        if self.is_authenticated()
+            && self
+                .server
+                .eval_if(
+                    &self.server.core.smtp.session.auth.must_match_from,
+                    self,
+                    self.data.session_id,
+                )
+                .await
+                .unwrap_or(false)
+        {
+            for address in parsed_message
+                .from()
+                .into_iter()
+                .flat_map(|from| from.iter())
+                .filter_map(|addr| addr.address())
+            {
+                let address_lcase = address.trim().to_lowercase();
+                if !self.is_allowed_sender(&address_lcase) {
+                    trc::event!(
+                        Smtp(SmtpEvent::FromUnauthorized),
+                        SpanId = self.data.session_id,
+                        From = address_lcase.clone(),
+                        AccountName = self.authenticated_as().unwrap_or_default().to_string(),
+                    );
+                    trc::event!(
+                        Security(SecurityEvent::UnauthorizedSender),
+                        SpanId = self.data.session_id,
+                        From = address_lcase.clone(),
+                        AccountName = self.authenticated_as().unwrap_or_default().to_string(),
+                    );
+
+                    return format!(
+                        "550 5.7.1 You are not allowed to send as <{address_lcase}>.\r\n"
+                    )
+                    .into_bytes()
+                    .into();
+                }
+            }
+        }
+
         // Verify DKIM
         let dkim = self
             .server
@@ -258,9 +318,7 @@ impl<T: SessionStream> Session<T> {
                                     &self.data.helo_domain
                                 },
                                 spf_output,
-                                domain_suffix_fn: |domain| {
-                                    psl::domain_str(domain).unwrap_or(domain)
-                                },
+                                domain_suffix_fn: org_domain,
                             },
                         ))
                         .await;
@@ -486,6 +544,13 @@ impl<T: SessionStream> Session<T> {
             }
         };
 
+        // Milters and MTA hooks quarantine messages by adding an X-Quarantine header
+        let is_quarantined = self.server.core.smtp.queue.quarantine.hold
+            && modifications.iter().any(|m| {
+                matches!(m, Modification::AddHeader { name, .. }
+                    if name.eq_ignore_ascii_case("X-Quarantine"))
+            });
+
         // Apply modifications
         let mut edited_message = if !modifications.is_empty() {
             self.data
@@ -589,12 +654,35 @@ impl<T: SessionStream> Session<T> {
             }
         }
 
+        // Assign a priority to messages that did not request one
+        if self.data.priority == 0 {
+            if let Some(priority) = self
+                .server
+                .eval_if::<i64, _>(&dc.priority, self, self.data.session_id)
+                .await
+            {
+                self.data.priority = priority.clamp(-9, 9) as i16;
+            }
+        }
+
         // Build message
         let mail_from = self.data.mail_from.clone().unwrap();
         let rcpt_to = std::mem::take(&mut self.data.rcpt_to);
         let mut message = self
             .build_message(mail_from, rcpt_to, message_id, self.data.session_id)
             .await;
+        if is_quarantined {
+            message.flags |= MAIL_QUARANTINED;
+        }
+
+        // The TLS-Required header field is ignored when REQUIRETLS was requested
+        if is_tls_not_required && (message.flags & MAIL_REQUIRETLS) == 0 {
+            trc::event!(
+                Smtp(SmtpEvent::TlsRequiredNo),
+                SpanId = self.data.session_id,
+            );
+            message.flags |= MAIL_TLS_NOT_REQUIRED;
+        }
 
         // Add Return-Path
         if self
@@ -634,12 +722,29 @@ impl<T: SessionStream> Session<T> {
 
         // DKIM sign
         let raw_message = edited_message.as_deref().unwrap_or(raw_message.as_slice());
-        for signer in self
+        let mut signers = self
             .server
             .eval_if::<Vec<String>, _>(&ac.dkim.sign, self, self.data.session_id)
             .await
-            .unwrap_or_default()
+            .unwrap_or_default();
+        if let Some(grant) = self
+            .data
+            .mail_from
+            .as_ref()
+            .and_then(|mail_from| self.sender_grant(&mail_from.address_lcase))
+            .filter(|grant| grant.dkim)
         {
+            // Sign with the keys of domains granted to the authenticated user
+            for signer in [
+                format!("rsa-{}", grant.domain()),
+                format!("ed25519-{}", grant.domain()),
+            ] {
+                if !signers.contains(&signer) {
+                    signers.push(signer);
+                }
+            }
+        }
+        for signer in signers {
             if let Some(signer) = self.server.get_dkim_signer(&signer, self.data.session_id) {
                 match signer.sign_chained(&[headers.as_ref(), raw_message]) {
                     Ok(signature) => {
@@ -658,6 +763,28 @@ impl<T: SessionStream> Session<T> {
 
         // Update size
         message.size = (raw_message.len() + headers.len()) as u64;
+        self.server
+            .record_load_test_stage(LoadTestStage::Filter, filter_start.elapsed());
+
+        // Discard messages addressed exclusively to test mode sink domains
+        let load_test = &self.server.core.smtp.load_test;
+        if load_test.enable
+            && message
+                .domains
+                .iter()
+                .all(|domain| load_test.is_sink_domain(&domain.domain))
+        {
+            trc::event!(
+                Smtp(SmtpEvent::TestSinkDiscard),
+                SpanId = self.data.session_id,
+                QueueId = message.queue_id,
+                Size = message.size,
+                Elapsed = self.data.data_started.elapsed(),
+            );
+
+            self.data.messages_sent += 1;
+            return (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into();
+        }
 
         // Verify queue quota
         if self.server.has_quota(&mut message).await {
@@ -911,7 +1038,10 @@ impl<T: SessionStream> Session<T> {
         }
         headers.extend_from_slice(b"by ");
         headers.extend_from_slice(self.hostname.as_bytes());
-        headers.extend_from_slice(b" (Stalwart SMTP) with ");
+        if !self.instance.banner.hide_product {
+            headers.extend_from_slice(b" (Stalwart SMTP)");
+        }
+        headers.extend_from_slice(b" with ");
         headers.extend_from_slice(match (self.stream.is_tls(), !self.is_authenticated()) {
             (true, true) => b"ESMTPS",
             (true, false) => b"ESMTPSA",
@@ -919,7 +1049,7 @@ impl<T: SessionStream> Session<T> {
             (false, false) => b"ESMTPA",
         });
         headers.extend_from_slice(b" id ");
-        headers.extend_from_slice(format!("{id:X}").as_bytes());
+        headers.extend_from_slice(format!("{id:x}").as_bytes());
         headers.extend_from_slice(b";\r\n\t");
         headers.extend_from_slice(Date::now().to_rfc822().as_bytes());
         headers.extend_from_slice(b"\r\n");
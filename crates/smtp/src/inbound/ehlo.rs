@@ -193,11 +193,12 @@ impl<T: SessionStream> Session<T> {
         }
 
         // Require TLS
-        if self
-            .server
-            .eval_if(&ec.requiretls, self, self.data.session_id)
-            .await
-            .unwrap_or(true)
+        if self.stream.is_tls()
+            && self
+                .server
+                .eval_if(&ec.requiretls, self, self.data.session_id)
+                .await
+                .unwrap_or(true)
         {
             response.capabilities |= EXT_REQUIRE_TLS;
         }
@@ -214,12 +215,12 @@ impl<T: SessionStream> Session<T> {
 
         // Authentication
         if !self.is_authenticated() {
-            response.auth_mechanisms = self
-                .server
-                .eval_if::<Mechanism, _>(&ac.mechanisms, self, self.data.session_id)
-                .await
-                .unwrap_or_default()
-                .into();
+            response.auth_mechanisms = u64::from(
+                self.server
+                    .eval_if::<Mechanism, _>(&ac.mechanisms, self, self.data.session_id)
+                    .await
+                    .unwrap_or_default(),
+            ) & !self.hidden_auth_mechanisms();
             if response.auth_mechanisms != 0 {
                 response.capabilities |= EXT_AUTH;
             }
@@ -284,6 +285,17 @@ impl<T: SessionStream> Session<T> {
             };
         }
 
+        // Hide capabilities as configured for this listener
+        for (capability, name) in [
+            (EXT_PIPELINING, "PIPELINING"),
+            (EXT_VRFY, "VRFY"),
+            (EXT_EXPN, "EXPN"),
+        ] {
+            if self.is_capability_hidden(name) {
+                response.capabilities &= !capability;
+            }
+        }
+
         // Generate response
         let mut buf = Vec::with_capacity(64);
         response.write(&mut buf).ok();
@@ -6,6 +6,7 @@
 
 pub mod client;
 pub mod message;
+pub mod upload;
 
 use ahash::AHashMap;
 
@@ -97,6 +98,8 @@ pub enum Stage {
     Rcpt,
     #[serde(rename = "data")]
     Data,
+    #[serde(rename = "upload")]
+    Upload,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -202,6 +205,7 @@ impl From<common::config::smtp::session::Stage> for Stage {
             common::config::smtp::session::Stage::Mail => Stage::Mail,
             common::config::smtp::session::Stage::Rcpt => Stage::Rcpt,
             common::config::smtp::session::Stage::Data => Stage::Data,
+            common::config::smtp::session::Stage::Upload => Stage::Upload,
         }
     }
 }
@@ -0,0 +1,189 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{net::IpAddr, time::Instant};
+
+use common::{
+    DAEMON_NAME, Server,
+    config::smtp::session::{MTAHook, Stage},
+    expr::{self, V_AUTHENTICATED_AS, V_PROTOCOL, V_REMOTE_IP, functions::ResolveVariable},
+};
+use mail_builder::encoders::base64::base64_encode;
+use trc::MtaHookEvent;
+
+use super::{
+    Action, Client, Context, Message, Protocol, Request, Sasl, Server as HookServer,
+    client::send_mta_hook_request,
+};
+
+pub struct UploadScan<'x> {
+    pub login: &'x str,
+    pub remote_ip: Option<IpAddr>,
+    pub content_type: &'x str,
+    pub contents: &'x [u8],
+}
+
+pub enum UploadScanResult {
+    Accept,
+    Reject(String),
+    TempFail(String),
+}
+
+pub trait UploadHooks: Sync + Send {
+    fn has_upload_hooks(&self) -> bool;
+
+    fn run_upload_hooks(
+        &self,
+        upload: &UploadScan<'_>,
+        session_id: u64,
+    ) -> impl std::future::Future<Output = UploadScanResult> + Send;
+}
+
+impl UploadHooks for Server {
+    fn has_upload_hooks(&self) -> bool {
+        self.core
+            .smtp
+            .session
+            .hooks
+            .iter()
+            .any(|hook| hook.run_on_stage.contains(&Stage::Upload))
+    }
+
+    async fn run_upload_hooks(&self, upload: &UploadScan<'_>, session_id: u64) -> UploadScanResult {
+        let remote_ip = upload
+            .remote_ip
+            .map(|ip| ip.to_string())
+            .unwrap_or_default();
+        let resolver = UploadResolver {
+            upload,
+            remote_ip: &remote_ip,
+        };
+
+        for mta_hook in &self.core.smtp.session.hooks {
+            if !mta_hook.run_on_stage.contains(&Stage::Upload)
+                || !self
+                    .eval_if(&mta_hook.enable, &resolver, session_id)
+                    .await
+                    .unwrap_or(false)
+            {
+                continue;
+            }
+
+            let time = Instant::now();
+            match run_upload_hook(mta_hook, upload, &remote_ip).await {
+                Ok(response) => {
+                    trc::event!(
+                        MtaHook(match response.action {
+                            Action::Accept => MtaHookEvent::ActionAccept,
+                            Action::Discard => MtaHookEvent::ActionDiscard,
+                            Action::Reject => MtaHookEvent::ActionReject,
+                            Action::Quarantine => MtaHookEvent::ActionQuarantine,
+                        }),
+                        SpanId = session_id,
+                        Id = mta_hook.id.clone(),
+                        Elapsed = time.elapsed(),
+                    );
+
+                    // Uploads cannot be modified or quarantined, any verdict other
+                    // than accept rejects the file.
+                    if !matches!(response.action, Action::Accept) {
+                        return UploadScanResult::Reject(
+                            response
+                                .response
+                                .and_then(|response| response.message)
+                                .unwrap_or_else(|| {
+                                    format!("File rejected by content scanner {:?}", mta_hook.id)
+                                }),
+                        );
+                    }
+                }
+                Err(err) => {
+                    trc::event!(
+                        MtaHook(MtaHookEvent::Error),
+                        SpanId = session_id,
+                        Id = mta_hook.id.clone(),
+                        Reason = err.clone(),
+                        Elapsed = time.elapsed(),
+                    );
+
+                    if mta_hook.tempfail_on_error {
+                        return UploadScanResult::TempFail(err);
+                    }
+                }
+            }
+        }
+
+        UploadScanResult::Accept
+    }
+}
+
+async fn run_upload_hook(
+    mta_hook: &MTAHook,
+    upload: &UploadScan<'_>,
+    remote_ip: &str,
+) -> Result<super::Response, String> {
+    // Binary contents are sent base64 encoded as a single MIME part
+    let contents = base64_encode(upload.contents)
+        .ok()
+        .and_then(|contents| String::from_utf8(contents).ok())
+        .unwrap_or_default();
+    let request = Request {
+        context: Context {
+            stage: Stage::Upload.into(),
+            client: Client {
+                ip: remote_ip.to_string(),
+                port: 0,
+                ptr: None,
+                helo: None,
+                active_connections: 1,
+            },
+            sasl: Some(Sasl {
+                login: upload.login.to_string(),
+                method: None,
+            }),
+            tls: None,
+            server: HookServer {
+                name: Some(DAEMON_NAME.into()),
+                port: 0,
+                ip: None,
+            },
+            queue: None,
+            protocol: Protocol { version: 1 },
+        },
+        envelope: None,
+        message: Some(Message {
+            headers: vec![
+                ("Content-Type".into(), upload.content_type.into()),
+                ("Content-Transfer-Encoding".into(), "base64".into()),
+            ],
+            server_headers: vec![],
+            contents,
+            size: upload.contents.len(),
+        }),
+    };
+
+    send_mta_hook_request(mta_hook, request).await
+}
+
+struct UploadResolver<'x> {
+    upload: &'x UploadScan<'x>,
+    remote_ip: &'x str,
+}
+
+impl ResolveVariable for UploadResolver<'_> {
+    fn resolve_variable(&self, variable: u32) -> expr::Variable<'_> {
+        match variable {
+            V_AUTHENTICATED_AS => self.upload.login.into(),
+            V_REMOTE_IP => self.remote_ip.into(),
+            V_PROTOCOL => "jmap".into(),
+            _ => expr::Variable::default(),
+        }
+    }
+
+    fn resolve_global(&self, _: &str) -> expr::Variable<'_> {
+        expr::Variable::Integer(0)
+    }
+}
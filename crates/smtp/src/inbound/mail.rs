@@ -10,7 +10,7 @@ use common::{config::smtp::session::Stage, listener::SessionStream, scripts::Scr
 
 use mail_auth::{IprevOutput, IprevResult, SpfOutput, SpfResult, spf::verify::SpfParameters};
 use smtp_proto::{MAIL_BY_NOTIFY, MAIL_BY_RETURN, MAIL_REQUIRETLS, MailFrom, MtPriority};
-use trc::SmtpEvent;
+use trc::{SecurityEvent, SmtpEvent};
 use utils::config::Rate;
 
 use crate::{
@@ -244,12 +244,7 @@ impl<T: SessionStream> Session<T> {
                     .unwrap_or(true) =>
             {
                 let address_lcase = self.data.mail_from.as_ref().unwrap().address_lcase.as_str();
-                if authenticated_as != address_lcase
-                    && !self.authenticated_emails().iter().any(|e| {
-                        e == address_lcase
-                            || (e.starts_with('@') && address_lcase.ends_with(e.as_str()))
-                    })
-                {
+                if !self.is_allowed_sender(address_lcase) {
                     trc::event!(
                         Smtp(SmtpEvent::MailFromUnauthorized),
                         SpanId = self.data.session_id,
@@ -263,6 +258,12 @@ impl<T: SessionStream> Session<T> {
                             )
                             .collect::<Vec<_>>()
                     );
+                    trc::event!(
+                        Security(SecurityEvent::UnauthorizedSender),
+                        SpanId = self.data.session_id,
+                        From = address_lcase.to_string(),
+                        AccountName = authenticated_as.to_string(),
+                    );
                     self.data.mail_from = None;
                     return self
                         .write(b"501 5.5.4 You are not allowed to send from this address.\r\n")
@@ -291,6 +292,17 @@ impl<T: SessionStream> Session<T> {
                 .write(b"501 5.5.4 REQUIRETLS has been disabled.\r\n")
                 .await;
         }
+        if (from.flags & MAIL_REQUIRETLS) != 0 && !self.stream.is_tls() {
+            trc::event!(
+                Smtp(SmtpEvent::RequireTlsDisabled),
+                SpanId = self.data.session_id,
+                Details = "REQUIRETLS received over a cleartext connection",
+            );
+            self.data.mail_from = None;
+            return self
+                .write(b"530 5.7.10 REQUIRETLS requires a TLS connection.\r\n")
+                .await;
+        }
         if (from.flags & (MAIL_BY_NOTIFY | MAIL_BY_RETURN)) != 0 {
             if let Some(duration) = self
                 .server
@@ -339,7 +351,23 @@ impl<T: SessionStream> Session<T> {
                 .is_some()
             {
                 if (-6..6).contains(&from.mt_priority) {
-                    self.data.priority = from.mt_priority as i16;
+                    // Enforce the maximum priority allowed for this sender
+                    let max_priority = self
+                        .server
+                        .eval_if::<i64, _>(&config.mt_priority_max, self, self.data.session_id)
+                        .await
+                        .unwrap_or(0);
+                    if from.mt_priority > max_priority {
+                        trc::event!(
+                            Smtp(SmtpEvent::MtPriorityLowered),
+                            SpanId = self.data.session_id,
+                            Details = from.mt_priority,
+                            Limit = max_priority,
+                        );
+                        self.data.priority = max_priority.clamp(-6, 5) as i16;
+                    } else {
+                        self.data.priority = from.mt_priority as i16;
+                    }
                 } else {
                     trc::event!(
                         Smtp(SmtpEvent::MtPriorityInvalid),
@@ -86,8 +86,15 @@ impl<T: SessionStream> Session<T> {
             self.data.rcpt_oks += 1;
             return self.write(b"250 2.1.5 OK\r\n").await;
         }
+        let is_sink = self.server.core.smtp.load_test.is_sink_domain(&rcpt.domain);
         self.data.rcpt_to.push(rcpt);
 
+        // Test mode sink domains accept any recipient
+        if is_sink {
+            self.data.rcpt_oks += 1;
+            return self.write(b"250 2.1.5 OK\r\n").await;
+        }
+
         // Address rewriting and Sieve filtering
         let rcpt_script = self
             .server
@@ -215,7 +222,89 @@ impl<T: SessionStream> Session<T> {
                         .rcpt(directory, &rcpt.address_lcase, self.data.session_id)
                         .await
                     {
-                        Ok(RcptType::Mailbox) => {}
+                        Ok(RcptType::Mailbox) => {
+                            // Mailboxes in maintenance are read-only, defer new deliveries
+                            match self
+                                .server
+                                .is_address_in_maintenance(
+                                    directory,
+                                    &rcpt.address_lcase,
+                                    self.data.session_id,
+                                )
+                                .await
+                            {
+                                Ok(false) => {}
+                                Ok(true) => {
+                                    trc::event!(
+                                        Limit(trc::LimitEvent::Maintenance),
+                                        SpanId = self.data.session_id,
+                                        To = rcpt.address_lcase.clone(),
+                                    );
+
+                                    self.data.rcpt_to.pop();
+                                    return self
+                                        .write(
+                                            b"452 4.2.1 Mailbox temporarily unavailable, try again later.\r\n",
+                                        )
+                                        .await;
+                                }
+                                Err(err) => {
+                                    trc::error!(
+                                        err.span_id(self.data.session_id)
+                                            .caused_by(trc::location!())
+                                            .details("Failed to verify maintenance status.")
+                                    );
+
+                                    self.data.rcpt_to.pop();
+                                    return self
+                                        .write(
+                                            b"451 4.4.3 Unable to verify address at this time.\r\n",
+                                        )
+                                        .await;
+                                }
+                            }
+
+                            // Defer deliveries while the mailbox indexing backlog is too large
+                            match self
+                                .server
+                                .is_address_index_backlogged(
+                                    directory,
+                                    &rcpt.address_lcase,
+                                    self.data.session_id,
+                                )
+                                .await
+                            {
+                                Ok(false) => {}
+                                Ok(true) => {
+                                    trc::event!(
+                                        Limit(trc::LimitEvent::IndexBacklog),
+                                        SpanId = self.data.session_id,
+                                        To = rcpt.address_lcase.clone(),
+                                    );
+
+                                    self.data.rcpt_to.pop();
+                                    return self
+                                        .write(
+                                            b"452 4.3.1 Mailbox temporarily unavailable, try again later.\r\n",
+                                        )
+                                        .await;
+                                }
+                                Err(err) => {
+                                    trc::error!(
+                                        err.span_id(self.data.session_id)
+                                            .caused_by(trc::location!())
+                                            .details("Failed to verify indexing backlog.")
+                                    );
+
+                                    self.data.rcpt_to.pop();
+                                    return self
+                                        .write(
+                                            b"451 4.4.3 Unable to verify address at this time.\r\n",
+                                        )
+                                        .await;
+                                }
+                            }
+                        }
                         Ok(RcptType::List(members)) => {
                             rcpt_members = Some(members);
                         }
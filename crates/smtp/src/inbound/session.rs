@@ -4,6 +4,8 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::time::Instant;
+
 use common::{
     config::{server::ServerProtocol, smtp::session::Mechanism},
     expr::{self, functions::ResolveVariable, *},
@@ -58,6 +60,7 @@ impl<T: SessionStream> Session<T> {
                                     self.write(b"354 Start mail input; end with <CRLF>.<CRLF>\r\n")
                                         .await?;
                                     self.data.message = Vec::with_capacity(1024);
+                                    self.data.data_started = Instant::now();
                                     state = State::Data(DataReceiver::new());
                                     continue 'outer;
                                 }
@@ -71,6 +74,7 @@ impl<T: SessionStream> Session<T> {
                                 {
                                     if self.data.message.is_empty() {
                                         self.data.message = Vec::with_capacity(chunk_size);
+                                        self.data.data_started = Instant::now();
                                     } else {
                                         self.data.message.reserve(chunk_size);
                                     }
@@ -95,6 +99,7 @@ impl<T: SessionStream> Session<T> {
                                     .await
                                     .unwrap_or_default()
                                     .into();
+                                let auth = auth & !self.hidden_auth_mechanisms();
                                 if auth == 0 || self.params.auth_directory.is_none() {
                                     trc::event!(
                                         Smtp(SmtpEvent::AuthNotAllowed),
@@ -193,8 +198,14 @@ impl<T: SessionStream> Session<T> {
                             Request::Help { .. } => {
                                 trc::event!(Smtp(SmtpEvent::Help), SpanId = self.data.session_id,);
 
-                                self.write(b"250 2.0.0 Help can be found at https://stalw.art\r\n")
+                                if !self.instance.banner.hide_product {
+                                    self.write(
+                                        b"250 2.0.0 Help can be found at https://stalw.art\r\n",
+                                    )
                                     .await?;
+                                } else {
+                                    self.write(b"250 2.0.0 OK\r\n").await?;
+                                }
                             }
                             Request::Helo { host } => {
                                 if self.instance.protocol == ServerProtocol::Smtp {
@@ -342,6 +353,12 @@ impl<T: SessionStream> Session<T> {
                     if self.data.message.len() + bytes.len() < self.params.max_message_size {
                         if receiver.ingest(&mut iter, &mut self.data.message) {
                             let message = self.queue_message().await;
+                            if let Some(fingerprint) = &self.data.tls_fingerprint {
+                                self.server.track_tls_fingerprint(
+                                    fingerprint,
+                                    message.is_empty() || message.starts_with(b"5"),
+                                );
+                            }
                             let num_responses = if self.instance.protocol == ServerProtocol::Smtp {
                                 1
                             } else {
@@ -369,6 +386,18 @@ impl<T: SessionStream> Session<T> {
                         if self.can_send_data().await? {
                             if receiver.is_last {
                                 let message = self.queue_message().await;
+                                if let Some(fingerprint) = &self.data.tls_fingerprint {
+                                    self.server.track_tls_fingerprint(
+                                        fingerprint,
+                                        message.is_empty() || message.starts_with(b"5"),
+                                    );
+                                }
+                                if let Some(fingerprint) = &self.data.tls_fingerprint {
+                                    self.server.track_tls_fingerprint(
+                                        fingerprint,
+                                        message.is_empty() || message.starts_with(b"5"),
+                                    );
+                                }
                                 if !message.is_empty() {
                                     let num_responses =
                                         if self.instance.protocol == ServerProtocol::Smtp {
@@ -579,6 +608,12 @@ impl<T: SessionStream> ResolveVariable for Session<T> {
             V_LOCAL_IP => self.data.local_ip_str.as_str().into(),
             V_LOCAL_PORT => self.data.local_port.into(),
             V_TLS => self.stream.is_tls().into(),
+            V_TLS_FINGERPRINT => self
+                .data
+                .tls_fingerprint
+                .as_deref()
+                .unwrap_or_default()
+                .into(),
             V_PRIORITY => self.data.priority.to_compact_string().into(),
             V_PROTOCOL => self.instance.protocol.as_str().into(),
             V_ASN => self
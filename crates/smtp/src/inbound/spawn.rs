@@ -25,15 +25,20 @@ impl SessionManager for SmtpSessionManager {
         // Build server and create session
         let server = self.inner.build_server();
         let _in_flight = session.in_flight;
+        let mut data = SessionData::new(
+            session.local_ip,
+            session.local_port,
+            session.remote_ip,
+            session.remote_port,
+            server.lookup_asn_country(session.remote_ip).await,
+            session.session_id,
+        );
+        if let Some(fingerprint) = session.tls_fingerprint {
+            server.track_tls_fingerprint(&fingerprint, false);
+            data.tls_fingerprint = Some(fingerprint);
+        }
         let mut session = Session {
-            data: SessionData::new(
-                session.local_ip,
-                session.local_port,
-                session.remote_ip,
-                session.remote_port,
-                server.lookup_asn_country(session.remote_ip).await,
-                session.session_id,
-            ),
+            data,
             hostname: "".into(),
             server,
             instance: session.instance,
@@ -130,13 +135,26 @@ impl<T: SessionStream> Session<T> {
         }
 
         // Obtain greeting
-        let greeting = self
-            .server
-            .eval_if::<String, _>(&config.greeting, self, self.data.session_id)
-            .await
-            .filter(|g| !g.is_empty())
-            .map(|g| format!("220 {}\r\n", g))
-            .unwrap_or_else(|| "220 Stalwart ESMTP at your service.\r\n".to_string());
+        let greeting = if let Some(greeting) =
+            self.instance
+                .banner
+                .greeting(&self.hostname, &self.instance.id, self.instance.protocol)
+        {
+            format!("220 {}\r\n", greeting)
+        } else {
+            self.server
+                .eval_if::<String, _>(&config.greeting, self, self.data.session_id)
+                .await
+                .filter(|g| !g.is_empty())
+                .map(|g| format!("220 {}\r\n", g))
+                .unwrap_or_else(|| {
+                    if !self.instance.banner.hide_product {
+                        "220 Stalwart ESMTP at your service.\r\n".to_string()
+                    } else {
+                        "220 ESMTP at your service.\r\n".to_string()
+                    }
+                })
+        };
 
         if self.write(greeting.as_bytes()).await.is_err() {
             return false;
@@ -256,13 +274,19 @@ impl<T: SessionStream> Session<T> {
         false
     }
 
-    pub async fn into_tls(self) -> Result<Session<TlsStream<T>>, ()> {
+    pub async fn into_tls(mut self) -> Result<Session<TlsStream<T>>, ()> {
+        let (stream, fingerprint) = self
+            .instance
+            .tls_accept_with_fingerprint(self.stream, self.data.session_id)
+            .await?;
+        if let Some(fingerprint) = fingerprint {
+            self.server.track_tls_fingerprint(&fingerprint, false);
+            self.data.tls_fingerprint = Some(fingerprint);
+        }
+
         Ok(Session {
             hostname: self.hostname,
-            stream: self
-                .instance
-                .tls_accept(self.stream, self.data.session_id)
-                .await?,
+            stream,
             state: self.state,
             data: self.data,
             instance: self.instance,
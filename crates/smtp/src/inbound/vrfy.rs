@@ -23,7 +23,7 @@ impl<T: SessionStream> Session<T> {
             .await
             .and_then(|name| self.server.get_directory(&name))
         {
-            Some(directory) if self.params.can_vrfy => {
+            Some(directory) if self.params.can_vrfy && !self.is_capability_hidden("VRFY") => {
                 match self
                     .server
                     .vrfy(directory, &address.to_lowercase(), self.data.session_id)
@@ -96,7 +96,7 @@ impl<T: SessionStream> Session<T> {
             .await
             .and_then(|name| self.server.get_directory(&name))
         {
-            Some(directory) if self.params.can_expn => {
+            Some(directory) if self.params.can_expn && !self.is_capability_hidden("EXPN") => {
                 match self
                     .server
                     .expn(directory, &address.to_lowercase(), self.data.session_id)
@@ -672,5 +672,8 @@ pub(crate) fn from_error_status(status: &Status<(), Error>) -> trc::Error {
         Error::RateLimited => event.details("Rate Limited"),
         Error::ConcurrencyLimited => event.details("Concurrency Limited"),
         Error::Io(err) => event.details("I/O Error").reason(err),
+        Error::RequireTls(err) => event
+            .details("REQUIRETLS Error")
+            .ctx(trc::Key::Reason, err.details.clone()),
     }
 }
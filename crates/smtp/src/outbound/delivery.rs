@@ -9,6 +9,7 @@ use crate::outbound::dane::dnssec::TlsaLookup;
 use crate::outbound::lookup::DnsLookup;
 use crate::outbound::mta_sts::lookup::MtaStsLookup;
 use crate::outbound::mta_sts::verify::VerifyPolicy;
+use crate::outbound::warmup::{IpWarmupManager, WarmupCounters};
 use crate::outbound::{client::StartTlsResult, dane::verify::TlsaVerify};
 use crate::queue::dsn::SendDsn;
 use crate::queue::spool::{LOCK_EXPIRY, SmtpSpool};
@@ -17,7 +18,7 @@ use crate::reporting::SmtpReporting;
 use common::Server;
 use common::config::{
     server::ServerProtocol,
-    smtp::{queue::RequireOptional, report::AggregateFrequency},
+    smtp::{classifier::ResponseClassifier, queue::RequireOptional, report::AggregateFrequency},
 };
 use common::ipc::{PolicyType, QueueEvent, QueueEventStatus, TlsEvent};
 
@@ -37,12 +38,12 @@ use store::write::{BatchBuilder, QueueClass, ValueClass, now};
 use trc::{DaneEvent, DeliveryEvent, MtaStsEvent, ServerEvent, TlsRptEvent};
 
 use crate::{
-    queue::{ErrorDetails, Message},
+    queue::{ErrorDetails, MAIL_TLS_NOT_REQUIRED, Message},
     reporting::tls::TlsRptOptions,
 };
 
 use super::{NextHop, TlsStrategy, lookup::ToNextHop, mta_sts, session::SessionParams};
-use crate::queue::{Domain, Error, QueueEnvelope, QueuedMessage, Status};
+use crate::queue::{Domain, Error, QueueEnvelope, QueuedMessage, Recipient, Status};
 
 impl QueuedMessage {
     pub fn try_deliver(self, server: Server) {
@@ -282,6 +283,8 @@ impl QueuedMessage {
                     .unwrap_or(RequireOptional::Optional),
                 ..Default::default()
             };
+            tls_strategy.apply_message_flags(message.flags);
+            let is_require_tls = (message.flags & MAIL_REQUIRETLS) != 0;
             let allow_invalid_certs = server
                 .eval_if(&queue_config.tls.invalid_certs, &envelope, message.span_id)
                 .await
@@ -583,11 +586,12 @@ impl QueuedMessage {
                 .await
                 .unwrap_or(2);
             let mut last_status = Status::Scheduled;
+            let mut warmup_reservations = Vec::new();
             'next_host: for remote_host in &remote_hosts {
                 // Validate MTA-STS
                 envelope.mx = remote_host.hostname();
                 if let Some(mta_sts_policy) = &mta_sts_policy {
-                    let strict = mta_sts_policy.enforce();
+                    let strict = mta_sts_policy.enforce() || is_require_tls;
                     if !mta_sts_policy.verify(envelope.mx) {
                         // Report MTA-STS failed verification
                         if let Some(tls_report) = &tls_report {
@@ -678,6 +682,7 @@ impl QueuedMessage {
                         continue 'next_host;
                     }
                 };
+                warmup_reservations.extend(resolve_result.warmup_reservations);
 
                 // Update TLS strategy
                 tls_strategy.dane = server
@@ -688,6 +693,7 @@ impl QueuedMessage {
                     .eval_if(&queue_config.tls.start, &envelope, message.span_id)
                     .await
                     .unwrap_or(RequireOptional::Optional);
+                tls_strategy.apply_message_flags(message.flags);
 
                 // Lookup DANE policy
                 let dane_policy = if tls_strategy.try_dane() && is_smtp {
@@ -848,6 +854,28 @@ impl QueuedMessage {
                     None
                 };
 
+                // RFC 8689 - REQUIRETLS needs an MX authenticated by MTA-STS or DANE
+                if is_require_tls
+                    && is_smtp
+                    && matches!(remote_host, NextHop::MX { .. })
+                    && mta_sts_policy.is_none()
+                    && dane_policy.is_none()
+                {
+                    trc::event!(
+                        Delivery(DeliveryEvent::RequireTlsFailed),
+                        SpanId = message.span_id,
+                        Domain = domain.domain.clone(),
+                        Hostname = envelope.mx.to_string(),
+                        Details = "MX could not be authenticated using MTA-STS or DANE",
+                    );
+
+                    last_status = Status::PermanentFailure(Error::RequireTls(ErrorDetails {
+                        entity: envelope.mx.into(),
+                        details: "MX could not be authenticated using MTA-STS or DANE".into(),
+                    }));
+                    continue 'next_host;
+                }
+
                 // Try each IP address
                 'next_ip: for remote_ip in resolve_result.remote_ips {
                     // Set source IP, if any
@@ -872,6 +900,9 @@ impl QueuedMessage {
                                 RemoteIp = remote_ip,
                             );
                             message.domains[domain_idx].set_rate_limiter_error(retry_at);
+                            server
+                                .release_warmup_reservations(warmup_reservations)
+                                .await;
                             continue 'next_domain;
                         }
                     }
@@ -969,11 +1000,12 @@ impl QueuedMessage {
 
                     // Prepare TLS connector
                     let is_strict_tls = tls_strategy.is_tls_required()
-                        || (message.flags & MAIL_REQUIRETLS) != 0
+                        || is_require_tls
                         || mta_sts_policy.is_some()
                         || dane_policy.is_some();
                     // As per RFC7671 Section 5.1, DANE-EE(3) allows name mismatch
                     let tls_connector = if allow_invalid_certs
+                        || (message.flags & MAIL_TLS_NOT_REQUIRED) != 0
                         || remote_host.allow_invalid_certs()
                         || dane_policy.as_ref().is_some_and(|t| t.has_end_entities)
                     {
@@ -982,6 +1014,15 @@ impl QueuedMessage {
                         &server.inner.data.smtp_connectors.pki_verify
                     };
 
+                    // Recipients delivered in previous attempts are not counted again
+                    let delivered_before = recipients
+                        .iter()
+                        .filter(|r| {
+                            r.domain_idx == domain_idx as u32
+                                && matches!(r.status, Status::Completed(_))
+                        })
+                        .count();
+
                     let delivery_result = if !remote_host.implicit_tls() {
                         // Read greeting
                         smtp_client.timeout = server
@@ -1164,7 +1205,15 @@ impl QueuedMessage {
                                             .await;
                                     }
 
-                                    if is_strict_tls {
+                                    if is_require_tls && response.is_none() {
+                                        last_status = Status::PermanentFailure(Error::RequireTls(
+                                            ErrorDetails {
+                                                entity: envelope.mx.into(),
+                                                details: "STARTTLS not advertised by host".into(),
+                                            },
+                                        ));
+                                        continue 'next_host;
+                                    } else if is_strict_tls {
                                         last_status =
                                             Status::from_starttls_error(envelope.mx, response);
                                         continue 'next_host;
@@ -1301,16 +1350,85 @@ impl QueuedMessage {
                         )
                         .await
                         .unwrap_or_else(|| vec![Duration::from_secs(60)]);
+
+                    // Track outcomes for source IPs under warmup
+                    if let Some(source_ip) = source_ip {
+                        let (mut delivered, mut bounced, mut deferred) = (0, 0, 0);
+                        for rcpt in recipients
+                            .iter()
+                            .filter(|r| r.domain_idx == domain_idx as u32)
+                        {
+                            match (&delivery_result, &rcpt.status) {
+                                (Status::PermanentFailure(_), _)
+                                | (_, Status::PermanentFailure(_)) => bounced += 1,
+                                (Status::TemporaryFailure(_), _)
+                                | (_, Status::TemporaryFailure(_)) => deferred += 1,
+                                (_, Status::Completed(_)) => delivered += 1,
+                                _ => (),
+                            }
+                        }
+
+                        // The capacity reserved for a delivered message is kept
+                        let sent = delivered > delivered_before;
+                        if sent {
+                            if let Some(pos) = warmup_reservations
+                                .iter()
+                                .position(|reservation| reservation.ip == source_ip)
+                            {
+                                warmup_reservations.swap_remove(pos);
+                            }
+                        }
+                        server
+                            .record_warmup_outcome(
+                                source_ip,
+                                WarmupCounters {
+                                    sent: sent as u64,
+                                    bounced,
+                                    deferred,
+                                },
+                            )
+                            .await;
+                    }
+                    server
+                        .release_warmup_reservations(warmup_reservations)
+                        .await;
+
+                    // Adapt the retry interval to the remote server's responses
+                    let schedule = message.domains[domain_idx]
+                        .classify_retry(
+                            &queue_config.classifier,
+                            &delivery_result,
+                            recipients
+                                .iter()
+                                .filter(|r| r.domain_idx == domain_idx as u32),
+                            message.span_id,
+                        )
+                        .map(|retry| vec![retry])
+                        .unwrap_or(schedule);
                     message.domains[domain_idx].set_status(delivery_result, &schedule);
                     continue 'next_domain;
                 }
             }
 
             // Update status
+            server
+                .release_warmup_reservations(warmup_reservations)
+                .await;
             let schedule = server
                 .eval_if::<Vec<Duration>, _>(&queue_config.retry, &envelope, message.span_id)
                 .await
                 .unwrap_or_else(|| vec![Duration::from_secs(60)]);
+            let schedule = message.domains[domain_idx]
+                .classify_retry(
+                    &queue_config.classifier,
+                    &last_status,
+                    recipients
+                        .iter()
+                        .filter(|r| r.domain_idx == domain_idx as u32),
+                    message.span_id,
+                )
+                .map(|retry| vec![retry])
+                .unwrap_or(schedule);
             message.domains[domain_idx].set_status(last_status, &schedule);
         }
         message.recipients = recipients;
@@ -1415,6 +1533,64 @@ impl Domain {
         }
     }
 
+    // Returns a retry interval for the classified temporary failures returned by the
+    // remote server. The regular schedule is used if any failure is unclassified or
+    // its category has no schedule, permanent failures are never rescheduled.
+    pub fn classify_retry<'x>(
+        &self,
+        classifier: &ResponseClassifier,
+        status: &Status<(), Error>,
+        recipients: impl Iterator<Item = &'x Recipient>,
+        span_id: u64,
+    ) -> Option<Duration> {
+        let responses = match status {
+            Status::TemporaryFailure(Error::UnexpectedResponse(response)) => {
+                vec![(response, None)]
+            }
+            Status::Scheduled => recipients
+                .filter_map(|rcpt| match &rcpt.status {
+                    Status::TemporaryFailure(response) => Some((response, Some(&rcpt.address))),
+                    _ => None,
+                })
+                .collect(),
+            _ => return None,
+        };
+        let mut retry: Option<Duration> = None;
+        let mut use_schedule = responses.is_empty();
+
+        for (response, rcpt) in responses {
+            let delay = classifier.classify(&response.response).and_then(|class| {
+                let delay = classifier.retry_delay(&class, self.retry.inner);
+
+                trc::event!(
+                    Delivery(DeliveryEvent::ResponseClassified),
+                    SpanId = span_id,
+                    Domain = self.domain.clone(),
+                    Hostname = response.hostname.entity.clone(),
+                    To = rcpt.cloned(),
+                    Code = response.response.code,
+                    Details = response.response.message.clone(),
+                    Type = class.category.as_str(),
+                    Id = class.rule,
+                    NextRetry = delay.map(|delay| trc::Value::Timestamp(now() + delay.as_secs())),
+                );
+
+                delay
+            });
+
+            match delay {
+                Some(delay) => {
+                    retry = Some(retry.map_or(delay, |retry| retry.min(delay)));
+                }
+                None => {
+                    use_schedule = true;
+                }
+            }
+        }
+
+        if use_schedule { None } else { retry }
+    }
+
     pub fn retry(&mut self, schedule: &[Duration]) {
         self.retry.due = now()
             + schedule[std::cmp::min(self.retry.inner as usize, schedule.len() - 1)].as_secs();
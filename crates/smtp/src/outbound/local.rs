@@ -4,17 +4,27 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use common::Server;
+use common::{
+    KV_ENCRYPTION_FAILURE, Server,
+    config::smtp::system::{TEMPLATE_ENCRYPTION_FAILURE, TEMPLATE_QUARANTINE_RELEASE},
+};
 use email::message::delivery::{IngestMessage, LocalDeliveryStatus, MailDelivery};
+use mail_parser::DateTime;
 use smtp_proto::Response;
+use store::{dispatch::lookup::KeyValue, write::now};
 use trc::SieveEvent;
 
 use crate::{
     queue::{
-        DomainPart, Error, ErrorDetails, HostResponse, Message, MessageSource, RCPT_STATUS_CHANGED,
-        Recipient, Status, quota::HasQueueQuota, spool::SmtpSpool,
+        DomainPart, Error, ErrorDetails, HostResponse, MAIL_QUARANTINED, MAIL_SKIP_ENCRYPTION,
+        Message, MessageSource, RCPT_ENCRYPTION_FAILED, RCPT_QUARANTINE_RELEASED, RCPT_QUARANTINED,
+        RCPT_STATUS_CHANGED, Recipient, Status, quarantine::QuarantineRelease,
+        quota::HasQueueQuota, spool::SmtpSpool,
+    },
+    reporting::{
+        SmtpReporting,
+        system::{SystemMessage, SystemMessages},
     },
-    reporting::SmtpReporting,
 };
 
 impl Message {
@@ -26,6 +36,7 @@ impl Message {
         // Prepare recipients list
         let mut total_rcpt = 0;
         let mut total_completed = 0;
+        let mut total_held = 0;
         let mut pending_recipients = Vec::new();
         let mut recipient_addresses = Vec::new();
         for rcpt in recipients {
@@ -37,9 +48,35 @@ impl Message {
                 total_completed += 1;
                 continue;
             }
+
+            // Quarantined messages are held until each recipient releases them
+            if (self.flags & MAIL_QUARANTINED) != 0 && (rcpt.flags & RCPT_QUARANTINE_RELEASED) == 0
+            {
+                if (rcpt.flags & RCPT_QUARANTINED) == 0 {
+                    rcpt.flags |= RCPT_QUARANTINED | RCPT_STATUS_CHANGED;
+                    rcpt.status = Status::TemporaryFailure(HostResponse {
+                        hostname: ErrorDetails {
+                            entity: "localhost".into(),
+                            details: format!("RCPT TO:<{}>", rcpt.address),
+                        },
+                        response: Response {
+                            code: 451,
+                            esc: [4, 7, 1],
+                            message: "Message held in quarantine".into(),
+                        },
+                    });
+                    self.quarantine_notice(server, rcpt).await;
+                }
+                total_held += 1;
+                continue;
+            }
+
             recipient_addresses.push(rcpt.address_lcase.clone());
             pending_recipients.push(rcpt);
         }
+        if pending_recipients.is_empty() && total_held > 0 {
+            return Status::Scheduled;
+        }
 
         // Deliver message
         let delivery_result = server
@@ -48,6 +85,7 @@ impl Message {
                 recipients: recipient_addresses,
                 message_blob: self.blob_hash.clone(),
                 message_size: self.size,
+                encrypt: (self.flags & MAIL_SKIP_ENCRYPTION) == 0,
                 session_id: self.span_id,
             })
             .await;
@@ -55,6 +93,7 @@ impl Message {
         // Process delivery results
         for (rcpt, result) in pending_recipients.into_iter().zip(delivery_result.status) {
             rcpt.flags |= RCPT_STATUS_CHANGED;
+            rcpt.flags &= !RCPT_ENCRYPTION_FAILED;
             match result {
                 LocalDeliveryStatus::Success => {
                     rcpt.status = Status::Completed(HostResponse {
@@ -94,6 +133,22 @@ impl Message {
                         },
                     });
                 }
+                LocalDeliveryStatus::EncryptionFailure { account_id, reason } => {
+                    self.encryption_failure_alert(server, rcpt, account_id, &reason)
+                        .await;
+                    rcpt.flags |= RCPT_ENCRYPTION_FAILED;
+                    rcpt.status = Status::TemporaryFailure(HostResponse {
+                        hostname: ErrorDetails {
+                            entity: "localhost".into(),
+                            details: format!("RCPT TO:<{}>", rcpt.address),
+                        },
+                        response: Response {
+                            code: 451,
+                            esc: [4, 7, 5],
+                            message: format!("Encryption at rest failed: {reason}"),
+                        },
+                    });
+                }
             }
         }
 
@@ -154,4 +209,124 @@ impl Message {
             Status::Scheduled
         }
     }
+
+    // Alerts the account owner and the operators once a recipient reaches the
+    // configured number of consecutive encryption failures for this message
+    async fn encryption_failure_alert(
+        &self,
+        server: &Server,
+        rcpt: &Recipient,
+        account_id: u32,
+        reason: &str,
+    ) {
+        let alert_after = server.core.jmap.encrypt_failure_alert_after;
+        if alert_after == 0 {
+            return;
+        }
+
+        let mut key = Vec::with_capacity(rcpt.address_lcase.len() + 9);
+        key.push(KV_ENCRYPTION_FAILURE);
+        key.extend_from_slice(&self.queue_id.to_be_bytes());
+        key.extend_from_slice(rcpt.address_lcase.as_bytes());
+        let expires = self
+            .domains
+            .get(rcpt.domain_idx as usize)
+            .map_or(0, |domain| domain.expires.saturating_sub(now()))
+            .max(86400);
+        let attempts = match server
+            .in_memory_store()
+            .counter_incr(KeyValue::new(key, 1).expires(expires), true)
+            .await
+        {
+            Ok(attempts) => attempts as u64,
+            Err(err) => {
+                trc::error!(
+                    err.details("Failed to update encryption failure counter.")
+                        .span_id(self.span_id)
+                        .caused_by(trc::location!())
+                );
+                return;
+            }
+        };
+        if attempts != alert_after {
+            return;
+        }
+
+        let account = server
+            .get_access_token(account_id)
+            .await
+            .map(|token| token.name.clone())
+            .unwrap_or_else(|_| rcpt.address_lcase.clone());
+        let attempts = attempts.to_string();
+        let queue_id = format!("{:x}", self.queue_id);
+        let message = SystemMessage {
+            template: TEMPLATE_ENCRYPTION_FAILURE,
+            from_name: None,
+            from_addr: None,
+            language: None,
+            variables: &[
+                ("account", account.as_str()),
+                ("attempts", attempts.as_str()),
+                ("queue_id", queue_id.as_str()),
+                ("reason", reason),
+            ],
+        };
+
+        // The owner's copy is stored unencrypted, it would fail for the same reason otherwise
+        server
+            .send_system_message_with_flags(
+                &message,
+                [rcpt.address_lcase.as_str()].into_iter(),
+                MAIL_SKIP_ENCRYPTION,
+                self.span_id,
+            )
+            .await;
+        server
+            .send_system_message(
+                &message,
+                server.core.jmap.encrypt_failure_notify.iter(),
+                self.span_id,
+            )
+            .await;
+    }
+
+    // Sends the recipient a link to release a message held in quarantine
+    async fn quarantine_notice(&self, server: &Server, rcpt: &Recipient) {
+        trc::event!(
+            Queue(trc::QueueEvent::Quarantined),
+            SpanId = self.span_id,
+            QueueId = self.queue_id,
+            From = self.return_path_lcase.clone(),
+            To = rcpt.address_lcase.clone(),
+        );
+
+        let (release_url, expires) =
+            server.quarantine_release_url(self.queue_id, &rcpt.address_lcase);
+        let from = if !self.return_path.is_empty() {
+            self.return_path.as_str()
+        } else {
+            "<>"
+        };
+        let queue_id = format!("{:x}", self.queue_id);
+        let expires = DateTime::from_timestamp(expires as i64).to_rfc822();
+        server
+            .send_system_message(
+                &SystemMessage {
+                    template: TEMPLATE_QUARANTINE_RELEASE,
+                    from_name: None,
+                    from_addr: None,
+                    language: None,
+                    variables: &[
+                        ("from", from),
+                        ("rcpt", rcpt.address.as_str()),
+                        ("queue_id", queue_id.as_str()),
+                        ("expires", expires.as_str()),
+                        ("release_url", release_url.as_str()),
+                    ],
+                },
+                [rcpt.address_lcase.as_str()].into_iter(),
+                self.span_id,
+            )
+            .await;
+    }
 }
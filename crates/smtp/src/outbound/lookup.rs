@@ -12,19 +12,23 @@ use std::{
 
 use common::{
     Server,
-    expr::{V_MX, functions::ResolveVariable},
+    expr::{V_MX, V_RECIPIENT_DOMAIN, functions::ResolveVariable},
 };
 use mail_auth::{IpLookupStrategy, MX};
-use rand::{Rng, seq::SliceRandom};
+use rand::seq::SliceRandom;
 
 use crate::queue::{Error, ErrorDetails, Status};
 
-use super::NextHop;
+use super::{
+    NextHop,
+    warmup::{IpWarmupManager, WarmupReservation},
+};
 
 pub struct IpLookupResult {
     pub source_ipv4: Option<IpAddr>,
     pub source_ipv6: Option<IpAddr>,
     pub remote_ips: Vec<IpAddr>,
+    pub warmup_reservations: Vec<WarmupReservation>,
 }
 
 pub trait DnsLookup: Sync + Send {
@@ -173,48 +177,59 @@ impl DnsLookup for Server {
                 source_ipv4: None,
                 source_ipv6: None,
                 remote_ips,
+                warmup_reservations: Vec::new(),
             };
 
-            // Obtain source IPv4 address
-            let source_ips = self
-                .eval_if::<Vec<Ipv4Addr>, _>(
-                    &self.core.smtp.queue.source_ip.ipv4,
-                    envelope,
-                    session_id,
-                )
-                .await
-                .unwrap_or_default();
-            match source_ips.len().cmp(&1) {
-                std::cmp::Ordering::Equal => {
-                    result.source_ipv4 = IpAddr::from(*source_ips.first().unwrap()).into();
-                }
-                std::cmp::Ordering::Greater => {
-                    result.source_ipv4 =
-                        IpAddr::from(source_ips[rand::rng().random_range(0..source_ips.len())])
-                            .into();
-                }
-                std::cmp::Ordering::Less => (),
+            // Obtain source IPv4 address, source IPs are only selected for the address
+            // families of the remote host so no warmup capacity is reserved needlessly
+            let rcpt_domain = envelope.resolve_variable(V_RECIPIENT_DOMAIN).into_string();
+            if result.remote_ips.iter().any(|ip| ip.is_ipv4()) {
+                let source_ips = self
+                    .eval_if::<Vec<Ipv4Addr>, _>(
+                        &self.core.smtp.queue.source_ip.ipv4,
+                        envelope,
+                        session_id,
+                    )
+                    .await
+                    .unwrap_or_default();
+                result.source_ipv4 = self
+                    .select_source_ip(
+                        source_ips.into_iter().map(IpAddr::from).collect(),
+                        rcpt_domain.as_str(),
+                        &mut result.warmup_reservations,
+                        session_id,
+                    )
+                    .await?;
             }
 
             // Obtain source IPv6 address
-            let source_ips = self
-                .eval_if::<Vec<Ipv6Addr>, _>(
-                    &self.core.smtp.queue.source_ip.ipv6,
-                    envelope,
-                    session_id,
-                )
-                .await
-                .unwrap_or_default();
-            match source_ips.len().cmp(&1) {
-                std::cmp::Ordering::Equal => {
-                    result.source_ipv6 = IpAddr::from(*source_ips.first().unwrap()).into();
-                }
-                std::cmp::Ordering::Greater => {
-                    result.source_ipv6 =
-                        IpAddr::from(source_ips[rand::rng().random_range(0..source_ips.len())])
-                            .into();
+            if result.remote_ips.iter().any(|ip| ip.is_ipv6()) {
+                let source_ips = self
+                    .eval_if::<Vec<Ipv6Addr>, _>(
+                        &self.core.smtp.queue.source_ip.ipv6,
+                        envelope,
+                        session_id,
+                    )
+                    .await
+                    .unwrap_or_default();
+                match self
+                    .select_source_ip(
+                        source_ips.into_iter().map(IpAddr::from).collect(),
+                        rcpt_domain.as_str(),
+                        &mut result.warmup_reservations,
+                        session_id,
+                    )
+                    .await
+                {
+                    Ok(source_ip) => {
+                        result.source_ipv6 = source_ip;
+                    }
+                    Err(status) => {
+                        self.release_warmup_reservations(result.warmup_reservations)
+                            .await;
+                        return Err(status);
+                    }
                 }
-                std::cmp::Ordering::Less => (),
             }
 
             Ok(result)
@@ -8,9 +8,9 @@ use common::Server;
 use common::config::smtp::queue::RequireOptional;
 use mail_send::Credentials;
 use smtp_proto::{
-    EXT_CHUNKING, EXT_DSN, EXT_REQUIRE_TLS, EXT_SIZE, EXT_SMTP_UTF8, EhloResponse, MAIL_REQUIRETLS,
-    MAIL_RET_FULL, MAIL_RET_HDRS, MAIL_SMTPUTF8, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE,
-    RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS, Severity,
+    EXT_CHUNKING, EXT_DSN, EXT_MT_PRIORITY, EXT_REQUIRE_TLS, EXT_SIZE, EXT_SMTP_UTF8, EhloResponse,
+    MAIL_REQUIRETLS, MAIL_RET_FULL, MAIL_RET_HDRS, MAIL_SMTPUTF8, RCPT_NOTIFY_DELAY,
+    RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS, Severity,
 };
 use std::time::Duration;
 use std::{fmt::Write, time::Instant};
@@ -18,7 +18,7 @@ use tokio::io::{AsyncRead, AsyncWrite};
 use trc::DeliveryEvent;
 
 use crate::outbound::client::{from_error_status, from_mail_send_error};
-use crate::queue::{ErrorDetails, HostResponse, RCPT_STATUS_CHANGED};
+use crate::queue::{ErrorDetails, HostResponse, MAIL_TLS_NOT_REQUIRED, RCPT_STATUS_CHANGED};
 
 use crate::queue::{Error, Message, Recipient, Status};
 
@@ -71,6 +71,24 @@ impl Message {
             }
         };
 
+        // RFC 8689 - The next hop must support REQUIRETLS
+        if self.has_flag(MAIL_REQUIRETLS)
+            && params.is_smtp
+            && !capabilities.has_capability(EXT_REQUIRE_TLS)
+        {
+            trc::event!(
+                Delivery(DeliveryEvent::RequireTlsFailed),
+                SpanId = params.session_id,
+                Hostname = params.hostname.to_string(),
+                Details = "REQUIRETLS not advertised by host",
+            );
+            smtp_client.quit().await;
+            return Status::PermanentFailure(Error::RequireTls(ErrorDetails {
+                entity: params.hostname.into(),
+                details: "REQUIRETLS not advertised by host".into(),
+            }));
+        }
+
         // Authenticate
         if let Some(credentials) = params.credentials {
             let time = Instant::now();
@@ -396,6 +414,9 @@ impl Message {
         if self.has_flag(MAIL_SMTPUTF8) & capabilities.has_capability(EXT_SMTP_UTF8) {
             mail_from.push_str(" SMTPUTF8");
         }
+        if self.priority != 0 && capabilities.has_capability(EXT_MT_PRIORITY) {
+            let _ = write!(mail_from, " MT-PRIORITY={}", self.priority);
+        }
         if capabilities.has_capability(EXT_DSN) {
             if self.has_flag(MAIL_RET_FULL) {
                 mail_from.push_str(" RET=FULL");
@@ -499,4 +520,25 @@ impl TlsStrategy {
             || self.is_dane_required()
             || self.is_mta_sts_required()
     }
+
+    /// Applies the sender's REQUIRETLS or "TLS-Required: No" request (RFC 8689)
+    pub fn apply_message_flags(&mut self, flags: u64) {
+        if (flags & MAIL_REQUIRETLS) != 0 {
+            // REQUIRETLS is strictly stronger than any configured policy
+            self.tls = RequireOptional::Require;
+            if matches!(self.mta_sts, RequireOptional::Disable) {
+                self.mta_sts = RequireOptional::Optional;
+            }
+            if matches!(self.dane, RequireOptional::Disable) {
+                self.dane = RequireOptional::Optional;
+            }
+        } else if (flags & MAIL_TLS_NOT_REQUIRED) != 0 {
+            // Attempt TLS opportunistically but never fail due to policy
+            self.mta_sts = RequireOptional::Disable;
+            self.dane = RequireOptional::Disable;
+            if matches!(self.tls, RequireOptional::Require) {
+                self.tls = RequireOptional::Optional;
+            }
+        }
+    }
 }
@@ -0,0 +1,437 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, net::IpAddr};
+
+use common::{
+    KV_IP_WARMUP, KV_IP_WARMUP_COUNTER, KV_LOCK_IP_WARMUP, Server, config::smtp::queue::IpWarmup,
+};
+use rand::{Rng, seq::SliceRandom};
+use store::{
+    InMemoryStore, Serialize,
+    dispatch::lookup::KeyValue,
+    write::{AlignedBytes, Archive, Archiver, now},
+};
+use trc::{AddContext, DeliveryEvent};
+
+use crate::queue::{Error, Status};
+
+pub const WARMUP_SENT: u8 = 0;
+pub const WARMUP_BOUNCED: u8 = 1;
+pub const WARMUP_DEFERRED: u8 = 2;
+
+const DAY: u64 = 86400;
+const COUNTER_EXPIRY: u64 = 7 * DAY;
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    serde::Serialize,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum WarmupStatus {
+    #[default]
+    Active,
+    Paused,
+    Completed,
+}
+
+#[derive(
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    serde::Serialize,
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct WarmupState {
+    pub phase: u32,
+    pub status: WarmupStatus,
+    pub day: u64,
+    pub updated: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct WarmupCounters {
+    pub sent: u64,
+    pub bounced: u64,
+    pub deferred: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WarmupReservation {
+    pub ip: IpAddr,
+    key: Vec<u8>,
+}
+
+pub trait IpWarmupManager: Sync + Send {
+    fn warmup_state(
+        &self,
+        config: &IpWarmup,
+    ) -> impl Future<Output = trc::Result<WarmupState>> + Send;
+
+    fn warmup_set_state(
+        &self,
+        config: &IpWarmup,
+        state: &WarmupState,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn warmup_counters(
+        &self,
+        ip: IpAddr,
+        day: u64,
+        destination: &str,
+    ) -> impl Future<Output = trc::Result<WarmupCounters>> + Send;
+
+    fn select_source_ip(
+        &self,
+        candidates: Vec<IpAddr>,
+        rcpt_domain: &str,
+        reservations: &mut Vec<WarmupReservation>,
+        session_id: u64,
+    ) -> impl Future<Output = Result<Option<IpAddr>, Status<(), Error>>> + Send;
+
+    fn release_warmup_reservations(
+        &self,
+        reservations: Vec<WarmupReservation>,
+    ) -> impl Future<Output = ()> + Send;
+
+    fn record_warmup_outcome(
+        &self,
+        ip: IpAddr,
+        outcome: WarmupCounters,
+    ) -> impl Future<Output = ()> + Send;
+
+    fn warmup_store(&self) -> InMemoryStore;
+}
+
+impl IpWarmupManager for Server {
+    async fn warmup_state(&self, config: &IpWarmup) -> trc::Result<WarmupState> {
+        let today = now() / DAY;
+        let state = self
+            .warmup_store()
+            .key_get::<Archive<AlignedBytes>>(KeyValue::<()>::build_key(
+                KV_IP_WARMUP,
+                config.ip.to_string(),
+            ))
+            .await
+            .caused_by(trc::location!())?
+            .map(|state| state.deserialize::<WarmupState>())
+            .transpose()
+            .caused_by(trc::location!())?;
+
+        match state {
+            Some(state) if state.day >= today => Ok(state),
+            Some(mut state) => {
+                // Evaluate the previous day only once across all nodes
+                if !self
+                    .in_memory_store()
+                    .try_lock(KV_LOCK_IP_WARMUP, config.ip.to_string().as_bytes(), 60)
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    return Ok(state);
+                }
+
+                let totals = self.warmup_counters(config.ip, state.day, "").await?;
+                if let Some(event) = state.advance(config, &totals) {
+                    trc::event!(
+                        Delivery(event),
+                        Id = config.id.clone(),
+                        LocalIp = config.ip,
+                        Total = state.phase,
+                        Limit = config.daily_limit(state.phase),
+                        Details = vec![
+                            trc::Value::from(totals.sent),
+                            trc::Value::from(totals.bounced),
+                            trc::Value::from(totals.deferred),
+                        ],
+                    );
+                }
+                state.day = today;
+                self.warmup_set_state(config, &state).await?;
+
+                Ok(state)
+            }
+            None => {
+                let state = WarmupState {
+                    day: today,
+                    ..Default::default()
+                };
+                self.warmup_set_state(config, &state).await?;
+
+                Ok(state)
+            }
+        }
+    }
+
+    async fn warmup_set_state(&self, config: &IpWarmup, state: &WarmupState) -> trc::Result<()> {
+        let mut state = state.clone();
+        state.updated = now();
+
+        self.warmup_store()
+            .key_set(KeyValue::with_prefix(
+                KV_IP_WARMUP,
+                config.ip.to_string(),
+                Archiver::new(state)
+                    .serialize()
+                    .caused_by(trc::location!())?,
+            ))
+            .await
+    }
+
+    async fn warmup_counters(
+        &self,
+        ip: IpAddr,
+        day: u64,
+        destination: &str,
+    ) -> trc::Result<WarmupCounters> {
+        let store = self.warmup_store();
+        Ok(WarmupCounters {
+            sent: store
+                .counter_get(counter_key(ip, day, WARMUP_SENT, destination))
+                .await?
+                .max(0) as u64,
+            bounced: store
+                .counter_get(counter_key(ip, day, WARMUP_BOUNCED, destination))
+                .await?
+                .max(0) as u64,
+            deferred: store
+                .counter_get(counter_key(ip, day, WARMUP_DEFERRED, destination))
+                .await?
+                .max(0) as u64,
+        })
+    }
+
+    async fn select_source_ip(
+        &self,
+        mut candidates: Vec<IpAddr>,
+        rcpt_domain: &str,
+        reservations: &mut Vec<WarmupReservation>,
+        session_id: u64,
+    ) -> Result<Option<IpAddr>, Status<(), Error>> {
+        let warmup = &self.core.smtp.queue.warmup;
+        if warmup.is_empty() || !candidates.iter().any(|ip| warmup.contains_key(ip)) {
+            return Ok(match candidates.len() {
+                0 => None,
+                1 => candidates.pop(),
+                len => candidates.get(rand::rng().random_range(0..len)).copied(),
+            });
+        }
+
+        // Split candidates into established and warming IPs
+        let mut established = Vec::with_capacity(candidates.len());
+        let mut warming = Vec::with_capacity(candidates.len());
+        for ip in candidates {
+            if let Some(config) = warmup.get(&ip) {
+                match self.warmup_state(config).await {
+                    Ok(state) if state.status == WarmupStatus::Completed => {
+                        established.push(ip);
+                    }
+                    Ok(state) => {
+                        warming.push((config, state));
+                    }
+                    Err(err) => {
+                        trc::error!(
+                            err.span_id(session_id)
+                                .details("Failed to obtain IP warmup state")
+                        );
+                    }
+                }
+            } else {
+                established.push(ip);
+            }
+        }
+
+        // Warming IPs take precedence until their daily limit is reached. Capacity is
+        // reserved on selection and given back unless the message is delivered.
+        let today = now() / DAY;
+        let store = self.warmup_store();
+        warming.shuffle(&mut rand::rng());
+        for (config, state) in &warming {
+            let limit = config.daily_limit(state.phase);
+            let key = counter_key(
+                config.ip,
+                today,
+                WARMUP_SENT,
+                config.destination(rcpt_domain),
+            );
+            match store
+                .counter_incr(KeyValue::new(key.clone(), 1).expires(COUNTER_EXPIRY), true)
+                .await
+            {
+                Ok(reserved) if (reserved.max(0) as u64) <= limit => {
+                    reservations.push(WarmupReservation { ip: config.ip, key });
+                    return Ok(Some(config.ip));
+                }
+                Ok(_) => {
+                    if let Err(err) = store.counter_incr(KeyValue::new(key, -1), false).await {
+                        trc::error!(
+                            err.span_id(session_id)
+                                .details("Failed to update IP warmup counter")
+                        );
+                    }
+                }
+                Err(err) => {
+                    trc::error!(
+                        err.span_id(session_id)
+                            .details("Failed to update IP warmup counter")
+                    );
+                }
+            }
+        }
+
+        if !warming.is_empty() {
+            trc::event!(
+                Delivery(DeliveryEvent::WarmupLimitExceeded),
+                SpanId = session_id,
+                Domain = rcpt_domain.to_string(),
+                LocalIp = warming
+                    .iter()
+                    .map(|(config, _)| trc::Value::from(config.ip))
+                    .collect::<Vec<_>>(),
+                Total = established.len(),
+            );
+        }
+
+        // Overflow to established IPs in the same pool
+        match established.len() {
+            0 => Err(Status::TemporaryFailure(Error::RateLimited)),
+            1 => Ok(established.pop()),
+            len => Ok(established.get(rand::rng().random_range(0..len)).copied()),
+        }
+    }
+
+    async fn release_warmup_reservations(&self, reservations: Vec<WarmupReservation>) {
+        let store = self.warmup_store();
+        for reservation in reservations {
+            if let Err(err) = store
+                .counter_incr(KeyValue::new(reservation.key, -1), false)
+                .await
+            {
+                trc::error!(
+                    err.caused_by(trc::location!())
+                        .details("Failed to release IP warmup reservation")
+                );
+            }
+        }
+    }
+
+    async fn record_warmup_outcome(&self, ip: IpAddr, outcome: WarmupCounters) {
+        if !self.core.smtp.queue.warmup.contains_key(&ip) {
+            return;
+        }
+
+        // Daily totals drive the ramp, delivered messages already count towards the
+        // limit of their destination through the reservation made on selection
+        let today = now() / DAY;
+        let store = self.warmup_store();
+        for (kind, value) in [
+            (WARMUP_SENT, outcome.sent),
+            (WARMUP_BOUNCED, outcome.bounced),
+            (WARMUP_DEFERRED, outcome.deferred),
+        ] {
+            if value > 0 {
+                if let Err(err) = store
+                    .counter_incr(
+                        KeyValue::new(counter_key(ip, today, kind, ""), value as i64)
+                            .expires(COUNTER_EXPIRY),
+                        false,
+                    )
+                    .await
+                {
+                    trc::error!(
+                        err.caused_by(trc::location!())
+                            .details("Failed to update IP warmup counter")
+                    );
+                }
+            }
+        }
+    }
+
+    // Warm-up progress is kept in the data store so it survives restarts
+    fn warmup_store(&self) -> InMemoryStore {
+        InMemoryStore::Store(self.store().clone())
+    }
+}
+
+impl WarmupState {
+    /// Moves the ramp forward based on the previous day's delivery outcomes.
+    pub fn advance(&mut self, config: &IpWarmup, totals: &WarmupCounters) -> Option<DeliveryEvent> {
+        if self.status == WarmupStatus::Completed {
+            return None;
+        }
+
+        // A day without delivery attempts says nothing about the IP's reputation
+        let is_healthy = match (totals.bounce_rate(), totals.deferral_rate()) {
+            (Some(bounce_rate), Some(deferral_rate)) => {
+                bounce_rate <= config.max_bounce_rate && deferral_rate <= config.max_deferral_rate
+            }
+            _ => return None,
+        };
+
+        match self.status {
+            WarmupStatus::Paused if is_healthy => {
+                self.status = WarmupStatus::Active;
+                Some(DeliveryEvent::WarmupResumed)
+            }
+            WarmupStatus::Active if !is_healthy => {
+                self.status = WarmupStatus::Paused;
+                Some(DeliveryEvent::WarmupPaused)
+            }
+            WarmupStatus::Active if totals.sent >= config.min_volume => {
+                if (self.phase as usize + 1) < config.schedule.len() {
+                    self.phase += 1;
+                    Some(DeliveryEvent::WarmupPhaseChanged)
+                } else {
+                    self.status = WarmupStatus::Completed;
+                    Some(DeliveryEvent::WarmupCompleted)
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl WarmupCounters {
+    pub fn attempts(&self) -> u64 {
+        self.sent + self.bounced + self.deferred
+    }
+
+    pub fn bounce_rate(&self) -> Option<f64> {
+        let attempts = self.attempts();
+        (attempts > 0).then(|| self.bounced as f64 / attempts as f64)
+    }
+
+    pub fn deferral_rate(&self) -> Option<f64> {
+        let attempts = self.attempts();
+        (attempts > 0).then(|| self.deferred as f64 / attempts as f64)
+    }
+}
+
+pub fn counter_key(ip: IpAddr, day: u64, kind: u8, destination: &str) -> Vec<u8> {
+    let mut key = Vec::with_capacity(16 + 8 + 2 + destination.len());
+    key.push(KV_IP_WARMUP_COUNTER);
+    match ip {
+        IpAddr::V4(ip) => key.extend_from_slice(&ip.octets()),
+        IpAddr::V6(ip) => key.extend_from_slice(&ip.octets()),
+    }
+    key.extend_from_slice(&day.to_be_bytes());
+    key.push(kind);
+    key.extend_from_slice(destination.as_bytes());
+    key
+}
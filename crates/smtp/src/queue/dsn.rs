@@ -25,7 +25,7 @@ use crate::reporting::SmtpReporting;
 use super::spool::SmtpSpool;
 use super::{
     Domain, Error, ErrorDetails, HostResponse, Message, MessageSource, QueueEnvelope,
-    RCPT_DSN_SENT, RCPT_STATUS_CHANGED, Recipient, Status,
+    RCPT_DSN_SENT, RCPT_QUARANTINED, RCPT_STATUS_CHANGED, Recipient, Status,
 };
 
 pub trait SendDsn: Sync + Send {
@@ -171,7 +171,8 @@ impl Message {
         let mut dsn = String::new();
 
         for rcpt in &mut self.recipients {
-            if rcpt.has_flag(RCPT_DSN_SENT | RCPT_NOTIFY_NEVER) {
+            // Quarantined messages are never reported back to the sender
+            if rcpt.has_flag(RCPT_DSN_SENT | RCPT_NOTIFY_NEVER | RCPT_QUARANTINED) {
                 continue;
             }
             let domain = &self.domains[rcpt.domain_idx as usize];
@@ -569,6 +570,13 @@ impl Error {
             Error::Io(err) => {
                 let _ = write!(dsn, "<{addr}> (queue error: {err})\r\n");
             }
+            Error::RequireTls(details) => {
+                let _ = write!(
+                    dsn,
+                    "<{}> (REQUIRETLS failed for '{}': {})\r\n",
+                    addr, details.entity, details.details
+                );
+            }
         }
     }
 }
@@ -576,6 +584,7 @@ impl Error {
 impl Message {
     fn write_dsn_headers(&self, dsn: &mut String, reporting_mta: &str) {
         let _ = write!(dsn, "Reporting-MTA: dns;{reporting_mta}\r\n");
+        let _ = write!(dsn, "X-Queue-ID: {:x}\r\n", self.queue_id);
         dsn.push_str("Arrival-Date: ");
         dsn.push_str(&DateTime::from_timestamp(self.created as i64).to_rfc822());
         dsn.push_str("\r\n");
@@ -701,6 +710,12 @@ impl Status<(), Error> {
             dsn.push_str("Status: ");
             if let Error::UnexpectedResponse(response) = err {
                 response.response.write_dsn_status(dsn);
+            } else if let Error::RequireTls(_) = err {
+                dsn.push_str(if matches!(self, Status::PermanentFailure(_)) {
+                    "5.7.30"
+                } else {
+                    "4.7.30"
+                });
             } else {
                 dsn.push_str(if matches!(self, Status::PermanentFailure(_)) {
                     "5.0.0"
@@ -720,7 +735,8 @@ impl Status<(), Error> {
                 })
                 | Error::ConnectionError(details)
                 | Error::TlsError(details)
-                | Error::DaneError(details) => {
+                | Error::DaneError(details)
+                | Error::RequireTls(details) => {
                     dsn.push_str("Remote-MTA: dns;");
                     dsn.push_str(&details.entity);
                     dsn.push_str("\r\n");
@@ -5,6 +5,7 @@
  */
 
 use std::{
+    cmp::Reverse,
     sync::{Arc, atomic::Ordering},
     time::{Duration, Instant},
 };
@@ -21,7 +22,7 @@ use store::write::now;
 use tokio::sync::mpsc;
 
 use super::{
-    Message, QueueId, Status,
+    Message, QueueId, QueuedMessage, Status,
     spool::{QUEUE_REFRESH, SmtpSpool},
 };
 
@@ -164,6 +165,10 @@ impl Queue {
                         queue_events.shuffle(&mut rand::rng());
                     }
 
+                    // Deliver higher priority messages first
+                    let aging = server.core.smtp.queue.priority_aging.as_secs();
+                    queue_events.sort_by_key(|event| Reverse(event.effective_priority(now, aging)));
+
                     for queue_event in &queue_events {
                         if queue_event.due <= now {
                             // Enforce global concurrency limits
@@ -268,6 +273,14 @@ impl Queue {
     }
 }
 
+impl QueuedMessage {
+    // Messages gain one priority level for each aging interval they have been
+    // waiting past their due time, which prevents low priority mail from starving.
+    pub fn effective_priority(&self, now: u64, aging: u64) -> i64 {
+        self.priority as i64 + (now.saturating_sub(self.due) / aging.max(1)) as i64
+    }
+}
+
 impl Message {
     pub fn next_event(&self) -> Option<u64> {
         let mut next_event = now();
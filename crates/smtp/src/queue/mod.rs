@@ -19,6 +19,7 @@ use utils::BlobHash;
 
 pub mod dsn;
 pub mod manager;
+pub mod quarantine;
 pub mod quota;
 pub mod spool;
 pub mod throttle;
@@ -35,6 +36,7 @@ pub struct Schedule<T> {
 pub struct QueuedMessage {
     pub due: u64,
     pub queue_id: u64,
+    pub priority: i16,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -123,6 +125,13 @@ pub struct Recipient {
 
 pub const RCPT_DSN_SENT: u64 = 1 << 32;
 pub const RCPT_STATUS_CHANGED: u64 = 2 << 32;
+pub const RCPT_ENCRYPTION_FAILED: u64 = 4 << 32;
+pub const RCPT_QUARANTINED: u64 = 8 << 32;
+pub const RCPT_QUARANTINE_RELEASED: u64 = 16 << 32;
+
+pub const MAIL_TLS_NOT_REQUIRED: u64 = 1 << 32;
+pub const MAIL_SKIP_ENCRYPTION: u64 = 2 << 32;
+pub const MAIL_QUARANTINED: u64 = 4 << 32;
 
 #[derive(
     Debug,
@@ -181,6 +190,7 @@ pub enum Error {
     RateLimited,
     ConcurrencyLimited,
     Io(String),
+    RequireTls(ErrorDetails),
 }
 
 #[derive(
@@ -332,6 +342,7 @@ impl<'x> ResolveVariable for QueueEnvelope<'x> {
                         Error::RateLimited => "rate",
                         Error::ConcurrencyLimited => "concurrency",
                         Error::Io(_) => "io",
+                        Error::RequireTls(_) => "requiretls",
                     },
                 })
                 .unwrap_or_default()
@@ -399,6 +410,20 @@ pub fn instant_to_timestamp(now: Instant, time: Instant) -> u64 {
         + time.checked_duration_since(now).map_or(0, |d| d.as_secs())
 }
 
+/// Parses a queue id in the decimal form used by the API or in the hexadecimal form shown
+/// in SMTP responses, Received headers and DSNs. Ids made only of decimal digits are
+/// ambiguous and read as decimal unless they carry a `0x` prefix.
+pub fn parse_queue_id(id: &str) -> Option<QueueId> {
+    let id = id.trim().trim_start_matches('<').trim_end_matches('>');
+    if let Some(id) = id.strip_prefix("0x").or_else(|| id.strip_prefix("0X")) {
+        QueueId::from_str_radix(id, 16).ok()
+    } else if id.contains(|ch| matches!(ch, 'a'..='f' | 'A'..='F')) {
+        QueueId::from_str_radix(id, 16).ok()
+    } else {
+        id.parse().ok()
+    }
+}
+
 pub trait InstantFromTimestamp {
     fn to_instant(&self) -> Instant;
 }
@@ -477,6 +502,13 @@ impl Display for Error {
             Error::Io(err) => {
                 write!(f, "Queue error: {err}")
             }
+            Error::RequireTls(details) => {
+                write!(
+                    f,
+                    "REQUIRETLS failed for '{}': {}",
+                    details.entity, details.details
+                )
+            }
         }
     }
 }
@@ -528,6 +560,13 @@ impl Display for ArchivedError {
             ArchivedError::Io(err) => {
                 write!(f, "Queue error: {err}")
             }
+            ArchivedError::RequireTls(details) => {
+                write!(
+                    f,
+                    "REQUIRETLS failed for '{}': {}",
+                    details.entity, details.details
+                )
+            }
         }
     }
 }
@@ -0,0 +1,260 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{fmt::Write, future::Future};
+
+use common::{KV_QUARANTINE_RELEASE, Server, ipc::QueueEvent};
+use rand::Rng;
+use store::{blake3, write::now};
+
+use super::{
+    Message, QueueId, RCPT_QUARANTINE_RELEASED, RCPT_QUARANTINED, RCPT_STATUS_CHANGED, Status,
+    spool::SmtpSpool,
+};
+
+const TOKEN_LEN: usize = 3 * std::mem::size_of::<u64>() + blake3::OUT_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReleaseToken {
+    pub queue_id: QueueId,
+    pub expires: u64,
+    pub nonce: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedMessage {
+    pub queue_id: QueueId,
+    pub token_id: String,
+    pub from: String,
+    pub rcpt: String,
+    pub expires: u64,
+}
+
+pub trait QuarantineRelease: Sync + Send {
+    fn quarantine_release_url(&self, queue_id: QueueId, rcpt: &str) -> (String, u64);
+
+    fn quarantined_message(
+        &self,
+        token: &str,
+    ) -> impl Future<Output = trc::Result<QuarantinedMessage>> + Send;
+
+    fn release_quarantined_message(
+        &self,
+        token: &str,
+        session_id: u64,
+    ) -> impl Future<Output = trc::Result<QuarantinedMessage>> + Send;
+}
+
+impl QuarantineRelease for Server {
+    // Returns the link included in the quarantine notice along with its expiration
+    fn quarantine_release_url(&self, queue_id: QueueId, rcpt: &str) -> (String, u64) {
+        let config = &self.core.smtp.queue.quarantine;
+        let token = ReleaseToken {
+            queue_id,
+            expires: now() + config.release_expiry.as_secs(),
+            nonce: rand::rng().random(),
+        };
+        let base_url = config
+            .release_url
+            .clone()
+            .unwrap_or_else(|| format!("https://{}", self.core.network.server_name));
+
+        (
+            format!(
+                "{base_url}/quarantine/release/{}",
+                token.encode(&token.signature(self, rcpt))
+            ),
+            token.expires,
+        )
+    }
+
+    async fn quarantined_message(&self, token: &str) -> trc::Result<QuarantinedMessage> {
+        find_quarantined(self, token)
+            .await
+            .map(|(token, message, rcpt_idx)| QuarantinedMessage::new(&token, &message, rcpt_idx))
+    }
+
+    async fn release_quarantined_message(
+        &self,
+        token: &str,
+        session_id: u64,
+    ) -> trc::Result<QuarantinedMessage> {
+        let queue_id = ReleaseToken::parse(token)
+            .map(|(token, _)| token.queue_id)
+            .ok_or_else(invalid_token)?;
+
+        // Delivery attempts must not overwrite the release
+        if !self.try_lock_event(queue_id).await {
+            return Err(trc::QueueEvent::Locked
+                .into_err()
+                .details("The message is being processed, please try again later"));
+        }
+        let result = release(self, token, session_id).await;
+        self.unlock_event(queue_id).await;
+
+        if result.is_ok() {
+            let _ = self.inner.ipc.queue_tx.send(QueueEvent::Refresh).await;
+        }
+
+        result
+    }
+}
+
+async fn release(server: &Server, token: &str, session_id: u64) -> trc::Result<QuarantinedMessage> {
+    let (token, mut message, rcpt_idx) = find_quarantined(server, token).await?;
+
+    // Tokens are single-use, the marker outlives the token so replays are always rejected
+    if !server
+        .in_memory_store()
+        .try_lock(
+            KV_QUARANTINE_RELEASE,
+            &token.nonce.to_be_bytes(),
+            token.expires.saturating_sub(now()) + 60,
+        )
+        .await?
+    {
+        return Err(trc::SecurityEvent::Unauthorized
+            .into_err()
+            .details("The release token has already been used")
+            .id(token.id()));
+    }
+
+    let result = QuarantinedMessage::new(&token, &message, rcpt_idx);
+    let prev_event = message.next_event().unwrap_or_default();
+    let rcpt = &mut message.recipients[rcpt_idx];
+    rcpt.flags = (rcpt.flags & !RCPT_QUARANTINED) | RCPT_QUARANTINE_RELEASED | RCPT_STATUS_CHANGED;
+    rcpt.status = Status::Scheduled;
+    let domain = &mut message.domains[rcpt.domain_idx as usize];
+    domain.retry.due = now();
+    if matches!(domain.status, Status::Completed(_)) {
+        domain.status = Status::Scheduled;
+    }
+    let next_event = message.next_event().unwrap_or_default();
+    message
+        .save_changes(server, prev_event.into(), next_event.into())
+        .await;
+
+    // Releases are recorded with the token id as there is no authenticated principal
+    trc::event!(
+        Queue(trc::QueueEvent::QuarantineReleased),
+        SpanId = session_id,
+        QueueId = result.queue_id,
+        Id = result.token_id.clone(),
+        From = result.from.clone(),
+        To = result.rcpt.clone(),
+    );
+
+    Ok(result)
+}
+
+async fn find_quarantined(
+    server: &Server,
+    token: &str,
+) -> trc::Result<(ReleaseToken, Message, usize)> {
+    let (token, signature) = ReleaseToken::parse(token).ok_or_else(invalid_token)?;
+    if token.expires <= now() {
+        return Err(trc::AuthEvent::TokenExpired
+            .into_err()
+            .details("The release link has expired")
+            .id(token.id()));
+    }
+
+    // The signature binds the token to a single held recipient of the message
+    let message = server
+        .read_message(token.queue_id)
+        .await
+        .ok_or_else(not_held)?;
+    let rcpt_idx = message
+        .recipients
+        .iter()
+        .position(|rcpt| {
+            (rcpt.flags & RCPT_QUARANTINED) != 0
+                && token.signature(server, &rcpt.address_lcase) == signature
+        })
+        .ok_or_else(not_held)?;
+
+    Ok((token, message, rcpt_idx))
+}
+
+impl ReleaseToken {
+    pub fn id(&self) -> String {
+        format!("{:016x}", self.nonce)
+    }
+
+    pub fn signature(&self, server: &Server, rcpt: &str) -> blake3::Hash {
+        let key = blake3::derive_key(
+            "stalwart quarantine release token",
+            server.core.oauth.oauth_key.as_bytes(),
+        );
+        let mut hasher = blake3::Hasher::new_keyed(&key);
+        hasher.update(&self.queue_id.to_be_bytes());
+        hasher.update(&self.expires.to_be_bytes());
+        hasher.update(&self.nonce.to_be_bytes());
+        hasher.update(rcpt.as_bytes());
+        hasher.finalize()
+    }
+
+    pub fn encode(&self, signature: &blake3::Hash) -> String {
+        let mut token = String::with_capacity(TOKEN_LEN * 2);
+        for byte in self
+            .queue_id
+            .to_be_bytes()
+            .into_iter()
+            .chain(self.expires.to_be_bytes())
+            .chain(self.nonce.to_be_bytes())
+            .chain(*signature.as_bytes())
+        {
+            let _ = write!(&mut token, "{byte:02x}");
+        }
+        token
+    }
+
+    pub fn parse(token: &str) -> Option<(Self, blake3::Hash)> {
+        if token.len() != TOKEN_LEN * 2 || !token.is_ascii() {
+            return None;
+        }
+        let mut bytes = [0u8; TOKEN_LEN];
+        for (byte, hex) in bytes.iter_mut().zip(token.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?;
+        }
+        let (fields, signature) = bytes.split_at(TOKEN_LEN - blake3::OUT_LEN);
+        let field =
+            |idx: usize| u64::from_be_bytes(fields[idx * 8..(idx + 1) * 8].try_into().unwrap());
+
+        Some((
+            ReleaseToken {
+                queue_id: field(0),
+                expires: field(1),
+                nonce: field(2),
+            },
+            blake3::Hash::from_bytes(signature.try_into().ok()?),
+        ))
+    }
+}
+
+impl QuarantinedMessage {
+    fn new(token: &ReleaseToken, message: &Message, rcpt_idx: usize) -> Self {
+        QuarantinedMessage {
+            queue_id: message.queue_id,
+            token_id: token.id(),
+            from: message.return_path.clone(),
+            rcpt: message.recipients[rcpt_idx].address.clone(),
+            expires: token.expires,
+        }
+    }
+}
+
+fn invalid_token() -> trc::Error {
+    trc::ResourceEvent::BadParameters
+        .into_err()
+        .details("Invalid release token")
+}
+
+fn not_held() -> trc::Error {
+    trc::ResourceEvent::NotFound
+        .into_err()
+        .details("The message is no longer held in quarantine")
+}
@@ -15,7 +15,7 @@ use store::write::key::DeserializeBigEndian;
 use store::write::{
     AlignedBytes, Archive, Archiver, BatchBuilder, BlobOp, QueueClass, ValueClass, now,
 };
-use store::{IterateParams, Serialize, SerializeInfallible, U64_LEN, ValueKey};
+use store::{Deserialize, IterateParams, Serialize, SerializeInfallible, U64_LEN, ValueKey};
 use trc::ServerEvent;
 use utils::BlobHash;
 
@@ -99,12 +99,17 @@ impl SmtpSpool for Server {
         let result = self
             .store()
             .iterate(
-                IterateParams::new(from_key, to_key).ascending().no_values(),
-                |key, _| {
+                IterateParams::new(from_key, to_key).ascending(),
+                |key, value| {
                     let due = key.deserialize_be_u64(0)?;
                     let queue_id = key.deserialize_be_u64(U64_LEN)?;
+                    let priority = i64::deserialize(value).unwrap_or_default() as i16;
 
-                    events.push(QueuedMessage { due, queue_id });
+                    events.push(QueuedMessage {
+                        due,
+                        queue_id,
+                        priority,
+                    });
 
                     Ok(due <= now)
                 },
@@ -293,7 +298,7 @@ impl Message {
                     due: self.next_event().unwrap_or_default(),
                     queue_id: self.queue_id,
                 })),
-                0u64.serialize(),
+                (self.priority as i64).serialize(),
             )
             .clear(BlobOp::Reserve {
                 hash: self.blob_hash.clone(),
@@ -439,7 +444,7 @@ impl Message {
                         due: next_event,
                         queue_id: self.queue_id,
                     })),
-                    0u64.serialize(),
+                    (self.priority as i64).serialize(),
                 );
         }
 
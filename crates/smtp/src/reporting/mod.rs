@@ -33,6 +33,7 @@ pub mod dkim;
 pub mod dmarc;
 pub mod scheduler;
 pub mod spf;
+pub mod system;
 pub mod tls;
 
 impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
@@ -91,6 +92,7 @@ pub trait SmtpReporting: Sync + Send {
         rcpts: impl Iterator<Item = impl Into<String> + Sync + Send> + Sync + Send,
         raw_message: Vec<u8>,
         sign_config: Option<&IfBlock>,
+        flags: u64,
         parent_session_id: u64,
     ) -> impl Future<Output = ()> + Send;
 
@@ -166,6 +168,7 @@ impl SmtpReporting for Server {
         rcpts: impl Iterator<Item = impl Into<String> + Sync + Send> + Sync + Send,
         raw_message: Vec<u8>,
         sign_config: Option<&IfBlock>,
+        flags: u64,
         parent_session_id: u64,
     ) {
         // Build message
@@ -178,6 +181,7 @@ impl SmtpReporting for Server {
             from_addr_domain,
             parent_session_id,
         );
+        message.flags |= flags;
         for rcpt in rcpts {
             message.add_recipient(rcpt, self).await;
         }
@@ -0,0 +1,279 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{KV_RATE_LIMIT_SYSTEM_MESSAGE, Server};
+use directory::Permission;
+use mail_builder::{
+    MessageBuilder,
+    headers::{
+        HeaderType,
+        address::{Address, EmailAddress},
+    },
+};
+use trc::{AddContext, OutgoingReportEvent};
+
+use crate::queue::{DomainPart, RecipientDomain};
+
+use super::SmtpReporting;
+
+pub struct SystemMessage<'x> {
+    pub template: &'x str,
+    pub from_name: Option<&'x str>,
+    pub from_addr: Option<&'x str>,
+    pub language: Option<&'x str>,
+    pub variables: &'x [(&'x str, &'x str)],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RenderedSystemMessage {
+    #[serde(rename = "fromName")]
+    pub from_name: Option<String>,
+    #[serde(rename = "fromAddress")]
+    pub from_addr: String,
+    pub subject: String,
+    pub body: String,
+}
+
+pub trait SystemMessages: Sync + Send {
+    fn render_system_message(
+        &self,
+        message: &SystemMessage<'_>,
+        domain: &str,
+        session_id: u64,
+    ) -> impl Future<Output = trc::Result<Option<RenderedSystemMessage>>> + Send;
+
+    fn send_system_message(
+        &self,
+        message: &SystemMessage<'_>,
+        rcpts: impl Iterator<Item = impl AsRef<str> + Sync + Send> + Sync + Send,
+        session_id: u64,
+    ) -> impl Future<Output = ()> + Send;
+
+    fn send_system_message_with_flags(
+        &self,
+        message: &SystemMessage<'_>,
+        rcpts: impl Iterator<Item = impl AsRef<str> + Sync + Send> + Sync + Send,
+        flags: u64,
+        session_id: u64,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+impl SystemMessages for Server {
+    async fn render_system_message(
+        &self,
+        message: &SystemMessage<'_>,
+        domain: &str,
+        session_id: u64,
+    ) -> trc::Result<Option<RenderedSystemMessage>> {
+        // Messages to remote domains are sent from the server's default domain
+        let domain = domain.to_lowercase();
+        let domain = if self
+            .core
+            .storage
+            .directory
+            .is_local_domain(&domain)
+            .await
+            .caused_by(trc::location!())?
+        {
+            domain
+        } else {
+            self.core.network.report_domain.clone()
+        };
+
+        let config = &self.core.smtp.system;
+        let Some(template) = config.template(message.template, &domain, message.language) else {
+            return Ok(None);
+        };
+
+        let from_addr = if let Some(from_addr) = message.from_addr {
+            from_addr.to_string()
+        } else {
+            self.eval_if::<String, _>(
+                &config.sender_address,
+                &RecipientDomain::new(&domain),
+                session_id,
+            )
+            .await
+            .unwrap_or_else(|| format!("no-reply@{domain}"))
+        };
+        let from_name = if let Some(from_name) = message.from_name {
+            Some(from_name.to_string())
+        } else {
+            self.eval_if::<String, _>(
+                &config.sender_name,
+                &RecipientDomain::new(&domain),
+                session_id,
+            )
+            .await
+        };
+
+        let mut variables = Vec::with_capacity(message.variables.len() + 1);
+        variables.push(("domain", domain.as_str()));
+        variables.extend_from_slice(message.variables);
+        let (subject, body) = template.render(&variables);
+
+        Ok(Some(RenderedSystemMessage {
+            from_name,
+            from_addr,
+            subject,
+            body,
+        }))
+    }
+
+    async fn send_system_message(
+        &self,
+        message: &SystemMessage<'_>,
+        rcpts: impl Iterator<Item = impl AsRef<str> + Sync + Send> + Sync + Send,
+        session_id: u64,
+    ) {
+        self.send_system_message_with_flags(message, rcpts, 0, session_id)
+            .await
+    }
+
+    async fn send_system_message_with_flags(
+        &self,
+        message: &SystemMessage<'_>,
+        rcpts: impl Iterator<Item = impl AsRef<str> + Sync + Send> + Sync + Send,
+        flags: u64,
+        session_id: u64,
+    ) {
+        for rcpt in rcpts {
+            let rcpt = rcpt.as_ref().trim().to_lowercase();
+
+            // Never notify accounts that are disabled or cannot receive mail
+            match self
+                .email_to_id(&self.core.storage.directory, &rcpt, session_id)
+                .await
+            {
+                Ok(Some(account_id)) => match self.get_access_token(account_id).await {
+                    Ok(access_token) => {
+                        if !access_token.has_permission(Permission::Authenticate)
+                            || !access_token.has_permission(Permission::EmailReceive)
+                        {
+                            trc::event!(
+                                OutgoingReport(OutgoingReportEvent::SystemMessageSuppressed),
+                                SpanId = session_id,
+                                Id = message.template.to_string(),
+                                To = rcpt,
+                                AccountId = account_id,
+                            );
+                            continue;
+                        }
+                    }
+                    Err(err) => {
+                        trc::error!(
+                            err.span_id(session_id)
+                                .caused_by(trc::location!())
+                                .details("Failed to obtain access token")
+                        );
+                        continue;
+                    }
+                },
+                Ok(None) => (),
+                Err(err) => {
+                    trc::error!(
+                        err.span_id(session_id)
+                            .caused_by(trc::location!())
+                            .details("Failed to lookup recipient")
+                    );
+                    continue;
+                }
+            }
+
+            // Throttle notifications per recipient
+            if let Some(rate) = &self.core.smtp.system.rate {
+                match self
+                    .in_memory_store()
+                    .is_rate_allowed(KV_RATE_LIMIT_SYSTEM_MESSAGE, rcpt.as_bytes(), rate, false)
+                    .await
+                {
+                    Ok(None) => (),
+                    Ok(Some(_)) => {
+                        trc::event!(
+                            OutgoingReport(OutgoingReportEvent::SystemMessageRateLimited),
+                            SpanId = session_id,
+                            Id = message.template.to_string(),
+                            To = rcpt,
+                            Limit = vec![
+                                trc::Value::from(rate.requests),
+                                trc::Value::from(rate.period)
+                            ],
+                        );
+                        continue;
+                    }
+                    Err(err) => {
+                        trc::error!(
+                            err.span_id(session_id)
+                                .caused_by(trc::location!())
+                                .details("Failed to check system message rate limit")
+                        );
+                        continue;
+                    }
+                }
+            }
+
+            let rendered = match self
+                .render_system_message(message, rcpt.domain_part(), session_id)
+                .await
+            {
+                Ok(Some(rendered)) => rendered,
+                Ok(None) => {
+                    trc::event!(
+                        OutgoingReport(OutgoingReportEvent::NotFound),
+                        SpanId = session_id,
+                        Id = message.template.to_string(),
+                        Details = "System message template not found",
+                    );
+                    return;
+                }
+                Err(err) => {
+                    trc::error!(
+                        err.span_id(session_id)
+                            .caused_by(trc::location!())
+                            .details("Failed to render system message")
+                    );
+                    continue;
+                }
+            };
+
+            let raw_message = MessageBuilder::new()
+                .from(Address::Address(EmailAddress {
+                    name: rendered.from_name.as_deref().map(Into::into),
+                    email: rendered.from_addr.as_str().into(),
+                }))
+                .to(Address::Address(EmailAddress {
+                    name: None,
+                    email: rcpt.as_str().into(),
+                }))
+                .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+                .subject(rendered.subject)
+                .text_body(rendered.body)
+                .write_to_vec()
+                .unwrap_or_default();
+
+            trc::event!(
+                OutgoingReport(OutgoingReportEvent::SystemMessage),
+                SpanId = session_id,
+                Id = message.template.to_string(),
+                From = rendered.from_addr.clone(),
+                To = rcpt.clone(),
+            );
+
+            // Local recipients are delivered through the regular ingest path
+            self.send_autogenerated(
+                rendered.from_addr,
+                [rcpt].into_iter(),
+                raw_message,
+                Some(&self.core.smtp.system.sign),
+                flags,
+                session_id,
+            )
+            .await;
+        }
+    }
+}
@@ -7,6 +7,7 @@
 use std::net::IpAddr;
 
 use compact_str::CompactString;
+use utils::suffixlist::registrable_domain;
 
 use crate::{Email, Hostname};
 
@@ -45,13 +46,7 @@ impl Hostname {
 
         Hostname {
             sld: if ip.is_none() {
-                psl::domain(fqdn.as_bytes()).and_then(|domain| {
-                    if domain.suffix().typ().is_some() {
-                        std::str::from_utf8(domain.as_bytes()).ok().map(Into::into)
-                    } else {
-                        None
-                    }
-                })
+                registrable_domain(&fqdn).map(Into::into)
             } else {
                 None
             },
@@ -6,7 +6,7 @@
 
 use crate::{
     BlobStore, CompressionAlgo, InMemoryStore, PurgeSchedule, PurgeStore, Store, Stores,
-    backend::fs::FsStore,
+    backend::fs::FsStore, dispatch::latency::LatencyBudget,
 };
 use utils::config::{Config, cron::SimpleCron, utils::ParseValue};
 
@@ -33,6 +33,10 @@ impl Stores {
 
     pub async fn parse_stores(&mut self, config: &mut Config) {
         let is_reload = !self.stores.is_empty();
+
+        // Apply latency budgets to data store operations
+        LatencyBudget::parse(config).apply();
+
         #[cfg(feature = "enterprise")]
         let mut composite_stores = Vec::new();
         let store_ids = config
@@ -319,6 +323,7 @@ impl Stores {
                                     "none",
                                 )
                                 .unwrap_or(CompressionAlgo::None),
+                            packs: None,
                         };
                         self.blob_stores.insert(id, store);
                     }
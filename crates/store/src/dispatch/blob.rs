@@ -7,13 +7,52 @@
 use std::{borrow::Cow, ops::Range, time::Instant};
 
 use trc::{AddContext, StoreEvent};
-use utils::config::utils::ParseValue;
+use utils::{BLOB_HASH_LEN, BlobHash, config::utils::ParseValue};
 
 use crate::{BlobBackend, BlobStore, CompressionAlgo, Store};
 
 impl BlobStore {
     pub async fn get_blob(&self, key: &[u8], range: Range<usize>) -> trc::Result<Option<Vec<u8>>> {
-        let read_range = match self.compression {
+        let result = self
+            .get_stored_blob(key, range.clone(), self.compression)
+            .await?;
+        if result.is_some() || key.len() != BLOB_HASH_LEN {
+            return Ok(result);
+        }
+
+        // Blobs that are not found might have been moved into a pack
+        if let Some(store) = &self.packs {
+            let hash = BlobHash::try_from_hash_slice(key).unwrap();
+
+            // A pack can be rewritten between the index lookup and the read, retry once
+            for _ in 0..2 {
+                let Some(location) = store.blob_pack_location(&hash).await? else {
+                    break;
+                };
+                let length = location.length as usize;
+                let start = location.offset as usize + range.start.min(length);
+                let end = location.offset as usize + range.end.max(range.start).min(length);
+                if let Some(bytes) = self.get_pack(location.pack.as_slice(), start..end).await? {
+                    return Ok(Some(bytes));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub async fn get_pack(&self, key: &[u8], range: Range<usize>) -> trc::Result<Option<Vec<u8>>> {
+        self.get_stored_blob(key, range, CompressionAlgo::None)
+            .await
+    }
+
+    async fn get_stored_blob(
+        &self,
+        key: &[u8],
+        range: Range<usize>,
+        compression: CompressionAlgo,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        let read_range = match compression {
             CompressionAlgo::None => range.clone(),
             CompressionAlgo::Lz4 => 0..usize::MAX,
         };
@@ -52,7 +91,7 @@ impl BlobStore {
                 .map_or(0, |data| data.as_ref().map_or(0, |data| data.len())),
         );
 
-        let decompressed = match self.compression {
+        let decompressed = match compression {
             CompressionAlgo::Lz4 => match result.caused_by(trc::location!())? {
                 Some(data)
                     if data.last().copied().unwrap_or_default()
@@ -90,7 +129,21 @@ impl BlobStore {
     }
 
     pub async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
-        let data: Cow<[u8]> = match self.compression {
+        self.put_stored_blob(key, data, self.compression).await
+    }
+
+    pub async fn put_pack(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
+        // Packs are stored uncompressed so packed blobs can be read using range requests
+        self.put_stored_blob(key, data, CompressionAlgo::None).await
+    }
+
+    async fn put_stored_blob(
+        &self,
+        key: &[u8],
+        data: &[u8],
+        compression: CompressionAlgo,
+    ) -> trc::Result<()> {
+        let data: Cow<[u8]> = match compression {
             CompressionAlgo::None => data.into(),
             CompressionAlgo::Lz4 => {
                 let mut compressed = lz4_flex::compress_prepend_size(data);
@@ -177,6 +230,15 @@ impl BlobStore {
         Self {
             backend: self.backend,
             compression,
+            packs: self.packs,
+        }
+    }
+
+    pub fn with_packs(self, store: Store) -> Self {
+        Self {
+            backend: self.backend,
+            compression: self.compression,
+            packs: (!matches!(store, Store::None)).then_some(store),
         }
     }
 }
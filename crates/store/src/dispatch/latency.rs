@@ -0,0 +1,266 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use trc::StoreEvent;
+use utils::config::Config;
+
+use crate::write::now;
+
+// Zero disables the corresponding check
+static SLOW_QUERY_MS: AtomicU64 = AtomicU64::new(0);
+static TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+
+static SLOW_OPERATIONS: Mutex<VecDeque<SlowOperation>> = Mutex::new(VecDeque::new());
+const MAX_SLOW_OPERATIONS: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StoreOperation {
+    GetValue,
+    GetBitmap,
+    GetCounter,
+    Iterate,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OperationContext {
+    pub operation: StoreOperation,
+    pub subspace: u8,
+    pub account_id: Option<u32>,
+    pub collection: Option<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SlowOperation {
+    pub context: OperationContext,
+    pub elapsed: Duration,
+    pub timed_out: bool,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SlowOperationSummary {
+    pub context: OperationContext,
+    pub count: u64,
+    pub timeouts: u64,
+    pub max_elapsed: Duration,
+    pub total_elapsed: Duration,
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LatencyBudget {
+    pub slow_query: Option<Duration>,
+    pub timeout: Option<Duration>,
+}
+
+impl LatencyBudget {
+    pub fn parse(config: &mut Config) -> Self {
+        LatencyBudget {
+            slow_query: config
+                .property_or_default::<Option<Duration>>("storage.latency.slow-query", "1s")
+                .unwrap_or_default(),
+            timeout: config
+                .property_or_default::<Option<Duration>>("storage.latency.timeout", "false")
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn apply(&self) {
+        SLOW_QUERY_MS.store(
+            self.slow_query.map_or(0, |d| d.as_millis() as u64),
+            Ordering::Relaxed,
+        );
+        TIMEOUT_MS.store(
+            self.timeout.map_or(0, |d| d.as_millis() as u64),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn current() -> Self {
+        let from_millis = |ms: u64| (ms > 0).then(|| Duration::from_millis(ms));
+
+        LatencyBudget {
+            slow_query: from_millis(SLOW_QUERY_MS.load(Ordering::Relaxed)),
+            timeout: from_millis(TIMEOUT_MS.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl StoreOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StoreOperation::GetValue => "get-value",
+            StoreOperation::GetBitmap => "get-bitmap",
+            StoreOperation::GetCounter => "get-counter",
+            StoreOperation::Iterate => "iterate",
+            StoreOperation::Write => "write",
+        }
+    }
+
+    fn event(&self) -> StoreEvent {
+        match self {
+            StoreOperation::GetValue | StoreOperation::GetCounter => StoreEvent::DataRead,
+            StoreOperation::GetBitmap => StoreEvent::BitmapRead,
+            StoreOperation::Iterate => StoreEvent::DataIterate,
+            StoreOperation::Write => StoreEvent::DataWrite,
+        }
+    }
+
+    // Writes are never cancelled, dropping an in-flight commit could leave it half applied
+    fn is_cancellable(&self) -> bool {
+        !matches!(self, StoreOperation::Write)
+    }
+}
+
+impl OperationContext {
+    pub fn new(operation: StoreOperation, subspace: u8) -> Self {
+        OperationContext {
+            operation,
+            subspace,
+            account_id: None,
+            collection: None,
+        }
+    }
+
+    pub fn with_account(mut self, account_id: u32, collection: u8) -> Self {
+        self.account_id = Some(account_id);
+        self.collection = Some(collection);
+        self
+    }
+
+    pub(crate) async fn run<T>(
+        self,
+        total: Option<usize>,
+        fut: impl Future<Output = trc::Result<T>>,
+    ) -> trc::Result<T> {
+        self.run_with_budget(&LatencyBudget::current(), total, fut)
+            .await
+    }
+
+    pub async fn run_with_budget<T>(
+        self,
+        budget: &LatencyBudget,
+        total: Option<usize>,
+        fut: impl Future<Output = trc::Result<T>>,
+    ) -> trc::Result<T> {
+        let start_time = Instant::now();
+        let timeout = budget.timeout.filter(|_| self.operation.is_cancellable());
+        let result = if let Some(timeout) = timeout {
+            match tokio::time::timeout(timeout, fut).await {
+                Ok(result) => result,
+                Err(_) => {
+                    let elapsed = start_time.elapsed();
+                    self.record(elapsed, true);
+
+                    return Err(StoreEvent::QueryTimeout
+                        .into_err()
+                        .ctx(trc::Key::Type, self.operation.as_str())
+                        .ctx(trc::Key::Details, self.subspace_name())
+                        .ctx(trc::Key::Collection, self.collection.map(u64::from))
+                        .ctx(trc::Key::AccountId, self.account_id)
+                        .ctx(trc::Key::Elapsed, elapsed)
+                        .ctx(trc::Key::Limit, timeout));
+                }
+            }
+        } else {
+            fut.await
+        };
+        let elapsed = start_time.elapsed();
+
+        trc::event!(
+            Store(self.operation.event()),
+            Elapsed = elapsed,
+            Total = total,
+        );
+
+        if let Some(slow_query) = budget.slow_query.filter(|limit| elapsed >= *limit) {
+            trc::event!(
+                Store(StoreEvent::SlowQuery),
+                Type = self.operation.as_str(),
+                Details = self.subspace_name(),
+                Collection = self.collection.map(u64::from),
+                AccountId = self.account_id,
+                Elapsed = elapsed,
+                Limit = slow_query,
+            );
+            self.record(elapsed, false);
+        }
+
+        result
+    }
+
+    pub fn subspace_name(&self) -> String {
+        char::from(self.subspace).to_string()
+    }
+
+    fn record(self, elapsed: Duration, timed_out: bool) {
+        let mut operations = SLOW_OPERATIONS
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if operations.len() >= MAX_SLOW_OPERATIONS {
+            operations.pop_front();
+        }
+        operations.push_back(SlowOperation {
+            context: self,
+            elapsed,
+            timed_out,
+            timestamp: now(),
+        });
+    }
+}
+
+pub fn recent_slow_operations() -> Vec<SlowOperation> {
+    SLOW_OPERATIONS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .iter()
+        .cloned()
+        .collect()
+}
+
+pub fn top_slow_operations(limit: usize) -> Vec<SlowOperationSummary> {
+    let mut summaries: HashMap<OperationContext, SlowOperationSummary> = HashMap::new();
+    for operation in recent_slow_operations() {
+        let summary = summaries
+            .entry(operation.context)
+            .or_insert_with(|| SlowOperationSummary {
+                context: operation.context,
+                count: 0,
+                timeouts: 0,
+                max_elapsed: Duration::ZERO,
+                total_elapsed: Duration::ZERO,
+                last_seen: 0,
+            });
+        summary.count += 1;
+        summary.timeouts += operation.timed_out as u64;
+        summary.max_elapsed = summary.max_elapsed.max(operation.elapsed);
+        summary.total_elapsed += operation.elapsed;
+        summary.last_seen = summary.last_seen.max(operation.timestamp);
+    }
+
+    let mut summaries = summaries.into_values().collect::<Vec<_>>();
+    summaries.sort_unstable_by(|a, b| b.max_elapsed.cmp(&a.max_elapsed));
+    summaries.truncate(limit);
+    summaries
+}
+
+pub fn clear_slow_operations() {
+    SLOW_OPERATIONS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .clear();
+}
@@ -10,6 +10,7 @@ use crate::Store;
 
 pub mod blob;
 pub mod fts;
+pub mod latency;
 pub mod lookup;
 pub mod pubsub;
 pub mod store;
@@ -4,14 +4,11 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{
-    ops::{BitAndAssign, Range},
-    time::Instant,
-};
+use std::ops::{BitAndAssign, Range};
 
 use compact_str::ToCompactString;
 use roaring::RoaringBitmap;
-use trc::{AddContext, StoreEvent};
+use trc::AddContext;
 
 use crate::{
     BitmapKey, Deserialize, IterateParams, Key, QueryResult, SUBSPACE_BITMAP_ID,
@@ -25,7 +22,10 @@ use crate::{
     },
 };
 
-use super::DocumentSet;
+use super::{
+    DocumentSet,
+    latency::{OperationContext, StoreOperation},
+};
 
 #[cfg(feature = "test_mode")]
 #[allow(clippy::type_complexity)]
@@ -42,44 +42,59 @@ impl Store {
     where
         U: Deserialize + 'static,
     {
-        match self {
-            #[cfg(feature = "sqlite")]
-            Self::SQLite(store) => store.get_value(key).await,
-            #[cfg(feature = "foundation")]
-            Self::FoundationDb(store) => store.get_value(key).await,
-            #[cfg(feature = "postgres")]
-            Self::PostgreSQL(store) => store.get_value(key).await,
-            #[cfg(feature = "mysql")]
-            Self::MySQL(store) => store.get_value(key).await,
-            #[cfg(feature = "rocks")]
-            Self::RocksDb(store) => store.get_value(key).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.get_value(key).await,
-            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
-        }
-        .caused_by(trc::location!())
+        OperationContext::new(StoreOperation::GetValue, key.subspace())
+            .run(None, async {
+                match self {
+                    #[cfg(feature = "sqlite")]
+                    Self::SQLite(store) => store.get_value(key).await,
+                    #[cfg(feature = "foundation")]
+                    Self::FoundationDb(store) => store.get_value(key).await,
+                    #[cfg(feature = "postgres")]
+                    Self::PostgreSQL(store) => store.get_value(key).await,
+                    #[cfg(feature = "mysql")]
+                    Self::MySQL(store) => store.get_value(key).await,
+                    #[cfg(feature = "rocks")]
+                    Self::RocksDb(store) => store.get_value(key).await,
+                    #[cfg(all(
+                        feature = "enterprise",
+                        any(feature = "postgres", feature = "mysql")
+                    ))]
+                    Self::SQLReadReplica(store) => store.get_value(key).await,
+                    Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+                }
+            })
+            .await
+            .caused_by(trc::location!())
     }
 
     pub async fn get_bitmap(
         &self,
         key: BitmapKey<BitmapClass>,
     ) -> trc::Result<Option<RoaringBitmap>> {
-        match self {
-            #[cfg(feature = "sqlite")]
-            Self::SQLite(store) => store.get_bitmap(key).await,
-            #[cfg(feature = "foundation")]
-            Self::FoundationDb(store) => store.get_bitmap(key).await,
-            #[cfg(feature = "postgres")]
-            Self::PostgreSQL(store) => store.get_bitmap(key).await,
-            #[cfg(feature = "mysql")]
-            Self::MySQL(store) => store.get_bitmap(key).await,
-            #[cfg(feature = "rocks")]
-            Self::RocksDb(store) => store.get_bitmap(key).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.get_bitmap(key).await,
-            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
-        }
-        .caused_by(trc::location!())
+        OperationContext::new(StoreOperation::GetBitmap, key.subspace())
+            .with_account(key.account_id, key.collection)
+            .run(None, async {
+                match self {
+                    #[cfg(feature = "sqlite")]
+                    Self::SQLite(store) => store.get_bitmap(key).await,
+                    #[cfg(feature = "foundation")]
+                    Self::FoundationDb(store) => store.get_bitmap(key).await,
+                    #[cfg(feature = "postgres")]
+                    Self::PostgreSQL(store) => store.get_bitmap(key).await,
+                    #[cfg(feature = "mysql")]
+                    Self::MySQL(store) => store.get_bitmap(key).await,
+                    #[cfg(feature = "rocks")]
+                    Self::RocksDb(store) => store.get_bitmap(key).await,
+                    #[cfg(all(
+                        feature = "enterprise",
+                        any(feature = "postgres", feature = "mysql")
+                    ))]
+                    Self::SQLReadReplica(store) => store.get_bitmap(key).await,
+                    Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+                }
+            })
+            .await
+            .caused_by(trc::location!())
     }
 
     pub async fn get_bitmaps_intersection(
@@ -109,52 +124,60 @@ impl Store {
         params: IterateParams<T>,
         cb: impl for<'x> FnMut(&'x [u8], &'x [u8]) -> trc::Result<bool> + Sync + Send,
     ) -> trc::Result<()> {
-        let start_time = Instant::now();
-        let result = match self {
-            #[cfg(feature = "sqlite")]
-            Self::SQLite(store) => store.iterate(params, cb).await,
-            #[cfg(feature = "foundation")]
-            Self::FoundationDb(store) => store.iterate(params, cb).await,
-            #[cfg(feature = "postgres")]
-            Self::PostgreSQL(store) => store.iterate(params, cb).await,
-            #[cfg(feature = "mysql")]
-            Self::MySQL(store) => store.iterate(params, cb).await,
-            #[cfg(feature = "rocks")]
-            Self::RocksDb(store) => store.iterate(params, cb).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.iterate(params, cb).await,
-            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
-        }
-        .caused_by(trc::location!());
-
-        trc::event!(
-            Store(StoreEvent::DataIterate),
-            Elapsed = start_time.elapsed(),
-        );
-
-        result
+        OperationContext::new(StoreOperation::Iterate, params.begin.subspace())
+            .run(None, async {
+                match self {
+                    #[cfg(feature = "sqlite")]
+                    Self::SQLite(store) => store.iterate(params, cb).await,
+                    #[cfg(feature = "foundation")]
+                    Self::FoundationDb(store) => store.iterate(params, cb).await,
+                    #[cfg(feature = "postgres")]
+                    Self::PostgreSQL(store) => store.iterate(params, cb).await,
+                    #[cfg(feature = "mysql")]
+                    Self::MySQL(store) => store.iterate(params, cb).await,
+                    #[cfg(feature = "rocks")]
+                    Self::RocksDb(store) => store.iterate(params, cb).await,
+                    #[cfg(all(
+                        feature = "enterprise",
+                        any(feature = "postgres", feature = "mysql")
+                    ))]
+                    Self::SQLReadReplica(store) => store.iterate(params, cb).await,
+                    Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+                }
+            })
+            .await
+            .caused_by(trc::location!())
     }
 
     pub async fn get_counter(
         &self,
         key: impl Into<ValueKey<ValueClass>> + Sync + Send,
     ) -> trc::Result<i64> {
-        match self {
-            #[cfg(feature = "sqlite")]
-            Self::SQLite(store) => store.get_counter(key).await,
-            #[cfg(feature = "foundation")]
-            Self::FoundationDb(store) => store.get_counter(key).await,
-            #[cfg(feature = "postgres")]
-            Self::PostgreSQL(store) => store.get_counter(key).await,
-            #[cfg(feature = "mysql")]
-            Self::MySQL(store) => store.get_counter(key).await,
-            #[cfg(feature = "rocks")]
-            Self::RocksDb(store) => store.get_counter(key).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.get_counter(key).await,
-            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
-        }
-        .caused_by(trc::location!())
+        let key = key.into();
+        OperationContext::new(StoreOperation::GetCounter, key.subspace())
+            .with_account(key.account_id, key.collection)
+            .run(None, async {
+                match self {
+                    #[cfg(feature = "sqlite")]
+                    Self::SQLite(store) => store.get_counter(key).await,
+                    #[cfg(feature = "foundation")]
+                    Self::FoundationDb(store) => store.get_counter(key).await,
+                    #[cfg(feature = "postgres")]
+                    Self::PostgreSQL(store) => store.get_counter(key).await,
+                    #[cfg(feature = "mysql")]
+                    Self::MySQL(store) => store.get_counter(key).await,
+                    #[cfg(feature = "rocks")]
+                    Self::RocksDb(store) => store.get_counter(key).await,
+                    #[cfg(all(
+                        feature = "enterprise",
+                        any(feature = "postgres", feature = "mysql")
+                    ))]
+                    Self::SQLReadReplica(store) => store.get_counter(key).await,
+                    Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+                }
+            })
+            .await
+            .caused_by(trc::location!())
     }
 
     #[allow(unreachable_patterns)]
@@ -185,32 +208,30 @@ impl Store {
     }
 
     pub async fn write(&self, batch: Batch<'_>) -> trc::Result<AssignedIds> {
-        let start_time = Instant::now();
         let ops = batch.ops.len();
 
-        let result = match self {
-            #[cfg(feature = "sqlite")]
-            Self::SQLite(store) => store.write(batch).await,
-            #[cfg(feature = "foundation")]
-            Self::FoundationDb(store) => store.write(batch).await,
-            #[cfg(feature = "postgres")]
-            Self::PostgreSQL(store) => store.write(batch).await,
-            #[cfg(feature = "mysql")]
-            Self::MySQL(store) => store.write(batch).await,
-            #[cfg(feature = "rocks")]
-            Self::RocksDb(store) => store.write(batch).await,
-            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
-            Self::SQLReadReplica(store) => store.write(batch).await,
-            Self::None => Err(trc::StoreEvent::NotConfigured.into()),
-        };
-
-        trc::event!(
-            Store(StoreEvent::DataWrite),
-            Elapsed = start_time.elapsed(),
-            Total = ops,
-        );
-
-        result
+        OperationContext::new(StoreOperation::Write, 0)
+            .run(Some(ops), async {
+                match self {
+                    #[cfg(feature = "sqlite")]
+                    Self::SQLite(store) => store.write(batch).await,
+                    #[cfg(feature = "foundation")]
+                    Self::FoundationDb(store) => store.write(batch).await,
+                    #[cfg(feature = "postgres")]
+                    Self::PostgreSQL(store) => store.write(batch).await,
+                    #[cfg(feature = "mysql")]
+                    Self::MySQL(store) => store.write(batch).await,
+                    #[cfg(feature = "rocks")]
+                    Self::RocksDb(store) => store.write(batch).await,
+                    #[cfg(all(
+                        feature = "enterprise",
+                        any(feature = "postgres", feature = "mysql")
+                    ))]
+                    Self::SQLReadReplica(store) => store.write(batch).await,
+                    Self::None => Err(trc::StoreEvent::NotConfigured.into()),
+                }
+            })
+            .await
     }
 
     pub async fn assign_document_ids(
@@ -582,7 +603,13 @@ impl Store {
             IterateParams::new(from_key, to_key).ascending().no_values(),
             |key, _| {
                 let account_id = key.deserialize_be_u32(0).caused_by(trc::location!())?;
-                if account_id != last_account_id {
+                let until = key
+                    .deserialize_be_u64(key.len() - U64_LEN)
+                    .caused_by(trc::location!())?;
+                if until == u64::MAX {
+                    // Blob packs are removed once all their blobs are purged
+                    return Ok(true);
+                } else if account_id != last_account_id {
                     last_account_id = account_id;
                     batch.with_account_id(account_id);
                 }
@@ -593,9 +620,7 @@ impl Store {
                             key.get(U32_LEN..U32_LEN + BLOB_HASH_LEN).unwrap(),
                         )
                         .unwrap(),
-                        until: key
-                            .deserialize_be_u64(key.len() - U64_LEN)
-                            .caused_by(trc::location!())?,
+                        until,
                     }),
                     op: ValueOp::Clear,
                 });
@@ -183,6 +183,7 @@ pub enum Store {
 pub struct BlobStore {
     pub backend: BlobBackend,
     pub compression: CompressionAlgo,
+    pub packs: Option<Store>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -275,6 +276,7 @@ impl From<FsStore> for BlobStore {
         BlobStore {
             backend: BlobBackend::Fs(Arc::new(store)),
             compression: CompressionAlgo::None,
+            packs: None,
         }
     }
 }
@@ -285,6 +287,7 @@ impl From<backend::s3::S3Store> for BlobStore {
         BlobStore {
             backend: BlobBackend::S3(Arc::new(store)),
             compression: CompressionAlgo::None,
+            packs: None,
         }
     }
 }
@@ -295,6 +298,7 @@ impl From<backend::azure::AzureStore> for BlobStore {
         BlobStore {
             backend: BlobBackend::Azure(Arc::new(store)),
             compression: CompressionAlgo::None,
+            packs: None,
         }
     }
 }
@@ -324,6 +328,7 @@ impl From<Store> for BlobStore {
         BlobStore {
             backend: BlobBackend::Store(store),
             compression: CompressionAlgo::None,
+            packs: None,
         }
     }
 }
@@ -339,6 +344,7 @@ impl Default for BlobStore {
         Self {
             backend: BlobBackend::Store(Store::None),
             compression: CompressionAlgo::None,
+            packs: None,
         }
     }
 }
@@ -15,6 +15,9 @@ use crate::{
 
 use super::{BlobOp, Operation, ValueClass, ValueOp, key::DeserializeBigEndian, now};
 
+// Marker used by the keys of blobs that have been moved into a pack
+const BLOB_PACKED: u8 = 1;
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct BlobQuota {
     pub bytes: usize,
@@ -149,7 +152,7 @@ impl Store {
                 let until = key.deserialize_be_u64(key.len() - U64_LEN)?;
                 if until <= now {
                     delete_keys.push((key.deserialize_be_u32(0)?, BlobOp::Reserve { until, hash }));
-                } else {
+                } else if until != u64::MAX {
                     active_hashes.insert(hash);
                 }
                 Ok(true)
@@ -176,6 +179,7 @@ impl Store {
             }),
         };
         let mut last_hash = BlobHash::default();
+        let mut packed_hashes = Vec::new();
         self.iterate(
             IterateParams::new(from_key, to_key).ascending().no_values(),
             |key, _| {
@@ -192,7 +196,11 @@ impl Store {
                     }
                 } else if last_hash != hash && !active_hashes.contains(&hash) {
                     // Unlinked or expired blob, delete.
-                    delete_keys.push((0, BlobOp::Commit { hash }));
+                    if key[BLOB_HASH_LEN + U32_LEN] == BLOB_PACKED {
+                        packed_hashes.push(hash);
+                    } else {
+                        delete_keys.push((0, BlobOp::Commit { hash }));
+                    }
                 }
 
                 Ok(true)
@@ -211,6 +219,13 @@ impl Store {
             }
         }
 
+        // Release blobs stored inside packs
+        if !packed_hashes.is_empty() {
+            self.blob_pack_unlink(&blob_store, packed_hashes)
+                .await
+                .caused_by(trc::location!())?;
+        }
+
         // Delete hashes
         let mut batch = BatchBuilder::new();
         let mut last_account_id = u32::MAX;
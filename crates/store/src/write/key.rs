@@ -297,6 +297,23 @@ impl ValueClass {
                     .write(if *learn_spam { 1u8 } else { 2u8 })
                     .write(document_id)
                     .write::<&[u8]>(hash.as_ref()),
+                TaskQueueClass::EncryptEmail { seq, hash } => serializer
+                    .write(*seq)
+                    .write(account_id)
+                    .write(3u8)
+                    .write(document_id)
+                    .write::<&[u8]>(hash.as_ref()),
+                TaskQueueClass::IngestHook {
+                    seq,
+                    hash,
+                    mailbox_id,
+                } => serializer
+                    .write(*seq)
+                    .write(account_id)
+                    .write(4u8)
+                    .write(document_id)
+                    .write::<&[u8]>(hash.as_ref())
+                    .write(*mailbox_id),
             },
             ValueClass::Blob(op) => match op {
                 BlobOp::Reserve { hash, until } => serializer
@@ -318,6 +335,15 @@ impl ValueClass {
                     .write((*id >> 32) as u32)
                     .write(u8::MAX)
                     .write(*id as u32),
+                BlobOp::Pack { hash } => serializer
+                    .write(account_id)
+                    .write::<&[u8]>(hash.as_ref())
+                    .write(u64::MAX),
+                BlobOp::Packed { hash } => serializer
+                    .write::<&[u8]>(hash.as_ref())
+                    .write(u32::MAX)
+                    .write(1u8)
+                    .write(u32::MAX),
             },
             ValueClass::Config(key) => serializer.write(key.as_slice()),
             ValueClass::InMemory(lookup) => match lookup {
@@ -560,10 +586,13 @@ impl ValueClass {
                 DirectoryClass::Index { word, .. } => word.len() + U32_LEN,
             },
             ValueClass::Blob(op) => match op {
-                BlobOp::Reserve { .. } => BLOB_HASH_LEN + U64_LEN + U32_LEN + 1,
-                BlobOp::Commit { .. } | BlobOp::Link { .. } | BlobOp::LinkId { .. } => {
-                    BLOB_HASH_LEN + U32_LEN * 2 + 2
+                BlobOp::Reserve { .. } | BlobOp::Pack { .. } => {
+                    BLOB_HASH_LEN + U64_LEN + U32_LEN + 1
                 }
+                BlobOp::Commit { .. }
+                | BlobOp::Link { .. }
+                | BlobOp::LinkId { .. }
+                | BlobOp::Packed { .. } => BLOB_HASH_LEN + U32_LEN * 2 + 2,
             },
             ValueClass::TaskQueue { .. } => BLOB_HASH_LEN + U64_LEN * 2,
             ValueClass::Queue(q) => match q {
@@ -602,10 +631,11 @@ impl ValueClass {
             ValueClass::FtsIndex(_) => SUBSPACE_FTS_INDEX,
             ValueClass::TaskQueue { .. } => SUBSPACE_TASK_QUEUE,
             ValueClass::Blob(op) => match op {
-                BlobOp::Reserve { .. } => SUBSPACE_BLOB_RESERVE,
-                BlobOp::Commit { .. } | BlobOp::Link { .. } | BlobOp::LinkId { .. } => {
-                    SUBSPACE_BLOB_LINK
-                }
+                BlobOp::Reserve { .. } | BlobOp::Pack { .. } => SUBSPACE_BLOB_RESERVE,
+                BlobOp::Commit { .. }
+                | BlobOp::Link { .. }
+                | BlobOp::LinkId { .. }
+                | BlobOp::Packed { .. } => SUBSPACE_BLOB_LINK,
             },
             ValueClass::Config(_) => SUBSPACE_SETTINGS,
             ValueClass::InMemory(lookup) => match lookup {
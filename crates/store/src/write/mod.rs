@@ -31,6 +31,7 @@ pub mod blob;
 pub mod hash;
 pub mod key;
 pub mod log;
+pub mod pack;
 pub mod serialize;
 
 pub(crate) const ARCHIVE_ALIGNMENT: usize = 16;
@@ -204,6 +205,15 @@ pub enum TaskQueueClass {
         hash: BlobHash,
         learn_spam: bool,
     },
+    EncryptEmail {
+        seq: u64,
+        hash: BlobHash,
+    },
+    IngestHook {
+        seq: u64,
+        hash: BlobHash,
+        mailbox_id: u32,
+    },
 }
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
@@ -296,6 +306,8 @@ pub enum BlobOp {
     Commit { hash: BlobHash },
     Link { hash: BlobHash },
     LinkId { hash: BlobHash, id: u64 },
+    Pack { hash: BlobHash },
+    Packed { hash: BlobHash },
 }
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
@@ -0,0 +1,545 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::AHashMap;
+use trc::AddContext;
+use utils::{BLOB_HASH_LEN, BlobHash};
+
+use crate::{
+    BlobStore, Deserialize, IterateParams, Serialize, Store, U32_LEN, U64_LEN, ValueKey,
+    write::BatchBuilder,
+};
+
+use super::{BlobOp, ValueClass, assert::AssertValue, key::DeserializeBigEndian, now};
+
+// Each packed blob requires an assertion and a value write, keep batches small
+pub const BLOB_PACK_MAX_ENTRIES: usize = 400;
+
+const ENTRY_LEN: usize = BLOB_HASH_LEN + U64_LEN + U32_LEN + 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobPack {
+    pub created: u64,
+    pub entries: Vec<BlobPackEntry>,
+    pub revision: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobPackEntry {
+    pub hash: BlobHash,
+    pub offset: u64,
+    pub length: u32,
+    pub is_live: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobPackLocation {
+    pub account_id: u32,
+    pub pack: BlobHash,
+    pub offset: u64,
+    pub length: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobPackSummary {
+    pub pack: BlobHash,
+    pub count: usize,
+    pub size: usize,
+}
+
+impl BlobPack {
+    // Packs are keyed by a random id rather than their contents, otherwise two nodes
+    // packing the same blobs would write to (and possibly delete) the same object.
+    fn generate_id(bytes: &[u8]) -> BlobHash {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(bytes);
+        hasher.update(&rand::random::<u64>().to_be_bytes());
+        BlobHash(hasher.finalize().into())
+    }
+
+    pub fn size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.length as u64).sum()
+    }
+
+    pub fn live_size(&self) -> u64 {
+        self.entries
+            .iter()
+            .filter(|entry| entry.is_live)
+            .map(|entry| entry.length as u64)
+            .sum()
+    }
+
+    pub fn live_count(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.is_live).count()
+    }
+
+    pub fn wasted_size(&self) -> u64 {
+        self.size() - self.live_size()
+    }
+
+    pub fn needs_rewrite(&self, threshold: f64) -> bool {
+        let size = self.size();
+        size > 0 && (self.wasted_size() as f64 / size as f64) >= threshold
+    }
+}
+
+impl Store {
+    pub async fn blob_pack_location(
+        &self,
+        hash: &BlobHash,
+    ) -> trc::Result<Option<BlobPackLocation>> {
+        self.get_value::<BlobPackLocation>(ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Packed { hash: hash.clone() }),
+        })
+        .await
+        .caused_by(trc::location!())
+    }
+
+    pub async fn blob_pack_get(
+        &self,
+        account_id: u32,
+        pack: &BlobHash,
+    ) -> trc::Result<Option<BlobPack>> {
+        self.get_value::<BlobPack>(ValueKey {
+            account_id,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Pack { hash: pack.clone() }),
+        })
+        .await
+        .caused_by(trc::location!())
+    }
+
+    pub async fn blob_packs(&self, account_id: u32) -> trc::Result<Vec<(BlobHash, BlobPack)>> {
+        let from_key = ValueKey {
+            account_id,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Reserve {
+                hash: BlobHash::default(),
+                until: 0,
+            }),
+        };
+        let to_key = ValueKey {
+            account_id: account_id + 1,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Reserve {
+                hash: BlobHash::default(),
+                until: 0,
+            }),
+        };
+
+        let mut packs = Vec::new();
+        self.iterate(
+            IterateParams::new(from_key, to_key).ascending(),
+            |key, value| {
+                if key.deserialize_be_u64(key.len() - U64_LEN)? == u64::MAX {
+                    packs.push((
+                        BlobHash::try_from_hash_slice(
+                            key.get(U32_LEN..U32_LEN + BLOB_HASH_LEN).ok_or_else(|| {
+                                trc::Error::corrupted_key(key, value.into(), trc::location!())
+                            })?,
+                        )
+                        .unwrap(),
+                        BlobPack::deserialize(value)?,
+                    ));
+                }
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        Ok(packs)
+    }
+
+    pub async fn blob_pack(
+        &self,
+        blob_store: &BlobStore,
+        account_id: u32,
+        hashes: impl IntoIterator<Item = BlobHash>,
+        min_entries: usize,
+    ) -> trc::Result<Option<BlobPackSummary>> {
+        // Fetch blobs that are committed and not already packed
+        let mut pack = BlobPack {
+            created: now(),
+            entries: Vec::new(),
+            revision: 0,
+        };
+        let mut bytes = Vec::new();
+        for hash in hashes {
+            if pack.entries.len() == BLOB_PACK_MAX_ENTRIES {
+                break;
+            } else if !self.blob_exists(&hash).await?
+                || self.blob_pack_location(&hash).await?.is_some()
+            {
+                continue;
+            }
+
+            // Blobs are only packed when their contents match the hash,
+            // this guarantees that reads from the pack are byte-identical.
+            if let Some(blob) = blob_store
+                .get_blob(hash.as_slice(), 0..usize::MAX)
+                .await
+                .caused_by(trc::location!())?
+                .filter(|blob| blob.len() <= u32::MAX as usize && BlobHash::generate(blob) == hash)
+            {
+                pack.entries.push(BlobPackEntry {
+                    hash,
+                    offset: bytes.len() as u64,
+                    length: blob.len() as u32,
+                    is_live: true,
+                });
+                bytes.extend_from_slice(&blob);
+            }
+        }
+        if pack.entries.len() < min_entries.max(2) {
+            return Ok(None);
+        }
+
+        // Upload pack
+        let pack_hash = BlobPack::generate_id(&bytes);
+        blob_store
+            .put_pack(pack_hash.as_slice(), &bytes)
+            .await
+            .caused_by(trc::location!())?;
+
+        // Index packed blobs
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .assert_value(
+                BlobOp::Pack {
+                    hash: pack_hash.clone(),
+                },
+                (),
+            )
+            .set(
+                BlobOp::Pack {
+                    hash: pack_hash.clone(),
+                },
+                pack.serialize()?,
+            );
+        for entry in &pack.entries {
+            let class = BlobOp::Packed {
+                hash: entry.hash.clone(),
+            };
+            batch.assert_value(class.clone(), ()).set(
+                class,
+                BlobPackLocation {
+                    account_id,
+                    pack: pack_hash.clone(),
+                    offset: entry.offset,
+                    length: entry.length,
+                }
+                .serialize()?,
+            );
+        }
+        match self.write(batch.build_all()).await {
+            Ok(_) => {}
+            Err(err) if err.is_assertion_failure() => {
+                // Another node packed some of these blobs concurrently
+                blob_store
+                    .delete_blob(pack_hash.as_slice())
+                    .await
+                    .caused_by(trc::location!())?;
+                return Ok(None);
+            }
+            Err(err) => {
+                return Err(err.caused_by(trc::location!()));
+            }
+        }
+
+        // Remove individual blobs, reads are now served from the pack
+        for entry in &pack.entries {
+            blob_store
+                .delete_blob(entry.hash.as_slice())
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(Some(BlobPackSummary {
+            pack: pack_hash,
+            count: pack.entries.len(),
+            size: bytes.len(),
+        }))
+    }
+
+    pub async fn blob_pack_rewrite(
+        &self,
+        blob_store: &BlobStore,
+        account_id: u32,
+        pack_hash: &BlobHash,
+    ) -> trc::Result<Option<BlobPackSummary>> {
+        let Some(pack) = self.blob_pack_get(account_id, pack_hash).await? else {
+            return Ok(None);
+        };
+        let Some(old_bytes) = blob_store
+            .get_pack(pack_hash.as_slice(), 0..usize::MAX)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(None);
+        };
+
+        // Copy live blobs into a new pack
+        let mut new_pack = BlobPack {
+            created: now(),
+            entries: Vec::with_capacity(pack.live_count()),
+            revision: 0,
+        };
+        let mut bytes = Vec::with_capacity(pack.live_size() as usize);
+        for entry in pack.entries.iter().filter(|entry| entry.is_live) {
+            let blob = old_bytes
+                .get(entry.offset as usize..entry.offset as usize + entry.length as usize)
+                .filter(|blob| BlobHash::generate(blob) == entry.hash)
+                .ok_or_else(|| {
+                    trc::StoreEvent::DataCorruption
+                        .into_err()
+                        .details("Packed blob does not match its hash")
+                        .ctx(trc::Key::Key, entry.hash.to_hex())
+                        .caused_by(trc::location!())
+                })?;
+            new_pack.entries.push(BlobPackEntry {
+                hash: entry.hash.clone(),
+                offset: bytes.len() as u64,
+                length: entry.length,
+                is_live: true,
+            });
+            bytes.extend_from_slice(blob);
+        }
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .assert_value(
+                BlobOp::Pack {
+                    hash: pack_hash.clone(),
+                },
+                AssertValue::U64(pack.revision),
+            )
+            .clear(BlobOp::Pack {
+                hash: pack_hash.clone(),
+            });
+        let new_hash = if !new_pack.entries.is_empty() {
+            let new_hash = BlobPack::generate_id(&bytes);
+            blob_store
+                .put_pack(new_hash.as_slice(), &bytes)
+                .await
+                .caused_by(trc::location!())?;
+            batch.set(
+                BlobOp::Pack {
+                    hash: new_hash.clone(),
+                },
+                new_pack.serialize()?,
+            );
+            for entry in &new_pack.entries {
+                batch.set(
+                    BlobOp::Packed {
+                        hash: entry.hash.clone(),
+                    },
+                    BlobPackLocation {
+                        account_id,
+                        pack: new_hash.clone(),
+                        offset: entry.offset,
+                        length: entry.length,
+                    }
+                    .serialize()?,
+                );
+            }
+            Some(new_hash)
+        } else {
+            None
+        };
+
+        match self.write(batch.build_all()).await {
+            Ok(_) => {}
+            Err(err) if err.is_assertion_failure() => {
+                // The pack was modified concurrently, try again on the next run
+                if let Some(new_hash) = new_hash {
+                    blob_store
+                        .delete_blob(new_hash.as_slice())
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+                return Ok(None);
+            }
+            Err(err) => {
+                return Err(err.caused_by(trc::location!()));
+            }
+        }
+
+        blob_store
+            .delete_blob(pack_hash.as_slice())
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(new_hash.map(|pack| BlobPackSummary {
+            pack,
+            count: new_pack.entries.len(),
+            size: bytes.len(),
+        }))
+    }
+
+    pub(crate) async fn blob_pack_unlink(
+        &self,
+        blob_store: &BlobStore,
+        hashes: Vec<BlobHash>,
+    ) -> trc::Result<()> {
+        // Group unlinked blobs by pack
+        let mut packs: AHashMap<(u32, BlobHash), Vec<BlobHash>> = AHashMap::new();
+        for hash in hashes {
+            if let Some(location) = self.blob_pack_location(&hash).await? {
+                packs
+                    .entry((location.account_id, location.pack))
+                    .or_default()
+                    .push(hash);
+            } else {
+                let mut batch = BatchBuilder::new();
+                batch.clear(BlobOp::Packed { hash });
+                self.write(batch.build_all())
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+        }
+
+        for ((account_id, pack_hash), hashes) in packs {
+            let mut attempts = 0;
+
+            loop {
+                let mut batch = BatchBuilder::new();
+                batch.with_account_id(account_id);
+                for hash in &hashes {
+                    batch.clear(BlobOp::Packed { hash: hash.clone() });
+                }
+
+                let is_empty =
+                    if let Some(mut pack) = self.blob_pack_get(account_id, &pack_hash).await? {
+                        let class = BlobOp::Pack {
+                            hash: pack_hash.clone(),
+                        };
+                        batch.assert_value(class.clone(), AssertValue::U64(pack.revision));
+                        for entry in pack.entries.iter_mut() {
+                            if hashes.contains(&entry.hash) {
+                                entry.is_live = false;
+                            }
+                        }
+                        if pack.live_count() == 0 {
+                            batch.clear(class);
+                            true
+                        } else {
+                            pack.revision += 1;
+                            batch.set(class, pack.serialize()?);
+                            false
+                        }
+                    } else {
+                        false
+                    };
+
+                match self.write(batch.build_all()).await {
+                    Ok(_) => {
+                        if is_empty {
+                            blob_store
+                                .delete_blob(pack_hash.as_slice())
+                                .await
+                                .caused_by(trc::location!())?;
+                        }
+                        break;
+                    }
+                    Err(err) if err.is_assertion_failure() && attempts < 3 => {
+                        attempts += 1;
+                    }
+                    Err(err) => {
+                        return Err(err.caused_by(trc::location!()));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Serialize for BlobPack {
+    fn serialize(&self) -> trc::Result<Vec<u8>> {
+        let mut serializer =
+            super::key::KeySerializer::new(U64_LEN + (self.entries.len() * ENTRY_LEN) + U64_LEN)
+                .write(self.created);
+        for entry in &self.entries {
+            serializer = serializer
+                .write::<&[u8]>(entry.hash.as_ref())
+                .write(entry.offset)
+                .write(entry.length)
+                .write(entry.is_live as u8);
+        }
+
+        // The revision is always last so it can be asserted
+        Ok(serializer.write(self.revision).finalize())
+    }
+}
+
+impl Deserialize for BlobPack {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        if bytes.len() < U64_LEN * 2 || (bytes.len() - U64_LEN * 2) % ENTRY_LEN != 0 {
+            return Err(trc::StoreEvent::DataCorruption
+                .caused_by(trc::location!())
+                .ctx(trc::Key::Value, bytes));
+        }
+
+        let mut entries = Vec::with_capacity((bytes.len() - U64_LEN * 2) / ENTRY_LEN);
+        let mut pos = U64_LEN;
+        while pos < bytes.len() - U64_LEN {
+            entries.push(BlobPackEntry {
+                hash: BlobHash::try_from_hash_slice(&bytes[pos..pos + BLOB_HASH_LEN]).unwrap(),
+                offset: bytes.deserialize_be_u64(pos + BLOB_HASH_LEN)?,
+                length: bytes.deserialize_be_u32(pos + BLOB_HASH_LEN + U64_LEN)?,
+                is_live: bytes[pos + ENTRY_LEN - 1] != 0,
+            });
+            pos += ENTRY_LEN;
+        }
+
+        Ok(BlobPack {
+            created: bytes.deserialize_be_u64(0)?,
+            entries,
+            revision: bytes.deserialize_be_u64(bytes.len() - U64_LEN)?,
+        })
+    }
+}
+
+impl Serialize for BlobPackLocation {
+    fn serialize(&self) -> trc::Result<Vec<u8>> {
+        Ok(
+            super::key::KeySerializer::new(U32_LEN + BLOB_HASH_LEN + U64_LEN + U32_LEN)
+                .write(self.account_id)
+                .write::<&[u8]>(self.pack.as_ref())
+                .write(self.offset)
+                .write(self.length)
+                .finalize(),
+        )
+    }
+}
+
+impl Deserialize for BlobPackLocation {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        Ok(BlobPackLocation {
+            account_id: bytes.deserialize_be_u32(0)?,
+            pack: BlobHash::try_from_hash_slice(
+                bytes.get(U32_LEN..U32_LEN + BLOB_HASH_LEN).ok_or_else(|| {
+                    trc::StoreEvent::DataCorruption
+                        .caused_by(trc::location!())
+                        .ctx(trc::Key::Value, bytes)
+                })?,
+            )
+            .unwrap(),
+            offset: bytes.deserialize_be_u64(U32_LEN + BLOB_HASH_LEN)?,
+            length: bytes.deserialize_be_u32(U32_LEN + BLOB_HASH_LEN + U64_LEN)?,
+        })
+    }
+}
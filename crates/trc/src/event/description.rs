@@ -54,6 +54,7 @@ impl EventType {
             EventType::Ai(event) => event.description(),
             EventType::WebDav(event) => event.description(),
             EventType::Calendar(event) => event.description(),
+            EventType::Encrypt(event) => event.description(),
         }
     }
 
@@ -104,6 +105,7 @@ impl EventType {
             EventType::Ai(event) => event.explain(),
             EventType::WebDav(event) => event.explain(),
             EventType::Calendar(event) => event.explain(),
+            EventType::Encrypt(event) => event.explain(),
         }
     }
 }
@@ -175,6 +177,9 @@ impl HousekeeperEvent {
             HousekeeperEvent::Stop => "Housekeeper process stopped",
             HousekeeperEvent::Schedule => "Housekeeper task scheduled",
             HousekeeperEvent::Run => "Housekeeper task run",
+            HousekeeperEvent::Deferred => "Housekeeper task deferred",
+            HousekeeperEvent::Paused => "Housekeeper task paused",
+            HousekeeperEvent::BudgetExceeded => "Housekeeper task exceeded its time budget",
         }
     }
 
@@ -184,6 +189,13 @@ impl HousekeeperEvent {
             HousekeeperEvent::Stop => "The housekeeper process has stopped",
             HousekeeperEvent::Schedule => "A housekeeper task has been scheduled",
             HousekeeperEvent::Run => "A housekeeper task is running",
+            HousekeeperEvent::Deferred => {
+                "A maintenance task was postponed until it is allowed to run"
+            }
+            HousekeeperEvent::Paused => "A maintenance task was skipped because it is paused",
+            HousekeeperEvent::BudgetExceeded => {
+                "A maintenance task was stopped after running for longer than allowed"
+            }
         }
     }
 }
@@ -196,6 +208,9 @@ impl TaskQueueEvent {
             TaskQueueEvent::BlobNotFound => "Blob not found for task",
             TaskQueueEvent::MetadataNotFound => "Metadata not found for task",
             TaskQueueEvent::BayesTrain => "Bayesian training completed",
+            TaskQueueEvent::Encrypt => "Message encryption completed",
+            TaskQueueEvent::BacklogHigh => "Full-text indexing backlog is high",
+            TaskQueueEvent::BacklogCleared => "Full-text indexing backlog cleared",
         }
     }
 
@@ -206,6 +221,13 @@ impl TaskQueueEvent {
             TaskQueueEvent::BlobNotFound => "The requested blob was not found for task",
             TaskQueueEvent::MetadataNotFound => "The metadata was not found for task",
             TaskQueueEvent::BayesTrain => "Bayesian training has been completed",
+            TaskQueueEvent::Encrypt => "A stored message has been encrypted at rest",
+            TaskQueueEvent::BacklogHigh => {
+                "The number of messages pending full-text indexing exceeded the alert threshold"
+            }
+            TaskQueueEvent::BacklogCleared => {
+                "The number of messages pending full-text indexing dropped below the alert threshold"
+            }
         }
     }
 }
@@ -479,6 +501,10 @@ impl SmtpEvent {
             SmtpEvent::RequestTooLarge => "Request too large",
             SmtpEvent::ConnectionStart => "SMTP connection started",
             SmtpEvent::ConnectionEnd => "SMTP connection ended",
+            SmtpEvent::TlsRequiredNo => "TLS-Required: No header received",
+            SmtpEvent::MtPriorityLowered => "MT-PRIORITY lowered",
+            SmtpEvent::TestSinkDiscard => "Message discarded by test-mode sink",
+            SmtpEvent::FromUnauthorized => "Sender not allowed in From header",
         }
     }
 
@@ -602,6 +628,18 @@ impl SmtpEvent {
             SmtpEvent::ConnectionStart => "A new SMTP connection was started",
             SmtpEvent::ConnectionEnd => "The SMTP connection was ended",
             SmtpEvent::StartTlsAlready => "TLS is already active",
+            SmtpEvent::TlsRequiredNo => {
+                "The message requested delivery without enforcing TLS policies"
+            }
+            SmtpEvent::MtPriorityLowered => {
+                "The requested MT-PRIORITY exceeds the maximum allowed and has been lowered"
+            }
+            SmtpEvent::TestSinkDiscard => {
+                "The message was addressed to a test-mode sink domain and was discarded after filtering"
+            }
+            SmtpEvent::FromUnauthorized => {
+                "The authenticated user is not allowed to use the address in the From header."
+            }
         }
     }
 }
@@ -647,6 +685,13 @@ impl DeliveryEvent {
             DeliveryEvent::DsnPermFail => "DSN permanent failure notification",
             DeliveryEvent::RawInput => "Raw SMTP input received",
             DeliveryEvent::RawOutput => "Raw SMTP output sent",
+            DeliveryEvent::WarmupLimitExceeded => "IP warmup limit exceeded",
+            DeliveryEvent::WarmupPhaseChanged => "IP warmup phase changed",
+            DeliveryEvent::WarmupPaused => "IP warmup paused",
+            DeliveryEvent::WarmupResumed => "IP warmup resumed",
+            DeliveryEvent::WarmupCompleted => "IP warmup completed",
+            DeliveryEvent::RequireTlsFailed => "REQUIRETLS requirement not met",
+            DeliveryEvent::ResponseClassified => "Remote response classified",
         }
     }
 
@@ -704,6 +749,21 @@ impl DeliveryEvent {
             }
             DeliveryEvent::RawInput => "Raw SMTP input received",
             DeliveryEvent::RawOutput => "Raw SMTP output sent",
+            DeliveryEvent::WarmupLimitExceeded => {
+                "The daily warmup limit for the source IP was exceeded, overflowing to another IP"
+            }
+            DeliveryEvent::WarmupPhaseChanged => "The source IP advanced to the next warmup phase",
+            DeliveryEvent::WarmupPaused => {
+                "The source IP warmup was paused due to high bounce or deferral rates"
+            }
+            DeliveryEvent::WarmupResumed => "The source IP warmup was resumed",
+            DeliveryEvent::WarmupCompleted => "The source IP completed its warmup schedule",
+            DeliveryEvent::RequireTlsFailed => {
+                "The message requires TLS (RFC 8689) and the destination does not meet the requirement"
+            }
+            DeliveryEvent::ResponseClassified => {
+                "A temporary failure from the remote server was classified and the retry interval adjusted"
+            }
         }
     }
 }
@@ -723,6 +783,8 @@ impl QueueEvent {
             QueueEvent::QueueDsn => "Queued DSN for delivery",
             QueueEvent::QueueAutogenerated => "Queued autogenerated message for delivery",
             QueueEvent::BackPressure => "Queue backpressure detected",
+            QueueEvent::Quarantined => "Message held in quarantine",
+            QueueEvent::QuarantineReleased => "Quarantined message released",
         }
     }
 
@@ -744,6 +806,12 @@ impl QueueEvent {
             QueueEvent::BackPressure => {
                 "Queue congested, processing can't keep up with incoming message rate"
             }
+            QueueEvent::Quarantined => {
+                "The message was quarantined and its delivery to the recipient is on hold"
+            }
+            QueueEvent::QuarantineReleased => {
+                "A quarantined message was released for delivery using a release token"
+            }
         }
     }
 }
@@ -818,6 +886,9 @@ impl OutgoingReportEvent {
             OutgoingReportEvent::SubmissionError => "Error submitting report",
             OutgoingReportEvent::NoRecipientsFound => "No recipients found for report",
             OutgoingReportEvent::Locked => "Report is locked by another process",
+            OutgoingReportEvent::SystemMessage => "System message sent",
+            OutgoingReportEvent::SystemMessageRateLimited => "System message rate limited",
+            OutgoingReportEvent::SystemMessageSuppressed => "System message suppressed",
         }
     }
 
@@ -842,6 +913,11 @@ impl OutgoingReportEvent {
             OutgoingReportEvent::SubmissionError => "Error submitting the report",
             OutgoingReportEvent::NoRecipientsFound => "No recipients found for the report",
             OutgoingReportEvent::Locked => "The report is locked by another process",
+            OutgoingReportEvent::SystemMessage => "A system message has been sent",
+            OutgoingReportEvent::SystemMessageRateLimited => "The system message was rate limited",
+            OutgoingReportEvent::SystemMessageSuppressed => {
+                "The system message was not sent because the recipient account is disabled"
+            }
         }
     }
 }
@@ -1053,6 +1129,7 @@ impl SieveEvent {
             SieveEvent::UnexpectedError => "Unexpected Sieve error",
             SieveEvent::NotSupported => "Sieve action not supported",
             SieveEvent::QuotaExceeded => "Sieve quota exceeded",
+            SieveEvent::PreludeError => "Sieve prelude error",
         }
     }
 
@@ -1072,6 +1149,9 @@ impl SieveEvent {
             SieveEvent::UnexpectedError => "An unexpected error occurred with the Sieve script",
             SieveEvent::NotSupported => "The Sieve action is not supported",
             SieveEvent::QuotaExceeded => "The Sieve quota was exceeded",
+            SieveEvent::PreludeError => {
+                "The Sieve prelude could not be run, only the user script was executed"
+            }
         }
     }
 }
@@ -1085,6 +1165,10 @@ impl TlsEvent {
             TlsEvent::CertificateNotFound => "TLS certificate not found",
             TlsEvent::NoCertificatesAvailable => "No TLS certificates available",
             TlsEvent::MultipleCertificatesAvailable => "Multiple TLS certificates available",
+            TlsEvent::ClientFingerprint => "TLS client fingerprint",
+            TlsEvent::CertificateReloaded => "TLS certificate reloaded",
+            TlsEvent::CertificateReloadError => "TLS certificate reload error",
+            TlsEvent::TicketKeysRotated => "TLS session ticket keys rotated",
         }
     }
 
@@ -1096,6 +1180,16 @@ impl TlsEvent {
             TlsEvent::CertificateNotFound => "The TLS certificate was not found",
             TlsEvent::NoCertificatesAvailable => "No TLS certificates are available",
             TlsEvent::MultipleCertificatesAvailable => "Multiple TLS certificates are available",
+            TlsEvent::ClientFingerprint => "A fingerprint was computed from the TLS ClientHello",
+            TlsEvent::CertificateReloaded => {
+                "A TLS certificate was reloaded from its configured files"
+            }
+            TlsEvent::CertificateReloadError => {
+                "A TLS certificate could not be reloaded, the previous certificate is still in use"
+            }
+            TlsEvent::TicketKeysRotated => {
+                "A new TLS session ticket key was generated, previous keys remain valid for decryption until their grace period ends"
+            }
         }
     }
 }
@@ -1213,6 +1307,8 @@ impl AcmeEvent {
             AcmeEvent::OrderValid => "ACME order valid",
             AcmeEvent::OrderInvalid => "ACME order invalid",
             AcmeEvent::RenewBackoff => "ACME renew backoff",
+            AcmeEvent::RenewScheduled => "ACME renewal scheduled",
+            AcmeEvent::RenewFailed => "ACME renewal failed",
             AcmeEvent::DnsRecordCreated => "ACME DNS record created",
             AcmeEvent::DnsRecordCreationFailed => "ACME DNS record creation failed",
             AcmeEvent::DnsRecordDeletionFailed => "ACME DNS record deletion failed",
@@ -1225,6 +1321,11 @@ impl AcmeEvent {
             AcmeEvent::TlsAlpnReceived => "ACME TLS ALPN received",
             AcmeEvent::TlsAlpnError => "ACME TLS ALPN error",
             AcmeEvent::TokenNotFound => "ACME token not found",
+            AcmeEvent::OcspStapled => "OCSP response stapled",
+            AcmeEvent::OcspError => "OCSP error",
+            AcmeEvent::TlsaPublished => "TLSA records published",
+            AcmeEvent::TlsaPublishFailed => "Failed to publish TLSA records",
+            AcmeEvent::DomainsExcluded => "Domains excluded from certificate",
             AcmeEvent::Error => "ACME error",
         }
     }
@@ -1245,6 +1346,8 @@ impl AcmeEvent {
             AcmeEvent::OrderValid => "ACME order is valid",
             AcmeEvent::OrderInvalid => "ACME order is invalid",
             AcmeEvent::RenewBackoff => "ACME renew backoff",
+            AcmeEvent::RenewScheduled => "The ACME certificate renewal has been scheduled",
+            AcmeEvent::RenewFailed => "Failed to renew the ACME certificate, retrying later",
             AcmeEvent::DnsRecordCreated => "ACME DNS record has been created",
             AcmeEvent::DnsRecordCreationFailed => "Failed to create ACME DNS record",
             AcmeEvent::DnsRecordDeletionFailed => "Failed to delete ACME DNS record",
@@ -1257,6 +1360,13 @@ impl AcmeEvent {
             AcmeEvent::TlsAlpnReceived => "ACME TLS ALPN received",
             AcmeEvent::TlsAlpnError => "ACME TLS ALPN error",
             AcmeEvent::TokenNotFound => "ACME token not found",
+            AcmeEvent::OcspStapled => "An OCSP response was stapled to a served certificate",
+            AcmeEvent::OcspError => "Failed to obtain an OCSP response for a served certificate",
+            AcmeEvent::TlsaPublished => "TLSA records for a new certificate were published",
+            AcmeEvent::TlsaPublishFailed => "Failed to publish TLSA records for a new certificate",
+            AcmeEvent::DomainsExcluded => {
+                "Domains that repeatedly failed authorization were excluded from the certificate"
+            }
             AcmeEvent::Error => "An error occurred with ACME",
         }
     }
@@ -1272,6 +1382,10 @@ impl PurgeEvent {
             PurgeEvent::InProgress => "Active purge in progress",
             PurgeEvent::AutoExpunge => "Auto-expunge executed",
             PurgeEvent::TombstoneCleanup => "Tombstone cleanup executed",
+            PurgeEvent::BlobPacked => "Blobs packed",
+            PurgeEvent::BlobPackRewritten => "Blob pack rewritten",
+            PurgeEvent::BlobPackSkipped => "Blob packing skipped",
+            PurgeEvent::MailboxCountersRepaired => "Mailbox counters repaired",
         }
     }
 
@@ -1284,6 +1398,16 @@ impl PurgeEvent {
             PurgeEvent::InProgress => "An active purge is in progress",
             PurgeEvent::AutoExpunge => "Auto-expunge has been executed",
             PurgeEvent::TombstoneCleanup => "Tombstone cleanup has been executed",
+            PurgeEvent::BlobPacked => "Small blobs have been merged into a pack",
+            PurgeEvent::BlobPackRewritten => {
+                "A blob pack has been rewritten to reclaim space used by deleted blobs"
+            }
+            PurgeEvent::BlobPackSkipped => {
+                "Blob packing was skipped for an account with frequent deletions"
+            }
+            PurgeEvent::MailboxCountersRepaired => {
+                "The cached mailbox counters did not match the mailbox contents and were rebuilt"
+            }
         }
     }
 }
@@ -1295,6 +1419,8 @@ impl EvalEvent {
             EvalEvent::Error => "Expression evaluation error",
             EvalEvent::DirectoryNotFound => "Directory not found while evaluating expression",
             EvalEvent::StoreNotFound => "Store not found while evaluating expression",
+            EvalEvent::BudgetExceeded => "Expression evaluation budget exceeded",
+            EvalEvent::FunctionTimeout => "Expression function timed out",
         }
     }
 
@@ -1306,6 +1432,12 @@ impl EvalEvent {
                 "The directory was not found while evaluating the expression"
             }
             EvalEvent::StoreNotFound => "The store was not found while evaluating the expression",
+            EvalEvent::BudgetExceeded => {
+                "An expression exceeded the maximum number of function calls or the evaluation time budget"
+            }
+            EvalEvent::FunctionTimeout => {
+                "An expression function did not complete within the configured timeout"
+            }
         }
     }
 }
@@ -1559,6 +1691,13 @@ impl StoreEvent {
             StoreEvent::CacheHit => "Cache hit",
             StoreEvent::CacheStale => "Cache is stale",
             StoreEvent::CacheUpdate => "Cache update",
+            StoreEvent::IntegrityMismatch => "Message integrity mismatch",
+            StoreEvent::IntegrityUpdate => "Message integrity updated",
+            StoreEvent::IntegrityAudit => "Message integrity audit completed",
+            StoreEvent::DataRead => "Data store read operation",
+            StoreEvent::BitmapRead => "Data store bitmap read operation",
+            StoreEvent::SlowQuery => "Slow data store operation",
+            StoreEvent::QueryTimeout => "Data store operation timed out",
         }
     }
 
@@ -1600,6 +1739,23 @@ impl StoreEvent {
             StoreEvent::CacheHit => "Cache entry found for the account, no update needed",
             StoreEvent::CacheStale => "Cache is too old, rebuilding",
             StoreEvent::CacheUpdate => "Cache updated with latest database changes",
+            StoreEvent::IntegrityMismatch => {
+                "A stored message does not match its recorded content hash or seal"
+            }
+            StoreEvent::IntegrityUpdate => {
+                "The content hash of a stored message was updated after a legitimate transformation"
+            }
+            StoreEvent::IntegrityAudit => {
+                "A scheduled integrity audit of stored messages has completed"
+            }
+            StoreEvent::DataRead => "A data store read operation was executed",
+            StoreEvent::BitmapRead => "A data store bitmap read operation was executed",
+            StoreEvent::SlowQuery => {
+                "A data store operation took longer than the configured slow query threshold"
+            }
+            StoreEvent::QueryTimeout => {
+                "A data store operation exceeded the configured timeout and was cancelled"
+            }
         }
     }
 }
@@ -1613,6 +1769,12 @@ impl MessageIngestEvent {
             MessageIngestEvent::JmapAppend => "Message appended via JMAP",
             MessageIngestEvent::Duplicate => "Skipping duplicate message",
             MessageIngestEvent::Error => "Message ingestion error",
+            MessageIngestEvent::HookDelivered => "Mailbox hook delivered",
+            MessageIngestEvent::HookError => "Mailbox hook error",
+            MessageIngestEvent::HookDeadLetter => "Mailbox hook failed",
+            MessageIngestEvent::EncryptionSkipped => "Message stored unencrypted",
+            MessageIngestEvent::EncryptionFailed => "Message encryption failed",
+            MessageIngestEvent::LoopDetected => "Mail loop detected",
         }
     }
 
@@ -1624,6 +1786,24 @@ impl MessageIngestEvent {
             MessageIngestEvent::JmapAppend => "The message has been appended via JMAP",
             MessageIngestEvent::Duplicate => "The message is a duplicate and has been skipped",
             MessageIngestEvent::Error => "An error occurred while ingesting the message",
+            MessageIngestEvent::HookDelivered => {
+                "The message notification was delivered to the mailbox hook endpoint"
+            }
+            MessageIngestEvent::HookError => {
+                "An error occurred while delivering the message notification to the mailbox hook endpoint"
+            }
+            MessageIngestEvent::HookDeadLetter => {
+                "The message notification could not be delivered to the mailbox hook endpoint and was moved to the dead-letter queue"
+            }
+            MessageIngestEvent::EncryptionSkipped => {
+                "The message exceeds the maximum size for encryption at rest and was stored unencrypted"
+            }
+            MessageIngestEvent::EncryptionFailed => {
+                "The message could not be encrypted at rest and its delivery was deferred"
+            }
+            MessageIngestEvent::LoopDetected => {
+                "The message was rejected because it was already delivered to this recipient"
+            }
         }
     }
 }
@@ -1699,6 +1879,8 @@ impl LimitEvent {
             LimitEvent::BlobQuota => "Blob quota limit reached",
             LimitEvent::TooManyRequests => "Too many requests",
             LimitEvent::TenantQuota => "Tenant quota limit reached",
+            LimitEvent::Maintenance => "Account in maintenance",
+            LimitEvent::IndexBacklog => "Indexing backlog limit reached",
         }
     }
 
@@ -1714,6 +1896,10 @@ impl LimitEvent {
             LimitEvent::BlobQuota => "The blob quota limit has been reached",
             LimitEvent::TooManyRequests => "Too many requests have been made",
             LimitEvent::TenantQuota => "One of the tenant quota limits has been reached",
+            LimitEvent::Maintenance => "The account is in read-only maintenance mode",
+            LimitEvent::IndexBacklog => {
+                "The account has too many messages pending full-text indexing, deliveries are deferred"
+            }
         }
     }
 }
@@ -1752,6 +1938,11 @@ impl AuthEvent {
             AuthEvent::Error => "Authentication error",
             AuthEvent::TokenExpired => "OAuth token expired",
             AuthEvent::ClientRegistration => "OAuth Client registration",
+            AuthEvent::ImpersonationGranted => "Impersonation granted",
+            AuthEvent::ImpersonationRevoked => "Impersonation revoked",
+            AuthEvent::Impersonated => "Impersonated access",
+            AuthEvent::ProtocolDisabled => "Protocol disabled",
+            AuthEvent::ProtocolsChanged => "Enabled protocols changed",
         }
     }
 
@@ -1764,6 +1955,15 @@ impl AuthEvent {
             AuthEvent::Error => "An error occurred with authentication",
             AuthEvent::TokenExpired => "OAuth authentication token has expired",
             AuthEvent::ClientRegistration => "OAuth client successfully registered",
+            AuthEvent::ImpersonationGranted => {
+                "An administrator was granted temporary access to another account"
+            }
+            AuthEvent::ImpersonationRevoked => "An impersonation session was revoked",
+            AuthEvent::Impersonated => "An account was accessed by an administrator on its behalf",
+            AuthEvent::ProtocolDisabled => {
+                "An account attempted to use a protocol that is disabled for it"
+            }
+            AuthEvent::ProtocolsChanged => "The protocols enabled for an account were changed",
         }
     }
 }
@@ -1799,6 +1999,9 @@ impl SecurityEvent {
             SecurityEvent::IpBlocked => "Blocked IP address",
             SecurityEvent::ScanBan => "Banned due to scan",
             SecurityEvent::Unauthorized => "Unauthorized access",
+            SecurityEvent::UnauthorizedSender => "Unauthorized sender address",
+            SecurityEvent::UploadRejected => "Upload rejected",
+            SecurityEvent::ClientCertificateRequired => "Client certificate required",
         }
     }
 
@@ -1814,6 +2017,15 @@ impl SecurityEvent {
             SecurityEvent::LoiterBan => "IP address was banned due to multiple loitering events",
             SecurityEvent::IpBlocked => "Rejected connection from blocked IP address",
             SecurityEvent::Unauthorized => "Account does not have permission to access resource",
+            SecurityEvent::UnauthorizedSender => {
+                "Authenticated user attempted to send from an unauthorized address"
+            }
+            SecurityEvent::UploadRejected => {
+                "An uploaded file was rejected by the upload policy or a content scanner"
+            }
+            SecurityEvent::ClientCertificateRequired => {
+                "A request to an endpoint that requires a TLS client certificate was rejected"
+            }
         }
     }
 }
@@ -1900,3 +2112,25 @@ impl CalendarEvent {
         }
     }
 }
+
+impl EncryptEvent {
+    pub fn description(&self) -> &'static str {
+        match self {
+            EncryptEvent::Success => "Message encrypted",
+            EncryptEvent::Failed => "Message encryption failed",
+            EncryptEvent::AlreadyEncrypted => "Message already encrypted",
+        }
+    }
+
+    pub fn explain(&self) -> &'static str {
+        match self {
+            EncryptEvent::Success => {
+                "A message was encrypted using the account's encryption-at-rest settings"
+            }
+            EncryptEvent::Failed => "An error occurred while encrypting a message",
+            EncryptEvent::AlreadyEncrypted => {
+                "The message was not encrypted because it is already encrypted"
+            }
+        }
+    }
+}
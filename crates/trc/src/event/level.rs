@@ -14,6 +14,8 @@ impl EventType {
             EventType::Store(event) => match event {
                 StoreEvent::DataWrite
                 | StoreEvent::DataIterate
+                | StoreEvent::DataRead
+                | StoreEvent::BitmapRead
                 | StoreEvent::BlobRead
                 | StoreEvent::BlobWrite
                 | StoreEvent::BlobDelete
@@ -45,8 +47,13 @@ impl EventType {
                 | StoreEvent::NotConfigured
                 | StoreEvent::NotSupported
                 | StoreEvent::UnexpectedError
-                | StoreEvent::CryptoError => Level::Error,
-                StoreEvent::BlobMissingMarker | StoreEvent::HttpStoreError => Level::Warn,
+                | StoreEvent::CryptoError
+                | StoreEvent::QueryTimeout
+                | StoreEvent::IntegrityMismatch => Level::Error,
+                StoreEvent::BlobMissingMarker
+                | StoreEvent::HttpStoreError
+                | StoreEvent::SlowQuery => Level::Warn,
+                StoreEvent::IntegrityUpdate | StoreEvent::IntegrityAudit => Level::Info,
             },
             EventType::Jmap(_) => Level::Debug,
             EventType::Imap(event) => match event {
@@ -201,7 +208,11 @@ impl EventType {
                 | SmtpEvent::AuthMechanismNotSupported
                 | SmtpEvent::ExpnDisabled
                 | SmtpEvent::RequestTooLarge
-                | SmtpEvent::TooManyRecipients => Level::Info,
+                | SmtpEvent::TooManyRecipients
+                | SmtpEvent::TlsRequiredNo
+                | SmtpEvent::MtPriorityLowered
+                | SmtpEvent::TestSinkDiscard
+                | SmtpEvent::FromUnauthorized => Level::Info,
                 SmtpEvent::RawInput | SmtpEvent::RawOutput => Level::Trace,
             },
             EventType::Network(event) => match event {
@@ -228,6 +239,8 @@ impl EventType {
                 LimitEvent::BlobQuota => Level::Debug,
                 LimitEvent::TooManyRequests => Level::Warn,
                 LimitEvent::TenantQuota => Level::Info,
+                LimitEvent::Maintenance => Level::Info,
+                LimitEvent::IndexBacklog => Level::Info,
             },
             EventType::Manage(_) => Level::Debug,
             EventType::Auth(cause) => match cause {
@@ -235,7 +248,13 @@ impl EventType {
                 AuthEvent::MissingTotp => Level::Trace,
                 AuthEvent::TooManyAttempts => Level::Warn,
                 AuthEvent::Error => Level::Error,
-                AuthEvent::Success | AuthEvent::ClientRegistration => Level::Info,
+                AuthEvent::Success
+                | AuthEvent::ClientRegistration
+                | AuthEvent::ImpersonationGranted
+                | AuthEvent::ImpersonationRevoked
+                | AuthEvent::Impersonated
+                | AuthEvent::ProtocolDisabled
+                | AuthEvent::ProtocolsChanged => Level::Info,
             },
             EventType::Config(cause) => match cause {
                 ConfigEvent::ParseError
@@ -271,16 +290,22 @@ impl EventType {
             EventType::Purge(event) => match event {
                 PurgeEvent::Started => Level::Debug,
                 PurgeEvent::Finished => Level::Debug,
-                PurgeEvent::Running => Level::Info,
-                PurgeEvent::Error => Level::Error,
-                PurgeEvent::InProgress | PurgeEvent::AutoExpunge | PurgeEvent::TombstoneCleanup => {
-                    Level::Debug
+                PurgeEvent::Running | PurgeEvent::BlobPacked | PurgeEvent::BlobPackRewritten => {
+                    Level::Info
                 }
+                PurgeEvent::Error => Level::Error,
+                PurgeEvent::InProgress
+                | PurgeEvent::AutoExpunge
+                | PurgeEvent::TombstoneCleanup
+                | PurgeEvent::BlobPackSkipped => Level::Debug,
+                PurgeEvent::MailboxCountersRepaired => Level::Warn,
             },
             EventType::Eval(event) => match event {
                 EvalEvent::Error | EvalEvent::StoreNotFound => Level::Debug,
                 EvalEvent::Result => Level::Trace,
-                EvalEvent::DirectoryNotFound => Level::Warn,
+                EvalEvent::DirectoryNotFound
+                | EvalEvent::BudgetExceeded
+                | EvalEvent::FunctionTimeout => Level::Warn,
             },
             EventType::Server(event) => match event {
                 ServerEvent::Startup | ServerEvent::Shutdown | ServerEvent::Licensing => {
@@ -301,7 +326,10 @@ impl EventType {
                 | AcmeEvent::OrderReady
                 | AcmeEvent::OrderValid
                 | AcmeEvent::OrderStart
-                | AcmeEvent::OrderCompleted => Level::Info,
+                | AcmeEvent::OrderCompleted
+                | AcmeEvent::OcspStapled
+                | AcmeEvent::TlsaPublished
+                | AcmeEvent::RenewScheduled => Level::Info,
                 AcmeEvent::Error => Level::Error,
                 AcmeEvent::OrderInvalid
                 | AcmeEvent::AuthError
@@ -309,6 +337,10 @@ impl EventType {
                 | AcmeEvent::TokenNotFound
                 | AcmeEvent::DnsRecordPropagationTimeout
                 | AcmeEvent::TlsAlpnError
+                | AcmeEvent::OcspError
+                | AcmeEvent::TlsaPublishFailed
+                | AcmeEvent::DomainsExcluded
+                | AcmeEvent::RenewFailed
                 | AcmeEvent::DnsRecordCreationFailed => Level::Warn,
                 AcmeEvent::RenewBackoff
                 | AcmeEvent::DnsRecordDeletionFailed
@@ -318,9 +350,12 @@ impl EventType {
                 | AcmeEvent::DnsRecordLookupFailed => Level::Debug,
             },
             EventType::Tls(event) => match event {
-                TlsEvent::Handshake => Level::Info,
+                TlsEvent::Handshake
+                | TlsEvent::ClientFingerprint
+                | TlsEvent::CertificateReloaded
+                | TlsEvent::TicketKeysRotated => Level::Info,
                 TlsEvent::HandshakeError | TlsEvent::CertificateNotFound => Level::Debug,
-                TlsEvent::NotConfigured => Level::Error,
+                TlsEvent::NotConfigured | TlsEvent::CertificateReloadError => Level::Error,
                 TlsEvent::NoCertificatesAvailable | TlsEvent::MultipleCertificatesAvailable => {
                     Level::Warn
                 }
@@ -330,6 +365,7 @@ impl EventType {
                 | SieveEvent::QuotaExceeded
                 | SieveEvent::ListNotFound
                 | SieveEvent::ScriptNotFound
+                | SieveEvent::PreludeError
                 | SieveEvent::MessageTooLarge => Level::Warn,
                 SieveEvent::SendMessage => Level::Info,
                 SieveEvent::UnexpectedError => Level::Error,
@@ -373,10 +409,17 @@ impl EventType {
             },
             EventType::Housekeeper(event) => match event {
                 HousekeeperEvent::Start | HousekeeperEvent::Stop => Level::Info,
-                HousekeeperEvent::Run | HousekeeperEvent::Schedule => Level::Debug,
+                HousekeeperEvent::Run
+                | HousekeeperEvent::Schedule
+                | HousekeeperEvent::Deferred
+                | HousekeeperEvent::Paused => Level::Debug,
+                HousekeeperEvent::BudgetExceeded => Level::Warn,
             },
             EventType::TaskQueue(event) => match event {
-                TaskQueueEvent::Index => Level::Info,
+                TaskQueueEvent::Index
+                | TaskQueueEvent::Encrypt
+                | TaskQueueEvent::BacklogCleared => Level::Info,
+                TaskQueueEvent::BacklogHigh => Level::Warn,
                 TaskQueueEvent::BlobNotFound
                 | TaskQueueEvent::Locked
                 | TaskQueueEvent::BayesTrain
@@ -446,7 +489,9 @@ impl EventType {
                 | DeliveryEvent::StartTlsError
                 | DeliveryEvent::StartTlsDisabled
                 | DeliveryEvent::ImplicitTlsError
-                | DeliveryEvent::DoubleBounce => Level::Info,
+                | DeliveryEvent::DoubleBounce
+                | DeliveryEvent::RequireTlsFailed
+                | DeliveryEvent::ResponseClassified => Level::Info,
                 DeliveryEvent::ConcurrencyLimitExceeded
                 | DeliveryEvent::RateLimitExceeded
                 | DeliveryEvent::MissingOutboundHostname => Level::Warn,
@@ -460,6 +505,11 @@ impl EventType {
                 | DeliveryEvent::MailFrom
                 | DeliveryEvent::RcptTo => Level::Debug,
                 DeliveryEvent::RawInput | DeliveryEvent::RawOutput => Level::Trace,
+                DeliveryEvent::WarmupLimitExceeded
+                | DeliveryEvent::WarmupPhaseChanged
+                | DeliveryEvent::WarmupResumed
+                | DeliveryEvent::WarmupCompleted => Level::Info,
+                DeliveryEvent::WarmupPaused => Level::Warn,
             },
             EventType::Queue(event) => match event {
                 QueueEvent::BackPressure => Level::Warn,
@@ -471,7 +521,9 @@ impl EventType {
                 | QueueEvent::RateLimitExceeded
                 | QueueEvent::ConcurrencyLimitExceeded
                 | QueueEvent::Rescheduled
-                | QueueEvent::QuotaExceeded => Level::Info,
+                | QueueEvent::QuotaExceeded
+                | QueueEvent::Quarantined
+                | QueueEvent::QuarantineReleased => Level::Info,
                 QueueEvent::Locked | QueueEvent::BlobNotFound => Level::Debug,
             },
             EventType::TlsRpt(event) => match event {
@@ -518,7 +570,10 @@ impl EventType {
                 | OutgoingReportEvent::UnauthorizedReportingAddress
                 | OutgoingReportEvent::ReportingAddressValidationError
                 | OutgoingReportEvent::SubmissionError
-                | OutgoingReportEvent::NoRecipientsFound => Level::Info,
+                | OutgoingReportEvent::NoRecipientsFound
+                | OutgoingReportEvent::SystemMessage
+                | OutgoingReportEvent::SystemMessageRateLimited
+                | OutgoingReportEvent::SystemMessageSuppressed => Level::Info,
             },
             EventType::Telemetry(_) => Level::Warn,
             EventType::MessageIngest(event) => match event {
@@ -526,8 +581,14 @@ impl EventType {
                 | MessageIngestEvent::Spam
                 | MessageIngestEvent::ImapAppend
                 | MessageIngestEvent::JmapAppend
-                | MessageIngestEvent::Duplicate => Level::Info,
+                | MessageIngestEvent::Duplicate
+                | MessageIngestEvent::HookDelivered
+                | MessageIngestEvent::EncryptionSkipped => Level::Info,
                 MessageIngestEvent::Error => Level::Error,
+                MessageIngestEvent::HookError => Level::Debug,
+                MessageIngestEvent::HookDeadLetter
+                | MessageIngestEvent::LoopDetected
+                | MessageIngestEvent::EncryptionFailed => Level::Warn,
             },
             EventType::Security(_) => Level::Info,
             EventType::Ai(event) => match event {
@@ -536,6 +597,10 @@ impl EventType {
             },
             EventType::WebDav(_) => Level::Debug,
             EventType::Calendar(CalendarEvent::RuleExpansionError) => Level::Debug,
+            EventType::Encrypt(event) => match event {
+                EncryptEvent::Success | EncryptEvent::AlreadyEncrypted => Level::Debug,
+                EncryptEvent::Failed => Level::Warn,
+            },
         }
     }
 }
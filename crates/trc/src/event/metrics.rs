@@ -36,6 +36,13 @@ impl MetricType {
             Self::QueueCount => "queue.count",
             Self::UserCount => "user.count",
             Self::DomainCount => "domain.count",
+            Self::BlobPackedCount => "store.blob-packed-count",
+            Self::BlobPackCount => "store.blob-pack-count",
+            Self::BlobPackWastedSize => "store.blob-pack-wasted-size",
+            Self::BlobPackSavedObjects => "store.blob-pack-saved-objects",
+            Self::StoreValueReadTime => "store.data-get-time",
+            Self::StoreBitmapReadTime => "store.bitmap-read-time",
+            Self::IndexQueueCount => "index-queue.count",
         }
     }
 
@@ -68,6 +75,13 @@ impl MetricType {
             Self::QueueCount => "Total number of messages in the queue",
             Self::UserCount => "Total number of users",
             Self::DomainCount => "Total number of domains",
+            Self::BlobPackedCount => "Total number of blobs stored in packs",
+            Self::BlobPackCount => "Total number of blob packs",
+            Self::BlobPackWastedSize => "Space used by deleted blobs in packs",
+            Self::BlobPackSavedObjects => "Blob store objects saved by packing",
+            Self::StoreValueReadTime => "Data store key lookup time",
+            Self::StoreBitmapReadTime => "Data store bitmap read time",
+            Self::IndexQueueCount => "Total number of messages pending full-text indexing",
         }
     }
 
@@ -79,6 +93,8 @@ impl MetricType {
             | Self::DeliveryTime
             | Self::StoreReadTime
             | Self::StoreWriteTime
+            | Self::StoreValueReadTime
+            | Self::StoreBitmapReadTime
             | Self::BlobReadTime
             | Self::BlobWriteTime
             | Self::DnsLookupTime
@@ -90,16 +106,20 @@ impl MetricType {
             Self::MessageSize
             | Self::MessageAuthSize
             | Self::ReportOutgoingSize
-            | Self::ServerMemory => "bytes",
+            | Self::ServerMemory
+            | Self::BlobPackWastedSize => "bytes",
             Self::HttpActiveConnections
             | Self::ImapActiveConnections
             | Self::Pop3ActiveConnections
             | Self::SmtpActiveConnections
             | Self::SieveActiveConnections
             | Self::DeliveryActiveConnections => "connections",
-            Self::QueueCount => "messages",
+            Self::QueueCount | Self::IndexQueueCount => "messages",
             Self::UserCount => "users",
             Self::DomainCount => "domains",
+            Self::BlobPackedCount => "blobs",
+            Self::BlobPackCount => "packs",
+            Self::BlobPackSavedObjects => "objects",
         }
     }
 
@@ -132,6 +152,13 @@ impl MetricType {
             Self::QueueCount => 24,
             Self::UserCount => 25,
             Self::DomainCount => 26,
+            Self::BlobPackedCount => 27,
+            Self::BlobPackCount => 28,
+            Self::BlobPackWastedSize => 29,
+            Self::StoreValueReadTime => 30,
+            Self::StoreBitmapReadTime => 31,
+            Self::IndexQueueCount => 32,
+            Self::BlobPackSavedObjects => 33,
         }
     }
 
@@ -164,6 +191,13 @@ impl MetricType {
             24 => Some(Self::QueueCount),
             25 => Some(Self::UserCount),
             26 => Some(Self::DomainCount),
+            27 => Some(Self::BlobPackedCount),
+            28 => Some(Self::BlobPackCount),
+            29 => Some(Self::BlobPackWastedSize),
+            30 => Some(Self::StoreValueReadTime),
+            31 => Some(Self::StoreBitmapReadTime),
+            32 => Some(Self::IndexQueueCount),
+            33 => Some(Self::BlobPackSavedObjects),
             _ => None,
         }
     }
@@ -197,6 +231,13 @@ impl MetricType {
             "queue.count" => Some(Self::QueueCount),
             "user.count" => Some(Self::UserCount),
             "domain.count" => Some(Self::DomainCount),
+            "store.blob-packed-count" => Some(Self::BlobPackedCount),
+            "store.blob-pack-count" => Some(Self::BlobPackCount),
+            "store.blob-pack-wasted-size" => Some(Self::BlobPackWastedSize),
+            "store.blob-pack-saved-objects" => Some(Self::BlobPackSavedObjects),
+            "store.data-get-time" => Some(Self::StoreValueReadTime),
+            "store.bitmap-read-time" => Some(Self::StoreBitmapReadTime),
+            "index-queue.count" => Some(Self::IndexQueueCount),
             _ => None,
         }
     }
@@ -230,6 +271,13 @@ impl MetricType {
             Self::QueueCount,
             Self::UserCount,
             Self::DomainCount,
+            Self::BlobPackedCount,
+            Self::BlobPackCount,
+            Self::BlobPackWastedSize,
+            Self::StoreValueReadTime,
+            Self::StoreBitmapReadTime,
+            Self::IndexQueueCount,
+            Self::BlobPackSavedObjects,
         ]
     }
 }
@@ -37,6 +37,10 @@ static STORE_DATA_READ_TIME: AtomicHistogram<12> =
     AtomicHistogram::<10>::new_short_durations(MetricType::StoreReadTime);
 static STORE_DATA_WRITE_TIME: AtomicHistogram<12> =
     AtomicHistogram::<10>::new_short_durations(MetricType::StoreWriteTime);
+static STORE_VALUE_READ_TIME: AtomicHistogram<12> =
+    AtomicHistogram::<10>::new_short_durations(MetricType::StoreValueReadTime);
+static STORE_BITMAP_READ_TIME: AtomicHistogram<12> =
+    AtomicHistogram::<10>::new_short_durations(MetricType::StoreBitmapReadTime);
 static STORE_BLOB_READ_TIME: AtomicHistogram<12> =
     AtomicHistogram::<10>::new_short_durations(MetricType::BlobReadTime);
 static STORE_BLOB_WRITE_TIME: AtomicHistogram<12> =
@@ -47,8 +51,13 @@ static DNS_LOOKUP_TIME: AtomicHistogram<12> =
 
 static SERVER_MEMORY: AtomicGauge = AtomicGauge::new(MetricType::ServerMemory);
 static QUEUE_COUNT: AtomicGauge = AtomicGauge::new(MetricType::QueueCount);
+static INDEX_QUEUE_COUNT: AtomicGauge = AtomicGauge::new(MetricType::IndexQueueCount);
 static USER_COUNT: AtomicGauge = AtomicGauge::new(MetricType::UserCount);
 static DOMAIN_COUNT: AtomicGauge = AtomicGauge::new(MetricType::DomainCount);
+static BLOB_PACKED_COUNT: AtomicGauge = AtomicGauge::new(MetricType::BlobPackedCount);
+static BLOB_PACK_COUNT: AtomicGauge = AtomicGauge::new(MetricType::BlobPackCount);
+static BLOB_PACK_WASTED_SIZE: AtomicGauge = AtomicGauge::new(MetricType::BlobPackWastedSize);
+static BLOB_PACK_SAVED_OBJECTS: AtomicGauge = AtomicGauge::new(MetricType::BlobPackSavedObjects);
 
 const CONN_SMTP_IN: usize = 0;
 const CONN_SMTP_OUT: usize = 1;
@@ -193,6 +202,12 @@ impl Collector {
             EventType::Store(StoreEvent::DataIterate) => {
                 STORE_DATA_READ_TIME.observe(elapsed);
             }
+            EventType::Store(StoreEvent::DataRead) => {
+                STORE_VALUE_READ_TIME.observe(elapsed);
+            }
+            EventType::Store(StoreEvent::BitmapRead) => {
+                STORE_BITMAP_READ_TIME.observe(elapsed);
+            }
 
             _ => {}
         }
@@ -226,9 +241,26 @@ impl Collector {
     }
 
     pub fn collect_gauges(is_enterprise: bool) -> impl Iterator<Item = &'static AtomicGauge> {
-        static E_GAUGES: &[&AtomicGauge] =
-            &[&SERVER_MEMORY, &QUEUE_COUNT, &USER_COUNT, &DOMAIN_COUNT];
-        static C_GAUGES: &[&AtomicGauge] = &[&SERVER_MEMORY, &USER_COUNT, &DOMAIN_COUNT];
+        static E_GAUGES: &[&AtomicGauge] = &[
+            &SERVER_MEMORY,
+            &QUEUE_COUNT,
+            &INDEX_QUEUE_COUNT,
+            &USER_COUNT,
+            &DOMAIN_COUNT,
+            &BLOB_PACKED_COUNT,
+            &BLOB_PACK_COUNT,
+            &BLOB_PACK_WASTED_SIZE,
+            &BLOB_PACK_SAVED_OBJECTS,
+        ];
+        static C_GAUGES: &[&AtomicGauge] = &[
+            &SERVER_MEMORY,
+            &USER_COUNT,
+            &DOMAIN_COUNT,
+            &BLOB_PACKED_COUNT,
+            &BLOB_PACK_COUNT,
+            &BLOB_PACK_WASTED_SIZE,
+            &BLOB_PACK_SAVED_OBJECTS,
+        ];
 
         if is_enterprise { E_GAUGES } else { C_GAUGES }
             .iter()
@@ -248,6 +280,8 @@ impl Collector {
             &MESSAGE_OUT_REPORT_SIZE,
             &STORE_DATA_READ_TIME,
             &STORE_DATA_WRITE_TIME,
+            &STORE_VALUE_READ_TIME,
+            &STORE_BITMAP_READ_TIME,
             &STORE_BLOB_READ_TIME,
             &STORE_BLOB_WRITE_TIME,
             &DNS_LOOKUP_TIME,
@@ -287,9 +321,12 @@ impl Collector {
                 CONNECTION_METRICS[CONN_SMTP_OUT].active_connections.get() as f64
             }
             MetricType::QueueCount => QUEUE_COUNT.get() as f64,
+            MetricType::IndexQueueCount => INDEX_QUEUE_COUNT.get() as f64,
             MetricType::ReportOutgoingSize => MESSAGE_OUT_REPORT_SIZE.average(),
             MetricType::StoreReadTime => STORE_DATA_READ_TIME.average(),
             MetricType::StoreWriteTime => STORE_DATA_WRITE_TIME.average(),
+            MetricType::StoreValueReadTime => STORE_VALUE_READ_TIME.average(),
+            MetricType::StoreBitmapReadTime => STORE_BITMAP_READ_TIME.average(),
             MetricType::BlobReadTime => STORE_BLOB_READ_TIME.average(),
             MetricType::BlobWriteTime => STORE_BLOB_WRITE_TIME.average(),
             MetricType::DnsLookupTime => DNS_LOOKUP_TIME.average(),
@@ -315,6 +352,10 @@ impl Collector {
             MetricType::SieveRequestTime => CONNECTION_METRICS[CONN_SIEVE].elapsed.average(),
             MetricType::UserCount => USER_COUNT.get() as f64,
             MetricType::DomainCount => DOMAIN_COUNT.get() as f64,
+            MetricType::BlobPackedCount => BLOB_PACKED_COUNT.get() as f64,
+            MetricType::BlobPackCount => BLOB_PACK_COUNT.get() as f64,
+            MetricType::BlobPackWastedSize => BLOB_PACK_WASTED_SIZE.get() as f64,
+            MetricType::BlobPackSavedObjects => BLOB_PACK_SAVED_OBJECTS.get() as f64,
         }
     }
 
@@ -322,8 +363,13 @@ impl Collector {
         match metric_type {
             MetricType::ServerMemory => SERVER_MEMORY.set(value),
             MetricType::QueueCount => QUEUE_COUNT.set(value),
+            MetricType::IndexQueueCount => INDEX_QUEUE_COUNT.set(value),
             MetricType::UserCount => USER_COUNT.set(value),
             MetricType::DomainCount => DOMAIN_COUNT.set(value),
+            MetricType::BlobPackedCount => BLOB_PACKED_COUNT.set(value),
+            MetricType::BlobPackCount => BLOB_PACK_COUNT.set(value),
+            MetricType::BlobPackWastedSize => BLOB_PACK_WASTED_SIZE.set(value),
+            MetricType::BlobPackSavedObjects => BLOB_PACK_SAVED_OBJECTS.set(value),
             _ => {}
         }
     }
@@ -427,6 +473,10 @@ impl EventType {
                 | AcmeEvent::TokenNotFound
                 | AcmeEvent::DnsRecordLookupFailed
                 | AcmeEvent::OrderInvalid
+                | AcmeEvent::OcspError
+                | AcmeEvent::TlsaPublishFailed
+                | AcmeEvent::DomainsExcluded
+                | AcmeEvent::RenewFailed
                 | AcmeEvent::Error,
             ) => true,
             EventType::Store(
@@ -452,8 +502,12 @@ impl EventType {
                 | StoreEvent::UnexpectedError
                 | StoreEvent::CryptoError
                 | StoreEvent::BlobMissingMarker
+                | StoreEvent::SlowQuery
+                | StoreEvent::QueryTimeout
                 | StoreEvent::DataWrite
                 | StoreEvent::DataIterate
+                | StoreEvent::DataRead
+                | StoreEvent::BitmapRead
                 | StoreEvent::BlobRead
                 | StoreEvent::BlobWrite
                 | StoreEvent::BlobDelete
@@ -574,7 +628,8 @@ impl EventType {
                 | SieveEvent::RuntimeError
                 | SieveEvent::UnexpectedError
                 | SieveEvent::NotSupported
-                | SieveEvent::QuotaExceeded,
+                | SieveEvent::QuotaExceeded
+                | SieveEvent::PreludeError,
             ) => true,
             EventType::Spam(
                 SpamEvent::PyzorError
@@ -585,6 +640,7 @@ impl EventType {
                 | SpamEvent::DnsblError,
             ) => true,
             EventType::PushSubscription(_) => true,
+            EventType::Encrypt(_) => true,
             EventType::Cluster(
                 ClusterEvent::SubscriberError
                 | ClusterEvent::PublisherError
@@ -594,7 +650,8 @@ impl EventType {
             EventType::TaskQueue(
                 TaskQueueEvent::Index
                 | TaskQueueEvent::BlobNotFound
-                | TaskQueueEvent::MetadataNotFound,
+                | TaskQueueEvent::MetadataNotFound
+                | TaskQueueEvent::BacklogHigh,
             ) => true,
             EventType::Milter(
                 MilterEvent::ActionAccept
@@ -189,6 +189,7 @@ pub enum EventType {
     Ai(AiEvent),
     WebDav(WebDavEvent),
     Calendar(CalendarEvent),
+    Encrypt(EncryptEvent),
 }
 
 #[event_type]
@@ -210,6 +211,9 @@ pub enum SecurityEvent {
     LoiterBan,
     IpBlocked,
     Unauthorized,
+    UnauthorizedSender,
+    UploadRejected,
+    ClientCertificateRequired,
 }
 
 #[event_type]
@@ -232,6 +236,9 @@ pub enum HousekeeperEvent {
     Stop,
     Schedule,
     Run,
+    Deferred,
+    Paused,
+    BudgetExceeded,
 }
 
 #[event_type]
@@ -241,6 +248,9 @@ pub enum TaskQueueEvent {
     Locked,
     BlobNotFound,
     MetadataNotFound,
+    Encrypt,
+    BacklogHigh,
+    BacklogCleared,
 }
 
 #[event_type]
@@ -431,6 +441,10 @@ pub enum SmtpEvent {
     UnsupportedParameter,
     SyntaxError,
     RequestTooLarge,
+    TlsRequiredNo,
+    MtPriorityLowered,
+    TestSinkDiscard,
+    FromUnauthorized,
 }
 
 #[event_type]
@@ -473,6 +487,13 @@ pub enum DeliveryEvent {
     DsnPermFail,
     RawInput,
     RawOutput,
+    WarmupLimitExceeded,
+    WarmupPhaseChanged,
+    WarmupPaused,
+    WarmupResumed,
+    WarmupCompleted,
+    RequireTlsFailed,
+    ResponseClassified,
 }
 
 #[event_type]
@@ -489,6 +510,8 @@ pub enum QueueEvent {
     ConcurrencyLimitExceeded,
     QuotaExceeded,
     BackPressure,
+    Quarantined,
+    QuarantineReleased,
 }
 
 #[event_type]
@@ -527,6 +550,9 @@ pub enum OutgoingReportEvent {
     SubmissionError,
     NoRecipientsFound,
     Locked,
+    SystemMessage,
+    SystemMessageRateLimited,
+    SystemMessageSuppressed,
 }
 
 #[event_type]
@@ -624,6 +650,7 @@ pub enum SieveEvent {
     UnexpectedError,
     NotSupported,
     QuotaExceeded,
+    PreludeError,
 }
 
 #[event_type]
@@ -634,6 +661,10 @@ pub enum TlsEvent {
     CertificateNotFound,
     NoCertificatesAvailable,
     MultipleCertificatesAvailable,
+    ClientFingerprint,
+    CertificateReloaded,
+    CertificateReloadError,
+    TicketKeysRotated,
 }
 
 #[event_type]
@@ -689,6 +720,8 @@ pub enum AcmeEvent {
     OrderValid,
     OrderInvalid,
     RenewBackoff,
+    RenewScheduled,
+    RenewFailed,
     DnsRecordCreated,
     DnsRecordCreationFailed,
     DnsRecordDeletionFailed,
@@ -701,6 +734,11 @@ pub enum AcmeEvent {
     TlsAlpnReceived,
     TlsAlpnError,
     TokenNotFound,
+    OcspStapled,
+    OcspError,
+    TlsaPublished,
+    TlsaPublishFailed,
+    DomainsExcluded,
     Error,
 }
 
@@ -713,6 +751,10 @@ pub enum PurgeEvent {
     InProgress,
     AutoExpunge,
     TombstoneCleanup,
+    BlobPacked,
+    BlobPackRewritten,
+    BlobPackSkipped,
+    MailboxCountersRepaired,
 }
 
 #[event_type]
@@ -721,6 +763,8 @@ pub enum EvalEvent {
     Error,
     DirectoryNotFound,
     StoreNotFound,
+    BudgetExceeded,
+    FunctionTimeout,
 }
 
 #[event_type]
@@ -839,6 +883,7 @@ pub enum StoreEvent {
     UnexpectedError,
     CryptoError,
     HttpStoreError,
+    QueryTimeout,
 
     // Caching
     CacheMiss,
@@ -848,10 +893,13 @@ pub enum StoreEvent {
 
     // Warnings
     BlobMissingMarker,
+    SlowQuery,
 
     // Traces
     DataWrite,
     DataIterate,
+    DataRead,
+    BitmapRead,
     BlobRead,
     BlobWrite,
     BlobDelete,
@@ -859,6 +907,11 @@ pub enum StoreEvent {
     LdapQuery,
     LdapBind,
     HttpStoreFetch,
+
+    // Integrity
+    IntegrityMismatch,
+    IntegrityUpdate,
+    IntegrityAudit,
 }
 
 #[event_type]
@@ -870,6 +923,12 @@ pub enum MessageIngestEvent {
     JmapAppend,
     Duplicate,
     Error,
+    HookDelivered,
+    HookError,
+    HookDeadLetter,
+    EncryptionSkipped,
+    EncryptionFailed,
+    LoopDetected,
 }
 
 #[event_type]
@@ -917,6 +976,8 @@ pub enum LimitEvent {
     BlobQuota,
     TenantQuota,
     TooManyRequests,
+    Maintenance,
+    IndexBacklog,
 }
 
 #[event_type]
@@ -938,6 +999,11 @@ pub enum AuthEvent {
     TooManyAttempts,
     ClientRegistration,
     Error,
+    ImpersonationGranted,
+    ImpersonationRevoked,
+    Impersonated,
+    ProtocolDisabled,
+    ProtocolsChanged,
 }
 
 #[event_type]
@@ -985,6 +1051,13 @@ pub enum CalendarEvent {
     RuleExpansionError,
 }
 
+#[event_type]
+pub enum EncryptEvent {
+    Success,
+    Failed,
+    AlreadyEncrypted,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MetricType {
     ServerMemory,
@@ -996,9 +1069,12 @@ pub enum MetricType {
     DeliveryTime,
     DeliveryActiveConnections,
     QueueCount,
+    IndexQueueCount,
     ReportOutgoingSize,
     StoreReadTime,
     StoreWriteTime,
+    StoreValueReadTime,
+    StoreBitmapReadTime,
     BlobReadTime,
     BlobWriteTime,
     DnsLookupTime,
@@ -1014,6 +1090,10 @@ pub enum MetricType {
     SieveRequestTime,
     UserCount,
     DomainCount,
+    BlobPackedCount,
+    BlobPackCount,
+    BlobPackWastedSize,
+    BlobPackSavedObjects,
 }
 
 pub const TOTAL_EVENT_COUNT: usize = total_event_count!();
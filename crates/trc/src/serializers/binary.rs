@@ -887,6 +887,72 @@ impl EventType {
             EventType::Store(StoreEvent::CacheHit) => 51,
             EventType::Store(StoreEvent::CacheStale) => 52,
             EventType::Store(StoreEvent::CacheUpdate) => 577,
+            EventType::Delivery(DeliveryEvent::WarmupLimitExceeded) => 578,
+            EventType::Delivery(DeliveryEvent::WarmupPhaseChanged) => 579,
+            EventType::Delivery(DeliveryEvent::WarmupPaused) => 580,
+            EventType::Delivery(DeliveryEvent::WarmupResumed) => 581,
+            EventType::Delivery(DeliveryEvent::WarmupCompleted) => 582,
+            EventType::Tls(TlsEvent::ClientFingerprint) => 583,
+            EventType::MessageIngest(MessageIngestEvent::HookDelivered) => 584,
+            EventType::MessageIngest(MessageIngestEvent::HookError) => 585,
+            EventType::MessageIngest(MessageIngestEvent::HookDeadLetter) => 586,
+            EventType::Delivery(DeliveryEvent::RequireTlsFailed) => 587,
+            EventType::Smtp(SmtpEvent::TlsRequiredNo) => 588,
+            EventType::Purge(PurgeEvent::BlobPacked) => 589,
+            EventType::Purge(PurgeEvent::BlobPackRewritten) => 590,
+            EventType::Purge(PurgeEvent::BlobPackSkipped) => 591,
+            EventType::Eval(EvalEvent::BudgetExceeded) => 592,
+            EventType::Eval(EvalEvent::FunctionTimeout) => 593,
+            EventType::Purge(PurgeEvent::MailboxCountersRepaired) => 594,
+            EventType::Smtp(SmtpEvent::MtPriorityLowered) => 595,
+            EventType::TaskQueue(TaskQueueEvent::Encrypt) => 596,
+            EventType::Smtp(SmtpEvent::TestSinkDiscard) => 597,
+            EventType::Smtp(SmtpEvent::FromUnauthorized) => 598,
+            EventType::Security(SecurityEvent::UnauthorizedSender) => 599,
+            EventType::Store(StoreEvent::IntegrityMismatch) => 600,
+            EventType::Store(StoreEvent::IntegrityUpdate) => 601,
+            EventType::Store(StoreEvent::IntegrityAudit) => 602,
+            EventType::Security(SecurityEvent::UploadRejected) => 603,
+            EventType::OutgoingReport(OutgoingReportEvent::SystemMessage) => 604,
+            EventType::OutgoingReport(OutgoingReportEvent::SystemMessageRateLimited) => 605,
+            EventType::OutgoingReport(OutgoingReportEvent::SystemMessageSuppressed) => 606,
+            EventType::MessageIngest(MessageIngestEvent::EncryptionSkipped) => 607,
+            EventType::Limit(LimitEvent::Maintenance) => 608,
+            EventType::Acme(AcmeEvent::OcspStapled) => 609,
+            EventType::Acme(AcmeEvent::OcspError) => 610,
+            EventType::Acme(AcmeEvent::RenewScheduled) => 611,
+            EventType::Acme(AcmeEvent::RenewFailed) => 612,
+            EventType::Sieve(SieveEvent::PreludeError) => 613,
+            EventType::Store(StoreEvent::DataRead) => 614,
+            EventType::Store(StoreEvent::BitmapRead) => 615,
+            EventType::Store(StoreEvent::SlowQuery) => 616,
+            EventType::Store(StoreEvent::QueryTimeout) => 617,
+            EventType::TaskQueue(TaskQueueEvent::BacklogHigh) => 618,
+            EventType::TaskQueue(TaskQueueEvent::BacklogCleared) => 619,
+            EventType::Limit(LimitEvent::IndexBacklog) => 620,
+            EventType::Security(SecurityEvent::ClientCertificateRequired) => 621,
+            EventType::MessageIngest(MessageIngestEvent::LoopDetected) => 622,
+            EventType::Delivery(DeliveryEvent::ResponseClassified) => 623,
+            EventType::Auth(AuthEvent::ImpersonationGranted) => 624,
+            EventType::Auth(AuthEvent::ImpersonationRevoked) => 625,
+            EventType::Auth(AuthEvent::Impersonated) => 626,
+            EventType::MessageIngest(MessageIngestEvent::EncryptionFailed) => 627,
+            EventType::Tls(TlsEvent::CertificateReloaded) => 628,
+            EventType::Tls(TlsEvent::CertificateReloadError) => 629,
+            EventType::Auth(AuthEvent::ProtocolDisabled) => 630,
+            EventType::Auth(AuthEvent::ProtocolsChanged) => 631,
+            EventType::Encrypt(EncryptEvent::Success) => 632,
+            EventType::Encrypt(EncryptEvent::Failed) => 633,
+            EventType::Encrypt(EncryptEvent::AlreadyEncrypted) => 634,
+            EventType::Housekeeper(HousekeeperEvent::Deferred) => 635,
+            EventType::Housekeeper(HousekeeperEvent::Paused) => 636,
+            EventType::Housekeeper(HousekeeperEvent::BudgetExceeded) => 637,
+            EventType::Acme(AcmeEvent::TlsaPublished) => 638,
+            EventType::Acme(AcmeEvent::TlsaPublishFailed) => 639,
+            EventType::Acme(AcmeEvent::DomainsExcluded) => 640,
+            EventType::Tls(TlsEvent::TicketKeysRotated) => 641,
+            EventType::Queue(QueueEvent::Quarantined) => 642,
+            EventType::Queue(QueueEvent::QuarantineReleased) => 643,
         }
     }
 
@@ -1510,6 +1576,84 @@ impl EventType {
             51 => Some(EventType::Store(StoreEvent::CacheHit)),
             52 => Some(EventType::Store(StoreEvent::CacheStale)),
             577 => Some(EventType::Store(StoreEvent::CacheUpdate)),
+            578 => Some(EventType::Delivery(DeliveryEvent::WarmupLimitExceeded)),
+            579 => Some(EventType::Delivery(DeliveryEvent::WarmupPhaseChanged)),
+            580 => Some(EventType::Delivery(DeliveryEvent::WarmupPaused)),
+            581 => Some(EventType::Delivery(DeliveryEvent::WarmupResumed)),
+            582 => Some(EventType::Delivery(DeliveryEvent::WarmupCompleted)),
+            583 => Some(EventType::Tls(TlsEvent::ClientFingerprint)),
+            584 => Some(EventType::MessageIngest(MessageIngestEvent::HookDelivered)),
+            585 => Some(EventType::MessageIngest(MessageIngestEvent::HookError)),
+            586 => Some(EventType::MessageIngest(MessageIngestEvent::HookDeadLetter)),
+            587 => Some(EventType::Delivery(DeliveryEvent::RequireTlsFailed)),
+            588 => Some(EventType::Smtp(SmtpEvent::TlsRequiredNo)),
+            589 => Some(EventType::Purge(PurgeEvent::BlobPacked)),
+            590 => Some(EventType::Purge(PurgeEvent::BlobPackRewritten)),
+            591 => Some(EventType::Purge(PurgeEvent::BlobPackSkipped)),
+            592 => Some(EventType::Eval(EvalEvent::BudgetExceeded)),
+            593 => Some(EventType::Eval(EvalEvent::FunctionTimeout)),
+            594 => Some(EventType::Purge(PurgeEvent::MailboxCountersRepaired)),
+            595 => Some(EventType::Smtp(SmtpEvent::MtPriorityLowered)),
+            596 => Some(EventType::TaskQueue(TaskQueueEvent::Encrypt)),
+            597 => Some(EventType::Smtp(SmtpEvent::TestSinkDiscard)),
+            598 => Some(EventType::Smtp(SmtpEvent::FromUnauthorized)),
+            599 => Some(EventType::Security(SecurityEvent::UnauthorizedSender)),
+            600 => Some(EventType::Store(StoreEvent::IntegrityMismatch)),
+            601 => Some(EventType::Store(StoreEvent::IntegrityUpdate)),
+            602 => Some(EventType::Store(StoreEvent::IntegrityAudit)),
+            603 => Some(EventType::Security(SecurityEvent::UploadRejected)),
+            604 => Some(EventType::OutgoingReport(
+                OutgoingReportEvent::SystemMessage,
+            )),
+            605 => Some(EventType::OutgoingReport(
+                OutgoingReportEvent::SystemMessageRateLimited,
+            )),
+            606 => Some(EventType::OutgoingReport(
+                OutgoingReportEvent::SystemMessageSuppressed,
+            )),
+            607 => Some(EventType::MessageIngest(
+                MessageIngestEvent::EncryptionSkipped,
+            )),
+            608 => Some(EventType::Limit(LimitEvent::Maintenance)),
+            609 => Some(EventType::Acme(AcmeEvent::OcspStapled)),
+            610 => Some(EventType::Acme(AcmeEvent::OcspError)),
+            611 => Some(EventType::Acme(AcmeEvent::RenewScheduled)),
+            612 => Some(EventType::Acme(AcmeEvent::RenewFailed)),
+            613 => Some(EventType::Sieve(SieveEvent::PreludeError)),
+            614 => Some(EventType::Store(StoreEvent::DataRead)),
+            615 => Some(EventType::Store(StoreEvent::BitmapRead)),
+            616 => Some(EventType::Store(StoreEvent::SlowQuery)),
+            617 => Some(EventType::Store(StoreEvent::QueryTimeout)),
+            618 => Some(EventType::TaskQueue(TaskQueueEvent::BacklogHigh)),
+            619 => Some(EventType::TaskQueue(TaskQueueEvent::BacklogCleared)),
+            620 => Some(EventType::Limit(LimitEvent::IndexBacklog)),
+            621 => Some(EventType::Security(
+                SecurityEvent::ClientCertificateRequired,
+            )),
+            622 => Some(EventType::MessageIngest(MessageIngestEvent::LoopDetected)),
+            623 => Some(EventType::Delivery(DeliveryEvent::ResponseClassified)),
+            624 => Some(EventType::Auth(AuthEvent::ImpersonationGranted)),
+            625 => Some(EventType::Auth(AuthEvent::ImpersonationRevoked)),
+            626 => Some(EventType::Auth(AuthEvent::Impersonated)),
+            627 => Some(EventType::MessageIngest(
+                MessageIngestEvent::EncryptionFailed,
+            )),
+            628 => Some(EventType::Tls(TlsEvent::CertificateReloaded)),
+            629 => Some(EventType::Tls(TlsEvent::CertificateReloadError)),
+            630 => Some(EventType::Auth(AuthEvent::ProtocolDisabled)),
+            631 => Some(EventType::Auth(AuthEvent::ProtocolsChanged)),
+            632 => Some(EventType::Encrypt(EncryptEvent::Success)),
+            633 => Some(EventType::Encrypt(EncryptEvent::Failed)),
+            634 => Some(EventType::Encrypt(EncryptEvent::AlreadyEncrypted)),
+            635 => Some(EventType::Housekeeper(HousekeeperEvent::Deferred)),
+            636 => Some(EventType::Housekeeper(HousekeeperEvent::Paused)),
+            637 => Some(EventType::Housekeeper(HousekeeperEvent::BudgetExceeded)),
+            638 => Some(EventType::Acme(AcmeEvent::TlsaPublished)),
+            639 => Some(EventType::Acme(AcmeEvent::TlsaPublishFailed)),
+            640 => Some(EventType::Acme(AcmeEvent::DomainsExcluded)),
+            641 => Some(EventType::Tls(TlsEvent::TicketKeysRotated)),
+            642 => Some(EventType::Queue(QueueEvent::Quarantined)),
+            643 => Some(EventType::Queue(QueueEvent::QuarantineReleased)),
             _ => None,
         }
     }
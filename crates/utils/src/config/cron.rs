@@ -6,7 +6,7 @@
 
 use std::time::Duration;
 
-use chrono::{Datelike, Local, TimeDelta, TimeZone, Timelike};
+use chrono::{DateTime, Datelike, Local, TimeDelta, TimeZone, Timelike};
 
 use super::utils::ParseValue;
 
@@ -129,3 +129,177 @@ impl Default for SimpleCron {
         SimpleCron::Hour { minute: 0 }
     }
 }
+
+// A recurring daily time range such as "mon-fri 01:00-05:00". Ranges that end
+// before they start span midnight and belong to the day they start on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+    days: u8,
+    start: u32,
+    end: u32,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: &DateTime<Local>) -> bool {
+        let minute = time.hour() * 60 + time.minute();
+        let day = time.weekday().num_days_from_monday();
+        if self.start < self.end {
+            self.has_day(day) && (self.start..self.end).contains(&minute)
+        } else {
+            (self.has_day(day) && minute >= self.start)
+                || (self.has_day((day + 6) % 7) && minute < self.end)
+        }
+    }
+
+    // Returns the start and end of the occurrences of this window that begin
+    // between the day before `from` and the given number of days after it
+    pub fn occurrences(
+        &self,
+        from: DateTime<Local>,
+        days: i64,
+    ) -> impl Iterator<Item = (DateTime<Local>, DateTime<Local>)> + '_ {
+        let today = from.date_naive();
+        (-1..=days).filter_map(move |offset| {
+            let date = today.checked_add_signed(TimeDelta::try_days(offset)?)?;
+            if !self.has_day(date.weekday().num_days_from_monday()) {
+                return None;
+            }
+            let end_date = if self.start < self.end {
+                date
+            } else {
+                date.succ_opt()?
+            };
+            let start = Local
+                .from_local_datetime(&date.and_hms_opt(self.start / 60, self.start % 60, 0)?)
+                .earliest()?;
+            let end = Local
+                .from_local_datetime(&end_date.and_hms_opt(self.end / 60, self.end % 60, 0)?)
+                .earliest()?;
+            Some((start, end))
+        })
+    }
+
+    fn has_day(&self, day: u32) -> bool {
+        self.days & (1 << day) != 0
+    }
+}
+
+impl ParseValue for TimeWindow {
+    fn parse_value(value: &str) -> super::Result<Self> {
+        let value = value.trim();
+        let (days, range) = match value.rsplit_once(' ') {
+            Some((days, range)) => (parse_days(days.trim())?, range),
+            None => (0x7f, value),
+        };
+        let (start, end) = range.split_once('-').ok_or_else(|| {
+            format!("Invalid time window {value:?}: expected a range such as \"01:00-05:00\"")
+        })?;
+
+        Ok(TimeWindow {
+            days,
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        })
+    }
+}
+
+fn parse_days(value: &str) -> super::Result<u8> {
+    let mut days = 0u8;
+    for item in value.split(',').map(|item| item.trim()) {
+        if item == "*" {
+            days = 0x7f;
+        } else if let Some((from, to)) = item.split_once('-') {
+            let (from, to) = (parse_weekday(from)?, parse_weekday(to)?);
+            let mut day = from;
+            loop {
+                days |= 1 << day;
+                if day == to {
+                    break;
+                }
+                day = (day + 1) % 7;
+            }
+        } else {
+            days |= 1 << parse_weekday(item)?;
+        }
+    }
+    Ok(days)
+}
+
+fn parse_weekday(value: &str) -> super::Result<u32> {
+    let value = value.trim().to_ascii_lowercase();
+    ["mon", "tue", "wed", "thu", "fri", "sat", "sun"]
+        .iter()
+        .position(|day| value.starts_with(day))
+        .map(|day| day as u32)
+        .or_else(|| {
+            value
+                .parse::<u32>()
+                .ok()
+                .filter(|day| (1..=7).contains(day))
+                .map(|day| day - 1)
+        })
+        .ok_or_else(|| format!("Invalid weekday {value:?}"))
+}
+
+fn parse_time(value: &str) -> super::Result<u32> {
+    value
+        .trim()
+        .split_once(':')
+        .and_then(|(hour, minute)| {
+            let hour = hour.parse::<u32>().ok().filter(|hour| *hour <= 24)?;
+            let minute = minute.parse::<u32>().ok().filter(|minute| *minute <= 59)?;
+            Some(hour * 60 + minute).filter(|time| *time <= 24 * 60)
+        })
+        .map(|time| time % (24 * 60))
+        .ok_or_else(|| format!("Invalid time {value:?}, expected HH:MM"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+
+    use super::TimeWindow;
+    use crate::config::utils::ParseValue;
+
+    #[test]
+    fn parse_time_window() {
+        // 2024-01-01 is a Monday
+        let at = |day: u32, hour: u32, minute: u32| {
+            Local
+                .with_ymd_and_hms(2024, 1, day, hour, minute, 0)
+                .unwrap()
+        };
+
+        for (window, time, expected) in [
+            ("01:00-05:00", at(1, 3, 0), true),
+            ("01:00-05:00", at(1, 5, 0), false),
+            ("01:00-05:00", at(7, 1, 0), true),
+            ("mon-fri 01:00-05:00", at(5, 1, 0), true),
+            ("mon-fri 01:00-05:00", at(6, 1, 0), false),
+            ("sat,sun 00:00-24:00", at(7, 23, 59), true),
+            ("sat,sun 00:00-24:00", at(1, 0, 0), false),
+            // Ranges spanning midnight belong to the day they start on
+            ("fri 22:00-06:00", at(5, 23, 0), true),
+            ("fri 22:00-06:00", at(6, 5, 59), true),
+            ("fri 22:00-06:00", at(5, 5, 0), false),
+            ("fri 22:00-06:00", at(6, 22, 0), false),
+            ("sun-mon 12:00-13:00", at(1, 12, 30), true),
+            ("sun-mon 12:00-13:00", at(2, 12, 30), false),
+            ("6,7 12:00-13:00", at(7, 12, 30), true),
+        ] {
+            assert_eq!(
+                TimeWindow::parse_value(window).unwrap().contains(&time),
+                expected,
+                "{window} at {time}"
+            );
+        }
+
+        for invalid in ["", "01:00", "xyz 01:00-02:00", "25:00-26:00", "01:60-02:00"] {
+            assert!(TimeWindow::parse_value(invalid).is_err(), "{invalid}");
+        }
+
+        let window = TimeWindow::parse_value("tue 22:00-02:00").unwrap();
+        let occurrences = window.occurrences(at(1, 12, 0), 7).collect::<Vec<_>>();
+        assert_eq!(occurrences, vec![(at(2, 22, 0), at(3, 2, 0))]);
+    }
+}
@@ -7,6 +7,7 @@
 pub mod cron;
 pub mod ipmask;
 pub mod parser;
+pub mod secret;
 pub mod utils;
 
 use std::{collections::BTreeMap, time::Duration};
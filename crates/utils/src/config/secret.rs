@@ -0,0 +1,178 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::OnceLock;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    digest::{SHA256, digest},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use super::{Config, ConfigError};
+
+pub const SEALED_PREFIX: &str = "encrypted:";
+pub const SEALED_PLACEHOLDER: &str = "encrypted:********";
+pub const MASTER_KEY_ENV: &str = "STALWART_MASTER_KEY";
+pub const MASTER_KEY_FILE_ENV: &str = "STALWART_MASTER_KEY_FILE";
+
+static MASTER_KEY: OnceLock<Result<Option<MasterKey>, String>> = OnceLock::new();
+
+pub struct MasterKey {
+    key: LessSafeKey,
+}
+
+impl MasterKey {
+    pub fn new(secret: &[u8]) -> Self {
+        // Any passphrase is accepted, the AES key is derived from its digest
+        let secret = digest(&SHA256, secret.trim_ascii());
+        MasterKey {
+            key: LessSafeKey::new(UnboundKey::new(&AES_256_GCM, secret.as_ref()).unwrap()),
+        }
+    }
+
+    pub fn from_env() -> Result<Option<Self>, String> {
+        if let Ok(secret) = std::env::var(MASTER_KEY_ENV) {
+            Ok(Some(MasterKey::new(secret.as_bytes())))
+        } else if let Ok(path) = std::env::var(MASTER_KEY_FILE_ENV) {
+            std::fs::read(&path)
+                .map(|secret| Some(MasterKey::new(&secret)))
+                .map_err(|err| format!("Failed to read master key from {path:?}: {err}"))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn seal(&self, value: &str) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .expect("Failed to generate nonce");
+        let mut sealed = value.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .expect("Failed to encrypt value");
+
+        let mut bytes = Vec::with_capacity(NONCE_LEN + sealed.len());
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(&sealed);
+        format!("{SEALED_PREFIX}{}", STANDARD.encode(bytes))
+    }
+
+    pub fn unseal(&self, value: &str) -> Result<String, String> {
+        let value = value.strip_prefix(SEALED_PREFIX).unwrap_or(value);
+        let mut bytes = STANDARD
+            .decode(value.trim())
+            .map_err(|_| "Encrypted value is not valid base64".to_string())?;
+        if bytes.len() <= NONCE_LEN {
+            return Err("Encrypted value is too short".to_string());
+        }
+        let mut sealed = bytes.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&bytes)
+            .map_err(|_| "Invalid encrypted value nonce".to_string())?;
+
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .map_err(|_| {
+                "Failed to decrypt value, the master key might be incorrect".to_string()
+            })?;
+        String::from_utf8(plaintext.to_vec())
+            .map_err(|_| "Decrypted value is not valid UTF-8".to_string())
+    }
+
+    pub fn global() -> Result<Option<&'static MasterKey>, &'static str> {
+        MASTER_KEY
+            .get_or_init(MasterKey::from_env)
+            .as_ref()
+            .map(Option::as_ref)
+            .map_err(String::as_str)
+    }
+}
+
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with(SEALED_PREFIX)
+}
+
+// Hides the ciphertext of sealed values from settings returned by the API
+pub fn redact(value: String) -> String {
+    if is_sealed(&value) {
+        SEALED_PLACEHOLDER.to_string()
+    } else {
+        value
+    }
+}
+
+impl Config {
+    pub fn resolve_secrets(&mut self) {
+        let master_key = MasterKey::global();
+
+        for (key, value) in self.keys.iter_mut() {
+            if !is_sealed(value) {
+                continue;
+            }
+
+            let result = match master_key {
+                Ok(Some(master_key)) => master_key.unseal(value),
+                Ok(None) => Err(format!(
+                    "Encrypted value found but no master key was provided in {MASTER_KEY_ENV} or {MASTER_KEY_FILE_ENV}"
+                )),
+                Err(err) => Err(err.to_string()),
+            };
+
+            match result {
+                Ok(plaintext) => {
+                    *value = plaintext;
+                }
+                Err(error) => {
+                    self.errors
+                        .insert(key.clone(), ConfigError::Macro { error });
+                }
+            }
+        }
+    }
+
+    pub fn sealed_keys(&self) -> Vec<&str> {
+        self.keys
+            .iter()
+            .filter(|(_, value)| is_sealed(value))
+            .map(|(key, _)| key.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MasterKey, SEALED_PREFIX, redact};
+
+    #[test]
+    fn seal_and_unseal() {
+        let master_key = MasterKey::new(b"correct horse battery staple\n");
+        let sealed = master_key.seal("s3cr3t-bind-password");
+        assert!(sealed.starts_with(SEALED_PREFIX));
+        assert!(!sealed.contains("s3cr3t"));
+        assert_ne!(sealed, master_key.seal("s3cr3t-bind-password"));
+        assert_eq!(master_key.unseal(&sealed).unwrap(), "s3cr3t-bind-password");
+
+        // Surrounding whitespace in the passphrase is ignored
+        assert_eq!(
+            MasterKey::new(b"correct horse battery staple")
+                .unseal(&sealed)
+                .unwrap(),
+            "s3cr3t-bind-password"
+        );
+        assert!(MasterKey::new(b"wrong key").unseal(&sealed).is_err());
+        assert!(master_key.unseal("encrypted:AAAA").is_err());
+
+        assert_eq!(redact(sealed), "encrypted:********");
+        assert_eq!(redact("plain".to_string()), "plain");
+    }
+}
@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{fmt::Display, sync::Arc};
+use std::{fmt::Display, net::IpAddr, sync::Arc};
 
 pub mod bimap;
 pub mod cache;
@@ -14,6 +14,7 @@ pub mod glob;
 pub mod json;
 pub mod map;
 pub mod snowflake;
+pub mod suffixlist;
 pub mod topological;
 pub mod url_params;
 
@@ -135,6 +136,56 @@ impl HttpLimitResponse for Response {
     }
 }
 
+// Returns false for loopback, private, link-local and other addresses that are
+// not reachable on the public Internet
+pub fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(&IpAddr::V4(ip));
+            }
+            let segments = ip.segments();
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (segments[0] & 0xfe00) == 0xfc00
+                || (segments[0] & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+// Resolver for HTTP clients that connect to user supplied URLs. Host names are
+// checked when connecting, so they cannot be rebound to internal addresses.
+#[derive(Debug, Default)]
+pub struct PublicIpResolver;
+
+impl reqwest::dns::Resolve for PublicIpResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(&addr.ip()))
+                .collect::<Vec<_>>();
+            if !addrs.is_empty() {
+                Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+            } else {
+                Err(format!("{} does not resolve to a public address", name.as_str()).into())
+            }
+        })
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[repr(transparent)]
 pub struct Semver(u64);
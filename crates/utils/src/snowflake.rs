@@ -60,7 +60,9 @@ impl SnowflakeIdGenerator {
         Self {
             epoch: SystemTime::UNIX_EPOCH + Duration::from_secs(DEFAULT_EPOCH), // 52 years after UNIX_EPOCH
             node_id,
-            sequence: 0.into(),
+            // Nodes without a configured id pick one at random, starting the sequence at a
+            // random offset makes collisions unlikely even if two nodes pick the same id
+            sequence: (rand::random::<u64>() & SEQUENCE_MASK).into(),
         }
     }
 
@@ -103,7 +105,7 @@ impl Clone for SnowflakeIdGenerator {
         Self {
             epoch: self.epoch,
             node_id: self.node_id,
-            sequence: 0.into(),
+            sequence: (rand::random::<u64>() & SEQUENCE_MASK).into(),
         }
     }
 }
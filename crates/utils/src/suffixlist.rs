@@ -4,199 +4,283 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::io::Read;
+use std::{io::Read, sync::Arc};
 
 use ahash::AHashSet;
 use mail_auth::flate2::read::GzDecoder;
+use parking_lot::RwLock;
 
-use crate::config::Config;
+// A list with fewer rules than this is most likely truncated or not a PSL at all
+const MIN_RULES: usize = 1000;
+
+// Runtime list downloaded from a mirror, the compiled-in list is used while unset
+static PUBLIC_SUFFIX: RwLock<Option<Arc<PublicSuffix>>> = parking_lot::const_rwlock(None);
 
 #[derive(Debug, Clone, Default)]
 pub struct PublicSuffix {
     pub suffixes: AHashSet<String>,
     pub exceptions: AHashSet<String>,
-    pub wildcards: Vec<String>,
-}
-
-#[derive(PartialEq, Eq, Clone, Copy)]
-pub enum DomainPart {
-    Sld,
-    Tld,
-    Host,
+    // Parent domains of wildcard rules, "*.ck" is stored as "ck"
+    pub wildcards: AHashSet<String>,
 }
 
 impl PublicSuffix {
     pub fn contains(&self, suffix: &str) -> bool {
         self.suffixes.contains(suffix)
             || (!self.exceptions.contains(suffix)
-                && self.wildcards.iter().any(|w| suffix.ends_with(w)))
+                && suffix
+                    .split_once('.')
+                    .is_some_and(|(_, parent)| self.wildcards.contains(parent)))
     }
 
-    pub fn domain_part(&self, domain: &str, part: DomainPart) -> Option<String> {
-        let d = domain.trim().to_lowercase();
-        let mut seen_dot = false;
-        for (pos, ch) in d.as_bytes().iter().enumerate().rev() {
-            if *ch == b'.' {
-                if seen_dot {
-                    let maybe_domain =
-                        std::str::from_utf8(&d.as_bytes()[pos + 1..]).unwrap_or_default();
-                    if !self.contains(maybe_domain) {
-                        return if part == DomainPart::Sld {
-                            maybe_domain
-                        } else {
-                            std::str::from_utf8(&d.as_bytes()[..pos]).unwrap_or_default()
-                        }
-                        .to_string()
-                        .into();
-                    }
-                } else if part == DomainPart::Tld {
-                    return std::str::from_utf8(&d.as_bytes()[pos + 1..])
-                        .unwrap_or_default()
-                        .to_string()
-                        .into();
-                } else {
-                    seen_dot = true;
-                }
-            }
+    // Returns the registrable domain (public suffix plus one label) as a
+    // slice of the input, or None if the domain is itself a public suffix.
+    pub fn org_domain<'x>(&self, domain: &'x str) -> Option<&'x str> {
+        self.lookup(domain).map(|(domain, _)| domain)
+    }
+
+    // Same as org_domain but requires the suffix to be listed, the default
+    // "*" rule is not applied.
+    pub fn registrable_domain<'x>(&self, domain: &'x str) -> Option<&'x str> {
+        self.lookup(domain)
+            .and_then(|(domain, is_listed)| if is_listed { Some(domain) } else { None })
+    }
+
+    fn lookup<'x>(&self, domain: &'x str) -> Option<(&'x str, bool)> {
+        let domain = domain.strip_suffix('.').unwrap_or(domain);
+        if domain.is_empty() || domain.starts_with('.') || domain.contains("..") {
+            return None;
+        }
+        let lowercase = domain.to_lowercase();
+        if lowercase.len() != domain.len() {
+            // Non-ASCII case folding changed the length, slicing would be unsafe
+            return None;
         }
 
-        if seen_dot {
-            if part == DomainPart::Sld {
-                d.into()
-            } else {
-                None
+        // Start offsets of each label
+        let labels = std::iter::once(0)
+            .chain(
+                lowercase
+                    .bytes()
+                    .enumerate()
+                    .filter(|(_, ch)| *ch == b'.')
+                    .map(|(pos, _)| pos + 1),
+            )
+            .collect::<Vec<_>>();
+
+        // The prevailing rule is the one with the most labels, exceptions win
+        let mut suffix_start = None;
+        for (idx, &start) in labels.iter().enumerate() {
+            let candidate = &lowercase[start..];
+            if self.exceptions.contains(candidate) {
+                suffix_start = labels.get(idx + 1).copied();
+                break;
+            } else if self.suffixes.contains(candidate)
+                || labels
+                    .get(idx + 1)
+                    .is_some_and(|&parent| self.wildcards.contains(&lowercase[parent..]))
+            {
+                suffix_start = Some(start);
+                break;
             }
-        } else if part == DomainPart::Host {
-            d.into()
+        }
+
+        // Default rule "*": the last label is the public suffix
+        let is_listed = suffix_start.is_some();
+        let suffix_start = suffix_start.unwrap_or_else(|| *labels.last().unwrap());
+        let suffix_idx = labels.iter().position(|&start| start == suffix_start)?;
+
+        if suffix_idx > 0 {
+            Some((&domain[labels[suffix_idx - 1]..], is_listed))
         } else {
             None
         }
     }
+
+    pub fn from_bytes(url: &str, bytes: Vec<u8>) -> Result<Self, String> {
+        let bytes = if url.ends_with(".gz") {
+            GzDecoder::new(&bytes[..])
+                .bytes()
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| format!("Failed to decompress public suffixes: {err}"))?
+        } else {
+            bytes
+        };
+        let list = String::from_utf8(bytes)
+            .map_err(|err| format!("Failed to parse public suffixes: {err}"))?;
+        let list = PublicSuffix::from(list.as_str());
+        let num_rules = list.suffixes.len() + list.exceptions.len() + list.wildcards.len();
+
+        if num_rules >= MIN_RULES {
+            Ok(list)
+        } else {
+            Err(format!(
+                "Public suffix list contains only {num_rules} rules, expected at least {MIN_RULES}"
+            ))
+        }
+    }
 }
 
 impl From<&str> for PublicSuffix {
     fn from(list: &str) -> Self {
         let mut ps = PublicSuffix::default();
         for line in list.lines() {
-            let line = line.trim().to_lowercase();
-            if !line.starts_with("//") {
-                if let Some(domain) = line.strip_prefix('*') {
-                    ps.wildcards.push(domain.to_string());
-                } else if let Some(domain) = line.strip_prefix('!') {
-                    ps.exceptions.insert(domain.to_string());
-                } else {
-                    ps.suffixes.insert(line.to_string());
+            // Rules end at the first whitespace
+            let line = line
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_lowercase();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            let (set, rule) = if let Some(domain) = line.strip_prefix("*.") {
+                (&mut ps.wildcards, domain)
+            } else if let Some(domain) = line.strip_prefix('!') {
+                (&mut ps.exceptions, domain)
+            } else {
+                (&mut ps.suffixes, line.as_str())
+            };
+
+            // Internationalized rules are also indexed by their A-label form
+            if !rule.is_ascii() {
+                if let Ok(ascii) = idna::domain_to_ascii(rule) {
+                    set.insert(ascii);
                 }
             }
+            set.insert(rule.to_string());
         }
         ps.suffixes.insert("onion".to_string());
         ps
     }
 }
 
-impl PublicSuffix {
-    #[allow(unused_variables)]
-    pub async fn parse(config: &mut Config, key: &str) -> PublicSuffix {
-        let mut values = config
-            .values(key)
-            .map(|(_, s)| s.to_string())
-            .collect::<Vec<_>>();
-        if values.is_empty() {
-            values = vec![
-                "https://publicsuffix.org/list/public_suffix_list.dat".to_string(),
-                "https://raw.githubusercontent.com/publicsuffix/list/master/public_suffix_list.dat"
-                    .to_string(),
-            ]
-        }
+pub fn set_public_suffix(list: PublicSuffix) {
+    *PUBLIC_SUFFIX.write() = Some(Arc::new(list));
+}
 
-        for (idx, value) in values.into_iter().enumerate() {
-            let bytes = if value.starts_with("https://") || value.starts_with("http://") {
-                let result = match reqwest::get(&value).await {
-                    Ok(r) => {
-                        if r.status().is_success() {
-                            r.bytes().await
-                        } else {
-                            config.new_build_warning(
-                                format!("{value}.{idx}"),
-                                format!(
-                                    "Failed to fetch public suffixes from {value:?}: Status {status}",
-                                    value = value,
-                                    status = r.status()
-                                ),
-                            );
-                            continue;
-                        }
-                    }
-                    Err(err) => Err(err),
-                };
-
-                match result {
-                    Ok(bytes) => bytes.to_vec(),
-                    Err(err) => {
-                        config.new_build_warning(
-                            format!("{value}.{idx}"),
-                            format!("Failed to fetch public suffixes from {value:?}: {err}",),
-                        );
-                        continue;
-                    }
-                }
-            } else if let Some(filename) = value.strip_prefix("file://") {
-                match std::fs::read(filename) {
-                    Ok(bytes) => bytes,
-                    Err(err) => {
-                        config.new_build_warning(
-                            format!("{value}.{idx}"),
-                            format!("Failed to read public suffixes from {value:?}: {err}",),
-                        );
-                        continue;
-                    }
-                }
-            } else {
-                config.new_parse_error(key, format!("Invalid public suffix file {value:?}"));
-                continue;
-            };
-            let bytes = if value.ends_with(".gz") {
-                match GzDecoder::new(&bytes[..])
-                    .bytes()
-                    .collect::<Result<Vec<_>, _>>()
-                {
-                    Ok(bytes) => bytes,
-                    Err(err) => {
-                        config.new_build_warning(
-                            format!("{value}.{idx}"),
-                            format!(
-                                "Failed to decompress public suffixes from {value:?}: {err}",
-                                value = value,
-                                err = err
-                            ),
-                        );
-                        continue;
-                    }
-                }
-            } else {
-                bytes
-            };
+// Organizational domain as used by DMARC, falling back to the domain itself
+pub fn org_domain(domain: &str) -> &str {
+    let list = PUBLIC_SUFFIX.read().clone();
+    if let Some(list) = list {
+        list.org_domain(domain)
+    } else {
+        psl::domain_str(domain)
+    }
+    .unwrap_or(domain)
+}
 
-            match String::from_utf8(bytes) {
-                Ok(list) => {
-                    return PublicSuffix::from(list.as_str());
-                }
-                Err(err) => {
-                    config.new_build_warning(
-                        format!("{value}.{idx}"),
-                        format!(
-                            "Failed to parse public suffixes from {value:?}: {err}",
-                            value = value,
-                            err = err
-                        ),
-                    );
-                }
-            }
+// Registrable domain under a listed public suffix, used for reputation keying
+pub fn registrable_domain(domain: &str) -> Option<&str> {
+    let list = PUBLIC_SUFFIX.read().clone();
+    if let Some(list) = list {
+        list.registrable_domain(domain)
+    } else {
+        psl::domain(domain.as_bytes())
+            .filter(|domain| domain.suffix().typ().is_some())
+            .and_then(|domain| std::str::from_utf8(domain.as_bytes()).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PublicSuffix;
+
+    #[test]
+    fn org_domain() {
+        let list = PublicSuffix::from(
+            r#"
+// ===BEGIN ICANN DOMAINS===
+com
+uk
+co.uk
+jp
+kyoto.jp
+*.kobe.jp
+!city.kobe.jp
+*.ck
+!www.ck
+// Unicode rules
+公司.cn
+cn
+рф
+"#,
+        );
+
+        for (domain, expected) in [
+            ("example.com", Some("example.com")),
+            ("mail.example.com", Some("example.com")),
+            ("a.b.c.example.com", Some("example.com")),
+            ("Mail.Example.COM", Some("Example.COM")),
+            ("example.com.", Some("example.com")),
+            ("com", None),
+            ("co.uk", None),
+            ("example.co.uk", Some("example.co.uk")),
+            ("www.example.co.uk", Some("example.co.uk")),
+            ("ide.kyoto.jp", Some("ide.kyoto.jp")),
+            ("b.ide.kyoto.jp", Some("ide.kyoto.jp")),
+            // Wildcards match one label only
+            ("c.kobe.jp", None),
+            ("b.c.kobe.jp", Some("b.c.kobe.jp")),
+            ("a.b.c.kobe.jp", Some("b.c.kobe.jp")),
+            // Exceptions override wildcards
+            ("city.kobe.jp", Some("city.kobe.jp")),
+            ("www.city.kobe.jp", Some("city.kobe.jp")),
+            ("ck", None),
+            ("test.ck", None),
+            ("b.test.ck", Some("b.test.ck")),
+            ("www.ck", Some("www.ck")),
+            ("www.www.ck", Some("www.ck")),
+            // Unlisted TLDs fall back to the default rule
+            ("example.example", Some("example.example")),
+            ("a.example.example", Some("example.example")),
+            // IDNA rules match both U-label and A-label forms
+            ("公司.cn", None),
+            ("xn--55qx5d.cn", None),
+            ("食狮.公司.cn", Some("食狮.公司.cn")),
+            (
+                "www.xn--85x722f.xn--55qx5d.cn",
+                Some("xn--85x722f.xn--55qx5d.cn"),
+            ),
+            ("пример.рф", Some("пример.рф")),
+            ("mail.xn--e1afmkfd.xn--p1ai", Some("xn--e1afmkfd.xn--p1ai")),
+            ("example.onion", Some("example.onion")),
+            // Malformed input
+            ("", None),
+            (".com", None),
+            ("a..example.com", None),
+        ] {
+            assert_eq!(list.org_domain(domain), expected, "failed for {domain:?}");
         }
 
-        #[cfg(not(feature = "test_mode"))]
-        config.new_build_warning(key, "Failed to parse public suffixes from any source.");
+        // Unlisted suffixes have no registrable domain
+        assert_eq!(
+            list.registrable_domain("mail.example.co.uk"),
+            Some("example.co.uk")
+        );
+        assert_eq!(list.registrable_domain("b.test.ck"), Some("b.test.ck"));
+        assert_eq!(list.registrable_domain("a.example.example"), None);
+
+        assert!(list.contains("co.uk"));
+        assert!(list.contains("c.kobe.jp"));
+        assert!(!list.contains("city.kobe.jp"));
+        assert!(!list.contains("example.com"));
+    }
 
-        PublicSuffix::default()
+    #[test]
+    fn reject_implausible_list() {
+        assert!(PublicSuffix::from_bytes("file:///tmp/psl.dat", b"com\nnet\n".to_vec()).is_err());
+        assert!(PublicSuffix::from_bytes("file:///tmp/psl.dat", vec![0xff, 0xfe]).is_err());
+        assert!(
+            PublicSuffix::from_bytes(
+                "file:///tmp/psl.dat",
+                (0..1000)
+                    .map(|n| format!("tld{n}\n"))
+                    .collect::<String>()
+                    .into_bytes()
+            )
+            .is_ok()
+        );
     }
 }
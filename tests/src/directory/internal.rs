@@ -306,6 +306,52 @@ async fn internal_directory() {
             .collect::<AHashSet<_>>()
         );
 
+        // Lists forwarding to each other must be rejected
+        store
+            .create_principal(
+                TestPrincipal {
+                    name: "list-relay".into(),
+                    typ: Type::List,
+                    emails: vec!["relay@example.org".into()],
+                    ..Default::default()
+                }
+                .into(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(
+            store
+                .update_principal(UpdatePrincipal::by_name("list-relay").with_updates(vec![
+                    PrincipalUpdate::add_item(
+                        PrincipalField::ExternalMembers,
+                        PrincipalValue::String("list@example.org".into()),
+                    )
+                ]))
+                .await
+                .is_ok()
+        );
+        assert_eq!(
+            store
+                .update_principal(UpdatePrincipal::by_name("list").with_updates(vec![
+                    PrincipalUpdate::add_item(
+                        PrincipalField::ExternalMembers,
+                        PrincipalValue::String("relay@example.org".into()),
+                    )
+                ]))
+                .await
+                .unwrap_err()
+                .value_as_str(trc::Key::Reason),
+            Some(
+                "Forwarding cycle detected: list@example.org -> relay@example.org -> list@example.org"
+            )
+        );
+        store
+            .delete_principal(QueryBy::Name("list-relay"))
+            .await
+            .unwrap();
+
         // Create groups
         store
             .create_principal(
@@ -15,6 +15,7 @@ use common::{Core, Server, config::smtp::session::AddressMapping};
 use directory::{
     Directories, Principal, Type,
     backend::internal::{PrincipalField, PrincipalSet, manage::ManageDirectory},
+    core::senders::SenderGrant,
 };
 use mail_send::Credentials;
 use rustls::ServerConfig;
@@ -722,6 +723,45 @@ async fn address_mappings() {
     }
 }
 
+#[test]
+fn sender_grants() {
+    for (value, expected) in [
+        ("client.org", Some(("@client.org", false))),
+        ("@Client.org", Some(("@client.org", false))),
+        ("@client.org;dkim", Some(("@client.org", true))),
+        ("News@Agency.org ; DKIM", Some(("news@agency.org", true))),
+        ("news@agency.org", Some(("news@agency.org", false))),
+        ("news@agency.org;sign", None),
+        ("@", None),
+        ("not an address", None),
+    ] {
+        let grant = SenderGrant::parse(value);
+        assert_eq!(
+            grant
+                .as_ref()
+                .map(|grant| (grant.address.as_str(), grant.dkim)),
+            expected,
+            "failed for {value:?}"
+        );
+        if let Some(grant) = grant {
+            assert_eq!(SenderGrant::parse(&grant.to_string()), Some(grant));
+        }
+    }
+
+    let domain = SenderGrant::parse("@client.org").unwrap();
+    assert!(domain.is_domain());
+    assert_eq!(domain.domain(), "client.org");
+    assert!(domain.matches("bill@client.org"));
+    assert!(!domain.matches("bill@subclient.org"));
+    assert!(!domain.matches("bill@sub.client.org"));
+
+    let address = SenderGrant::parse("news@agency.org").unwrap();
+    assert!(!address.is_domain());
+    assert_eq!(address.domain(), "agency.org");
+    assert!(address.matches("news@agency.org"));
+    assert!(!address.matches("sales@agency.org"));
+}
+
 async fn map_account_ids(store: &Store, names: Vec<impl AsRef<str>>) -> Vec<u32> {
     let mut ids = Vec::with_capacity(names.len());
     for name in names {
@@ -8,7 +8,7 @@ use std::{fs, io};
 
 use imap_proto::ResponseType;
 
-use crate::jmap::wait_for_index;
+use crate::{directory::internal::TestInternalDirectory, jmap::wait_for_index};
 
 use super::{AssertResult, IMAPTest, ImapConnection, Type, resources_dir};
 
@@ -21,6 +21,66 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection, h
         .await
         .assert_response_code("TRYCREATE");
 
+    // Literal limits are advertised
+    imap.send("CAPABILITY").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("LITERAL-")
+        .assert_contains("APPENDLIMIT=52428800");
+
+    // Oversized messages are rejected before the literal is sent
+    imap.send("APPEND INBOX {60000000}").await;
+    imap.assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("TOOBIG");
+
+    // Non-synchronizing literals are limited to 4096 bytes outside APPEND
+    imap.send(&format!("SELECT {{5000+}}\r\n{}", "a".repeat(5000)))
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Bad)
+        .await
+        .assert_response_code("TOOBIG");
+    imap.send("NOOP").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // Messages that do not fit in the quota are rejected upfront
+    handle
+        .server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "frugal@example.com",
+            "secret",
+            "Frugal User",
+            &["frugal@example.com"],
+        )
+        .await;
+    handle
+        .server
+        .core
+        .storage
+        .data
+        .set_test_quota("frugal@example.com", 1024)
+        .await;
+    let mut frugal = ImapConnection::connect(b"_z ").await;
+    frugal.assert_read(Type::Untagged, ResponseType::Ok).await;
+    frugal.authenticate("frugal@example.com", "secret").await;
+    frugal.send("APPEND INBOX {2048}").await;
+    frugal
+        .assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("OVERQUOTA");
+    frugal
+        .send(&format!("APPEND INBOX {{2048+}}\r\n{}", "a".repeat(2048)))
+        .await;
+    frugal
+        .assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("OVERQUOTA");
+    frugal.send("NOOP").await;
+    frugal.assert_read(Type::Tagged, ResponseType::Ok).await;
+
     // Import test messages
     let mut entries = fs::read_dir(resources_dir())
         .unwrap()
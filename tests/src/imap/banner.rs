@@ -0,0 +1,120 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use mail_send::smtp::tls::build_tls_connector;
+use rustls_pki_types::ServerName;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+use crate::smtp::session::VerifyResponse;
+
+pub async fn test() {
+    println!("Running banner and capability tests...");
+
+    // Capabilities hidden before TLS should not be advertised
+    let mut imap = BufReader::new(TcpStream::connect("127.0.0.1:9993").await.unwrap());
+    let greeting = read_tagged(&mut imap, "* ").await;
+    greeting
+        .assert_contains("imap.example.org ready")
+        .assert_contains("STARTTLS")
+        .assert_contains("LOGINDISABLED")
+        .assert_not_contains("AUTH=PLAIN")
+        .assert_not_contains(" ID ")
+        .assert_not_contains("Stalwart");
+    send(&mut imap, "a CAPABILITY").await;
+    let capabilities = read_tagged(&mut imap, "a OK").await;
+    capabilities
+        .assert_contains("STARTTLS")
+        .assert_contains("LOGINDISABLED")
+        .assert_contains("AUTH=OAUTHBEARER")
+        .assert_not_contains("AUTH=PLAIN")
+        .assert_not_contains(" ID ");
+
+    // Hidden capabilities should be rejected
+    send(&mut imap, "a ID").await;
+    read_tagged(&mut imap, "a NO").await;
+    send(
+        &mut imap,
+        "a AUTHENTICATE PLAIN AGpkb2VAZXhhbXBsZS5jb20Ac2VjcmV0",
+    )
+    .await;
+    read_tagged(&mut imap, "a NO").await;
+    send(&mut imap, "a LOGIN jdoe@example.com secret").await;
+    read_tagged(&mut imap, "a NO").await;
+
+    // Capabilities should be refreshed after STARTTLS
+    send(&mut imap, "a STARTTLS").await;
+    read_tagged(&mut imap, "a OK").await;
+    let mut imap = BufReader::new(
+        build_tls_connector(true)
+            .connect(
+                ServerName::try_from("imap.example.org").unwrap().to_owned(),
+                imap.into_inner(),
+            )
+            .await
+            .unwrap(),
+    );
+    send(&mut imap, "a CAPABILITY").await;
+    let capabilities_tls = read_tagged(&mut imap, "a OK").await;
+    capabilities_tls
+        .assert_contains("AUTH=PLAIN")
+        .assert_not_contains("STARTTLS")
+        .assert_not_contains("LOGINDISABLED")
+        .assert_not_contains(" ID ");
+    assert_ne!(capabilities, capabilities_tls);
+
+    // ID is available after authentication, without product details
+    send(
+        &mut imap,
+        "a AUTHENTICATE PLAIN AGpkb2VAZXhhbXBsZS5jb20Ac2VjcmV0",
+    )
+    .await;
+    read_tagged(&mut imap, "a OK").await.assert_contains(" ID ");
+    send(&mut imap, "a ID").await;
+    read_tagged(&mut imap, "a OK")
+        .await
+        .assert_contains("* ID NIL")
+        .assert_not_contains("Stalwart");
+    send(&mut imap, "a LOGOUT").await;
+}
+
+async fn send<T: AsyncRead + AsyncWrite + Unpin>(stream: &mut BufReader<T>, text: &str) {
+    stream.write_all(text.as_bytes()).await.unwrap();
+    stream.write_all(b"\r\n").await.unwrap();
+}
+
+async fn read_tagged<T: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut BufReader<T>,
+    tag: &str,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        match tokio::time::timeout(Duration::from_millis(1500), stream.read_line(&mut line)).await {
+            Ok(Ok(len)) if len > 0 => {
+                let line = line.trim_end().to_string();
+                let is_done = line.starts_with(tag)
+                    || line.starts_with("a NO")
+                    || line.starts_with("a BAD")
+                    || line.starts_with("a OK");
+                lines.push(line);
+                if is_done {
+                    if !lines.last().unwrap().starts_with(tag) {
+                        panic!("Expected {tag:?} from server but got: {lines:?}");
+                    }
+                    return lines;
+                }
+            }
+            Ok(Ok(_)) => panic!("Connection closed: {lines:?}"),
+            Ok(Err(err)) => panic!("Connection broken: {err} ({lines:?})"),
+            Err(_) => panic!("Timeout while waiting for server response: {lines:?}"),
+        }
+    }
+}
@@ -32,6 +32,13 @@ pub async fn test() {
         .send("AUTHENTICATE \"PLAIN\" \"AGpkb2VAZXhhbXBsZS5jb20Ac2VjcmV0\"")
         .await;
     sieve.assert_read(ResponseType::Ok).await;
+
+    // Global includes are advertised
+    sieve.send("CAPABILITY").await;
+    sieve
+        .assert_read(ResponseType::Ok)
+        .await
+        .assert_contains(" include ");
     /*sieve
     .assert_read(ResponseType::Ok)
     .await
@@ -6,6 +6,7 @@
 
 pub mod acl;
 pub mod append;
+pub mod banner;
 pub mod basic;
 pub mod bayes;
 pub mod body_structure;
@@ -112,6 +113,9 @@ pub async fn imap_tests() {
     // Bayes training
     bayes::test(&handle).await;
 
+    // Run banner and capability tests
+    banner::test().await;
+
     // Run ManageSieve tests
     managesieve::test().await;
 
@@ -619,6 +623,15 @@ protocol = "imap"
 max-connections = 81920
 tls.implicit = true
 
+[server.listener.imap-hardened]
+bind = ["127.0.0.1:9993"]
+protocol = "imap"
+max-connections = 81920
+banner.greeting = "{hostname} ready"
+banner.hide-product = true
+capabilities.hide-before-tls = ["AUTH=PLAIN"]
+capabilities.hide-before-auth = ["id"]
+
 [server.listener.sieve]
 bind = ["127.0.0.1:4190"]
 protocol = "managesieve"
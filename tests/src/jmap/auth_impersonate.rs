@@ -0,0 +1,222 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use base64::{Engine, engine::general_purpose};
+use common::auth::impersonate::ImpersonationSession;
+use imap_proto::ResponseType;
+use jmap_client::{
+    client::{Client, Credentials},
+    email,
+    mailbox::Role,
+};
+use jmap_proto::types::id::Id;
+use serde_json::json;
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    imap::{AssertResult, ImapConnection, Type},
+    jmap::{ManagementApi, assert_is_empty, mailbox::destroy_all_mailboxes},
+};
+
+use super::{JMAPTest, enterprise::List};
+
+#[derive(serde::Deserialize)]
+struct Impersonation {
+    token: String,
+    session: ImpersonationSession,
+}
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running impersonation tests...");
+
+    // Create test account
+    let server = params.server.clone();
+    let account_id = Id::from(
+        server
+            .core
+            .storage
+            .data
+            .create_test_user(
+                "jane.support@example.com",
+                "abcde",
+                "Jane Support",
+                &["jane.support@example.com"],
+            )
+            .await,
+    )
+    .to_string();
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Regular users cannot mint impersonation tokens
+    ManagementApi::new(8899, "jane.support@example.com", "abcde")
+        .post::<Impersonation>("/api/impersonate", &json!({"account": "admin"}))
+        .await
+        .unwrap()
+        .expect_error("forbidden");
+
+    // Mint a read-only token
+    let impersonation = api
+        .post::<Impersonation>(
+            "/api/impersonate",
+            &json!({
+                "account": "jane.support@example.com",
+                "duration": 600,
+                "reason": "Debugging a sync issue"
+            }),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(impersonation.session.read_only);
+    assert_eq!(impersonation.session.admin_name, "admin");
+    assert_eq!(
+        impersonation.session.expires_at - impersonation.session.created_at,
+        600
+    );
+    let sessions = api
+        .get::<List<ImpersonationSession>>("/api/impersonate")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(sessions.items, vec![impersonation.session.clone()]);
+
+    // The account owner is notified
+    let client = Client::new()
+        .credentials(Credentials::bearer(&impersonation.token))
+        .accept_invalid_certs(true)
+        .connect("https://127.0.0.1:8899")
+        .await
+        .unwrap();
+    assert_eq!(client.default_account_id(), account_id);
+    wait_for_notifications(&client, 1).await;
+
+    // Read-only sessions cannot modify the account
+    assert!(
+        client
+            .mailbox_create("Impersonated", None::<String>, Role::None)
+            .await
+            .is_err()
+    );
+
+    // IMAP sessions are authorized through the SASL authorization identity
+    let mut imap = ImapConnection::connect(b"_x ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    let creds = general_purpose::STANDARD.encode("jane.support@example.com\0admin\0secret");
+    imap.send(&format!("AUTHENTICATE PLAIN {creds}")).await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("SELECT INBOX").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_response_code("READ-ONLY");
+    imap.send("CREATE Impersonated").await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
+
+    // The authorization identity must match an active session
+    let mut imap = ImapConnection::connect(b"_x ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    let creds = general_purpose::STANDARD.encode("jdoe@example.com\0admin\0secret");
+    imap.send(&format!("AUTHENTICATE PLAIN {creds}")).await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
+
+    // Read-write sessions can modify the account
+    let rw_impersonation = api
+        .post::<Impersonation>(
+            "/api/impersonate",
+            &json!({
+                "account": "jane.support@example.com",
+                "readOnly": false,
+            }),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(!rw_impersonation.session.read_only);
+    let rw_client = Client::new()
+        .credentials(Credentials::bearer(&rw_impersonation.token))
+        .accept_invalid_certs(true)
+        .connect("https://127.0.0.1:8899")
+        .await
+        .unwrap();
+    wait_for_notifications(&rw_client, 2).await;
+    rw_client
+        .mailbox_create("Impersonated", None::<String>, Role::None)
+        .await
+        .unwrap();
+
+    // Revoked sessions are rejected immediately
+    for session in [&impersonation.session, &rw_impersonation.session] {
+        api.delete::<()>(&format!("/api/impersonate/{}", session.id))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+    api.delete::<()>(&format!("/api/impersonate/{}", impersonation.session.id))
+        .await
+        .unwrap()
+        .expect_error("notFound");
+    assert!(
+        api.get::<List<ImpersonationSession>>("/api/impersonate")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .items
+            .is_empty()
+    );
+    match Client::new()
+        .credentials(Credentials::bearer(&impersonation.token))
+        .accept_invalid_certs(true)
+        .connect("https://127.0.0.1:8899")
+        .await
+    {
+        Ok(_) => panic!("Expected unauthorized access."),
+        Err(err) => {
+            let err = err.to_string();
+            assert!(err.contains("Unauthorized"), "{}", err);
+        }
+    }
+    let mut imap = ImapConnection::connect(b"_x ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    let creds = general_purpose::STANDARD.encode("jane.support@example.com\0admin\0secret");
+    imap.send(&format!("AUTHENTICATE PLAIN {creds}")).await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
+
+    // Tampered tokens are rejected
+    let mut token = rw_impersonation.token.clone();
+    let last = token.pop().unwrap();
+    token.push(if last == 'x' { 'y' } else { 'x' });
+    assert!(
+        Client::new()
+            .credentials(Credentials::bearer(&token))
+            .accept_invalid_certs(true)
+            .connect("https://127.0.0.1:8899")
+            .await
+            .is_err()
+    );
+
+    // Cleanup
+    params.client.set_default_account_id(&account_id);
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
+
+async fn wait_for_notifications(client: &Client, expected: usize) {
+    for _ in 0..20 {
+        if client
+            .email_query(None::<email::query::Filter>, None::<Vec<_>>)
+            .await
+            .unwrap()
+            .ids()
+            .len()
+            == expected
+        {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+    panic!("Expected {expected} impersonation notifications");
+}
@@ -4,12 +4,33 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use ahash::AHashSet;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use common::{
+    KV_UPLOAD_SCAN,
+    config::smtp::session::{MTAHook, Stage},
+    expr::if_block::IfBlock,
+};
 use email::mailbox::INBOX_ID;
+use http_proto::HttpResponse;
+use hyper::{HeaderMap, StatusCode, header::CONTENT_TYPE};
+use jmap::blob::scan::{BlobScan, ScanRequest, ScanVerdict};
 use jmap_proto::types::id::Id;
 use serde_json::Value;
+use smtp::inbound::hooks::{self, SmtpResponse};
+use utils::BlobHash;
 
 use crate::{
     directory::internal::TestInternalDirectory,
+    http_server::{HttpMessage, spawn_mock_http_server},
     jmap::{assert_is_empty, jmap_json_request, mailbox::destroy_all_mailboxes},
 };
 
@@ -479,8 +500,246 @@ pub async fn test(params: &mut JMAPTest) {
         );
     }
 
+    // Scan uploaded files
+    upload_scan(params, account_id).await;
+
     // Remove test data
     params.client.set_default_account_id(account_id.to_string());
     destroy_all_mailboxes(params).await;
     assert_is_empty(server).await;
 }
+
+async fn upload_scan(params: &mut JMAPTest, account_id: Id) {
+    println!("Running upload scan tests...");
+    let scans = Arc::new(AtomicUsize::new(0));
+    let scans_ = scans.clone();
+    let _tx = spawn_mock_http_server(Arc::new(move |req: HttpMessage| {
+        scans_.fetch_add(1, Ordering::Relaxed);
+        let request =
+            serde_json::from_slice::<hooks::Request>(req.body.as_deref().unwrap_or_default())
+                .unwrap();
+        let contents = STANDARD
+            .decode(request.message.unwrap().contents.as_bytes())
+            .unwrap();
+        let response = if contents.windows(5).any(|window| window == b"EICAR") {
+            hooks::Response {
+                action: hooks::Action::Reject,
+                response: SmtpResponse {
+                    message: Some("Virus found: EICAR-Test-File".into()),
+                    ..Default::default()
+                }
+                .into(),
+                modifications: vec![],
+            }
+        } else {
+            hooks::Response {
+                action: hooks::Action::Accept,
+                response: None,
+                modifications: vec![],
+            }
+        };
+        HttpResponse::new(StatusCode::OK)
+            .with_content_type("application/json")
+            .with_text_body(serde_json::to_string(&response).unwrap())
+    }))
+    .await;
+
+    // Enable upload scanning
+    let original_core = params.server.inner.shared_core.load_full();
+    let mut core = original_core.as_ref().clone();
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    core.jmap.upload_scan_block_types = vec!["application/x-msdownload".into(), "video/*".into()];
+    core.jmap.upload_scan_async_size = 1024;
+    core.smtp.session.hooks.push(MTAHook {
+        enable: IfBlock::new::<()>("session.hook.scanner.enable", [], "true"),
+        id: "scanner".into(),
+        url: "https://127.0.0.1:9090/scan".into(),
+        timeout: Duration::from_secs(5),
+        headers,
+        tls_allow_invalid_certs: true,
+        tempfail_on_error: true,
+        run_on_stage: AHashSet::from_iter([Stage::Upload]),
+        max_response_size: 1024 * 1024,
+    });
+    params.server.inner.shared_core.store(core.into());
+    let server = params.server.inner.build_server();
+    server.core.storage.data.blob_expire_all().await;
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+    let upload = |content_type: &'static str, data: Vec<u8>| {
+        client
+            .post(format!("https://127.0.0.1:8899/jmap/upload/{account_id}/"))
+            .basic_auth("jdoe@example.com", Some("12345"))
+            .header(CONTENT_TYPE, content_type)
+            .body(data)
+            .send()
+    };
+
+    // Blocked types are rejected without contacting the scanners
+    for content_type in ["application/x-msdownload", "video/mp4; codecs=avc1"] {
+        let response = upload(content_type, b"MZ executable".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 422);
+        let problem = response.json::<Value>().await.unwrap();
+        assert_eq!(
+            problem["title"].as_str(),
+            Some("Upload rejected"),
+            "{problem:?}"
+        );
+        assert!(
+            problem["detail"]
+                .as_str()
+                .unwrap()
+                .contains("are not allowed"),
+            "{problem:?}"
+        );
+    }
+    assert_eq!(scans.load(Ordering::Relaxed), 0);
+
+    // Files flagged by a scanner are rejected
+    let response = upload("application/pdf", b"%PDF EICAR test".to_vec())
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(
+        response.json::<Value>().await.unwrap()["detail"].as_str(),
+        Some("Virus found: EICAR-Test-File")
+    );
+    assert_eq!(scans.load(Ordering::Relaxed), 1);
+
+    // Clean files are accepted and their verdict is cached
+    for _ in 0..2 {
+        let response = upload("application/pdf", b"%PDF clean document".to_vec())
+            .await
+            .unwrap()
+            .json::<Value>()
+            .await
+            .unwrap();
+        assert_eq!(
+            response["scanStatus"].as_str(),
+            Some("clean"),
+            "{response:?}"
+        );
+        assert_eq!(scans.load(Ordering::Relaxed), 2);
+    }
+
+    // Large files are scanned in the background
+    let large_file = vec![b'A'; 2048];
+    let response = upload("application/octet-stream", large_file.clone())
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert_eq!(
+        response["scanStatus"].as_str(),
+        Some("pending"),
+        "{response:?}"
+    );
+    let hash = BlobHash::generate(&large_file);
+    let mut verdict = None;
+    for _ in 0..20 {
+        verdict = server.upload_scan_verdict(&hash).await.unwrap();
+        if verdict != Some(ScanVerdict::Pending) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(verdict, Some(ScanVerdict::Clean));
+
+    // Blob/upload rejects files using the same policy
+    let response = jmap_json_request(
+        r#"[[
+            "Blob/upload",
+            {
+             "accountId": "$$",
+             "create": {
+              "abc": {
+               "data" : [{"data:asText": "EICAR in a blob"}],
+               "type": "text/plain"
+              }
+             }
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id.to_string()),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/notCreated/abc/type")
+            .and_then(|v| v.as_str()),
+        Some("forbidden"),
+        "Response: {response:?}"
+    );
+
+    // Attachments are scanned again on submission when they have no recent verdict
+    let request = ScanRequest {
+        login: "jdoe@example.com",
+        account_id: account_id.document_id(),
+        remote_ip: None,
+        session_id: 0,
+    };
+    let message = |attachment: &str| {
+        format!(
+            concat!(
+                "From: jdoe@example.com\r\n",
+                "To: jane@example.com\r\n",
+                "Subject: Report\r\n",
+                "Content-Type: multipart/mixed; boundary=\"b\"\r\n",
+                "\r\n",
+                "--b\r\n",
+                "Content-Type: text/plain\r\n",
+                "\r\n",
+                "See attached.\r\n",
+                "--b\r\n",
+                "Content-Type: application/pdf; name=\"report.pdf\"\r\n",
+                "Content-Disposition: attachment; filename=\"report.pdf\"\r\n",
+                "\r\n",
+                "{}\r\n",
+                "--b--\r\n"
+            ),
+            attachment
+        )
+    };
+    assert_eq!(
+        server
+            .upload_scan_message(request, message("%PDF clean document").as_bytes())
+            .await
+            .unwrap(),
+        Ok(())
+    );
+    assert_eq!(scans.load(Ordering::Relaxed), 4);
+    server
+        .in_memory_store()
+        .key_delete_prefix(&[KV_UPLOAD_SCAN])
+        .await
+        .unwrap();
+    assert_eq!(
+        server
+            .upload_scan_message(request, message("%PDF clean document").as_bytes())
+            .await
+            .unwrap(),
+        Ok(())
+    );
+    assert_eq!(scans.load(Ordering::Relaxed), 5);
+    assert_eq!(
+        server
+            .upload_scan_message(request, message("%PDF EICAR document").as_bytes())
+            .await
+            .unwrap(),
+        Err("Attachment \"report.pdf\" was rejected: Virus found: EICAR-Test-File".to_string())
+    );
+
+    // Restore configuration
+    params.server.inner.shared_core.store(original_core);
+    server.core.storage.data.blob_expire_all().await;
+}
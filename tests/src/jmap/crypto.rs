@@ -4,29 +4,80 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::path::PathBuf;
+use std::{io::Write, path::PathBuf, sync::Arc, time::Duration};
 
-use email::message::crypto::{
-    Algorithm, EncryptMessage, EncryptionMethod, EncryptionParams, EncryptionType, try_parse_certs,
+use aes_gcm::{Aes128Gcm, Aes256Gcm, KeyInit, Nonce, aead::Aead, aes::cipher::BlockDecrypt};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use common::auth::AccessToken;
+use email::{
+    mailbox::INBOX_ID,
+    message::{
+        copy::EmailCopy,
+        crypto::{
+            Algorithm, AuthEnvelopedData, BASE64_MIME_BLOCK, Base64MimeWriter, CertParseError,
+            Compression, DecryptMessage, DecryptMessageError, DecryptionKey,
+            ENCRYPT_STREAM_THRESHOLD, EccCmsSharedInfo, EncryptMessage, EncryptMessageError,
+            EncryptionIdentities, EncryptionMethod, EncryptionParams, EncryptionSummary,
+            EncryptionType, GcmParameters, RsaPadding, certificate_info, content_info_header,
+            fingerprint, pgp_symmetric_algorithm, try_parse_certs, try_parse_certs_with_password,
+            validate_certs,
+        },
+        ingest::{EmailIngest, IngestEmail, IngestSource},
+        integrity::{
+            EmailIntegrity, IntegrityFailure, IntegrityReason, IntegrityReportEntry,
+            IntegritySource, IntegrityStatus,
+        },
+        wkd::{wkd_certs, wkd_urls},
+    },
 };
-use jmap_proto::types::id::Id;
-use mail_parser::{MessageParser, MimeHeaders};
+use http::{
+    auth::client_cert::{ClientCertificateGate, parse_client_certificate},
+    management::queue::Message as QueuedMessage,
+};
+use http_proto::HttpSessionData;
+use jmap_proto::types::{collection::Collection, id::Id, property::Property};
+use mail_parser::{MessageParser, MimeHeaders, PartType};
+use rasn_cms::{
+    AlgorithmIdentifier, CONTENT_ENVELOPED_DATA, EnvelopedData, OriginatorIdentifierOrKey,
+    RecipientInfo, pkcs7_compat::EncapsulatedContentInfo,
+};
+use rsa::{Oaep, Pkcs1v15Encrypt, RsaPrivateKey, pkcs8::DecodePrivateKey};
+use sequoia_openpgp::{
+    self as openpgp, Cert, KeyHandle,
+    cert::CertBuilder,
+    crypto::SessionKey,
+    packet::{PKESK, SKESK},
+    parse::{
+        Parse,
+        stream::{
+            DecryptionHelper, DecryptorBuilder, MessageLayer, MessageStructure, VerificationHelper,
+        },
+    },
+    policy::StandardPolicy,
+    serialize::SerializeInto,
+    types::{CompressionAlgorithm, SymmetricAlgorithm},
+};
+use sha2::Digest;
+use smtp::queue::Status;
 use store::{
     Deserialize, Serialize,
-    write::{Archive, Archiver},
+    rand::{Rng, rng},
+    write::{Archive, Archiver, BatchBuilder, now},
 };
+use trc::{Collector, EncryptEvent, EventType, ipc::subscriber::Interests};
 
 use crate::{
     directory::internal::TestInternalDirectory,
-    jmap::{ManagementApi, delivery::SmtpConnection},
+    jmap::{ManagementApi, delivery::SmtpConnection, enterprise::List},
+    smtp::{TestSMTP, session::test_server_instance},
 };
 
-use super::JMAPTest;
+use super::{JMAPTest, wait_for_index};
 
 pub async fn test(params: &mut JMAPTest) {
     println!("Running Encryption-at-rest tests...");
 
-    // Create test account
+    // Create test account, the user ID of the OpenPGP test key is john@example.org
     let server = params.server.clone();
     let client = &mut params.client;
     let account_id = Id::from(
@@ -38,7 +89,7 @@ pub async fn test(params: &mut JMAPTest) {
                 "jdoe@example.com",
                 "12345",
                 "John Doe",
-                &["jdoe@example.com"],
+                &["jdoe@example.com", "john@example.org"],
             )
             .await,
     )
@@ -47,11 +98,41 @@ pub async fn test(params: &mut JMAPTest) {
     // Build API
     let api = ManagementApi::new(8899, "jdoe@example.com", "12345");
 
+    // OpenPGP keys have to belong to one of the account's addresses
+    api.post::<u32>(
+        "/api/account/crypto",
+        &EncryptionType::PGP {
+            algo: Algorithm::Aes256,
+            certs: std::fs::read_to_string(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("resources")
+                    .join("crypto")
+                    .join("cert_pgp_subkeys.pem"),
+            )
+            .unwrap(),
+            exclude_mailboxes: vec![],
+            fetch_wkd: false,
+            max_encrypt_size: None,
+            compression: None,
+        },
+    )
+    .await
+    .unwrap()
+    .expect_error("no user ID matching");
+
     // Try importing using multiple methods and symmetric algos
     for (file_name, method, num_certs) in [
-        ("cert_smime.pem", EncryptionMethod::SMIME, 3),
+        ("cert_smime_rsa.pem", EncryptionMethod::SMIME, 1),
         ("cert_pgp.pem", EncryptionMethod::PGP, 1),
     ] {
+        // Identities are appended, so start each method from scratch
+        assert_eq!(
+            api.post::<Option<String>>("/api/account/crypto", &EncryptionType::Disabled)
+                .await
+                .unwrap()
+                .unwrap_data(),
+            None
+        );
         let certs = std::fs::read_to_string(
             PathBuf::from(env!("CARGO_MANIFEST_DIR"))
                 .join("resources")
@@ -60,15 +141,38 @@ pub async fn test(params: &mut JMAPTest) {
         )
         .unwrap();
 
-        for algo in [Algorithm::Aes128, Algorithm::Aes256] {
+        for algo in [
+            Algorithm::Aes128,
+            Algorithm::Aes256,
+            Algorithm::Aes128Gcm,
+            Algorithm::Aes256Gcm,
+            Algorithm::Chacha20Poly1305,
+        ] {
+            if method == EncryptionMethod::PGP
+                && matches!(
+                    algo,
+                    Algorithm::Aes128Gcm | Algorithm::Aes256Gcm | Algorithm::Chacha20Poly1305
+                )
+            {
+                continue;
+            }
+
             let request = match method {
                 EncryptionMethod::PGP => EncryptionType::PGP {
                     algo,
                     certs: certs.clone(),
+                    exclude_mailboxes: vec![],
+                    fetch_wkd: false,
+                    max_encrypt_size: None,
+                    compression: None,
                 },
                 EncryptionMethod::SMIME => EncryptionType::SMIME {
                     algo,
+                    padding: RsaPadding::Oaep,
                     certs: certs.clone(),
+                    certificate_password: None,
+                    exclude_mailboxes: vec![],
+                    max_encrypt_size: None,
                 },
             };
 
@@ -122,6 +226,118 @@ pub async fn test(params: &mut JMAPTest) {
     )
     .await;
 
+    // Exclude the Inbox from encryption
+    let certs = std::fs::read_to_string(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("crypto")
+            .join("cert_pgp.pem"),
+    )
+    .unwrap();
+    assert_eq!(
+        api.post::<u32>(
+            "/api/account/crypto",
+            &EncryptionType::PGP {
+                algo: Algorithm::Aes256,
+                certs,
+                exclude_mailboxes: vec![INBOX_ID],
+                fetch_wkd: false,
+                max_encrypt_size: None,
+                compression: None,
+            }
+        )
+        .await
+        .unwrap()
+        .unwrap_data(),
+        1
+    );
+
+    // Only a summary of the certificates should be returned, uploading the
+    // same certificates again replaced the existing identity
+    let mut summaries = api
+        .get::<Vec<EncryptionSummary>>("/api/account/crypto/identities")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(summaries.len(), 1, "{summaries:?}");
+    match summaries.pop().unwrap() {
+        EncryptionSummary::PGP {
+            algo,
+            certificates,
+            exclude_mailboxes,
+            max_encrypt_size,
+            compression,
+            updated_at,
+        } => {
+            assert!(matches!(algo, Algorithm::Aes256));
+            assert_eq!(certificates.len(), 1);
+            assert_eq!(
+                certificates[0].fingerprint,
+                "16C31D5E33E01AA3CA241B32F8E60ACC4D2820C5"
+            );
+            assert_eq!(
+                certificates[0].subject.as_deref(),
+                Some("John Doe <john@example.org>")
+            );
+            assert_eq!(exclude_mailboxes, vec![INBOX_ID]);
+            assert_eq!(max_encrypt_size, 52428800);
+            assert_eq!(compression, None);
+            assert!(updated_at.is_some_and(|updated_at| updated_at <= now()));
+        }
+        summary => panic!("Unexpected encryption summary: {summary:?}"),
+    }
+
+    // The original endpoint returns the settings with the certificates in a format
+    // that can be uploaded again
+    match api
+        .get::<EncryptionType>("/api/account/crypto")
+        .await
+        .unwrap()
+        .unwrap_data()
+    {
+        EncryptionType::PGP {
+            algo,
+            certs,
+            exclude_mailboxes,
+            ..
+        } => {
+            assert!(matches!(algo, Algorithm::Aes256));
+            assert_eq!(exclude_mailboxes, vec![INBOX_ID]);
+            assert!(certs.starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----"));
+            assert_eq!(
+                try_parse_certs(EncryptionMethod::PGP, certs.into_bytes())
+                    .unwrap()
+                    .iter()
+                    .map(|cert| fingerprint(EncryptionMethod::PGP, cert))
+                    .collect::<Vec<_>>(),
+                vec!["16C31D5E33E01AA3CA241B32F8E60ACC4D2820C5".to_string()]
+            );
+        }
+        encryption => panic!("Unexpected encryption settings: {encryption:?}"),
+    }
+
+    // The encryption status should be reported in the JMAP session
+    let status = session_encryption_status(&account_id).await;
+    assert_eq!(status["isEnabled"], true, "{status}");
+    assert_eq!(status["method"], "OpenPGP", "{status}");
+    assert_eq!(status["algorithm"], "AES-256", "{status}");
+    assert_eq!(status["numCertificates"], 1, "{status}");
+
+    // Send a new message to an excluded mailbox, which should NOT be encrypted
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report (excluded mailbox)\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP. ",
+            "So, if you could do that, that'd be great."
+        ),
+    )
+    .await;
+
     // Disable encryption
     assert_eq!(
         api.post::<Option<String>>("/api/account/crypto", &EncryptionType::Disabled)
@@ -130,6 +346,9 @@ pub async fn test(params: &mut JMAPTest) {
             .unwrap_data(),
         None
     );
+    let status = session_encryption_status(&account_id).await;
+    assert_eq!(status["isEnabled"], false, "{status}");
+    assert!(status.get("method").is_none(), "{status}");
 
     // Send a new message, which should NOT be encrypted
     lmtp.ingest(
@@ -151,7 +370,7 @@ pub async fn test(params: &mut JMAPTest) {
     let mut request = client.build();
     request.get_email();
     let emails = request.send_get_email().await.unwrap().take_list();
-    assert_eq!(emails.len(), 3, "3 messages were expected: {:#?}.", emails);
+    assert_eq!(emails.len(), 4, "4 messages were expected: {:#?}.", emails);
 
     for email in emails {
         let message =
@@ -167,7 +386,7 @@ pub async fn test(params: &mut JMAPTest) {
                     && message.contains("xjMEZMYfNhYJKwYBBAHaRw8BAQdAYy"),
                 "got message {message}, expected message to be left intact"
             );
-        } else if message.contains("plain text") {
+        } else if message.contains("plain text") || message.contains("excluded mailbox") {
             assert!(
                 message.contains("I'm going to need those TPS reports ASAP."),
                 "got message {message}, expected plain text message"
@@ -176,93 +395,3107 @@ pub async fn test(params: &mut JMAPTest) {
             panic!("Unexpected message: {:#?}", message)
         }
     }
-}
 
-#[tokio::test]
-pub async fn import_certs_and_encrypt() {
-    for (name, method, expected_certs) in [
-        ("cert_pgp.pem", EncryptionMethod::PGP, 1),
-        //("cert_pgp.der", EncryptionMethod::PGP, 1),
-        ("cert_smime.pem", EncryptionMethod::SMIME, 3),
-        ("cert_smime.der", EncryptionMethod::SMIME, 1),
-    ] {
-        let mut certs = try_parse_certs(
-            method,
-            std::fs::read(
-                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-                    .join("resources")
-                    .join("crypto")
-                    .join(name),
-            )
-            .unwrap(),
+    // Enable encryption and encrypt the existing messages
+    let certs = std::fs::read_to_string(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("crypto")
+            .join("cert_pgp.pem"),
+    )
+    .unwrap();
+    assert_eq!(
+        api.post::<u32>(
+            "/api/account/crypto",
+            &EncryptionType::PGP {
+                algo: Algorithm::Aes256,
+                certs,
+                exclude_mailboxes: vec![],
+                fetch_wkd: false,
+                max_encrypt_size: None,
+                compression: None,
+            }
         )
-        .expect(name);
-
-        assert_eq!(certs.len(), expected_certs);
+        .await
+        .unwrap()
+        .unwrap_data(),
+        1
+    );
+    assert_eq!(
+        api.post::<u64>("/api/account/crypto/existing", &())
+            .await
+            .unwrap()
+            .unwrap_data(),
+        4
+    );
+    wait_for_index(&server).await;
+    assert_eq!(
+        api.get::<serde_json::Value>("/api/account/crypto/existing")
+            .await
+            .unwrap()
+            .unwrap_data(),
+        serde_json::json!({"total": 4, "pending": 0})
+    );
 
-        if method == EncryptionMethod::PGP && certs.len() == 2 {
-            // PGP library won't encrypt using EC
-            certs.pop();
+    // Plain text messages should now be encrypted
+    let mut request = client.build();
+    request.get_email();
+    let emails = request.send_get_email().await.unwrap().take_list();
+    assert_eq!(emails.len(), 4, "4 messages were expected: {:#?}.", emails);
+    for email in emails {
+        let message =
+            String::from_utf8(client.download(email.blob_id().unwrap()).await.unwrap()).unwrap();
+        if message.contains("already encrypted") {
+            assert!(
+                message.contains("Content-Type: application/pkcs7-mime")
+                    && message.contains("xjMEZMYfNhYJKwYBBAHaRw8BAQdAYy"),
+                "got message {message}, expected message to be left intact"
+            );
+        } else {
+            assert!(
+                message.contains("Content-Type: multipart/encrypted")
+                    && !message.contains("I'm going to need those TPS reports ASAP."),
+                "got message {message}, expected encrypted message"
+            );
+            assert_eq!(email.size(), message.len());
         }
+    }
 
-        let mut params = EncryptionParams {
-            method,
-            algo: Algorithm::Aes128,
-            certs,
-        };
+    // Messages above the size limit should be stored unencrypted
+    let certs = std::fs::read_to_string(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("crypto")
+            .join("cert_pgp.pem"),
+    )
+    .unwrap();
+    for max_encrypt_size in [Some(1024), None] {
+        assert_eq!(
+            api.post::<u32>(
+                "/api/account/crypto",
+                &EncryptionType::PGP {
+                    algo: Algorithm::Aes256,
+                    certs: certs.clone(),
+                    exclude_mailboxes: vec![],
+                    fetch_wkd: false,
+                    max_encrypt_size,
+                    compression: None,
+                }
+            )
+            .await
+            .unwrap()
+            .unwrap_data(),
+            1
+        );
+        match api
+            .get::<Vec<EncryptionSummary>>("/api/account/crypto/identities")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .as_slice()
+        {
+            [
+                EncryptionSummary::PGP {
+                    max_encrypt_size: size,
+                    ..
+                },
+            ] => assert_eq!(*size, max_encrypt_size.unwrap_or(52428800)),
+            summary => panic!("Unexpected encryption summary: {summary:?}"),
+        }
+        if max_encrypt_size.is_none() {
+            break;
+        }
 
-        for algo in [Algorithm::Aes128, Algorithm::Aes256] {
-            let message = MessageParser::new()
-                .parse(b"Subject: test\r\ntest\r\n")
-                .unwrap();
-            assert!(!message.is_encrypted());
-            params.algo = algo;
-            let arch =
-                Archive::deserialize_owned(Archiver::new(params.clone()).serialize().unwrap())
+        lmtp.ingest(
+            "bill@example.com",
+            &["jdoe@example.com"],
+            &format!(
+                concat!(
+                    "From: bill@example.com\r\n",
+                    "To: jdoe@example.com\r\n",
+                    "Subject: TPS Report (too large)\r\n",
+                    "\r\n",
+                    "I'm going to need those TPS reports ASAP. {}"
+                ),
+                "So, if you could do that, that'd be great. ".repeat(30)
+            ),
+        )
+        .await;
+        let mut request = client.build();
+        request.get_email();
+        let emails = request.send_get_email().await.unwrap().take_list();
+        assert_eq!(emails.len(), 5, "5 messages were expected: {:#?}.", emails);
+        let mut found = false;
+        for email in emails {
+            let message =
+                String::from_utf8(client.download(email.blob_id().unwrap()).await.unwrap())
                     .unwrap();
-            message
-                .encrypt(arch.unarchive::<EncryptionParams>().unwrap())
-                .await
-                .unwrap();
+            if message.contains("too large") {
+                assert!(
+                    message.contains("I'm going to need those TPS reports ASAP."),
+                    "got message {message}, expected plain text message"
+                );
+                client.email_destroy(email.id().unwrap()).await.unwrap();
+                found = true;
+            }
         }
+        assert!(found);
     }
 
-    // S/MIME and PGP should not be allowed mixed
+    // Re-encrypted messages record the change in their integrity history
+    let account_id_ = Id::from_bytes(account_id.as_bytes()).unwrap().document_id();
+    let mut request = client.build();
+    request.get_email();
+    let emails = request.send_get_email().await.unwrap().take_list();
+    let mut document_ids = Vec::new();
+    let mut encrypted = 0;
+    for email in &emails {
+        let document_id = Id::from_bytes(email.id().unwrap().as_bytes())
+            .unwrap()
+            .document_id();
+        let integrity = server
+            .message_integrity_get(account_id_, document_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let reasons = integrity
+            .changes
+            .iter()
+            .map(|change| change.reason)
+            .collect::<Vec<_>>();
+        if reasons.len() == 2 {
+            assert_eq!(
+                reasons,
+                vec![IntegrityReason::Ingest, IntegrityReason::Encrypt]
+            );
+            encrypted += 1;
+        } else {
+            assert_eq!(reasons, vec![IntegrityReason::Ingest]);
+        }
+        assert!(
+            integrity
+                .changes
+                .iter()
+                .all(|change| change.actor.is_none())
+        );
+        assert_eq!(
+            server
+                .verify_message_integrity(account_id_, document_id, None)
+                .await
+                .unwrap()
+                .status,
+            IntegrityStatus::Valid
+        );
+        document_ids.push(document_id);
+    }
+    assert_eq!(encrypted, 2);
+
+    // Tampering with a stored message should be detected on download and audit
+    let document_id = document_ids[0];
+    let hash = server
+        .message_integrity_get(account_id_, document_id)
+        .await
+        .unwrap()
+        .unwrap()
+        .current()
+        .unwrap()
+        .hash
+        .clone();
+    let original = server
+        .blob_store()
+        .get_blob(hash.as_slice(), 0..usize::MAX)
+        .await
+        .unwrap()
+        .unwrap();
+    server
+        .blob_store()
+        .put_blob(
+            hash.as_slice(),
+            b"Subject: tampered\r\n\r\nThis was changed.",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        server
+            .verify_message_integrity(account_id_, document_id, None)
+            .await
+            .unwrap()
+            .status,
+        IntegrityStatus::Failed(IntegrityFailure::HashMismatch)
+    );
+    client.download(emails[0].blob_id().unwrap()).await.unwrap();
+    let admin_api = ManagementApi::new(8899, "admin", "secret");
+    let report = admin_api
+        .get::<List<IntegrityReportEntry>>("/api/store/integrity")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items;
+    assert_eq!(report.len(), 1, "{report:?}");
+    assert_eq!(report[0].account_id, account_id_);
+    assert_eq!(report[0].document_id, document_id);
+    assert_eq!(report[0].blob_hash, hash.to_hex());
+    assert_eq!(report[0].failure, IntegrityFailure::HashMismatch);
+    assert_eq!(report[0].source, IntegritySource::Download);
+    api.get::<List<IntegrityReportEntry>>("/api/store/integrity")
+        .await
+        .unwrap()
+        .expect_request_error("Forbidden");
+    let stats = server.audit_messages_sample().await.unwrap();
+    assert!(stats.checked >= 4 && stats.failed >= 1, "{stats:?}");
     assert!(
-        try_parse_certs(
-            EncryptionMethod::PGP,
-            std::fs::read(
-                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-                    .join("resources")
-                    .join("crypto")
-                    .join("cert_mixed.pem"),
-            )
-            .unwrap(),
+        admin_api
+            .get::<List<IntegrityReportEntry>>("/api/store/integrity")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .items
+            .iter()
+            .any(|item| item.document_id == document_id
+                && item.account_id == account_id_
+                && item.source == IntegritySource::Audit)
+    );
+
+    // Restore the message and clear the report
+    server
+        .blob_store()
+        .put_blob(hash.as_slice(), &original)
+        .await
+        .unwrap();
+    assert_eq!(
+        server
+            .verify_message_integrity(account_id_, document_id, None)
+            .await
+            .unwrap()
+            .status,
+        IntegrityStatus::Valid
+    );
+    admin_api
+        .delete::<()>(&format!("/api/store/integrity/{account_id_}-{document_id}"))
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(
+        admin_api
+            .get::<List<IntegrityReportEntry>>("/api/store/integrity")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .total,
+        0
+    );
+
+    // Disable encryption
+    assert_eq!(
+        api.post::<Option<String>>("/api/account/crypto", &EncryptionType::Disabled)
+            .await
+            .unwrap()
+            .unwrap_data(),
+        None
+    );
+
+    // Administrators can configure encryption on behalf of other accounts
+    admin_api
+        .post::<u32>(
+            "/api/crypto/jdoe@example.com",
+            &EncryptionType::PGP {
+                algo: Algorithm::Aes256,
+                certs: "invalid".into(),
+                exclude_mailboxes: vec![],
+                fetch_wkd: false,
+                max_encrypt_size: None,
+                compression: None,
+            },
         )
-        .is_err()
+        .await
+        .unwrap()
+        .expect_error("Could not find any valid certificates");
+    admin_api
+        .post::<u32>(
+            "/api/crypto/jdoe@example.com",
+            &EncryptionType::PGP {
+                algo: Algorithm::Aes128Gcm,
+                certs: "invalid".into(),
+                exclude_mailboxes: vec![],
+                fetch_wkd: false,
+                max_encrypt_size: None,
+                compression: None,
+            },
+        )
+        .await
+        .unwrap()
+        .expect_error("only supported for S/MIME");
+    admin_api
+        .post::<u32>("/api/crypto/unknown@example.com", &EncryptionType::Disabled)
+        .await
+        .unwrap()
+        .expect_error("notFound");
+    api.post::<u32>("/api/crypto/jdoe@example.com", &EncryptionType::Disabled)
+        .await
+        .unwrap()
+        .expect_request_error("Forbidden");
+    assert_eq!(
+        admin_api
+            .post::<u32>(
+                "/api/crypto/jdoe@example.com",
+                &EncryptionType::PGP {
+                    algo: Algorithm::Aes128,
+                    certs: std::fs::read_to_string(
+                        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                            .join("resources")
+                            .join("crypto")
+                            .join("cert_pgp.pem"),
+                    )
+                    .unwrap(),
+                    exclude_mailboxes: vec![],
+                    fetch_wkd: false,
+                    max_encrypt_size: None,
+                    compression: None,
+                },
+            )
+            .await
+            .unwrap()
+            .unwrap_data(),
+        1
     );
-}
+    for api in [&admin_api, &api] {
+        let path = if api.username == "admin" {
+            "/api/crypto/jdoe@example.com"
+        } else {
+            "/api/account/crypto/identities"
+        };
+        assert!(matches!(
+            api.get::<Vec<EncryptionSummary>>(path)
+                .await
+                .unwrap()
+                .unwrap_data()
+                .as_slice(),
+            [EncryptionSummary::PGP {
+                algo: Algorithm::Aes128,
+                ..
+            }]
+        ));
+    }
 
-#[test]
-pub fn check_is_encrypted() {
-    let messages = std::fs::read_to_string(
-        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("resources")
-            .join("crypto")
-            .join("is_encrypted.txt"),
+    // Messages filed by Sieve scripts should be encrypted
+    let script_id = client
+        .sieve_script_create(
+            "test_encrypt",
+            b"require \"fileinto\"; fileinto \"INBOX\";".to_vec(),
+            true,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report (fileinto)\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP."
+        ),
     )
-    .unwrap();
+    .await;
+    client.sieve_script_deactivate().await.unwrap();
+    client.sieve_script_destroy(&script_id).await.unwrap();
+    let mut request = client.build();
+    request.get_email();
+    let email = request
+        .send_get_email()
+        .await
+        .unwrap()
+        .take_list()
+        .into_iter()
+        .find(|email| email.subject() == Some("TPS Report (fileinto)"))
+        .unwrap();
+    let message =
+        String::from_utf8(client.download(email.blob_id().unwrap()).await.unwrap()).unwrap();
+    assert!(
+        message.contains("Content-Type: multipart/encrypted"),
+        "got message {message}, expected encrypted message"
+    );
 
-    for raw_message in messages.split("!!!") {
-        let is_encrypted = raw_message.contains("TRUE");
-        let message = MessageParser::new()
-            .parse(raw_message.trim().as_bytes())
+    // Messages copied or appended by other users should be encrypted, while
+    // appends made by the account owner are left as is
+    let other_account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "bill.lumbergh@example.com",
+            "12345",
+            "Bill Lumbergh",
+            &["bill.lumbergh@example.com"],
+        )
+        .await;
+    let resource_token = server
+        .get_access_token(account_id_)
+        .await
+        .unwrap()
+        .as_resource_token();
+    let raw_message = concat!(
+        "From: bill.lumbergh@example.com\r\n",
+        "To: jdoe@example.com\r\n",
+        "Subject: TPS Report (shared)\r\n",
+        "\r\n",
+        "I'm going to need those TPS reports ASAP."
+    )
+    .as_bytes();
+    let ingest = |resource_token, source| IngestEmail {
+        raw_message,
+        message: MessageParser::new().parse(raw_message),
+        resource: resource_token,
+        mailbox_ids: vec![INBOX_ID],
+        keywords: vec![],
+        received_at: None,
+        source,
+        spam_classify: false,
+        spam_train: false,
+        session_id: 0,
+    };
+    let source = server
+        .email_ingest(ingest(
+            server
+                .get_access_token(other_account_id)
+                .await
+                .unwrap()
+                .as_resource_token(),
+            IngestSource::Jmap { shared: false },
+        ))
+        .await
+        .unwrap();
+    let copied = server
+        .copy_message(
+            other_account_id,
+            source.id.document_id(),
+            &resource_token,
+            vec![INBOX_ID],
+            vec![],
+            None,
+            0,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    let appended = server
+        .email_ingest(ingest(
+            resource_token.clone(),
+            IngestSource::Imap { shared: true },
+        ))
+        .await
+        .unwrap();
+    let owner_appended = server
+        .email_ingest(ingest(
+            resource_token.clone(),
+            IngestSource::Imap { shared: false },
+        ))
+        .await
+        .unwrap();
+    for (email, is_encrypted) in [
+        (source, false),
+        (copied, true),
+        (appended, true),
+        (owner_appended, false),
+    ] {
+        let raw_message = server
+            .blob_store()
+            .get_blob(email.blob_id.hash.as_slice(), 0..usize::MAX)
+            .await
+            .unwrap()
             .unwrap();
-        assert!(message.content_type().is_some());
         assert_eq!(
-            message.is_encrypted(),
+            MessageParser::new()
+                .parse(&raw_message)
+                .unwrap()
+                .is_encrypted(),
             is_encrypted,
-            "failed for {raw_message}"
+            "{}",
+            String::from_utf8_lossy(&raw_message)
         );
     }
+
+    // OpenPGP and S/MIME identities cannot be combined
+    admin_api
+        .post::<u32>(
+            "/api/crypto/jdoe@example.com",
+            &EncryptionType::SMIME {
+                algo: Algorithm::Aes256,
+                padding: RsaPadding::Oaep,
+                certs: std::fs::read_to_string(
+                    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                        .join("resources")
+                        .join("crypto")
+                        .join("cert_smime_rsa.pem"),
+                )
+                .unwrap(),
+                certificate_password: None,
+                exclude_mailboxes: vec![],
+                max_encrypt_size: None,
+            },
+        )
+        .await
+        .unwrap()
+        .expect_error("Cannot combine OpenPGP and S/MIME identities");
+
+    // Additional identities are appended to the existing ones and share their cipher
+    let (laptop_key, _) = CertBuilder::new()
+        .add_userid("John Doe <jdoe@example.com>")
+        .add_transport_encryption_subkey()
+        .generate()
+        .unwrap();
+    assert_eq!(
+        admin_api
+            .post::<u32>(
+                "/api/crypto/jdoe@example.com",
+                &EncryptionType::PGP {
+                    algo: Algorithm::Aes256,
+                    certs: String::from_utf8(laptop_key.armored().to_vec().unwrap()).unwrap(),
+                    exclude_mailboxes: vec![],
+                    fetch_wkd: false,
+                    max_encrypt_size: None,
+                    compression: None,
+                },
+            )
+            .await
+            .unwrap()
+            .unwrap_data(),
+        1
+    );
+    assert!(matches!(
+        api.get::<Vec<EncryptionSummary>>("/api/account/crypto/identities")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .as_slice(),
+        [
+            EncryptionSummary::PGP {
+                algo: Algorithm::Aes256,
+                ..
+            },
+            EncryptionSummary::PGP {
+                algo: Algorithm::Aes256,
+                ..
+            }
+        ]
+    ));
+    assert_eq!(
+        session_encryption_status(&account_id).await["numCertificates"],
+        2
+    );
+
+    // Identities can be removed individually by certificate fingerprint
+    let laptop_fingerprint = laptop_key.fingerprint().to_hex();
+    admin_api
+        .delete::<Vec<EncryptionSummary>>("/api/crypto/jdoe@example.com/0000")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+    let remaining = admin_api
+        .delete::<Vec<EncryptionSummary>>(&format!(
+            "/api/crypto/jdoe@example.com/{}",
+            laptop_fingerprint.to_lowercase()
+        ))
+        .await
+        .unwrap()
+        .unwrap_data();
+    let remaining_fingerprint = match remaining.as_slice() {
+        [EncryptionSummary::PGP { certificates, .. }] => {
+            assert!(
+                certificates
+                    .iter()
+                    .all(|cert| cert.fingerprint != laptop_fingerprint)
+            );
+            certificates[0].fingerprint.clone()
+        }
+        other => panic!("Unexpected identities {other:?}"),
+    };
+    assert_eq!(
+        session_encryption_status(&account_id).await["numCertificates"],
+        1
+    );
+
+    // Removing the last identity disables encryption
+    assert!(
+        api.delete::<Vec<EncryptionSummary>>(&format!(
+            "/api/account/crypto/identities/{remaining_fingerprint}"
+        ))
+        .await
+        .unwrap()
+        .unwrap_data()
+        .is_empty()
+    );
+    assert!(
+        api.get::<Vec<EncryptionSummary>>("/api/account/crypto/identities")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .is_empty()
+    );
+    assert_eq!(
+        admin_api
+            .post::<Option<String>>("/api/crypto/jdoe@example.com", &EncryptionType::Disabled)
+            .await
+            .unwrap()
+            .unwrap_data(),
+        None
+    );
+    assert!(
+        api.get::<Vec<EncryptionSummary>>("/api/account/crypto/identities")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .is_empty()
+    );
+
+    // Corrupt the certificates after they were validated
+    assert_eq!(
+        admin_api
+            .post::<u32>(
+                "/api/crypto/jdoe@example.com",
+                &EncryptionType::PGP {
+                    algo: Algorithm::Aes256,
+                    certs: std::fs::read_to_string(
+                        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                            .join("resources")
+                            .join("crypto")
+                            .join("cert_pgp.pem"),
+                    )
+                    .unwrap(),
+                    exclude_mailboxes: vec![],
+                    fetch_wkd: false,
+                    max_encrypt_size: None,
+                    compression: None,
+                },
+            )
+            .await
+            .unwrap()
+            .unwrap_data(),
+        1
+    );
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(account_id_)
+        .with_collection(Collection::Principal)
+        .update_document(0)
+        .set(
+            Property::Parameters,
+            Archiver::new(vec![EncryptionParams {
+                method: EncryptionMethod::PGP,
+                algo: Algorithm::Aes256,
+                padding: RsaPadding::default(),
+                certs: vec![b"corrupted certificate".to_vec()],
+                exclude_mailboxes: vec![],
+                max_encrypt_size: None,
+                compression: None,
+            }])
+            .serialize()
+            .unwrap(),
+        );
+    server
+        .core
+        .storage
+        .data
+        .write(batch.build_all())
+        .await
+        .unwrap();
+
+    // Messages that cannot be encrypted are held in the queue
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report (corrupted certificates)\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP."
+        ),
+    )
+    .await;
+    let message = queued_encryption_failure(&admin_api).await;
+    let rcpt = &message.domains[0].recipients[0];
+    assert!(rcpt.encryption_failed, "{message:?}");
+    match &rcpt.status {
+        Status::TemporaryFailure(reason) => {
+            assert!(reason.contains("Encryption at rest failed"), "{reason}")
+        }
+        status => panic!("Unexpected status: {status:?}"),
+    }
+    assert!(!message.skip_encryption);
+    let mut request = client.build();
+    request.get_email();
+    let num_emails = request.send_get_email().await.unwrap().take_list().len();
+
+    // The account owner and the operators are alerted after consecutive failures
+    admin_api
+        .patch::<bool>(&format!("/api/queue/messages/{:x}", message.id), &())
+        .await
+        .unwrap()
+        .unwrap_data();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let message = queued_encryption_failure(&admin_api).await;
+    assert!(message.domains[0].recipients[0].encryption_failed);
+    for (account_id, expected) in [
+        (account_id.clone(), num_emails + 1),
+        (Id::from(other_account_id).to_string(), 1),
+    ] {
+        client.set_default_account_id(&account_id);
+        let mut request = client.build();
+        request.get_email();
+        let emails = request.send_get_email().await.unwrap().take_list();
+        assert_eq!(emails.len(), expected, "{emails:#?}");
+        let alert = emails
+            .into_iter()
+            .find(|email| {
+                email.subject()
+                    == Some("Incoming messages for jdoe@example.com cannot be encrypted")
+            })
+            .unwrap();
+        let alert =
+            String::from_utf8(client.download(alert.blob_id().unwrap()).await.unwrap()).unwrap();
+        assert!(
+            alert.contains("after 2 delivery attempts")
+                && alert.contains("Failed to parse OpenPGP public key"),
+            "{alert}"
+        );
+    }
+    client.set_default_account_id(&account_id);
+
+    // Operators can allow storing the message unencrypted
+    admin_api
+        .patch::<bool>(
+            &format!("/api/queue/messages/{:x}?skip-encryption=true", message.id),
+            &(),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(
+        admin_api
+            .get::<List<QueuedMessage>>("/api/queue/messages?values=1&to=jdoe@example.com")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .items
+            .is_empty()
+    );
+    let mut request = client.build();
+    request.get_email();
+    let email = request
+        .send_get_email()
+        .await
+        .unwrap()
+        .take_list()
+        .into_iter()
+        .find(|email| email.subject() == Some("TPS Report (corrupted certificates)"))
+        .unwrap();
+    let message =
+        String::from_utf8(client.download(email.blob_id().unwrap()).await.unwrap()).unwrap();
+    assert!(
+        message.contains("I'm going to need those TPS reports ASAP."),
+        "got message {message}, expected plain text message"
+    );
+
+    // Broken parameters can be cleared by an administrator
+    admin_api
+        .delete::<Option<String>>("/api/crypto/jdoe@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(
+        api.get::<Vec<EncryptionSummary>>("/api/account/crypto/identities")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .is_empty()
+    );
+}
+
+async fn queued_encryption_failure(api: &ManagementApi) -> QueuedMessage {
+    let mut messages = api
+        .get::<List<QueuedMessage>>("/api/queue/messages?values=1&to=jdoe@example.com")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items;
+    assert_eq!(messages.len(), 1, "{messages:?}");
+    messages.pop().unwrap()
+}
+
+async fn session_encryption_status(account_id: &str) -> serde_json::Value {
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap()
+        .get("https://127.0.0.1:8899/.well-known/jmap")
+        .header(
+            "Authorization",
+            format!("Basic {}", STANDARD.encode("jdoe@example.com:12345")),
+        )
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    let session = serde_json::from_slice::<serde_json::Value>(&response).unwrap();
+
+    session["accounts"][account_id]["accountCapabilities"]
+        ["urn:stalwart:params:jmap:encryptionAtRest"]
+        .clone()
+}
+
+#[test]
+fn certificate_fingerprints() {
+    // Fingerprints match the ones reported by gpg and openssl
+    for (name, method, expected) in [
+        (
+            "cert_pgp.pem",
+            EncryptionMethod::PGP,
+            "16C31D5E33E01AA3CA241B32F8E60ACC4D2820C5",
+        ),
+        (
+            "cert_smime_rsa.pem",
+            EncryptionMethod::SMIME,
+            "97FCF1813C309784191DCE72F498B98C83CF6915866B382E43257F0B47424ACF",
+        ),
+    ] {
+        let certs = try_parse_certs(
+            method,
+            std::fs::read(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("resources")
+                    .join("crypto")
+                    .join(name),
+            )
+            .unwrap(),
+        )
+        .expect(name);
+        assert_eq!(fingerprint(method, &certs[0]), expected, "{name}");
+        assert_eq!(certificate_info(method, &certs[0]).fingerprint, expected);
+    }
+}
+
+#[tokio::test]
+pub async fn import_certs_and_encrypt() {
+    for (name, method, expected_certs) in [
+        ("cert_pgp.pem", EncryptionMethod::PGP, 1),
+        //("cert_pgp.der", EncryptionMethod::PGP, 1),
+        ("cert_smime.pem", EncryptionMethod::SMIME, 3),
+        ("cert_smime.der", EncryptionMethod::SMIME, 1),
+    ] {
+        let mut certs = try_parse_certs(
+            method,
+            std::fs::read(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("resources")
+                    .join("crypto")
+                    .join(name),
+            )
+            .unwrap(),
+        )
+        .expect(name);
+
+        assert_eq!(certs.len(), expected_certs);
+
+        if method == EncryptionMethod::PGP && certs.len() == 2 {
+            // PGP library won't encrypt using EC
+            certs.pop();
+        }
+
+        let mut params = EncryptionParams {
+            method,
+            algo: Algorithm::Aes128,
+            padding: RsaPadding::default(),
+            certs,
+            exclude_mailboxes: vec![],
+            max_encrypt_size: None,
+            compression: None,
+        };
+
+        for algo in [Algorithm::Aes128, Algorithm::Aes256] {
+            let message = MessageParser::new()
+                .parse(b"Subject: test\r\ntest\r\n")
+                .unwrap();
+            assert!(!message.is_encrypted());
+            params.algo = algo;
+            let arch =
+                Archive::deserialize_owned(Archiver::new(params.clone()).serialize().unwrap())
+                    .unwrap();
+            message
+                .encrypt(
+                    std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+    }
+
+    // S/MIME and PGP should not be allowed mixed
+    assert_eq!(
+        try_parse_certs(
+            EncryptionMethod::PGP,
+            std::fs::read(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("resources")
+                    .join("crypto")
+                    .join("cert_mixed.pem"),
+            )
+            .unwrap(),
+        ),
+        Err(CertParseError::MixedMethods)
+    );
+}
+
+#[tokio::test]
+pub async fn pgp_encryption_subkeys() {
+    // Keys with separate signing and encryption subkeys should encrypt to the encryption subkey
+    let certs = try_parse_certs(
+        EncryptionMethod::PGP,
+        std::fs::read(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("resources")
+                .join("crypto")
+                .join("cert_pgp_subkeys.pem"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(certs.len(), 1);
+    let params = EncryptionParams {
+        method: EncryptionMethod::PGP,
+        algo: Algorithm::Aes256,
+        padding: RsaPadding::default(),
+        certs,
+        exclude_mailboxes: vec![],
+        max_encrypt_size: None,
+        compression: None,
+    };
+    let arch = Archive::deserialize_owned(Archiver::new(params).serialize().unwrap()).unwrap();
+    let encrypted = MessageParser::new()
+        .parse(b"Subject: test\r\ntest\r\n")
+        .unwrap()
+        .encrypt(
+            std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+            None,
+        )
+        .await
+        .unwrap();
+    let recipients = pgp_recipients(&encrypted);
+    assert_eq!(recipients, vec!["4311C24621180191".to_string()]);
+
+    // Keys without an encryption-capable subkey should be rejected
+    let certs = std::fs::read(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("crypto")
+            .join("cert_pgp_sign_only.pem"),
+    )
+    .unwrap();
+    assert!(try_parse_certs(EncryptionMethod::PGP, certs.clone()).is_err());
+    let params = EncryptionParams {
+        method: EncryptionMethod::PGP,
+        algo: Algorithm::Aes256,
+        padding: RsaPadding::default(),
+        certs: vec![certs],
+        exclude_mailboxes: vec![],
+        max_encrypt_size: None,
+        compression: None,
+    };
+    let arch = Archive::deserialize_owned(Archiver::new(params).serialize().unwrap()).unwrap();
+    match MessageParser::new()
+        .parse(b"Subject: test\r\ntest\r\n")
+        .unwrap()
+        .encrypt(
+            std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+            None,
+        )
+        .await
+    {
+        Err(EncryptMessageError::Error(err)) => {
+            assert!(err.contains("encryption-capable subkey"), "{err}")
+        }
+        result => panic!("Unexpected result: {result:?}"),
+    }
+}
+
+#[tokio::test]
+pub async fn pgp_keyrings() {
+    // Keyrings with multiple keys in a single block should be split into one
+    // certificate per key, each recipient getting its own session key packet
+    let certs = try_parse_certs(
+        EncryptionMethod::PGP,
+        std::fs::read(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("resources")
+                .join("crypto")
+                .join("cert_pgp_keyring.pem"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(certs.len(), 2);
+    assert_eq!(
+        certs
+            .iter()
+            .map(|cert| certificate_info(EncryptionMethod::PGP, cert).subject)
+            .collect::<Vec<_>>(),
+        vec![
+            Some("John Doe <john@example.org>".to_string()),
+            Some("Jane Doe <jane@example.com>".to_string())
+        ]
+    );
+    let params = EncryptionParams {
+        method: EncryptionMethod::PGP,
+        algo: Algorithm::Aes256,
+        padding: RsaPadding::default(),
+        certs,
+        exclude_mailboxes: vec![],
+        max_encrypt_size: None,
+        compression: None,
+    };
+    let arch = Archive::deserialize_owned(Archiver::new(params).serialize().unwrap()).unwrap();
+    let encrypted = MessageParser::new()
+        .parse(b"Subject: test\r\ntest\r\n")
+        .unwrap()
+        .encrypt(
+            std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+            None,
+        )
+        .await
+        .unwrap();
+    let mut recipients = pgp_recipients(&encrypted);
+    recipients.sort();
+    assert_eq!(
+        recipients,
+        vec![
+            "3E36E27F669E3250".to_string(),
+            "4311C24621180191".to_string()
+        ]
+    );
+
+    // Invalid keys should be reported with their block number and user id
+    for (certs, expected_error) in [
+        (
+            std::fs::read(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("resources")
+                    .join("crypto")
+                    .join("cert_pgp_invalid.pem"),
+            )
+            .unwrap(),
+            "block 2 (Jane Doe <jane@example.com>): unsupported key version",
+        ),
+        (
+            [
+                "cert_pgp.pem",
+                "cert_pgp_keyring.pem",
+                "cert_pgp_sign_only.pem",
+            ]
+            .into_iter()
+            .flat_map(|name| {
+                std::fs::read(
+                    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                        .join("resources")
+                        .join("crypto")
+                        .join(name),
+                )
+                .unwrap()
+            })
+            .collect(),
+            "block 3 (Sign Only <sign@example.com>): no encryption-capable keys found",
+        ),
+    ] {
+        match try_parse_certs(EncryptionMethod::PGP, certs) {
+            Err(err) => assert_eq!(err.to_string(), expected_error),
+            Ok(certs) => panic!(
+                "Expected error {expected_error:?}, got {} certs",
+                certs.len()
+            ),
+        }
+    }
+}
+
+#[test]
+pub fn cert_parse_error_codes() {
+    // API clients receive a machine-readable code for each parse error
+    for (err, code) in [
+        (CertParseError::MixedMethods, "mixed-certificate-types"),
+        (CertParseError::MethodMismatch, "mixed-certificate-types"),
+        (CertParseError::Pkcs12WrongPassword, "invalid-credentials"),
+        (CertParseError::NoCertificates, "no-valid-certificates"),
+        (
+            CertParseError::InvalidX509("bad".to_string()),
+            "no-valid-certificates",
+        ),
+    ] {
+        assert_eq!(err.code(), code);
+    }
+}
+
+#[test]
+pub fn cert_validation() {
+    // Certificates should be rejected when they are outside their validity period
+    // or their key usage extensions do not allow encryption
+    for (name, method, expected_error) in [
+        ("cert_pgp.pem", EncryptionMethod::PGP, None),
+        ("cert_smime_rsa.pem", EncryptionMethod::SMIME, None),
+        ("cert_smime_ec.pem", EncryptionMethod::SMIME, None),
+        ("cert_smime.der", EncryptionMethod::SMIME, None),
+        (
+            "cert_smime.pem",
+            EncryptionMethod::SMIME,
+            Some(
+                "cannot be used for encryption: keyUsage does not include keyEncipherment or dataEncipherment",
+            ),
+        ),
+        (
+            "cert_smime_tls_only.pem",
+            EncryptionMethod::SMIME,
+            Some(
+                "cannot be used for encryption: extendedKeyUsage does not include emailProtection",
+            ),
+        ),
+        (
+            "cert_smime_expired.pem",
+            EncryptionMethod::SMIME,
+            Some("expired on 2021-01-01T00:00:00"),
+        ),
+        (
+            "cert_smime_future.pem",
+            EncryptionMethod::SMIME,
+            Some("is not valid until 2090-01-01T00:00:00"),
+        ),
+    ] {
+        let certs = try_parse_certs(
+            method,
+            std::fs::read(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("resources")
+                    .join("crypto")
+                    .join(name),
+            )
+            .unwrap(),
+        )
+        .expect(name);
+
+        match (validate_certs(method, &certs), expected_error) {
+            (Ok(warnings), None) => assert!(warnings.is_empty(), "{name}: {warnings:?}"),
+            (Err(err), Some(expected_error)) => {
+                assert!(err.contains(expected_error), "{name}: {err}")
+            }
+            (result, _) => panic!("Unexpected result for {name}: {result:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+pub async fn multiple_identities() {
+    let identity = |method, name: &str, exclude_mailboxes: Vec<u32>| EncryptionParams {
+        method,
+        algo: Algorithm::Aes256,
+        padding: RsaPadding::default(),
+        certs: try_parse_certs(
+            method,
+            std::fs::read(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("resources")
+                    .join("crypto")
+                    .join(name),
+            )
+            .unwrap(),
+        )
+        .unwrap(),
+        exclude_mailboxes,
+        max_encrypt_size: None,
+        compression: None,
+    };
+
+    // Messages are encrypted to the keys of all identities
+    let laptop = identity(EncryptionMethod::PGP, "cert_pgp.pem", vec![INBOX_ID]);
+    let phone = identity(EncryptionMethod::PGP, "cert_pgp_subkeys.pem", vec![]);
+    let arch = Archive::deserialize_owned(
+        Archiver::new(vec![laptop.clone(), phone.clone()])
+            .serialize()
+            .unwrap(),
+    )
+    .unwrap();
+    let identities = arch.unarchive::<Vec<EncryptionParams>>().unwrap();
+    let encrypted = MessageParser::new()
+        .parse(b"Subject: test\r\ntest\r\n")
+        .unwrap()
+        .encrypt(identities, None)
+        .await
+        .unwrap();
+    let recipients = pgp_recipients(&encrypted);
+    assert_eq!(recipients.len(), 2, "{recipients:?}");
+    assert!(recipients.contains(&"4311C24621180191".to_string()));
+
+    // A mailbox is only excluded when all identities exclude it
+    assert!(!identities.is_excluded(&[INBOX_ID]));
+    assert!(identities[..1].is_excluded(&[INBOX_ID]));
+
+    // OpenPGP and S/MIME identities cannot be combined
+    let arch = Archive::deserialize_owned(
+        Archiver::new(vec![
+            laptop,
+            identity(EncryptionMethod::SMIME, "cert_smime.der", vec![]),
+        ])
+        .serialize()
+        .unwrap(),
+    )
+    .unwrap();
+    match MessageParser::new()
+        .parse(b"Subject: test\r\ntest\r\n")
+        .unwrap()
+        .encrypt(arch.unarchive::<Vec<EncryptionParams>>().unwrap(), None)
+        .await
+    {
+        Err(EncryptMessageError::Error(err)) => {
+            assert!(err.contains("cannot be combined"), "{err}")
+        }
+        result => panic!("Unexpected result: {result:?}"),
+    }
+}
+
+#[tokio::test]
+pub async fn encryption_events() {
+    // Encryption events are counted as metrics
+    let mut interests = Interests::default();
+    for event in EventType::variants() {
+        if event.is_metric() {
+            interests.set(event);
+        }
+    }
+    Collector::set_metrics(interests);
+    let counter = |event| Collector::read_event_metric(EventType::Encrypt(event).id());
+    let (success, failed, already_encrypted) = (
+        counter(EncryptEvent::Success),
+        counter(EncryptEvent::Failed),
+        counter(EncryptEvent::AlreadyEncrypted),
+    );
+
+    let params = |certs| {
+        Archive::deserialize_owned(
+            Archiver::new(vec![EncryptionParams {
+                method: EncryptionMethod::PGP,
+                algo: Algorithm::Aes256,
+                padding: RsaPadding::default(),
+                certs,
+                exclude_mailboxes: vec![],
+                max_encrypt_size: None,
+                compression: None,
+            }])
+            .serialize()
+            .unwrap(),
+        )
+        .unwrap()
+    };
+    let valid = params(
+        try_parse_certs(
+            EncryptionMethod::PGP,
+            std::fs::read(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("resources")
+                    .join("crypto")
+                    .join("cert_pgp.pem"),
+            )
+            .unwrap(),
+        )
+        .unwrap(),
+    );
+    let valid = valid.unarchive::<Vec<EncryptionParams>>().unwrap();
+
+    // Successful encryptions
+    let encrypted = MessageParser::new()
+        .parse(b"Subject: test\r\ntest\r\n")
+        .unwrap()
+        .encrypt(valid, None)
+        .await
+        .unwrap();
+    assert!(counter(EncryptEvent::Success) > success);
+
+    // Messages that are already encrypted
+    assert!(matches!(
+        MessageParser::new()
+            .parse(&encrypted)
+            .unwrap()
+            .encrypt(valid, None)
+            .await,
+        Err(EncryptMessageError::AlreadyEncrypted)
+    ));
+    assert!(counter(EncryptEvent::AlreadyEncrypted) > already_encrypted);
+
+    // Failed encryptions
+    let invalid = params(vec![b"not a certificate".to_vec()]);
+    assert!(matches!(
+        MessageParser::new()
+            .parse(b"Subject: test\r\ntest\r\n")
+            .unwrap()
+            .encrypt(invalid.unarchive::<Vec<EncryptionParams>>().unwrap(), None)
+            .await,
+        Err(EncryptMessageError::Error(_))
+    ));
+    assert!(counter(EncryptEvent::Failed) > failed);
+}
+
+#[tokio::test]
+pub async fn client_certificate_gate() {
+    let server = TestSMTP::new(
+        "jmap_client_certificate_gate",
+        r#"
+[http.client-certificate]
+require = ["/api/account/crypto"]
+match-email = true
+"#,
+    )
+    .await
+    .build_smtp();
+    let session = |client_cert| HttpSessionData {
+        instance: Arc::new(test_server_instance()),
+        local_ip: "127.0.0.1".parse().unwrap(),
+        local_port: 443,
+        remote_ip: "127.0.0.1".parse().unwrap(),
+        remote_port: 40000,
+        is_tls: true,
+        session_id: 0,
+        client_cert,
+    };
+    let account = |email: &str| AccessToken {
+        name: email.to_string(),
+        emails: vec![email.to_string()],
+        ..Default::default()
+    };
+
+    // Only the configured endpoints require a certificate
+    assert!(server.requires_client_certificate("/api/account/crypto"));
+    assert!(server.requires_client_certificate("/api/account/crypto/identities"));
+    assert!(!server.requires_client_certificate("/api/account/auth"));
+    assert!(!server.requires_client_certificate("/jmap/session"));
+
+    // Certificate addresses are read from the subject and the alternative names
+    let cert = parse_client_certificate(
+        &std::fs::read(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("resources")
+                .join("crypto")
+                .join("cert_smime.der"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(cert.subject, "CN=Alice Lovelace");
+    assert_eq!(cert.emails, vec!["alice@smime.example".to_string()]);
+    assert!(parse_client_certificate(b"not a certificate").is_none());
+
+    // Requests without a certificate are rejected before authentication
+    let err = server
+        .assert_client_certificate(&session(None), None)
+        .unwrap_err();
+    assert!(err.matches(trc::EventType::Security(
+        trc::SecurityEvent::ClientCertificateRequired
+    )));
+
+    // The certificate has to belong to the authenticated account
+    let session = session(Some(Arc::new(cert)));
+    server.assert_client_certificate(&session, None).unwrap();
+    server
+        .assert_client_certificate(&session, Some(&account("Alice@smime.example")))
+        .unwrap();
+    let err = server
+        .assert_client_certificate(&session, Some(&account("mallory@example.com")))
+        .unwrap_err();
+    assert!(err.matches(trc::EventType::Security(
+        trc::SecurityEvent::ClientCertificateRequired
+    )));
+}
+
+#[tokio::test]
+pub async fn pgp_mime_structure() {
+    let certs = try_parse_certs(
+        EncryptionMethod::PGP,
+        std::fs::read(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("resources")
+                .join("crypto")
+                .join("cert_pgp.pem"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let params = EncryptionParams {
+        method: EncryptionMethod::PGP,
+        algo: Algorithm::Aes256,
+        padding: RsaPadding::default(),
+        certs,
+        exclude_mailboxes: vec![],
+        max_encrypt_size: None,
+        compression: None,
+    };
+    let arch = Archive::deserialize_owned(Archiver::new(params).serialize().unwrap()).unwrap();
+    let encrypted = MessageParser::new()
+        .parse(b"Subject: test\r\n\r\nI'm going to need those TPS reports ASAP.\r\n")
+        .unwrap()
+        .encrypt(
+            std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+            None,
+        )
+        .await
+        .unwrap();
+
+    // All lines, including the armored data, should end with CRLF
+    assert!(
+        encrypted
+            .iter()
+            .enumerate()
+            .all(|(pos, ch)| *ch != b'\n' || pos > 0 && encrypted[pos - 1] == b'\r')
+    );
+
+    // RFC 3156 requires a multipart/encrypted body with exactly two parts
+    let message = MessageParser::new().parse(&encrypted).unwrap();
+    assert_eq!(message.subject(), Some("test"));
+    let content_type = message.content_type().unwrap();
+    assert_eq!(
+        (content_type.ctype(), content_type.subtype()),
+        ("multipart", Some("encrypted"))
+    );
+    assert_eq!(
+        content_type.attribute("protocol"),
+        Some("application/pgp-encrypted")
+    );
+    let sub_parts = match &message.root_part().body {
+        PartType::Multipart(sub_parts) => sub_parts.clone(),
+        body => panic!("Unexpected body: {body:?}"),
+    };
+    assert_eq!(sub_parts.len(), 2);
+
+    // Version identification part
+    let control = message.part(sub_parts[0]).unwrap();
+    let content_type = control.content_type().unwrap();
+    assert_eq!(
+        (content_type.ctype(), content_type.subtype()),
+        ("application", Some("pgp-encrypted"))
+    );
+    assert_eq!(
+        control.content_description(),
+        Some("PGP/MIME version identification")
+    );
+    assert_eq!(
+        std::str::from_utf8(control.contents()).unwrap().trim(),
+        "Version: 1"
+    );
+
+    // Encrypted data part
+    let data = message.part(sub_parts[1]).unwrap();
+    let content_type = data.content_type().unwrap();
+    assert_eq!(
+        (content_type.ctype(), content_type.subtype()),
+        ("application", Some("octet-stream"))
+    );
+    assert_eq!(
+        data.content_description(),
+        Some("OpenPGP encrypted message")
+    );
+    let contents = std::str::from_utf8(data.contents()).unwrap().trim();
+    assert!(
+        contents.starts_with("-----BEGIN PGP MESSAGE-----")
+            && contents.ends_with("-----END PGP MESSAGE-----"),
+        "{contents}"
+    );
+    assert_eq!(
+        pgp_recipients(&encrypted),
+        vec!["3E36E27F669E3250".to_string()]
+    );
+}
+
+#[test]
+pub fn smime_streaming_encoding() {
+    let fixture = std::fs::read(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("crypto")
+            .join("cert_smime.der"),
+    )
+    .unwrap();
+
+    for len in [
+        0,
+        1,
+        56,
+        57,
+        58,
+        127,
+        128,
+        BASE64_MIME_BLOCK - 1,
+        BASE64_MIME_BLOCK,
+        BASE64_MIME_BLOCK + 1,
+        3 * BASE64_MIME_BLOCK + 5,
+        70_000,
+    ] {
+        let content = fixture
+            .iter()
+            .copied()
+            .cycle()
+            .take(len)
+            .collect::<Vec<_>>();
+
+        // The ContentInfo header followed by the content must match the DER encoding
+        let header = content_info_header(CONTENT_ENVELOPED_DATA, content.len()).unwrap();
+        let pkcs7 = rasn::der::encode(&EncapsulatedContentInfo {
+            content_type: CONTENT_ENVELOPED_DATA.into(),
+            content: Some(content.clone().into()),
+        })
+        .unwrap();
+        assert_eq!([header.as_slice(), &content].concat(), pkcs7, "len {len}");
+
+        // Chunked base64 output must match encoding the whole structure at once
+        let mut expected = Vec::new();
+        mail_builder::encoders::base64::base64_encode_mime(&pkcs7, &mut expected, false).unwrap();
+        let mut streamed = Vec::new();
+        let mut writer = Base64MimeWriter::new(&mut streamed);
+        for chunk in [header.as_slice(), &content] {
+            for chunk in chunk.chunks(1000) {
+                writer.write_all(chunk).unwrap();
+            }
+        }
+        writer.finish().unwrap();
+        assert!(streamed == expected, "len {len}");
+    }
+}
+
+#[tokio::test]
+pub async fn smime_rsa_padding() {
+    let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("crypto");
+    let certs = try_parse_certs(
+        EncryptionMethod::SMIME,
+        std::fs::read(resources.join("cert_smime_rsa.pem")).unwrap(),
+    )
+    .unwrap();
+    let private_key = RsaPrivateKey::from_pkcs8_pem(
+        &std::fs::read_to_string(resources.join("key_smime_rsa.pem")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        certificate_info(EncryptionMethod::SMIME, &certs[0])
+            .subject
+            .as_deref(),
+        Some("CN=John Doe, emailAddress=jdoe@example.com")
+    );
+
+    for (padding, expected_oid) in [
+        (RsaPadding::Pkcs1v15, [1, 2, 840, 113549, 1, 1, 1]),
+        (RsaPadding::Oaep, [1, 2, 840, 113549, 1, 1, 7]),
+    ] {
+        for (algo, key_size) in [(Algorithm::Aes128, 16), (Algorithm::Aes256, 32)] {
+            let arch = Archive::deserialize_owned(
+                Archiver::new(EncryptionParams {
+                    method: EncryptionMethod::SMIME,
+                    algo,
+                    padding,
+                    certs: certs.clone(),
+                    exclude_mailboxes: vec![],
+                    max_encrypt_size: None,
+                    compression: None,
+                })
+                .serialize()
+                .unwrap(),
+            )
+            .unwrap();
+            let encrypted = MessageParser::new()
+                .parse(b"Subject: test\r\ntest\r\n")
+                .unwrap()
+                .encrypt(
+                    std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+                    None,
+                )
+                .await
+                .unwrap();
+
+            // Obtain the recipient info from the enveloped data
+            let encrypted = MessageParser::new().parse(&encrypted).unwrap();
+            let content_info =
+                rasn::der::decode::<EncapsulatedContentInfo>(encrypted.part(0).unwrap().contents())
+                    .unwrap();
+            let enveloped_data =
+                rasn::der::decode::<EnvelopedData>(content_info.content.unwrap().as_bytes())
+                    .unwrap();
+            assert_eq!(enveloped_data.recipient_infos.into_iter().count(), 1);
+            let Some(RecipientInfo::KeyTransRecipientInfo(info)) =
+                enveloped_data.recipient_infos.into_iter().next()
+            else {
+                panic!("Expected a KeyTransRecipientInfo");
+            };
+            let oid: &[u32] = &info.key_encryption_algorithm.algorithm;
+            assert_eq!(oid, expected_oid, "padding {padding}");
+
+            // The content encryption key must decrypt using the chosen padding
+            let key = match padding {
+                RsaPadding::Pkcs1v15 => {
+                    private_key.decrypt(Pkcs1v15Encrypt, &info.encrypted_key[..])
+                }
+                RsaPadding::Oaep => {
+                    private_key.decrypt(Oaep::new::<sha2::Sha256>(), &info.encrypted_key[..])
+                }
+            }
+            .unwrap_or_else(|err| panic!("Failed to decrypt key using {padding}: {err}"));
+            assert_eq!(key.len(), key_size);
+        }
+    }
+}
+
+#[tokio::test]
+pub async fn smime_rsa_undersized_key() {
+    let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("crypto");
+    let certs = try_parse_certs(
+        EncryptionMethod::SMIME,
+        std::fs::read(resources.join("cert_smime_rsa_512.pem")).unwrap(),
+    )
+    .unwrap();
+
+    // A 512-bit key is too small to wrap a content encryption key using OAEP,
+    // encrypting to it fails without taking down the worker
+    for algo in [Algorithm::Aes128, Algorithm::Aes256, Algorithm::Aes256Gcm] {
+        let arch = Archive::deserialize_owned(
+            Archiver::new(EncryptionParams {
+                method: EncryptionMethod::SMIME,
+                algo,
+                padding: RsaPadding::Oaep,
+                certs: certs.clone(),
+                exclude_mailboxes: vec![],
+                max_encrypt_size: None,
+                compression: None,
+            })
+            .serialize()
+            .unwrap(),
+        )
+        .unwrap();
+        match MessageParser::new()
+            .parse(b"Subject: test\r\n\r\ntest\r\n")
+            .unwrap()
+            .encrypt(
+                std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+                None,
+            )
+            .await
+        {
+            Err(EncryptMessageError::Error(err)) => assert!(
+                err.contains("Failed to encrypt key for 512-bit RSA public key"),
+                "algorithm {algo}: {err}"
+            ),
+            other => panic!("Expected an encryption error for {algo}, got {other:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+pub async fn smime_multiple_recipients() {
+    let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("crypto");
+    let mut certs = try_parse_certs(
+        EncryptionMethod::SMIME,
+        std::fs::read(resources.join("cert_smime_rsa.pem")).unwrap(),
+    )
+    .unwrap();
+    certs.push(std::fs::read(resources.join("cert_smime.der")).unwrap());
+    let certs = certs.repeat(10);
+    let arch = Archive::deserialize_owned(
+        Archiver::new(EncryptionParams {
+            method: EncryptionMethod::SMIME,
+            algo: Algorithm::Aes256,
+            padding: RsaPadding::Oaep,
+            certs: certs.clone(),
+            exclude_mailboxes: vec![],
+            max_encrypt_size: None,
+            compression: None,
+        })
+        .serialize()
+        .unwrap(),
+    )
+    .unwrap();
+
+    // Recipients must be wrapped for every certificate in a reproducible order
+    let mut last_recipients = None;
+    for _ in 0..2 {
+        let encrypted = MessageParser::new()
+            .parse(b"Subject: test\r\ntest\r\n")
+            .unwrap()
+            .encrypt(
+                std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+                None,
+            )
+            .await
+            .unwrap();
+        let encrypted = MessageParser::new().parse(&encrypted).unwrap();
+        let content_info =
+            rasn::der::decode::<EncapsulatedContentInfo>(encrypted.part(0).unwrap().contents())
+                .unwrap();
+        let enveloped_data =
+            rasn::der::decode::<EnvelopedData>(content_info.content.unwrap().as_bytes()).unwrap();
+        let recipients = enveloped_data
+            .recipient_infos
+            .into_iter()
+            .map(|info| match info {
+                RecipientInfo::KeyTransRecipientInfo(info) => info.rid,
+                _ => panic!("Expected a KeyTransRecipientInfo"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(recipients.len(), certs.len());
+        assert!(recipients.is_sorted());
+        if let Some(last_recipients) = &last_recipients {
+            assert_eq!(&recipients, last_recipients);
+        }
+        last_recipients = Some(recipients);
+    }
+
+    // CA certificates in stored chains are not allowed to encrypt and are skipped
+    let chain = try_parse_certs(
+        EncryptionMethod::SMIME,
+        std::fs::read(resources.join("cert_smime.pem")).unwrap(),
+    )
+    .unwrap();
+    let arch = Archive::deserialize_owned(
+        Archiver::new(EncryptionParams {
+            method: EncryptionMethod::SMIME,
+            algo: Algorithm::Aes256,
+            padding: RsaPadding::Oaep,
+            certs: chain,
+            exclude_mailboxes: vec![],
+            max_encrypt_size: None,
+            compression: None,
+        })
+        .serialize()
+        .unwrap(),
+    )
+    .unwrap();
+    let encrypted = MessageParser::new()
+        .parse(b"Subject: test\r\ntest\r\n")
+        .unwrap()
+        .encrypt(
+            std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+            None,
+        )
+        .await
+        .unwrap();
+    let encrypted = MessageParser::new().parse(&encrypted).unwrap();
+    let content_info =
+        rasn::der::decode::<EncapsulatedContentInfo>(encrypted.part(0).unwrap().contents())
+            .unwrap();
+    let enveloped_data =
+        rasn::der::decode::<EnvelopedData>(content_info.content.unwrap().as_bytes()).unwrap();
+    assert_eq!(enveloped_data.recipient_infos.into_iter().count(), 1);
+}
+
+#[tokio::test]
+pub async fn smime_ec_recipients() {
+    let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("crypto");
+    let mut certs = try_parse_certs(
+        EncryptionMethod::SMIME,
+        std::fs::read(resources.join("cert_smime_rsa.pem")).unwrap(),
+    )
+    .unwrap();
+    certs.extend(
+        try_parse_certs(
+            EncryptionMethod::SMIME,
+            std::fs::read(resources.join("cert_smime_ec.pem")).unwrap(),
+        )
+        .unwrap(),
+    );
+    let rsa_key = RsaPrivateKey::from_pkcs8_pem(
+        &std::fs::read_to_string(resources.join("key_smime_rsa.pem")).unwrap(),
+    )
+    .unwrap();
+    let ec_key = p256::SecretKey::from_pkcs8_pem(
+        &std::fs::read_to_string(resources.join("key_smime_ec.pem")).unwrap(),
+    )
+    .unwrap();
+
+    for (algo, key_size, expected_wrap_oid) in [
+        (Algorithm::Aes128, 16, [2, 16, 840, 1, 101, 3, 4, 1, 5]),
+        (Algorithm::Aes256, 32, [2, 16, 840, 1, 101, 3, 4, 1, 45]),
+    ] {
+        let arch = Archive::deserialize_owned(
+            Archiver::new(EncryptionParams {
+                method: EncryptionMethod::SMIME,
+                algo,
+                padding: RsaPadding::Oaep,
+                certs: certs.clone(),
+                exclude_mailboxes: vec![],
+                max_encrypt_size: None,
+                compression: None,
+            })
+            .serialize()
+            .unwrap(),
+        )
+        .unwrap();
+        let encrypted = MessageParser::new()
+            .parse(b"Subject: test\r\ntest\r\n")
+            .unwrap()
+            .encrypt(
+                std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+                None,
+            )
+            .await
+            .unwrap();
+        let encrypted = MessageParser::new().parse(&encrypted).unwrap();
+        let content_info =
+            rasn::der::decode::<EncapsulatedContentInfo>(encrypted.part(0).unwrap().contents())
+                .unwrap();
+        let enveloped_data =
+            rasn::der::decode::<EnvelopedData>(content_info.content.unwrap().as_bytes()).unwrap();
+
+        // Key agreement recipients require version 2
+        assert_eq!(enveloped_data.version, 2.into());
+        assert_eq!(enveloped_data.recipient_infos.len(), 2);
+        let mut key_trans = None;
+        let mut key_agree = None;
+        for info in enveloped_data.recipient_infos {
+            match info {
+                RecipientInfo::KeyTransRecipientInfo(info) => key_trans = Some(info),
+                RecipientInfo::KeyAgreeRecipientInfo(info) => key_agree = Some(info),
+                _ => panic!("Unexpected recipient info"),
+            }
+        }
+        let key_trans = key_trans.expect("Expected a KeyTransRecipientInfo");
+        let key_agree = key_agree.expect("Expected a KeyAgreeRecipientInfo");
+
+        // Obtain the content encryption key from the RSA recipient
+        let key = rsa_key
+            .decrypt(Oaep::new::<sha2::Sha256>(), &key_trans.encrypted_key[..])
+            .unwrap();
+        assert_eq!(key.len(), key_size);
+
+        // Validate the key agreement parameters
+        assert_eq!(key_agree.version, 3.into());
+        let oid: &[u32] = &key_agree.key_encryption_algorithm.algorithm;
+        assert_eq!(oid, [1, 3, 132, 1, 11, 1]);
+        let wrap_algorithm = rasn::der::decode::<AlgorithmIdentifier>(
+            key_agree
+                .key_encryption_algorithm
+                .parameters
+                .as_ref()
+                .unwrap()
+                .as_bytes(),
+        )
+        .unwrap();
+        let oid: &[u32] = &wrap_algorithm.algorithm;
+        assert_eq!(oid, expected_wrap_oid, "algorithm {algo}");
+        let OriginatorIdentifierOrKey::OriginatorPublicKey(originator) = key_agree.originator
+        else {
+            panic!("Expected an originator public key");
+        };
+        let oid: &[u32] = &originator.algorithm.algorithm;
+        assert_eq!(oid, [1, 2, 840, 10045, 2, 1]);
+        let ephemeral_key = originator.public_key.as_raw_slice();
+        assert_eq!(ephemeral_key.len(), 65);
+        assert_eq!(ephemeral_key[0], 0x04);
+        assert_eq!(key_agree.recipient_encrypted_keys.len(), 1);
+        let wrapped_key = &key_agree.recipient_encrypted_keys[0].encrypted_key;
+        assert_eq!(wrapped_key.len(), key_size + 8);
+
+        // Derive the key-encryption key and unwrap the content encryption key
+        let shared_secret = p256::ecdh::diffie_hellman(
+            ec_key.to_nonzero_scalar(),
+            p256::PublicKey::from_sec1_bytes(ephemeral_key)
+                .unwrap()
+                .as_affine(),
+        );
+        let shared_info = rasn::der::encode(&EccCmsSharedInfo {
+            key_info: wrap_algorithm,
+            entity_u_info: None,
+            supp_pub_info: ((key_size * 8) as u32).to_be_bytes().to_vec().into(),
+        })
+        .unwrap();
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(shared_secret.raw_secret_bytes());
+        hasher.update(1u32.to_be_bytes());
+        hasher.update(&shared_info);
+        let kek = hasher.finalize()[..key_size].to_vec();
+        assert_eq!(aes_key_unwrap(&kek, wrapped_key), key, "algorithm {algo}");
+    }
+}
+
+// AES key unwrap (RFC 3394)
+fn aes_key_unwrap(kek: &[u8], wrapped_key: &[u8]) -> Vec<u8> {
+    let decrypt_block = |block: &mut aes_gcm::aes::Block| match kek.len() {
+        16 => aes_gcm::aes::Aes128::new_from_slice(kek)
+            .unwrap()
+            .decrypt_block(block),
+        _ => aes_gcm::aes::Aes256::new_from_slice(kek)
+            .unwrap()
+            .decrypt_block(block),
+    };
+    let mut iv = wrapped_key[..8].to_vec();
+    let mut blocks = wrapped_key[8..]
+        .chunks(8)
+        .map(|chunk| chunk.to_vec())
+        .collect::<Vec<_>>();
+    let n = blocks.len() as u64;
+
+    for j in (0..6u64).rev() {
+        for (i, block) in blocks.iter_mut().enumerate().rev() {
+            let t = (n * j + i as u64 + 1).to_be_bytes();
+            let mut buf = aes_gcm::aes::Block::default();
+            for (b, (iv, t)) in buf[..8].iter_mut().zip(iv.iter().zip(t)) {
+                *b = iv ^ t;
+            }
+            buf[8..].copy_from_slice(block);
+            decrypt_block(&mut buf);
+            iv.copy_from_slice(&buf[..8]);
+            block.copy_from_slice(&buf[8..]);
+        }
+    }
+    assert_eq!(iv, [0xA6; 8], "Integrity check failed");
+
+    blocks.concat()
+}
+
+#[tokio::test]
+pub async fn smime_aes_gcm() {
+    let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("crypto");
+    let certs = try_parse_certs(
+        EncryptionMethod::SMIME,
+        std::fs::read(resources.join("cert_smime_rsa.pem")).unwrap(),
+    )
+    .unwrap();
+    let private_key = RsaPrivateKey::from_pkcs8_pem(
+        &std::fs::read_to_string(resources.join("key_smime_rsa.pem")).unwrap(),
+    )
+    .unwrap();
+
+    for (algo, expected_oid) in [
+        (Algorithm::Aes128Gcm, [2, 16, 840, 1, 101, 3, 4, 1, 6]),
+        (Algorithm::Aes256Gcm, [2, 16, 840, 1, 101, 3, 4, 1, 46]),
+        (
+            Algorithm::Chacha20Poly1305,
+            [1, 2, 840, 113549, 1, 9, 16, 3, 18],
+        ),
+    ] {
+        let arch = Archive::deserialize_owned(
+            Archiver::new(EncryptionParams {
+                method: EncryptionMethod::SMIME,
+                algo,
+                padding: RsaPadding::Oaep,
+                certs: certs.clone(),
+                exclude_mailboxes: vec![],
+                max_encrypt_size: None,
+                compression: None,
+            })
+            .serialize()
+            .unwrap(),
+        )
+        .unwrap();
+        let encrypted = MessageParser::new()
+            .parse(b"Subject: test\r\n\r\nI'm going to need those TPS reports ASAP.\r\n")
+            .unwrap()
+            .encrypt(
+                std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+                None,
+            )
+            .await
+            .unwrap();
+
+        // Authenticated ciphers must be wrapped in AuthEnvelopedData
+        let encrypted = MessageParser::new().parse(&encrypted).unwrap();
+        assert_eq!(
+            encrypted
+                .content_type()
+                .unwrap()
+                .attribute("smime-type")
+                .unwrap(),
+            "authEnveloped-data"
+        );
+        let content_info =
+            rasn::der::decode::<EncapsulatedContentInfo>(encrypted.part(0).unwrap().contents())
+                .unwrap();
+        let oid: &[u32] = &content_info.content_type;
+        assert_eq!(oid, [1, 2, 840, 113549, 1, 9, 16, 1, 23]);
+        let auth_enveloped_data =
+            rasn::der::decode::<AuthEnvelopedData>(content_info.content.unwrap().as_bytes())
+                .unwrap();
+        let content_info = auth_enveloped_data.auth_encrypted_content_info;
+        let oid: &[u32] = &content_info.content_encryption_algorithm.algorithm;
+        assert_eq!(oid, expected_oid, "algorithm {algo}");
+        let parameters = content_info
+            .content_encryption_algorithm
+            .parameters
+            .unwrap();
+        let nonce = if matches!(algo, Algorithm::Chacha20Poly1305) {
+            // RFC 8103 encodes the nonce as a bare octet string
+            rasn::der::decode::<rasn::types::OctetString>(parameters.as_bytes()).unwrap()
+        } else {
+            let gcm_params = rasn::der::decode::<GcmParameters>(parameters.as_bytes()).unwrap();
+            assert_eq!(gcm_params.icv_len, Some(16));
+            gcm_params.nonce
+        };
+        assert_eq!(nonce.len(), 12);
+        assert_eq!(auth_enveloped_data.mac.len(), 16);
+
+        // Decrypt the content encryption key and verify the authentication tag
+        let Some(RecipientInfo::KeyTransRecipientInfo(info)) =
+            auth_enveloped_data.recipient_infos.into_iter().next()
+        else {
+            panic!("Expected a KeyTransRecipientInfo");
+        };
+        let key = private_key
+            .decrypt(Oaep::new::<sha2::Sha256>(), &info.encrypted_key[..])
+            .unwrap();
+        let mut contents = content_info.encrypted_content.unwrap().to_vec();
+        contents.extend_from_slice(&auth_enveloped_data.mac);
+        let decrypt = |contents: &[u8]| match algo {
+            Algorithm::Aes128Gcm => {
+                Aes128Gcm::new(key.as_slice().into()).decrypt(Nonce::from_slice(&nonce), contents)
+            }
+            Algorithm::Chacha20Poly1305 => {
+                let mut contents = contents.to_vec();
+                ring::aead::LessSafeKey::new(
+                    ring::aead::UnboundKey::new(&ring::aead::CHACHA20_POLY1305, &key).unwrap(),
+                )
+                .open_in_place(
+                    ring::aead::Nonce::try_assume_unique_for_key(&nonce).unwrap(),
+                    ring::aead::Aad::empty(),
+                    &mut contents,
+                )
+                .map(|plaintext| plaintext.to_vec())
+                .map_err(|_| aes_gcm::Error)
+            }
+            _ => Aes256Gcm::new(key.as_slice().into()).decrypt(Nonce::from_slice(&nonce), contents),
+        };
+        let decrypted = decrypt(&contents).unwrap();
+        assert!(
+            std::str::from_utf8(&decrypted)
+                .unwrap()
+                .contains("I'm going to need those TPS reports ASAP."),
+            "algorithm {algo}"
+        );
+
+        // Tampered content must be rejected
+        contents[0] ^= 0xff;
+        assert!(decrypt(&contents).is_err());
+    }
+}
+
+#[test]
+pub fn smime_gcm_selectors() {
+    for (selector, expected) in [
+        ("smime-128-gcm", Algorithm::Aes128Gcm),
+        ("smime-256-gcm", Algorithm::Aes256Gcm),
+        ("Aes256Gcm", Algorithm::Aes256Gcm),
+        ("Aes128", Algorithm::Aes128),
+    ] {
+        let request = serde_json::json!({
+            "type": "sMIME",
+            "algo": selector,
+            "certs": "",
+        });
+
+        match serde_json::from_value::<EncryptionType>(request).unwrap() {
+            EncryptionType::SMIME { algo, .. } => {
+                assert_eq!(
+                    algo.to_string(),
+                    expected.to_string(),
+                    "selector {selector}"
+                )
+            }
+            other => panic!("unexpected request {other:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+pub async fn smime_iv_length() {
+    let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("crypto");
+    let certs = try_parse_certs(
+        EncryptionMethod::SMIME,
+        std::fs::read(resources.join("cert_smime_rsa.pem")).unwrap(),
+    )
+    .unwrap();
+
+    for (algo, iv_size) in [
+        (Algorithm::Aes128, 16),
+        (Algorithm::Aes256, 16),
+        (Algorithm::Aes128Gcm, 12),
+        (Algorithm::Aes256Gcm, 12),
+        (Algorithm::Chacha20Poly1305, 12),
+    ] {
+        assert_eq!(algo.iv_size(), iv_size, "algorithm {algo}");
+
+        let arch = Archive::deserialize_owned(
+            Archiver::new(EncryptionParams {
+                method: EncryptionMethod::SMIME,
+                algo,
+                padding: RsaPadding::Oaep,
+                certs: certs.clone(),
+                exclude_mailboxes: vec![],
+                max_encrypt_size: None,
+                compression: None,
+            })
+            .serialize()
+            .unwrap(),
+        )
+        .unwrap();
+        let encrypted = MessageParser::new()
+            .parse(b"Subject: test\r\n\r\ntest\r\n")
+            .unwrap()
+            .encrypt(
+                std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+                None,
+            )
+            .await
+            .unwrap();
+
+        // The IV encoded in the content encryption parameters must match the algorithm
+        let encrypted = MessageParser::new().parse(&encrypted).unwrap();
+        let content_info =
+            rasn::der::decode::<EncapsulatedContentInfo>(encrypted.part(0).unwrap().contents())
+                .unwrap();
+        let content = content_info.content.unwrap();
+        let parameters = if algo.is_authenticated() {
+            rasn::der::decode::<AuthEnvelopedData>(content.as_bytes())
+                .unwrap()
+                .auth_encrypted_content_info
+                .content_encryption_algorithm
+                .parameters
+        } else {
+            rasn::der::decode::<EnvelopedData>(content.as_bytes())
+                .unwrap()
+                .encrypted_content_info
+                .content_encryption_algorithm
+                .parameters
+        }
+        .unwrap();
+        let iv = if matches!(algo, Algorithm::Aes128Gcm | Algorithm::Aes256Gcm) {
+            rasn::der::decode::<GcmParameters>(parameters.as_bytes())
+                .unwrap()
+                .nonce
+        } else {
+            rasn::der::decode::<rasn::types::OctetString>(parameters.as_bytes()).unwrap()
+        };
+        assert_eq!(iv.len(), iv_size, "algorithm {algo}");
+    }
+}
+
+#[tokio::test]
+pub async fn smime_header_placement() {
+    let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("crypto");
+    let certs = try_parse_certs(
+        EncryptionMethod::SMIME,
+        std::fs::read(resources.join("cert_smime_rsa.pem")).unwrap(),
+    )
+    .unwrap();
+    let private_key = RsaPrivateKey::from_pkcs8_pem(
+        &std::fs::read_to_string(resources.join("key_smime_rsa.pem")).unwrap(),
+    )
+    .unwrap();
+    let arch = Archive::deserialize_owned(
+        Archiver::new(EncryptionParams {
+            method: EncryptionMethod::SMIME,
+            algo: Algorithm::Aes256Gcm,
+            padding: RsaPadding::Oaep,
+            certs,
+            exclude_mailboxes: vec![],
+            max_encrypt_size: None,
+            compression: None,
+        })
+        .serialize()
+        .unwrap(),
+    )
+    .unwrap();
+    let encrypted = MessageParser::new()
+        .parse(concat!(
+            "From: jdoe@example.com\r\n",
+            "X-Content-Foo: bar\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Language: en\r\n",
+            "content-type: text/plain; charset=utf-8\r\n",
+            "Content-Transfer-Encoding: 7bit\r\n",
+            "Subject: test\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.\r\n"
+        ))
+        .unwrap()
+        .encrypt(
+            std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+            None,
+        )
+        .await
+        .unwrap();
+
+    // Only content headers are moved to the encrypted part
+    let outer_headers = std::str::from_utf8(&encrypted)
+        .unwrap()
+        .split_once("Content-Type: application/pkcs7-mime")
+        .unwrap()
+        .0;
+    assert_eq!(
+        outer_headers,
+        concat!(
+            "From: jdoe@example.com\r\n",
+            "X-Content-Foo: bar\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Language: en\r\n",
+            "Subject: test\r\n",
+        )
+    );
+
+    // Decrypt the inner message
+    let encrypted = MessageParser::new().parse(&encrypted).unwrap();
+    let content_info =
+        rasn::der::decode::<EncapsulatedContentInfo>(encrypted.part(0).unwrap().contents())
+            .unwrap();
+    let auth_enveloped_data =
+        rasn::der::decode::<AuthEnvelopedData>(content_info.content.unwrap().as_bytes()).unwrap();
+    let content_info = auth_enveloped_data.auth_encrypted_content_info;
+    let gcm_params = rasn::der::decode::<GcmParameters>(
+        content_info
+            .content_encryption_algorithm
+            .parameters
+            .unwrap()
+            .as_bytes(),
+    )
+    .unwrap();
+    let Some(RecipientInfo::KeyTransRecipientInfo(info)) =
+        auth_enveloped_data.recipient_infos.into_iter().next()
+    else {
+        panic!("Expected a KeyTransRecipientInfo");
+    };
+    let key = private_key
+        .decrypt(Oaep::new::<sha2::Sha256>(), &info.encrypted_key[..])
+        .unwrap();
+    let mut contents = content_info.encrypted_content.unwrap().to_vec();
+    contents.extend_from_slice(&auth_enveloped_data.mac);
+    let decrypted = Aes256Gcm::new(key.as_slice().into())
+        .decrypt(Nonce::from_slice(&gcm_params.nonce), &contents[..])
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(&decrypted).unwrap(),
+        concat!(
+            "MIME-Version: 1.0\r\n",
+            "content-type: text/plain; charset=utf-8\r\n",
+            "Content-Transfer-Encoding: 7bit\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.\r\n"
+        )
+    );
+}
+
+// Returns the key ids of the public key encrypted session key packets
+#[test]
+pub fn smime_pkcs12() {
+    let read = |name: &str| {
+        std::fs::read(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("resources")
+                .join("crypto")
+                .join(name),
+        )
+        .unwrap()
+    };
+    let expected_certs =
+        try_parse_certs(EncryptionMethod::SMIME, read("cert_smime_rsa.pem")).unwrap();
+    let bundle = read("cert_smime.p12");
+
+    // Only the certificate matching the bundle's private key is extracted
+    for bundle in [bundle.clone(), STANDARD.encode(&bundle).into_bytes()] {
+        assert_eq!(
+            try_parse_certs_with_password(EncryptionMethod::SMIME, bundle, Some("secret")).unwrap(),
+            expected_certs
+        );
+    }
+    assert_eq!(
+        try_parse_certs_with_password(EncryptionMethod::PGP, bundle.clone(), Some("secret")),
+        Err(CertParseError::MethodMismatch)
+    );
+
+    // Wrong or missing passwords
+    assert_eq!(
+        try_parse_certs_with_password(EncryptionMethod::SMIME, bundle.clone(), Some("wrong")),
+        Err(CertParseError::Pkcs12WrongPassword)
+    );
+    assert_eq!(
+        try_parse_certs(EncryptionMethod::SMIME, bundle),
+        Err(CertParseError::Pkcs12WrongPassword)
+    );
+
+    // Legacy MAC algorithms are rejected
+    assert_eq!(
+        try_parse_certs_with_password(
+            EncryptionMethod::SMIME,
+            read("cert_smime_md5.p12"),
+            Some("secret")
+        ),
+        Err(CertParseError::Pkcs12UnsupportedMac(
+            "1.2.840.113549.2.5".to_string()
+        ))
+    );
+}
+
+#[test]
+pub fn smime_binary_certs() {
+    let read = |name: &str| {
+        std::fs::read(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("resources")
+                .join("crypto")
+                .join(name),
+        )
+        .unwrap()
+    };
+    let chain = try_parse_certs(EncryptionMethod::SMIME, read("cert_smime.pem")).unwrap();
+    let der = read("cert_smime.der");
+    let p7b = read("cert_smime.p7b");
+
+    // Raw and base64 encoded DER certificates, as uploaded through a multipart form
+    for cert in [der.clone(), STANDARD.encode(&der).into_bytes()] {
+        assert_eq!(
+            try_parse_certs(EncryptionMethod::SMIME, cert).unwrap(),
+            vec![der.clone()]
+        );
+    }
+    assert_eq!(
+        try_parse_certs(EncryptionMethod::PGP, der),
+        Err(CertParseError::MethodMismatch)
+    );
+
+    // Only the end-entity certificate of a PKCS#7 bundle is used, the CA certificates are ignored
+    let pem = format!(
+        "-----BEGIN PKCS7-----\n{}\n-----END PKCS7-----\n",
+        STANDARD.encode(&p7b)
+    );
+    for bundle in [
+        p7b.clone(),
+        STANDARD.encode(&p7b).into_bytes(),
+        pem.into_bytes(),
+    ] {
+        assert_eq!(
+            try_parse_certs(EncryptionMethod::SMIME, bundle).unwrap(),
+            vec![chain[2].clone()]
+        );
+    }
+    assert_eq!(
+        try_parse_certs(EncryptionMethod::PGP, p7b),
+        Err(CertParseError::MethodMismatch)
+    );
+
+    // Bundles with a single self-signed certificate are accepted, multiple unrelated
+    // certificates are ambiguous
+    assert_eq!(
+        try_parse_certs(EncryptionMethod::SMIME, read("cert_smime_rsa.p7b")).unwrap(),
+        try_parse_certs(EncryptionMethod::SMIME, read("cert_smime_rsa.pem")).unwrap()
+    );
+    assert_eq!(
+        try_parse_certs(EncryptionMethod::SMIME, read("cert_smime_ambiguous.p7b")),
+        Err(CertParseError::AmbiguousCertificates)
+    );
+    assert_eq!(
+        CertParseError::AmbiguousCertificates.code(),
+        "ambiguous-certificates"
+    );
+
+    // The error lists the accepted file types
+    let err = try_parse_certs(EncryptionMethod::SMIME, b"not a certificate".to_vec())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains(".der") && err.contains(".p7b"), "{err}");
+}
+
+#[tokio::test]
+pub async fn pgp_sign_then_encrypt() {
+    let (recipient, _) = CertBuilder::new()
+        .add_userid("John Doe <jdoe@example.com>")
+        .add_transport_encryption_subkey()
+        .generate()
+        .unwrap();
+    let (signer, _) = CertBuilder::new()
+        .add_userid("Postmaster <postmaster@example.com>")
+        .add_signing_subkey()
+        .generate()
+        .unwrap();
+
+    let params = EncryptionParams {
+        method: EncryptionMethod::PGP,
+        algo: Algorithm::Aes256,
+        padding: RsaPadding::default(),
+        certs: vec![recipient.to_vec().unwrap()],
+        exclude_mailboxes: vec![],
+        max_encrypt_size: None,
+        compression: None,
+    };
+    let arch = Archive::deserialize_owned(Archiver::new(params).serialize().unwrap()).unwrap();
+    let identities = std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap());
+    let signing_key = signer.as_tsk().armored().to_vec().unwrap();
+    let encrypted = MessageParser::new()
+        .parse(b"Subject: test\r\n\r\nI'm going to need those TPS reports ASAP.\r\n")
+        .unwrap()
+        .encrypt(identities, Some(&signing_key))
+        .await
+        .unwrap();
+
+    // The recipient should be able to decrypt the message and verify the server signature
+    let encrypted = std::str::from_utf8(&encrypted).unwrap();
+    let armored = encrypted
+        .find("-----BEGIN PGP MESSAGE-----")
+        .and_then(|start| {
+            encrypted[start..]
+                .find("-----END PGP MESSAGE-----")
+                .map(|end| &encrypted[start..start + end + 25])
+        })
+        .unwrap();
+    let policy = StandardPolicy::new();
+    let mut decryptor = DecryptorBuilder::from_bytes(armored.as_bytes())
+        .unwrap()
+        .with_policy(
+            &policy,
+            None,
+            PgpTestHelper {
+                policy: &policy,
+                recipient: &recipient,
+                signer: &signer,
+                verified: false,
+                compression: None,
+            },
+        )
+        .unwrap();
+    let mut decrypted = Vec::new();
+    std::io::copy(&mut decryptor, &mut decrypted).unwrap();
+    assert!(decryptor.into_helper().verified);
+    assert!(
+        String::from_utf8(decrypted)
+            .unwrap()
+            .contains("I'm going to need those TPS reports ASAP.")
+    );
+
+    // Signing keys without secret key material should be rejected
+    match MessageParser::new()
+        .parse(b"Subject: test\r\ntest\r\n")
+        .unwrap()
+        .encrypt(identities, Some(&signer.to_vec().unwrap()))
+        .await
+    {
+        Err(EncryptMessageError::Error(err)) => {
+            assert!(err.contains("signing-capable"), "{err}")
+        }
+        result => panic!("Unexpected result: {result:?}"),
+    }
+}
+
+#[tokio::test]
+pub async fn pgp_compression() {
+    let (recipient, _) = CertBuilder::new()
+        .add_userid("John Doe <jdoe@example.com>")
+        .add_transport_encryption_subkey()
+        .generate()
+        .unwrap();
+    let text_message = format!(
+        "Subject: test\r\nContent-Type: text/plain\r\n\r\n{}",
+        "I'm going to need those TPS reports ASAP.\r\n".repeat(500)
+    );
+    let mut attachment = vec![0u8; 16384];
+    rng().fill(&mut attachment[..]);
+    let binary_message = format!(
+        concat!(
+            "Subject: test\r\n",
+            "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n",
+            "--b\r\n",
+            "Content-Type: text/plain\r\n\r\n",
+            "See attached.\r\n",
+            "--b\r\n",
+            "Content-Type: application/octet-stream\r\n",
+            "Content-Transfer-Encoding: base64\r\n\r\n",
+            "{}\r\n",
+            "--b--\r\n"
+        ),
+        STANDARD.encode(&attachment)
+    );
+
+    for (message, compression, expected) in [
+        (&text_message, None, Some(CompressionAlgorithm::Zlib)),
+        (&text_message, Some(Compression::None), None),
+        (
+            &text_message,
+            Some(Compression::Zip),
+            Some(CompressionAlgorithm::Zip),
+        ),
+        (
+            &text_message,
+            Some(Compression::Zlib),
+            Some(CompressionAlgorithm::Zlib),
+        ),
+        (&binary_message, None, None),
+        (
+            &binary_message,
+            Some(Compression::Zlib),
+            Some(CompressionAlgorithm::Zlib),
+        ),
+    ] {
+        let test_name = format!("{compression:?} {}", message.len());
+        let params = EncryptionParams {
+            method: EncryptionMethod::PGP,
+            algo: Algorithm::Aes256,
+            padding: RsaPadding::default(),
+            certs: vec![recipient.to_vec().unwrap()],
+            exclude_mailboxes: vec![],
+            max_encrypt_size: None,
+            compression,
+        };
+        let arch = Archive::deserialize_owned(Archiver::new(params).serialize().unwrap()).unwrap();
+        let encrypted = MessageParser::new()
+            .parse(message.as_bytes())
+            .unwrap()
+            .encrypt(
+                std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+                None,
+            )
+            .await
+            .unwrap();
+        let encrypted = std::str::from_utf8(&encrypted).unwrap();
+        let armored = encrypted
+            .find("-----BEGIN PGP MESSAGE-----")
+            .and_then(|start| {
+                encrypted[start..]
+                    .find("-----END PGP MESSAGE-----")
+                    .map(|end| &encrypted[start..start + end + 25])
+            })
+            .unwrap();
+        if expected.is_some() && message == &text_message {
+            assert!(armored.len() < message.len() / 4, "{test_name}");
+        }
+
+        // Compressed messages are decrypted transparently by OpenPGP clients
+        let policy = StandardPolicy::new();
+        let mut decryptor = DecryptorBuilder::from_bytes(armored.as_bytes())
+            .unwrap()
+            .with_policy(
+                &policy,
+                None,
+                PgpTestHelper {
+                    policy: &policy,
+                    recipient: &recipient,
+                    signer: &recipient,
+                    verified: false,
+                    compression: None,
+                },
+            )
+            .unwrap();
+        let mut decrypted = Vec::new();
+        std::io::copy(&mut decryptor, &mut decrypted).unwrap();
+        assert_eq!(decryptor.into_helper().compression, expected, "{test_name}");
+        let decrypted = String::from_utf8(decrypted).unwrap();
+        assert!(
+            decrypted.ends_with(message.split_once("\r\n\r\n").unwrap().1),
+            "{test_name}"
+        );
+    }
+}
+
+#[tokio::test]
+pub async fn decrypt_round_trip() {
+    let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("crypto");
+    let message = concat!(
+        "From: John Doe <jdoe@example.com>\r\n",
+        "Subject: test\r\n",
+        "MIME-Version: 1.0\r\n",
+        "Content-Type: text/plain; charset=utf-8\r\n",
+        "Content-Transfer-Encoding: 7bit\r\n",
+        "\r\n",
+        "I'm going to need those TPS reports ASAP.\r\n"
+    )
+    .as_bytes();
+    let rsa_key =
+        DecryptionKey::try_parse(&std::fs::read(resources.join("key_smime_rsa.pem")).unwrap())
+            .unwrap();
+    let ec_key =
+        DecryptionKey::try_parse(&std::fs::read(resources.join("key_smime_ec.pem")).unwrap())
+            .unwrap();
+    let (pgp_cert, _) = CertBuilder::new()
+        .add_userid("John Doe <jdoe@example.com>")
+        .add_transport_encryption_subkey()
+        .generate()
+        .unwrap();
+    let pgp_key = DecryptionKey::try_parse(&pgp_cert.as_tsk().armored().to_vec().unwrap()).unwrap();
+    assert!(matches!(rsa_key, DecryptionKey::Rsa(_)));
+    assert!(matches!(ec_key, DecryptionKey::P256(_)));
+    assert!(matches!(pgp_key, DecryptionKey::Pgp(_)));
+
+    // Public keys cannot be used for decryption
+    assert!(DecryptionKey::try_parse(&pgp_cert.armored().to_vec().unwrap()).is_err());
+
+    let mut tests = Vec::new();
+    for cert_file in ["cert_smime_rsa.pem", "cert_smime_ec.pem"] {
+        for algo in [
+            Algorithm::Aes128,
+            Algorithm::Aes256,
+            Algorithm::Aes128Gcm,
+            Algorithm::Aes256Gcm,
+            Algorithm::Chacha20Poly1305,
+        ] {
+            for padding in [RsaPadding::Pkcs1v15, RsaPadding::Oaep] {
+                tests.push(EncryptionParams {
+                    method: EncryptionMethod::SMIME,
+                    algo,
+                    padding,
+                    certs: try_parse_certs(
+                        EncryptionMethod::SMIME,
+                        std::fs::read(resources.join(cert_file)).unwrap(),
+                    )
+                    .unwrap(),
+                    exclude_mailboxes: vec![],
+                    max_encrypt_size: None,
+                    compression: None,
+                });
+            }
+        }
+    }
+    for algo in [Algorithm::Aes128, Algorithm::Aes256] {
+        tests.push(EncryptionParams {
+            method: EncryptionMethod::PGP,
+            algo,
+            padding: RsaPadding::default(),
+            certs: vec![pgp_cert.to_vec().unwrap()],
+            exclude_mailboxes: vec![],
+            max_encrypt_size: None,
+            compression: None,
+        });
+    }
+
+    let private_keys = [rsa_key, ec_key, pgp_key];
+    for params in tests {
+        let test_name = format!("{:?} {:?} {}", params.method, params.algo, params.padding);
+        let arch = Archive::deserialize_owned(Archiver::new(params).serialize().unwrap()).unwrap();
+        let encrypted = MessageParser::new()
+            .parse(message)
+            .unwrap()
+            .encrypt(
+                std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+                None,
+            )
+            .await
+            .unwrap();
+        let encrypted = MessageParser::new().parse(&encrypted).unwrap();
+
+        // The original message is restored byte for byte
+        let decrypted = encrypted
+            .decrypt(&private_keys)
+            .unwrap_or_else(|err| panic!("Failed to decrypt {test_name}: {err:?}"));
+        assert_eq!(
+            String::from_utf8(decrypted).unwrap(),
+            std::str::from_utf8(message).unwrap(),
+            "{test_name}"
+        );
+
+        // Without a private key the message cannot be decrypted
+        assert!(
+            matches!(
+                encrypted.decrypt(&[]),
+                Err(DecryptMessageError::NoMatchingKey)
+            ),
+            "{test_name}"
+        );
+    }
+
+    // Plain messages are reported as not encrypted
+    assert!(matches!(
+        MessageParser::new()
+            .parse(message)
+            .unwrap()
+            .decrypt(&private_keys),
+        Err(DecryptMessageError::NotEncrypted)
+    ));
+}
+
+#[tokio::test]
+pub async fn encrypt_large_message_round_trip() {
+    let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("crypto");
+    let mut message = concat!(
+        "From: John Doe <jdoe@example.com>\r\n",
+        "Subject: test\r\n",
+        "MIME-Version: 1.0\r\n",
+        "Content-Type: text/plain; charset=utf-8\r\n",
+        "Content-Transfer-Encoding: 7bit\r\n",
+        "\r\n",
+    )
+    .as_bytes()
+    .to_vec();
+    let mut line_num = 0;
+    while message.len() <= ENCRYPT_STREAM_THRESHOLD + 1000 {
+        message.extend_from_slice(format!("Line {line_num} of the TPS report.\r\n").as_bytes());
+        line_num += 1;
+    }
+
+    let rsa_key =
+        DecryptionKey::try_parse(&std::fs::read(resources.join("key_smime_rsa.pem")).unwrap())
+            .unwrap();
+    let (pgp_cert, _) = CertBuilder::new()
+        .add_userid("John Doe <jdoe@example.com>")
+        .add_transport_encryption_subkey()
+        .generate()
+        .unwrap();
+    let pgp_key = DecryptionKey::try_parse(&pgp_cert.as_tsk().armored().to_vec().unwrap()).unwrap();
+
+    // CBC and OpenPGP contents are encrypted in chunks, GCM uses the whole message
+    let mut tests = Vec::new();
+    for algo in [Algorithm::Aes128, Algorithm::Aes256, Algorithm::Aes256Gcm] {
+        tests.push(EncryptionParams {
+            method: EncryptionMethod::SMIME,
+            algo,
+            padding: RsaPadding::Oaep,
+            certs: try_parse_certs(
+                EncryptionMethod::SMIME,
+                std::fs::read(resources.join("cert_smime_rsa.pem")).unwrap(),
+            )
+            .unwrap(),
+            exclude_mailboxes: vec![],
+            max_encrypt_size: None,
+            compression: None,
+        });
+    }
+    for compression in [Compression::None, Compression::Zlib] {
+        tests.push(EncryptionParams {
+            method: EncryptionMethod::PGP,
+            algo: Algorithm::Aes256,
+            padding: RsaPadding::default(),
+            certs: vec![pgp_cert.to_vec().unwrap()],
+            exclude_mailboxes: vec![],
+            max_encrypt_size: None,
+            compression: Some(compression),
+        });
+    }
+
+    let private_keys = [rsa_key, pgp_key];
+    for params in tests {
+        let test_name = format!(
+            "{:?} {:?} {:?}",
+            params.method, params.algo, params.compression
+        );
+        let arch = Archive::deserialize_owned(Archiver::new(params).serialize().unwrap()).unwrap();
+        let encrypted = MessageParser::new()
+            .parse(&message)
+            .unwrap()
+            .encrypt(
+                std::slice::from_ref(arch.unarchive::<EncryptionParams>().unwrap()),
+                None,
+            )
+            .await
+            .unwrap();
+
+        // The original message is restored byte for byte
+        let decrypted = MessageParser::new()
+            .parse(&encrypted)
+            .unwrap()
+            .decrypt(&private_keys)
+            .unwrap_or_else(|err| panic!("Failed to decrypt {test_name}: {err:?}"));
+        assert!(decrypted == message, "{test_name}");
+    }
+}
+
+#[test]
+fn pgp_symmetric_algorithm_negotiation() {
+    let recipient = |name: &str, algos: Option<&[SymmetricAlgorithm]>| {
+        (name.to_string(), algos.map(|algos| algos.to_vec()))
+    };
+
+    // The configured algorithm is used when all recipients accept it
+    assert_eq!(
+        pgp_symmetric_algorithm(
+            SymmetricAlgorithm::AES128,
+            &[
+                recipient("A", None),
+                recipient(
+                    "B",
+                    Some(&[SymmetricAlgorithm::AES256, SymmetricAlgorithm::AES128])
+                ),
+            ]
+        )
+        .unwrap(),
+        SymmetricAlgorithm::AES128
+    );
+
+    // Otherwise the preferences of the recipients are honored
+    assert_eq!(
+        pgp_symmetric_algorithm(
+            SymmetricAlgorithm::AES256,
+            &[
+                recipient(
+                    "A",
+                    Some(&[SymmetricAlgorithm::AES128, SymmetricAlgorithm::AES192])
+                ),
+                recipient("B", Some(&[SymmetricAlgorithm::AES192])),
+            ]
+        )
+        .unwrap(),
+        SymmetricAlgorithm::AES192
+    );
+
+    // No common algorithm
+    match pgp_symmetric_algorithm(
+        SymmetricAlgorithm::AES256,
+        &[
+            recipient("A", Some(&[SymmetricAlgorithm::AES128])),
+            recipient("B", None),
+            recipient("C", Some(&[SymmetricAlgorithm::AES256])),
+        ],
+    ) {
+        Err(EncryptMessageError::Error(err)) => assert_eq!(
+            err,
+            "OpenPGP keys A, C do not share a supported symmetric algorithm"
+        ),
+        result => panic!("Unexpected result: {result:?}"),
+    }
+}
+
+struct PgpTestHelper<'x> {
+    policy: &'x StandardPolicy<'x>,
+    recipient: &'x Cert,
+    signer: &'x Cert,
+    verified: bool,
+    compression: Option<CompressionAlgorithm>,
+}
+
+impl VerificationHelper for PgpTestHelper<'_> {
+    fn get_certs(&mut self, _: &[KeyHandle]) -> openpgp::Result<Vec<Cert>> {
+        Ok(vec![self.signer.clone()])
+    }
+
+    fn check(&mut self, structure: MessageStructure) -> openpgp::Result<()> {
+        for layer in structure {
+            match layer {
+                MessageLayer::SignatureGroup { results } => {
+                    self.verified = results.iter().any(|result| result.is_ok());
+                }
+                MessageLayer::Compression { algo } => {
+                    self.compression = Some(algo);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DecryptionHelper for PgpTestHelper<'_> {
+    fn decrypt(
+        &mut self,
+        pkesks: &[PKESK],
+        _: &[SKESK],
+        sym_algo: Option<SymmetricAlgorithm>,
+        decrypt: &mut dyn FnMut(Option<SymmetricAlgorithm>, &SessionKey) -> bool,
+    ) -> openpgp::Result<Option<Cert>> {
+        let mut keypair = self
+            .recipient
+            .keys()
+            .unencrypted_secret()
+            .with_policy(self.policy, None)
+            .for_transport_encryption()
+            .next()
+            .unwrap()
+            .key()
+            .clone()
+            .into_keypair()?;
+        for pkesk in pkesks {
+            if pkesk
+                .decrypt(&mut keypair, sym_algo)
+                .is_some_and(|(algo, session_key)| decrypt(algo, &session_key))
+            {
+                break;
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn pgp_recipients(message: &[u8]) -> Vec<String> {
+    let message = std::str::from_utf8(message).unwrap();
+    let armored = message
+        .split_once("-----BEGIN PGP MESSAGE-----")
+        .and_then(|(_, armored)| armored.split_once("-----END PGP MESSAGE-----"))
+        .unwrap()
+        .0;
+    let packets = STANDARD
+        .decode(
+            armored
+                .lines()
+                .filter(|line| !line.is_empty() && !line.starts_with('='))
+                .collect::<String>(),
+        )
+        .unwrap();
+
+    let mut recipients = Vec::new();
+    let mut pos = 0;
+    while pos < packets.len() {
+        // Parse OpenPGP packet header
+        let tag = packets[pos];
+        let (packet_tag, header_len, body_len) = if tag & 0x40 != 0 {
+            let (header_len, body_len) = match packets[pos + 1] {
+                len @ 0..192 => (2, len as usize),
+                len @ 192..224 => (
+                    3,
+                    ((len as usize - 192) << 8) + packets[pos + 2] as usize + 192,
+                ),
+                255 => (
+                    6,
+                    u32::from_be_bytes(packets[pos + 2..pos + 6].try_into().unwrap()) as usize,
+                ),
+                _ => break,
+            };
+            (tag & 0x3f, header_len, body_len)
+        } else {
+            let (header_len, body_len) = match tag & 0x03 {
+                0 => (2, packets[pos + 1] as usize),
+                1 => (
+                    3,
+                    u16::from_be_bytes(packets[pos + 1..pos + 3].try_into().unwrap()) as usize,
+                ),
+                2 => (
+                    5,
+                    u32::from_be_bytes(packets[pos + 1..pos + 5].try_into().unwrap()) as usize,
+                ),
+                _ => break,
+            };
+            ((tag >> 2) & 0x0f, header_len, body_len)
+        };
+
+        // Version 3 PKESK packets contain the recipient key id
+        let body = &packets[pos + header_len..];
+        if packet_tag == 1 && body[0] == 3 {
+            recipients.push(body[1..9].iter().map(|b| format!("{b:02X}")).collect());
+        } else if packet_tag != 1 {
+            break;
+        }
+        pos += header_len + body_len;
+    }
+
+    recipients
+}
+
+#[tokio::test]
+pub async fn check_is_encrypted() {
+    let messages = std::fs::read_to_string(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join("crypto")
+            .join("is_encrypted.txt"),
+    )
+    .unwrap();
+    let certs = try_parse_certs(
+        EncryptionMethod::PGP,
+        std::fs::read(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("resources")
+                .join("crypto")
+                .join("cert_pgp.pem"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let params = Archive::deserialize_owned(
+        Archiver::new(EncryptionParams {
+            method: EncryptionMethod::PGP,
+            algo: Algorithm::Aes256,
+            padding: RsaPadding::default(),
+            certs,
+            exclude_mailboxes: vec![],
+            max_encrypt_size: None,
+            compression: None,
+        })
+        .serialize()
+        .unwrap(),
+    )
+    .unwrap();
+
+    for raw_message in messages.split("!!!") {
+        let is_encrypted = raw_message.contains("TRUE");
+        let message = MessageParser::new()
+            .parse(raw_message.trim().as_bytes())
+            .unwrap();
+        assert!(message.content_type().is_some());
+        assert_eq!(
+            message.is_encrypted(),
+            is_encrypted,
+            "failed for {raw_message}"
+        );
+
+        // Signed-only messages are encrypted by wrapping the whole signed entity
+        if !is_encrypted && raw_message.to_lowercase().contains("signed") {
+            let encrypted = message
+                .encrypt(
+                    std::slice::from_ref(params.unarchive::<EncryptionParams>().unwrap()),
+                    None,
+                )
+                .await
+                .unwrap();
+            let encrypted_message = MessageParser::new().parse(&encrypted).unwrap();
+            assert!(encrypted_message.is_encrypted(), "failed for {raw_message}");
+            assert_eq!(encrypted_message.subject(), Some("FALSE"));
+            let encrypted = String::from_utf8(encrypted).unwrap();
+            assert!(
+                !encrypted.contains("signed") && !encrypted.contains("SIGNED"),
+                "failed for {raw_message}"
+            );
+        }
+    }
+}
+
+#[test]
+pub fn wkd_lookup() {
+    // Lookup URLs use the z-base-32 encoded SHA-1 hash of the lowercased local part
+    let [advanced, direct] = wkd_urls("Joe.Doe@Example.ORG").unwrap();
+    assert_eq!(
+        advanced.as_str(),
+        concat!(
+            "https://openpgpkey.example.org/.well-known/openpgpkey/example.org/hu/",
+            "iy9q119eutrkn8s1mk4r39qejnbu3n5q?l=Joe.Doe"
+        )
+    );
+    assert_eq!(
+        direct.as_str(),
+        "https://example.org/.well-known/openpgpkey/hu/iy9q119eutrkn8s1mk4r39qejnbu3n5q?l=Joe.Doe"
+    );
+    assert!(wkd_urls("invalid").is_none());
+    assert!(wkd_urls("@example.org").is_none());
+
+    // Only keys with a user ID matching the address are accepted
+    let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("crypto");
+    let der = std::fs::read(resources.join("cert_pgp.der")).unwrap();
+    assert_eq!(wkd_certs("John@Example.org", der.clone()).unwrap().len(), 1);
+    assert_eq!(
+        wkd_certs("jane@example.org", der).unwrap_err(),
+        "The OpenPGP key published for jane@example.org has no matching user ID"
+    );
+    let keyring = std::fs::read(resources.join("cert_pgp_keyring.pem")).unwrap();
+    let certs = wkd_certs("jane@example.com", keyring).unwrap();
+    assert_eq!(certs.len(), 1);
+    assert_eq!(
+        certificate_info(EncryptionMethod::PGP, &certs[0])
+            .subject
+            .as_deref(),
+        Some("Jane Doe <jane@example.com>")
+    );
+    assert!(
+        wkd_certs("john@example.org", b"not a key".to_vec())
+            .unwrap_err()
+            .starts_with("Could not find any valid certificates")
+    );
 }
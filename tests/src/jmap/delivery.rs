@@ -4,8 +4,9 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use common::auth::maintenance::MaintenanceTarget;
 use email::{
-    cache::{MessageCacheFetch, email::MessageCacheAccess},
+    cache::{MessageCacheFetch, email::MessageCacheAccess, mailbox::MailboxCacheAccess},
     mailbox::{INBOX_ID, JUNK_ID},
 };
 use jmap_proto::types::{collection::Collection, id::Id};
@@ -186,6 +187,28 @@ pub async fn test(params: &mut JMAPTest) {
         );
     }
 
+    // Deliveries to accounts or domains in maintenance are deferred
+    let jane_id = Id::from_bytes(account_id_2.as_bytes())
+        .unwrap()
+        .document_id();
+    for target in [
+        MaintenanceTarget::Account(jane_id),
+        MaintenanceTarget::Domain("example.com"),
+    ] {
+        server.set_maintenance(target, Some(3600)).await.unwrap();
+        assert!(server.get_maintenance(target).await.unwrap().is_some());
+        lmtp.mail_from("bill@example.com", 2).await;
+        lmtp.rcpt_to("jane@example.com", 4)
+            .await
+            .assert_contains("4.2.1");
+        lmtp.rset().await;
+        server.clear_maintenance(target).await.unwrap();
+        assert!(server.get_maintenance(target).await.unwrap().is_none());
+    }
+    lmtp.mail_from("bill@example.com", 2).await;
+    lmtp.rcpt_to("jane@example.com", 2).await;
+    lmtp.rset().await;
+
     // Removing members from the mailing list and chunked ingest
     params
         .server
@@ -264,8 +287,89 @@ pub async fn test(params: &mut JMAPTest) {
         );
     }
 
+    // Catch-all deliveries are filed into mailboxes named after the targeted address
+    let catch_all_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "catchall@catchall.org",
+            "secret",
+            "Catch-all",
+            &["catchall@catchall.org", "@catchall.org"],
+        )
+        .await;
+    for rcpt in [
+        "sales@catchall.org",
+        "support@catchall.org",
+        "sales@catchall.org",
+        "random@catchall.org",
+        "catchall@catchall.org",
+    ] {
+        lmtp.ingest(
+            "bill@example.com",
+            &[rcpt],
+            &format!(
+                concat!(
+                    "From: bill@example.com\r\n",
+                    "To: {}\r\n",
+                    "Subject: Catch me\r\n",
+                    "\r\n",
+                    "Hello."
+                ),
+                rcpt
+            ),
+        )
+        .await;
+    }
+    let catch_all_cache = server.get_cached_messages(catch_all_id).await.unwrap();
+    for (path, num_messages) in [("Catch-all/sales", 2), ("Catch-all/support", 1)] {
+        let mailbox = catch_all_cache
+            .mailbox_by_path(path)
+            .unwrap_or_else(|| panic!("missing mailbox {path}"));
+        assert_eq!(
+            catch_all_cache.in_mailbox(mailbox.document_id).count(),
+            num_messages,
+            "for {path}"
+        );
+    }
+
+    // The number of per-address mailboxes is capped, direct deliveries are not filed
+    assert!(
+        catch_all_cache
+            .mailbox_by_path("Catch-all/random")
+            .is_none()
+    );
+    assert_eq!(catch_all_cache.in_mailbox(INBOX_ID).count(), 2);
+
+    // The original recipient can be queried
+    let catch_all_account = Id::from(catch_all_id).to_string();
+    params.client.set_default_account_id(&catch_all_account);
+    assert_eq!(
+        params
+            .client
+            .email_query(
+                jmap_client::email::query::Filter::header(
+                    "X-Original-To",
+                    Some("Sales@catchall.org")
+                )
+                .into(),
+                None::<Vec<_>>
+            )
+            .await
+            .unwrap()
+            .ids()
+            .len(),
+        2
+    );
+
     // Remove test data
-    for account_id in [&account_id_1, &account_id_2, &account_id_3] {
+    for account_id in [
+        &account_id_1,
+        &account_id_2,
+        &account_id_3,
+        &catch_all_account,
+    ] {
         params.client.set_default_account_id(account_id);
         destroy_all_mailboxes(params).await;
     }
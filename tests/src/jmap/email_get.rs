@@ -4,14 +4,20 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{fs, path::PathBuf};
+use std::{fs, path::PathBuf, sync::Arc, time::Duration};
 
 use ::email::mailbox::INBOX_ID;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use http_proto::HttpResponse;
+use hyper::StatusCode;
 use jmap_client::email::{self, Header, HeaderForm, import::EmailImportResponse};
 use jmap_proto::types::id::Id;
 use mail_parser::HeaderName;
 
-use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes, replace_blob_ids};
+use crate::{
+    http_server::{HttpMessage, spawn_mock_http_server},
+    jmap::{assert_is_empty, mailbox::destroy_all_mailboxes, replace_blob_ids},
+};
 
 use super::JMAPTest;
 
@@ -171,10 +177,127 @@ pub async fn test(params: &mut JMAPTest) {
         }
     }
 
+    // Test remote content proxy
+    remote_content(params, &mailbox_id).await;
+
     destroy_all_mailboxes(params).await;
     assert_is_empty(server).await;
 }
 
+async fn remote_content(params: &mut JMAPTest, mailbox_id: &str) {
+    let _tx = spawn_mock_http_server(Arc::new(|req: HttpMessage| match req.uri.path() {
+        "/image.png" => HttpResponse::new(StatusCode::OK)
+            .with_content_type("image/png")
+            .with_binary_body(b"\x89PNG test image".to_vec()),
+        "/redirect" => HttpResponse::new(StatusCode::FOUND).with_location("/image.png"),
+        _ => HttpResponse::new(StatusCode::OK)
+            .with_content_type("text/html")
+            .with_text_body("<html><script></script></html>"),
+    }))
+    .await;
+
+    // Import an HTML message with remote images
+    let message = concat!(
+        "From: john@example.org\r\n",
+        "To: jane@example.org\r\n",
+        "Subject: Remote images\r\n",
+        "Content-Type: text/html; charset=utf-8\r\n",
+        "\r\n",
+        "<html><body><img src=\"https://127.0.0.1:9090/image.png?a=1&amp;b=2\" ",
+        "srcset=\"https://127.0.0.1:9090/image.png 2x\"> ",
+        "<IMG SRC='https://127.0.0.1:9090/redirect'> ",
+        "<img src=\"cid:part1@example.org\"> ",
+        "<img src=\"https://127.0.0.1:9090/page.html\"></body></html>\r\n"
+    );
+    let email_id = params
+        .client
+        .email_import(
+            message.as_bytes().to_vec(),
+            [mailbox_id.to_string()],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    let mut request = params.client.build();
+    request
+        .get_email()
+        .ids([email_id.as_str()])
+        .properties([email::Property::BodyValues])
+        .arguments()
+        .fetch_html_body_values(true);
+    let html = request
+        .send_get_email()
+        .await
+        .unwrap()
+        .take_list()
+        .pop()
+        .unwrap()
+        .body_value("0")
+        .unwrap()
+        .value()
+        .to_string();
+    let urls = html
+        .split("src=\"")
+        .skip(1)
+        .filter_map(|part| part.split_once('"').map(|(url, _)| url.to_string()))
+        .collect::<Vec<_>>();
+    assert!(!html.contains("srcset"), "{html}");
+    assert!(!html.contains("https://127.0.0.1:9090"), "{html}");
+    assert_eq!(urls.len(), 4, "{html}");
+    assert_eq!(urls[2], "cid:part1@example.org");
+
+    // Fetch images through the proxy
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+    let fetch = |path: String| {
+        client
+            .get(format!("https://127.0.0.1:8899{path}"))
+            .basic_auth("admin", Some("secret"))
+            .send()
+    };
+    for url in [&urls[0], &urls[1]] {
+        assert!(url.starts_with("/jmap/proxy/"), "{url}");
+        let response = fetch(url.to_string()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+        assert_eq!(
+            response.headers().get("x-content-type-options").unwrap(),
+            "nosniff"
+        );
+        assert_eq!(
+            response.bytes().await.unwrap().as_ref(),
+            b"\x89PNG test image"
+        );
+    }
+
+    // Disallowed content types are rejected
+    assert!(!fetch(urls[3].clone()).await.unwrap().status().is_success());
+
+    // Tampered URLs are rejected
+    let (prefix, encoded_url) = urls[0].rsplit_once('/').unwrap();
+    let tampered_url = format!(
+        "{prefix}/{}",
+        URL_SAFE_NO_PAD.encode("https://127.0.0.1:9090/page.html")
+    );
+    assert_eq!(
+        fetch(tampered_url).await.unwrap().status(),
+        StatusCode::NOT_FOUND
+    );
+    let (prefix, _) = prefix.rsplit_once('/').unwrap();
+    assert_eq!(
+        fetch(format!("{prefix}/{}/{encoded_url}", "0".repeat(64)))
+            .await
+            .unwrap()
+            .status(),
+        StatusCode::NOT_FOUND
+    );
+}
+
 pub fn all_headers() -> Vec<email::Property> {
     let mut properties = Vec::new();
 
@@ -0,0 +1,150 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::{Duration, Instant};
+
+use base64::{Engine, engine::general_purpose};
+use jmap_proto::types::id::Id;
+use reqwest::{Version, header};
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    jmap::{
+        assert_is_empty, delivery::SmtpConnection, mailbox::destroy_all_mailboxes, wait_for_index,
+    },
+};
+
+use super::JMAPTest;
+
+const ITERATIONS: usize = 50;
+const QUERY_AND_GET: &str = r##"{
+    "using": [ "urn:ietf:params:jmap:core", "urn:ietf:params:jmap:mail" ],
+    "methodCalls": [
+        [ "Email/query", { "accountId": "$$", "limit": 10 }, "q" ],
+        [ "Email/get", {
+            "accountId": "$$",
+            "#ids": { "resultOf": "q", "name": "Email/query", "path": "/ids" },
+            "properties": [ "subject", "from", "receivedAt", "preview" ]
+        }, "g" ]
+    ]
+}"##;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running HTTP/2 tests...");
+
+    // Create test account
+    let server = params.server.clone();
+    let account_id = Id::from(
+        server
+            .core
+            .storage
+            .data
+            .create_test_user(
+                "jdoe@example.com",
+                "12345",
+                "John Doe",
+                &["jdoe@example.com"],
+            )
+            .await,
+    )
+    .to_string();
+
+    // Ingest a few messages
+    let mut lmtp = SmtpConnection::connect().await;
+    for num in 0..10 {
+        lmtp.ingest(
+            "bill@example.com",
+            &["jdoe@example.com"],
+            &format!(
+                concat!(
+                    "From: bill@example.com\r\n",
+                    "To: jdoe@example.com\r\n",
+                    "Subject: TPS Report #{}\r\n",
+                    "\r\n",
+                    "I'm going to need those TPS reports ASAP."
+                ),
+                num
+            ),
+        )
+        .await;
+    }
+    lmtp.quit().await;
+    wait_for_index(&server).await;
+
+    // Compare session establishment plus Email/query and Email/get over HTTP/1.1 and HTTP/2
+    let h1 = benchmark(&account_id, Version::HTTP_11).await;
+    let h2 = benchmark(&account_id, Version::HTTP_2).await;
+    println!(
+        "{ITERATIONS} sessions with Email/query + Email/get: HTTP/1.1 {:?} ({:?}/session), HTTP/2 {:?} ({:?}/session)",
+        h1,
+        h1 / ITERATIONS as u32,
+        h2,
+        h2 / ITERATIONS as u32,
+    );
+
+    params.client.set_default_account_id(account_id);
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
+
+async fn benchmark(account_id: &str, version: Version) -> Duration {
+    let mut headers = header::HeaderMap::new();
+    headers.insert(
+        header::AUTHORIZATION,
+        header::HeaderValue::from_str(&format!(
+            "Basic {}",
+            general_purpose::STANDARD.encode("jdoe@example.com:12345")
+        ))
+        .unwrap(),
+    );
+    let body = QUERY_AND_GET.replace("$$", account_id);
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        let builder = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .timeout(Duration::from_secs(5))
+            .default_headers(headers.clone());
+        let client = if version == Version::HTTP_2 {
+            builder.http2_prior_knowledge()
+        } else {
+            builder.http1_only()
+        }
+        .build()
+        .unwrap();
+
+        // Fetch session
+        let response = client
+            .get("https://127.0.0.1:8899/.well-known/jmap")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.version(), version);
+        assert!(response.status().is_success());
+        response.bytes().await.unwrap();
+
+        // Email/query followed by Email/get
+        let response = client
+            .post("https://127.0.0.1:8899/jmap")
+            .body(body.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.version(), version);
+        let response =
+            serde_json::from_slice::<serde_json::Value>(&response.bytes().await.unwrap()).unwrap();
+        assert_eq!(
+            response["methodResponses"][1][1]["list"]
+                .as_array()
+                .unwrap()
+                .len(),
+            10,
+            "{response}"
+        );
+    }
+
+    start.elapsed()
+}
@@ -0,0 +1,150 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use email::message::hook::{
+    IngestHook, IngestHookDeadLetter, IngestHookDeadLetters, IngestHookTask, ingest_hook_signature,
+};
+use ring::hmac;
+
+#[test]
+fn ingest_hook_signing() {
+    let payload = r#"{"accountId":"a","mailboxId":"b"}"#;
+    let signature = ingest_hook_signature("secret", 1700000000, payload);
+
+    // The signature covers the timestamp and the payload
+    let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+    hmac::verify(
+        &key,
+        format!("1700000000.{payload}").as_bytes(),
+        &STANDARD.decode(&signature).unwrap(),
+    )
+    .unwrap();
+    assert!(
+        hmac::verify(
+            &key,
+            payload.as_bytes(),
+            &STANDARD.decode(&signature).unwrap()
+        )
+        .is_err()
+    );
+
+    // Replaying the payload with a different timestamp invalidates the signature
+    assert_ne!(
+        signature,
+        ingest_hook_signature("secret", 1700000001, payload)
+    );
+    assert_ne!(
+        signature,
+        ingest_hook_signature("other", 1700000000, payload)
+    );
+}
+
+#[test]
+fn ingest_hook_retry_backoff() {
+    let now = 1700000000;
+    let interval = Duration::from_secs(60);
+    let mut task = IngestHookTask::new("jdoe@example.org", now);
+
+    // Each failed attempt doubles the delay until all attempts are used
+    for (attempt, delay) in [(1, 60), (2, 120), (3, 240), (4, 480)] {
+        assert!(task.retry(now, interval, 5));
+        assert_eq!(task.attempts, attempt);
+        assert_eq!(task.due, now + delay);
+    }
+    assert!(!task.retry(now, interval, 5));
+    assert_eq!(task.attempts, 5);
+
+    // The backoff is capped
+    let mut task = IngestHookTask {
+        attempts: 20,
+        ..IngestHookTask::new("jdoe@example.org", now)
+    };
+    assert!(task.retry(now, interval, 100));
+    assert_eq!(task.due, now + 60 * 1024);
+
+    // A single attempt is made when retries are disabled
+    let mut task = IngestHookTask::new("jdoe@example.org", now);
+    assert!(!task.retry(now, interval, 0));
+    assert_eq!(task.attempts, 1);
+}
+
+#[test]
+fn ingest_hook_dead_letters() {
+    let mut dead_letters = IngestHookDeadLetters::default();
+    for id in 0..5 {
+        dead_letters.push(
+            IngestHookDeadLetter {
+                id,
+                document_id: id as u32,
+                attempts: 5,
+                reason: "Hook request failed".to_string(),
+                task: IngestHookTask::new("jdoe@example.org", 0),
+                ..Default::default()
+            },
+            3,
+        );
+    }
+
+    // The oldest dead letters are discarded first
+    assert_eq!(
+        dead_letters
+            .items
+            .iter()
+            .map(|item| item.id)
+            .collect::<Vec<_>>(),
+        vec![2, 3, 4]
+    );
+
+    // Internal fields are not exposed
+    let json = serde_json::to_value(&dead_letters.items[0]).unwrap();
+    assert_eq!(json["id"], 2);
+    assert_eq!(json["attempts"], 5);
+    assert!(json.get("task").is_none());
+    assert!(json.get("hash").is_none());
+}
+
+#[test]
+fn ingest_hook_urls() {
+    for (url, allow_private_ips, expected) in [
+        ("https://hooks.example.org/ingest", false, Ok(())),
+        ("https://8.8.8.8/ingest", false, Ok(())),
+        (
+            "http://hooks.example.org/ingest",
+            false,
+            Err("Only HTTPS endpoints are supported"),
+        ),
+        (
+            "https://127.0.0.1/ingest",
+            false,
+            Err("Only public addresses are supported"),
+        ),
+        (
+            "https://[::1]/ingest",
+            false,
+            Err("Only public addresses are supported"),
+        ),
+        (
+            "https://10.0.0.1/ingest",
+            false,
+            Err("Only public addresses are supported"),
+        ),
+        ("https://10.0.0.1/ingest", true, Ok(())),
+        ("not a url", false, Err("Invalid URL")),
+    ] {
+        let hook = IngestHook {
+            url: url.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            hook.validate_url(allow_private_ips).map(|_| ()),
+            expected,
+            "{url}"
+        );
+    }
+}
@@ -4,6 +4,12 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use ::email::cache::{
+    MessageCacheFetch,
+    email::{MessageCacheAccess, build_counters, update_counters},
+};
+use common::{MailboxCounters, MessageCache, MessageUidCache};
+use futures::future::join_all;
 use jmap_client::{
     Error, Set,
     client::Client,
@@ -11,11 +17,15 @@ use jmap_client::{
         query::Filter,
         set::{SetError, SetErrorType, SetObject, SetRequest},
     },
+    email,
     mailbox::{self, Mailbox, Role},
 };
-use jmap_proto::types::{id::Id, state::State};
+use jmap_proto::types::{id::Id, keyword::SEEN, state::State};
 use serde::{Deserialize, Serialize};
-use store::ahash::AHashMap;
+use store::{
+    ahash::{AHashMap, AHashSet},
+    rand::Rng,
+};
 
 use crate::jmap::assert_is_empty;
 
@@ -608,6 +618,93 @@ pub async fn test(params: &mut JMAPTest) {
         ["inbox", "sent", "spam"]
     );
 
+    // Counters should remain consistent under concurrent flag changes
+    let mut mail_ids = Vec::new();
+    for thread_num in 0..5 {
+        for message_num in 0..4 {
+            mail_ids.push(
+                client
+                    .email_import(
+                        format!(
+                            concat!(
+                                "From: test@test.com\nMessage-ID: <{}.{}@test.com>\n",
+                                "References: <{}.0@test.com>\nSubject: storm {}\n\ntest"
+                            ),
+                            thread_num, message_num, thread_num, thread_num
+                        )
+                        .into_bytes(),
+                        [&id_map["spam"]],
+                        None::<Vec<&str>>,
+                        None,
+                    )
+                    .await
+                    .unwrap()
+                    .take_id(),
+            );
+        }
+    }
+    for round in 0..5 {
+        let mut futures = Vec::new();
+        for (num, mail_id) in mail_ids.iter().enumerate() {
+            futures.push(client.email_set_keyword(mail_id, "$seen", (num + round) % 3 == 0));
+            futures.push(client.email_set_keyword(mail_id, "$seen", (num * round) % 2 == 0));
+        }
+        for result in join_all(futures).await {
+            result.unwrap();
+        }
+    }
+    let mut unread_emails = 0;
+    let mut unread_threads = AHashSet::new();
+    for mail_id in &mail_ids {
+        let email = client
+            .email_get(
+                mail_id,
+                [email::Property::Keywords, email::Property::ThreadId].into(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        if !email.keywords().contains(&"$seen") {
+            unread_emails += 1;
+            unread_threads.insert(email.thread_id().unwrap().to_string());
+        }
+    }
+    let spam = client
+        .mailbox_get(
+            &id_map["spam"],
+            [
+                mailbox::Property::TotalEmails,
+                mailbox::Property::UnreadEmails,
+                mailbox::Property::TotalThreads,
+                mailbox::Property::UnreadThreads,
+            ]
+            .into(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(spam.total_emails(), 20);
+    assert_eq!(spam.unread_emails(), unread_emails);
+    assert_eq!(spam.total_threads(), 5);
+    assert_eq!(spam.unread_threads(), unread_threads.len());
+
+    // The consistency check should not find any drift
+    let spam_id = Id::from_bytes(id_map["spam"].as_bytes())
+        .unwrap()
+        .document_id();
+    let counters = server
+        .get_cached_messages(0)
+        .await
+        .unwrap()
+        .mailbox_counters(spam_id)
+        .cloned()
+        .unwrap();
+    server.repair_mailbox_counters(0).await.unwrap();
+    let cache = server.get_cached_messages(0).await.unwrap();
+    let repaired_counters = cache.mailbox_counters(spam_id).unwrap();
+    assert!(counters.has_same_counts(repaired_counters));
+    assert_eq!(counters.change_id, repaired_counters.change_id);
+
     destroy_all_mailboxes(params).await;
     params.client.set_default_account_id(Id::from(1u64));
     assert_is_empty(server).await;
@@ -784,3 +881,64 @@ const TEST_MAILBOXES: &[u8] = br#"
     }
 ]
 "#;
+
+#[test]
+fn mailbox_counters_incremental() {
+    let mut rng = store::rand::rng();
+
+    for _ in 0..100 {
+        let mut items: Vec<MessageCache> = Vec::new();
+        let mut counters = AHashMap::new();
+
+        for change_id in 0..200u64 {
+            let document_id = rng.random_range(0..30u32);
+            let prev_item = items
+                .iter()
+                .position(|item| item.document_id == document_id)
+                .map(|idx| items.swap_remove(idx));
+
+            // Remove the previous version of the message
+            if let Some(item) = &prev_item {
+                update_counters(&mut counters, item, change_id, false);
+            }
+
+            // Insert an updated version, unless the message was deleted
+            if rng.random_bool(0.8) {
+                let mut mailbox_ids = (0..rng.random_range(1..=3))
+                    .map(|_| rng.random_range(0..4u32))
+                    .collect::<Vec<_>>();
+                mailbox_ids.sort_unstable();
+                mailbox_ids.dedup();
+                let item = MessageCache {
+                    document_id,
+                    mailboxes: mailbox_ids
+                        .into_iter()
+                        .map(|mailbox_id| MessageUidCache {
+                            mailbox_id,
+                            uid: change_id as u32,
+                        })
+                        .collect(),
+                    keywords: if rng.random_bool(0.5) { 1 << SEEN } else { 0 },
+                    thread_id: prev_item
+                        .as_ref()
+                        .filter(|_| rng.random_bool(0.7))
+                        .map_or_else(|| rng.random_range(0..8u32), |item| item.thread_id),
+                    change_id,
+                };
+                update_counters(&mut counters, &item, change_id, true);
+                items.push(item);
+            }
+
+            let expected = build_counters(&items);
+            for mailbox_id in 0..4u32 {
+                let empty = MailboxCounters::default();
+                let current = counters.get(&mailbox_id).unwrap_or(&empty);
+                let expected = expected.get(&mailbox_id).unwrap_or(&empty);
+                assert!(
+                    current.has_same_counts(expected),
+                    "mailbox {mailbox_id}: {current:?} != {expected:?}"
+                );
+            }
+        }
+    }
+}
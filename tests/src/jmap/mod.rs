@@ -51,6 +51,7 @@ use crate::{
 };
 
 pub mod auth_acl;
+pub mod auth_impersonate;
 pub mod auth_limits;
 pub mod auth_oauth;
 pub mod blob;
@@ -67,6 +68,8 @@ pub mod email_set;
 pub mod email_submission;
 pub mod enterprise;
 pub mod event_source;
+pub mod http2;
+pub mod ingest_hook;
 pub mod mailbox;
 pub mod permissions;
 pub mod purge;
@@ -105,12 +108,14 @@ async fn jmap_tests() {
     auth_acl::test(&mut params).await;
     auth_limits::test(&mut params).await;
     auth_oauth::test(&mut params).await;
+    auth_impersonate::test(&mut params).await;
     event_source::test(&mut params).await;
     push_subscription::test(&mut params).await;
     sieve_script::test(&mut params).await;
     vacation_response::test(&mut params).await;
     email_submission::test(&mut params).await;
     websocket::test(&mut params).await;
+    http2::test(&mut params).await;
     quota::test(&mut params).await;
     crypto::test(&mut params).await;
     blob::test(&mut params).await;
@@ -766,6 +771,10 @@ authentication = "100/5s"
 [authentication]
 rate-limit = "100/2s"
 
+[authentication.impersonation]
+enable = true
+allow-write = true
+
 [session.ehlo]
 reject-non-fqdn = false
 
@@ -895,9 +904,24 @@ throttle = "500ms"
 throttle = "500ms"
 attempts.interval = "500ms"
 
+[jmap.thread]
+max-tree-nodes = 5
+
 [email]
 auto-expunge = "1s"
 
+[email.remote-content]
+enable = true
+allow-private-ips = true
+
+[email.catch-all]
+auto-file = true
+max-mailboxes = 2
+
+[email.encryption.failure]
+alert-after = 2
+notify = "bill.lumbergh@example.com"
+
 [changes]
 max-history = "1"
 
@@ -998,6 +1022,24 @@ events = ["auth.*", "delivery.dsn*", "message-ingest.*", "security.authenticatio
 signature-key = "ovos-moles"
 throttle = "100ms"
 
+[sieve.untrusted]
+prelude = [ { if = "sender == 'prelude@remote.org'", then = "'prelude'" },
+            { if = "sender == 'broken@remote.org'", then = "'missing'" },
+            { else = false } ]
+
+[sieve.untrusted.scripts."prelude@example.com"]
+contents = '''
+require ["include", "variables", "fileinto", "mailbox"];
+
+global "prelude";
+set "prelude" "done";
+
+if header :contains "subject" "[SPAM]" {
+    fileinto :create "Junk Mail";
+    stop;
+}
+'''
+
 [sieve.untrusted.scripts."common"]
 contents = '''
 require "reject";
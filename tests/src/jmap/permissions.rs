@@ -7,16 +7,31 @@
 use std::sync::Arc;
 
 use ahash::AHashSet;
-use common::auth::{AccessToken, TenantInfo};
+use common::{
+    auth::{AccessToken, TenantInfo},
+    config::smtp::system::SystemTemplate,
+};
 
 use directory::{
     Permission, Type,
     backend::internal::{PrincipalField, PrincipalSet, PrincipalUpdate, PrincipalValue},
+    core::protocols::Protocol,
 };
 use email::message::delivery::{IngestMessage, LocalDeliveryStatus, MailDelivery};
+use http::management::{
+    senders::{AuthorizedSender, SenderSource},
+    tls::{CertificateSource, CertificateStatus},
+};
+use imap_proto::ResponseType;
+use jmap_client::client::{Client, Credentials};
+use serde_json::json;
+use smtp::reporting::system::RenderedSystemMessage;
 use utils::BlobHash;
 
-use crate::jmap::assert_is_empty;
+use crate::{
+    imap::{AssertResult, ImapConnection, Type as ImapType},
+    jmap::assert_is_empty,
+};
 
 use super::{JMAPTest, ManagementApi, enterprise::List};
 
@@ -614,6 +629,7 @@ pub async fn test(params: &JMAPTest) {
                 recipients: vec!["john@foobar.org".to_string()],
                 message_blob: message_blob.clone(),
                 message_size: TEST_MESSAGE.len() as u64,
+                encrypt: true,
                 session_id: 0,
             })
             .await
@@ -652,6 +668,7 @@ pub async fn test(params: &JMAPTest) {
                 recipients: vec!["john@foobar.org".to_string()],
                 message_blob: message_blob.clone(),
                 message_size: TEST_MESSAGE.len() as u64,
+                encrypt: true,
                 session_id: 0,
             })
             .await
@@ -677,6 +694,7 @@ pub async fn test(params: &JMAPTest) {
                 recipients: vec!["john@foobar.org".to_string()],
                 message_blob,
                 message_size: TEST_MESSAGE.len() as u64,
+                encrypt: true,
                 session_id: 0,
             })
             .await
@@ -741,6 +759,251 @@ pub async fn test(params: &JMAPTest) {
         .unwrap()
         .unwrap_data();
 
+    // Sender grants must be valid addresses or domains
+    api.post::<u32>(
+        "/api/principal",
+        &PrincipalSet::new(u32::MAX, Type::Group)
+            .with_field(PrincipalField::Name, "agency")
+            .with_field(PrincipalField::Senders, vec!["not an address".to_string()]),
+    )
+    .await
+    .unwrap()
+    .expect_error("Invalid sender");
+
+    // Create a group with a domain grant and a member with an address grant
+    api.post::<u32>(
+        "/api/principal",
+        &PrincipalSet::new(u32::MAX, Type::Group)
+            .with_field(PrincipalField::Name, "agency")
+            .with_field(
+                PrincipalField::Senders,
+                vec!["@Client.org;dkim".to_string()],
+            ),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let sender_id = api
+        .post::<u32>(
+            "/api/principal",
+            &PrincipalSet::new(u32::MAX, Type::Individual)
+                .with_field(PrincipalField::Name, "sender")
+                .with_field(PrincipalField::Senders, vec!["news@agency.org".to_string()])
+                .with_field(PrincipalField::MemberOf, vec!["agency".to_string()]),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    let access_token = server.get_access_token(sender_id).await.unwrap();
+    assert!(access_token.sender_grant("news@agency.org").is_some());
+    assert!(access_token.sender_grant("sales@agency.org").is_none());
+    assert!(
+        access_token
+            .sender_grant("bill@client.org")
+            .is_some_and(|grant| grant.dkim)
+    );
+
+    // Query who can send as a domain
+    let senders = api
+        .get::<List<AuthorizedSender>>("/api/senders/client.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(senders.total, 2);
+    for (name, via) in [("agency", None), ("sender", Some("agency"))] {
+        assert!(
+            senders.items.iter().any(|sender| sender.name == name
+                && sender.address == "@client.org"
+                && sender.dkim
+                && sender.source == SenderSource::Grant
+                && sender.via.as_deref() == via),
+            "{name} not found in {:?}",
+            senders.items
+        );
+    }
+
+    // Removing a group grant should invalidate the access tokens of its members
+    api.patch::<()>(
+        "/api/principal/agency",
+        &vec![PrincipalUpdate::remove_item(
+            PrincipalField::Senders,
+            PrincipalValue::String("@client.org;dkim".to_string()),
+        )],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let access_token = server.get_access_token(sender_id).await.unwrap();
+    assert!(access_token.sender_grant("bill@client.org").is_none());
+    assert!(access_token.sender_grant("news@agency.org").is_some());
+    assert_eq!(
+        api.get::<List<AuthorizedSender>>("/api/senders/client.org")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .total,
+        0
+    );
+
+    for query in ["/api/principal/sender", "/api/principal/agency"] {
+        api.delete::<()>(query).await.unwrap().unwrap_data();
+    }
+
+    // Enabled protocols must be supported
+    api.post::<u32>(
+        "/api/principal",
+        &PrincipalSet::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "kiosk")
+            .with_field(PrincipalField::EnabledProtocols, vec!["gopher".to_string()]),
+    )
+    .await
+    .unwrap()
+    .expect_error("Invalid protocol");
+
+    // Create an IMAP-only account
+    let kiosk_id = api
+        .post::<u32>(
+            "/api/principal",
+            &PrincipalSet::new(u32::MAX, Type::Individual)
+                .with_field(PrincipalField::Name, "kiosk")
+                .with_field(PrincipalField::Secrets, vec!["kiosk-pass".to_string()])
+                .with_field(PrincipalField::Roles, vec!["user".to_string()])
+                .with_field(PrincipalField::EnabledProtocols, vec!["IMAP".to_string()]),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    let access_token = server.get_access_token(kiosk_id).await.unwrap();
+    assert!(access_token.is_protocol_enabled(Protocol::Imap));
+    assert!(!access_token.is_protocol_enabled(Protocol::Jmap));
+    let mut imap = ImapConnection::connect(b"_x ").await;
+    imap.assert_read(ImapType::Untagged, ResponseType::Ok).await;
+    imap.send("LOGIN kiosk kiosk-pass").await;
+    imap.assert_read(ImapType::Tagged, ResponseType::Ok).await;
+    assert!(matches!(
+        Client::new()
+            .credentials(Credentials::basic("kiosk", "kiosk-pass"))
+            .accept_invalid_certs(true)
+            .connect("https://127.0.0.1:8899")
+            .await,
+        Err(jmap_client::Error::Problem(err)) if err.status() == Some(403)));
+
+    // Enabling JMAP takes effect for new sessions immediately
+    api.patch::<()>(
+        "/api/principal/kiosk",
+        &vec![PrincipalUpdate::add_item(
+            PrincipalField::EnabledProtocols,
+            PrincipalValue::String("jmap".to_string()),
+        )],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    Client::new()
+        .credentials(Credentials::basic("kiosk", "kiosk-pass"))
+        .accept_invalid_certs(true)
+        .connect("https://127.0.0.1:8899")
+        .await
+        .unwrap();
+
+    // Disabling IMAP rejects new IMAP logins with a clear error
+    api.patch::<()>(
+        "/api/principal/kiosk",
+        &vec![PrincipalUpdate::set(
+            PrincipalField::EnabledProtocols,
+            PrincipalValue::StringList(vec!["jmap".to_string()]),
+        )],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let mut imap = ImapConnection::connect(b"_x ").await;
+    imap.assert_read(ImapType::Untagged, ResponseType::Ok).await;
+    imap.send("LOGIN kiosk kiosk-pass").await;
+    imap.assert_read(ImapType::Tagged, ResponseType::No)
+        .await
+        .assert_contains("protocol disabled for this account");
+    api.delete::<()>("/api/principal/kiosk")
+        .await
+        .unwrap()
+        .unwrap_data();
+
+    // Preview system message templates
+    let mut core = params.server.inner.shared_core.load_full().as_ref().clone();
+    for (domain, language, subject) in [
+        (None, None, "Account locked on {{domain}}"),
+        (None, Some("es"), "Cuenta bloqueada en {{domain}}"),
+        (Some("notify.org"), None, "Notify: {{name}}"),
+    ] {
+        core.smtp.system.templates.push(SystemTemplate {
+            name: "account-locked".to_string(),
+            domain: domain.map(|d| d.to_string()),
+            language: language.map(|l| l.to_string()),
+            subject: subject.to_string(),
+            body: "Hello {{name}}, {{unknown}}".to_string(),
+        });
+    }
+    params.server.inner.shared_core.store(core.into());
+    api.post::<u32>(
+        "/api/principal",
+        &PrincipalSet::new(u32::MAX, Type::Domain).with_field(PrincipalField::Name, "notify.org"),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let report_domain = &server.core.network.report_domain;
+    for (domain, language, from, subject) in [
+        (
+            "remote.org",
+            None,
+            format!("no-reply@{report_domain}"),
+            format!("Account locked on {report_domain}"),
+        ),
+        (
+            "remote.org",
+            Some("es"),
+            format!("no-reply@{report_domain}"),
+            format!("Cuenta bloqueada en {report_domain}"),
+        ),
+        (
+            "Notify.org",
+            Some("es"),
+            "no-reply@notify.org".to_string(),
+            "Notify: Jane".to_string(),
+        ),
+    ] {
+        let rendered = api
+            .post::<RenderedSystemMessage>(
+                "/api/system-message/account-locked/preview",
+                &json!({
+                    "domain": domain,
+                    "language": language,
+                    "variables": {
+                        "name": "Jane"
+                    }
+                }),
+            )
+            .await
+            .unwrap()
+            .unwrap_data();
+        assert_eq!(
+            rendered,
+            RenderedSystemMessage {
+                from_name: Some("Mail System".to_string()),
+                from_addr: from,
+                subject,
+                body: "Hello Jane, {{unknown}}".to_string(),
+            }
+        );
+    }
+    api.delete::<()>("/api/principal/notify.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    let mut core = params.server.inner.shared_core.load_full().as_ref().clone();
+    core.smtp.system.templates.clear();
+    params.server.inner.shared_core.store(core.into());
+
     server
         .core
         .storage
@@ -749,6 +1012,44 @@ pub async fn test(params: &JMAPTest) {
         .await
         .unwrap();
 
+    // List the certificates held by the server
+    let certificates = api
+        .get::<List<CertificateStatus>>("/api/tls/certificates")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items;
+    let certificate = certificates
+        .iter()
+        .find(|certificate| certificate.domains == ["localhost"])
+        .unwrap_or_else(|| panic!("Missing test certificate: {certificates:?}"));
+    assert_eq!(certificate.source, CertificateSource::Static);
+    assert_eq!(certificate.acme_provider, None);
+    assert_eq!(certificate.issuer.as_deref(), Some("CN=localhost"));
+    assert_eq!(
+        certificate.not_after.as_ref().map(|dt| dt.to_timestamp()),
+        Some(1684237234)
+    );
+    assert_eq!(certificate.renew_at, None);
+    assert_eq!(
+        api.get::<List<CertificateStatus>>("/api/tls/certificates?source=acme")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .total,
+        0
+    );
+    api.get::<List<CertificateStatus>>("/api/tls/certificates?cursor=AQA")
+        .await
+        .unwrap()
+        .expect_error("Invalid or expired cursor");
+
+    // Renewing requires an existing ACME provider
+    api.post::<()>("/api/tls/acme/unknown/renew", &())
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
     assert_is_empty(server).await;
 }
 
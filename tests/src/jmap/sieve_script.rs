@@ -509,6 +509,73 @@ pub async fn test(params: &mut JMAPTest) {
         panic!("Email {:?} not found in: {:#?}", subject, emails);
     }
 
+    // Run prelude tests, the domain prelude runs before the user script and
+    // can stop it from running. A missing prelude falls back to the user script.
+    client
+        .sieve_script_create("test_prelude", get_script("test_prelude"), true)
+        .await
+        .unwrap();
+    for (sender, subject) in [
+        ("prelude@remote.org", "Prelude order"),
+        ("prelude@remote.org", "[SPAM] Prelude stop"),
+        ("broken@remote.org", "Prelude fallback"),
+    ] {
+        lmtp.ingest(
+            sender,
+            &["jdoe@example.com"],
+            &format!(
+                concat!(
+                    "From: {}\r\n",
+                    "To: jdoe@example.com\r\n",
+                    "Subject: {}\r\n",
+                    "\r\n",
+                    "Testing the Sieve prelude."
+                ),
+                sender, subject
+            ),
+        )
+        .await;
+    }
+    for (folder, subject) in [
+        ("Prelude First", "Prelude order"),
+        ("Junk Mail", "[SPAM] Prelude stop"),
+        ("Inbox", "Prelude fallback"),
+    ] {
+        let mailbox_id = client
+            .mailbox_query(
+                mailbox::query::Filter::name(folder.to_string()).into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .take_ids()
+            .pop()
+            .unwrap_or_else(|| panic!("Mailbox {:?} not found", folder));
+        let email_ids = client
+            .email_query(
+                email::query::Filter::and([
+                    email::query::Filter::in_mailbox(&mailbox_id),
+                    email::query::Filter::subject(subject),
+                ])
+                .into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .take_ids();
+        assert_eq!(email_ids.len(), 1, "{subject:?} not found in {folder:?}");
+        let email = client
+            .email_get(&email_ids[0], [email::Property::MailboxIds].into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            email.mailbox_ids(),
+            &[mailbox_id.as_str()],
+            "{subject:?} was filed elsewhere"
+        );
+    }
+
     // Remove test data
     client.sieve_script_deactivate().await.unwrap();
     let mut request = client.build();
@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes};
+use crate::jmap::{assert_is_empty, jmap_json_request, mailbox::destroy_all_mailboxes};
 use jmap_client::mailbox::Role;
 use jmap_proto::types::id::Id;
 
@@ -51,6 +51,68 @@ pub async fn test(params: &mut JMAPTest) {
         expected_result
     );
 
+    // Build a reply tree from the References and In-Reply-To headers
+    let mut email_ids = Vec::new();
+    for (num, headers) in [
+        "Message-ID: <a@tree>\nSubject: tree",
+        "Message-ID: <b@tree>\nIn-Reply-To: <a@tree>\nReferences: <a@tree>\nSubject: Re: tree",
+        "Message-ID: <c@tree>\nReferences: <a@tree> <b@tree>\nSubject: Re: tree",
+        "Message-ID: <d@tree>\nReferences: <a@tree> <missing@tree>\nSubject: Re: tree",
+        "Message-ID: <e@tree>\nReferences: <missing@tree>\nSubject: Re: tree",
+        "Message-ID: <f@tree>\nReferences: <a@tree>\nSubject: Re: tree",
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut email = params
+            .client
+            .email_import(
+                format!("{headers}\n\n{num}").into_bytes(),
+                [&mailbox_id],
+                None::<Vec<String>>,
+                Some(20000i64 + num as i64),
+            )
+            .await
+            .unwrap();
+        thread_id = email.thread_id().unwrap().to_string();
+        email_ids.push(email.take_id());
+    }
+
+    let response = jmap_json_request(
+        format!(
+            r#"[["Thread/get", {{"accountId": "{}", "ids": ["{}"], "properties": ["id", "emailTree"]}}, "0"]]"#,
+            Id::new(1),
+            thread_id
+        ),
+        "admin",
+        "secret",
+    )
+    .await;
+    let thread = response.pointer("/methodResponses/0/1/list/0").unwrap();
+    assert_eq!(thread.get("emailIds"), None, "{response}");
+    assert_eq!(thread["isTruncated"], true, "{response}");
+
+    // The tree is capped at the configured number of nodes, "f" is left out
+    let tree = thread["emailTree"].as_array().unwrap();
+    assert_eq!(tree.len(), 5, "{response}");
+    for (node, (email_id, parent_id, is_parent_missing)) in tree.iter().zip([
+        (&email_ids[0], None, false),
+        (&email_ids[1], Some(&email_ids[0]), false),
+        (&email_ids[2], Some(&email_ids[1]), false),
+        // Attached to the closest ancestor present in the thread
+        (&email_ids[3], Some(&email_ids[0]), true),
+        // No ancestors in the thread, attached using the subject
+        (&email_ids[4], Some(&email_ids[0]), true),
+    ]) {
+        assert_eq!(node["emailId"], email_id.as_str(), "{response}");
+        assert_eq!(
+            node["parentId"].as_str(),
+            parent_id.map(|id| id.as_str()),
+            "{response}"
+        );
+        assert_eq!(node["isParentMissing"], is_parent_missing, "{response}");
+    }
+
     destroy_all_mailboxes(params).await;
     assert_is_empty(server).await;
 }
@@ -250,6 +250,8 @@ async fn test_multi_thread(params: &mut JMAPTest) {
                         received_at: None,
                         source: IngestSource::Smtp {
                             deliver_to: "test@domain.org",
+                            catch_all: false,
+                            encrypt: true,
                         },
                         spam_classify: false,
                         spam_train: false,
@@ -6,22 +6,33 @@
 
 use std::{fs, net::IpAddr, path::PathBuf, sync::Arc, time::Duration};
 
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 use common::{
-    Server,
+    Data, Inner, Server,
     config::{
         server::{Listener, Listeners, ServerProtocol, TcpListener},
         smtp::*,
     },
     expr::{functions::ResolveVariable, if_block::*, tokenizer::TokenMap, *},
+    listener::{TcpAcceptor, tickets::TicketKeySettings, tls::ResolveSni},
 };
 
 use compact_str::ToCompactString;
+use rustls::{
+    CipherSuite, ClientConfig, ClientConnection, Connection, HandshakeKind, ServerConfig,
+    ServerConnection,
+};
+use rustls_pki_types::ServerName;
+use store::write::now;
 use throttle::parse_queue_rate_limiter;
 use tokio::net::TcpSocket;
 
-use utils::config::{Config, Rate};
+use utils::{
+    config::{Config, ConfigError, Rate},
+    rustls_client_config,
+};
 
-use super::add_test_certs;
+use super::{TestSMTP, add_test_certs};
 
 struct TestEnvelope {
     pub local_ip: IpAddr,
@@ -321,6 +332,7 @@ fn parse_servers() {
             max_connections: 8192,
             proxy_networks: vec![],
             span_id_gen: id_generator.clone(),
+            banner: Default::default(),
         },
         Listener {
             id: "smtps".into(),
@@ -346,6 +358,7 @@ fn parse_servers() {
             max_connections: 1024,
             proxy_networks: vec![],
             span_id_gen: id_generator.clone(),
+            banner: Default::default(),
         },
         Listener {
             id: "submission".into(),
@@ -361,6 +374,7 @@ fn parse_servers() {
             max_connections: 8192,
             proxy_networks: vec![],
             span_id_gen: id_generator.clone(),
+            banner: Default::default(),
         },
     ];
 
@@ -397,6 +411,428 @@ fn parse_servers() {
     }
 }
 
+#[test]
+fn parse_listener_banner() {
+    let mut config = Config::new(
+        r#"
+[server.listener.imap]
+bind = ["127.0.0.1:9143"]
+protocol = "imap"
+banner.greeting = "{hostname} ({listener}) ready"
+banner.hide-product = true
+capabilities.hide-before-tls = ["auth=plain", "STARTTLS"]
+capabilities.hide-before-auth = ["ID", "AUTH=OAUTHBEARER"]
+
+[server.listener.pop3]
+bind = ["127.0.0.1:9110"]
+protocol = "pop3"
+tls.enable = false
+banner.greeting = "ready\r\n+OK"
+capabilities.hide-before-tls = ["AUTH=PLAIN", "AUTH=OAUTHBEARER", "AUTH=XOAUTH2"]
+"#,
+    )
+    .unwrap();
+    let servers = Listeners::parse(&mut config).servers;
+
+    let imap = &servers.iter().find(|s| s.id == "imap").unwrap().banner;
+    assert_eq!(
+        imap.greeting("mx.example.org", "imap", ServerProtocol::Imap)
+            .unwrap(),
+        "mx.example.org (imap) ready"
+    );
+    assert!(imap.hide_product);
+    assert!(imap.is_hidden("AUTH=PLAIN", false, false));
+    assert!(!imap.is_hidden("AUTH=PLAIN", true, false));
+    assert!(imap.is_hidden("ID", true, false));
+    assert!(!imap.is_hidden("ID", true, true));
+
+    // Capabilities required by the protocol cannot be hidden
+    assert!(!imap.is_hidden("STARTTLS", false, false));
+    assert!(!imap.is_hidden("AUTH=OAUTHBEARER", false, false));
+    for key in [
+        "server.listener.imap.capabilities.hide-before-tls",
+        "server.listener.imap.capabilities.hide-before-auth",
+        "server.listener.pop3.banner.greeting",
+        "server.listener.pop3.capabilities.hide-before-tls",
+    ] {
+        assert!(config.errors.contains_key(key), "missing error for {key}");
+    }
+
+    // Authentication must remain possible on listeners without TLS
+    let pop3 = &servers.iter().find(|s| s.id == "pop3").unwrap().banner;
+    assert!(pop3.greeting.is_none());
+    assert!(!pop3.is_hidden("AUTH=PLAIN", false, false));
+}
+
+#[test]
+fn parse_listener_tls_policy() {
+    let mut config = Config::new(
+        r#"
+[server.listener.smtp]
+bind = ["127.0.0.1:9925"]
+protocol = "smtp"
+tls.max-version = "TLSv1.2"
+tls.ciphers = ["TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256", "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384"]
+
+[server.listener.jmap]
+bind = ["127.0.0.1:9943"]
+protocol = "http"
+tls.min-version = "TLSv1.3"
+tls.alpn = ["http/1.1"]
+
+[server.listener.unknown-cipher]
+bind = ["127.0.0.1:9944"]
+protocol = "http"
+tls.ciphers = ["TLS13_AES_128_GCM_SHA256", "TLS_RSA_WITH_RC4_128_SHA"]
+
+[server.listener.inverted-range]
+bind = ["127.0.0.1:9945"]
+protocol = "imap"
+tls.min-version = "TLSv1.3"
+tls.max-version = "TLSv1.2"
+
+[server.listener.legacy]
+bind = ["127.0.0.1:9946"]
+protocol = "smtp"
+tls.min-version = "TLSv1.0"
+
+[server.listener.unusable-ciphers]
+bind = ["127.0.0.1:9947"]
+protocol = "imap"
+tls.min-version = "TLSv1.3"
+tls.ciphers = ["TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"]
+"#,
+    )
+    .unwrap();
+    let mut listeners = Listeners::parse(&mut config);
+    listeners.parse_tcp_acceptors(&mut config, Arc::new(Inner::default()));
+
+    // Each listener gets its own server config
+    let TcpAcceptor::Tls { config: smtp, .. } = &listeners.tcp_acceptors["smtp"] else {
+        panic!("Expected TLS acceptor for smtp");
+    };
+    let TcpAcceptor::Tls { config: jmap, .. } = &listeners.tcp_acceptors["jmap"] else {
+        panic!("Expected TLS acceptor for jmap");
+    };
+    assert!(!Arc::ptr_eq(smtp, jmap));
+    assert_eq!(
+        smtp.crypto_provider()
+            .cipher_suites
+            .iter()
+            .map(|suite| suite.suite())
+            .collect::<Vec<_>>(),
+        vec![
+            CipherSuite::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+            CipherSuite::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384
+        ]
+    );
+    assert!(smtp.alpn_protocols.is_empty());
+    assert_eq!(jmap.alpn_protocols, vec![b"http/1.1".to_vec()]);
+
+    // Listeners with an invalid policy are not started
+    for (id, key, error) in [
+        (
+            "unknown-cipher",
+            "tls.ciphers",
+            "Unknown cipher suite \"TLS_RSA_WITH_RC4_128_SHA\"",
+        ),
+        (
+            "inverted-range",
+            "tls.min-version",
+            "Minimum TLS version TLSv1.3 is higher than maximum version TLSv1.2",
+        ),
+        ("legacy", "tls.min-version", "is not supported"),
+        ("unusable-ciphers", "tls.ciphers", "None of the configured"),
+    ] {
+        let details = match config
+            .errors
+            .get(&format!("server.listener.{id}.{key}"))
+            .unwrap_or_else(|| panic!("missing error for {id}"))
+        {
+            ConfigError::Build { error } => error,
+            other => panic!("unexpected error for {id}: {other:?}"),
+        };
+        assert!(
+            details.starts_with(&format!("Listener {id:?}: ")) && details.contains(error),
+            "unexpected error for {id}: {details}"
+        );
+        assert!(!listeners.tcp_acceptors.contains_key(id));
+        assert!(!listeners.servers.iter().any(|server| server.id == id));
+    }
+}
+
+#[tokio::test]
+async fn tls_session_resumption() {
+    let config = add_test_certs(
+        r#"
+[server.listener.imap]
+bind = ["127.0.0.1:9948"]
+protocol = "imap"
+
+[server.listener.no-tickets]
+bind = ["127.0.0.1:9949"]
+protocol = "imap"
+tls.session-tickets.enable = false
+
+[certificate.default]
+cert = '%{file:{CERT}}%'
+private-key = '%{file:{PK}}%'
+"#,
+    );
+    let (node_a, imap_a, no_tickets) = build_tls_node(&config).await;
+    let (node_b, imap_b, _) = build_tls_node(&config).await;
+    assert!(imap_a.ticketer.enabled());
+    assert!(!no_tickets.ticketer.enabled());
+
+    // Sessions are resumed after the certificate is renewed
+    let client = Arc::new(rustls_client_config(true));
+    assert_eq!(tls_handshake(&client, &imap_a), HandshakeKind::Full);
+    assert_eq!(tls_handshake(&client, &imap_a), HandshakeKind::Resumed);
+    let mut certificates = node_a.data.tls_certificates.load().as_ref().clone();
+    for cert in certificates.values_mut() {
+        *cert = Arc::new(cert.as_ref().clone());
+    }
+    node_a.data.tls_certificates.store(certificates.into());
+    assert_eq!(tls_handshake(&client, &imap_a), HandshakeKind::Resumed);
+
+    // Retired ticket keys decrypt tickets during the grace period
+    let client = Arc::new(rustls_client_config(true));
+    assert_eq!(tls_handshake(&client, &imap_a), HandshakeKind::Full);
+    let mut keys = node_a.data.tls_ticket_keys.load().as_ref().clone();
+    keys.rotate(&TicketKeySettings::default(), now()).unwrap();
+    node_a.data.tls_ticket_keys.store(keys.into());
+    assert_eq!(tls_handshake(&client, &imap_a), HandshakeKind::Resumed);
+
+    // Tickets issued with expired keys require a full handshake
+    let client = Arc::new(rustls_client_config(true));
+    assert_eq!(tls_handshake(&client, &imap_a), HandshakeKind::Full);
+    let mut keys = node_a.data.tls_ticket_keys.load().as_ref().clone();
+    keys.rotate(
+        &TicketKeySettings {
+            grace_period: Duration::ZERO,
+            ..Default::default()
+        },
+        now(),
+    )
+    .unwrap();
+    node_a.data.tls_ticket_keys.store(keys.into());
+    assert_eq!(tls_handshake(&client, &imap_a), HandshakeKind::Full);
+
+    // Sessions are resumed on other nodes once the shared keys are installed
+    let client = Arc::new(rustls_client_config(true));
+    assert_eq!(tls_handshake(&client, &imap_a), HandshakeKind::Full);
+    let mut keys = node_a.data.tls_ticket_keys.load().as_ref().clone();
+    keys.merge(
+        node_b.data.tls_ticket_keys.load().as_ref(),
+        &TicketKeySettings::default(),
+        now(),
+    );
+    node_b.data.tls_ticket_keys.store(keys.into());
+    assert_eq!(tls_handshake(&client, &imap_b), HandshakeKind::Resumed);
+    assert_eq!(tls_handshake(&client, &imap_a), HandshakeKind::Resumed);
+
+    // Stateful resumption is used when tickets are disabled
+    let client = Arc::new(rustls_client_config(true));
+    assert_eq!(tls_handshake(&client, &no_tickets), HandshakeKind::Full);
+    assert_eq!(tls_handshake(&client, &no_tickets), HandshakeKind::Resumed);
+}
+
+#[tokio::test]
+async fn listener_certificates() {
+    let config = add_test_certs(
+        r#"
+[server.listener.smtp]
+bind = ["127.0.0.1:9950"]
+protocol = "smtp"
+tls.certificate = "internal"
+
+[server.listener.imap]
+bind = ["127.0.0.1:9951"]
+protocol = "imap"
+
+[server.listener.unknown-cert]
+bind = ["127.0.0.1:9952"]
+protocol = "imap"
+tls.certificate = "missing"
+
+[certificate.shared]
+cert = '%{file:{CERT}}%'
+private-key = '%{file:{PK}}%'
+subjects = ["mail.example.org"]
+default = true
+
+[certificate.internal]
+cert = '%{file:{CERT}}%'
+private-key = '%{file:{PK}}%'
+subjects = ["mail.example.org"]
+"#,
+    );
+    let mut config = Config::new(config).unwrap();
+    config.resolve_all_macros().await;
+    let inner = Arc::new(Inner {
+        data: Data::parse(&mut config),
+        ..Default::default()
+    });
+    let mut listeners = Listeners::parse(&mut config);
+    listeners.parse_tcp_acceptors(&mut config, inner.clone());
+
+    // Bound certificates are stored under the listener id
+    let certs = inner.data.tls_certificates.load();
+    let mut names = certs.keys().map(String::as_str).collect::<Vec<_>>();
+    names.sort_unstable();
+    assert_eq!(
+        names,
+        [
+            "*",
+            "localhost",
+            "mail.example.org",
+            "smtp/localhost",
+            "smtp/mail.example.org"
+        ]
+    );
+    assert!(!Arc::ptr_eq(
+        &certs["mail.example.org"],
+        &certs["smtp/mail.example.org"]
+    ));
+
+    // Listeners resolve their own certificates before the shared ones
+    let resolver = |id: &str| match &listeners.tcp_acceptors[id] {
+        TcpAcceptor::Tls {
+            resolver: Some(resolver),
+            ..
+        } => resolver.clone(),
+        _ => panic!("Expected TLS acceptor for {id}"),
+    };
+    let smtp = resolver("smtp");
+    let imap = resolver("imap");
+    assert_eq!(smtp.listener_id.as_deref(), Some("smtp"));
+    assert_eq!(imap.listener_id, None);
+    for (listener, sni, expected) in [
+        (&smtp, Some("mail.example.org"), "smtp/mail.example.org"),
+        (&smtp, Some("example.net"), "*"),
+        (&smtp, None, "*"),
+        (&imap, Some("mail.example.org"), "mail.example.org"),
+        (&imap, Some("localhost"), "localhost"),
+    ] {
+        assert_eq!(
+            certs
+                .resolve_sni(listener.listener_id.as_deref(), sni)
+                .map(Arc::as_ptr),
+            Some(Arc::as_ptr(&certs[expected])),
+            "listener: {:?}, sni: {sni:?}",
+            listener.listener_id
+        );
+    }
+
+    // Listeners referencing an unknown certificate are not started
+    match config
+        .errors
+        .get("server.listener.unknown-cert.tls.certificate")
+        .expect("missing error for unknown-cert")
+    {
+        ConfigError::Build { error } => assert!(
+            error.contains("Unknown certificate or ACME provider \"missing\""),
+            "unexpected error: {error}"
+        ),
+        other => panic!("unexpected error: {other:?}"),
+    }
+    assert!(!listeners.tcp_acceptors.contains_key("unknown-cert"));
+
+    // ACME certificates are only installed for the listeners bound to the provider
+    let server = TestSMTP::new("smtp_listener_certificates", ACME_LISTENER)
+        .await
+        .build_smtp();
+    let provider = server.core.acme.providers.get("internal").unwrap();
+    assert_eq!(provider.listeners, ["submission"]);
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("resources");
+    path.push("smtp");
+    path.push("certs");
+    let mut pem = fs::read(path.join("tls_privatekey.pem")).unwrap();
+    pem.extend_from_slice(&fs::read(path.join("tls_cert.pem")).unwrap());
+    server
+        .core
+        .storage
+        .config
+        .set(
+            [("acme.internal.cert", URL_SAFE_NO_PAD.encode(&pem).as_str())],
+            true,
+        )
+        .await
+        .unwrap();
+    server.init_acme(provider).await.unwrap();
+    let certs = server.inner.data.tls_certificates.load();
+    assert!(certs.contains_key("submission/mx.example.org"));
+    assert!(!certs.contains_key("mx.example.org"));
+}
+
+const ACME_LISTENER: &str = r#"
+[server.listener.submission]
+bind = ["127.0.0.1:9953"]
+protocol = "smtp"
+tls.certificate = "internal"
+
+[acme."internal"]
+directory = "https://127.0.0.1:1/directory"
+contact = ["postmaster@example.org"]
+domains = ["mx.example.org"]
+"#;
+
+async fn build_tls_node(config: &str) -> (Arc<Inner>, Arc<ServerConfig>, Arc<ServerConfig>) {
+    let mut config = Config::new(config).unwrap();
+    config.resolve_all_macros().await;
+    let inner = Arc::new(Inner {
+        data: Data::parse(&mut config),
+        ..Default::default()
+    });
+    let mut listeners = Listeners::parse(&mut config);
+    listeners.parse_tcp_acceptors(&mut config, inner.clone());
+    let server_config = |id: &str| match &listeners.tcp_acceptors[id] {
+        TcpAcceptor::Tls { config, .. } => config.clone(),
+        _ => panic!("Expected TLS acceptor for {id}"),
+    };
+
+    (inner, server_config("imap"), server_config("no-tickets"))
+}
+
+fn tls_handshake(
+    client_config: &Arc<ClientConfig>,
+    server_config: &Arc<ServerConfig>,
+) -> HandshakeKind {
+    let mut client = Connection::from(
+        ClientConnection::new(
+            client_config.clone(),
+            ServerName::try_from("mail.example.org").unwrap(),
+        )
+        .unwrap(),
+    );
+    let mut server = Connection::from(ServerConnection::new(server_config.clone()).unwrap());
+    for _ in 0..10 {
+        if !client.is_handshaking() && !server.is_handshaking() {
+            break;
+        }
+        tls_transfer(&mut client, &mut server);
+        tls_transfer(&mut server, &mut client);
+    }
+    assert!(!client.is_handshaking() && !server.is_handshaking());
+
+    // Deliver the tickets sent after the handshake
+    tls_transfer(&mut server, &mut client);
+    client.handshake_kind().unwrap()
+}
+
+fn tls_transfer(from: &mut Connection, to: &mut Connection) {
+    let mut buf = Vec::new();
+    while from.wants_write() {
+        from.write_tls(&mut buf).unwrap();
+    }
+    let mut bytes = buf.as_slice();
+    while !bytes.is_empty() {
+        to.read_tls(&mut bytes).unwrap();
+        to.process_new_packets().unwrap();
+    }
+}
+
 #[tokio::test]
 async fn eval_if() {
     let mut file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
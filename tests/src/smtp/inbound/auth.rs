@@ -40,6 +40,7 @@ secret = "secret"
 email = ["john@example.org", "jdoe@example.org", "john.doe@example.org"]
 email-list = ["info@example.org"]
 member-of = ["sales"]
+sender = ["@client.org;dkim", "news@agency.org"]
 
 [[directory."local".principals]]
 name = "jane"
@@ -57,6 +58,7 @@ mechanisms = [{if = "remote_ip = '10.0.0.1' && is_tls", then = "[plain, login]"}
 directory = [{if = "remote_ip = '10.0.0.1'", then = "'local'"},
              {else = false}]
 must-match-sender = true
+must-match-from = true
 
 [session.auth.errors]
 total = [{if = "remote_ip = '10.0.0.1'", then = 2},
@@ -80,7 +82,9 @@ async fn auth() {
     config.assert_no_errors();
 
     // EHLO should not advertise plain text auth without TLS
-    let mut session = Session::test(TestSMTP::from_core(core).server);
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server);
     session.data.remote_ip_str = "10.0.0.1".into();
     session.eval_session_params().await;
     session.stream.tls = false;
@@ -127,6 +131,33 @@ async fn auth() {
     session.mail_from("john@example.org", "250").await;
     session.data.mail_from.take();
 
+    // Sender grants allow using additional addresses and domains
+    session.mail_from("bill@client.org", "250").await;
+    session.data.mail_from.take();
+    session.mail_from("news@agency.org", "250").await;
+    session.data.mail_from.take();
+    session.mail_from("sales@agency.org", "501 5.5.4").await;
+
+    // From header addresses must be authorized as well
+    session.mail_from("john@example.org", "250").await;
+    session.rcpt_to("bill@foobar.org", "250").await;
+    session
+        .data(
+            "From: Sales <sales@agency.org>\r\nSubject: test\r\n\r\ntest",
+            "550 5.7.1",
+        )
+        .await;
+    qr.assert_no_events();
+    session
+        .send_message(
+            "bill@client.org",
+            &["bill@foobar.org"],
+            "From: Bill <bill@client.org>\r\nSubject: test\r\n\r\ntest",
+            "250",
+        )
+        .await;
+    qr.expect_message().await;
+
     // Should not be able to authenticate twice
     session
         .cmd("AUTH PLAIN AGpvaG4Ac2VjcmV0", "503 5.5.1")
@@ -4,8 +4,12 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::time::{Duration, Instant};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use ahash::AHashSet;
 use common::Core;
 use mail_auth::{SpfResult, common::parse::TxtRecordParser, spf::Spf};
 
@@ -14,7 +18,7 @@ use utils::config::Config;
 
 use crate::smtp::{
     DnsCache, TestSMTP,
-    session::{TestSession, VerifyResponse},
+    session::{TestSession, VerifyResponse, test_server_instance},
 };
 
 const CONFIG: &str = r#"
@@ -27,6 +31,10 @@ future-release = [{if = "remote_ip = '10.0.0.1'", then = '1h'},
                   {else = false}]
 mt-priority = [{if = "remote_ip = '10.0.0.1'", then = 'nsep'},
                {else = false}]
+vrfy = true
+
+[session.auth]
+mechanisms = "[plain, login]"
 
 [session.ehlo]
 reject-non-fqdn = "starts_with(remote_ip, '10.0.0.')"
@@ -100,4 +108,34 @@ async fn ehlo() {
         .assert_not_contains("MT-PRIORITY")
         .assert_not_contains("FUTURERELEASE")
         .assert_not_contains("STARTTLS");
+
+    // Capabilities hidden until STARTTLS
+    let mut instance = test_server_instance();
+    instance.banner.hide_before_tls =
+        AHashSet::from_iter(["AUTH=PLAIN".to_string(), "VRFY".to_string()]);
+    session.instance = Arc::new(instance);
+    session.data.helo_domain = "".into();
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.stream.tls = false;
+    session.eval_session_params().await;
+    session
+        .cmd("EHLO mx1.foobar.org", "250")
+        .await
+        .assert_contains("AUTH LOGIN")
+        .assert_not_contains("PLAIN")
+        .assert_not_contains("VRFY")
+        .assert_contains("STARTTLS");
+    session.cmd("VRFY john", "252 2.5.1").await;
+
+    // Capabilities are advertised again after STARTTLS
+    session.stream.tls = true;
+    session.eval_session_params().await;
+    session
+        .cmd("EHLO mx1.foobar.org", "250")
+        .await
+        .assert_contains("PLAIN")
+        .assert_contains("LOGIN")
+        .assert_contains("VRFY")
+        .assert_not_contains("STARTTLS");
 }
@@ -0,0 +1,136 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{Core, telemetry::load_test::LoadTestStage};
+
+use store::Stores;
+use utils::config::Config;
+
+use smtp::core::{
+    Session,
+    load_test::{LoadTestGenerator, LoadTestRequest},
+};
+
+use crate::smtp::{
+    TempDir, TestSMTP,
+    session::{TestSession, VerifyResponse},
+};
+
+const CONFIG: &str = r#"
+[storage]
+data = "rocksdb"
+lookup = "rocksdb"
+blob = "rocksdb"
+fts = "rocksdb"
+directory = "local"
+
+[store."rocksdb"]
+type = "rocksdb"
+path = "{TMP}/data.db"
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "john"
+description = "John Doe"
+secret = "secret"
+email = "john@foobar.org"
+
+[session.rcpt]
+directory = "'local'"
+relay = false
+
+[test-mode]
+enable = {ENABLE}
+
+[test-mode.sink]
+domains = ["sink.foobar.org"]
+"#;
+
+#[tokio::test]
+async fn load_test_sink() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_load_test", true);
+    let mut config =
+        Config::new(tmp_dir.update_config(CONFIG.replace("{ENABLE}", "true"))).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+
+    // Messages addressed to sink domains should be discarded after filtering
+    let test = TestSMTP::from_core(core);
+    let mut qr = test.queue_receiver;
+    let mut session = Session::test(test.server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+    session
+        .send_message(
+            "john@doe.org",
+            &["anyone@sink.foobar.org", "other@sink.foobar.org"],
+            "test:no_msgid",
+            "250",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Stage latencies should have been recorded
+    let stats = test.server.inner.data.load_test.report();
+    for (stage, count) in [
+        (LoadTestStage::Receive, 1),
+        (LoadTestStage::Filter, 1),
+        (LoadTestStage::Encrypt, 0),
+        (LoadTestStage::Store, 0),
+    ] {
+        let report = stats.iter().find(|s| s.stage == Some(stage)).unwrap();
+        assert_eq!(report.count, count, "{stage:?}");
+        assert_eq!(
+            report.histogram.iter().map(|b| b.count).sum::<u64>(),
+            count,
+            "{stage:?}"
+        );
+    }
+
+    // Unknown recipients on other domains are still rejected
+    session.mail_from("john@doe.org", "250").await;
+    session.rcpt_to("anyone@foobar.org", "550 5.1.2").await;
+    session.rset().await;
+
+    // Mixed recipients are queued as usual
+    session
+        .send_message(
+            "john@doe.org",
+            &["john@foobar.org", "anyone@sink.foobar.org"],
+            "test:no_msgid",
+            "250",
+        )
+        .await;
+    qr.expect_message().await;
+
+    // The generator must refuse to run when test mode is disabled
+    let tmp_dir = TempDir::new("smtp_load_test_disabled", true);
+    let mut config =
+        Config::new(tmp_dir.update_config(CONFIG.replace("{ENABLE}", "false"))).unwrap();
+    let stores = Stores::parse_all(&mut config, false).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    let test = TestSMTP::from_core(core);
+    assert!(
+        test.server
+            .run_load_test(LoadTestRequest::default())
+            .await
+            .is_err()
+    );
+
+    // Sink domains are ignored when test mode is disabled
+    let mut session = Session::test(test.server.clone());
+    session.data.remote_ip_str = "10.0.0.1".into();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+    session.mail_from("john@doe.org", "250").await;
+    session.rcpt_to("anyone@sink.foobar.org", "550 5.1.2").await;
+}
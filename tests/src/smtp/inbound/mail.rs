@@ -51,6 +51,8 @@ requiretls = [{if = "remote_ip = '10.0.0.2'", then = true},
             {else = false}]
 mt-priority = [{if = "remote_ip = '10.0.0.2'", then = 'nsep'},
                {else = false}]
+mt-priority-max = [{if = "remote_ip = '10.0.0.2'", then = 2},
+                   {else = 0}]
 
 [session.mail]
 is-allowed = "sender_domain != 'blocked.com'"
@@ -239,8 +241,21 @@ async fn mail() {
     session.response().assert_code("250");
     assert_eq!(session.data.priority, -3);
     session.rset().await;
+    session
+        .ingest(b"MAIL FROM:<jane@foobar.org> MT-PRIORITY=4\r\n")
+        .await
+        .unwrap();
+    session.response().assert_code("250");
+    assert_eq!(session.data.priority, 2);
+    session.rset().await;
 
     // Test REQUIRETLS extension
+    session
+        .ingest(b"MAIL FROM:<jane@foobar.org> REQUIRETLS\r\n")
+        .await
+        .unwrap();
+    session.response().assert_code("530 5.7.10");
+    session.stream.tls = true;
     session
         .ingest(b"MAIL FROM:<jane@foobar.org> REQUIRETLS\r\n")
         .await
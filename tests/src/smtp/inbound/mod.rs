@@ -30,6 +30,7 @@ pub mod data;
 pub mod dmarc;
 pub mod ehlo;
 pub mod limits;
+pub mod load_test;
 pub mod mail;
 pub mod milter;
 pub mod rcpt;
@@ -143,6 +144,7 @@ impl QueueReceiver {
         QueuedMessage {
             due: self.message_due(queue_id).await,
             queue_id,
+            priority: 0,
         }
     }
 
@@ -6,7 +6,7 @@
 
 use std::time::{Duration, Instant};
 
-use common::Core;
+use common::{Core, config::smtp::auth::MailAuthConfig};
 
 use mail_auth::{
     common::{parse::TxtRecordParser, verify::DomainKey},
@@ -219,3 +219,38 @@ async fn sign_and_seal() {
             "ARC-Message-Signature: i=1; a=ed25519-sha256; s=ed; d=example.com; c=relaxed/simple;",
         );
 }
+
+#[test]
+fn signed_headers() {
+    let mut config = Config::new(concat!(
+        "[signature.no-from]\n",
+        "algorithm = 'ed25519-sha256'\n",
+        "domain = 'example.com'\n",
+        "selector = 'ed'\n",
+        "headers = ['From', 'To', 'Subject']\n",
+        "exclude = ['from']\n",
+        "\n",
+        "[signature.oversign]\n",
+        "algorithm = 'ed25519-sha256'\n",
+        "domain = 'example.com'\n",
+        "selector = 'ed'\n",
+        "oversign = ['From:2', 'Subject:many']\n",
+    ))
+    .unwrap();
+    MailAuthConfig::parse(&mut config);
+
+    assert!(
+        config.errors.contains_key("signature.no-from.headers"),
+        "{:?}",
+        config.errors
+    );
+    assert!(
+        config
+            .errors
+            .keys()
+            .any(|key| key.starts_with("signature.oversign.oversign")),
+        "{:?}",
+        config.errors
+    );
+    assert_eq!(config.errors.len(), 2, "{:?}", config.errors);
+}
@@ -93,6 +93,40 @@ expect = "-0-1-world-1"
 expr = "counter_get('sql', 'county') + '-' + counter_incr('sql', 'county', 1) + '-' + counter_incr('sql', 'county', 1) + '-' + counter_get('sql', 'county')"
 expect = "0-1-2-2"
 
+[test."dns_exists"]
+expr = "dns_exists('mx', rcpt_domain) + '-' + dns_lookup('mx', rcpt_domain)[0]"
+expect = "1-mx.foobar.org"
+
+[test."kv_get"]
+expr = "kv_get('sql', 'hello')"
+expect = "world"
+
+[test."rate_ok"]
+expr = "rate_ok('bucket', 2, 1h) + '-' + rate_ok('bucket', 2, 1h) + '-' + rate_ok('bucket', 2, 1h)"
+expect = "1-1-0"
+
+[test."idna"]
+expr = "idna_to_ascii('bücher.example') + ' ' + idna_to_unicode('xn--bcher-kva.example')"
+expect = "xn--bcher-kva.example bücher.example"
+
+[test."address_parts"]
+expr = "local_part('jane@foobar.org') + ' ' + domain_part('jane@foobar.org')"
+expect = "jane foobar.org"
+
+[test."budget"]
+expr = "key_exists('sql', 'a') + key_exists('sql', 'b') + key_exists('sql', 'c') + key_exists('sql', 'd') + key_exists('sql', 'e') + key_exists('sql', 'f')"
+
+[test."invalid_dns_type"]
+expr = "dns_exists('aaaa', rcpt_domain)"
+
+[test."invalid_rate"]
+expr = "rate_ok('bucket', 0, 1h)"
+
+[expression.limits]
+max-calls = 5
+call-timeout = "1s"
+timeout = "5s"
+
 "#;
 
 #[tokio::test]
@@ -214,7 +248,17 @@ async fn lookup_sql() {
         V_LOCAL_IP,
         V_PRIORITY,
     ]);
-    for test_name in ["sql", "dns", "key_get", "counter_get"] {
+    for test_name in [
+        "sql",
+        "dns",
+        "key_get",
+        "counter_get",
+        "dns_exists",
+        "kv_get",
+        "rate_ok",
+        "idna",
+        "address_parts",
+    ] {
         let e =
             Expression::try_parse(&mut config, ("test", test_name, "expr"), &token_map).unwrap();
         assert_eq!(
@@ -228,6 +272,50 @@ async fn lookup_sql() {
         );
     }
 
+    // Function calls exceeding the evaluation budget should fail
+    let e = Expression::try_parse(&mut config, ("test", "budget", "expr"), &token_map).unwrap();
+    assert!(
+        test.server
+            .eval_expr::<String, _>(&e, &RecipientDomain::new("test.org"), "text", 0)
+            .await
+            .is_none()
+    );
+
+    // Dry-run evaluations should include a trace of each function call
+    let e = Expression::try_parse(&mut config, ("test", "dns_exists", "expr"), &token_map).unwrap();
+    let (result, calls) = test
+        .server
+        .eval_expr_with_trace(&e, &RecipientDomain::new("test.org"), 0)
+        .await;
+    assert_eq!(result.unwrap().to_string(), "1-mx.foobar.org");
+    assert_eq!(
+        calls
+            .iter()
+            .map(|call| (call.function, call.arguments.join(","), call.result.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "dns_exists",
+                "mx,test.org".to_string(),
+                Some("1".to_string())
+            ),
+            (
+                "dns_lookup",
+                "mx,test.org".to_string(),
+                Some("mx.foobar.org".to_string())
+            ),
+        ]
+    );
+
+    // Invalid function arguments should be rejected at config validation
+    for test_name in ["invalid_dns_type", "invalid_rate"] {
+        assert!(
+            Expression::try_parse(&mut config, ("test", test_name, "expr"), &token_map).is_none(),
+            "failed for '{}'",
+            test_name
+        );
+    }
+
     let mut session = Session::test(test.server);
     session.data.remote_ip_str = "10.0.0.50".parse().unwrap();
     session.eval_session_params().await;
@@ -61,6 +61,8 @@ relay = true
 pub(super) struct List<T> {
     pub items: Vec<T>,
     pub total: usize,
+    #[serde(default)]
+    pub cursor: Option<String>,
 }
 
 #[tokio::test]
@@ -258,6 +260,40 @@ async fn manage_queue() {
     }
     assert_eq!(id_map.len(), 6);
 
+    // Page through the queue using cursors
+    let mut paged_ids = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let page = api
+            .request::<List<QueueId>>(
+                Method::GET,
+                &format!(
+                    "/api/queue/messages?limit=4{}",
+                    cursor
+                        .as_ref()
+                        .map(|cursor| format!("&cursor={cursor}"))
+                        .unwrap_or_default()
+                ),
+            )
+            .await
+            .unwrap()
+            .unwrap_data();
+        assert_eq!(page.total, 6);
+        paged_ids.extend(page.items);
+        if let Some(next) = page.cursor {
+            cursor = Some(next);
+        } else {
+            break;
+        }
+    }
+    let mut expected_ids = id_map.values().copied().collect::<Vec<_>>();
+    expected_ids.sort_unstable();
+    assert_eq!(paged_ids, expected_ids);
+    api.request::<List<QueueId>>(Method::GET, "/api/queue/messages?cursor=invalid")
+        .await
+        .unwrap()
+        .expect_error("Invalid or expired cursor");
+
     // Test list search
     for (query, expected_ids) in [
         (
@@ -294,6 +330,29 @@ async fn manage_queue() {
         assert_eq!(ids, expected_ids, "failed for {query}");
     }
 
+    // Queue ids are accepted in decimal and hexadecimal form, invalid ids are not found
+    let id = *id_map.get("a").unwrap();
+    for query in [format!("{id}"), format!("{id:#x}"), format!("<{id:#x}>")] {
+        assert!(
+            api.request::<Message>(Method::GET, &format!("/api/queue/messages/{query}"))
+                .await
+                .unwrap()
+                .try_unwrap_data()
+                .is_some(),
+            "failed for {query}"
+        );
+    }
+    for query in ["not-an-id", "0xzz"] {
+        assert!(
+            api.request::<Message>(Method::GET, &format!("/api/queue/messages/{query}"))
+                .await
+                .unwrap()
+                .try_unwrap_data()
+                .is_none(),
+            "failed for {query}"
+        );
+    }
+
     // Retry delivery
     for id in [id_map.get("e").unwrap(), id_map.get("f").unwrap()] {
         assert!(
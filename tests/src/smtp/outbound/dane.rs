@@ -4,11 +4,15 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use crate::smtp::{
-    DnsCache, TestSMTP,
-    inbound::{TestMessage, TestQueueEvent, TestReportingEvent},
-    session::{TestSession, VerifyResponse},
+use crate::{
+    http_server::{HttpMessage, spawn_mock_http_server},
+    smtp::{
+        DnsCache, TestSMTP,
+        inbound::{TestMessage, TestQueueEvent, TestReportingEvent},
+        session::{TestSession, VerifyResponse},
+    },
 };
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 use common::{
     Core,
     config::{
@@ -17,6 +21,8 @@ use common::{
     },
     ipc::PolicyType,
 };
+use http_proto::{JsonResponse, ToHttpResponse};
+use hyper::Method;
 use mail_auth::{
     MX, MessageAuthenticator,
     common::parse::TxtRecordParser,
@@ -37,7 +43,7 @@ use std::{
     io::{BufRead, BufReader},
     num::ParseIntError,
     path::PathBuf,
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
@@ -351,6 +357,118 @@ async fn dane_test() {
     }
 }
 
+const TLSA_PUBLISH: &str = r#"
+[acme."dane"]
+directory = "https://127.0.0.1:1/directory"
+contact = ["postmaster@example.org"]
+domains = ["mx.example.org"]
+dane.url = "https://127.0.0.1:9090/tlsa"
+dane.allow-invalid-certs = true
+dane.hosts = ["mx1.example.org", "mx2.example.org"]
+
+[acme."dane-next"]
+directory = "https://127.0.0.1:1/directory"
+contact = ["postmaster@example.org"]
+domains = ["mx.example.org"]
+dane.url = "https://127.0.0.1:9090/tlsa"
+dane.allow-invalid-certs = true
+dane.pre-publish = true
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn dane_tlsa_publish() {
+    // Spawn mock DANE hook
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let requests_ = requests.clone();
+    let _tx = spawn_mock_http_server(Arc::new(move |req: HttpMessage| {
+        assert_eq!(req.method, Method::POST);
+        assert_eq!(req.uri.path(), "/tlsa");
+        requests_
+            .lock()
+            .unwrap()
+            .push(serde_json::from_slice::<serde_json::Value>(req.body.as_ref().unwrap()).unwrap());
+        JsonResponse::new(&serde_json::json!({})).into_http_response()
+    }))
+    .await;
+    let server = TestSMTP::new("smtp_dane_tlsa_publish", TLSA_PUBLISH)
+        .await
+        .build_smtp();
+
+    // Cache an issued certificate for both providers
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("resources");
+    path.push("smtp");
+    path.push("certs");
+    let mut pem = fs::read(path.join("tls_privatekey.pem")).unwrap();
+    pem.extend_from_slice(&fs::read(path.join("tls_cert.pem")).unwrap());
+    for acme_id in ["dane", "dane-next"] {
+        server
+            .core
+            .storage
+            .config
+            .set(
+                [(
+                    format!("acme.{acme_id}.cert").as_str(),
+                    URL_SAFE_NO_PAD.encode(&pem).as_str(),
+                )],
+                true,
+            )
+            .await
+            .unwrap();
+    }
+    let digest = "49ba2c6a0dc664b4002c9ebc0fc327c63dfed7ed641a0f9bdbeb784080ac1100";
+
+    // Loading the certificate publishes its TLSA record to the listed MX hosts
+    let provider = server.core.acme.providers.get("dane").unwrap();
+    server.init_acme(provider).await.unwrap();
+    let request = requests.lock().unwrap().pop().unwrap();
+    assert_eq!(request["provider"], "dane");
+    assert_eq!(
+        request["names"],
+        serde_json::json!(["_25._tcp.mx1.example.org", "_25._tcp.mx2.example.org"])
+    );
+    assert_eq!(request["new_digest"], digest);
+    assert!(request.get("old_digest").is_none());
+    assert!(request.get("next_digest").is_none());
+    assert_eq!(
+        request["records"],
+        serde_json::json!([format!("3 1 1 {digest}")])
+    );
+
+    // Unchanged keys are not published again
+    server.init_acme(provider).await.unwrap();
+    assert!(requests.lock().unwrap().is_empty());
+
+    // Pre-publishing includes the record of the next key, which is kept for the next order
+    let provider = server.core.acme.providers.get("dane-next").unwrap();
+    server.init_acme(provider).await.unwrap();
+    let request = requests.lock().unwrap().pop().unwrap();
+    assert_eq!(
+        request["names"],
+        serde_json::json!(["_25._tcp.mx.example.org"])
+    );
+    assert_eq!(request["new_digest"], digest);
+    let next_digest = request["next_digest"].as_str().unwrap().to_string();
+    assert_ne!(next_digest, digest);
+    assert_eq!(
+        request["records"],
+        serde_json::json!([format!("3 1 1 {digest}"), format!("3 1 1 {next_digest}")])
+    );
+    assert!(
+        server
+            .core
+            .storage
+            .config
+            .get("acme.dane-next.next-key")
+            .await
+            .unwrap()
+            .is_some()
+    );
+    server.init_acme(provider).await.unwrap();
+    assert!(requests.lock().unwrap().is_empty());
+}
+
 pub fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
     (0..s.len())
         .step_by(2)
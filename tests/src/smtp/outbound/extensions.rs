@@ -7,7 +7,8 @@
 use std::time::{Duration, Instant};
 
 use common::config::server::ServerProtocol;
-use mail_auth::MX;
+use mail_auth::{MX, common::parse::TxtRecordParser, mta_sts::MtaSts};
+use smtp::{outbound::mta_sts::lookup::STS_TEST_POLICY, queue::MAIL_TLS_NOT_REQUIRED};
 use smtp_proto::{MAIL_REQUIRETLS, MAIL_RET_HDRS, MAIL_SMTPUTF8, RCPT_NOTIFY_NEVER};
 
 use crate::smtp::{
@@ -22,6 +23,12 @@ relay = true
 
 [session.extensions]
 dsn = true
+mt-priority = "mixer"
+mt-priority-max = 4
+
+[session.data]
+priority = [{if = "sender = 'alerts@test.org'", then = 3},
+            {else = 0}]
 "#;
 
 const REMOTE: &str = r#"
@@ -37,6 +44,8 @@ size = 1500
 [session.extensions]
 dsn = true
 requiretls = true
+mt-priority = "mixer"
+mt-priority-max = 5
 
 [session.data.add-headers]
 received = true
@@ -78,6 +87,7 @@ async fn extensions() {
 
     let mut session = local.new_session();
     session.data.remote_ip_str = "10.0.0.1".into();
+    session.stream.tls = true;
     session.eval_session_params().await;
     session.ehlo("mx.test.org").await;
     session
@@ -134,6 +144,48 @@ async fn extensions() {
     local.queue_receiver.read_event().await.assert_done();
     remote.queue_receiver.assert_no_events();
 
+    // REQUIRETLS should fail when the MX cannot be authenticated
+    session
+        .send_message(
+            "<john@test.org> REQUIRETLS",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    local
+        .queue_receiver
+        .expect_message_then_deliver()
+        .await
+        .try_deliver(core.clone());
+    local
+        .queue_receiver
+        .expect_message()
+        .await
+        .read_lines(&local.queue_receiver)
+        .await
+        .assert_contains("<bill@foobar.org> (REQUIRETLS failed for 'mx.foobar.org'")
+        .assert_contains("Action: failed")
+        .assert_contains("Status: 5.7.30");
+    local.queue_receiver.read_event().await.assert_done();
+    remote.queue_receiver.assert_no_events();
+
+    // Authenticate the MX using MTA-STS
+    core.txt_add(
+        "_mta-sts.foobar.org",
+        MtaSts::parse(b"v=STSv1; id=requiretls;").unwrap(),
+        Instant::now() + Duration::from_secs(10),
+    );
+    STS_TEST_POLICY.lock().extend_from_slice(
+        concat!(
+            "version: STSv1\n",
+            "mode: testing\n",
+            "mx: *.foobar.org\n",
+            "max_age: 604800\n"
+        )
+        .as_bytes(),
+    );
+
     // Test DSN, SMTPUTF8 and REQUIRETLS extensions
     session
         .send_message(
@@ -155,4 +207,64 @@ async fn extensions() {
     assert!((message.flags & MAIL_REQUIRETLS) != 0);
     assert!((message.flags & MAIL_SMTPUTF8) != 0);
     assert!((message.recipients.last().unwrap().flags & RCPT_NOTIFY_NEVER) != 0);
+    STS_TEST_POLICY.lock().clear();
+
+    // Test MT-PRIORITY extension, priorities above the maximum are lowered
+    for (mail_from, local_priority) in [
+        ("<john@test.org> MT-PRIORITY=4", 4),
+        ("<john@test.org> MT-PRIORITY=5", 4),
+        ("<alerts@test.org>", 3),
+    ] {
+        session
+            .send_message(mail_from, &["bill@foobar.org"], "test:no_dkim", "250")
+            .await;
+        let queued = local.queue_receiver.expect_message().await;
+        assert_eq!(queued.priority, local_priority, "{mail_from}");
+        local
+            .queue_receiver
+            .delivery_attempt(queued.queue_id)
+            .await
+            .try_deliver(core.clone());
+        local.queue_receiver.read_event().await.assert_done();
+        assert_eq!(
+            remote.queue_receiver.expect_message().await.priority,
+            local_priority,
+            "{mail_from}"
+        );
+    }
+
+    // Test TLS-Required header field
+    let message = concat!(
+        "From: john@test.org\r\n",
+        "To: bill@foobar.org\r\n",
+        "Subject: TLS optional\r\n",
+        "TLS-Required: No\r\n",
+        "\r\n",
+        "Deliver this message even if TLS is not available.\r\n"
+    );
+    session
+        .send_message("john@test.org", &["bill@foobar.org"], message, "250")
+        .await;
+    let queued = local.queue_receiver.expect_message().await;
+    assert!((queued.flags & MAIL_TLS_NOT_REQUIRED) != 0);
+    local
+        .queue_receiver
+        .delivery_attempt(queued.queue_id)
+        .await
+        .try_deliver(core.clone());
+    local.queue_receiver.read_event().await.assert_done();
+    remote.queue_receiver.expect_message().await;
+
+    // TLS-Required: No is ignored when REQUIRETLS is requested
+    session
+        .send_message(
+            "<john@test.org> REQUIRETLS",
+            &["bill@foobar.org"],
+            message,
+            "250",
+        )
+        .await;
+    let queued = local.queue_receiver.expect_message().await;
+    assert!((queued.flags & MAIL_REQUIRETLS) != 0);
+    assert!((queued.flags & MAIL_TLS_NOT_REQUIRED) == 0);
 }
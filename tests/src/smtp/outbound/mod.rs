@@ -13,3 +13,4 @@ pub mod mta_sts;
 pub mod smtp;
 pub mod throttle;
 pub mod tls;
+pub mod warmup;
@@ -0,0 +1,264 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::net::IpAddr;
+
+use smtp::{
+    outbound::warmup::{
+        IpWarmupManager, WARMUP_SENT, WarmupCounters, WarmupState, WarmupStatus, counter_key,
+    },
+    queue::{Error, Status},
+};
+use store::write::now;
+use trc::DeliveryEvent;
+
+use crate::smtp::TestSMTP;
+
+const CONFIG: &str = r#"
+[queue.warmup."new-ip"]
+address = "10.0.0.5"
+schedule = [2, 4]
+destinations = ["gmail.com"]
+min-volume = 1
+max-bounce-rate = 0.05
+max-deferral-rate = 0.1
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn ip_warmup() {
+    // Enable logging
+    crate::enable_logging();
+
+    let local = TestSMTP::new("smtp_warmup", CONFIG).await;
+    let server = local.build_smtp();
+    let warming: IpAddr = "10.0.0.5".parse().unwrap();
+    let established: IpAddr = "10.0.0.1".parse().unwrap();
+
+    // Warming IPs are preferred until the daily limit for a destination is reached,
+    // capacity is reserved on selection
+    let mut reservations = Vec::new();
+    for _ in 0..2 {
+        assert_eq!(
+            server
+                .select_source_ip(
+                    vec![warming, established],
+                    "gmail.com",
+                    &mut reservations,
+                    0
+                )
+                .await
+                .unwrap(),
+            Some(warming)
+        );
+    }
+    assert_eq!(reservations.len(), 2);
+    assert_eq!(
+        server
+            .select_source_ip(
+                vec![warming, established],
+                "gmail.com",
+                &mut reservations,
+                0
+            )
+            .await
+            .unwrap(),
+        Some(established)
+    );
+    assert_eq!(reservations.len(), 2);
+
+    // Capacity is given back when a message is not delivered
+    server
+        .record_warmup_outcome(
+            warming,
+            WarmupCounters {
+                sent: 0,
+                bounced: 1,
+                deferred: 0,
+            },
+        )
+        .await;
+    server
+        .release_warmup_reservations(vec![reservations.pop().unwrap()])
+        .await;
+    assert_eq!(
+        server
+            .select_source_ip(
+                vec![warming, established],
+                "gmail.com",
+                &mut reservations,
+                0
+            )
+            .await
+            .unwrap(),
+        Some(warming)
+    );
+    server
+        .record_warmup_outcome(
+            warming,
+            WarmupCounters {
+                sent: 1,
+                bounced: 0,
+                deferred: 0,
+            },
+        )
+        .await;
+    assert_eq!(
+        server
+            .select_source_ip(
+                vec![warming, established],
+                "gmail.com",
+                &mut reservations,
+                0
+            )
+            .await
+            .unwrap(),
+        Some(established)
+    );
+
+    // Counters are kept in the data store
+    let today = now() / 86400;
+    assert_eq!(
+        server
+            .warmup_counters(warming, today, "gmail.com")
+            .await
+            .unwrap()
+            .sent,
+        2
+    );
+    assert_eq!(
+        server.warmup_counters(warming, today, "").await.unwrap(),
+        WarmupCounters {
+            sent: 1,
+            bounced: 1,
+            deferred: 0,
+        }
+    );
+    assert_eq!(
+        server
+            .warmup_store()
+            .counter_get(counter_key(warming, today, WARMUP_SENT, ""))
+            .await
+            .unwrap(),
+        1
+    );
+
+    // Other destinations are counted separately
+    assert_eq!(
+        server
+            .select_source_ip(
+                vec![warming, established],
+                "example.org",
+                &mut reservations,
+                0
+            )
+            .await
+            .unwrap(),
+        Some(warming)
+    );
+
+    // Deliveries are deferred when there are no established IPs to overflow to
+    assert!(matches!(
+        server
+            .select_source_ip(vec![warming], "gmail.com", &mut reservations, 0)
+            .await,
+        Err(Status::TemporaryFailure(Error::RateLimited))
+    ));
+
+    // IPs without a warmup schedule are not affected
+    assert_eq!(
+        server
+            .select_source_ip(vec![established], "gmail.com", &mut reservations, 0)
+            .await
+            .unwrap(),
+        Some(established)
+    );
+
+    // Ramp progression
+    let config = server.core.smtp.queue.warmup.get(&warming).unwrap();
+    let mut state = WarmupState::default();
+    assert_eq!(config.daily_limit(state.phase), 2);
+    assert_eq!(
+        state.advance(
+            config,
+            &WarmupCounters {
+                sent: 100,
+                bounced: 1,
+                deferred: 2,
+            }
+        ),
+        Some(DeliveryEvent::WarmupPhaseChanged)
+    );
+    assert_eq!(config.daily_limit(state.phase), 4);
+
+    // High bounce rates pause the ramp
+    assert_eq!(
+        state.advance(
+            config,
+            &WarmupCounters {
+                sent: 100,
+                bounced: 10,
+                deferred: 0,
+            }
+        ),
+        Some(DeliveryEvent::WarmupPaused)
+    );
+    assert_eq!(state.status, WarmupStatus::Paused);
+    assert_eq!(state.phase, 1);
+
+    // Days without delivery attempts do not resume a paused ramp
+    assert_eq!(state.advance(config, &WarmupCounters::default()), None);
+    assert_eq!(state.status, WarmupStatus::Paused);
+
+    // Rates are relative to all delivery attempts
+    let counters = WarmupCounters {
+        sent: 8,
+        bounced: 1,
+        deferred: 1,
+    };
+    assert_eq!(counters.attempts(), 10);
+    assert_eq!(counters.bounce_rate(), Some(0.1));
+    assert_eq!(counters.deferral_rate(), Some(0.1));
+    assert_eq!(WarmupCounters::default().bounce_rate(), None);
+    assert_eq!(
+        state.advance(
+            config,
+            &WarmupCounters {
+                sent: 0,
+                bounced: 1,
+                deferred: 0,
+            }
+        ),
+        None
+    );
+    assert_eq!(state.status, WarmupStatus::Paused);
+    assert_eq!(
+        state.advance(
+            config,
+            &WarmupCounters {
+                sent: 100,
+                bounced: 0,
+                deferred: 0,
+            }
+        ),
+        Some(DeliveryEvent::WarmupResumed)
+    );
+
+    // Completing the last phase ends the warmup
+    assert_eq!(
+        state.advance(
+            config,
+            &WarmupCounters {
+                sent: 100,
+                bounced: 0,
+                deferred: 0,
+            }
+        ),
+        Some(DeliveryEvent::WarmupCompleted)
+    );
+    assert_eq!(state.status, WarmupStatus::Completed);
+    assert_eq!(state.advance(config, &WarmupCounters::default()), None);
+}
@@ -4,11 +4,11 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::time::Duration;
+use std::{cmp::Reverse, time::Duration};
 
 use mail_auth::hickory_resolver::proto::op::ResponseCode;
 
-use smtp::queue::{Domain, Message, Schedule, Status, spool::SmtpSpool};
+use smtp::queue::{Domain, Message, Schedule, Status, parse_queue_id, spool::SmtpSpool};
 use store::write::now;
 
 use crate::smtp::TestSMTP;
@@ -65,6 +65,71 @@ async fn queue_due() {
     qr.assert_queue_is_empty().await;
 }
 
+#[tokio::test]
+async fn queue_priority() {
+    // Enable logging
+    crate::enable_logging();
+
+    let local = TestSMTP::new("smtp_queue_priority_test", CONFIG).await;
+    let core = local.build_smtp();
+    let qr = &local.queue_receiver;
+
+    for (queue_id, priority) in [(0, 0), (1, 5), (2, -3)] {
+        let mut message = new_message(queue_id);
+        message.priority = priority;
+        message.domains.push(domain("a", 0, 10, 20));
+        let due = message.next_delivery_event();
+        message.save_changes(&core, 0.into(), due.into()).await;
+    }
+
+    // Priorities are stored with the queue events
+    let mut events = core.next_event().await;
+    events.sort_by_key(|event| event.queue_id);
+    assert_eq!(
+        events
+            .iter()
+            .map(|event| (event.queue_id, event.priority))
+            .collect::<Vec<_>>(),
+        vec![(0, 0), (1, 5), (2, -3)]
+    );
+
+    // Messages waiting past their due time gain priority
+    let now = now();
+    let aging = core.core.smtp.queue.priority_aging.as_secs();
+    let mut events = events
+        .into_iter()
+        .map(|mut event| {
+            event.due = now;
+            event
+        })
+        .collect::<Vec<_>>();
+    events[2].due = now - (aging * 9);
+    events.sort_by_key(|event| Reverse(event.effective_priority(now, aging)));
+    assert_eq!(
+        events
+            .iter()
+            .map(|event| event.queue_id)
+            .collect::<Vec<_>>(),
+        vec![2, 1, 0]
+    );
+    events[0].due = now - (aging * 4);
+    events.sort_by_key(|event| Reverse(event.effective_priority(now, aging)));
+    assert_eq!(
+        events
+            .iter()
+            .map(|event| event.queue_id)
+            .collect::<Vec<_>>(),
+        vec![1, 2, 0]
+    );
+
+    for queue_id in 0..3 {
+        let message = core.read_message(queue_id).await.unwrap();
+        let due = message.next_delivery_event();
+        message.remove(&core, due).await;
+    }
+    qr.assert_queue_is_empty().await;
+}
+
 #[test]
 fn delivery_events() {
     let mut message = new_message(0);
@@ -128,6 +193,32 @@ fn delivery_events() {
     assert!(message.next_event().is_none());
 }
 
+#[test]
+fn queue_id_parsing() {
+    for (id, expected) in [
+        // Decimal ids as returned by the management API
+        ("1234", Some(1234)),
+        ("18446744073709551615", Some(u64::MAX)),
+        // Hexadecimal ids as shown in SMTP responses and DSNs
+        ("4d2", Some(0x4d2)),
+        ("<4D2>", Some(0x4d2)),
+        ("ffffffffffffffff", Some(u64::MAX)),
+        // Hexadecimal ids made only of digits require a prefix
+        ("0x1234", Some(0x1234)),
+        ("0X1234", Some(0x1234)),
+        (" <0x1234> ", Some(0x1234)),
+        // Invalid ids
+        ("", None),
+        ("0x", None),
+        ("queue", None),
+        ("4d2g", None),
+        ("18446744073709551616", None),
+        ("1ffffffffffffffff", None),
+    ] {
+        assert_eq!(parse_queue_id(id), expected, "failed for {id:?}");
+    }
+}
+
 pub fn new_message(queue_id: u64) -> Message {
     Message {
         size: 0,
@@ -7,4 +7,5 @@
 pub mod concurrent;
 pub mod dsn;
 pub mod manager;
+pub mod quarantine;
 pub mod retry;
@@ -0,0 +1,142 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::ipc::QueueEvent;
+use smtp::queue::{
+    Domain, MAIL_QUARANTINED, RCPT_QUARANTINE_RELEASED, RCPT_QUARANTINED, Recipient, Schedule,
+    Status,
+    quarantine::{QuarantineRelease, ReleaseToken},
+    spool::SmtpSpool,
+};
+use store::write::now;
+
+use crate::smtp::{TestSMTP, queue::manager::new_message};
+
+const CONFIG: &str = r#"
+[queue.quarantine]
+hold = true
+release.url = "https://mail.example.org/"
+release.expiry = "1d"
+"#;
+
+#[tokio::test]
+async fn quarantine_release() {
+    // Enable logging
+    crate::enable_logging();
+
+    let mut local = TestSMTP::new("smtp_quarantine_release_test", CONFIG).await;
+    let core = local.build_smtp();
+
+    // Queue a quarantined message with two held recipients
+    let mut message = new_message(1);
+    message.flags |= MAIL_QUARANTINED;
+    message.domains.push(Domain {
+        domain: "foobar.org".into(),
+        retry: Schedule::later(Duration::from_secs(3600)),
+        notify: Schedule::later(Duration::from_secs(7200)),
+        expires: now() + 86400,
+        status: Status::Scheduled,
+    });
+    for rcpt in ["john@foobar.org", "jane@foobar.org"] {
+        message.recipients.push(Recipient {
+            domain_idx: 0,
+            address: rcpt.into(),
+            address_lcase: rcpt.into(),
+            status: Status::Scheduled,
+            flags: RCPT_QUARANTINED,
+            orcpt: None,
+        });
+    }
+    let due = message.next_event();
+    message.save_changes(&core, 0.into(), due).await;
+
+    // Links point to the configured URL and expire after the configured period
+    let (url, expires) = core.quarantine_release_url(1, "john@foobar.org");
+    let token = url
+        .strip_prefix("https://mail.example.org/quarantine/release/")
+        .unwrap()
+        .to_string();
+    assert!(expires > now() + 86000 && expires <= now() + 86400);
+
+    // Tampered tokens and tokens issued for other recipients are rejected
+    let mut tampered = token.clone().into_bytes();
+    tampered[20] = if tampered[20] == b'0' { b'1' } else { b'0' };
+    let tampered = String::from_utf8(tampered).unwrap();
+    let other_rcpt = core.quarantine_release_url(1, "nobody@foobar.org").0;
+    let other_message = core.quarantine_release_url(2, "john@foobar.org").0;
+    for token in [
+        tampered.as_str(),
+        "invalid",
+        other_rcpt.rsplit_once('/').unwrap().1,
+        other_message.rsplit_once('/').unwrap().1,
+    ] {
+        assert!(
+            core.quarantined_message(token).await.is_err(),
+            "token {token} was accepted"
+        );
+    }
+
+    // Expired tokens are rejected
+    let expired = ReleaseToken {
+        queue_id: 1,
+        expires: now() - 1,
+        nonce: 1,
+    };
+    let expired = expired.encode(&expired.signature(&core, "john@foobar.org"));
+    assert!(
+        core.quarantined_message(&expired)
+            .await
+            .unwrap_err()
+            .matches(trc::EventType::Auth(trc::AuthEvent::TokenExpired))
+    );
+
+    // Looking up a token does not release the message
+    let held = core.quarantined_message(&token).await.unwrap();
+    assert_eq!(held.queue_id, 1);
+    assert_eq!(held.rcpt, "john@foobar.org");
+    assert_eq!(held.from, "sender@foobar.org");
+    assert_eq!(held.expires, expires);
+    local.queue_receiver.assert_no_events();
+
+    // Only the recipient bound to the token is released
+    let released = core.release_quarantined_message(&token, 0).await.unwrap();
+    assert_eq!(released, held);
+    assert!(matches!(
+        local.queue_receiver.read_event().await,
+        QueueEvent::Refresh
+    ));
+    let mut message = core.read_message(1).await.unwrap();
+    assert_eq!(
+        message
+            .recipients
+            .iter()
+            .map(|rcpt| (
+                rcpt.address_lcase.as_str(),
+                (rcpt.flags & RCPT_QUARANTINED) != 0,
+                (rcpt.flags & RCPT_QUARANTINE_RELEASED) != 0
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            ("john@foobar.org", false, true),
+            ("jane@foobar.org", true, false)
+        ]
+    );
+    assert!(message.domains[0].retry.due <= now());
+
+    // Tokens are single-use, even if the recipient was held again
+    let prev_event = message.next_event();
+    message.recipients[0].flags = RCPT_QUARANTINED;
+    let next_event = message.next_event();
+    message.save_changes(&core, prev_event, next_event).await;
+    assert!(
+        core.release_quarantined_message(&token, 0)
+            .await
+            .unwrap_err()
+            .matches(trc::EventType::Security(trc::SecurityEvent::Unauthorized))
+    );
+}
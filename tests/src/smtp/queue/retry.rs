@@ -12,9 +12,16 @@ use crate::smtp::{
     session::{TestSession, VerifyResponse},
 };
 use ahash::AHashSet;
-use common::ipc::{QueueEvent, QueueEventStatus};
-use smtp::queue::spool::SmtpSpool;
+use common::{
+    config::smtp::{classifier::ResponseCategory, queue::QueueConfig},
+    ipc::{QueueEvent, QueueEventStatus},
+};
+use smtp::queue::{
+    Domain, Error, ErrorDetails, HostResponse, Recipient, Schedule, Status, spool::SmtpSpool,
+};
+use smtp_proto::Response;
 use store::write::now;
+use utils::config::Config;
 
 const CONFIG: &str = r#"
 [session.ehlo]
@@ -194,3 +201,188 @@ async fn queue_retry() {
     let schedule = qr.expect_message().await;
     assert!([3599, 3600].contains(&(schedule.domains.first().unwrap().notify.due - now())));
 }
+
+const CLASSIFIER_CONFIG: &str = r#"
+[queue.classifier]
+max-retry-after = "12h"
+
+[queue.classifier.rule."acme-slowdown"]
+category = "rate-limit"
+match = ["please slow down"]
+code = [421]
+
+[queue.classifier.retry]
+mailbox-full = ["2h", "8h"]
+"#;
+
+#[test]
+fn queue_retry_classifier() {
+    let mut config = Config::new(CLASSIFIER_CONFIG).unwrap();
+    let classifier = QueueConfig::parse(&mut config).classifier;
+    assert!(config.errors.is_empty(), "{:?}", config.errors);
+
+    // Classify common remote responses
+    for (code, esc, message, expected) in [
+        (
+            451,
+            [4, 7, 1],
+            "Greylisted, please try again in 4 hours",
+            Some((ResponseCategory::Greylisted, "greylist", Some(4 * 3600))),
+        ),
+        (
+            421,
+            [4, 7, 0],
+            "[TSS04] Messages from 10.0.0.1 temporarily deferred",
+            Some((ResponseCategory::RateLimited, "rate-limit", None)),
+        ),
+        (
+            450,
+            [4, 2, 1],
+            "The user you are trying to contact is receiving mail at a rate that prevents additional messages from being delivered",
+            Some((ResponseCategory::RateLimited, "rate-limit", None)),
+        ),
+        (
+            452,
+            [4, 2, 2],
+            "The email account that you tried to reach is over quota",
+            Some((ResponseCategory::MailboxFull, "mailbox-full", None)),
+        ),
+        (
+            451,
+            [4, 7, 1],
+            "Service unavailable, client host blocked using zen.spamhaus.org",
+            Some((ResponseCategory::PolicyBlock, "policy", None)),
+        ),
+        (
+            421,
+            [0, 0, 0],
+            "Please slow down and retry after 30 minutes",
+            Some((ResponseCategory::RateLimited, "acme-slowdown", Some(1800))),
+        ),
+        // Hints are capped
+        (
+            450,
+            [4, 7, 1],
+            "Greylisted, try again in 48 hours",
+            Some((ResponseCategory::Greylisted, "greylist", Some(12 * 3600))),
+        ),
+        // Configured rules only match their codes
+        (451, [0, 0, 0], "Please slow down", None),
+        (451, [4, 3, 0], "Temporary local problem", None),
+        // Permanent failures are never classified
+        (550, [5, 2, 2], "Mailbox full", None),
+        (
+            554,
+            [5, 7, 1],
+            "Greylisted, please try again in 5 minutes",
+            None,
+        ),
+    ] {
+        let response = Response {
+            code,
+            esc,
+            message: message.to_string(),
+        };
+        assert_eq!(
+            classifier.classify(&response).map(|class| (
+                class.category,
+                class.rule,
+                class.retry_after.map(|retry| retry.as_secs())
+            )),
+            expected.map(|(category, rule, retry)| (category, rule.to_string(), retry)),
+            "{message}"
+        );
+    }
+
+    // Hints take precedence over the category schedule
+    let mut domain = Domain {
+        domain: "example.org".into(),
+        retry: Schedule::now(),
+        notify: Schedule::now(),
+        expires: now() + 86400,
+        status: Status::Scheduled,
+    };
+    let status = Status::TemporaryFailure(Error::UnexpectedResponse(host_response(
+        451,
+        "Greylisted, please try again in 4 hours",
+    )));
+    let retry = domain
+        .classify_retry(&classifier, &status, [].iter(), 0)
+        .unwrap();
+    assert_eq!(retry, Duration::from_secs(4 * 3600));
+    domain.set_status(status, &[retry]);
+    assert!((4 * 3600 - 1..=4 * 3600).contains(&(domain.retry.due - now())));
+
+    // Category schedules are indexed by the retry number
+    let mailbox_full = recipient(452, "Mailbox full");
+    assert_eq!(
+        domain.classify_retry(
+            &classifier,
+            &Status::Scheduled,
+            [&mailbox_full].into_iter(),
+            0
+        ),
+        Some(Duration::from_secs(8 * 3600))
+    );
+
+    // The shortest interval is used when all the recipients were classified
+    let rate_limited = recipient(421, "Rate limit exceeded");
+    assert_eq!(
+        domain.classify_retry(
+            &classifier,
+            &Status::Scheduled,
+            [&mailbox_full, &rate_limited].into_iter(),
+            0
+        ),
+        Some(Duration::from_secs(30 * 60))
+    );
+
+    // Unclassified failures and categories without a schedule use the default schedule
+    let unclassified = recipient(451, "Temporary local problem");
+    let policy = recipient(451, "Rejected by policy");
+    for rcpts in [[&mailbox_full, &unclassified], [&mailbox_full, &policy]] {
+        assert_eq!(
+            domain.classify_retry(&classifier, &Status::Scheduled, rcpts.into_iter(), 0),
+            None
+        );
+    }
+
+    // Permanent failures are not rescheduled
+    let status = Status::PermanentFailure(Error::UnexpectedResponse(host_response(
+        550,
+        "Mailbox full",
+    )));
+    assert_eq!(
+        domain.classify_retry(&classifier, &status, [].iter(), 0),
+        None
+    );
+    let retry = domain.retry.clone();
+    domain.set_status(status, &[Duration::from_secs(60)]);
+    assert_eq!(domain.retry.due, retry.due);
+    assert_eq!(domain.retry.inner, retry.inner);
+}
+
+fn host_response(code: u16, message: &str) -> HostResponse<ErrorDetails> {
+    HostResponse {
+        hostname: ErrorDetails {
+            entity: "mx.example.org".into(),
+            details: "RCPT TO:<john@example.org>".into(),
+        },
+        response: Response {
+            code,
+            esc: [0, 0, 0],
+            message: message.into(),
+        },
+    }
+}
+
+fn recipient(code: u16, message: &str) -> Recipient {
+    Recipient {
+        domain_idx: 0,
+        address: "john@example.org".into(),
+        address_lcase: "john@example.org".into(),
+        status: Status::TemporaryFailure(host_response(code, message)),
+        flags: 0,
+        orcpt: None,
+    }
+}
@@ -358,11 +358,14 @@ impl TestServerInstance for ServerInstance {
                 config: tls_config.clone(),
                 acceptor: TlsAcceptor::from(tls_config),
                 implicit: false,
+                fingerprint: false,
+                resolver: None,
             },
             limiter: ConcurrencyLimiter::new(100),
             shutdown_rx,
             proxy_networks: vec![],
             span_id_gen: Arc::new(SnowflakeIdGenerator::new()),
+            banner: Default::default(),
         }
     }
 }
@@ -411,6 +411,126 @@ pub async fn blob_tests() {
                     ^ ct
             );
         }
+
+        // Link small blobs to accountId 3 and pack them
+        let blob_store = blob_store.with_packs(store.clone());
+        let blobs: [&[u8]; 3] = [b"packed-1", b"packed-22", b"packed-333"];
+        let hashes = blobs
+            .iter()
+            .map(|blob| BlobHash::generate(*blob))
+            .collect::<Vec<_>>();
+        for (document_id, (blob, hash)) in blobs.iter().zip(hashes.iter()).enumerate() {
+            store
+                .write(
+                    BatchBuilder::new()
+                        .with_account_id(3)
+                        .with_collection(0)
+                        .update_document(document_id as u32)
+                        .set(BlobOp::Link { hash: hash.clone() }, vec![])
+                        .set(BlobOp::Commit { hash: hash.clone() }, vec![])
+                        .build_all(),
+                )
+                .await
+                .unwrap();
+            blob_store.put_blob(hash.as_ref(), blob).await.unwrap();
+        }
+
+        // Packing should be skipped when there are not enough blobs
+        assert!(
+            store
+                .blob_pack(&blob_store, 3, hashes.clone(), 4)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let summary = store
+            .blob_pack(&blob_store, 3, hashes.clone(), 2)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.size, 27);
+
+        // Packed blobs should be readable and no longer packed again
+        for (blob, hash) in blobs.iter().zip(hashes.iter()) {
+            assert!(store.blob_pack_location(hash).await.unwrap().is_some());
+            assert_eq!(
+                blob_store
+                    .get_blob(hash.as_ref(), 0..usize::MAX)
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some(*blob)
+            );
+            assert_eq!(
+                blob_store
+                    .get_blob(hash.as_ref(), 1..3)
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some(&blob[1..3])
+            );
+        }
+        assert!(
+            store
+                .blob_pack(&blob_store, 3, hashes.clone(), 1)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Unlink one blob, purge and rewrite the pack
+        store
+            .write(
+                BatchBuilder::new()
+                    .with_account_id(3)
+                    .with_collection(0)
+                    .update_document(0)
+                    .clear(BlobOp::Link {
+                        hash: hashes[0].clone(),
+                    })
+                    .build_all(),
+            )
+            .await
+            .unwrap();
+        store.purge_blobs(blob_store.clone()).await.unwrap();
+        assert!(
+            blob_store
+                .get_blob(hashes[0].as_ref(), 0..usize::MAX)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let packs = store.blob_packs(3).await.unwrap();
+        assert_eq!(packs.len(), 1);
+        let (pack_hash, pack) = &packs[0];
+        assert_eq!(pack.live_count(), 2);
+        assert_eq!(pack.wasted_size(), 8);
+        assert!(pack.needs_rewrite(0.2));
+        let summary = store
+            .blob_pack_rewrite(&blob_store, 3, pack_hash)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(summary.count, 2);
+        for (blob, hash) in blobs.iter().zip(hashes.iter()).skip(1) {
+            assert_eq!(
+                blob_store
+                    .get_blob(hash.as_ref(), 0..usize::MAX)
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some(*blob)
+            );
+        }
+
+        // Unlink remaining blobs and make sure the pack is removed
+        store.blob_hash_unlink_account(3).await.unwrap();
+        store.purge_blobs(blob_store.clone()).await.unwrap();
+        assert!(store.blob_packs(3).await.unwrap().is_empty());
+        for hash in &hashes {
+            assert!(store.blob_pack_location(hash).await.unwrap().is_none());
+        }
     }
     temp_dir.delete();
 }
@@ -0,0 +1,114 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use store::{
+    SUBSPACE_INDEXES,
+    dispatch::latency::{
+        LatencyBudget, OperationContext, StoreOperation, recent_slow_operations,
+        top_slow_operations,
+    },
+};
+
+#[tokio::test]
+async fn latency_budgets() {
+    // Use an account id that no other test writes to, slow operations are kept in a global buffer
+    const ACCOUNT_ID: u32 = u32::MAX - 1;
+
+    let budget = LatencyBudget {
+        slow_query: Some(Duration::from_millis(50)),
+        timeout: Some(Duration::from_millis(300)),
+    };
+    let context =
+        |operation| OperationContext::new(operation, SUBSPACE_INDEXES).with_account(ACCOUNT_ID, 1);
+    let slow_operations = || {
+        recent_slow_operations()
+            .into_iter()
+            .filter(|op| op.context.account_id == Some(ACCOUNT_ID))
+            .collect::<Vec<_>>()
+    };
+
+    // Fast operations are not recorded
+    assert_eq!(
+        context(StoreOperation::GetValue)
+            .run_with_budget(&budget, None, async { Ok(1) })
+            .await
+            .unwrap(),
+        1
+    );
+    assert!(slow_operations().is_empty());
+
+    // Operations over the slow query threshold complete but are recorded
+    assert_eq!(
+        context(StoreOperation::Iterate)
+            .run_with_budget(&budget, Some(10), async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Ok(2)
+            })
+            .await
+            .unwrap(),
+        2
+    );
+    let recorded = slow_operations();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].context.operation, StoreOperation::Iterate);
+    assert_eq!(recorded[0].context.collection, Some(1));
+    assert!(recorded[0].elapsed >= Duration::from_millis(100));
+    assert!(!recorded[0].timed_out);
+
+    // Reads over the hard cap are cancelled with a retryable error
+    let err = context(StoreOperation::GetBitmap)
+        .run_with_budget(&budget, None, async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(3)
+        })
+        .await
+        .unwrap_err();
+    assert!(err.matches(trc::EventType::Store(trc::StoreEvent::QueryTimeout)));
+    let recorded = slow_operations();
+    assert_eq!(recorded.len(), 2);
+    assert_eq!(recorded[1].context.operation, StoreOperation::GetBitmap);
+    assert!(recorded[1].timed_out);
+
+    // Writes are never cancelled, only logged
+    assert_eq!(
+        context(StoreOperation::Write)
+            .run_with_budget(&budget, None, async {
+                tokio::time::sleep(Duration::from_millis(400)).await;
+                Ok(4)
+            })
+            .await
+            .unwrap(),
+        4
+    );
+    let recorded = slow_operations();
+    assert_eq!(recorded.len(), 3);
+    assert_eq!(recorded[2].context.operation, StoreOperation::Write);
+    assert!(!recorded[2].timed_out);
+
+    // Slow operations are aggregated by operation, account and collection
+    let summary = top_slow_operations(usize::MAX)
+        .into_iter()
+        .find(|summary| summary.context == context(StoreOperation::GetBitmap))
+        .unwrap();
+    assert_eq!(summary.count, 1);
+    assert_eq!(summary.timeouts, 1);
+    assert!(summary.max_elapsed >= Duration::from_millis(300));
+
+    // Disabled budgets never record nor cancel operations
+    assert_eq!(
+        context(StoreOperation::GetCounter)
+            .run_with_budget(&LatencyBudget::default(), None, async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Ok(5)
+            })
+            .await
+            .unwrap(),
+        5
+    );
+    assert_eq!(slow_operations().len(), 3);
+}
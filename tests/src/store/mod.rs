@@ -6,6 +6,7 @@
 
 pub mod blob;
 pub mod import_export;
+pub mod latency;
 pub mod lookup;
 pub mod ops;
 pub mod query;